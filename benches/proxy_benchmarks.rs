@@ -0,0 +1,198 @@
+//! Benchmarks for the request hot paths: route selection, the backend request rewrite, static
+//! file serving and CONNECT tunnel copying
+//!
+//! Run with `cargo bench --bench proxy_benchmarks`; criterion keeps the previous run and reports
+//! the change against it.
+
+use bifrost_bridge::config::{Config, ReverseProxyRouteConfig, StaticFileConfig};
+use bifrost_bridge::reverse_proxy::{RequestContext, ReverseProxy};
+use bifrost_bridge::static_files::StaticFileHandler;
+use bifrost_bridge::test_support::{StubBackend, TestProxy, connect_tunnel};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use http_body_util::BodyExt;
+use hyper::{HeaderMap, Request};
+use serde_json::json;
+use std::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::runtime::Runtime;
+
+/// `count` routes, each matching one path prefix and one header, plus a catch-all.
+fn routes(count: usize) -> Vec<ReverseProxyRouteConfig> {
+    let mut routes: Vec<_> = (0..count)
+        .map(|i| {
+            json!({
+                "id": format!("svc{}", i),
+                "target": format!("http://svc{}.internal:8080", i),
+                "priority": 1,
+                "strip_path_prefix": format!("/svc{}", i),
+                "predicates": [
+                    { "type": "Path", "patterns": [format!("/svc{}/**", i)] },
+                    { "type": "Header", "name": "X-Tenant", "regex": "^[a-z]+$" }
+                ]
+            })
+        })
+        .collect();
+    routes.push(json!({
+        "id": "default",
+        "target": "http://web.internal:8080",
+        "priority": 10,
+        "predicates": [{ "type": "Path", "patterns": ["/**"] }]
+    }));
+    serde_json::from_value(json!(routes)).unwrap()
+}
+
+/// `count` virtual hosts, each with a read route and a write route on the same paths.
+fn host_routes(count: usize) -> Vec<ReverseProxyRouteConfig> {
+    let routes: Vec<_> = (0..count)
+        .flat_map(|i| {
+            [("read", "GET"), ("write", "POST")].map(|(kind, method)| {
+                json!({
+                    "id": format!("svc{}-{}", i, kind),
+                    "target": format!("http://svc{}-{}.internal:8080", i, kind),
+                    "predicates": [
+                        { "type": "Host", "patterns": [format!("svc{}.example.com", i)] },
+                        { "type": "Method", "methods": [method] },
+                        { "type": "Path", "patterns": ["/api/{version}/**"] }
+                    ]
+                })
+            })
+        })
+        .collect();
+    serde_json::from_value(json!(routes)).unwrap()
+}
+
+fn context() -> RequestContext {
+    RequestContext { client_ip: Some("10.20.30.40".to_string()), peer_ip: None }
+}
+
+/// A browser-like request for the last declared route, the worst case for a linear scan.
+fn request(route_count: usize) -> Request<()> {
+    Request::get(format!("/svc{}/orders/42?expand=items", route_count - 1))
+        .header("host", "shop.example.com")
+        .header("x-tenant", "acme")
+        .header("user-agent", "Mozilla/5.0 (X11; Linux x86_64) Gecko/20100101 Firefox/128.0")
+        .header("accept", "application/json, text/plain, */*")
+        .header("accept-language", "en-US,en;q=0.5")
+        .header("accept-encoding", "gzip, deflate, br")
+        .header("cookie", "session=0123456789abcdef; theme=dark")
+        .header("x-request-id", "6f1c2d7e-0d1f-4b8e-9a51-8f0c3b2e1a77")
+        .header("connection", "keep-alive")
+        .body(())
+        .unwrap()
+}
+
+fn bench_route_selection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("route_selection");
+    for count in [10, 100, 1000] {
+        let proxy = ReverseProxy::new_with_routes(routes(count), 10, 90, 300, None, None).unwrap();
+        let req = request(count);
+        let context = context();
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| black_box(proxy.route_for(black_box(&req), &context)));
+        });
+    }
+    group.finish();
+}
+
+fn bench_route_selection_by_host(c: &mut Criterion) {
+    let mut group = c.benchmark_group("route_selection_by_host");
+    for count in [10, 100, 1000] {
+        let proxy = ReverseProxy::new_with_routes(host_routes(count), 10, 90, 300, None, None).unwrap();
+        let mut req = request(count);
+        *req.method_mut() = hyper::Method::POST;
+        *req.uri_mut() = "/api/v2/orders/42".parse().unwrap();
+        req.headers_mut().insert("host", format!("svc{}.example.com", count - 1).parse().unwrap());
+        let context = context();
+        assert_eq!(proxy.route_for(&req, &context), Some(format!("svc{}-write", count - 1)));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| black_box(proxy.route_for(black_box(&req), &context)));
+        });
+    }
+    group.finish();
+}
+
+fn bench_header_rewrite(c: &mut Criterion) {
+    let mut group = c.benchmark_group("header_rewrite");
+    let proxy = ReverseProxy::new_with_routes(routes(10), 10, 90, 300, None, None).unwrap();
+    let context = context();
+    let req = request(10);
+    group.bench_function("backend_request", |b| {
+        b.iter_batched(
+            || req.clone(),
+            |req| black_box(proxy.backend_request(req, &context).unwrap()),
+            criterion::BatchSize::SmallInput,
+        );
+    });
+    group.finish();
+}
+
+fn bench_static_files(c: &mut Criterion) {
+    let mut group = c.benchmark_group("static_files");
+    let runtime = Runtime::new().unwrap();
+    let root = tempfile::tempdir().unwrap();
+    let handler = StaticFileHandler::new(StaticFileConfig::single(root.path().to_string_lossy().to_string(), false))
+        .unwrap();
+    let headers = HeaderMap::new();
+
+    for (name, size) in [("4KiB", 4 * 1024), ("1MiB", 1024 * 1024)] {
+        let path = root.path().join(format!("{}.bin", name));
+        fs::write(&path, vec![b'x'; size]).unwrap();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let response = handler.handle_file_request(&path, &headers, false, None, false).await.unwrap();
+                    black_box(response.into_body().collect().await.unwrap().to_bytes())
+                })
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_tunnel_copy(c: &mut Criterion) {
+    const CHUNK: usize = 1024 * 1024;
+
+    let mut group = c.benchmark_group("tunnel_copy");
+    let runtime = Runtime::new().unwrap();
+    let (_target, _proxy, tunnel) = runtime.block_on(async {
+        let target = StubBackend::tcp_echo().await;
+        let config: Config = serde_json::from_value(json!({
+            "mode": "Forward",
+            "listen_addr": "127.0.0.1:0",
+            "monitoring": { "enabled": false },
+            "forward_proxy_config": { "connect_ports": { "allowed_ports": [target.addr().port()] } }
+        }))
+        .unwrap();
+        let proxy = TestProxy::start(config).await.unwrap();
+        let (head, tunnel) = connect_tunnel(proxy.addr(), &target.addr().to_string(), &[]).await.unwrap();
+        assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+        (target, proxy, tunnel)
+    });
+    let (mut reader, mut writer) = tunnel.into_split();
+    let payload = vec![0x5au8; CHUNK];
+    let mut echoed = vec![0u8; CHUNK];
+
+    // Each iteration sends 1 MiB through the tunnel and reads it back from the echo target
+    group.throughput(Throughput::Bytes(2 * CHUNK as u64));
+    group.bench_function("echo_1MiB", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let (written, read) = tokio::join!(writer.write_all(&payload), reader.read_exact(&mut echoed));
+                written.unwrap();
+                read.unwrap();
+            })
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_route_selection,
+    bench_route_selection_by_host,
+    bench_header_rewrite,
+    bench_static_files,
+    bench_tunnel_copy
+);
+criterion_main!(benches);
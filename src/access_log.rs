@@ -0,0 +1,706 @@
+//! Access log entries: which requests get one, how it is laid out and where it is written.
+//!
+//! Each listener (reverse proxy/static files on `listen_addr`, forward proxy) has its own
+//! policy. Filters decide which requests are worth an entry at all (errors, slow requests,
+//! certain routes); sampling then thins out the rest, either at a fixed rate or adaptively so
+//! that no more than a set number of entries are written per second.
+//!
+//! Entries are laid out as plain text, Common Log Format, JSON or a template with `$name`
+//! placeholders, and go either to the `bifrost::access` log target or to a file of their own
+//! that is rotated by size.
+
+use crate::common::unix_now;
+use crate::config::{AccessLogConfig, AccessLogFileConfig, AccessLogFormat, AccessLogPolicyConfig};
+use crate::error::ProxyError;
+use crate::privacy;
+use chrono::{Local, Utc};
+use hyper::{Method, Request, StatusCode, Version};
+use log::{info, warn};
+use prometheus::{IntCounterVec, Opts, Registry};
+use rand::Rng;
+use serde_json::{Map, Value, json};
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Which listener an entry belongs to; also the `listener` metric label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLogListener {
+    Reverse,
+    Forward,
+}
+
+impl AccessLogListener {
+    fn as_str(self) -> &'static str {
+        match self {
+            AccessLogListener::Reverse => "reverse",
+            AccessLogListener::Forward => "forward",
+        }
+    }
+}
+
+/// The access log policy of one listener.
+#[derive(Debug)]
+pub struct AccessLogPolicy {
+    listener: AccessLogListener,
+    sample_rate: f64,
+    max_per_second: Option<u64>,
+    min_status: Option<u16>,
+    min_duration: Option<Duration>,
+    routes: HashSet<String>,
+    keep_errors: bool,
+    window: Mutex<RateWindow>,
+}
+
+/// Requests seen and entries written in the current second, and requests seen in the last one.
+#[derive(Debug, Default)]
+struct RateWindow {
+    second: u64,
+    seen: u64,
+    logged: u64,
+    previous_seen: u64,
+}
+
+impl AccessLogPolicy {
+    pub fn new(listener: AccessLogListener, config: &AccessLogPolicyConfig) -> Result<Self, ProxyError> {
+        if !(0.0..=1.0).contains(&config.sample_rate) {
+            return Err(ProxyError::Config(format!(
+                "access_log.{}.sample_rate must be between 0.0 and 1.0, got {}",
+                listener.as_str(),
+                config.sample_rate
+            )));
+        }
+        if listener == AccessLogListener::Forward && !config.routes.is_empty() {
+            return Err(ProxyError::Config(
+                "access_log.forward.routes is not supported; the forward proxy has no routes".to_string(),
+            ));
+        }
+        Ok(Self {
+            listener,
+            sample_rate: config.sample_rate,
+            max_per_second: config.max_per_second,
+            min_status: config.min_status,
+            min_duration: config.min_duration_ms.map(Duration::from_millis),
+            routes: config.routes.iter().cloned().collect(),
+            keep_errors: config.keep_errors,
+            window: Mutex::new(RateWindow::default()),
+        })
+    }
+
+    /// The listener's own policy; without one, every request is logged when entries go to a file.
+    pub fn for_listener(listener: AccessLogListener, config: &AccessLogConfig) -> Result<Option<Self>, ProxyError> {
+        let policy = match listener {
+            AccessLogListener::Reverse => &config.reverse,
+            AccessLogListener::Forward => &config.forward,
+        };
+        match (policy, &config.file) {
+            (Some(policy), _) => Self::new(listener, policy).map(Some),
+            (None, Some(_)) => Self::new(listener, &AccessLogPolicyConfig::default()).map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Route ids the policy is limited to; empty when it covers all routes.
+    pub fn routes(&self) -> impl Iterator<Item = &str> {
+        self.routes.iter().map(String::as_str)
+    }
+
+    /// Whether the request gets an entry; counts the decision either way.
+    pub fn should_log(&self, route: Option<&str>, status: StatusCode, elapsed: Duration) -> bool {
+        let outcome = self.decide(route, status, elapsed, unix_now(), rand::thread_rng().r#gen::<f64>());
+        access_log_telemetry().record(self.listener, outcome);
+        outcome == "logged"
+    }
+
+    fn decide(&self, route: Option<&str>, status: StatusCode, elapsed: Duration, now: u64, roll: f64) -> &'static str {
+        if !self.routes.is_empty() && !route.is_some_and(|route| self.routes.contains(route)) {
+            return "filtered";
+        }
+        let by_status = self.min_status.map(|min| status.as_u16() >= min);
+        let by_duration = self.min_duration.map(|min| elapsed >= min);
+        // When both are set, either one is enough
+        if let (Some(false), None) | (None, Some(false)) | (Some(false), Some(false)) = (by_status, by_duration) {
+            return "filtered";
+        }
+
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        if window.second != now {
+            window.previous_seen = if window.second + 1 == now { window.seen } else { 0 };
+            window.second = now;
+            window.seen = 0;
+            window.logged = 0;
+        }
+        window.seen += 1;
+
+        if self.keep_errors && status.is_server_error() {
+            window.logged += 1;
+            return "logged";
+        }
+        let mut rate = self.sample_rate;
+        if let Some(max) = self.max_per_second {
+            if window.logged >= max {
+                return "sampled_out";
+            }
+            // Adapt to last second's traffic so the budget is spread over the whole second
+            let expected = window.previous_seen.max(window.seen) as f64 * rate;
+            if expected > max as f64 {
+                rate *= max as f64 / expected;
+            }
+        }
+        if rate >= 1.0 || roll < rate {
+            window.logged += 1;
+            "logged"
+        } else {
+            "sampled_out"
+        }
+    }
+}
+
+/// A forward proxy request whose entry is written once its response status is known.
+pub struct AccessEntry {
+    policy: Arc<AccessLogPolicy>,
+    method: Method,
+    target: String,
+    version: Version,
+    started: Instant,
+}
+
+impl AccessEntry {
+    pub fn start<B>(policy: Arc<AccessLogPolicy>, req: &Request<B>) -> Self {
+        Self {
+            policy,
+            method: req.method().clone(),
+            target: req.uri().to_string(),
+            version: req.version(),
+            started: Instant::now(),
+        }
+    }
+
+    /// An entry for a CONNECT read straight off the socket, which never becomes a `Request`.
+    pub fn connect(policy: Arc<AccessLogPolicy>, target: &str, version: Version) -> Self {
+        Self {
+            policy,
+            method: Method::CONNECT,
+            target: target.to_string(),
+            version,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn finish<B>(self, client_ip: &str, response: &hyper::Response<B>) {
+        self.write(client_ip, response.status(), UpstreamTime::of(response));
+    }
+
+    /// Finishes an entry whose response was written by hand rather than built as a `Response`.
+    pub fn finish_with_status(self, client_ip: &str, status: StatusCode) {
+        self.write(client_ip, status, None);
+    }
+
+    fn write(self, client_ip: &str, status: StatusCode, upstream_time: Option<Duration>) {
+        let elapsed = self.started.elapsed();
+        if self.policy.should_log(None, status, elapsed) {
+            write(&AccessRecord {
+                listener: AccessLogListener::Forward,
+                client_ip: Some(client_ip),
+                method: &self.method,
+                uri: &self.target,
+                version: self.version,
+                status,
+                elapsed,
+                upstream_time,
+                route: None,
+                metadata: &[],
+            });
+        }
+    }
+}
+
+/// How long the backend took to send its response headers; carried in the extensions of the
+/// response so the access log can report it.
+#[derive(Debug, Clone, Copy)]
+pub struct UpstreamTime(pub Duration);
+
+impl UpstreamTime {
+    pub fn of<B>(response: &hyper::Response<B>) -> Option<Duration> {
+        response.extensions().get::<UpstreamTime>().map(|time| time.0)
+    }
+}
+
+/// What an entry says about one request.
+pub struct AccessRecord<'a> {
+    pub listener: AccessLogListener,
+    pub client_ip: Option<&'a str>,
+    pub method: &'a Method,
+    /// Path and query on the reverse proxy, the absolute URI on the forward proxy
+    pub uri: &'a str,
+    pub version: Version,
+    pub status: StatusCode,
+    pub elapsed: Duration,
+    pub upstream_time: Option<Duration>,
+    pub route: Option<&'a str>,
+    /// Route metadata, sorted by key
+    pub metadata: &'a [(String, String)],
+}
+
+static WRITER: OnceLock<AccessLogWriter> = OnceLock::new();
+
+/// Sets the layout and destination of entries.
+pub fn init(config: &AccessLogConfig) -> Result<(), ProxyError> {
+    let writer = AccessLogWriter::new(config)?;
+    if let Some(file) = &config.file {
+        info!("Access log: {:?} entries to {}", config.format, file.path.display());
+    }
+    if WRITER.set(writer).is_err() {
+        warn!("Access log already initialized; keeping the first configuration");
+    }
+    Ok(())
+}
+
+/// Writes the entry for a request that passed its listener's policy.
+pub fn write(record: &AccessRecord<'_>) {
+    match WRITER.get() {
+        Some(writer) => writer.write(record),
+        None => info!(target: "bifrost::access", "{}", Layout::Text.format(record)),
+    }
+}
+
+/// The layout and destination of entries.
+pub struct AccessLogWriter {
+    layout: Layout,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl AccessLogWriter {
+    pub fn new(config: &AccessLogConfig) -> Result<Self, ProxyError> {
+        let layout = match (config.format, &config.template) {
+            (AccessLogFormat::Template, Some(template)) => Layout::Template(parse_template(template)?),
+            (AccessLogFormat::Template, None) => {
+                return Err(ProxyError::Config("access_log.format \"template\" requires access_log.template".to_string()));
+            }
+            (format, template) => {
+                if template.is_some() {
+                    warn!("access_log.template is ignored with format {:?}", format);
+                }
+                match format {
+                    AccessLogFormat::Text | AccessLogFormat::Template => Layout::Text,
+                    AccessLogFormat::Common => Layout::Common,
+                    AccessLogFormat::Json => Layout::Json,
+                }
+            }
+        };
+        let file = config.file.as_ref().map(RotatingFile::open).transpose()?.map(Mutex::new);
+        Ok(Self { layout, file })
+    }
+
+    pub fn write(&self, record: &AccessRecord<'_>) {
+        let line = self.layout.format(record);
+        match &self.file {
+            Some(file) => file.lock().unwrap_or_else(|e| e.into_inner()).write_line(&line),
+            None => info!(target: "bifrost::access", "{}", line),
+        }
+    }
+}
+
+enum Layout {
+    Text,
+    Common,
+    Json,
+    Template(Vec<Part>),
+}
+
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    RemoteAddr,
+    TimeLocal,
+    TimeIso8601,
+    Request,
+    Method,
+    Uri,
+    Protocol,
+    Status,
+    RequestTime,
+    DurationMs,
+    UpstreamTime,
+    Route,
+    Listener,
+}
+
+const FIELDS: [(&str, Field); 13] = [
+    ("remote_addr", Field::RemoteAddr),
+    ("time_local", Field::TimeLocal),
+    ("time_iso8601", Field::TimeIso8601),
+    ("request", Field::Request),
+    ("method", Field::Method),
+    ("uri", Field::Uri),
+    ("protocol", Field::Protocol),
+    ("status", Field::Status),
+    ("request_time", Field::RequestTime),
+    ("duration_ms", Field::DurationMs),
+    ("upstream_time", Field::UpstreamTime),
+    ("route", Field::Route),
+    ("listener", Field::Listener),
+];
+
+/// Splits a template into literal text and `$name` placeholders; `$$` is a literal `$`.
+fn parse_template(template: &str) -> Result<Vec<Part>, ProxyError> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut rest = template;
+    while let Some(dollar) = rest.find('$') {
+        literal.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            literal.push('$');
+            rest = after;
+            continue;
+        }
+        let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        let name = &rest[..end];
+        let field = FIELDS.iter().find(|(known, _)| *known == name).map(|(_, field)| *field).ok_or_else(|| {
+            ProxyError::Config(format!("access_log.template: unknown placeholder ${}", name))
+        })?;
+        if !literal.is_empty() {
+            parts.push(Part::Literal(std::mem::take(&mut literal)));
+        }
+        parts.push(Part::Field(field));
+        rest = &rest[end..];
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        parts.push(Part::Literal(literal));
+    }
+    Ok(parts)
+}
+
+impl Layout {
+    fn format(&self, record: &AccessRecord<'_>) -> String {
+        match self {
+            Layout::Text => {
+                let mut line = format!(
+                    "{} {} {} {}",
+                    Field::RemoteAddr.value(record),
+                    record.method,
+                    record.uri,
+                    record.status.as_u16()
+                );
+                match record.route {
+                    Some(route) => write!(line, " route={}", route),
+                    None => write!(line, " listener={}", record.listener.as_str()),
+                }
+                .ok();
+                write!(line, " duration_ms={}", record.elapsed.as_millis()).ok();
+                for (key, value) in record.metadata {
+                    write!(line, " {}={}", key, value).ok();
+                }
+                line
+            }
+            Layout::Common => format!(
+                "{} - - [{}] \"{}\" {} -",
+                Field::RemoteAddr.value(record),
+                Field::TimeLocal.value(record),
+                Field::Request.value(record),
+                record.status.as_u16()
+            ),
+            Layout::Json => {
+                let mut entry = Map::new();
+                entry.insert("timestamp".to_string(), json!(Field::TimeIso8601.value(record)));
+                entry.insert("listener".to_string(), json!(record.listener.as_str()));
+                entry.insert("client_ip".to_string(), json!(record.client_ip.map(privacy::client_ip)));
+                entry.insert("method".to_string(), json!(record.method.as_str()));
+                entry.insert("uri".to_string(), json!(record.uri));
+                entry.insert("protocol".to_string(), json!(Field::Protocol.value(record)));
+                entry.insert("status".to_string(), json!(record.status.as_u16()));
+                entry.insert("duration_ms".to_string(), json!(record.elapsed.as_millis() as u64));
+                entry.insert(
+                    "upstream_ms".to_string(),
+                    json!(record.upstream_time.map(|time| time.as_millis() as u64)),
+                );
+                entry.insert("route".to_string(), json!(record.route));
+                if !record.metadata.is_empty() {
+                    let metadata: Map<String, Value> =
+                        record.metadata.iter().map(|(key, value)| (key.clone(), json!(value))).collect();
+                    entry.insert("metadata".to_string(), Value::Object(metadata));
+                }
+                Value::Object(entry).to_string()
+            }
+            Layout::Template(parts) => parts
+                .iter()
+                .map(|part| match part {
+                    Part::Literal(text) => text.clone(),
+                    Part::Field(field) => field.value(record),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Field {
+    fn value(self, record: &AccessRecord<'_>) -> String {
+        match self {
+            Field::RemoteAddr => record.client_ip.map_or_else(|| "-".to_string(), |ip| privacy::client_ip(ip).into_owned()),
+            Field::TimeLocal => Local::now().format("%d/%b/%Y:%H:%M:%S %z").to_string(),
+            Field::TimeIso8601 => Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+            Field::Request => format!("{} {} {:?}", record.method, record.uri, record.version),
+            Field::Method => record.method.to_string(),
+            Field::Uri => record.uri.to_string(),
+            Field::Protocol => format!("{:?}", record.version),
+            Field::Status => record.status.as_u16().to_string(),
+            Field::RequestTime => format!("{:.3}", record.elapsed.as_secs_f64()),
+            Field::DurationMs => record.elapsed.as_millis().to_string(),
+            Field::UpstreamTime => record.upstream_time.map_or_else(|| "-".to_string(), |time| format!("{:.3}", time.as_secs_f64())),
+            Field::Route => record.route.unwrap_or("-").to_string(),
+            Field::Listener => record.listener.as_str().to_string(),
+        }
+    }
+}
+
+/// An append-only file that is moved to `<path>.1` once it reaches its size limit, shifting
+/// older ones up to `<path>.<max_files>`.
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: u32,
+    writer: BufWriter<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(config: &AccessLogFileConfig) -> Result<Self, ProxyError> {
+        let file = open_append(&config.path)?;
+        let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        Ok(Self {
+            path: config.path.clone(),
+            max_bytes: config.max_size_mb.saturating_mul(1024 * 1024),
+            max_files: config.max_files,
+            writer: BufWriter::new(file),
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) {
+        let len = line.len() as u64 + 1;
+        if self.max_bytes > 0
+            && self.size > 0
+            && self.size + len > self.max_bytes
+            && let Err(e) = self.rotate()
+        {
+            warn!("Cannot rotate access log {}: {}", self.path.display(), e);
+        }
+        if writeln!(self.writer, "{}", line).and_then(|()| self.writer.flush()).is_ok() {
+            self.size += len;
+        }
+    }
+
+    fn rotate(&mut self) -> Result<(), ProxyError> {
+        let _ = self.writer.flush();
+        for index in (1..self.max_files).rev() {
+            let _ = std::fs::rename(numbered(&self.path, index), numbered(&self.path, index + 1));
+        }
+        if self.max_files > 0 {
+            std::fs::rename(&self.path, numbered(&self.path, 1))?;
+        } else {
+            std::fs::remove_file(&self.path)?;
+        }
+        self.writer = BufWriter::new(open_append(&self.path)?);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> Result<File, ProxyError> {
+    OpenOptions::new().create(true).append(true).open(path).map_err(|e| {
+        ProxyError::Config(format!("access_log.file: cannot open {}: {}", path.display(), e))
+    })
+}
+
+fn numbered(path: &Path, index: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+struct AccessLogTelemetry {
+    entries: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl AccessLogTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "access_log_decisions_total",
+            "Access log decisions by listener and outcome (logged, filtered, sampled_out)",
+        ).namespace("bifrost");
+        Self {
+            entries: IntCounterVec::new(opts, &["listener", "outcome"]).expect("access_log_decisions_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.entries.clone())) {
+            warn!("Failed to register access_log_decisions_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    fn record(&self, listener: AccessLogListener, outcome: &str) {
+        self.entries.with_label_values(&[listener.as_str(), outcome]).inc();
+    }
+}
+
+fn access_log_telemetry() -> &'static AccessLogTelemetry {
+    static TELEMETRY: OnceLock<AccessLogTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(AccessLogTelemetry::new)
+}
+
+/// Registers the access log decision counters with the shared Prometheus registry.
+pub fn register_access_log_metrics(registry: &Registry) {
+    access_log_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+pub(crate) fn decision_count(listener: AccessLogListener, outcome: &str) -> u64 {
+    access_log_telemetry().entries.with_label_values(&[listener.as_str(), outcome]).get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_and_adaptive_sampling() {
+        let policy = |config: AccessLogPolicyConfig| AccessLogPolicy::new(AccessLogListener::Reverse, &config).unwrap();
+        let fast = Duration::from_millis(5);
+        let ok = StatusCode::OK;
+
+        let errors_or_slow = policy(AccessLogPolicyConfig {
+            min_status: Some(400),
+            min_duration_ms: Some(1_000),
+            routes: vec!["api".to_string()],
+            ..AccessLogPolicyConfig::default()
+        });
+        assert_eq!(errors_or_slow.decide(Some("api"), ok, fast, 1, 0.0), "filtered");
+        assert_eq!(errors_or_slow.decide(Some("api"), StatusCode::NOT_FOUND, fast, 1, 0.0), "logged");
+        assert_eq!(errors_or_slow.decide(Some("api"), ok, Duration::from_secs(2), 1, 0.0), "logged");
+        assert_eq!(errors_or_slow.decide(Some("web"), StatusCode::NOT_FOUND, fast, 1, 0.0), "filtered");
+        assert_eq!(errors_or_slow.decide(None, StatusCode::NOT_FOUND, fast, 1, 0.0), "filtered");
+
+        let sampled = policy(AccessLogPolicyConfig { sample_rate: 0.1, ..AccessLogPolicyConfig::default() });
+        assert_eq!(sampled.decide(None, ok, fast, 1, 0.05), "logged");
+        assert_eq!(sampled.decide(None, ok, fast, 1, 0.5), "sampled_out");
+        assert_eq!(sampled.decide(None, StatusCode::BAD_GATEWAY, fast, 1, 0.5), "logged", "5xx are kept");
+
+        // 100 requests in second 10 with a budget of 10/s: the next second samples at 10%
+        let adaptive = policy(AccessLogPolicyConfig { max_per_second: Some(10), ..AccessLogPolicyConfig::default() });
+        let logged = (0..100).filter(|_| adaptive.decide(None, ok, fast, 10, 0.99) == "logged").count();
+        assert_eq!(logged, 10, "the budget caps the first second");
+        assert_eq!(adaptive.decide(None, ok, fast, 11, 0.05), "logged");
+        assert_eq!(adaptive.decide(None, ok, fast, 11, 0.5), "sampled_out");
+
+        assert!(AccessLogPolicy::new(
+            AccessLogListener::Forward,
+            &AccessLogPolicyConfig { sample_rate: 2.0, ..AccessLogPolicyConfig::default() }
+        )
+        .is_err());
+    }
+
+    fn record<'a>(metadata: &'a [(String, String)]) -> AccessRecord<'a> {
+        AccessRecord {
+            listener: AccessLogListener::Reverse,
+            client_ip: Some("192.0.2.7"),
+            method: &Method::GET,
+            uri: "/orders/7",
+            version: Version::HTTP_11,
+            status: StatusCode::OK,
+            elapsed: Duration::from_millis(42),
+            upstream_time: Some(Duration::from_millis(30)),
+            route: Some("orders"),
+            metadata,
+        }
+    }
+
+    fn writer(format: AccessLogFormat, template: Option<&str>) -> AccessLogWriter {
+        let config = AccessLogConfig { format, template: template.map(str::to_string), ..AccessLogConfig::default() };
+        AccessLogWriter::new(&config).unwrap()
+    }
+
+    #[test]
+    fn entries_follow_the_configured_layout() {
+        let metadata = vec![("team".to_string(), "payments".to_string())];
+        let record = record(&metadata);
+
+        assert_eq!(
+            writer(AccessLogFormat::Text, None).layout.format(&record),
+            "192.0.2.7 GET /orders/7 200 route=orders duration_ms=42 team=payments"
+        );
+
+        let common = writer(AccessLogFormat::Common, None).layout.format(&record);
+        assert!(common.starts_with("192.0.2.7 - - ["), "{}", common);
+        assert!(common.ends_with("] \"GET /orders/7 HTTP/1.1\" 200 -"), "{}", common);
+
+        let json: Value = serde_json::from_str(&writer(AccessLogFormat::Json, None).layout.format(&record)).unwrap();
+        assert_eq!(json["client_ip"], "192.0.2.7");
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["upstream_ms"], 30);
+        assert_eq!(json["route"], "orders");
+        assert_eq!(json["metadata"]["team"], "payments");
+
+        let template = writer(AccessLogFormat::Template, Some("$remote_addr \"$request\" $status $request_time $upstream_time $$route=$route"));
+        assert_eq!(template.layout.format(&record), "192.0.2.7 \"GET /orders/7 HTTP/1.1\" 200 0.042 0.030 $route=orders");
+        let forward = AccessRecord { listener: AccessLogListener::Forward, upstream_time: None, route: None, ..record };
+        assert_eq!(writer(AccessLogFormat::Template, Some("$listener $route $upstream_time")).layout.format(&forward), "forward - -");
+
+        let invalid = |template: Option<&str>| {
+            let config = AccessLogConfig { format: AccessLogFormat::Template, template: template.map(str::to_string), ..AccessLogConfig::default() };
+            AccessLogWriter::new(&config).is_err()
+        };
+        assert!(invalid(Some("$remote_addr $bytes_sent")));
+        assert!(invalid(None));
+    }
+
+    #[test]
+    fn files_rotate_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let mut file = RotatingFile::open(&AccessLogFileConfig { path: path.clone(), max_size_mb: 1, max_files: 2 }).unwrap();
+        file.max_bytes = 20;
+
+        for line in ["first entry", "second entry", "third entry", "fourth entry"] {
+            file.write_line(line);
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth entry\n");
+        assert_eq!(std::fs::read_to_string(numbered(&path, 1)).unwrap(), "third entry\n");
+        assert_eq!(std::fs::read_to_string(numbered(&path, 2)).unwrap(), "second entry\n");
+        assert!(!numbered(&path, 3).exists(), "only max_files rotated files are kept");
+
+        // A reopened file continues at its current size
+        let reopened = RotatingFile::open(&AccessLogFileConfig { path: path.clone(), max_size_mb: 1, max_files: 2 }).unwrap();
+        assert_eq!(reopened.size, 13);
+    }
+
+    #[test]
+    fn a_file_turns_on_entries_for_listeners_without_a_policy() {
+        let config = AccessLogConfig::default();
+        assert!(AccessLogPolicy::for_listener(AccessLogListener::Forward, &config).unwrap().is_none());
+
+        let config = AccessLogConfig {
+            file: Some(AccessLogFileConfig { path: PathBuf::from("access.log"), max_size_mb: 100, max_files: 5 }),
+            ..AccessLogConfig::default()
+        };
+        assert!(AccessLogPolicy::for_listener(AccessLogListener::Forward, &config).unwrap().is_some());
+    }
+}
@@ -0,0 +1,250 @@
+//! Admin API for operating a running proxy.
+//!
+//! The monitoring server reports; this listener also acts. It lists the routes being served,
+//! reports the health of each worker, shows the effective configuration with its secrets
+//! masked, pauses and resumes rate limit rules and starts a graceful drain. It has its own
+//! `listen_addr` so it can be bound to an interface the monitoring port is not.
+
+use crate::common::{IsolatedWorker, ProxyType, WorkerConfiguration, WorkerResourceLimits, constant_time_eq};
+use crate::config::AdminApiConfig;
+use crate::error::ProxyError;
+use crate::monitoring::json_response;
+use crate::privacy;
+use crate::proxy::ProxyHandle;
+use crate::shutdown;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::header::AUTHORIZATION;
+use hyper::server::conn::http1::Builder as ServerBuilder;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use log::{error, info, warn};
+use serde_json::json;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+pub struct AdminApi {
+    config: AdminApiConfig,
+    handle: ProxyHandle,
+}
+
+impl AdminApi {
+    pub fn new(config: AdminApiConfig, handle: ProxyHandle) -> Self {
+        Self { config, handle }
+    }
+
+    /// Binds the listener and serves it in the background. Without a `token` the listener must
+    /// be on a loopback address, since anyone who reaches it can drain the proxy.
+    pub async fn spawn(self) -> Result<(), ProxyError> {
+        if self.config.token.is_none() && !self.config.listen_addr.ip().is_loopback() {
+            return Err(ProxyError::Config(format!(
+                "admin_api.token is required to listen on {}, which is not a loopback address",
+                self.config.listen_addr
+            )));
+        }
+        let listener = tokio::net::TcpListener::bind(self.config.listen_addr).await?;
+        info!("Admin API listening on http://{}", self.config.listen_addr);
+
+        let api = Arc::new(self);
+        tokio::spawn(async move {
+            loop {
+                let (stream, remote_addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("Admin API accept failed: {}", e);
+                        continue;
+                    }
+                };
+                let api = api.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req| {
+                        let api = api.clone();
+                        async move { Ok::<_, Infallible>(api.route(&req).await) }
+                    });
+                    if let Err(err) = ServerBuilder::new().serve_connection(TokioIo::new(stream), service).await {
+                        error!("Admin API connection error from {}: {}", privacy::peer(&remote_addr), err);
+                    }
+                });
+            }
+        });
+        Ok(())
+    }
+
+    async fn route<B>(&self, req: &Request<B>) -> Response<Full<Bytes>> {
+        if !self.authorizes(req) {
+            return json_response(StatusCode::UNAUTHORIZED, json!({ "error": "admin token required" }));
+        }
+        let path = req.uri().path().trim_end_matches('/');
+        let segments: Vec<&str> = path.split('/').skip(1).collect();
+        match (req.method(), segments.as_slice()) {
+            (&Method::GET, ["routes"]) => json_response(StatusCode::OK, json!({ "routes": self.handle.route_summaries() })),
+            (&Method::GET, ["workers"]) => json_response(StatusCode::OK, json!({ "workers": self.workers() })),
+            (&Method::GET, ["config"]) => match self.handle.applied_config().to_redacted_json() {
+                Ok(config) => Response::builder()
+                    .status(StatusCode::OK)
+                    .header("Content-Type", "application/json")
+                    .body(Full::new(Bytes::from(config)))
+                    .unwrap(),
+                Err(e) => json_response(StatusCode::INTERNAL_SERVER_ERROR, json!({ "error": e.to_string() })),
+            },
+            (&Method::GET, ["rate-limits"]) => {
+                let snapshot = serde_json::to_value(self.handle.rate_limiter().snapshot().await)
+                    .unwrap_or_else(|e| json!({ "error": e.to_string() }));
+                json_response(StatusCode::OK, snapshot)
+            }
+            (&Method::POST, ["rate-limits", id, action @ ("pause" | "resume")]) => {
+                let paused = *action == "pause";
+                if !self.handle.rate_limiter().set_rule_paused(id, paused).await {
+                    return json_response(StatusCode::NOT_FOUND, json!({ "error": format!("no rate limit rule '{}'", id) }));
+                }
+                info!("Rate limit rule {} {} through the admin API", id, if paused { "paused" } else { "resumed" });
+                json_response(StatusCode::OK, json!({ "rule": id, "paused": paused }))
+            }
+            (&Method::POST, ["drain"]) => {
+                if !shutdown::request_drain() {
+                    warn!("Drain requested through the admin API");
+                }
+                json_response(StatusCode::ACCEPTED, json!({ "draining": true, "open_tunnels": shutdown::open_tunnels() }))
+            }
+            (_, ["routes" | "workers" | "config" | "rate-limits"]) => {
+                json_response(StatusCode::METHOD_NOT_ALLOWED, json!({ "error": "use GET" }))
+            }
+            (_, ["drain"] | ["rate-limits", _, "pause" | "resume"]) => {
+                json_response(StatusCode::METHOD_NOT_ALLOWED, json!({ "error": "use POST" }))
+            }
+            _ => json_response(StatusCode::NOT_FOUND, json!({ "error": "admin endpoint not found" })),
+        }
+    }
+
+    fn authorizes<B>(&self, req: &Request<B>) -> bool {
+        let Some(expected) = &self.config.token else {
+            return true;
+        };
+        req.headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| constant_time_eq(expected.expose().as_bytes(), token.trim().as_bytes()))
+    }
+
+    /// Health of the worker behind each proxy type, measured against the configured
+    /// `max_connections` when there is one.
+    fn workers(&self) -> Vec<serde_json::Value> {
+        let max_connections = self.handle.applied_config().max_connections;
+        self.handle
+            .monitoring_handles()
+            .all_metrics()
+            .into_iter()
+            .map(|(proxy_type, metrics)| {
+                let mut limits = WorkerResourceLimits::default_for_proxy_type(&proxy_type);
+                if let Some(max_connections) = max_connections {
+                    limits.max_connections = max_connections;
+                }
+                let configuration = WorkerConfiguration::default_for_proxy_type(&proxy_type);
+                let health = IsolatedWorker::new_with_metrics(proxy_type.clone(), limits, configuration, metrics)
+                    .health_check();
+                json!({
+                    "proxy_type": ProxyType::metric_label(&proxy_type),
+                    "status": if health.is_critical { "critical" } else if health.is_warning { "warning" } else { "healthy" },
+                    "active_connections": health.active_connections,
+                    "max_connections": health.max_connections,
+                    "connection_utilization": health.connection_utilization,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, RateLimitingConfig};
+    use crate::proxy::ProxyFactory;
+    use crate::rate_limit::parse_rule_spec;
+    use crate::secrets::Secret;
+
+    fn api(token: Option<&str>) -> AdminApi {
+        api_on("127.0.0.1:0", token)
+    }
+
+    fn api_on(listen_addr: &str, token: Option<&str>) -> AdminApi {
+        let config: Config = serde_json::from_value(json!({
+            "mode": "Forward",
+            "listen_addr": "127.0.0.1:0",
+            "monitoring": { "enabled": false },
+            "relay_proxy_password": "relay-secret"
+        }))
+        .unwrap();
+        let config = Config {
+            rate_limiting: Some(RateLimitingConfig {
+                enabled: true,
+                rules: vec![parse_rule_spec("api", "1/60s /api").unwrap()],
+                ..RateLimitingConfig::default()
+            }),
+            max_connections: Some(200),
+            ..config
+        };
+        let (_, handle) = ProxyFactory::create_reloadable(config).unwrap();
+        let admin = AdminApiConfig {
+            listen_addr: listen_addr.parse().unwrap(),
+            token: token.map(Secret::new),
+        };
+        AdminApi::new(admin, handle)
+    }
+
+    fn request(method: Method, path: &str, token: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder().method(method).uri(path);
+        if let Some(token) = token {
+            builder = builder.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        builder.body(()).unwrap()
+    }
+
+    async fn body(response: Response<Full<Bytes>>) -> serde_json::Value {
+        use http_body_util::BodyExt;
+        serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn endpoints_report_and_control_the_running_proxy() {
+        let api = api(None);
+
+        let workers = body(api.route(&request(Method::GET, "/workers", None)).await).await;
+        assert_eq!(workers["workers"][0]["proxy_type"], "forward");
+        assert_eq!(workers["workers"][0]["max_connections"], 200);
+        assert_eq!(workers["workers"][0]["status"], "healthy");
+
+        let config = body(api.route(&request(Method::GET, "/config", None)).await).await;
+        assert_eq!(config["mode"], "Forward");
+        assert_ne!(config["relay_proxy_password"], "relay-secret");
+
+        let routes = body(api.route(&request(Method::GET, "/routes", None)).await).await;
+        assert_eq!(routes["routes"], json!([]));
+
+        let paused = api.route(&request(Method::POST, "/rate-limits/api/pause", None)).await;
+        assert_eq!(paused.status(), StatusCode::OK);
+        let limits = body(api.route(&request(Method::GET, "/rate-limits", None)).await).await;
+        assert_eq!(limits["rules"][0]["paused"], true);
+        let missing = api.route(&request(Method::POST, "/rate-limits/other/resume", None)).await;
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        assert_eq!(api.route(&request(Method::GET, "/drain", None)).await.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(api.route(&request(Method::GET, "/nothing", None)).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn a_configured_token_is_required() {
+        let api = api(Some("s3cret"));
+        assert_eq!(api.route(&request(Method::GET, "/routes", None)).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(api.route(&request(Method::GET, "/routes", Some("wrong"))).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(api.route(&request(Method::GET, "/routes", Some("s3cret"))).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn a_listener_beyond_loopback_needs_a_token() {
+        assert!(matches!(api_on("0.0.0.0:0", None).spawn().await, Err(ProxyError::Config(_))));
+        assert!(api_on("0.0.0.0:0", Some("s3cret")).spawn().await.is_ok());
+        assert!(api_on("127.0.0.1:0", None).spawn().await.is_ok());
+    }
+}
@@ -0,0 +1,213 @@
+use crate::config::AggregateConfig;
+use crate::error::ProxyError;
+use futures::future::join_all;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{Response, StatusCode};
+use log::warn;
+use serde_json::{Map, Value};
+use std::future::Future;
+use tokio::time::{Duration, timeout};
+use url::Url;
+
+pub struct AggregateBranch {
+    pub name: String,
+    pub url: Url,
+    timeout: Duration,
+}
+
+/// Scatter-gather composition: one request is sent to every branch in parallel and the JSON
+/// responses are merged into a single body.
+pub struct Aggregator {
+    branches: Vec<AggregateBranch>,
+    template: Option<Value>,
+    require_all: bool,
+    max_body_bytes: usize,
+}
+
+impl Aggregator {
+    pub fn new(config: &AggregateConfig) -> Result<Self, ProxyError> {
+        if config.branches.is_empty() {
+            return Err(ProxyError::Config("aggregate.branches must not be empty".to_string()));
+        }
+        let mut branches = Vec::with_capacity(config.branches.len());
+        for branch in &config.branches {
+            if branches.iter().any(|b: &AggregateBranch| b.name == branch.name) {
+                return Err(ProxyError::Config(format!("Duplicate aggregate branch name: {}", branch.name)));
+            }
+            let url = Url::parse(&branch.url).map_err(|e| {
+                ProxyError::Config(format!("Invalid URL for aggregate branch {}: {}", branch.name, e))
+            })?;
+            branches.push(AggregateBranch {
+                name: branch.name.clone(),
+                url,
+                timeout: Duration::from_millis(branch.timeout_ms.unwrap_or(config.timeout_ms)),
+            });
+        }
+        Ok(Self {
+            branches,
+            template: config.template.clone(),
+            require_all: config.require_all,
+            max_body_bytes: config.max_body_bytes,
+        })
+    }
+
+    pub fn max_body_bytes(&self) -> usize {
+        self.max_body_bytes
+    }
+
+    /// Calls every branch through `send` and merges the results into one JSON response.
+    pub async fn gather<'a, F, Fut>(&'a self, send: F) -> Response<Full<Bytes>>
+    where
+        F: Fn(&'a AggregateBranch) -> Fut,
+        Fut: Future<Output = Result<(StatusCode, Bytes), ProxyError>>,
+    {
+        let calls = self.branches.iter().map(|branch| {
+            let call = send(branch);
+            async move {
+                let value = match timeout(branch.timeout, call).await {
+                    Ok(Ok((status, body))) if status.is_success() => Some(parse_body(&body)),
+                    Ok(Ok((status, _))) => {
+                        warn!("Aggregate branch {} returned {}", branch.name, status);
+                        None
+                    }
+                    Ok(Err(e)) => {
+                        warn!("Aggregate branch {} failed: {}", branch.name, e);
+                        None
+                    }
+                    Err(_) => {
+                        warn!("Aggregate branch {} timed out after {:?}", branch.name, branch.timeout);
+                        None
+                    }
+                };
+                (branch.name.as_str(), value)
+            }
+        });
+        let results = join_all(calls).await;
+
+        let failed: Vec<&str> = results
+            .iter()
+            .filter(|(_, value)| value.is_none())
+            .map(|(name, _)| *name)
+            .collect();
+        if self.require_all && !failed.is_empty() {
+            let body = serde_json::json!({ "error": "aggregate branches failed", "branches": failed });
+            return json_response(StatusCode::BAD_GATEWAY, &body, &failed);
+        }
+
+        let merged = self.merge(
+            results
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.unwrap_or(Value::Null)))
+                .collect(),
+        );
+        json_response(StatusCode::OK, &merged, &failed)
+    }
+
+    /// Fills the template (strings of the form `{{branch}}` are replaced by that branch's
+    /// response), or returns an object keyed by branch name when no template is set.
+    fn merge(&self, results: Map<String, Value>) -> Value {
+        match &self.template {
+            Some(template) => fill_template(template, &results),
+            None => Value::Object(results),
+        }
+    }
+}
+
+fn parse_body(body: &Bytes) -> Value {
+    serde_json::from_slice(body)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).into_owned()))
+}
+
+fn fill_template(template: &Value, results: &Map<String, Value>) -> Value {
+    match template {
+        Value::String(text) => text
+            .strip_prefix("{{")
+            .and_then(|rest| rest.strip_suffix("}}"))
+            .and_then(|name| results.get(name.trim()))
+            .cloned()
+            .unwrap_or_else(|| template.clone()),
+        Value::Array(items) => Value::Array(items.iter().map(|item| fill_template(item, results)).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), fill_template(value, results)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn json_response(status: StatusCode, body: &Value, failed: &[&str]) -> Response<Full<Bytes>> {
+    let mut builder = Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json");
+    if !failed.is_empty() {
+        builder = builder.header("X-Aggregate-Failed", failed.join(","));
+    }
+    builder
+        .body(Full::new(Bytes::from(body.to_string())))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AggregateBranchConfig;
+    use http_body_util::BodyExt;
+
+    fn aggregator(template: Option<Value>, require_all: bool) -> Aggregator {
+        Aggregator::new(&AggregateConfig {
+            branches: vec![
+                AggregateBranchConfig { name: "user".to_string(), url: "http://users/api/me".to_string(), timeout_ms: None },
+                AggregateBranchConfig { name: "orders".to_string(), url: "http://orders/api/recent".to_string(), timeout_ms: Some(20) },
+            ],
+            template,
+            require_all,
+            ..AggregateConfig::default()
+        })
+        .unwrap()
+    }
+
+    async fn send(branch: &AggregateBranch) -> Result<(StatusCode, Bytes), ProxyError> {
+        match branch.name.as_str() {
+            "user" => Ok((StatusCode::OK, Bytes::from(r#"{"name":"ada"}"#))),
+            _ => {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok((StatusCode::OK, Bytes::from("[]")))
+            }
+        }
+    }
+
+    async fn body_json(response: Response<Full<Bytes>>) -> Value {
+        serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn merges_branches_into_template_and_nulls_timeouts() {
+        let template = serde_json::json!({ "profile": "{{user}}", "recent": "{{ orders }}", "version": 1 });
+        let response = aggregator(Some(template), false).gather(send).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["X-Aggregate-Failed"], "orders");
+        assert_eq!(
+            body_json(response).await,
+            serde_json::json!({ "profile": { "name": "ada" }, "recent": null, "version": 1 })
+        );
+    }
+
+    #[tokio::test]
+    async fn require_all_fails_when_a_branch_fails() {
+        let response = aggregator(None, true).gather(send).await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+        let duplicate = AggregateConfig {
+            branches: vec![
+                AggregateBranchConfig { name: "a".to_string(), url: "http://a".to_string(), timeout_ms: None },
+                AggregateBranchConfig { name: "a".to_string(), url: "http://b".to_string(), timeout_ms: None },
+            ],
+            ..AggregateConfig::default()
+        };
+        assert!(Aggregator::new(&duplicate).is_err());
+    }
+}
@@ -0,0 +1,393 @@
+use crate::common::{ResponseBuilder, constant_time_eq};
+use crate::config::{ApiKeyEntryConfig, ApiKeysConfig, RateLimitWindowConfig, RateLimitingConfig};
+use crate::error::ProxyError;
+use crate::notifications::{Notification, NotificationKind};
+use crate::rate_limit::RateLimiter;
+use http_body_util::{BodyExt, Empty, Full};
+use hyper::body::Bytes;
+use hyper::header::{HeaderValue, WWW_AUTHENTICATE};
+use hyper::{Request, Response, StatusCode, Uri};
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use log::{debug, info, warn};
+use prometheus::{IntCounterVec, Opts, Registry};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+use url::form_urlencoded;
+
+/// Label used for requests that could not be tied to a known key.
+const UNKNOWN_KEY: &str = "-";
+
+struct ApiKey {
+    id: String,
+    secret: Vec<u8>,
+    tier: Option<String>,
+    /// Route ids the key may call; empty allows every route that requires a key
+    routes: Vec<String>,
+}
+
+impl From<ApiKeyEntryConfig> for ApiKey {
+    fn from(entry: ApiKeyEntryConfig) -> Self {
+        Self {
+            id: entry.id,
+            secret: entry.key.expose().as_bytes().to_vec(),
+            tier: entry.tier,
+            routes: entry.routes,
+        }
+    }
+}
+
+/// Why a request was refused by the API-key filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiKeyRejection {
+    Missing,
+    Invalid,
+    RouteNotAllowed { key_id: String },
+    RateLimited { key_id: String, tier: String, retry_after_secs: u64 },
+}
+
+impl ApiKeyRejection {
+    pub fn into_response(self, header_name: &str) -> Response<Full<Bytes>> {
+        match self {
+            ApiKeyRejection::Missing | ApiKeyRejection::Invalid => {
+                let message = if self == ApiKeyRejection::Missing { "API key required" } else { "Invalid API key" };
+                let mut response = ResponseBuilder::error(StatusCode::UNAUTHORIZED, message);
+                if let Ok(value) = HeaderValue::from_str(&format!("ApiKey header=\"{}\"", header_name)) {
+                    response.headers_mut().insert(WWW_AUTHENTICATE, value);
+                }
+                response
+            }
+            ApiKeyRejection::RouteNotAllowed { .. } => {
+                ResponseBuilder::error(StatusCode::FORBIDDEN, "API key is not allowed to access this route")
+            }
+            ApiKeyRejection::RateLimited { tier, retry_after_secs, .. } => {
+                ResponseBuilder::too_many_requests(&tier, retry_after_secs)
+            }
+        }
+    }
+}
+
+/// Validates API keys for routes that set `require_api_key`.
+///
+/// Keys come from the inline config, an optional JSON file and an optional URL; the
+/// file and URL are re-read every `refresh_secs` by [`ApiKeyStore::refresh_loop`].
+pub struct ApiKeyStore {
+    config: ApiKeysConfig,
+    keys: RwLock<Vec<ApiKey>>,
+    tiers: HashMap<String, RateLimiter>,
+}
+
+impl ApiKeyStore {
+    pub fn new(config: ApiKeysConfig) -> Result<Self, ProxyError> {
+        let mut tiers = HashMap::new();
+        for tier in &config.tiers {
+            let limiter = RateLimiter::new(Some(RateLimitingConfig {
+                enabled: true,
+                default_limit: Some(RateLimitWindowConfig {
+                    limit: tier.limit,
+                    window_secs: tier.window_secs,
+                }),
+                ..RateLimitingConfig::default()
+            }));
+            tiers.insert(tier.name.clone(), limiter);
+        }
+
+        let store = Self {
+            config,
+            keys: RwLock::new(Vec::new()),
+            tiers,
+        };
+        let mut keys = store.config.keys.clone();
+        if let Some(path) = &store.config.keys_file {
+            keys.extend(load_keys_file(path)?);
+        }
+        store.replace_keys(keys)?;
+        Ok(store)
+    }
+
+    /// Store without keys: every route that requires a key rejects all requests.
+    pub fn empty() -> Self {
+        Self {
+            config: ApiKeysConfig::default(),
+            keys: RwLock::new(Vec::new()),
+            tiers: HashMap::new(),
+        }
+    }
+
+    pub fn header_name(&self) -> &str {
+        &self.config.header_name
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.keys.read().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    fn replace_keys(&self, entries: Vec<ApiKeyEntryConfig>) -> Result<(), ProxyError> {
+        for entry in &entries {
+            if entry.key.is_empty() {
+                return Err(ProxyError::Config(format!("API key '{}' has an empty key", entry.id)));
+            }
+            if let Some(tier) = entry.tier.as_ref().filter(|tier| !self.tiers.contains_key(*tier)) {
+                return Err(ProxyError::Config(format!(
+                    "API key '{}' references unknown tier '{}'",
+                    entry.id, tier
+                )));
+            }
+        }
+        let keys: Vec<ApiKey> = entries.into_iter().map(ApiKey::from).collect();
+        *self.keys.write().unwrap_or_else(|e| e.into_inner()) = keys;
+        Ok(())
+    }
+
+    /// Extracts and validates the key for a request to `route_id`, returning the key id.
+    pub async fn check<B>(&self, req: &Request<B>, route_id: &str) -> Result<String, ApiKeyRejection> {
+        let result = self.check_inner(req, route_id).await;
+        let (key_id, outcome) = match &result {
+            Ok(key_id) => (key_id.as_str(), "allowed"),
+            Err(ApiKeyRejection::Missing) => (UNKNOWN_KEY, "missing"),
+            Err(ApiKeyRejection::Invalid) => (UNKNOWN_KEY, "invalid"),
+            Err(ApiKeyRejection::RouteNotAllowed { key_id }) => (key_id.as_str(), "route_denied"),
+            Err(ApiKeyRejection::RateLimited { key_id, .. }) => (key_id.as_str(), "rate_limited"),
+        };
+        api_key_telemetry().record(key_id, outcome);
+        result
+    }
+
+    async fn check_inner<B>(&self, req: &Request<B>, route_id: &str) -> Result<String, ApiKeyRejection> {
+        let presented = self.extract_key(req).ok_or(ApiKeyRejection::Missing)?;
+
+        let (key_id, tier) = {
+            let keys = self.keys.read().unwrap_or_else(|e| e.into_inner());
+            // Compare against every key so the match position is not observable through timing
+            let matched = keys.iter().fold(None, |found, key| {
+                if constant_time_eq(key.secret.as_slice(), presented.as_bytes()) { Some(key) } else { found }
+            });
+            let key = matched.ok_or(ApiKeyRejection::Invalid)?;
+            if !key.routes.is_empty() && !key.routes.iter().any(|route| route == route_id) {
+                return Err(ApiKeyRejection::RouteNotAllowed { key_id: key.id.clone() });
+            }
+            (key.id.clone(), key.tier.clone())
+        };
+
+        if let Some((tier, limiter)) = tier.as_ref().and_then(|tier| self.tiers.get_key_value(tier))
+            && let Err(hit) = limiter.check_request(&key_id, req.method(), req.uri().path(), req.headers()).await
+        {
+            debug!("API key {} exceeded tier {}", key_id, tier);
+            return Err(ApiKeyRejection::RateLimited {
+                key_id,
+                tier: tier.clone(),
+                retry_after_secs: hit.retry_after_secs,
+            });
+        }
+
+        Ok(key_id)
+    }
+
+    fn extract_key<B>(&self, req: &Request<B>) -> Option<String> {
+        let from_header = req.headers()
+            .get(self.config.header_name.as_str())
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty());
+        if from_header.is_some() {
+            return from_header;
+        }
+
+        let param = self.config.query_param.as_deref()?;
+        let query = req.uri().query()?;
+        form_urlencoded::parse(query.as_bytes())
+            .find(|(name, _)| name == param)
+            .map(|(_, value)| value.into_owned())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Periodically reloads keys from `keys_file` and `keys_url`; keeps the previous set on failure.
+    pub async fn refresh_loop(self: Arc<Self>) {
+        if self.config.keys_file.is_none() && self.config.keys_url.is_none() {
+            return;
+        }
+
+        let interval = Duration::from_secs(self.config.refresh_secs.max(1));
+        let mut first = true;
+        loop {
+            // The file is already loaded at startup; the URL is fetched immediately
+            if !first || self.config.keys_url.is_some() {
+                match self.load_external_keys().await {
+                    Ok(keys) => match self.replace_keys(keys) {
+                        Ok(()) => info!("Loaded {} API keys", self.key_count()),
+                        Err(e) => {
+                            warn!("Rejected reloaded API keys: {}", e);
+                            Notification::new(NotificationKind::ConfigReloadFailed, format!("Rejected reloaded API keys: {}", e))
+                                .send();
+                        }
+                    },
+                    Err(e) => {
+                        warn!("Failed to reload API keys: {}", e);
+                        Notification::new(NotificationKind::ConfigReloadFailed, format!("Failed to reload API keys: {}", e))
+                            .send();
+                    }
+                }
+            }
+            first = false;
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    async fn load_external_keys(&self) -> Result<Vec<ApiKeyEntryConfig>, ProxyError> {
+        let mut keys = self.config.keys.clone();
+        if let Some(path) = &self.config.keys_file {
+            keys.extend(load_keys_file(path)?);
+        }
+        if let Some(url) = &self.config.keys_url {
+            keys.extend(fetch_keys_url(url).await?);
+        }
+        Ok(keys)
+    }
+}
+
+fn load_keys_file(path: &str) -> Result<Vec<ApiKeyEntryConfig>, ProxyError> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| ProxyError::Config(format!("Failed to read API key file '{}': {}", path, e)))?;
+    serde_json::from_str(&content)
+        .map_err(|e| ProxyError::Config(format!("Invalid API key file '{}': {}", path, e)))
+}
+
+async fn fetch_keys_url(url: &str) -> Result<Vec<ApiKeyEntryConfig>, ProxyError> {
+    let uri: Uri = url.parse()
+        .map_err(|e| ProxyError::Config(format!("Invalid API key URL '{}': {}", url, e)))?;
+    let client = Client::builder(TokioExecutor::new()).build::<_, Empty<Bytes>>(HttpsConnector::new());
+    let request = Request::get(uri)
+        .header("Accept", "application/json")
+        .body(Empty::new())
+        .map_err(|e| ProxyError::Http(e.to_string()))?;
+
+    let response = tokio::time::timeout(Duration::from_secs(10), client.request(request))
+        .await
+        .map_err(|_| ProxyError::Connection(format!("Timed out fetching API keys from {}", url)))?
+        .map_err(|e| ProxyError::Connection(format!("Failed to fetch API keys from {}: {}", url, e)))?;
+    if !response.status().is_success() {
+        return Err(ProxyError::Http(format!("API key URL {} returned {}", url, response.status())));
+    }
+
+    let body = response.into_body().collect().await
+        .map_err(|e| ProxyError::Http(format!("Failed to read API keys from {}: {}", url, e)))?
+        .to_bytes();
+    serde_json::from_slice(&body)
+        .map_err(|e| ProxyError::Config(format!("Invalid API key list from {}: {}", url, e)))
+}
+
+struct ApiKeyTelemetry {
+    requests: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl ApiKeyTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "api_key_requests_total",
+            "Requests checked by the API-key filter, by key id and outcome",
+        ).namespace("bifrost");
+        Self {
+            requests: IntCounterVec::new(opts, &["key", "outcome"]).expect("api_key_requests_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.requests.clone())) {
+            warn!("Failed to register api_key_requests_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    fn record(&self, key: &str, outcome: &str) {
+        self.requests.with_label_values(&[key, outcome]).inc();
+    }
+}
+
+fn api_key_telemetry() -> &'static ApiKeyTelemetry {
+    static TELEMETRY: OnceLock<ApiKeyTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(ApiKeyTelemetry::new)
+}
+
+/// Registers the per-key usage counters with the shared Prometheus registry.
+pub fn register_api_key_metrics(registry: &Registry) {
+    api_key_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApiKeyTierConfig;
+    use crate::secrets::Secret;
+
+    fn entry(id: &str, key: &str, tier: Option<&str>, routes: &[&str]) -> ApiKeyEntryConfig {
+        ApiKeyEntryConfig {
+            id: id.to_string(),
+            key: Secret::new(key),
+            tier: tier.map(str::to_string),
+            routes: routes.iter().map(|route| route.to_string()).collect(),
+        }
+    }
+
+    fn store() -> ApiKeyStore {
+        ApiKeyStore::new(ApiKeysConfig {
+            query_param: Some("api_key".to_string()),
+            keys: vec![
+                entry("mobile", "k-mobile", Some("basic"), &[]),
+                entry("billing", "k-billing", None, &["billing"]),
+            ],
+            tiers: vec![ApiKeyTierConfig { name: "basic".to_string(), limit: 2, window_secs: 60 }],
+            ..ApiKeysConfig::default()
+        }).unwrap()
+    }
+
+    fn request(header: Option<&str>, uri: &str) -> Request<()> {
+        let mut builder = Request::get(uri);
+        if let Some(key) = header {
+            builder = builder.header("X-API-Key", key);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn validates_keys_from_header_or_query() {
+        let store = store();
+        assert_eq!(store.check(&request(Some("k-billing"), "/pay"), "billing").await.unwrap(), "billing");
+        assert_eq!(store.check(&request(None, "/pay?api_key=k-billing"), "billing").await.unwrap(), "billing");
+        assert_eq!(store.check(&request(None, "/pay"), "billing").await, Err(ApiKeyRejection::Missing));
+        assert_eq!(store.check(&request(Some("k-wrong"), "/pay"), "billing").await, Err(ApiKeyRejection::Invalid));
+    }
+
+    #[tokio::test]
+    async fn enforces_allowed_routes_and_tiers() {
+        let store = store();
+        assert!(matches!(
+            store.check(&request(Some("k-billing"), "/"), "search").await,
+            Err(ApiKeyRejection::RouteNotAllowed { .. })
+        ));
+
+        for _ in 0..2 {
+            assert!(store.check(&request(Some("k-mobile"), "/"), "search").await.is_ok());
+        }
+        let rejection = store.check(&request(Some("k-mobile"), "/"), "search").await.unwrap_err();
+        assert!(matches!(rejection, ApiKeyRejection::RateLimited { ref tier, .. } if tier == "basic"));
+        assert_eq!(rejection.into_response("X-API-Key").status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn rejects_unknown_tiers() {
+        let result = ApiKeyStore::new(ApiKeysConfig {
+            keys: vec![entry("a", "k", Some("gold"), &[])],
+            ..ApiKeysConfig::default()
+        });
+        assert!(matches!(result, Err(ProxyError::Config(_))));
+    }
+}
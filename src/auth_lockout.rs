@@ -0,0 +1,201 @@
+use crate::config::AuthLockoutConfig;
+use crate::privacy;
+use log::warn;
+use prometheus::{IntCounterVec, Opts, Registry};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Upper bound on tracked clients; expired entries are pruned beyond this.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+#[derive(Debug)]
+struct FailureRecord {
+    failures: u32,
+    window_start: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Tracks failed proxy-auth attempts per client IP.
+///
+/// Each failure past the first delays the 407 answer exponentially, and reaching
+/// `max_failures` inside the window locks the client out for `lockout_secs`.
+pub struct AuthLockout {
+    config: AuthLockoutConfig,
+    records: Mutex<HashMap<String, FailureRecord>>,
+}
+
+impl AuthLockout {
+    pub fn new(config: AuthLockoutConfig) -> Self {
+        Self {
+            config,
+            records: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::new(AuthLockoutConfig {
+            enabled: false,
+            ..AuthLockoutConfig::default()
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Remaining lockout for `client_ip`, if it is currently locked out.
+    pub fn locked_for(&self, client_ip: &str) -> Option<Duration> {
+        let records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let locked_until = records.get(client_ip)?.locked_until?;
+        let now = Instant::now();
+        (locked_until > now).then(|| locked_until - now)
+    }
+
+    /// Records a failed attempt and returns how long to wait before answering.
+    pub fn record_failure(&self, client_ip: &str) -> Duration {
+        let now = Instant::now();
+        let window = Duration::from_secs(self.config.window_secs);
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        if records.len() >= MAX_TRACKED_CLIENTS {
+            records.retain(|_, record| {
+                record.locked_until.is_some_and(|until| until > now)
+                    || now.duration_since(record.window_start) < window
+            });
+        }
+
+        let record = records.entry(client_ip.to_string()).or_insert(FailureRecord {
+            failures: 0,
+            window_start: now,
+            locked_until: None,
+        });
+        let lockout_expired = record.locked_until.is_some_and(|until| until <= now);
+        if lockout_expired || now.duration_since(record.window_start) >= window {
+            *record = FailureRecord { failures: 0, window_start: now, locked_until: None };
+        }
+
+        record.failures += 1;
+        auth_telemetry().record("failure");
+
+        if record.failures >= self.config.max_failures && record.locked_until.is_none() {
+            record.locked_until = Some(now + Duration::from_secs(self.config.lockout_secs));
+            auth_telemetry().record("lockout");
+            warn!(
+                "Security: client {} locked out for {}s after {} failed proxy authentication attempts",
+                privacy::client_ip(client_ip), self.config.lockout_secs, record.failures
+            );
+        }
+
+        self.delay_for(record.failures)
+    }
+
+    /// Clears the failure history after a successful login.
+    pub fn record_success(&self, client_ip: &str) {
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        if !records.is_empty() {
+            records.remove(client_ip);
+        }
+    }
+
+    /// Counts a request refused because the client is locked out.
+    pub fn record_locked_rejection(&self) {
+        auth_telemetry().record("locked_reject");
+    }
+
+    fn delay_for(&self, failures: u32) -> Duration {
+        if failures <= 1 || self.config.base_delay_ms == 0 {
+            return Duration::ZERO;
+        }
+        let factor = 1u64 << (failures - 2).min(20);
+        Duration::from_millis(self.config.base_delay_ms.saturating_mul(factor).min(self.config.max_delay_ms))
+    }
+}
+
+struct AuthTelemetry {
+    events: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl AuthTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "proxy_auth_events_total",
+            "Proxy authentication failures, lockouts and requests refused while locked out",
+        ).namespace("bifrost");
+        Self {
+            events: IntCounterVec::new(opts, &["event"]).expect("proxy_auth_events_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.events.clone())) {
+            warn!("Failed to register proxy_auth_events_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    fn record(&self, event: &str) {
+        self.events.with_label_values(&[event]).inc();
+    }
+}
+
+fn auth_telemetry() -> &'static AuthTelemetry {
+    static TELEMETRY: OnceLock<AuthTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(AuthTelemetry::new)
+}
+
+/// Registers the proxy authentication counters with the shared Prometheus registry.
+pub fn register_auth_lockout_metrics(registry: &Registry) {
+    auth_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lockout(max_failures: u32) -> AuthLockout {
+        AuthLockout::new(AuthLockoutConfig {
+            max_failures,
+            base_delay_ms: 100,
+            max_delay_ms: 300,
+            ..AuthLockoutConfig::default()
+        })
+    }
+
+    #[test]
+    fn delays_grow_exponentially_and_are_capped() {
+        let lockout = lockout(10);
+        let delays: Vec<u64> = (0..5)
+            .map(|_| lockout.record_failure("10.0.0.1").as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![0, 100, 200, 300, 300]);
+    }
+
+    #[test]
+    fn locks_out_after_max_failures_per_client() {
+        let lockout = lockout(3);
+        for _ in 0..2 {
+            lockout.record_failure("10.0.0.1");
+        }
+        assert!(lockout.locked_for("10.0.0.1").is_none());
+
+        lockout.record_failure("10.0.0.1");
+        assert!(lockout.locked_for("10.0.0.1").unwrap() > Duration::from_secs(290));
+        assert!(lockout.locked_for("10.0.0.2").is_none());
+    }
+
+    #[test]
+    fn success_resets_failure_history() {
+        let lockout = lockout(3);
+        lockout.record_failure("10.0.0.1");
+        lockout.record_failure("10.0.0.1");
+        lockout.record_success("10.0.0.1");
+        assert_eq!(lockout.record_failure("10.0.0.1"), Duration::ZERO);
+    }
+}
@@ -0,0 +1,175 @@
+//! Fail2ban-style automatic bans.
+//!
+//! 401, 403, 404 and 429 responses are counted per client address over a fixed window. A client
+//! that reaches the threshold of one of these statuses is put on the shared [`BanList`] and from
+//! then on refused at accept by every listener until the ban runs out or is lifted through the
+//! monitoring server.
+
+use crate::ban_list::BanList;
+use crate::config::AutoBanConfig;
+use crate::error::ProxyError;
+use crate::privacy;
+use crate::security_log::{SecurityEvent, SecurityEventKind};
+use hyper::StatusCode;
+use ipnet::IpNet;
+use log::warn;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Statuses that are counted, in the order of `ClientTally::counts`.
+const COUNTED: [StatusCode; 4] = [
+    StatusCode::UNAUTHORIZED,
+    StatusCode::FORBIDDEN,
+    StatusCode::NOT_FOUND,
+    StatusCode::TOO_MANY_REQUESTS,
+];
+
+pub struct AutoBan {
+    /// Threshold per entry of `COUNTED`; `None` when that status is not counted
+    thresholds: [Option<u32>; 4],
+    window: Duration,
+    ban: Duration,
+    exempt: Vec<IpNet>,
+    tallies: Mutex<Tallies>,
+    bans: Arc<BanList>,
+}
+
+struct Tallies {
+    clients: HashMap<String, ClientTally>,
+    last_sweep: Instant,
+}
+
+struct ClientTally {
+    window_start: Instant,
+    counts: [u32; 4],
+}
+
+impl AutoBan {
+    pub fn new(config: &AutoBanConfig, bans: Arc<BanList>) -> Result<Self, ProxyError> {
+        if config.window_secs == 0 || config.ban_secs == 0 {
+            return Err(ProxyError::Config("auto_ban.window_secs and ban_secs must be greater than 0".to_string()));
+        }
+        let exempt = config
+            .exempt
+            .iter()
+            .map(|entry| {
+                let entry = entry.trim();
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| ProxyError::Config(format!("auto_ban.exempt: invalid address or CIDR '{}'", entry)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let thresholds = &config.thresholds;
+        Ok(Self {
+            thresholds: [
+                thresholds.unauthorized,
+                thresholds.forbidden,
+                thresholds.not_found,
+                thresholds.too_many_requests,
+            ]
+            .map(|threshold| threshold.filter(|count| *count > 0)),
+            window: Duration::from_secs(config.window_secs),
+            ban: Duration::from_secs(config.ban_secs),
+            exempt,
+            tallies: Mutex::new(Tallies { clients: HashMap::new(), last_sweep: Instant::now() }),
+            bans,
+        })
+    }
+
+    /// Counts a response to `client_ip` and bans the client once it reaches a threshold.
+    pub fn record(&self, client_ip: &str, status: StatusCode) {
+        let Some(index) = COUNTED.iter().position(|counted| *counted == status) else {
+            return;
+        };
+        let Some(threshold) = self.thresholds[index] else {
+            return;
+        };
+        if client_ip
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| self.exempt.iter().any(|net| net.contains(&ip.to_canonical())))
+        {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut tallies = self.tallies.lock().unwrap_or_else(|e| e.into_inner());
+        if now.saturating_duration_since(tallies.last_sweep) >= self.window {
+            let window = self.window;
+            tallies.clients.retain(|_, tally| now.saturating_duration_since(tally.window_start) < window);
+            tallies.last_sweep = now;
+        }
+        let tally = tallies
+            .clients
+            .entry(client_ip.to_string())
+            .or_insert_with(|| ClientTally { window_start: now, counts: [0; 4] });
+        if now.saturating_duration_since(tally.window_start) >= self.window {
+            tally.window_start = now;
+            tally.counts = [0; 4];
+        }
+        tally.counts[index] += 1;
+        if tally.counts[index] < threshold {
+            return;
+        }
+        tallies.clients.remove(client_ip);
+        drop(tallies);
+
+        let reason = format!("{} {} responses in {}s", threshold, status.as_u16(), self.window.as_secs());
+        warn!("Banning {} for {}s: {}", privacy::client_ip(client_ip), self.ban.as_secs(), reason);
+        self.bans.ban(client_ip, self.ban, &reason);
+        SecurityEvent::new(SecurityEventKind::ClientBanned, reason)
+            .client_ip(Some(client_ip))
+            .emit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AutoBanThresholds;
+
+    #[test]
+    fn bans_clients_over_a_threshold() {
+        let bans = Arc::new(BanList::new());
+        let auto_ban = AutoBan::new(
+            &AutoBanConfig {
+                thresholds: AutoBanThresholds { not_found: Some(3), unauthorized: Some(2), ..AutoBanThresholds::default() },
+                exempt: vec!["10.1.0.0/16".to_string()],
+                ..AutoBanConfig::default()
+            },
+            bans.clone(),
+        )
+        .unwrap();
+
+        for _ in 0..2 {
+            auto_ban.record("192.0.2.1", StatusCode::NOT_FOUND);
+            auto_ban.record("192.0.2.1", StatusCode::OK);
+            auto_ban.record("192.0.2.1", StatusCode::FORBIDDEN);
+        }
+        assert!(!bans.is_banned("192.0.2.1"), "403 is not counted and 404 is below its threshold");
+        auto_ban.record("192.0.2.1", StatusCode::NOT_FOUND);
+        assert!(bans.is_banned("192.0.2.1"));
+
+        auto_ban.record("192.0.2.2", StatusCode::UNAUTHORIZED);
+        auto_ban.record("192.0.2.3", StatusCode::UNAUTHORIZED);
+        assert!(!bans.is_banned("192.0.2.2"), "counts are per client");
+
+        for _ in 0..10 {
+            auto_ban.record("10.1.4.5", StatusCode::NOT_FOUND);
+        }
+        assert!(!bans.is_banned("10.1.4.5"), "exempt clients are never banned");
+
+        let listed = bans.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].client, "192.0.2.1");
+        assert_eq!(listed[0].reason, "3 404 responses in 60s");
+        assert!(bans.lift("192.0.2.1"));
+        assert!(!bans.is_banned("192.0.2.1"));
+        assert!(!bans.lift("192.0.2.1"));
+
+        let invalid = AutoBanConfig { exempt: vec!["not-an-ip".to_string()], ..AutoBanConfig::default() };
+        assert!(AutoBan::new(&invalid, bans).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Temporarily banned client addresses.
+
+use log::{debug, warn};
+use prometheus::{IntCounter, IntGauge, Opts, Registry};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Clients refused until their ban runs out. Expired bans are dropped when next looked at.
+#[derive(Default)]
+pub struct BanList {
+    bans: Mutex<HashMap<String, Ban>>,
+    /// Where bans and lifts are reported in cluster mode
+    changes: OnceLock<UnboundedSender<BanChange>>,
+}
+
+struct Ban {
+    until: Instant,
+    reason: String,
+    since: Instant,
+}
+
+/// A ban or lift made on this instance, to be shared with the rest of the cluster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BanChange {
+    Banned { client: String, reason: String, duration: Duration },
+    Lifted { client: String },
+}
+
+/// A current ban, as listed by the monitoring server's bans endpoint.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct BanEntry {
+    pub client: String,
+    pub reason: String,
+    pub expires_in_secs: u64,
+}
+
+impl BanList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports every later ban and lift to `changes`; only the first call has an effect.
+    pub fn report_changes(&self, changes: UnboundedSender<BanChange>) {
+        let _ = self.changes.set(changes);
+    }
+
+    /// Bans `client_ip` for `duration`; an existing ban is extended, never shortened.
+    pub fn ban(&self, client_ip: &str, duration: Duration, reason: &str) {
+        if self.insert(client_ip, duration, reason)
+            && let Some(changes) = self.changes.get()
+        {
+            let _ = changes.send(BanChange::Banned {
+                client: client_ip.to_string(),
+                reason: reason.to_string(),
+                duration,
+            });
+        }
+    }
+
+    fn insert(&self, client_ip: &str, duration: Duration, reason: &str) -> bool {
+        let now = Instant::now();
+        let until = now + duration;
+        let mut bans = self.bans.lock().unwrap_or_else(|e| e.into_inner());
+        match bans.get_mut(client_ip) {
+            Some(ban) if ban.until >= until => return false,
+            Some(ban) => {
+                ban.until = until;
+                ban.reason = reason.to_string();
+            }
+            None => {
+                bans.insert(client_ip.to_string(), Ban { until, reason: reason.to_string(), since: now });
+            }
+        }
+        ban_telemetry().bans.inc();
+        ban_telemetry().active.set(bans.len() as i64);
+        true
+    }
+
+    /// Takes over the cluster's bans as of `fetched_at`: remote bans are added or extended, and
+    /// bans from before `fetched_at` the cluster no longer has were lifted elsewhere.
+    pub fn merge(&self, remote: &[(String, String, Duration)], fetched_at: Instant) {
+        for (client, reason, remaining) in remote {
+            self.insert(client, *remaining, reason);
+        }
+        let mut bans = self.bans.lock().unwrap_or_else(|e| e.into_inner());
+        bans.retain(|client, ban| ban.since >= fetched_at || remote.iter().any(|(remote, _, _)| remote == client));
+        ban_telemetry().active.set(bans.len() as i64);
+    }
+
+    pub fn is_banned(&self, client_ip: &str) -> bool {
+        let mut bans = self.bans.lock().unwrap_or_else(|e| e.into_inner());
+        match bans.get(client_ip) {
+            Some(ban) if ban.until > Instant::now() => true,
+            Some(_) => {
+                bans.remove(client_ip);
+                ban_telemetry().active.set(bans.len() as i64);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Whether a connection from `remote_addr` is refused at accept; counts the refusal.
+    pub fn refuses(&self, remote_addr: &SocketAddr) -> bool {
+        self.refuses_client(&remote_addr.ip().to_string())
+    }
+
+    /// Whether a request from `client_ip` is refused; for clients behind a trusted proxy,
+    /// whose connections are the proxy's. Counts the refusal.
+    pub fn refuses_client(&self, client_ip: &str) -> bool {
+        if !self.is_banned(client_ip) {
+            return false;
+        }
+        debug!("Refusing banned client {}", client_ip);
+        ban_telemetry().refused.inc();
+        true
+    }
+
+    /// Lifts the ban of `client_ip`; returns whether there was one.
+    pub fn lift(&self, client_ip: &str) -> bool {
+        let mut bans = self.bans.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let lifted = bans.remove(client_ip).is_some_and(|ban| ban.until > now);
+        ban_telemetry().active.set(bans.len() as i64);
+        if lifted && let Some(changes) = self.changes.get() {
+            let _ = changes.send(BanChange::Lifted { client: client_ip.to_string() });
+        }
+        lifted
+    }
+
+    /// Current bans, ordered by client; expired ones are dropped.
+    pub fn list(&self) -> Vec<BanEntry> {
+        let mut bans = self.bans.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        bans.retain(|_, ban| ban.until > now);
+        ban_telemetry().active.set(bans.len() as i64);
+        let mut entries: Vec<BanEntry> = bans
+            .iter()
+            .map(|(client, ban)| BanEntry {
+                client: client.clone(),
+                reason: ban.reason.clone(),
+                expires_in_secs: ban.until.saturating_duration_since(now).as_secs().max(1),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.client.cmp(&b.client));
+        entries
+    }
+}
+
+struct BanTelemetry {
+    bans: IntCounter,
+    active: IntGauge,
+    refused: IntCounter,
+    registered: AtomicBool,
+}
+
+impl BanTelemetry {
+    fn new() -> Self {
+        Self {
+            bans: IntCounter::with_opts(
+                Opts::new("client_bans_total", "Client bans started or extended").namespace("bifrost"),
+            )
+            .expect("client_bans_total metric"),
+            active: IntGauge::with_opts(
+                Opts::new("banned_clients", "Clients currently banned").namespace("bifrost"),
+            )
+            .expect("banned_clients metric"),
+            refused: IntCounter::with_opts(
+                Opts::new("banned_connections_refused_total", "Connections from banned clients refused at accept")
+                    .namespace("bifrost"),
+            )
+            .expect("banned_connections_refused_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        let collectors: [Box<dyn prometheus::core::Collector>; 3] =
+            [Box::new(self.bans.clone()), Box::new(self.active.clone()), Box::new(self.refused.clone())];
+        for collector in collectors {
+            if let Err(err) = registry.register(collector) {
+                warn!("Failed to register ban metrics: {}", err);
+                return;
+            }
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn ban_telemetry() -> &'static BanTelemetry {
+    static TELEMETRY: OnceLock<BanTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(BanTelemetry::new)
+}
+
+/// Registers the ban counters with the shared Prometheus registry.
+pub fn register_ban_metrics(registry: &Registry) {
+    ban_telemetry().register_if_needed(registry);
+}
@@ -0,0 +1,130 @@
+use crate::common::unix_now;
+use crate::config::ByteQuotaConfig;
+use log::debug;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Where a client stands against its byte quota.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuotaStatus {
+    pub limit: u64,
+    pub remaining: u64,
+    /// Seconds until the current window ends and the quota resets
+    pub reset_secs: u64,
+}
+
+/// Bytes each forward proxy client may transfer per window.
+///
+/// Clients are keyed by their authenticated user, or by address when proxy authentication is
+/// off. Windows start at multiples of the window length since the Unix epoch, so a one-day
+/// window resets at midnight UTC for every client.
+#[derive(Clone)]
+pub struct ByteQuota {
+    enabled: bool,
+    limit: u64,
+    window_secs: u64,
+    usage: Arc<Mutex<QuotaUsage>>,
+}
+
+#[derive(Default)]
+struct QuotaUsage {
+    /// Start of the window the `used` counts belong to
+    window_start: u64,
+    used: HashMap<String, u64>,
+}
+
+impl ByteQuota {
+    pub fn new(config: Option<ByteQuotaConfig>) -> Self {
+        let Some(config) = config else {
+            return Self::disabled();
+        };
+
+        Self {
+            enabled: config.limit_bytes > 0 && config.window_secs > 0,
+            limit: config.limit_bytes,
+            window_secs: config.window_secs.max(1),
+            usage: Arc::new(Mutex::new(QuotaUsage::default())),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            limit: 0,
+            window_secs: 1,
+            usage: Arc::new(Mutex::new(QuotaUsage::default())),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The client's standing; `Err` once the quota is used up.
+    pub fn check(&self, client: &str) -> Result<QuotaStatus, QuotaStatus> {
+        self.check_at(client, unix_now())
+    }
+
+    /// Adds transferred bytes to the client's usage and returns whether quota is left.
+    pub fn charge(&self, client: &str, bytes: u64) -> bool {
+        self.charge_at(client, bytes, unix_now())
+    }
+
+    fn check_at(&self, client: &str, now: u64) -> Result<QuotaStatus, QuotaStatus> {
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        self.roll(&mut usage, now);
+        let used = usage.used.get(client).copied().unwrap_or(0);
+        let status = QuotaStatus {
+            limit: self.limit,
+            remaining: self.limit.saturating_sub(used),
+            reset_secs: usage.window_start + self.window_secs - now,
+        };
+        if status.remaining == 0 { Err(status) } else { Ok(status) }
+    }
+
+    fn charge_at(&self, client: &str, bytes: u64, now: u64) -> bool {
+        if !self.enabled || bytes == 0 {
+            return true;
+        }
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        self.roll(&mut usage, now);
+        let used = usage.used.entry(client.to_string()).or_default();
+        *used = used.saturating_add(bytes);
+        *used < self.limit
+    }
+
+    /// Starts a new window, forgetting every client's usage, once the current one has ended.
+    fn roll(&self, usage: &mut QuotaUsage, now: u64) {
+        let window_start = now - now % self.window_secs;
+        if usage.window_start != window_start {
+            if !usage.used.is_empty() {
+                debug!("Byte quota window ended; resetting usage of {} clients", usage.used.len());
+            }
+            usage.used.clear();
+            usage.window_start = window_start;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quota_is_used_up_and_resets_with_the_window() {
+        let quota = ByteQuota::new(Some(ByteQuotaConfig { limit_bytes: 1_000, window_secs: 3_600 }));
+        let start = 7_200;
+
+        assert!(quota.charge_at("alice", 600, start + 10));
+        assert_eq!(
+            quota.check_at("alice", start + 10),
+            Ok(QuotaStatus { limit: 1_000, remaining: 400, reset_secs: 3_590 })
+        );
+        assert!(!quota.charge_at("alice", 400, start + 20));
+        assert_eq!(quota.check_at("alice", start + 20).unwrap_err().remaining, 0);
+        assert!(quota.check_at("bob", start + 20).is_ok(), "quotas are per client");
+
+        assert_eq!(quota.check_at("alice", start + 3_600).map(|status| status.remaining), Ok(1_000));
+        assert!(ByteQuota::disabled().charge("alice", u64::MAX));
+    }
+}
@@ -0,0 +1,207 @@
+//! `Range` requests for static files.
+//!
+//! Only the `bytes` unit is understood. A header that does not parse is ignored and the whole
+//! file is sent, as RFC 9110 allows; a header that parses but selects nothing in the file gets
+//! a 416. Overlapping or adjacent ranges are merged, so a client asking for the same bytes many
+//! times receives them once, and a request with more than [`MAX_RANGES`] ranges is answered with
+//! the whole file.
+
+use rand::RngCore;
+
+/// Most ranges served as a multipart response; more are answered with the whole file.
+pub const MAX_RANGES: usize = 32;
+
+/// An inclusive range of byte offsets within a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// An inclusive range always holds at least one byte.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Value of the `Content-Range` header for this range.
+    pub fn content_range(&self, file_size: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, file_size)
+    }
+}
+
+/// How a `Range` header applies to a file of a given size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeSelection {
+    /// No usable `Range` header: send the whole file
+    Full,
+    /// One or more satisfiable ranges, sorted and merged
+    Partial(Vec<ByteRange>),
+    /// The header is valid but no range overlaps the file
+    Unsatisfiable,
+}
+
+/// Applies the value of a `Range` header to a file of `file_size` bytes.
+pub fn select(header: &str, file_size: u64) -> RangeSelection {
+    let Some((unit, specs)) = header.split_once('=') else {
+        return RangeSelection::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return RangeSelection::Full;
+    }
+
+    let mut ranges = Vec::new();
+    let mut parsed = 0usize;
+    for spec in specs.split(',') {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            continue;
+        }
+        parsed += 1;
+        if parsed > MAX_RANGES {
+            return RangeSelection::Full;
+        }
+        let Some((first, last)) = spec.split_once('-') else {
+            return RangeSelection::Full;
+        };
+        let (first, last) = (first.trim(), last.trim());
+        let range = if first.is_empty() {
+            // Suffix range: the last N bytes
+            let Ok(suffix) = last.parse::<u64>() else {
+                return RangeSelection::Full;
+            };
+            if suffix == 0 || file_size == 0 {
+                continue;
+            }
+            ByteRange { start: file_size.saturating_sub(suffix), end: file_size - 1 }
+        } else {
+            let Ok(start) = first.parse::<u64>() else {
+                return RangeSelection::Full;
+            };
+            let end = if last.is_empty() {
+                u64::MAX
+            } else {
+                match last.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return RangeSelection::Full,
+                }
+            };
+            if start >= file_size {
+                continue;
+            }
+            ByteRange { start, end: end.min(file_size - 1) }
+        };
+        ranges.push(range);
+    }
+
+    if parsed == 0 {
+        return RangeSelection::Full;
+    }
+    if ranges.is_empty() {
+        return RangeSelection::Unsatisfiable;
+    }
+    RangeSelection::Partial(merge(ranges))
+}
+
+/// Sorts ranges and joins the ones that overlap or touch.
+fn merge(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// A fresh boundary for a `multipart/byteranges` response.
+pub fn multipart_boundary() -> String {
+    let mut bytes = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("bifrost-{}", hex)
+}
+
+/// Header lines that open one part of a `multipart/byteranges` body.
+pub fn part_header(boundary: &str, content_type: &str, range: &ByteRange, file_size: u64) -> String {
+    format!(
+        "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+        boundary,
+        content_type,
+        range.content_range(file_size)
+    )
+}
+
+/// Line that closes a `multipart/byteranges` body.
+pub fn closing_delimiter(boundary: &str) -> String {
+    format!("\r\n--{}--\r\n", boundary)
+}
+
+/// Exact length of a `multipart/byteranges` body, for its `Content-Length`.
+pub fn multipart_length(boundary: &str, content_type: &str, ranges: &[ByteRange], file_size: u64) -> u64 {
+    ranges
+        .iter()
+        .map(|range| part_header(boundary, content_type, range, file_size).len() as u64 + range.len())
+        .sum::<u64>()
+        + closing_delimiter(boundary).len() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn selects_single_suffix_and_open_ranges() {
+        assert_eq!(select("bytes=0-99", 1000), RangeSelection::Partial(vec![range(0, 99)]));
+        assert_eq!(select("bytes=900-", 1000), RangeSelection::Partial(vec![range(900, 999)]));
+        assert_eq!(select("bytes=-100", 1000), RangeSelection::Partial(vec![range(900, 999)]));
+        assert_eq!(select("bytes=-5000", 1000), RangeSelection::Partial(vec![range(0, 999)]));
+        assert_eq!(select("bytes=990-5000", 1000), RangeSelection::Partial(vec![range(990, 999)]));
+    }
+
+    #[test]
+    fn merges_overlapping_ranges_and_keeps_disjoint_ones() {
+        assert_eq!(
+            select("bytes=500-599, 0-99, 50-150, 151-200", 1000),
+            RangeSelection::Partial(vec![range(0, 200), range(500, 599)])
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_headers_and_rejects_unsatisfiable_ones() {
+        assert_eq!(select("items=0-1", 1000), RangeSelection::Full);
+        assert_eq!(select("bytes=abc", 1000), RangeSelection::Full);
+        assert_eq!(select("bytes=10-5", 1000), RangeSelection::Full);
+        assert_eq!(select("bytes=", 1000), RangeSelection::Full);
+        assert_eq!(select("bytes=1000-", 1000), RangeSelection::Unsatisfiable);
+        assert_eq!(select("bytes=-0", 1000), RangeSelection::Unsatisfiable);
+        assert_eq!(select("bytes=0-", 0), RangeSelection::Unsatisfiable);
+
+        let many = (0..=MAX_RANGES).map(|i| format!("{}-{}", i * 10, i * 10)).collect::<Vec<_>>().join(",");
+        assert_eq!(select(&format!("bytes={}", many), 1000), RangeSelection::Full);
+    }
+
+    #[test]
+    fn multipart_length_matches_the_parts() {
+        let boundary = "b";
+        let ranges = [range(0, 9), range(20, 29)];
+        let expected = part_header(boundary, "text/plain", &ranges[0], 100).len()
+            + 10
+            + part_header(boundary, "text/plain", &ranges[1], 100).len()
+            + 10
+            + closing_delimiter(boundary).len();
+        assert_eq!(multipart_length(boundary, "text/plain", &ranges, 100), expected as u64);
+    }
+}
@@ -0,0 +1,221 @@
+//! Expiry tracking for the TLS certificates the proxy serves.
+//!
+//! Certificate files are re-read on every check, so a rotated certificate is picked up without a
+//! restart and the gauge always reflects what the next TLS handshake will present. A certificate
+//! entering the warning window, expiring or being replaced by a later-expiring one is also sent
+//! as a notification, once per change.
+
+use crate::common::{TlsConfig, unix_now};
+use crate::config::CertificateExpiryConfig;
+use crate::notifications::{Notification, NotificationKind};
+use log::{debug, info, warn};
+use prometheus::{IntGaugeVec, Opts, Registry};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::time::Duration;
+
+pub struct CertificateExpiryMonitor {
+    paths: Vec<String>,
+    config: CertificateExpiryConfig,
+    /// Last state notified per path and chain position
+    reported: Mutex<HashMap<(String, usize), Reported>>,
+}
+
+struct Reported {
+    not_after: i64,
+    stage: Stage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    Valid,
+    Expiring,
+    Expired,
+}
+
+impl CertificateExpiryMonitor {
+    pub fn new(paths: Vec<String>, config: CertificateExpiryConfig) -> Self {
+        Self { paths, config, reported: Mutex::new(HashMap::new()) }
+    }
+
+    /// Spawns the periodic check; the first check runs immediately.
+    pub fn spawn(self) {
+        let interval = Duration::from_secs(self.config.check_interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                self.check(unix_now() as i64);
+            }
+        });
+    }
+
+    /// Updates the expiry gauge and warns about certificates inside the warning window.
+    /// Returns how many certificates expire within that window (or already have).
+    fn check(&self, now: i64) -> usize {
+        let telemetry = cert_telemetry();
+        let warning_secs = self.config.warning_days as i64 * 86_400;
+        let mut expiring = 0;
+
+        for path in &self.paths {
+            let chain = match TlsConfig::certificate_validity(path) {
+                Ok(chain) => chain,
+                Err(e) => {
+                    warn!("Unable to read certificate expiry from {}: {}", path, e);
+                    continue;
+                }
+            };
+            for (index, validity) in chain.iter().enumerate() {
+                telemetry
+                    .expiry
+                    .with_label_values(&[path.as_str(), &index.to_string()])
+                    .set(validity.not_after);
+
+                let remaining = validity.not_after - now;
+                let stage = if remaining <= 0 {
+                    Stage::Expired
+                } else if remaining < warning_secs {
+                    Stage::Expiring
+                } else {
+                    Stage::Valid
+                };
+                self.report(path, index, validity.not_after, stage, remaining);
+
+                if remaining <= 0 {
+                    expiring += 1;
+                    warn!("TLS certificate {} (chain position {}) has expired", path, index);
+                } else if remaining < warning_secs {
+                    expiring += 1;
+                    warn!(
+                        "TLS certificate {} (chain position {}) expires in {} day(s)",
+                        path,
+                        index,
+                        remaining / 86_400
+                    );
+                } else {
+                    debug!("TLS certificate {} (chain position {}) valid for {} day(s)", path, index, remaining / 86_400);
+                }
+            }
+        }
+        expiring
+    }
+
+    /// Notifies a renewal, or a certificate getting closer to or past its expiry, once each.
+    fn report(&self, path: &str, index: usize, not_after: i64, stage: Stage, remaining: i64) {
+        let mut reported = self.reported.lock().unwrap_or_else(|e| e.into_inner());
+        let previous = reported.insert((path.to_string(), index), Reported { not_after, stage });
+        drop(reported);
+
+        let previous_stage = match previous {
+            Some(previous) if not_after > previous.not_after => {
+                info!("TLS certificate {} (chain position {}) was renewed", path, index);
+                Notification::new(NotificationKind::CertificateRenewed, format!("TLS certificate {} was renewed", path))
+                    .detail("path", path)
+                    .detail("position", index)
+                    .detail("expires_in_days", remaining / 86_400)
+                    .send();
+                Stage::Valid
+            }
+            Some(previous) if not_after == previous.not_after => previous.stage,
+            _ => Stage::Valid,
+        };
+        if stage > previous_stage {
+            let message = match stage {
+                Stage::Expired => format!("TLS certificate {} has expired", path),
+                _ => format!("TLS certificate {} expires in {} day(s)", path, remaining / 86_400),
+            };
+            Notification::new(NotificationKind::CertificateExpiring, message)
+                .detail("path", path)
+                .detail("position", index)
+                .detail("expires_in_days", remaining / 86_400)
+                .send();
+        }
+    }
+}
+
+struct CertTelemetry {
+    expiry: IntGaugeVec,
+    registered: AtomicBool,
+}
+
+impl CertTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "tls_cert_expiry_timestamp_seconds",
+            "Expiry (notAfter) of each served TLS certificate as a Unix timestamp",
+        ).namespace("bifrost");
+        Self {
+            expiry: IntGaugeVec::new(opts, &["path", "position"]).expect("tls_cert_expiry_timestamp_seconds metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.expiry.clone())) {
+            warn!("Failed to register tls_cert_expiry_timestamp_seconds metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn cert_telemetry() -> &'static CertTelemetry {
+    static TELEMETRY: OnceLock<CertTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(CertTelemetry::new)
+}
+
+/// Registers the certificate expiry gauge with the shared Prometheus registry.
+pub fn register_cert_expiry_metrics(registry: &Registry) {
+    cert_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag, contents.len() as u8];
+        out.extend_from_slice(contents);
+        out
+    }
+
+    /// A structurally minimal certificate: just enough DER for the validity to be read.
+    fn pem(not_after: &[u8]) -> String {
+        let validity = [der(0x17, b"240101000000Z"), der(0x17, not_after)].concat();
+        let tbs = [der(0x02, &[1]), der(0x30, &[]), der(0x30, &[]), der(0x30, &validity)].concat();
+        let encoded = base64::engine::general_purpose::STANDARD.encode(der(0x30, &der(0x30, &tbs)));
+        format!("-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n", encoded)
+    }
+
+    #[test]
+    fn exports_expiry_and_counts_certificates_near_expiry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chain.pem");
+        // Leaf expires 2024-03-01, intermediate 2030-01-01
+        std::fs::write(&path, pem(b"240301000000Z") + &pem(b"300101000000Z")).unwrap();
+        let path = path.display().to_string();
+
+        let monitor = CertificateExpiryMonitor::new(vec![path.clone()], CertificateExpiryConfig::default());
+        // 2024-02-15: the leaf is inside the 30-day window, the intermediate is not
+        assert_eq!(monitor.check(1_707_955_200), 1);
+        // 2024-03-02: the leaf has expired
+        assert_eq!(monitor.check(1_709_337_600), 1);
+
+        let expiry = &cert_telemetry().expiry;
+        assert_eq!(expiry.with_label_values(&[path.as_str(), "0"]).get(), 1_709_251_200);
+        assert_eq!(expiry.with_label_values(&[path.as_str(), "1"]).get(), 1_893_456_000);
+        let stage = |position: usize| monitor.reported.lock().unwrap()[&(path.clone(), position)].stage;
+        assert_eq!(stage(0), Stage::Expired);
+        assert_eq!(stage(1), Stage::Valid);
+
+        // The leaf is replaced by one valid until 2025-03-01
+        std::fs::write(&path, pem(b"250301000000Z") + &pem(b"300101000000Z")).unwrap();
+        assert_eq!(monitor.check(1_709_337_600), 0);
+        assert_eq!(stage(0), Stage::Valid);
+    }
+}
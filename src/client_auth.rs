@@ -0,0 +1,155 @@
+//! Client certificates (mutual TLS) on HTTPS listeners.
+//!
+//! The handshake verifies the certificate against the configured CA bundle; rustls refuses
+//! clients without one unless the mode is `optional`. Requests then carry the common name of the
+//! verified certificate in a header, so a backend behind the proxy can tell clients apart without
+//! terminating TLS itself. The header is always cleared first, so clients cannot claim a name.
+
+use crate::common::der_element;
+use crate::config::{ClientAuthConfig, ClientAuthMode};
+use crate::error::ProxyError;
+use hyper::HeaderMap;
+use hyper::header::{HeaderName, HeaderValue};
+use rustls::RootCertStore;
+use rustls::pki_types::CertificateDer;
+use rustls::server::WebPkiClientVerifier;
+use rustls::server::danger::ClientCertVerifier;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+/// DER encoding of the `commonName` attribute type, 2.5.4.3
+const COMMON_NAME_OID: &[u8] = &[0x55, 0x04, 0x03];
+
+/// Verifier for client certificates chaining to `config.ca_file`.
+pub fn client_verifier(config: &ClientAuthConfig) -> Result<Arc<dyn ClientCertVerifier>, ProxyError> {
+    let invalid = |reason: String| ProxyError::Config(format!("client_auth.ca_file {}: {}", config.ca_file, reason));
+    let file = File::open(&config.ca_file).map_err(|e| invalid(e.to_string()))?;
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut BufReader::new(file)) {
+        roots.add(cert.map_err(|e| invalid(e.to_string()))?).map_err(|e| invalid(e.to_string()))?;
+    }
+    if roots.is_empty() {
+        return Err(invalid("no PEM certificates found".to_string()));
+    }
+
+    let builder = WebPkiClientVerifier::builder(Arc::new(roots));
+    let builder = match config.mode {
+        ClientAuthMode::Required => builder,
+        ClientAuthMode::Optional => builder.allow_unauthenticated(),
+    };
+    builder.build().map_err(|e| invalid(e.to_string()))
+}
+
+/// The header naming the client certificate's subject, shared by all connections of a listener.
+#[derive(Debug, Clone)]
+pub struct ClientCertHeader {
+    name: HeaderName,
+}
+
+impl ClientCertHeader {
+    pub fn new(config: &ClientAuthConfig) -> Result<Self, ProxyError> {
+        let name = HeaderName::from_bytes(config.subject_header.as_bytes()).map_err(|e| {
+            ProxyError::Config(format!("client_auth.subject_header '{}': {}", config.subject_header, e))
+        })?;
+        Ok(Self { name })
+    }
+
+    /// The identity of one connection, from the chain its client presented.
+    pub fn identity(&self, peer_certificates: Option<&[CertificateDer<'_>]>) -> ClientCertIdentity {
+        let common_name = peer_certificates
+            .and_then(<[_]>::first)
+            .and_then(|leaf| certificate_common_name(leaf.as_ref()))
+            .and_then(|name| HeaderValue::from_str(&name).ok());
+        ClientCertIdentity { name: self.name.clone(), common_name }
+    }
+}
+
+/// The verified client of one TLS connection, written onto each of its requests.
+#[derive(Debug, Clone)]
+pub struct ClientCertIdentity {
+    name: HeaderName,
+    common_name: Option<HeaderValue>,
+}
+
+impl ClientCertIdentity {
+    /// Replaces whatever the client sent in the header with the certificate's common name.
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        headers.remove(&self.name);
+        if let Some(common_name) = &self.common_name {
+            headers.insert(self.name.clone(), common_name.clone());
+        }
+    }
+}
+
+/// The first common name in the subject of a DER certificate.
+pub fn certificate_common_name(der: &[u8]) -> Option<String> {
+    let (_, certificate, _) = der_element(der)?;
+    let (_, mut fields, _) = der_element(certificate)?;
+
+    // The explicit [0] version field is optional
+    let (tag, _, rest) = der_element(fields)?;
+    if tag == 0xa0 {
+        fields = rest;
+    }
+    // serialNumber, signature, issuer and validity come before subject
+    for _ in 0..4 {
+        fields = der_element(fields)?.2;
+    }
+
+    // Name: a SEQUENCE of SETs of (type, value) SEQUENCEs
+    let (_, mut names, _) = der_element(fields)?;
+    while !names.is_empty() {
+        let (_, mut set, rest) = der_element(names)?;
+        names = rest;
+        while !set.is_empty() {
+            let (_, attribute, rest) = der_element(set)?;
+            set = rest;
+            let (_, oid, value) = der_element(attribute)?;
+            if oid == COMMON_NAME_OID {
+                let (_, value, _) = der_element(value)?;
+                return String::from_utf8(value.to_vec()).ok();
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_certificate() -> Vec<u8> {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/client.crt");
+        let mut reader = BufReader::new(File::open(path).unwrap());
+        rustls_pemfile::certs(&mut reader).next().unwrap().unwrap().to_vec()
+    }
+
+    #[test]
+    fn common_name_is_read_from_the_subject() {
+        assert_eq!(certificate_common_name(&test_certificate()).as_deref(), Some("service-a"));
+        assert_eq!(certificate_common_name(b"not a certificate"), None);
+    }
+
+    #[test]
+    fn identity_replaces_the_header_sent_by_the_client() {
+        let config = ClientAuthConfig {
+            ca_file: String::new(),
+            mode: ClientAuthMode::Required,
+            subject_header: "X-Client-Cert-CN".to_string(),
+        };
+        let header = ClientCertHeader::new(&config).unwrap();
+        let certificate = CertificateDer::from(test_certificate());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-client-cert-cn", HeaderValue::from_static("admin"));
+        header.identity(Some(&[certificate])).apply(&mut headers);
+        assert_eq!(headers["x-client-cert-cn"], "service-a");
+
+        header.identity(None).apply(&mut headers);
+        assert!(headers.get("x-client-cert-cn").is_none());
+
+        let invalid = ClientAuthConfig { subject_header: "bad header".to_string(), ..config };
+        assert!(ClientCertHeader::new(&invalid).is_err());
+    }
+}
@@ -1,4 +1,37 @@
+use crate::client_auth::client_verifier;
+use crate::config::{ClientAuthConfig, TlsSessionConfig, WebSocketConfig, WebSocketExtensionsMode};
+use crate::tls_session::RotatingTicketer;
 use crate::error::ProxyError;
+use crate::response_body::ResponseBody;
+use crate::api_keys::register_api_key_metrics;
+use crate::auth_lockout::register_auth_lockout_metrics;
+use crate::content_hook::register_content_hook_metrics;
+use crate::download_scan::register_download_scan_metrics;
+use crate::cert_expiry::register_cert_expiry_metrics;
+use crate::fan_out::register_fan_out_metrics;
+use crate::replay::register_replay_metrics;
+use crate::access_log::register_access_log_metrics;
+use crate::security_log::register_security_metrics;
+use crate::ban_list::register_ban_metrics;
+use crate::tarpit::register_tarpit_metrics;
+use crate::cluster::register_cluster_metrics;
+use crate::metrics_push::register_metrics_push_metrics;
+use crate::notifications::register_notification_metrics;
+use crate::dns::register_dns_metrics;
+use crate::graphql::register_graphql_metrics;
+use crate::tenants::register_tenant_metrics;
+use crate::secret_providers::register_secret_provider_metrics;
+use crate::protocol_mux::register_protocol_mux_metrics;
+use crate::forward_proxy::register_forward_metrics;
+use crate::rate_limit::{RateLimitHit, register_rate_limit_metrics};
+use crate::request_signing::register_request_signing_metrics;
+use crate::resource_monitor::register_resource_monitor_metrics;
+use crate::tls_session::register_tls_session_metrics;
+use crate::reverse_proxy::{register_router_metrics, register_websocket_metrics};
+use crate::stale_connection::register_stale_connection_metrics;
+use crate::upstream_error::register_upstream_error_metrics;
+use crate::outlier::register_outlier_metrics;
+use crate::route_metadata::register_route_metadata_metrics;
 use crate::secrets::register_secret_metrics;
 use hyper::{Response, StatusCode, body::{Body, Frame}};
 use hyper::body::Bytes;
@@ -6,6 +39,7 @@ use http_body_util::Full;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
+use std::borrow::Cow;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::net::SocketAddr;
@@ -13,11 +47,14 @@ use std::sync::Arc;
 use std::time::{Instant, SystemTime, UNIX_EPOCH, Duration};
 use std::sync::atomic::{AtomicU64, Ordering};
 use rustls::ServerConfig;
+use rustls::server::{NoServerSessionStorage, ServerSessionMemoryCache};
 use tokio::fs::File as TokioFile;
 use tokio_util::io::ReaderStream;
 use tokio_rustls::TlsAcceptor;
-use futures::Stream;
-use hyper::header::{CONNECTION, UPGRADE, RETRY_AFTER};
+use futures::{Stream, TryStreamExt};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use crate::byte_range::{self, ByteRange};
+use hyper::header::{CONNECTION, UPGRADE, RETRY_AFTER, SEC_WEBSOCKET_EXTENSIONS};
 use prometheus::{
     Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
     Opts, Registry, TextEncoder,
@@ -76,6 +113,24 @@ impl ResponseBuilder {
             .unwrap()
     }
 
+    /// Creates the response for a rate limit hit: 429 with retry information, or the deny
+    /// list's status for denied clients
+    pub fn rate_limited(hit: &RateLimitHit) -> Response<Full<Bytes>> {
+        let mut response = if hit.denied {
+            Response::builder()
+                .status(hit.status)
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body(Full::new(Bytes::from(hit.message())))
+                .unwrap()
+        } else {
+            Self::too_many_requests(&hit.rule_id, hit.retry_after_secs)
+        };
+        if let Some(info) = &hit.info {
+            info.write_headers(response.headers_mut());
+        }
+        response
+    }
+
     /// Creates a 429 Too Many Requests response with retry information
     pub fn too_many_requests(rule: &str, retry_after_secs: u64) -> Response<Full<Bytes>> {
         let mut builder = Response::builder()
@@ -102,6 +157,26 @@ impl TlsConfig {
     /// Creates a TLS configuration from certificate and key files
     /// This eliminates the ~30 lines of duplicated TLS setup code
     pub fn create_config(private_key_path: &str, cert_path: &str) -> Result<ServerConfig, ProxyError> {
+        Self::create_config_with_session(private_key_path, cert_path, None)
+    }
+
+    /// Same as [`TlsConfig::create_config`], applying session resumption settings when given
+    pub fn create_config_with_session(
+        private_key_path: &str,
+        cert_path: &str,
+        session: Option<&TlsSessionConfig>,
+    ) -> Result<ServerConfig, ProxyError> {
+        Self::create_config_with_client_auth(private_key_path, cert_path, session, None)
+    }
+
+    /// Same as [`TlsConfig::create_config_with_session`], also verifying client certificates
+    /// against the CA bundle of `client_auth` when given
+    pub fn create_config_with_client_auth(
+        private_key_path: &str,
+        cert_path: &str,
+        session: Option<&TlsSessionConfig>,
+        client_auth: Option<&ClientAuthConfig>,
+    ) -> Result<ServerConfig, ProxyError> {
         let mut private_key_file = BufReader::new(
             File::open(private_key_path)
                 .map_err(|e| ProxyError::Config(format!("Failed to open private key file: {}", e)))?
@@ -126,11 +201,26 @@ impl TlsConfig {
             .map_err(|e| ProxyError::Config(format!("Failed to read private key: {}", e)))?
             .ok_or_else(|| ProxyError::Config("No valid private key found".to_string()))?;
 
-        let config = ServerConfig::builder()
-            .with_no_client_auth()
+        let builder = match client_auth {
+            Some(client_auth) => ServerConfig::builder().with_client_cert_verifier(client_verifier(client_auth)?),
+            None => ServerConfig::builder().with_no_client_auth(),
+        };
+        let mut config = builder
             .with_single_cert(certs, private_key)
             .map_err(|e| ProxyError::Config(format!("Failed to create TLS config: {}", e)))?;
 
+        if let Some(session) = session {
+            config.session_storage = if session.session_cache_size == 0 {
+                Arc::new(NoServerSessionStorage {})
+            } else {
+                ServerSessionMemoryCache::new(session.session_cache_size)
+            };
+            if session.tickets {
+                config.ticketer = Arc::new(RotatingTicketer::new(session)?);
+            }
+            config.max_early_data_size = session.max_early_data_bytes;
+        }
+
         Ok(config)
     }
 
@@ -146,19 +236,159 @@ impl TlsConfig {
 
         Ok(())
     }
+
+    /// Reads the validity window of every certificate in a PEM file, leaf first
+    pub fn certificate_validity(cert_path: &str) -> Result<Vec<CertificateValidity>, ProxyError> {
+        let mut cert_file = BufReader::new(
+            File::open(cert_path)
+                .map_err(|e| ProxyError::Config(format!("Failed to open certificate file: {}", e)))?
+        );
+
+        let certs = rustls_pemfile::certs(&mut cert_file)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ProxyError::Config(format!("Failed to read certificate: {}", e)))?;
+
+        if certs.is_empty() {
+            return Err(ProxyError::Config("No valid certificate found".to_string()));
+        }
+
+        certs
+            .iter()
+            .map(|cert| {
+                parse_certificate_validity(cert.as_ref()).ok_or_else(|| {
+                    ProxyError::Config(format!("Failed to read validity period from certificate in {}", cert_path))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Validity window of an X.509 certificate, as Unix timestamps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CertificateValidity {
+    pub not_before: i64,
+    pub not_after: i64,
+}
+
+/// Splits one DER element off `input`, returning its tag, contents and the remaining bytes
+pub(crate) fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first & 0x80 == 0 {
+        (usize::from(first), rest)
+    } else {
+        let len_bytes = usize::from(first & 0x7f);
+        if len_bytes == 0 || len_bytes > 4 || rest.len() < len_bytes {
+            return None;
+        }
+        let len = rest[..len_bytes].iter().fold(0usize, |acc, byte| (acc << 8) | usize::from(*byte));
+        (len, &rest[len_bytes..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+/// Walks Certificate -> TBSCertificate -> Validity without a full X.509 parser
+fn parse_certificate_validity(der: &[u8]) -> Option<CertificateValidity> {
+    let (_, certificate, _) = der_element(der)?;
+    let (_, mut fields, _) = der_element(certificate)?;
+
+    // The explicit [0] version field is optional
+    let (tag, _, rest) = der_element(fields)?;
+    if tag == 0xa0 {
+        fields = rest;
+    }
+    // serialNumber, signature and issuer come before validity
+    for _ in 0..3 {
+        fields = der_element(fields)?.2;
+    }
+
+    let (_, validity, _) = der_element(fields)?;
+    let (before_tag, not_before, rest) = der_element(validity)?;
+    let (after_tag, not_after, _) = der_element(rest)?;
+    Some(CertificateValidity {
+        not_before: parse_der_time(before_tag, not_before)?,
+        not_after: parse_der_time(after_tag, not_after)?,
+    })
+}
+
+fn parse_der_time(tag: u8, value: &[u8]) -> Option<i64> {
+    let text = std::str::from_utf8(value).ok()?.strip_suffix('Z')?;
+    let full = match tag {
+        // UTCTime: two-digit years 50-99 are 19xx, 00-49 are 20xx (RFC 5280)
+        0x17 => {
+            let year: u32 = text.get(..2)?.parse().ok()?;
+            format!("{}{}", if year >= 50 { "19" } else { "20" }, text)
+        }
+        // GeneralizedTime
+        0x18 => text.to_string(),
+        _ => return None,
+    };
+    chrono::NaiveDateTime::parse_from_str(&full, "%Y%m%d%H%M%S")
+        .ok()
+        .map(|time| time.and_utc().timestamp())
 }
 
 /// Zero-copy file streaming body that implements the Body trait
 pub struct StreamingFileBody {
-    stream: ReaderStream<TokioFile>,
+    stream: Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>,
 }
 
 impl StreamingFileBody {
     pub fn new(file: TokioFile) -> Self {
         Self {
-            stream: ReaderStream::new(file),
+            stream: Box::pin(ReaderStream::new(file)),
         }
     }
+
+    /// Streams `len` bytes of `file` starting at `start`
+    pub fn range(file: TokioFile, start: u64, len: u64) -> Self {
+        let range = ByteRange { start, end: start + len - 1 };
+        Self {
+            stream: Box::pin(Self::sections(file, vec![(Bytes::new(), range)], Bytes::new())),
+        }
+    }
+
+    /// Streams a `multipart/byteranges` body: each range of `file` behind its part header
+    pub fn multipart(file: TokioFile, ranges: &[ByteRange], boundary: &str, content_type: &str, file_size: u64) -> Self {
+        let sections = ranges
+            .iter()
+            .map(|range| (Bytes::from(byte_range::part_header(boundary, content_type, range, file_size)), *range))
+            .collect();
+        let closing = Bytes::from(byte_range::closing_delimiter(boundary));
+        Self {
+            stream: Box::pin(Self::sections(file, sections, closing)),
+        }
+    }
+
+    /// Reads each range of `file` in turn, sending its prefix before it and `closing` at the end
+    fn sections(
+        file: TokioFile,
+        sections: Vec<(Bytes, ByteRange)>,
+        closing: Bytes,
+    ) -> impl Stream<Item = std::io::Result<Bytes>> + Send {
+        const CHUNK: u64 = 64 * 1024;
+        let state = (file, sections.into_iter(), 0u64, Some(closing));
+        futures::stream::try_unfold(state, |(mut file, mut sections, remaining, closing)| async move {
+            if remaining > 0 {
+                let mut buf = vec![0u8; remaining.min(CHUNK) as usize];
+                let read = file.read(&mut buf).await?;
+                if read == 0 {
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "file shrank while being sent"));
+                }
+                buf.truncate(read);
+                return Ok(Some((Bytes::from(buf), (file, sections, remaining - read as u64, closing))));
+            }
+            if let Some((prefix, range)) = sections.next() {
+                file.seek(std::io::SeekFrom::Start(range.start)).await?;
+                return Ok(Some((prefix, (file, sections, range.len(), closing))));
+            }
+            Ok(closing.map(|closing| (closing, (file, sections, 0, None))))
+        })
+        .try_filter(|chunk| futures::future::ready(!chunk.is_empty()))
+    }
 }
 
 impl Body for StreamingFileBody {
@@ -186,6 +416,8 @@ impl Body for StreamingFileBody {
 pub enum FileBody {
     InMemory(Full<Bytes>),
     Streaming(StreamingFileBody),
+    /// A reverse proxy response, passed on as the backend sends it
+    Proxied(ResponseBody),
 }
 
 impl Body for FileBody {
@@ -203,6 +435,7 @@ impl Body for FileBody {
             FileBody::Streaming(stream) => {
                 Pin::new(stream).poll_frame(cx).map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
             }
+            FileBody::Proxied(proxied) => Pin::new(proxied).poll_frame(cx),
         }
     }
 
@@ -210,6 +443,7 @@ impl Body for FileBody {
         match self {
             FileBody::InMemory(full) => full.size_hint(),
             FileBody::Streaming(stream) => stream.size_hint(),
+            FileBody::Proxied(proxied) => proxied.size_hint(),
         }
     }
 }
@@ -269,6 +503,59 @@ impl FileStreaming {
             .map_err(|e| ProxyError::Http(e.to_string()))?)
     }
 
+    /// Creates a 206 response carrying `ranges` of the file, as one range or `multipart/byteranges`
+    pub async fn create_range_file_response(
+        file_path: &Path,
+        content_type: &str,
+        file_size: u64,
+        ranges: &[ByteRange],
+        no_cache: bool,
+        cache_millisecs: u64,
+    ) -> Result<Response<FileBody>, ProxyError> {
+        let file = tokio::fs::File::open(file_path).await
+            .map_err(|e| ProxyError::Config(format!("Cannot open file: {}", e)))?;
+
+        let builder = Response::builder()
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header("Accept-Ranges", "bytes")
+            .header("Cache-Control", Self::cache_control(no_cache, cache_millisecs));
+
+        let response = match ranges {
+            [range] => builder
+                .header("Content-Type", content_type)
+                .header("Content-Range", range.content_range(file_size))
+                .header("Content-Length", range.len().to_string())
+                .body(FileBody::Streaming(StreamingFileBody::range(file, range.start, range.len()))),
+            _ => {
+                let boundary = byte_range::multipart_boundary();
+                let length = byte_range::multipart_length(&boundary, content_type, ranges, file_size);
+                builder
+                    .header("Content-Type", format!("multipart/byteranges; boundary={}", boundary))
+                    .header("Content-Length", length.to_string())
+                    .body(FileBody::Streaming(StreamingFileBody::multipart(file, ranges, &boundary, content_type, file_size)))
+            }
+        };
+        response.map_err(|e| ProxyError::Http(e.to_string()))
+    }
+
+    /// Creates a 416 response for a `Range` header that selects no bytes of the file
+    pub fn range_not_satisfiable_response(file_size: u64) -> Result<Response<FileBody>, ProxyError> {
+        Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header("Content-Range", format!("bytes */{}", file_size))
+            .header("Content-Length", "0")
+            .body(FileBody::InMemory(Full::new(Bytes::new())))
+            .map_err(|e| ProxyError::Http(e.to_string()))
+    }
+
+    fn cache_control(no_cache: bool, cache_millisecs: u64) -> String {
+        if no_cache {
+            "no-cache, no-store, must-revalidate".to_string()
+        } else {
+            format!("public, max-age={}", cache_millisecs)
+        }
+    }
+
     /// Creates an optimized file response with size-aware serving strategy (LEGACY: returns Full<Bytes>)
     /// Deprecated: Use create_optimized_file_response for streaming support
     pub async fn create_optimized_response(
@@ -435,6 +722,36 @@ impl MonitoringRegistry {
         registry.register(Box::new(average_response_time_ms.clone())).expect("register average_response_time_ms");
         registry.register(Box::new(request_duration_seconds.clone())).expect("register request_duration_seconds");
         register_secret_metrics(&registry);
+        register_forward_metrics(&registry);
+        register_rate_limit_metrics(&registry);
+        register_content_hook_metrics(&registry);
+        register_auth_lockout_metrics(&registry);
+        register_api_key_metrics(&registry);
+        register_request_signing_metrics(&registry);
+        register_fan_out_metrics(&registry);
+        register_replay_metrics(&registry);
+        register_access_log_metrics(&registry);
+        register_security_metrics(&registry);
+        register_ban_metrics(&registry);
+        register_tarpit_metrics(&registry);
+        register_cluster_metrics(&registry);
+        register_metrics_push_metrics(&registry);
+        register_notification_metrics(&registry);
+        register_dns_metrics(&registry);
+        register_graphql_metrics(&registry);
+        register_tenant_metrics(&registry);
+        register_secret_provider_metrics(&registry);
+        register_protocol_mux_metrics(&registry);
+        register_websocket_metrics(&registry);
+        register_router_metrics(&registry);
+        register_stale_connection_metrics(&registry);
+        register_upstream_error_metrics(&registry);
+        register_outlier_metrics(&registry);
+        register_route_metadata_metrics(&registry);
+        register_resource_monitor_metrics(&registry);
+        register_cert_expiry_metrics(&registry);
+        register_tls_session_metrics(&registry);
+        register_download_scan_metrics(&registry);
 
         Self {
             registry,
@@ -793,22 +1110,158 @@ impl Drop for ConnectionTracker {
 
 /// Determines if an HTTP request is attempting to upgrade to a WebSocket connection
 pub fn is_websocket_upgrade(headers: &http::HeaderMap) -> bool {
-    let connection_tokens = headers
-        .get(CONNECTION)
-        .and_then(|v| v.to_str().ok())
-        .map(|value| value.to_ascii_lowercase())
-        .unwrap_or_default();
+    upgrade_token(headers).is_some_and(|token| token.eq_ignore_ascii_case("websocket"))
+}
+
+/// Returns the lowercased `Upgrade` protocol when the request asks for a connection upgrade.
+pub fn upgrade_protocol(headers: &http::HeaderMap) -> Option<String> {
+    upgrade_token(headers).map(str::to_ascii_lowercase)
+}
 
-    let upgrade_value = headers
+/// The `Upgrade` value as sent, when `Connection` lists the `upgrade` token.
+fn upgrade_token(headers: &http::HeaderMap) -> Option<&str> {
+    let connection = headers.get(CONNECTION).and_then(|v| v.to_str().ok())?;
+    if !connection
+        .split([',', ' '])
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+    {
+        return None;
+    }
+
+    headers
         .get(UPGRADE)
         .and_then(|v| v.to_str().ok())
-        .map(|value| value.to_ascii_lowercase())
-        .unwrap_or_default();
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
 
-    connection_tokens
-        .split(|c| c == ',' || c == ' ')
-        .any(|token| token.trim() == "upgrade") &&
-        upgrade_value == "websocket"
+/// Splits `host[:port]` into the host and the port text, if any. An IPv6 host is written
+/// `[2001:db8::1]:443`, or bare without a port; either way it comes back without brackets.
+/// `None` when the brackets are unbalanced or something other than a port follows them.
+pub fn split_host_port(authority: &str) -> Option<(&str, Option<&str>)> {
+    if let Some(rest) = authority.strip_prefix('[') {
+        let (host, after) = rest.split_once(']')?;
+        return match after {
+            "" => Some((host, None)),
+            _ => Some((host, Some(after.strip_prefix(':')?))),
+        };
+    }
+    if authority.contains(']') {
+        return None;
+    }
+    match authority.split_once(':') {
+        // More than one colon is only valid for a bare IPv6 literal
+        Some((_, rest)) if rest.contains(':') => {
+            authority.parse::<std::net::Ipv6Addr>().ok().map(|_| (authority, None))
+        }
+        Some((host, port)) => Some((host, Some(port))),
+        None => Some((authority, None)),
+    }
+}
+
+/// Splits `host:port` with a numeric port, unbracketing an IPv6 host.
+pub fn parse_host_port(authority: &str) -> Option<(&str, u16)> {
+    match split_host_port(authority)? {
+        (host, Some(port)) if !host.is_empty() => port.parse().ok().map(|port| (host, port)),
+        _ => None,
+    }
+}
+
+/// `host:port`, with an IPv6 host put back in brackets.
+pub fn join_host_port(host: &str, port: u16) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// `host` without the brackets URIs put around IPv6 literals.
+pub fn unbracket_host(host: &str) -> &str {
+    host.strip_prefix('[').and_then(|h| h.strip_suffix(']')).unwrap_or(host)
+}
+
+/// `name` as an ASCII hostname: lowercased, with internationalized labels in punycode, so
+/// `münchen.example` and `xn--mnchen-3ya.example` compare equal. Labels that are not valid IDNs,
+/// wildcards among them, are only lowercased.
+pub fn normalize_domain(name: &str) -> Cow<'_, str> {
+    if name.is_ascii() {
+        return ascii_lowercase(name);
+    }
+    let labels: Vec<String> = name
+        .split('.')
+        .map(|label| match label.is_ascii() {
+            true => label.to_ascii_lowercase(),
+            false => idna::domain_to_ascii(label).unwrap_or_else(|_| label.to_lowercase()),
+        })
+        .collect();
+    Cow::Owned(labels.join("."))
+}
+
+/// `value` in ASCII lowercase, borrowed when it already is.
+pub fn ascii_lowercase(value: &str) -> Cow<'_, str> {
+    if value.bytes().any(|b| b.is_ascii_uppercase()) {
+        Cow::Owned(value.to_ascii_lowercase())
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Applies the WebSocket extension policy to a client handshake before it is forwarded.
+///
+/// Offers are comma separated (`permessage-deflate; client_max_window_bits, x-webkit-deflate-frame`);
+/// each one is kept or dropped whole, parameters included.
+pub fn filter_websocket_extensions(headers: &mut http::HeaderMap, config: &WebSocketConfig) {
+    if config.extensions == WebSocketExtensionsMode::Strip {
+        headers.remove(SEC_WEBSOCKET_EXTENSIONS);
+        return;
+    }
+    if config.allowed_extensions.is_empty() {
+        return;
+    }
+
+    let kept: Vec<String> = headers
+        .get_all(SEC_WEBSOCKET_EXTENSIONS)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|offer| {
+            let name = offer.split(';').next().unwrap_or("").trim();
+            config
+                .allowed_extensions
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(name))
+        })
+        .map(str::to_string)
+        .collect();
+
+    headers.remove(SEC_WEBSOCKET_EXTENSIONS);
+    if !kept.is_empty()
+        && let Ok(value) = kept.join(", ").parse()
+    {
+        headers.insert(SEC_WEBSOCKET_EXTENSIONS, value);
+    }
+}
+
+/// Compares two secrets without returning early at the first difference, so the time taken
+/// does not tell how much of a guess was right.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Seconds since the Unix epoch, or 0 on a clock set before it.
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0)
+}
+
+/// `value` with the characters that are markup in HTML text and attribute values escaped.
+pub(crate) fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
 }
 
 /// Efficient HTML template compilation system
@@ -1120,7 +1573,7 @@ impl ConfigValidation {
         if let Ok(socket_addr) = addr.parse::<SocketAddr>() {
             // Valid IP address format
             Self::validate_port(socket_addr.port())?;
-        } else if let Some((host, port_str)) = addr.rsplit_once(':') {
+        } else if let Some((host, Some(port_str))) = split_host_port(addr) {
             // Host:port format
             let port: u16 = port_str.parse()
                 .map_err(|_| ProxyError::Config(format!("Invalid port number: {}", port_str)))?;
@@ -1235,279 +1688,6 @@ impl ConfigValidation {
     }
 }
 
-/// Comprehensive performance benchmarking system
-pub struct PerformanceBenchmark;
-
-impl PerformanceBenchmark {
-    /// Runs a comprehensive benchmark suite
-    pub fn run_comprehensive_benchmark() -> BenchmarkResults {
-        let start_time = Instant::now();
-
-        // Test TLS configuration performance
-        let tls_benchmark = Self::benchmark_tls_config();
-
-        // Test template rendering performance
-        let template_benchmark = Self::benchmark_template_rendering();
-
-        // Test response building performance
-        let response_benchmark = Self::benchmark_response_building();
-
-        // Test error handling performance
-        let error_benchmark = Self::benchmark_error_handling();
-
-        let total_time = start_time.elapsed();
-
-        BenchmarkResults {
-            tls_config: tls_benchmark,
-            template_rendering: template_benchmark,
-            response_building: response_benchmark,
-            error_handling: error_benchmark,
-            total_benchmark_time_ms: total_time.as_millis() as u64,
-        }
-    }
-
-    /// Benchmarks TLS configuration creation
-    fn benchmark_tls_config() -> BenchmarkMetric {
-        let iterations = 1000;
-        let start_time = Instant::now();
-
-        for _ in 0..iterations {
-            // Simulate TLS config operations
-            let _ = std::fs::File::open("/dev/null");
-        }
-
-        let elapsed = start_time.elapsed();
-        let ops_per_second = (iterations as f64 / elapsed.as_secs_f64()) as u64;
-
-        BenchmarkMetric {
-            name: "TLS Configuration Creation".to_string(),
-            iterations,
-            total_time_ms: elapsed.as_millis() as u64,
-            ops_per_second,
-            average_time_ms: elapsed.as_millis() as u64 / iterations,
-            status: if ops_per_second > 1000 { "Excellent" } else { "Good" }.to_string(),
-        }
-    }
-
-    /// Benchmarks template rendering performance
-    fn benchmark_template_rendering() -> BenchmarkMetric {
-        let iterations = 100;
-        let sample_entries = vec!["file1.txt".to_string(), "dir1/".to_string(), "file2.html".to_string()];
-        let start_time = Instant::now();
-
-        for _ in 0..iterations {
-            let _ = HtmlTemplates::render_directory_listing(
-                "/test/path",
-                &sample_entries,
-                Some("/parent"),
-            );
-        }
-
-        let elapsed = start_time.elapsed();
-        let ops_per_second = (iterations as f64 / elapsed.as_secs_f64()) as u64;
-
-        BenchmarkMetric {
-            name: "HTML Template Rendering".to_string(),
-            iterations,
-            total_time_ms: elapsed.as_millis() as u64,
-            ops_per_second,
-            average_time_ms: elapsed.as_millis() as u64 / iterations,
-            status: if ops_per_second > 100 { "Excellent" } else { "Good" }.to_string(),
-        }
-    }
-
-    /// Benchmarks response building performance
-    fn benchmark_response_building() -> BenchmarkMetric {
-        let iterations = 10000;
-        let start_time = Instant::now();
-
-        for _ in 0..iterations {
-            let _ = ResponseBuilder::internal_server_error();
-        }
-
-        let elapsed = start_time.elapsed();
-        let ops_per_second = (iterations as f64 / elapsed.as_secs_f64()) as u64;
-
-        BenchmarkMetric {
-            name: "Response Building".to_string(),
-            iterations,
-            total_time_ms: elapsed.as_millis() as u64,
-            ops_per_second,
-            average_time_ms: elapsed.as_millis() as u64 / iterations,
-            status: if ops_per_second > 100000 { "Excellent" } else { "Good" }.to_string(),
-        }
-    }
-
-    /// Benchmarks error handling performance
-    fn benchmark_error_handling() -> BenchmarkMetric {
-        let iterations = 5000;
-        let start_time = Instant::now();
-
-        for i in 0..iterations {
-            let _ = ResponseBuilder::error(
-                StatusCode::from_u16(400 + (i % 99) as u16).unwrap_or(StatusCode::BAD_REQUEST),
-                "Test error message"
-            );
-        }
-
-        let elapsed = start_time.elapsed();
-        let ops_per_second = (iterations as f64 / elapsed.as_secs_f64()) as u64;
-
-        BenchmarkMetric {
-            name: "Error Response Building".to_string(),
-            iterations,
-            total_time_ms: elapsed.as_millis() as u64,
-            ops_per_second,
-            average_time_ms: elapsed.as_millis() as u64 / iterations,
-            status: if ops_per_second > 50000 { "Excellent" } else { "Good" }.to_string(),
-        }
-    }
-
-    /// Generates optimization report
-    pub fn generate_optimization_report(results: &BenchmarkResults) -> String {
-        let mut report = String::with_capacity(4096);
-
-        report.push_str(r#"# Bifrost Bridge Performance Optimization Report
-
-## Executive Summary
-✅ **Status**: OPTIMIZATION COMPLETE
-🚀 **Improvement**: 75% reduction in code duplication + advanced performance features
-📊 **Benchmark Time**: "#);
-
-        report.push_str(&format!("{}ms\n\n", results.total_benchmark_time_ms));
-
-        report.push_str(r#"## Performance Metrics
-
-### TLS Configuration Performance
-- **Operations/sec**: "#);
-        report.push_str(&results.tls_config.ops_per_second.to_string());
-        report.push_str(r#"
-- **Average time**: "#);
-        report.push_str(&format!("{}ms", results.tls_config.average_time_ms));
-        report.push_str(r#"
-- **Status**: "#);
-        report.push_str(&results.tls_config.status);
-        report.push_str(r#"
-
-### Template Rendering Performance
-- **Operations/sec**: "#);
-        report.push_str(&results.template_rendering.ops_per_second.to_string());
-        report.push_str(r#"
-- **Average time**: "#);
-        report.push_str(&format!("{}ms", results.template_rendering.average_time_ms));
-        report.push_str(r#"
-- **Status**: "#);
-        report.push_str(&results.template_rendering.status);
-        report.push_str(r#"
-
-### Response Building Performance
-- **Operations/sec**: "#);
-        report.push_str(&results.response_building.ops_per_second.to_string());
-        report.push_str(r#"
-- **Average time**: "#);
-        report.push_str(&format!("{}ms", results.response_building.average_time_ms));
-        report.push_str(r#"
-- **Status**: "#);
-        report.push_str(&results.response_building.status);
-        report.push_str(r#"
-
-### Error Handling Performance
-- **Operations/sec**: "#);
-        report.push_str(&results.error_handling.ops_per_second.to_string());
-        report.push_str(r#"
-- **Average time**: "#);
-        report.push_str(&format!("{}ms", results.error_handling.average_time_ms));
-        report.push_str(r#"
-- **Status**: "#);
-        report.push_str(&results.error_handling.status);
-        report.push_str(r#"
-
-## Optimization Achievements
-
-### Code Quality Improvements
-- ✅ **75% reduction** in code duplication (~150+ lines eliminated)
-- ✅ **Centralized error handling** via ResponseBuilder utilities
-- ✅ **Unified TLS configuration** via TlsConfig utilities
-- ✅ **Shared server patterns** via SharedServer trait
-
-### Performance Enhancements
-- ✅ **Zero-copy streaming foundation** implemented
-- ✅ **Size-aware file serving** with 1MB streaming threshold
-- ✅ **Advanced metrics collection** for performance monitoring
-- ✅ **Compiled HTML templates** for faster responses
-- ✅ **Configuration validation** for better reliability
-
-### Infrastructure Improvements
-- ✅ **Worker sharing confirmed** across proxy types
-- ✅ **Memory optimization** for large file handling
-- ✅ **Future-ready architecture** for connection pooling
-- ✅ **Comprehensive validation** system
-
-## Production Recommendations
-
-1. **Enable HTTPS** with valid TLS certificates
-2. **Monitor metrics** using the new PerformanceMetrics system
-3. **Use compiled templates** for HTML responses
-4. **Implement connection pooling** (infrastructure ready)
-5. **Enable compression** for text-based content
-6. **Set up monitoring** for response times and error rates
-7. **Regularly rotate** TLS certificates
-8. **Monitor memory usage** with the new size-aware serving
-
-## Future Enhancement Roadmap
-
-### Phase 1: Connection Pooling
-- Implement shared HTTP client pools
-- Add connection reuse optimization
-- Enable connection keep-alive
-
-### Phase 2: Response Compression
-- Add gzip/deflate compression
-- Implement content-type detection
-- Optimize for mobile clients
-
-### Phase 3: Advanced Monitoring
-- Add detailed request tracing
-- Implement performance dashboards
-- Set up alerting for anomalies
-
-## Validation Results
-
-- ✅ All 11 tests pass - 100% compatibility maintained
-- ✅ No breaking changes introduced
-- ✅ Build optimization successful
-- ✅ Performance improvements validated
-
----
-*Generated on "#);
-
-        report.push_str(&chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string());
-        report.push_str(r#"
-🚀 **Bifrost Bridge - Production-Ready Optimized Proxy Server**"#);
-
-        report
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct BenchmarkResults {
-    pub tls_config: BenchmarkMetric,
-    pub template_rendering: BenchmarkMetric,
-    pub response_building: BenchmarkMetric,
-    pub error_handling: BenchmarkMetric,
-    pub total_benchmark_time_ms: u64,
-}
-
-#[derive(Debug, Clone)]
-pub struct BenchmarkMetric {
-    pub name: String,
-    pub iterations: u64,
-    pub total_time_ms: u64,
-    pub ops_per_second: u64,
-    pub average_time_ms: u64,
-    pub status: String,
-}
-
 /// Infrastructure foundation for future connection pooling and compression
 pub struct PerformanceInfrastructure;
 
@@ -2212,3 +2392,135 @@ pub mod service {
         Ok(ResponseBuilder::internal_server_error())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handshake(extensions: &[&str]) -> http::HeaderMap {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(CONNECTION, "Upgrade".parse().unwrap());
+        headers.insert(UPGRADE, "websocket".parse().unwrap());
+        for value in extensions {
+            headers.append(SEC_WEBSOCKET_EXTENSIONS, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn websocket_extensions_pass_through_by_default() {
+        let mut headers = handshake(&["permessage-deflate; client_max_window_bits"]);
+        filter_websocket_extensions(&mut headers, &WebSocketConfig::default());
+        assert_eq!(headers[SEC_WEBSOCKET_EXTENSIONS], "permessage-deflate; client_max_window_bits");
+    }
+
+    #[test]
+    fn websocket_extensions_can_be_stripped() {
+        let mut headers = handshake(&["permessage-deflate", "x-webkit-deflate-frame"]);
+        let config = WebSocketConfig {
+            extensions: WebSocketExtensionsMode::Strip,
+            ..WebSocketConfig::default()
+        };
+        filter_websocket_extensions(&mut headers, &config);
+        assert!(headers.get(SEC_WEBSOCKET_EXTENSIONS).is_none());
+        assert!(is_websocket_upgrade(&headers));
+    }
+
+    #[test]
+    fn upgrade_tokens_match_case_insensitively() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(CONNECTION, "keep-alive, Upgrade".parse().unwrap());
+        headers.insert(UPGRADE, " WebSocket ".parse().unwrap());
+        assert!(is_websocket_upgrade(&headers));
+        assert_eq!(upgrade_protocol(&headers).as_deref(), Some("websocket"));
+
+        headers.insert(CONNECTION, "keep-alive".parse().unwrap());
+        assert!(!is_websocket_upgrade(&headers));
+        assert_eq!(upgrade_protocol(&headers), None);
+    }
+
+    #[test]
+    fn host_port_splitting_handles_ipv6_literals() {
+        assert_eq!(split_host_port("example.com:443"), Some(("example.com", Some("443"))));
+        assert_eq!(split_host_port("example.com"), Some(("example.com", None)));
+        assert_eq!(split_host_port("[2001:db8::1]:443"), Some(("2001:db8::1", Some("443"))));
+        assert_eq!(split_host_port("[2001:db8::1]"), Some(("2001:db8::1", None)));
+        assert_eq!(split_host_port("2001:db8::1"), Some(("2001:db8::1", None)));
+        assert_eq!(split_host_port("[2001:db8::1]443"), None);
+        assert_eq!(split_host_port("[2001:db8::1:443"), None);
+        assert_eq!(split_host_port("a:b:c"), None);
+
+        assert_eq!(parse_host_port("[::1]:8080"), Some(("::1", 8080)));
+        assert_eq!(parse_host_port("127.0.0.1:8080"), Some(("127.0.0.1", 8080)));
+        assert_eq!(parse_host_port("::1"), None);
+        assert_eq!(parse_host_port(":8080"), None);
+        assert_eq!(parse_host_port("host:99999"), None);
+
+        assert_eq!(join_host_port("::1", 443), "[::1]:443");
+        assert_eq!(join_host_port("[::1]", 443), "[::1]:443");
+        assert_eq!(join_host_port("example.com", 443), "example.com:443");
+        assert_eq!(unbracket_host("[::1]"), "::1");
+    }
+
+    #[test]
+    fn internationalized_names_normalize_to_punycode() {
+        assert_eq!(normalize_domain("münchen.example"), "xn--mnchen-3ya.example");
+        assert_eq!(normalize_domain("MÜNCHEN.Example"), "xn--mnchen-3ya.example");
+        assert_eq!(normalize_domain("*.münchen.example"), "*.xn--mnchen-3ya.example");
+        assert_eq!(normalize_domain("XN--MNCHEN-3YA.example"), "xn--mnchen-3ya.example");
+        assert!(matches!(normalize_domain("api.example.com"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn ascii_lowercase_borrows_lowercase_input() {
+        assert!(matches!(ascii_lowercase("api.example.com"), Cow::Borrowed("api.example.com")));
+        assert_eq!(ascii_lowercase("API.Example.com"), "api.example.com");
+    }
+
+    #[test]
+    fn websocket_extensions_are_filtered_by_name() {
+        let config = WebSocketConfig {
+            allowed_extensions: vec!["PerMessage-Deflate".to_string()],
+            ..WebSocketConfig::default()
+        };
+
+        let mut headers = handshake(&[
+            "x-webkit-deflate-frame, permessage-deflate; server_no_context_takeover",
+            "permessage-deflate",
+        ]);
+        filter_websocket_extensions(&mut headers, &config);
+        assert_eq!(
+            headers[SEC_WEBSOCKET_EXTENSIONS],
+            "permessage-deflate; server_no_context_takeover, permessage-deflate"
+        );
+
+        let mut headers = handshake(&["x-webkit-deflate-frame"]);
+        filter_websocket_extensions(&mut headers, &config);
+        assert!(headers.get(SEC_WEBSOCKET_EXTENSIONS).is_none());
+    }
+
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag, contents.len() as u8];
+        out.extend_from_slice(contents);
+        out
+    }
+
+    #[test]
+    fn reads_certificate_validity_from_der() {
+        let validity = [der(0x17, b"240101000000Z"), der(0x18, b"20500101000000Z")].concat();
+        let tbs = [
+            der(0xa0, &der(0x02, &[2])),
+            der(0x02, &[1]),
+            der(0x30, &[]),
+            der(0x30, &[]),
+            der(0x30, &validity),
+        ]
+        .concat();
+        let certificate = der(0x30, &der(0x30, &tbs));
+
+        let parsed = parse_certificate_validity(&certificate).unwrap();
+        assert_eq!(parsed.not_before, 1_704_067_200);
+        assert_eq!(parsed.not_after, 2_524_608_000);
+        assert!(parse_certificate_validity(&certificate[..10]).is_none());
+    }
+}
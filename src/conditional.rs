@@ -0,0 +1,212 @@
+//! Validators and conditional requests for static files.
+//!
+//! A file's `ETag` is either weak, from its size and modification time, or strong, from a
+//! SHA-256 of its contents (see [`EtagMode`]). Content hashes are remembered per path until the
+//! file's size or mtime changes, so an unchanged file is read once. `If-None-Match` takes
+//! precedence over `If-Modified-Since`, as RFC 9110 requires, and `If-Range` decides whether a
+//! `Range` header is honored.
+
+use crate::config::EtagMode;
+use crate::error::ProxyError;
+use http::HeaderMap;
+use http::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Most content hashes remembered; the cache is cleared when it would grow past this.
+const MAX_CACHED_HASHES: usize = 4096;
+
+/// Size and mtime of a file when its content hash was computed, and the resulting `ETag`.
+type CachedHash = (u64, SystemTime, String);
+
+/// Content hashes by path.
+#[derive(Clone, Default)]
+pub struct EtagCache {
+    hashes: Arc<Mutex<HashMap<PathBuf, CachedHash>>>,
+}
+
+impl EtagCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The `ETag` of a file with the given size and mtime, or `None` with [`EtagMode::Off`].
+    pub async fn etag(
+        &self,
+        mode: EtagMode,
+        path: &Path,
+        size: u64,
+        modified: SystemTime,
+    ) -> Result<Option<String>, ProxyError> {
+        match mode {
+            EtagMode::Off => Ok(None),
+            EtagMode::Mtime => {
+                let nanos = modified.duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+                Ok(Some(format!("W/\"{:x}-{:x}\"", size, nanos)))
+            }
+            EtagMode::Content => {
+                if let Some((cached_size, cached_modified, etag)) = self.hashes.lock().unwrap().get(path)
+                    && *cached_size == size
+                    && *cached_modified == modified
+                {
+                    return Ok(Some(etag.clone()));
+                }
+                let owned = path.to_path_buf();
+                let digest = tokio::task::spawn_blocking(move || hash_file(&owned))
+                    .await
+                    .map_err(|e| ProxyError::Config(format!("ETag hashing error: {}", e)))?
+                    .map_err(|e| ProxyError::Config(format!("Cannot read file for ETag: {}", e)))?;
+                let etag = format!("\"{}\"", digest);
+                let mut hashes = self.hashes.lock().unwrap();
+                if hashes.len() >= MAX_CACHED_HASHES {
+                    hashes.clear();
+                }
+                hashes.insert(path.to_path_buf(), (size, modified, etag.clone()));
+                Ok(Some(etag))
+            }
+        }
+    }
+}
+
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buf[..read]);
+    }
+    // 128 bits are plenty to tell versions of one file apart
+    Ok(context.finish().as_ref()[..16].iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// A modification time as an HTTP date.
+pub fn http_date(modified: SystemTime) -> String {
+    httpdate::fmt_http_date(modified)
+}
+
+fn whole_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn opaque_tag(tag: &str) -> &str {
+    tag.trim().strip_prefix("W/").unwrap_or(tag.trim())
+}
+
+/// Whether the request's `If-None-Match` or `If-Modified-Since` shows that the client's copy is
+/// current, so a 304 can be sent instead of the file.
+pub fn is_not_modified(headers: &HeaderMap, etag: Option<&str>, last_modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = headers.get(IF_NONE_MATCH).and_then(|value| value.to_str().ok()) {
+        // Weak comparison: W/"x" and "x" match
+        let Some(etag) = etag else {
+            return false;
+        };
+        return if_none_match.trim() == "*"
+            || if_none_match.split(',').any(|tag| opaque_tag(tag) == opaque_tag(etag));
+    }
+    match (headers.get(IF_MODIFIED_SINCE).and_then(|value| value.to_str().ok()), last_modified) {
+        (Some(since), Some(modified)) => httpdate::parse_http_date(since)
+            .map(|since| whole_seconds(modified) <= whole_seconds(since))
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Whether a `Range` header may be honored: without `If-Range`, or when its validator still
+/// matches the file. Entity tags need a strong match, dates an exact one.
+pub fn range_allowed(headers: &HeaderMap, etag: Option<&str>, last_modified: Option<SystemTime>) -> bool {
+    let Some(if_range) = headers.get(IF_RANGE) else {
+        return true;
+    };
+    let Ok(if_range) = if_range.to_str() else {
+        return false;
+    };
+    let if_range = if_range.trim();
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return matches!(etag, Some(etag) if !etag.starts_with("W/") && !if_range.starts_with("W/") && etag == if_range);
+    }
+    match (httpdate::parse_http_date(if_range), last_modified) {
+        (Ok(date), Some(modified)) => whole_seconds(date) == whole_seconds(modified),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, value.parse().unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison_and_wins_over_if_modified_since() {
+        let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let etag = Some("W/\"10-abc\"");
+        assert!(is_not_modified(&headers(&[("if-none-match", "\"10-abc\"")]), etag, Some(modified)));
+        assert!(is_not_modified(&headers(&[("if-none-match", "\"x\", W/\"10-abc\"")]), etag, Some(modified)));
+        assert!(is_not_modified(&headers(&[("if-none-match", "*")]), etag, Some(modified)));
+        assert!(!is_not_modified(
+            &headers(&[("if-none-match", "\"other\""), ("if-modified-since", &http_date(modified))]),
+            etag,
+            Some(modified)
+        ));
+    }
+
+    #[test]
+    fn if_modified_since_compares_whole_seconds() {
+        let modified = UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        let same_second = http_date(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        let earlier = http_date(UNIX_EPOCH + Duration::from_secs(1_699_999_999));
+        assert!(is_not_modified(&headers(&[("if-modified-since", &same_second)]), None, Some(modified)));
+        assert!(!is_not_modified(&headers(&[("if-modified-since", &earlier)]), None, Some(modified)));
+        assert!(!is_not_modified(&headers(&[("if-modified-since", &same_second)]), None, None));
+        assert!(!is_not_modified(&headers(&[("if-modified-since", "garbage")]), None, Some(modified)));
+    }
+
+    #[test]
+    fn if_range_needs_a_strong_tag_or_the_exact_date() {
+        let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert!(range_allowed(&HeaderMap::new(), None, None));
+        assert!(range_allowed(&headers(&[("if-range", "\"abc\"")]), Some("\"abc\""), Some(modified)));
+        assert!(!range_allowed(&headers(&[("if-range", "\"abc\"")]), Some("\"def\""), Some(modified)));
+        assert!(!range_allowed(&headers(&[("if-range", "W/\"abc\"")]), Some("W/\"abc\""), Some(modified)));
+        assert!(range_allowed(&headers(&[("if-range", &http_date(modified))]), None, Some(modified)));
+        assert!(!range_allowed(
+            &headers(&[("if-range", &http_date(modified - Duration::from_secs(1)))]),
+            None,
+            Some(modified)
+        ));
+    }
+
+    #[tokio::test]
+    async fn content_etags_follow_the_file_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.js");
+        std::fs::write(&path, "one").unwrap();
+        let cache = EtagCache::new();
+        let modified = UNIX_EPOCH + Duration::from_secs(1);
+
+        let first = cache.etag(EtagMode::Content, &path, 3, modified).await.unwrap().unwrap();
+        assert!(first.starts_with('"'));
+        std::fs::write(&path, "two").unwrap();
+        // Same size and mtime: the remembered hash is used
+        assert_eq!(cache.etag(EtagMode::Content, &path, 3, modified).await.unwrap().unwrap(), first);
+        let changed = cache.etag(EtagMode::Content, &path, 3, modified + Duration::from_secs(1)).await.unwrap().unwrap();
+        assert_ne!(changed, first);
+
+        assert_eq!(cache.etag(EtagMode::Off, &path, 3, modified).await.unwrap(), None);
+        assert!(cache.etag(EtagMode::Mtime, &path, 3, modified).await.unwrap().unwrap().starts_with("W/\""));
+    }
+}
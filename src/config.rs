@@ -1,5 +1,6 @@
+use crate::secrets::Secret;
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 use thiserror::Error;
 
@@ -15,6 +16,14 @@ fn default_metrics_endpoint() -> String {
     "/metrics".to_string()
 }
 
+fn default_metrics_json_endpoint() -> String {
+    "/metrics.json".to_string()
+}
+
+fn default_dashboard_endpoint() -> String {
+    "/dashboard".to_string()
+}
+
 fn default_health_endpoint() -> String {
     "/health".to_string()
 }
@@ -23,6 +32,26 @@ fn default_status_endpoint() -> String {
     "/status".to_string()
 }
 
+fn default_ready_endpoint() -> String {
+    "/readyz".to_string()
+}
+
+fn default_relay_match_endpoint() -> String {
+    "/relay-match".to_string()
+}
+
+fn default_rate_limits_endpoint() -> String {
+    "/rate-limits".to_string()
+}
+
+fn default_bans_endpoint() -> String {
+    "/bans".to_string()
+}
+
+fn default_tenants_endpoint() -> String {
+    "/tenants".to_string()
+}
+
 fn default_monitoring_listen_addr() -> Option<SocketAddr> {
     "127.0.0.1:9900".parse().ok()
 }
@@ -31,6 +60,14 @@ fn default_rate_limiting_enabled() -> bool {
     true
 }
 
+fn default_rate_limit_refresh_secs() -> u64 {
+    30
+}
+
+fn default_rate_limit_deny_status() -> u16 {
+    403
+}
+
 fn default_websocket_enabled() -> bool {
     true
 }
@@ -39,6 +76,10 @@ fn default_websocket_allowed_origins() -> Vec<String> {
     vec!["*".to_string()]
 }
 
+fn default_websocket_shutdown_drain() -> u64 {
+    5
+}
+
 fn default_websocket_timeout() -> u64 {
     300
 }
@@ -104,6 +145,21 @@ pub struct LoggingConfig {
     pub level: Option<LogLevel>,
     pub format: Option<LogFormat>,
     pub targets: Option<Vec<LogTarget>>,
+    /// Dedicated sink for security events (see `security_log`)
+    #[serde(default)]
+    pub security: Option<SecurityLogConfig>,
+}
+
+/// Where security events are written, one JSON object per line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityLogConfig {
+    #[serde(rename = "type")]
+    pub output_type: LogOutputType,
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Also write each event to the regular log under the `bifrost::security` target
+    #[serde(default)]
+    pub main_log: bool,
 }
 
 impl Default for LoggingConfig {
@@ -116,6 +172,7 @@ impl Default for LoggingConfig {
                 path: None,
                 level: None,
             }]),
+            security: None,
         }
     }
 }
@@ -126,10 +183,34 @@ pub struct MonitoringConfig {
     pub enabled: bool,
     #[serde(default = "default_metrics_endpoint")]
     pub metrics_endpoint: String,
+    /// The request, byte, connection and latency totals as one JSON object
+    #[serde(default = "default_metrics_json_endpoint")]
+    pub metrics_json_endpoint: String,
+    /// The HTML dashboard also served at `status_endpoint`
+    #[serde(default = "default_dashboard_endpoint")]
+    pub dashboard_endpoint: String,
     #[serde(default = "default_health_endpoint")]
     pub health_endpoint: String,
     #[serde(default = "default_status_endpoint")]
     pub status_endpoint: String,
+    /// Readiness probe; answers 503 until startup (and preflight, if enabled) has finished
+    #[serde(default = "default_ready_endpoint")]
+    pub ready_endpoint: String,
+    /// Dry-run endpoint explaining which relay rule a host would use (`?host=`)
+    #[serde(default = "default_relay_match_endpoint")]
+    pub relay_match_endpoint: String,
+    /// Lists the rate limit rules, their counters and the most limited clients
+    #[serde(default = "default_rate_limits_endpoint")]
+    pub rate_limits_endpoint: String,
+    /// Accept PUT and DELETE on `rate_limits_endpoint` to change rate limit rules at runtime
+    #[serde(default)]
+    pub rate_limit_updates: bool,
+    /// Lists banned clients; DELETE with `?client=` lifts a ban
+    #[serde(default = "default_bans_endpoint")]
+    pub bans_endpoint: String,
+    /// Lists tenants; `?tenant=` shows one tenant's routes, quota and rate limits
+    #[serde(default = "default_tenants_endpoint")]
+    pub tenants_endpoint: String,
     #[serde(default)]
     pub include_detailed_metrics: bool,
     #[serde(default = "default_monitoring_listen_addr")]
@@ -141,8 +222,16 @@ impl Default for MonitoringConfig {
         Self {
             enabled: true,
             metrics_endpoint: default_metrics_endpoint(),
+            metrics_json_endpoint: default_metrics_json_endpoint(),
+            dashboard_endpoint: default_dashboard_endpoint(),
             health_endpoint: default_health_endpoint(),
             status_endpoint: default_status_endpoint(),
+            ready_endpoint: default_ready_endpoint(),
+            relay_match_endpoint: default_relay_match_endpoint(),
+            rate_limits_endpoint: default_rate_limits_endpoint(),
+            rate_limit_updates: false,
+            bans_endpoint: default_bans_endpoint(),
+            tenants_endpoint: default_tenants_endpoint(),
             include_detailed_metrics: true,
             listen_address: default_monitoring_listen_addr(),
         }
@@ -157,6 +246,35 @@ pub struct RateLimitingConfig {
     pub default_limit: Option<RateLimitWindowConfig>,
     #[serde(default)]
     pub rules: Vec<RateLimitRuleConfig>,
+    /// JSON file with more rules, re-read every `refresh_secs` and applied when it changes
+    #[serde(default)]
+    pub rules_file: Option<String>,
+    #[serde(default = "default_rate_limit_refresh_secs")]
+    pub refresh_secs: u64,
+    /// Clients refused outright, before any rule is checked
+    #[serde(default)]
+    pub deny: Option<RateLimitClientMatchConfig>,
+    /// Status returned to denied clients: 403 or 429
+    #[serde(default = "default_rate_limit_deny_status")]
+    pub deny_status: u16,
+    /// Add `X-RateLimit-Limit`, `-Remaining` and `-Reset` to responses of rate limited requests
+    #[serde(default)]
+    pub response_headers: bool,
+}
+
+impl Default for RateLimitingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_rate_limiting_enabled(),
+            default_limit: None,
+            rules: Vec::new(),
+            rules_file: None,
+            refresh_secs: default_rate_limit_refresh_secs(),
+            deny: None,
+            deny_status: default_rate_limit_deny_status(),
+            response_headers: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -174,6 +292,26 @@ pub struct RateLimitRuleConfig {
     pub path_prefix: Option<String>,
     #[serde(default)]
     pub methods: Option<Vec<String>>,
+    /// Clients this rule does not apply to
+    #[serde(default)]
+    pub exempt: Option<RateLimitClientMatchConfig>,
+    /// Only GraphQL requests with these operations count towards the rule
+    #[serde(default)]
+    pub graphql: Option<GraphQlOperationMatchConfig>,
+}
+
+/// Clients picked out by any of their address, API key or a header value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RateLimitClientMatchConfig {
+    /// Client networks, e.g. `203.0.113.0/24` (a bare address matches only itself)
+    #[serde(default)]
+    pub cidrs: Vec<String>,
+    /// API keys, compared with the API key header (`api_keys.header_name`, `X-API-Key` by default)
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    /// Header name to the values that match it
+    #[serde(default)]
+    pub headers: std::collections::HashMap<String, Vec<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -186,6 +324,24 @@ pub struct WebSocketConfig {
     pub supported_protocols: Vec<String>,
     #[serde(default = "default_websocket_timeout")]
     pub timeout_seconds: u64,
+    /// Whether `Sec-WebSocket-Extensions` offers reach the backend
+    #[serde(default)]
+    pub extensions: WebSocketExtensionsMode,
+    /// Extension names that may pass through (empty = any); ignored when extensions are stripped
+    #[serde(default)]
+    pub allowed_extensions: Vec<String>,
+    /// Time open tunnels keep running after shutdown begins before both sides receive a Close frame
+    #[serde(default = "default_websocket_shutdown_drain")]
+    pub shutdown_drain_seconds: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebSocketExtensionsMode {
+    #[default]
+    Passthrough,
+    /// Remove extension offers so the backend negotiates a plain connection
+    Strip,
 }
 
 impl Default for WebSocketConfig {
@@ -195,6 +351,9 @@ impl Default for WebSocketConfig {
             allowed_origins: default_websocket_allowed_origins(),
             supported_protocols: Vec::new(),
             timeout_seconds: default_websocket_timeout(),
+            extensions: WebSocketExtensionsMode::default(),
+            allowed_extensions: Vec::new(),
+            shutdown_drain_seconds: default_websocket_shutdown_drain(),
         }
     }
 }
@@ -203,6 +362,8 @@ impl Default for WebSocketConfig {
 pub enum ProxyMode {
     Forward,
     Reverse,
+    /// Forward proxy on `forward_listen_addr` and reverse proxy/static files on `listen_addr`
+    Combined,
 }
 
 /// Health check configuration for reverse proxy connection pool
@@ -238,6 +399,33 @@ impl Default for HealthCheckConfig {
     }
 }
 
+/// TLS to the `https://` targets of a route
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamTlsConfig {
+    /// Verify backend certificates; `false` accepts any certificate
+    #[serde(default = "default_upstream_tls_verify")]
+    pub verify: bool,
+    /// PEM bundle of CA certificates trusted instead of the system roots
+    #[serde(default)]
+    pub ca_file: Option<String>,
+    /// Name sent as SNI and matched against the certificate instead of the target host
+    #[serde(default)]
+    pub server_name: Option<String>,
+    /// Offer `h2` through ALPN; backends that choose it are spoken to over HTTP/2
+    #[serde(default)]
+    pub http2: bool,
+}
+
+fn default_upstream_tls_verify() -> bool {
+    true
+}
+
+impl Default for UpstreamTlsConfig {
+    fn default() -> Self {
+        Self { verify: default_upstream_tls_verify(), ca_file: None, server_name: None, http2: false }
+    }
+}
+
 /// Reverse proxy specific configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReverseProxyConfig {
@@ -254,6 +442,58 @@ pub struct ReverseProxyConfig {
     /// Health check configuration (optional)
     #[serde(default)]
     pub health_check: Option<HealthCheckConfig>,
+    /// TCP keepalive probes on backend connections
+    /// Default: first probe after pool_idle_timeout_secs, OS interval and retry count
+    #[serde(default)]
+    pub keepalive: Option<TcpKeepaliveConfig>,
+    /// Resend a request on a new connection when a pooled connection turns out to be closed
+    /// before any of the request was written
+    /// Default: true
+    #[serde(default = "default_retry_on_reuse_error")]
+    pub retry_on_reuse_error: bool,
+    /// Warm connections kept open to every target (optional)
+    #[serde(default)]
+    pub prewarm: Option<PrewarmConfig>,
+    /// Forward the client's Host header instead of the target's authority
+    /// Default: true
+    #[serde(default)]
+    pub preserve_host_header: Option<bool>,
+    /// Egress proxies that backend connections go through, chosen by `relay_proxy_domains`
+    /// (first match wins). `http://` proxies tunnel with CONNECT, `socks5://` with SOCKS5.
+    /// Default: connect directly
+    #[serde(default)]
+    pub relay_proxies: Vec<RelayProxyConfig>,
+    /// Local addresses backend connections are opened from, at most one IPv4 and one IPv6
+    /// Default: chosen by the OS
+    #[serde(default)]
+    pub source_addresses: Vec<IpAddr>,
+    /// Network interface backend connections are bound to (Linux only, needs CAP_NET_RAW)
+    #[serde(default)]
+    pub interface: Option<String>,
+    /// DSCP value (0-63) marked on backend connections, e.g. 46 for Expedited Forwarding
+    #[serde(default)]
+    pub dscp: Option<u8>,
+    /// Casing of header names sent to HTTP/1.1 backends
+    /// Default: lowercase
+    #[serde(default)]
+    pub header_case: HeaderCase,
+}
+
+/// How header names are spelled on HTTP/1.1 requests to a backend. Header names are
+/// case-insensitive, but some legacy backends only accept one spelling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderCase {
+    #[default]
+    Lowercase,
+    /// `Content-Type`, `X-Request-Id`
+    TitleCase,
+    /// As the client sent them; headers the proxy adds are lowercase
+    Preserve,
+}
+
+fn default_retry_on_reuse_error() -> bool {
+    true
 }
 
 fn default_pool_max_idle_per_host() -> usize {
@@ -270,6 +510,47 @@ impl Default for ReverseProxyConfig {
             pool_max_idle_per_host: 10,
             pool_idle_timeout_secs: 90,
             health_check: None,
+            keepalive: None,
+            retry_on_reuse_error: true,
+            prewarm: None,
+            preserve_host_header: None,
+            relay_proxies: Vec::new(),
+            source_addresses: Vec::new(),
+            interface: None,
+            dscp: None,
+            header_case: HeaderCase::default(),
+        }
+    }
+}
+
+/// Opens connections to every target ahead of traffic and keeps them in the pool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrewarmConfig {
+    /// Connections per target, at most `pool_max_idle_per_host` (default: 2)
+    #[serde(default = "default_prewarm_connections")]
+    pub connections: usize,
+    /// Path requested with HEAD to open and refresh the connections (default: "/")
+    #[serde(default = "default_prewarm_path")]
+    pub path: String,
+    /// Seconds between refreshes (default: half of `pool_idle_timeout_secs`)
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+}
+
+fn default_prewarm_connections() -> usize {
+    2
+}
+
+fn default_prewarm_path() -> String {
+    "/".to_string()
+}
+
+impl Default for PrewarmConfig {
+    fn default() -> Self {
+        Self {
+            connections: default_prewarm_connections(),
+            path: default_prewarm_path(),
+            interval_secs: None,
         }
     }
 }
@@ -302,12 +583,146 @@ pub struct ReverseProxyTargetConfig {
 pub struct LoadBalancingConfig {
     #[serde(default)]
     pub policy: LoadBalancingPolicy,
+    /// Passive detection of failing or slow targets
+    #[serde(default)]
+    pub outlier_detection: OutlierDetectionConfig,
+    /// Adapt target weights to observed latency and errors; replaces `policy` when set
+    #[serde(default)]
+    pub dynamic_weights: Option<DynamicWeightsConfig>,
+    /// Ramp up the traffic share of targets that come back into rotation
+    #[serde(default)]
+    pub slow_start: Option<SlowStartConfig>,
 }
 
 impl Default for LoadBalancingConfig {
     fn default() -> Self {
         Self {
             policy: LoadBalancingPolicy::RoundRobin,
+            outlier_detection: OutlierDetectionConfig::default(),
+            dynamic_weights: None,
+            slow_start: None,
+        }
+    }
+}
+
+/// A target that recovers gets a share of its normal traffic that grows linearly from
+/// `min_percent` to 100% over `window_secs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlowStartConfig {
+    #[serde(default = "default_slow_start_window_secs")]
+    pub window_secs: u64,
+    #[serde(default = "default_slow_start_min_percent")]
+    pub min_percent: u32,
+}
+
+fn default_slow_start_window_secs() -> u64 {
+    30
+}
+
+fn default_slow_start_min_percent() -> u32 {
+    10
+}
+
+impl Default for SlowStartConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: default_slow_start_window_secs(),
+            min_percent: default_slow_start_min_percent(),
+        }
+    }
+}
+
+/// Power of two choices over the target weights: of two targets drawn in proportion to their
+/// weights, the one with the lower cost (latency EWMA × requests in flight, raised by errors) wins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DynamicWeightsConfig {
+    /// Cost multiplier per unit of error rate: cost × (1 + error_penalty × error rate)
+    #[serde(default = "default_dynamic_weights_error_penalty")]
+    pub error_penalty: f64,
+}
+
+fn default_dynamic_weights_error_penalty() -> f64 {
+    10.0
+}
+
+impl Default for DynamicWeightsConfig {
+    fn default() -> Self {
+        Self { error_penalty: default_dynamic_weights_error_penalty() }
+    }
+}
+
+/// Thresholds for setting aside targets based on the traffic they serve
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutlierDetectionConfig {
+    /// Deprioritize outliers in target selection (statistics are collected either way)
+    #[serde(default = "default_outlier_enabled")]
+    pub enabled: bool,
+    /// Failures in a row that make a target an outlier (0 disables)
+    #[serde(default = "default_outlier_consecutive_failures")]
+    pub consecutive_failures: u32,
+    /// Number of recent requests the success rate is computed over
+    #[serde(default = "default_outlier_window_requests")]
+    pub window_requests: usize,
+    /// Requests a target must have served before success rate and latency are judged
+    #[serde(default = "default_outlier_min_requests")]
+    pub min_requests: usize,
+    /// Success rate below which a target is an outlier
+    #[serde(default = "default_outlier_min_success_rate")]
+    pub min_success_rate: f64,
+    /// A target is an outlier when its latency EWMA exceeds this multiple of the fastest target's (0 disables)
+    #[serde(default = "default_outlier_slow_latency_factor")]
+    pub slow_latency_factor: f64,
+    /// Weight of the newest sample in the latency EWMA
+    #[serde(default = "default_outlier_latency_ewma_alpha")]
+    pub latency_ewma_alpha: f64,
+    /// How long an outlier is set aside before it gets traffic again
+    #[serde(default = "default_outlier_ejection_secs")]
+    pub ejection_secs: u64,
+}
+
+fn default_outlier_enabled() -> bool {
+    true
+}
+
+fn default_outlier_consecutive_failures() -> u32 {
+    5
+}
+
+fn default_outlier_window_requests() -> usize {
+    100
+}
+
+fn default_outlier_min_requests() -> usize {
+    20
+}
+
+fn default_outlier_min_success_rate() -> f64 {
+    0.5
+}
+
+fn default_outlier_slow_latency_factor() -> f64 {
+    3.0
+}
+
+fn default_outlier_latency_ewma_alpha() -> f64 {
+    0.2
+}
+
+fn default_outlier_ejection_secs() -> u64 {
+    30
+}
+
+impl Default for OutlierDetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_outlier_enabled(),
+            consecutive_failures: default_outlier_consecutive_failures(),
+            window_requests: default_outlier_window_requests(),
+            min_requests: default_outlier_min_requests(),
+            min_success_rate: default_outlier_min_success_rate(),
+            slow_latency_factor: default_outlier_slow_latency_factor(),
+            latency_ewma_alpha: default_outlier_latency_ewma_alpha(),
+            ejection_secs: default_outlier_ejection_secs(),
         }
     }
 }
@@ -318,6 +733,8 @@ pub enum LoadBalancingPolicy {
     RoundRobin,
     WeightedRoundRobin,
     LeastConnections,
+    /// Fewest HTTP requests in flight relative to weight; ties rotate
+    LeastRequests,
     Random,
 }
 
@@ -390,6 +807,237 @@ pub struct RetryPolicyConfig {
     pub methods: Vec<String>,
 }
 
+fn default_api_key_header() -> String {
+    "X-API-Key".to_string()
+}
+
+fn default_api_key_refresh_secs() -> u64 {
+    300
+}
+
+/// API key store used by reverse proxy routes with `require_api_key`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeysConfig {
+    /// Request header carrying the key
+    #[serde(default = "default_api_key_header")]
+    pub header_name: String,
+    /// Optional query parameter checked when the header is absent
+    #[serde(default)]
+    pub query_param: Option<String>,
+    #[serde(default)]
+    pub keys: Vec<ApiKeyEntryConfig>,
+    /// JSON file containing an array of key entries
+    #[serde(default)]
+    pub keys_file: Option<String>,
+    /// HTTP(S) URL returning a JSON array of key entries
+    #[serde(default)]
+    pub keys_url: Option<String>,
+    /// How often `keys_file` and `keys_url` are reloaded
+    #[serde(default = "default_api_key_refresh_secs")]
+    pub refresh_secs: u64,
+    /// Named rate-limit tiers applied per key
+    #[serde(default)]
+    pub tiers: Vec<ApiKeyTierConfig>,
+}
+
+impl Default for ApiKeysConfig {
+    fn default() -> Self {
+        Self {
+            header_name: default_api_key_header(),
+            query_param: None,
+            keys: Vec::new(),
+            keys_file: None,
+            keys_url: None,
+            refresh_secs: default_api_key_refresh_secs(),
+            tiers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyEntryConfig {
+    /// Identifier used in logs and metrics (never the key itself)
+    pub id: String,
+    pub key: Secret,
+    #[serde(default)]
+    pub tier: Option<String>,
+    /// Route ids this key may access; empty allows all routes
+    #[serde(default)]
+    pub routes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyTierConfig {
+    pub name: String,
+    pub limit: u64,
+    pub window_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HmacAlgorithm {
+    #[default]
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+fn default_signature_header() -> String {
+    "X-Signature".to_string()
+}
+
+fn default_signature_timestamp_header() -> String {
+    "X-Timestamp".to_string()
+}
+
+fn default_signature_max_skew_secs() -> u64 {
+    300
+}
+
+fn default_signature_payload_format() -> String {
+    "{timestamp}.{body}".to_string()
+}
+
+fn default_signature_max_body_bytes() -> usize {
+    1024 * 1024
+}
+
+/// HMAC signature verification for signed requests such as webhooks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HmacVerificationConfig {
+    pub secret: Secret,
+    #[serde(default)]
+    pub algorithm: HmacAlgorithm,
+    #[serde(default = "default_signature_header")]
+    pub signature_header: String,
+    /// Prefix in front of the encoded signature (e.g. `sha256=`)
+    #[serde(default)]
+    pub signature_prefix: Option<String>,
+    #[serde(default)]
+    pub encoding: SignatureEncoding,
+    /// Header with the Unix timestamp (seconds or milliseconds); empty disables the freshness check
+    #[serde(default = "default_signature_timestamp_header")]
+    pub timestamp_header: String,
+    #[serde(default = "default_signature_max_skew_secs")]
+    pub max_skew_secs: u64,
+    /// Signed payload template: `{timestamp}`, `{method}`, `{path}`, `{body}`, `{body_sha256}`
+    #[serde(default = "default_signature_payload_format")]
+    pub payload_format: String,
+    /// Larger bodies are rejected with 413 before verification
+    #[serde(default = "default_signature_max_body_bytes")]
+    pub max_body_bytes: usize,
+}
+
+impl Default for HmacVerificationConfig {
+    fn default() -> Self {
+        Self {
+            secret: Secret::new(""),
+            algorithm: HmacAlgorithm::default(),
+            signature_header: default_signature_header(),
+            signature_prefix: None,
+            encoding: SignatureEncoding::default(),
+            timestamp_header: default_signature_timestamp_header(),
+            max_skew_secs: default_signature_max_skew_secs(),
+            payload_format: default_signature_payload_format(),
+            max_body_bytes: default_signature_max_body_bytes(),
+        }
+    }
+}
+
+fn default_fan_out_sample_rate() -> f64 {
+    1.0
+}
+
+fn default_fan_out_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_fan_out_max_body_bytes() -> usize {
+    1024 * 1024
+}
+
+/// Background delivery of request copies to extra targets (e.g. duplicating webhooks to staging).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FanOutConfig {
+    /// Target URLs receiving a copy of each sampled request
+    pub targets: Vec<String>,
+    /// Fraction of requests copied, between 0.0 and 1.0
+    #[serde(default = "default_fan_out_sample_rate")]
+    pub sample_rate: f64,
+    #[serde(default = "default_fan_out_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Larger bodies are rejected with 413 because copies need a buffered body
+    #[serde(default = "default_fan_out_max_body_bytes")]
+    pub max_body_bytes: usize,
+}
+
+impl Default for FanOutConfig {
+    fn default() -> Self {
+        Self {
+            targets: Vec::new(),
+            sample_rate: default_fan_out_sample_rate(),
+            timeout_ms: default_fan_out_timeout_ms(),
+            max_body_bytes: default_fan_out_max_body_bytes(),
+        }
+    }
+}
+
+fn default_aggregate_timeout_ms() -> u64 {
+    5_000
+}
+
+fn default_aggregate_max_body_bytes() -> usize {
+    1024 * 1024
+}
+
+/// Scatter-gather route: the request is sent to every branch and the JSON responses are merged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregateConfig {
+    pub branches: Vec<AggregateBranchConfig>,
+    /// Default per-branch timeout
+    #[serde(default = "default_aggregate_timeout_ms")]
+    pub timeout_ms: u64,
+    /// JSON template; string values of the form `{{branch}}` are replaced by that branch's response
+    #[serde(default)]
+    pub template: Option<serde_json::Value>,
+    /// Answer 502 when any branch fails instead of merging `null` for it
+    #[serde(default)]
+    pub require_all: bool,
+    /// The body is buffered and sent to every branch; larger ones are rejected with 413
+    #[serde(default = "default_aggregate_max_body_bytes")]
+    pub max_body_bytes: usize,
+}
+
+impl Default for AggregateConfig {
+    fn default() -> Self {
+        Self {
+            branches: Vec::new(),
+            timeout_ms: default_aggregate_timeout_ms(),
+            template: None,
+            require_all: false,
+            max_body_bytes: default_aggregate_max_body_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregateBranchConfig {
+    pub name: String,
+    /// Full backend URL; the caller's query string is appended
+    pub url: String,
+    /// Overrides the aggregate `timeout_ms` for this branch
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
 /// Reverse proxy route configuration supporting multiple targets and predicates
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReverseProxyRouteConfig {
@@ -419,15 +1067,179 @@ pub struct ReverseProxyRouteConfig {
     /// Optional path prefix to strip before forwarding (e.g., "/test" -> "/api")
     #[serde(default)]
     pub strip_path_prefix: Option<String>,
+    /// Forward the client's Host header instead of the target's authority
+    /// Default: `reverse_proxy_config.preserve_host_header`, then true
+    #[serde(default)]
+    pub preserve_host_header: Option<bool>,
     /// Optional priority (lower number = higher priority). Defaults to 0.
     #[serde(default)]
     pub priority: Option<i32>,
+    /// Require a valid key from the `api_keys` store for this route
+    #[serde(default)]
+    pub require_api_key: bool,
+    /// Verify HMAC request signatures before forwarding
+    #[serde(default)]
+    pub hmac_verification: Option<HmacVerificationConfig>,
+    /// Copy requests to additional targets in the background
+    #[serde(default)]
+    pub fan_out: Option<FanOutConfig>,
+    /// Answer with the merged JSON responses of several backends instead of proxying to a target
+    #[serde(default)]
+    pub aggregate: Option<AggregateConfig>,
+    /// Record backend responses to disk and serve them back when the backend is unavailable
+    #[serde(default)]
+    pub replay: Option<ReplayConfig>,
+    /// Answer with a configured response instead of proxying to a target
+    #[serde(default)]
+    pub static_response: Option<StaticResponseConfig>,
+    /// Methods this route serves; others get 405 with an `Allow` header (empty allows any)
+    #[serde(default)]
+    pub allowed_methods: Vec<String>,
+    /// Handling of `X-HTTP-Method-Override`
+    /// Default: forwarded to the backend unchanged
+    #[serde(default)]
+    pub method_override: Option<MethodOverride>,
+    /// Non-WebSocket `Upgrade` protocols tunneled transparently (e.g. `SPDY/3.1`, `tcp`; `*` allows any)
+    #[serde(default)]
+    pub allowed_upgrades: Vec<String>,
+    /// Named path variables copied to request headers (variable name -> header name)
+    #[serde(default)]
+    pub path_param_headers: std::collections::HashMap<String, String>,
+    /// Timeouts, body size limit and body buffering for this route
+    #[serde(default)]
+    pub limits: Option<RouteLimitsConfig>,
+    /// Certificate verification, SNI and HTTP/2 for `https://` targets
+    /// Default: verify against the system roots, HTTP/1.1 only
+    #[serde(default)]
+    pub upstream_tls: Option<UpstreamTlsConfig>,
+    /// Free-form ownership tags (team, service, tier) surfaced in logs, metrics and headers
+    #[serde(default)]
+    pub metadata: std::collections::HashMap<String, String>,
     /// Predicate list (logical AND). Empty list is invalid.
     #[serde(default)]
     pub predicates: Vec<RoutePredicateConfig>,
 }
 
-/// Predicate configuration for reverse proxy routing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MethodOverride {
+    /// Pass the header on for the backend to interpret
+    #[default]
+    Forward,
+    /// Treat a POST carrying the header as a request with the method it names
+    Apply,
+    /// Remove the header before forwarding
+    Strip,
+    /// Refuse requests carrying the header with 400
+    Reject,
+}
+
+/// Fixed response of a mock route, optionally filled in from the request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StaticResponseConfig {
+    #[serde(default = "default_static_response_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default = "default_fallback_content_type")]
+    pub content_type: String,
+    #[serde(default)]
+    pub headers: std::collections::HashMap<String, String>,
+    /// Replace `{{variable}}` placeholders in the body and header values
+    #[serde(default)]
+    pub template: bool,
+}
+
+fn default_static_response_status() -> u16 {
+    200
+}
+
+impl Default for StaticResponseConfig {
+    fn default() -> Self {
+        Self {
+            status: default_static_response_status(),
+            body: String::new(),
+            content_type: default_fallback_content_type(),
+            headers: std::collections::HashMap::new(),
+            template: false,
+        }
+    }
+}
+
+/// Recordings of backend responses, served back as a stub
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayConfig {
+    #[serde(default)]
+    pub mode: ReplayMode,
+    /// Directory holding the recordings, one subdirectory per route
+    pub dir: String,
+    /// Methods whose responses are recorded and replayed
+    #[serde(default = "default_replay_methods")]
+    pub methods: Vec<String>,
+    /// Request headers whose values are part of the recording key (e.g. `Accept`)
+    #[serde(default)]
+    pub match_headers: Vec<String>,
+    /// Backend statuses that count as unavailable: never recorded, and answered from a recording
+    #[serde(default = "default_replay_unavailable_statuses")]
+    pub unavailable_statuses: Vec<u16>,
+}
+
+fn default_replay_methods() -> Vec<String> {
+    vec!["GET".to_string(), "HEAD".to_string()]
+}
+
+fn default_replay_unavailable_statuses() -> Vec<u16> {
+    vec![502, 503, 504]
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayMode {
+    /// Forward to the backend and save its responses; recordings stand in while it is unavailable
+    #[default]
+    Record,
+    /// Answer only from recordings, without contacting the backend
+    Replay,
+}
+
+/// Per-route overrides of the global timeouts and request body handling
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RouteLimitsConfig {
+    /// Backend connect timeout (defaults to the global connect timeout)
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+    /// Time allowed for the backend to deliver the response headers, retries included
+    #[serde(default)]
+    pub response_timeout_secs: Option<u64>,
+    /// Longest pause allowed between chunks of the request or response body
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+    /// Largest accepted request body; larger requests get 413
+    #[serde(default)]
+    pub max_body_bytes: Option<usize>,
+    /// Whether the request body is streamed to the backend or read completely first
+    #[serde(default)]
+    pub request_body: BodyMode,
+    /// Whether the response body is streamed to the client or read completely first
+    #[serde(default)]
+    pub response_body: BodyMode,
+    /// Most of a streamed body read into memory when a feature needs all of it (response
+    /// buffering, replay recordings, retries, aggregate branches); defaults to 10 MiB
+    #[serde(default)]
+    pub max_buffer_bytes: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyMode {
+    /// Forward the body as it arrives
+    #[default]
+    Stream,
+    /// Read the whole body before contacting the backend
+    Buffer,
+}
+
+/// Predicate configuration for reverse proxy routing
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum RoutePredicateConfig {
@@ -473,12 +1285,33 @@ pub enum RoutePredicateConfig {
     After { instant: String },
     Before { instant: String },
     Between { start: String, end: String },
+    /// JSON request body field match by presence, exact value, or regex. Buffers the body
+    /// before routing; see docs/configuration.md
+    JsonBody {
+        field: String,
+        #[serde(default)]
+        value: Option<String>,
+        #[serde(default)]
+        regex: Option<String>,
+        #[serde(default = "default_json_body_max_bytes")]
+        max_body_bytes: usize,
+    },
+    /// GraphQL operation name or type; needs the top-level `graphql` section
+    #[serde(rename = "GraphQL")]
+    GraphQl {
+        #[serde(flatten)]
+        operation: GraphQlOperationMatchConfig,
+    },
     /// Remote address in CIDR ranges
     RemoteAddr { cidrs: Vec<String> },
     /// Weighted routing participation
     Weight { group: String, weight: u32 },
 }
 
+fn default_json_body_max_bytes() -> usize {
+    64 * 1024
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StaticMount {
     pub path: String,        // URL path prefix (e.g., "/app", "/api", "/assets")
@@ -495,6 +1328,12 @@ pub struct StaticMount {
     pub no_cache_files: Option<Vec<String>>,
     #[serde(default)]
     pub cache_millisecs: Option<u64>,
+    #[serde(default)]
+    pub etag: Option<EtagMode>,
+    #[serde(default)]
+    pub last_modified: Option<bool>,
+    #[serde(default)]
+    pub precompressed: Option<bool>,
 }
 
 impl StaticMount {
@@ -517,6 +1356,12 @@ impl StaticMount {
                 .unwrap_or_else(|| parent_config.no_cache_files.clone()),
             cache_millisecs: self.cache_millisecs
                 .unwrap_or(parent_config.cache_millisecs),
+            etag: self.etag
+                .unwrap_or(parent_config.etag),
+            last_modified: self.last_modified
+                .unwrap_or(parent_config.last_modified),
+            precompressed: self.precompressed
+                .unwrap_or(parent_config.precompressed),
         }
     }
 }
@@ -531,6 +1376,22 @@ pub struct ResolvedStaticMount {
     pub spa_fallback_file: String,
     pub no_cache_files: Vec<String>,
     pub cache_millisecs: u64,
+    pub etag: EtagMode,
+    pub last_modified: bool,
+    pub precompressed: bool,
+}
+
+/// How static files derive their `ETag`, which `If-None-Match` is compared against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EtagMode {
+    /// No `ETag`; `If-None-Match` is ignored
+    Off,
+    /// Weak tag from the file size and modification time; costs no reads
+    #[default]
+    Mtime,
+    /// Strong tag from a SHA-256 of the contents, for files whose mtime changes on every deploy
+    Content,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -547,6 +1408,23 @@ pub struct StaticFileConfig {
     pub no_cache_files: Vec<String>,
     #[serde(default = "default_cache_millisecs")]
     pub cache_millisecs: u64,
+    /// `ETag` of served files, inherited by mounts that do not set their own
+    #[serde(default)]
+    pub etag: EtagMode,
+    /// Send `Last-Modified` and answer `If-Modified-Since`, inherited by mounts
+    #[serde(default = "default_last_modified")]
+    pub last_modified: bool,
+    /// Serve `.br` / `.gz` sidecars of files to clients that accept them, inherited by mounts
+    #[serde(default = "default_precompressed")]
+    pub precompressed: bool,
+}
+
+fn default_last_modified() -> bool {
+    true
+}
+
+fn default_precompressed() -> bool {
+    true
 }
 
 // For backward compatibility
@@ -562,6 +1440,9 @@ impl Default for StaticFileConfig {
                 spa_fallback_file: None, // Will inherit from parent
                 no_cache_files: None, // Will inherit from parent
                 cache_millisecs: None, // Will inherit from parent
+                etag: None, // Will inherit from parent
+                last_modified: None, // Will inherit from parent
+                precompressed: None, // Will inherit from parent
             }],
             enable_directory_listing: false,
             index_files: vec!["index.html".to_string(), "index.htm".to_string()],
@@ -571,126 +1452,1769 @@ impl Default for StaticFileConfig {
             custom_mime_types: std::collections::HashMap::new(),
             no_cache_files: vec![],
             cache_millisecs: 3600,
+            etag: EtagMode::default(),
+            last_modified: true,
+            precompressed: true,
+        }
+    }
+}
+
+impl StaticFileConfig {
+    pub fn single(root_dir: String, spa_mode: bool) -> Self {
+        Self {
+            mounts: vec![StaticMount {
+                path: "/".to_string(),
+                root_dir,
+                enable_directory_listing: None, // Will inherit from parent
+                index_files: None, // Will inherit from parent
+                spa_mode: Some(spa_mode), // Override SPA mode
+                spa_fallback_file: None, // Will inherit from parent
+                no_cache_files: None, // Will inherit from parent
+                cache_millisecs: None, // Will inherit from parent
+                etag: None, // Will inherit from parent
+                last_modified: None, // Will inherit from parent
+                precompressed: None, // Will inherit from parent
+            }],
+            enable_directory_listing: false,
+            index_files: vec!["index.html".to_string(), "index.htm".to_string()],
+            spa_mode,
+            spa_fallback_file: "index.html".to_string(),
+            worker_threads: None,
+            custom_mime_types: std::collections::HashMap::new(),
+            no_cache_files: vec![],
+            cache_millisecs: 3600,
+            etag: EtagMode::default(),
+            last_modified: true,
+            precompressed: true,
+        }
+    }
+
+    pub fn add_mount(&mut self, path: String, root_dir: String, spa_mode: bool) {
+        self.mounts.push(StaticMount {
+            path,
+            root_dir,
+            enable_directory_listing: None, // Will inherit from parent
+            index_files: None, // Will inherit from parent
+            spa_mode: Some(spa_mode), // Override SPA mode
+            spa_fallback_file: None, // Will inherit from parent
+            no_cache_files: None, // Will inherit from parent
+            cache_millisecs: None, // Will inherit from parent
+            etag: None, // Will inherit from parent
+            last_modified: None, // Will inherit from parent
+            precompressed: None, // Will inherit from parent
+        });
+    }
+
+    pub fn add_custom_mime_type(&mut self, extension: String, mime_type: String) {
+        // Remove leading dot if present
+        let clean_ext = extension.strip_prefix('.').unwrap_or(&extension).to_lowercase();
+        self.custom_mime_types.insert(clean_ext, mime_type);
+    }
+}
+
+/// Forward proxy specific configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ForwardProxyConfig {
+    /// Caps on simultaneous CONNECT tunnels (optional)
+    #[serde(default)]
+    pub tunnel_limits: Option<TunnelLimitsConfig>,
+    /// `GET ftp://...` gateway for legacy clients (optional, disabled by default)
+    #[serde(default)]
+    pub ftp_gateway: Option<FtpGatewayConfig>,
+    /// External DLP/AV callout applied to plain HTTP requests/responses (optional)
+    #[serde(default)]
+    pub content_hook: Option<ContentHookConfig>,
+    /// Malware scanning of downloaded response bodies (optional)
+    #[serde(default)]
+    pub download_scan: Option<DownloadScanConfig>,
+    /// Destination ports CONNECT may tunnel to (defaults to 443 only)
+    #[serde(default)]
+    pub connect_ports: Option<ConnectPortsConfig>,
+    /// Limits applied while reading the CONNECT request head
+    #[serde(default)]
+    pub connect_request_limits: Option<ConnectRequestLimitsConfig>,
+    /// Offer HTTP Digest (SHA-256) proxy authentication next to Basic
+    #[serde(default)]
+    pub digest_auth: Option<DigestAuthConfig>,
+    /// Per-client lockout after repeated proxy authentication failures (enabled by default)
+    #[serde(default)]
+    pub auth_lockout: Option<AuthLockoutConfig>,
+    /// Kerberos/SPNEGO (`Negotiate`) proxy authentication; requires the `spnego` build feature
+    #[serde(default)]
+    pub negotiate_auth: Option<NegotiateAuthConfig>,
+    /// Bytes each client may transfer per time window (optional)
+    #[serde(default)]
+    pub byte_quota: Option<ByteQuotaConfig>,
+}
+
+fn default_auth_lockout_enabled() -> bool {
+    true
+}
+
+fn default_auth_lockout_max_failures() -> u32 {
+    5
+}
+
+fn default_auth_lockout_window_secs() -> u64 {
+    300
+}
+
+fn default_auth_lockout_secs() -> u64 {
+    300
+}
+
+fn default_auth_lockout_base_delay_ms() -> u64 {
+    250
+}
+
+fn default_auth_lockout_max_delay_ms() -> u64 {
+    5000
+}
+
+/// Brute-force protection for proxy credentials, tracked per client IP.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthLockoutConfig {
+    #[serde(default = "default_auth_lockout_enabled")]
+    pub enabled: bool,
+    /// Failed attempts within `window_secs` that trigger a lockout
+    #[serde(default = "default_auth_lockout_max_failures")]
+    pub max_failures: u32,
+    #[serde(default = "default_auth_lockout_window_secs")]
+    pub window_secs: u64,
+    /// How long a locked-out client is refused without checking credentials
+    #[serde(default = "default_auth_lockout_secs")]
+    pub lockout_secs: u64,
+    /// Delay before answering the second failure; doubles with each further failure
+    #[serde(default = "default_auth_lockout_base_delay_ms")]
+    pub base_delay_ms: u64,
+    #[serde(default = "default_auth_lockout_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+impl Default for AuthLockoutConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_auth_lockout_enabled(),
+            max_failures: default_auth_lockout_max_failures(),
+            window_secs: default_auth_lockout_window_secs(),
+            lockout_secs: default_auth_lockout_secs(),
+            base_delay_ms: default_auth_lockout_base_delay_ms(),
+            max_delay_ms: default_auth_lockout_max_delay_ms(),
+        }
+    }
+}
+
+/// Kerberos/SPNEGO proxy authentication settings.
+///
+/// The service key is read from the default keytab (`KRB5_KTNAME`), which must hold
+/// the `HTTP/<proxy-fqdn>` principal clients request tickets for.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NegotiateAuthConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Realm removed from principals in accounting logs (e.g. `CORP.EXAMPLE`)
+    #[serde(default)]
+    pub strip_realm: Option<String>,
+}
+
+fn default_digest_nonce_ttl_secs() -> u64 {
+    300
+}
+
+/// Digest proxy authentication settings (RFC 7616, `algorithm=SHA-256`, `qop=auth`).
+///
+/// Uses the same `proxy_username` / `proxy_password` as Basic authentication.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestAuthConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// How long a server nonce stays valid before clients must re-authenticate
+    #[serde(default = "default_digest_nonce_ttl_secs")]
+    pub nonce_ttl_secs: u64,
+}
+
+impl Default for DigestAuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            nonce_ttl_secs: default_digest_nonce_ttl_secs(),
+        }
+    }
+}
+
+fn default_connect_header_timeout_secs() -> u64 {
+    10
+}
+
+fn default_connect_max_line_bytes() -> usize {
+    8192
+}
+
+fn default_connect_max_headers() -> usize {
+    100
+}
+
+/// Bounds on the raw CONNECT request line and headers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectRequestLimitsConfig {
+    /// Time allowed to receive the complete request head (408 when exceeded)
+    #[serde(default = "default_connect_header_timeout_secs")]
+    pub header_timeout_secs: u64,
+    /// Maximum length of the request line or a single header line
+    #[serde(default = "default_connect_max_line_bytes")]
+    pub max_line_bytes: usize,
+    /// Maximum number of header lines (431 when exceeded)
+    #[serde(default = "default_connect_max_headers")]
+    pub max_headers: usize,
+}
+
+impl Default for ConnectRequestLimitsConfig {
+    fn default() -> Self {
+        Self {
+            header_timeout_secs: default_connect_header_timeout_secs(),
+            max_line_bytes: default_connect_max_line_bytes(),
+            max_headers: default_connect_max_headers(),
+        }
+    }
+}
+
+fn default_connect_allowed_ports() -> Vec<u16> {
+    vec![443]
+}
+
+/// Port policy for CONNECT tunnels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectPortsConfig {
+    /// Ports any destination may be tunneled to
+    #[serde(default = "default_connect_allowed_ports")]
+    pub allowed_ports: Vec<u16>,
+    /// Additional ports for specific destinations
+    #[serde(default)]
+    pub overrides: Vec<ConnectPortOverrideConfig>,
+}
+
+impl Default for ConnectPortsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_ports: default_connect_allowed_ports(),
+            overrides: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectPortOverrideConfig {
+    /// Domain patterns in NO_PROXY format (same syntax as `relay_proxy_domains`)
+    pub domains: Vec<String>,
+    pub ports: Vec<u16>,
+}
+
+fn default_clamd_addr() -> String {
+    "127.0.0.1:3310".to_string()
+}
+
+fn default_scan_min_size_bytes() -> usize {
+    1024
+}
+
+fn default_scan_max_size_bytes() -> usize {
+    25 * 1024 * 1024
+}
+
+fn default_scan_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_scan_rule_enabled() -> bool {
+    true
+}
+
+/// Antivirus scanning applied to forward-proxy downloads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadScanConfig {
+    /// clamd TCP address (`host:port`) used with the INSTREAM protocol
+    #[serde(default = "default_clamd_addr")]
+    pub clamd_addr: String,
+    /// Responses smaller than this are not scanned
+    #[serde(default = "default_scan_min_size_bytes")]
+    pub min_size_bytes: usize,
+    /// Responses larger than this are passed through unscanned (clamd's StreamMaxLength)
+    #[serde(default = "default_scan_max_size_bytes")]
+    pub max_size_bytes: usize,
+    #[serde(default = "default_scan_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Applied when the scanner is unreachable, times out or reports an error
+    #[serde(default)]
+    pub on_failure: HookFailurePolicy,
+    /// Per content-type overrides; first match wins. When empty every content type is scanned.
+    #[serde(default)]
+    pub content_types: Vec<ScanContentTypeRule>,
+}
+
+impl Default for DownloadScanConfig {
+    fn default() -> Self {
+        Self {
+            clamd_addr: default_clamd_addr(),
+            min_size_bytes: default_scan_min_size_bytes(),
+            max_size_bytes: default_scan_max_size_bytes(),
+            timeout_ms: default_scan_timeout_ms(),
+            on_failure: HookFailurePolicy::default(),
+            content_types: Vec::new(),
+        }
+    }
+}
+
+/// Scanning override for responses whose `Content-Type` matches `content_type`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanContentTypeRule {
+    /// MIME type, `type/*` wildcard or `*` (parameters such as `charset` are ignored)
+    pub content_type: String,
+    #[serde(default = "default_scan_rule_enabled")]
+    pub scan: bool,
+    /// Overrides `min_size_bytes` for this content type
+    #[serde(default)]
+    pub min_size_bytes: Option<usize>,
+}
+
+fn default_hook_inspect_requests() -> bool {
+    true
+}
+
+fn default_hook_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_hook_max_body_bytes() -> usize {
+    1024 * 1024
+}
+
+/// What to do when the content hook cannot be reached or times out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookFailurePolicy {
+    /// Forward the message uninspected (fail open)
+    #[default]
+    Bypass,
+    /// Reject the message with 503 (fail closed)
+    Block,
+}
+
+/// HTTP callout used for request/response content adaptation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentHookConfig {
+    /// Callout endpoint, e.g. `http://dlp.internal:8080/scan`
+    pub url: String,
+    #[serde(default = "default_hook_inspect_requests")]
+    pub inspect_requests: bool,
+    #[serde(default)]
+    pub inspect_responses: bool,
+    #[serde(default = "default_hook_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default)]
+    pub on_failure: HookFailurePolicy,
+    /// Larger request bodies are refused with `413`; larger response bodies are truncated before being sent to the hook
+    #[serde(default = "default_hook_max_body_bytes")]
+    pub max_body_bytes: usize,
+}
+
+impl Default for ContentHookConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            inspect_requests: default_hook_inspect_requests(),
+            inspect_responses: false,
+            timeout_ms: default_hook_timeout_ms(),
+            on_failure: HookFailurePolicy::default(),
+            max_body_bytes: default_hook_max_body_bytes(),
+        }
+    }
+}
+
+fn default_ftp_anonymous_password() -> String {
+    "bifrost@".to_string()
+}
+
+fn default_ftp_timeout() -> u64 {
+    30
+}
+
+/// FTP-over-HTTP gateway settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FtpGatewayConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Password sent for anonymous logins when the URL has no credentials
+    #[serde(default = "default_ftp_anonymous_password")]
+    pub anonymous_password: String,
+    /// Timeout in seconds for connecting and for each control-channel reply
+    #[serde(default = "default_ftp_timeout")]
+    pub timeout_secs: u64,
+}
+
+impl Default for FtpGatewayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            anonymous_password: default_ftp_anonymous_password(),
+            timeout_secs: default_ftp_timeout(),
+        }
+    }
+}
+
+/// Transfer allowance per forward proxy client, counted over fixed windows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ByteQuotaConfig {
+    /// Bytes allowed per window, request and response traffic together
+    pub limit_bytes: u64,
+    /// Window length in seconds; windows start at multiples of it since the Unix epoch (UTC)
+    #[serde(default = "default_byte_quota_window_secs")]
+    pub window_secs: u64,
+}
+
+fn default_byte_quota_window_secs() -> u64 {
+    86_400
+}
+
+/// Concurrency caps for CONNECT tunnels, keyed by destination host.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TunnelLimitsConfig {
+    /// Maximum simultaneous tunnels to one destination host across all clients
+    #[serde(default)]
+    pub max_per_destination: Option<usize>,
+    /// Maximum simultaneous tunnels from one client IP to one destination host
+    #[serde(default)]
+    pub max_per_client_per_destination: Option<usize>,
+    /// Domain-level caps shared by every host matching the patterns (first match wins)
+    #[serde(default)]
+    pub domain_limits: Vec<TunnelDomainLimitConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelDomainLimitConfig {
+    /// Domain patterns in NO_PROXY format (same syntax as `relay_proxy_domains`)
+    pub domains: Vec<String>,
+    pub max_concurrent: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayProxyConfig {
+    pub relay_proxy_url: String,
+    #[serde(default)]
+    pub relay_proxy_username: Option<String>,
+    #[serde(default)]
+    pub relay_proxy_password: Option<Secret>,
+    // Domain patterns in NO_PROXY format
+    // Supports: "example.com", ".example.com", "*.example.com", "subdomain.example.com"
+    #[serde(default)]
+    pub relay_proxy_domains: Vec<String>,
+    /// Scheme used to authenticate to the relay (`basic` or `ntlm`)
+    #[serde(default)]
+    pub relay_proxy_auth_scheme: RelayAuthScheme,
+}
+
+/// How the forward proxy authenticates to an upstream relay proxy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelayAuthScheme {
+    #[default]
+    Basic,
+    /// NTLMv2 handshake per relay connection; `DOMAIN\\user` usernames carry the domain
+    Ntlm,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    pub mode: ProxyMode,
+    pub listen_addr: SocketAddr,
+    // Forward proxy listener in Combined mode
+    #[serde(default)]
+    pub forward_listen_addr: Option<SocketAddr>,
+    pub reverse_proxy_target: Option<String>,
+    #[serde(default)]
+    pub reverse_proxy_routes: Vec<ReverseProxyRouteConfig>,
+    pub max_connections: Option<usize>,
+    // New timeout configurations
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub max_connection_lifetime_secs: Option<u64>,
+    // Legacy timeout field for backward compatibility
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    // Worker threads for reverse proxy and static file serving (shared)
+    #[serde(default)]
+    pub worker_threads: Option<usize>,
+    pub static_files: Option<StaticFileConfig>,
+    #[serde(default)]
+    pub private_key: Option<String>,
+    #[serde(default)]
+    pub certificate: Option<String>,
+    #[serde(default)]
+    pub connection_pool_enabled: Option<bool>,
+    #[serde(default = "default_max_header_size")]
+    pub max_header_size: Option<usize>,
+    // Multiple relay proxy configurations
+    #[serde(default)]
+    pub relay_proxies: Option<Vec<RelayProxyConfig>>,
+    // Legacy single relay proxy fields (deprecated, use relay_proxies instead)
+    #[serde(default)]
+    pub relay_proxy_url: Option<String>,
+    #[serde(default)]
+    pub relay_proxy_username: Option<String>,
+    #[serde(default)]
+    pub relay_proxy_password: Option<Secret>,
+    #[serde(default)]
+    pub relay_proxy_domain_suffixes: Option<Vec<String>>,
+    // Basic authentication for forward proxy
+    #[serde(default)]
+    pub proxy_username: Option<String>,
+    #[serde(default)]
+    pub proxy_password: Option<Secret>,
+    // Reverse proxy specific configuration
+    #[serde(default)]
+    pub reverse_proxy_config: Option<ReverseProxyConfig>,
+    // Logging configuration
+    #[serde(default)]
+    pub logging: Option<LoggingConfig>,
+    #[serde(default)]
+    pub monitoring: MonitoringConfig,
+    #[serde(default)]
+    pub websocket: Option<WebSocketConfig>,
+    #[serde(default)]
+    pub rate_limiting: Option<RateLimitingConfig>,
+    // Forward proxy specific configuration
+    #[serde(default)]
+    pub forward_proxy_config: Option<ForwardProxyConfig>,
+    // API keys for reverse proxy routes that set `require_api_key`
+    #[serde(default)]
+    pub api_keys: Option<ApiKeysConfig>,
+    // File descriptor monitoring and idle tunnel reaping
+    #[serde(default)]
+    pub resource_monitor: Option<ResourceMonitorConfig>,
+    // Startup checks run before the proxy reports ready
+    #[serde(default)]
+    pub preflight: Option<PreflightConfig>,
+    // Expiry metric and warnings for the served TLS certificate
+    #[serde(default)]
+    pub certificate_expiry: Option<CertificateExpiryConfig>,
+    // TLS session cache and ticket settings for HTTPS listeners
+    #[serde(default)]
+    pub tls_session: Option<TlsSessionConfig>,
+    // Client certificates required or accepted by HTTPS listeners
+    #[serde(default)]
+    pub client_auth: Option<ClientAuthConfig>,
+    // HTTP/2 on the proxy listeners; without this section they speak HTTP/1.1 only
+    #[serde(default)]
+    pub http2: Option<Http2Config>,
+    // TCP options for the proxy listener and the connections it accepts
+    #[serde(default)]
+    pub listener: Option<ListenerConfig>,
+    // Longest request target, most headers and largest header a request may have
+    #[serde(default)]
+    pub request_limits: Option<RequestLimitsConfig>,
+    // What the reverse proxy does with requests no route matches
+    #[serde(default)]
+    pub route_fallback: Option<RouteFallbackConfig>,
+    // Where route metadata is surfaced: access log, response headers, metric labels
+    #[serde(default)]
+    pub route_metadata: Option<RouteMetadataConfig>,
+    // Sampling and filters for the access log of each listener
+    #[serde(default)]
+    pub access_log: Option<AccessLogConfig>,
+    // How client IPs appear in logs, security events and status output
+    #[serde(default)]
+    pub privacy: Option<PrivacyConfig>,
+    // Slow or fake answers for paths only vulnerability scanners ask for
+    #[serde(default)]
+    pub tarpit: Option<TarpitConfig>,
+    // Temporary bans of clients that collect too many 401/403/404/429 responses
+    #[serde(default)]
+    pub auto_ban: Option<AutoBanConfig>,
+    // Rate limit counters, bans and health results shared with other instances
+    #[serde(default)]
+    pub cluster: Option<ClusterConfig>,
+    // Periodic push of all metrics to a remote-write or OTLP endpoint
+    #[serde(default)]
+    pub metrics_push: Option<MetricsPushConfig>,
+    // Listener for inspecting and controlling the running proxy
+    #[serde(default)]
+    pub admin_api: Option<AdminApiConfig>,
+    // Webhooks told about operational events such as targets going offline
+    #[serde(default)]
+    pub notifications: Option<NotificationsConfig>,
+    // Backend hostname resolution that follows DNS record TTLs
+    #[serde(default)]
+    pub dns: Option<DnsConfig>,
+    // Proxies in front of this one whose X-Forwarded-For names the real client
+    #[serde(default)]
+    pub trusted_proxies: Option<TrustedProxiesConfig>,
+    // Operation names and types read from GraphQL requests for routing, metrics and rate limits
+    #[serde(default)]
+    pub graphql: Option<GraphQlConfig>,
+    // Teams sharing this instance, each with its own routes, limits, API keys and quota
+    #[serde(default)]
+    pub tenants: Vec<TenantConfig>,
+    // Providers for credentials written as `{secret}` references, and their refresh
+    #[serde(default)]
+    pub secrets: Option<SecretsConfig>,
+    // Watching the configuration file for changes, in addition to reloading on SIGHUP
+    #[serde(default)]
+    pub config_reload: Option<ConfigReloadConfig>,
+    // How request paths are normalized before routes and static mounts see them
+    #[serde(default)]
+    pub path_normalization: Option<PathNormalizationConfig>,
+}
+
+fn default_resource_check_interval() -> u64 {
+    30
+}
+
+fn default_fd_warning_ratio() -> f64 {
+    0.8
+}
+
+/// Background checks of descriptor usage and tunnel idleness.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceMonitorConfig {
+    #[serde(default = "default_resource_check_interval")]
+    pub interval_secs: u64,
+    /// Warn when open descriptors reach this fraction of the soft RLIMIT_NOFILE
+    #[serde(default = "default_fd_warning_ratio")]
+    pub fd_warning_ratio: f64,
+    /// Raise the soft RLIMIT_NOFILE to the hard limit at startup
+    #[serde(default)]
+    pub raise_fd_limit: bool,
+    /// Close CONNECT and WebSocket tunnels with no traffic for this long
+    #[serde(default)]
+    pub tunnel_idle_timeout_secs: Option<u64>,
+}
+
+impl Default for ResourceMonitorConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_resource_check_interval(),
+            fd_warning_ratio: default_fd_warning_ratio(),
+            raise_fd_limit: false,
+            tunnel_idle_timeout_secs: None,
+        }
+    }
+}
+
+fn default_preflight_connect_timeout() -> u64 {
+    3000
+}
+
+fn default_cert_warning_days() -> u64 {
+    30
+}
+
+/// Startup checks of backends, relays, static directories, TLS files and listen ports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreflightConfig {
+    /// Refuse to start when any check fails instead of logging and continuing
+    #[serde(default)]
+    pub fail_fast: bool,
+    /// Timeout for each backend and relay connection attempt
+    #[serde(default = "default_preflight_connect_timeout")]
+    pub connect_timeout_ms: u64,
+    /// Warn when the TLS certificate expires within this many days
+    #[serde(default = "default_cert_warning_days")]
+    pub cert_warning_days: u64,
+}
+
+impl Default for PreflightConfig {
+    fn default() -> Self {
+        Self {
+            fail_fast: false,
+            connect_timeout_ms: default_preflight_connect_timeout(),
+            cert_warning_days: default_cert_warning_days(),
+        }
+    }
+}
+
+fn default_cert_check_interval() -> u64 {
+    3600
+}
+
+/// Periodic expiry check of the served TLS certificate chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateExpiryConfig {
+    #[serde(default = "default_cert_check_interval")]
+    pub check_interval_secs: u64,
+    /// Log a warning on every check once a certificate expires within this many days
+    #[serde(default = "default_cert_warning_days")]
+    pub warning_days: u64,
+}
+
+impl Default for CertificateExpiryConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: default_cert_check_interval(),
+            warning_days: default_cert_warning_days(),
+        }
+    }
+}
+
+fn default_session_cache_size() -> usize {
+    256
+}
+
+fn default_session_tickets() -> bool {
+    true
+}
+
+fn default_ticket_lifetime() -> u32 {
+    6 * 60 * 60
+}
+
+/// Session resumption for HTTPS listeners.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsSessionConfig {
+    /// Sessions kept in the in-memory (stateful) resumption cache; 0 disables the cache
+    #[serde(default = "default_session_cache_size")]
+    pub session_cache_size: usize,
+    /// Issue stateless session tickets
+    #[serde(default = "default_session_tickets")]
+    pub tickets: bool,
+    /// Ticket lifetime hint, which is also the ticket key rotation interval
+    #[serde(default = "default_ticket_lifetime")]
+    pub ticket_lifetime_secs: u32,
+    /// File holding a shared secret (at least 32 bytes) that ticket keys are derived from,
+    /// so instances sharing it accept each other's tickets
+    #[serde(default)]
+    pub ticket_key_file: Option<String>,
+    /// Accept up to this many bytes of TLS 1.3 early data (0-RTT) on resumed sessions; 0 disables it
+    #[serde(default)]
+    pub max_early_data_bytes: u32,
+    /// What to do with non-idempotent requests (anything but GET/HEAD) sent as early data
+    #[serde(default)]
+    pub early_data_policy: EarlyDataPolicy,
+}
+
+/// Client certificate (mutual TLS) verification on HTTPS listeners.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientAuthConfig {
+    /// PEM bundle of the CAs that client certificates must chain to
+    pub ca_file: String,
+    /// Whether a client without a certificate may still connect
+    #[serde(default)]
+    pub mode: ClientAuthMode,
+    /// Request header carrying the common name of the verified client certificate. Any value the
+    /// client sends itself is removed.
+    #[serde(default = "default_client_cert_header")]
+    pub subject_header: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientAuthMode {
+    /// Refuse the handshake without a valid client certificate
+    #[default]
+    Required,
+    /// Accept clients without a certificate; one that is presented must still be valid
+    Optional,
+}
+
+fn default_client_cert_header() -> String {
+    "X-Client-Cert-CN".to_string()
+}
+
+/// HTTP/2 for clients of the proxy listeners. Backends are still spoken to over HTTP/1.1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Http2Config {
+    /// Offer `h2` through ALPN on HTTPS listeners
+    #[serde(default = "default_http2_alpn")]
+    pub alpn: bool,
+    /// Accept cleartext HTTP/2 with prior knowledge (h2c) on plain listeners, next to HTTP/1.1
+    #[serde(default)]
+    pub h2c: bool,
+    /// Streams a client may have open at once on one connection
+    #[serde(default = "default_http2_max_concurrent_streams")]
+    pub max_concurrent_streams: u32,
+    /// Send a PING on idle connections at this interval and close those that stop answering
+    #[serde(default)]
+    pub keep_alive_interval_secs: Option<u64>,
+}
+
+fn default_http2_alpn() -> bool {
+    true
+}
+
+fn default_http2_max_concurrent_streams() -> u32 {
+    200
+}
+
+impl Default for Http2Config {
+    fn default() -> Self {
+        Self {
+            alpn: default_http2_alpn(),
+            h2c: false,
+            max_concurrent_streams: default_http2_max_concurrent_streams(),
+            keep_alive_interval_secs: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EarlyDataPolicy {
+    /// Answer `425 Too Early` so the client retries once the handshake is complete
+    #[default]
+    Reject,
+    /// Process the request after the handshake has completed
+    Delay,
+}
+
+impl Default for TlsSessionConfig {
+    fn default() -> Self {
+        Self {
+            session_cache_size: default_session_cache_size(),
+            tickets: default_session_tickets(),
+            ticket_lifetime_secs: default_ticket_lifetime(),
+            ticket_key_file: None,
+            max_early_data_bytes: 0,
+            early_data_policy: EarlyDataPolicy::default(),
+        }
+    }
+}
+
+fn default_listen_backlog() -> u32 {
+    1024
+}
+
+fn default_keepalive_time() -> u64 {
+    60
+}
+
+fn default_keepalive_interval() -> u64 {
+    10
+}
+
+fn default_keepalive_retries() -> u32 {
+    5
+}
+
+/// Handling of reverse proxy requests that match no route
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteFallbackConfig {
+    /// Id of a configured route that serves unmatched requests, regardless of its predicates
+    #[serde(default)]
+    pub route_id: Option<String>,
+    /// Status of the response sent when there is no fallback route
+    #[serde(default = "default_fallback_status")]
+    pub status: u16,
+    /// Body of that response
+    #[serde(default = "default_fallback_body")]
+    pub body: String,
+    #[serde(default = "default_fallback_content_type")]
+    pub content_type: String,
+}
+
+fn default_fallback_status() -> u16 {
+    404
+}
+
+fn default_fallback_body() -> String {
+    "No matching route".to_string()
+}
+
+fn default_fallback_content_type() -> String {
+    "text/plain; charset=utf-8".to_string()
+}
+
+impl Default for RouteFallbackConfig {
+    fn default() -> Self {
+        Self {
+            route_id: None,
+            status: default_fallback_status(),
+            body: default_fallback_body(),
+            content_type: default_fallback_content_type(),
+        }
+    }
+}
+
+/// Where the `metadata` of reverse proxy routes is surfaced
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RouteMetadataConfig {
+    /// Write an access log entry (target `bifrost::access`) per request, with the route's metadata
+    #[serde(default)]
+    pub access_log: bool,
+    /// When set, every metadata entry is returned as a `<prefix><key>` response header
+    #[serde(default)]
+    pub response_header_prefix: Option<String>,
+    /// Metadata keys added as labels to `bifrost_route_requests_total`
+    #[serde(default)]
+    pub metric_labels: Vec<String>,
+}
+
+fn default_access_log_sample_rate() -> f64 {
+    1.0
+}
+
+fn default_access_log_keep_errors() -> bool {
+    true
+}
+
+/// Access log policies per listener, and the layout and destination of entries. A listener
+/// without a policy keeps its default behaviour: reverse proxy entries follow
+/// `route_metadata.access_log`, the forward proxy writes none. With a `file`, both log every
+/// request unless their policy says otherwise.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccessLogConfig {
+    /// Reverse proxy routes on `listen_addr`
+    #[serde(default)]
+    pub reverse: Option<AccessLogPolicyConfig>,
+    /// Forward proxy requests; CONNECT tunnels on a plain HTTP listener are not logged
+    #[serde(default)]
+    pub forward: Option<AccessLogPolicyConfig>,
+    /// Layout of each entry
+    #[serde(default)]
+    pub format: AccessLogFormat,
+    /// Entry layout for `format: "template"`, e.g. `$remote_addr "$request" $status $upstream_time`
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Write entries to this file instead of the `bifrost::access` log target
+    #[serde(default)]
+    pub file: Option<AccessLogFileConfig>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// `client method path status route=... duration_ms=...`, followed by route metadata
+    #[default]
+    Text,
+    /// Common Log Format, as written by Apache and nginx
+    Common,
+    /// One JSON object per line
+    Json,
+    /// The `template`, with `$name` placeholders filled in
+    Template,
+}
+
+/// An access log file, rotated by size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessLogFileConfig {
+    pub path: PathBuf,
+    /// Rotate once the file reaches this size; 0 never rotates
+    #[serde(default = "default_access_log_max_size_mb")]
+    pub max_size_mb: u64,
+    /// Rotated files kept as `<path>.1` (newest) to `<path>.<max_files>`
+    #[serde(default = "default_access_log_max_files")]
+    pub max_files: u32,
+}
+
+fn default_access_log_max_size_mb() -> u64 {
+    100
+}
+
+fn default_access_log_max_files() -> u32 {
+    5
+}
+
+/// Which requests of a listener get an access log entry.
+///
+/// Filters run first: with `routes` only those routes are logged, and with `min_status` and/or
+/// `min_duration_ms` a request must reach at least one of them. What passes is sampled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessLogPolicyConfig {
+    /// Fraction of requests logged, 0.0 to 1.0
+    #[serde(default = "default_access_log_sample_rate")]
+    pub sample_rate: f64,
+    /// Adaptive sampling: lower the rate as traffic grows so at most this many entries are
+    /// written per second
+    #[serde(default)]
+    pub max_per_second: Option<u64>,
+    /// Only log responses with at least this status
+    #[serde(default)]
+    pub min_status: Option<u16>,
+    /// Only log requests that took at least this long
+    #[serde(default)]
+    pub min_duration_ms: Option<u64>,
+    /// Only log these route ids (reverse proxy only)
+    #[serde(default)]
+    pub routes: Vec<String>,
+    /// Log every 5xx response regardless of sampling
+    #[serde(default = "default_access_log_keep_errors")]
+    pub keep_errors: bool,
+}
+
+impl Default for AccessLogPolicyConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: default_access_log_sample_rate(),
+            max_per_second: None,
+            min_status: None,
+            min_duration_ms: None,
+            routes: Vec::new(),
+            keep_errors: default_access_log_keep_errors(),
+        }
+    }
+}
+
+/// How client IPs are written out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientIpMode {
+    /// The address as is
+    #[default]
+    Full,
+    /// Only the network part (`ipv4_prefix` / `ipv6_prefix`)
+    Truncate,
+    /// A keyed hash that stays the same within one key rotation window
+    Hash,
+}
+
+fn default_privacy_ipv4_prefix() -> u8 {
+    24
+}
+
+fn default_privacy_ipv6_prefix() -> u8 {
+    48
+}
+
+fn default_privacy_key_rotation_secs() -> u64 {
+    86400
+}
+
+/// Anonymization of client IPs in logs, security events and the rate limit status.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrivacyConfig {
+    #[serde(default)]
+    pub client_ip: ClientIpMode,
+    #[serde(default = "default_privacy_ipv4_prefix")]
+    pub ipv4_prefix: u8,
+    #[serde(default = "default_privacy_ipv6_prefix")]
+    pub ipv6_prefix: u8,
+    /// HMAC key for `hash`; when unset a random key is generated at startup, so values do not
+    /// match across restarts
+    #[serde(default)]
+    pub hash_key: Option<String>,
+    /// How long one derived hash key is used before the next one takes over
+    #[serde(default = "default_privacy_key_rotation_secs")]
+    pub key_rotation_secs: u64,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            client_ip: ClientIpMode::default(),
+            ipv4_prefix: default_privacy_ipv4_prefix(),
+            ipv6_prefix: default_privacy_ipv6_prefix(),
+            hash_key: None,
+            key_rotation_secs: default_privacy_key_rotation_secs(),
+        }
+    }
+}
+
+/// How the tarpit answers once the delay is over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TarpitAction {
+    /// 404 Not Found
+    #[default]
+    Delay,
+    /// 200 OK with `fake_body`
+    Fake,
+}
+
+fn default_tarpit_paths() -> Vec<String> {
+    [
+        "/wp-login.php",
+        "/xmlrpc.php",
+        "/wp-admin/**",
+        "/.env",
+        "/.git/**",
+        "/phpmyadmin/**",
+        "/cgi-bin/**",
+        "/vendor/phpunit/**",
+    ]
+    .iter()
+    .map(|path| path.to_string())
+    .collect()
+}
+
+fn default_tarpit_delay_secs() -> u64 {
+    30
+}
+
+fn default_tarpit_fake_body() -> String {
+    "<html><body></body></html>".to_string()
+}
+
+fn default_tarpit_max_concurrent() -> usize {
+    100
+}
+
+/// Tarpit for requests to known scanner paths, checked before routing on the reverse proxy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TarpitConfig {
+    /// Ant-style path patterns, matched case-insensitively
+    #[serde(default = "default_tarpit_paths")]
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub action: TarpitAction,
+    /// How long a matching request is held before it is answered
+    #[serde(default = "default_tarpit_delay_secs")]
+    pub delay_secs: u64,
+    /// Body of the `fake` answer
+    #[serde(default = "default_tarpit_fake_body")]
+    pub fake_body: String,
+    /// Requests held at once; further matches are answered without delay
+    #[serde(default = "default_tarpit_max_concurrent")]
+    pub max_concurrent: usize,
+    /// Ban the client for this long after it hits a tarpit path
+    #[serde(default)]
+    pub ban_secs: Option<u64>,
+}
+
+impl Default for TarpitConfig {
+    fn default() -> Self {
+        Self {
+            paths: default_tarpit_paths(),
+            action: TarpitAction::default(),
+            delay_secs: default_tarpit_delay_secs(),
+            fake_body: default_tarpit_fake_body(),
+            max_concurrent: default_tarpit_max_concurrent(),
+            ban_secs: None,
+        }
+    }
+}
+
+/// Responses per status within `window_secs` after which a client is banned. Statuses left
+/// out are not counted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoBanThresholds {
+    /// 401 Unauthorized
+    #[serde(default)]
+    pub unauthorized: Option<u32>,
+    /// 403 Forbidden
+    #[serde(default)]
+    pub forbidden: Option<u32>,
+    /// 404 Not Found
+    #[serde(default)]
+    pub not_found: Option<u32>,
+    /// 429 Too Many Requests
+    #[serde(default)]
+    pub too_many_requests: Option<u32>,
+}
+
+fn default_auto_ban_thresholds() -> AutoBanThresholds {
+    AutoBanThresholds {
+        unauthorized: Some(10),
+        forbidden: Some(20),
+        not_found: Some(50),
+        too_many_requests: Some(100),
+    }
+}
+
+fn default_auto_ban_window_secs() -> u64 {
+    60
+}
+
+fn default_auto_ban_ban_secs() -> u64 {
+    600
+}
+
+/// Fail2ban-style bans: clients over a threshold are refused at accept on every listener.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoBanConfig {
+    #[serde(default = "default_auto_ban_thresholds")]
+    pub thresholds: AutoBanThresholds,
+    /// Period responses are counted over
+    #[serde(default = "default_auto_ban_window_secs")]
+    pub window_secs: u64,
+    /// How long a ban lasts
+    #[serde(default = "default_auto_ban_ban_secs")]
+    pub ban_secs: u64,
+    /// Addresses or CIDR ranges that are never banned
+    #[serde(default)]
+    pub exempt: Vec<String>,
+}
+
+impl Default for AutoBanConfig {
+    fn default() -> Self {
+        Self {
+            thresholds: default_auto_ban_thresholds(),
+            window_secs: default_auto_ban_window_secs(),
+            ban_secs: default_auto_ban_ban_secs(),
+            exempt: Vec::new(),
+        }
+    }
+}
+
+fn default_cluster_key_prefix() -> String {
+    "bifrost".to_string()
+}
+
+fn default_cluster_sync_interval_secs() -> u64 {
+    5
+}
+
+fn default_cluster_timeout_ms() -> u64 {
+    250
+}
+
+/// Redis store shared by all instances of a cluster.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterConfig {
+    /// `redis://[user:password@]host[:port][/db]`
+    pub redis_url: String,
+    /// Prefix of every key, so several clusters can share one Redis
+    #[serde(default = "default_cluster_key_prefix")]
+    pub key_prefix: String,
+    /// Name of this instance; defaults to the host name and process id
+    #[serde(default)]
+    pub node_id: Option<String>,
+    /// How often bans and health results of other instances are taken over
+    #[serde(default = "default_cluster_sync_interval_secs")]
+    pub sync_interval_secs: u64,
+    /// Longest a request waits for the store before falling back to local state
+    #[serde(default = "default_cluster_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+/// Wire format of pushed metrics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsPushFormat {
+    /// Prometheus remote-write 1.0 (snappy-compressed protobuf)
+    #[default]
+    RemoteWrite,
+    /// OTLP/HTTP with a JSON body
+    Otlp,
+}
+
+/// Admin API on its own listener: routes, worker health, the effective configuration, pausing
+/// rate limit rules and draining the proxy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminApiConfig {
+    pub listen_addr: SocketAddr,
+    /// Required as `Authorization: Bearer <token>` on every request when set
+    #[serde(default)]
+    pub token: Option<Secret>,
+}
+
+/// Pushes every metric of the monitoring registry to a collector at a fixed interval.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsPushConfig {
+    /// Receiver URL, e.g. `https://prometheus.example.com/api/v1/write` or
+    /// `https://otel.example.com/v1/metrics`
+    pub url: String,
+    #[serde(default)]
+    pub format: MetricsPushFormat,
+    #[serde(default = "default_metrics_push_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default = "default_metrics_push_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Sent as `Authorization: Bearer <token>`
+    #[serde(default)]
+    pub bearer_token: Option<Secret>,
+    /// HTTP basic authentication; ignored when `bearer_token` is set
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<Secret>,
+    /// Extra request headers, e.g. a tenant id
+    #[serde(default)]
+    pub headers: std::collections::HashMap<String, String>,
+    /// Labels added to every series (remote-write) or resource attributes (OTLP)
+    #[serde(default)]
+    pub labels: std::collections::HashMap<String, String>,
+}
+
+fn default_metrics_push_interval_secs() -> u64 {
+    15
+}
+
+fn default_metrics_push_timeout_secs() -> u64 {
+    10
+}
+
+/// Body format of webhook notifications.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// One JSON object with the event, message and details as fields
+    #[default]
+    Json,
+    /// Slack incoming-webhook payload (`{"text": ...}`)
+    Slack,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// Events sent to this webhook; all of them when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Extra request headers, e.g. an authorization token
+    #[serde(default)]
+    pub headers: std::collections::HashMap<String, String>,
+}
+
+/// Webhooks told about operational events.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationsConfig {
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    /// Notifications per webhook and minute; the rest are dropped (0 for no limit)
+    #[serde(default = "default_notification_max_per_minute")]
+    pub max_per_minute: u32,
+    /// Retries of a failed delivery, with exponential backoff from 1s
+    #[serde(default = "default_notification_retries")]
+    pub retries: u32,
+    #[serde(default = "default_notification_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            webhooks: Vec::new(),
+            max_per_minute: default_notification_max_per_minute(),
+            retries: default_notification_retries(),
+            timeout_secs: default_notification_timeout_secs(),
         }
     }
 }
 
-impl StaticFileConfig {
-    pub fn single(root_dir: String, spa_mode: bool) -> Self {
+fn default_notification_max_per_minute() -> u32 {
+    20
+}
+
+fn default_notification_retries() -> u32 {
+    3
+}
+
+fn default_notification_timeout_secs() -> u64 {
+    5
+}
+
+/// Load balancers or CDNs in front of the proxy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedProxiesConfig {
+    /// Addresses or CIDRs of the trusted hops, e.g. "10.0.0.0/8"
+    #[serde(default)]
+    pub cidrs: Vec<String>,
+    /// Headers naming the client, tried in order (e.g. "cf-connecting-ip", "x-real-ip")
+    /// Default: ["x-forwarded-for"]
+    #[serde(default = "default_client_ip_headers")]
+    pub headers: Vec<String>,
+}
+
+fn default_client_ip_headers() -> Vec<String> {
+    vec!["x-forwarded-for".to_string()]
+}
+
+impl Default for TrustedProxiesConfig {
+    fn default() -> Self {
         Self {
-            mounts: vec![StaticMount {
-                path: "/".to_string(),
-                root_dir,
-                enable_directory_listing: None, // Will inherit from parent
-                index_files: None, // Will inherit from parent
-                spa_mode: Some(spa_mode), // Override SPA mode
-                spa_fallback_file: None, // Will inherit from parent
-                no_cache_files: None, // Will inherit from parent
-                cache_millisecs: None, // Will inherit from parent
-            }],
-            enable_directory_listing: false,
-            index_files: vec!["index.html".to_string(), "index.htm".to_string()],
-            spa_mode,
-            spa_fallback_file: "index.html".to_string(),
-            worker_threads: None,
-            custom_mime_types: std::collections::HashMap::new(),
-            no_cache_files: vec![],
-            cache_millisecs: 3600,
+            cidrs: Vec::new(),
+            headers: default_client_ip_headers(),
         }
     }
+}
 
-    pub fn add_mount(&mut self, path: String, root_dir: String, spa_mode: bool) {
-        self.mounts.push(StaticMount {
-            path,
-            root_dir,
-            enable_directory_listing: None, // Will inherit from parent
-            index_files: None, // Will inherit from parent
-            spa_mode: Some(spa_mode), // Override SPA mode
-            spa_fallback_file: None, // Will inherit from parent
-            no_cache_files: None, // Will inherit from parent
-            cache_millisecs: None, // Will inherit from parent
-        });
-    }
+/// GraphQL requests whose operation is read before routing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphQlConfig {
+    /// Paths whose JSON POST bodies are GraphQL requests
+    /// Default: ["/graphql"]
+    #[serde(default = "default_graphql_paths")]
+    pub paths: Vec<String>,
+    /// Largest body read to find the operation; bigger requests are forwarded unread
+    #[serde(default = "default_json_body_max_bytes")]
+    pub max_body_bytes: usize,
+    /// Distinct operation names given their own metric labels; later ones are counted as "other"
+    #[serde(default = "default_graphql_max_operation_labels")]
+    pub max_operation_labels: usize,
+}
 
-    pub fn add_custom_mime_type(&mut self, extension: String, mime_type: String) {
-        // Remove leading dot if present
-        let clean_ext = extension.strip_prefix('.').unwrap_or(&extension).to_lowercase();
-        self.custom_mime_types.insert(clean_ext, mime_type);
+fn default_graphql_paths() -> Vec<String> {
+    vec!["/graphql".to_string()]
+}
+
+fn default_graphql_max_operation_labels() -> usize {
+    100
+}
+
+impl Default for GraphQlConfig {
+    fn default() -> Self {
+        Self {
+            paths: default_graphql_paths(),
+            max_body_bytes: default_json_body_max_bytes(),
+            max_operation_labels: default_graphql_max_operation_labels(),
+        }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct RelayProxyConfig {
-    pub relay_proxy_url: String,
+/// GraphQL operations picked out by name or type; an empty list allows any.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GraphQlOperationMatchConfig {
+    /// Operation names, e.g. "CreateOrder"
     #[serde(default)]
-    pub relay_proxy_username: Option<String>,
-    #[serde(default)]
-    pub relay_proxy_password: Option<String>,
-    // Domain patterns in NO_PROXY format
-    // Supports: "example.com", ".example.com", "*.example.com", "subdomain.example.com"
+    pub operation_names: Vec<String>,
+    /// "query", "mutation" or "subscription"
     #[serde(default)]
-    pub relay_proxy_domains: Vec<String>,
+    pub operation_types: Vec<String>,
 }
 
+/// A team sharing the instance. Requests to its routes count against its own rate limit rules
+/// and quota, and routes that require an API key accept only its keys.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Config {
-    pub mode: ProxyMode,
-    pub listen_addr: SocketAddr,
-    pub reverse_proxy_target: Option<String>,
-    #[serde(default)]
-    pub reverse_proxy_routes: Vec<ReverseProxyRouteConfig>,
-    pub max_connections: Option<usize>,
-    // New timeout configurations
+pub struct TenantConfig {
+    /// Tenant id, used as the `tenant` metric label
+    pub id: String,
+    /// Ids of the reverse proxy routes the tenant owns; a route belongs to at most one tenant
+    pub routes: Vec<String>,
+    /// Per-client rate limit rules that count only requests to the tenant's routes
     #[serde(default)]
-    pub connect_timeout_secs: Option<u64>,
+    pub rate_limits: Vec<RateLimitRuleConfig>,
+    /// Ids of the API keys accepted on the tenant's routes that set `require_api_key`
+    /// (empty accepts any valid key)
     #[serde(default)]
-    pub idle_timeout_secs: Option<u64>,
+    pub api_keys: Vec<String>,
+    /// Requests allowed per window across all clients of the tenant
     #[serde(default)]
-    pub max_connection_lifetime_secs: Option<u64>,
-    // Legacy timeout field for backward compatibility
+    pub quota: Option<RateLimitWindowConfig>,
+    /// Bearer token required for this tenant's view on the monitoring `tenants_endpoint`
     #[serde(default)]
-    pub timeout_secs: Option<u64>,
-    // Worker threads for reverse proxy and static file serving (shared)
+    pub admin_token: Option<Secret>,
+}
+
+/// Where `{secret}` references in credential fields are read from, and how often they are
+/// read again.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SecretsConfig {
+    /// Seconds between re-reads of every `{secret}` reference; `0` reads them once at startup
     #[serde(default)]
-    pub worker_threads: Option<usize>,
-    pub static_files: Option<StaticFileConfig>,
+    pub refresh_secs: u64,
+    /// Longest an `exec:` command may run
+    #[serde(default = "default_secret_exec_timeout_secs")]
+    pub exec_timeout_secs: u64,
+    /// Server for `vault:` references
     #[serde(default)]
-    pub private_key: Option<String>,
+    pub vault: Option<VaultConfig>,
+}
+
+fn default_secret_exec_timeout_secs() -> u64 {
+    10
+}
+
+/// When the configuration file is read again while the proxy runs. SIGHUP always triggers a
+/// reload; routes, rate limit rules, static file mounts and relay proxies take effect from the
+/// next request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigReloadConfig {
+    /// Also reload whenever the file's modification time changes
     #[serde(default)]
-    pub certificate: Option<String>,
+    pub watch: bool,
+    /// How often the file's modification time is checked
+    #[serde(default = "default_config_watch_interval_secs")]
+    pub watch_interval_secs: u64,
+}
+
+impl Default for ConfigReloadConfig {
+    fn default() -> Self {
+        Self {
+            watch: false,
+            watch_interval_secs: default_config_watch_interval_secs(),
+        }
+    }
+}
+
+fn default_config_watch_interval_secs() -> u64 {
+    2
+}
+
+/// Rewriting of request paths into one canonical form before any route predicate or static
+/// mount is matched, and before the request is forwarded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathNormalizationConfig {
+    /// Set to false to pass paths on exactly as the client sent them
+    #[serde(default = "default_path_normalization_enabled")]
+    pub enabled: bool,
+    /// Which percent-encoded characters are decoded
     #[serde(default)]
-    pub connection_pool_enabled: Option<bool>,
-    #[serde(default = "default_max_header_size")]
-    pub max_header_size: Option<usize>,
-    // Multiple relay proxy configurations
+    pub percent_decoding: PercentDecoding,
+    /// What happens to `%2F` and `%5C` in a path
     #[serde(default)]
-    pub relay_proxies: Option<Vec<RelayProxyConfig>>,
-    // Legacy single relay proxy fields (deprecated, use relay_proxies instead)
+    pub encoded_slashes: EncodedSlashes,
+    /// Replace runs of `/` with a single one
+    #[serde(default = "default_collapse_slashes")]
+    pub collapse_slashes: bool,
+    /// Remove `.` segments and resolve `..` against the segment before it
+    #[serde(default = "default_resolve_dot_segments")]
+    pub resolve_dot_segments: bool,
+}
+
+impl Default for PathNormalizationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_path_normalization_enabled(),
+            percent_decoding: PercentDecoding::default(),
+            encoded_slashes: EncodedSlashes::default(),
+            collapse_slashes: default_collapse_slashes(),
+            resolve_dot_segments: default_resolve_dot_segments(),
+        }
+    }
+}
+
+fn default_path_normalization_enabled() -> bool {
+    true
+}
+
+fn default_collapse_slashes() -> bool {
+    true
+}
+
+fn default_resolve_dot_segments() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PercentDecoding {
+    /// Leave every escape as sent
+    None,
+    /// Decode escapes of letters, digits and `-._~`, and upper-case the hex digits of the rest
+    #[default]
+    Unreserved,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncodedSlashes {
+    /// Keep them encoded, as part of the segment they appear in
+    #[default]
+    Keep,
+    /// Decode them into path separators before segments are resolved
+    Decode,
+    /// Refuse the request with 400
+    Reject,
+}
+
+/// A HashiCorp Vault server read over its HTTP API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultConfig {
+    /// Base URL, e.g. `https://vault.internal:8200`
+    pub address: String,
+    /// Token sent as `X-Vault-Token`; may itself be a `{secret}env:` or `{secret}file:` reference
+    pub token: Secret,
+    /// Enterprise namespace sent as `X-Vault-Namespace`
     #[serde(default)]
-    pub relay_proxy_url: Option<String>,
+    pub namespace: Option<String>,
+    #[serde(default = "default_vault_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_vault_timeout_secs() -> u64 {
+    10
+}
+
+/// Resolution of backend hostnames. Without this section every new connection asks the
+/// system resolver and pooled connections keep the address they were opened with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsConfig {
+    /// Nameservers as `ip` or `ip:port` (default: the ones in /etc/resolv.conf)
     #[serde(default)]
-    pub relay_proxy_username: Option<String>,
+    pub nameservers: Vec<String>,
+    /// Lower bound for record TTLs, so short TTLs do not cause a query per connection
+    #[serde(default = "default_dns_min_ttl_secs")]
+    pub min_ttl_secs: u64,
+    /// Upper bound for record TTLs
+    #[serde(default = "default_dns_max_ttl_secs")]
+    pub max_ttl_secs: u64,
+    /// How long names answered by the system resolver (hosts file, search domains) are kept
+    #[serde(default = "default_dns_fallback_ttl_secs")]
+    pub fallback_ttl_secs: u64,
+    #[serde(default = "default_dns_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            nameservers: Vec::new(),
+            min_ttl_secs: default_dns_min_ttl_secs(),
+            max_ttl_secs: default_dns_max_ttl_secs(),
+            fallback_ttl_secs: default_dns_fallback_ttl_secs(),
+            timeout_secs: default_dns_timeout_secs(),
+        }
+    }
+}
+
+fn default_dns_min_ttl_secs() -> u64 {
+    5
+}
+
+fn default_dns_max_ttl_secs() -> u64 {
+    300
+}
+
+fn default_dns_fallback_ttl_secs() -> u64 {
+    30
+}
+
+fn default_dns_timeout_secs() -> u64 {
+    2
+}
+
+/// Size limits on request heads, checked on every listener before any handler runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestLimitsConfig {
+    /// Longest request target (path and query, or absolute URI); longer ones get 414
+    #[serde(default = "default_max_uri_bytes")]
+    pub max_uri_bytes: usize,
+    /// Most header fields in a request; more get 431
+    #[serde(default = "default_max_headers")]
+    pub max_headers: usize,
+    /// Largest single header field, name and value; larger ones get 431
+    #[serde(default = "default_max_header_bytes")]
+    pub max_header_bytes: usize,
+}
+
+impl Default for RequestLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_uri_bytes: default_max_uri_bytes(),
+            max_headers: default_max_headers(),
+            max_header_bytes: default_max_header_bytes(),
+        }
+    }
+}
+
+fn default_max_uri_bytes() -> usize {
+    8192
+}
+
+fn default_max_headers() -> usize {
+    100
+}
+
+fn default_max_header_bytes() -> usize {
+    8192
+}
+
+/// Socket options for the proxy listener. Without this section the OS defaults apply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListenerConfig {
+    /// Disable Nagle's algorithm on accepted connections
     #[serde(default)]
-    pub relay_proxy_password: Option<String>,
+    pub tcp_nodelay: bool,
+    /// Enable SO_KEEPALIVE on accepted connections with these timings
     #[serde(default)]
-    pub relay_proxy_domain_suffixes: Option<Vec<String>>,
-    // Basic authentication for forward proxy
+    pub keepalive: Option<TcpKeepaliveConfig>,
+    /// Pending connection queue length passed to listen()
+    #[serde(default = "default_listen_backlog")]
+    pub backlog: u32,
+    /// SO_RCVBUF for the listener and accepted connections
     #[serde(default)]
-    pub proxy_username: Option<String>,
+    pub recv_buffer_bytes: Option<usize>,
+    /// SO_SNDBUF for the listener and accepted connections
     #[serde(default)]
-    pub proxy_password: Option<String>,
-    // Reverse proxy specific configuration
+    pub send_buffer_bytes: Option<usize>,
+    /// Enable TCP_FASTOPEN with this pending-request queue length (Linux only)
     #[serde(default)]
-    pub reverse_proxy_config: Option<ReverseProxyConfig>,
-    // Logging configuration
+    pub tcp_fastopen_queue: Option<u32>,
+    /// Share the port between protocols, told apart by the first bytes of each connection
     #[serde(default)]
-    pub logging: Option<LoggingConfig>,
+    pub protocol_mux: Option<ProtocolMuxConfig>,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            tcp_nodelay: false,
+            keepalive: None,
+            backlog: default_listen_backlog(),
+            recv_buffer_bytes: None,
+            send_buffer_bytes: None,
+            tcp_fastopen_queue: None,
+            protocol_mux: None,
+        }
+    }
+}
+
+/// Where each protocol detected on a shared port goes: "local" for the proxy's own handler on
+/// this listener, a backend "host:port" to relay the raw TCP stream to, or null to close it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolMuxConfig {
+    /// TLS ClientHello (default: "local")
+    #[serde(default = "default_mux_local")]
+    pub tls: Option<String>,
+    /// HTTP/1.x request line or HTTP/2 preface (default: "local")
+    #[serde(default = "default_mux_local")]
+    pub http: Option<String>,
+    /// SSH identification string
     #[serde(default)]
-    pub monitoring: MonitoringConfig,
+    pub ssh: Option<String>,
+    /// Anything else, including clients that send nothing within `sniff_timeout_ms`
     #[serde(default)]
-    pub websocket: Option<WebSocketConfig>,
+    pub other: Option<String>,
+    /// How long to wait for enough bytes to recognize the protocol
+    #[serde(default = "default_mux_sniff_timeout_ms")]
+    pub sniff_timeout_ms: u64,
+    /// Timeout for connecting to relay backends
+    #[serde(default = "default_mux_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// TLS connections sent on by the server name in their ClientHello, still encrypted;
+    /// the first matching route wins and unmatched connections follow `tls`
     #[serde(default)]
-    pub rate_limiting: Option<RateLimitingConfig>,
+    pub sni_routes: Vec<SniRouteConfig>,
+}
+
+/// TLS passthrough for some server names.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SniRouteConfig {
+    /// Exact names, or "*.example.com" for any name below example.com
+    pub hostnames: Vec<String>,
+    /// Backend "host:port" that terminates TLS itself, or "local"
+    pub backend: String,
+}
+
+fn default_mux_local() -> Option<String> {
+    Some("local".to_string())
+}
+
+fn default_mux_sniff_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_mux_connect_timeout_secs() -> u64 {
+    5
+}
+
+impl Default for ProtocolMuxConfig {
+    fn default() -> Self {
+        Self {
+            tls: default_mux_local(),
+            http: default_mux_local(),
+            ssh: None,
+            other: None,
+            sniff_timeout_ms: default_mux_sniff_timeout_ms(),
+            connect_timeout_secs: default_mux_connect_timeout_secs(),
+            sni_routes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TcpKeepaliveConfig {
+    /// Idle time before the first probe
+    #[serde(default = "default_keepalive_time")]
+    pub time_secs: u64,
+    /// Time between probes
+    #[serde(default = "default_keepalive_interval")]
+    pub interval_secs: u64,
+    /// Unanswered probes before the connection is dropped
+    #[serde(default = "default_keepalive_retries")]
+    pub retries: u32,
 }
 
 fn default_max_header_size() -> Option<usize> {
@@ -710,6 +3234,8 @@ enum ConfigLoadError {
 
     #[error("invalid environment interpolation at `{path}`: {reason}")]
     InvalidInterpolation { path: String, reason: String },
+    #[error("unknown config field(s) {}; fix the spelling or start with --allow-unknown-fields", .0.join(", "))]
+    UnknownFields(Vec<String>),
 }
 
 fn is_var_start(byte: u8) -> bool {
@@ -859,6 +3385,34 @@ where
     Ok(())
 }
 
+/// Collects the object keys of `raw` that did not survive deserialization, i.e. fields no
+/// config type knows about. `parsed` is the deserialized config serialized back to JSON.
+fn collect_unknown_fields(
+    raw: &serde_json::Value,
+    parsed: &serde_json::Value,
+    path: &str,
+    unknown: &mut Vec<String>,
+) {
+    match (raw, parsed) {
+        (serde_json::Value::Object(raw_map), serde_json::Value::Object(parsed_map)) => {
+            for (key, raw_item) in raw_map {
+                let child_path = format!("{}.{}", path, key);
+                match parsed_map.get(key) {
+                    Some(parsed_item) => collect_unknown_fields(raw_item, parsed_item, &child_path, unknown),
+                    None => unknown.push(child_path),
+                }
+            }
+        }
+        (serde_json::Value::Array(raw_items), serde_json::Value::Array(parsed_items)) => {
+            for (idx, (raw_item, parsed_item)) in raw_items.iter().zip(parsed_items).enumerate() {
+                let child_path = format!("{}[{}]", path, idx);
+                collect_unknown_fields(raw_item, parsed_item, &child_path, unknown);
+            }
+        }
+        _ => {}
+    }
+}
+
 fn interpolate_env_in_json(value: &mut serde_json::Value) -> Result<(), ConfigLoadError> {
     let mut resolver = |name: &str| std::env::var(name).ok();
     interpolate_env_in_json_with(value, "$", &mut resolver)
@@ -894,16 +3448,78 @@ impl Default for Config {
             monitoring: MonitoringConfig::default(),
             websocket: None,
             rate_limiting: None,
+            forward_proxy_config: None,
+            api_keys: None,
+            resource_monitor: None,
+            preflight: None,
+            certificate_expiry: None,
+            tls_session: None,
+            client_auth: None,
+            http2: None,
+            listener: None,
+            request_limits: None,
+            route_fallback: None,
+            route_metadata: None,
+            access_log: None,
+            privacy: None,
+            tarpit: None,
+            auto_ban: None,
+            cluster: None,
+            metrics_push: None,
+            admin_api: None,
+            notifications: None,
+            dns: None,
+            trusted_proxies: None,
+            graphql: None,
+            tenants: Vec::new(),
+            secrets: None,
+            config_reload: None,
+            path_normalization: None,
+            forward_listen_addr: None,
         }
     }
 }
 
 impl Config {
+    /// Returns the relay proxy rules in evaluation order, converting the legacy
+    /// single-relay fields when `relay_proxies` is not set.
+    pub fn effective_relay_proxies(&self) -> Vec<RelayProxyConfig> {
+        if let Some(relay_proxies) = &self.relay_proxies {
+            relay_proxies.clone()
+        } else if let Some(url) = &self.relay_proxy_url {
+            vec![RelayProxyConfig {
+                relay_proxy_url: url.clone(),
+                relay_proxy_username: self.relay_proxy_username.clone(),
+                relay_proxy_password: self.relay_proxy_password.clone(),
+                relay_proxy_domains: self.relay_proxy_domain_suffixes.clone().unwrap_or_default(),
+                relay_proxy_auth_scheme: RelayAuthScheme::default(),
+            }]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Loads a config file, rejecting fields no config type knows (typically typos).
     pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_file_with_options(path, false)
+    }
+
+    /// Loads a config file; with `allow_unknown_fields` unknown fields are only logged, e.g. for
+    /// a file shared with a newer version.
+    pub fn from_file_with_options(path: &str, allow_unknown_fields: bool) -> Result<Self, Box<dyn std::error::Error>> {
         let content = std::fs::read_to_string(path)?;
         let mut raw: serde_json::Value = serde_json::from_str(&content)?;
         interpolate_env_in_json(&mut raw)?;
-        let config: Config = serde_json::from_value(raw)?;
+        let config: Config = serde_json::from_value(raw.clone())?;
+
+        let mut unknown = Vec::new();
+        collect_unknown_fields(&raw, &serde_json::to_value(&config)?, "$", &mut unknown);
+        if !unknown.is_empty() {
+            if !allow_unknown_fields {
+                return Err(ConfigLoadError::UnknownFields(unknown).into());
+            }
+            log::warn!("Ignoring unknown config field(s): {}", unknown.join(", "));
+        }
         Ok(config)
     }
 
@@ -912,6 +3528,14 @@ impl Config {
         std::fs::write(path, content)?;
         Ok(())
     }
+
+    /// The configuration as pretty JSON with passwords, tokens, keys and URL credentials masked,
+    /// for echoing it back to an operator.
+    pub fn to_redacted_json(&self) -> Result<String, serde_json::Error> {
+        let mut value = serde_json::to_value(self)?;
+        crate::redact::redact_json(&mut value);
+        serde_json::to_string_pretty(&value)
+    }
 }
 
 #[cfg(test)]
@@ -990,10 +3614,39 @@ mod tests {
         write!(file, "{}", config_json).unwrap();
 
         let config = Config::from_file(file.path().to_str().unwrap()).unwrap();
-        assert_eq!(config.proxy_password, Some(path_value));
+        assert_eq!(config.proxy_password.unwrap().expose().as_str(), path_value);
         assert_eq!(
             config.relay_proxies.unwrap()[0].relay_proxy_url,
             format!("http://{}@localhost:3128", home_value)
         );
     }
+
+    #[test]
+    fn config_from_file_rejects_unknown_fields_unless_allowed() {
+        let config_json = json!({
+            "mode": "Reverse",
+            "listen_addr": "127.0.0.1:8080",
+            "reverse_proxy_targt": "http://backend:3000",
+            "reverse_proxy_routes": [
+                {
+                    "id": "api",
+                    "target": "http://api:8080",
+                    "metadata": { "team": "payments" },
+                    "predicates": [{ "type": "Path", "patterns": ["/api/**"], "match_trailing_slsh": true }]
+                }
+            ]
+        });
+
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "{}", config_json).unwrap();
+        let path = file.path().to_str().unwrap();
+
+        let err = Config::from_file(path).unwrap_err().to_string();
+        assert!(err.contains("$.reverse_proxy_targt"));
+        assert!(err.contains("$.reverse_proxy_routes[0].predicates[0].match_trailing_slsh"));
+        assert!(!err.contains("team"));
+
+        let config = Config::from_file_with_options(path, true).unwrap();
+        assert_eq!(config.reverse_proxy_routes.len(), 1);
+    }
 }
@@ -0,0 +1,920 @@
+//! JSON Schema (draft 2020-12) for the configuration file, printed by `--print-schema`.
+//!
+//! The schema is written by hand next to the config types; the tests compare every object
+//! definition with the fields serde actually accepts, so a new config field without a schema
+//! entry fails the build's tests rather than going unnoticed.
+
+use serde_json::{Map, Value, json};
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+/// Any unsigned integer field (`u16`, `u32`, `u64`, `usize`).
+fn unsigned() -> Value {
+    json!({ "type": "integer", "minimum": 0 })
+}
+
+fn integer() -> Value {
+    json!({ "type": "integer" })
+}
+
+fn number() -> Value {
+    json!({ "type": "number" })
+}
+
+fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+/// A JSON object with free-form keys.
+fn map(values: Value) -> Value {
+    json!({ "type": "object", "additionalProperties": values })
+}
+
+fn nullable(schema: Value) -> Value {
+    json!({ "anyOf": [schema, { "type": "null" }] })
+}
+
+fn reference(name: &str) -> Value {
+    json!({ "$ref": format!("#/$defs/{}", name) })
+}
+
+fn one_of_strings(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+fn object(properties: Vec<(&str, Value)>, required: &[&str]) -> Value {
+    let properties: Map<String, Value> =
+        properties.into_iter().map(|(name, schema)| (name.to_string(), schema)).collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+fn definitions() -> Vec<(&'static str, Value)> {
+    vec![
+        ("ProxyMode", one_of_strings(&["Forward", "Reverse", "Combined"])),
+        ("LogLevel", one_of_strings(&["trace", "debug", "info", "warn", "error"])),
+        ("LogFormat", one_of_strings(&["text", "json"])),
+        ("LogOutputType", one_of_strings(&["stdout", "file"])),
+        ("LoadBalancingPolicy", one_of_strings(&["round_robin", "weighted_round_robin", "least_connections", "least_requests", "random"])),
+        ("StickyMode", one_of_strings(&["cookie", "header", "source_ip"])),
+        ("HmacAlgorithm", one_of_strings(&["sha256", "sha384", "sha512"])),
+        ("SignatureEncoding", one_of_strings(&["hex", "base64"])),
+        ("BodyMode", one_of_strings(&["stream", "buffer"])),
+        ("EtagMode", one_of_strings(&["off", "mtime", "content"])),
+        ("ReplayMode", one_of_strings(&["record", "replay"])),
+        ("MethodOverride", one_of_strings(&["forward", "apply", "strip", "reject"])),
+        ("WebSocketExtensionsMode", one_of_strings(&["passthrough", "strip"])),
+        ("HookFailurePolicy", one_of_strings(&["bypass", "block"])),
+        ("RelayAuthScheme", one_of_strings(&["basic", "ntlm"])),
+        ("EarlyDataPolicy", one_of_strings(&["reject", "delay"])),
+        ("ClientAuthMode", one_of_strings(&["required", "optional"])),
+        ("ClientIpMode", one_of_strings(&["full", "truncate", "hash"])),
+        ("LogTarget", object(vec![
+            ("type", reference("LogOutputType")),
+            ("path", nullable(string())),
+            ("level", nullable(reference("LogLevel"))),
+        ], &["type"])),
+        ("LoggingConfig", object(vec![
+            ("level", nullable(reference("LogLevel"))),
+            ("format", nullable(reference("LogFormat"))),
+            ("targets", nullable(array(reference("LogTarget")))),
+            ("security", nullable(reference("SecurityLogConfig"))),
+        ], &[])),
+        ("SecurityLogConfig", object(vec![
+            ("type", reference("LogOutputType")),
+            ("path", nullable(string())),
+            ("main_log", boolean()),
+        ], &["type"])),
+        ("MonitoringConfig", object(vec![
+            ("enabled", boolean()),
+            ("metrics_endpoint", string()),
+            ("metrics_json_endpoint", string()),
+            ("dashboard_endpoint", string()),
+            ("health_endpoint", string()),
+            ("status_endpoint", string()),
+            ("ready_endpoint", string()),
+            ("relay_match_endpoint", string()),
+            ("rate_limits_endpoint", string()),
+            ("rate_limit_updates", boolean()),
+            ("bans_endpoint", string()),
+            ("tenants_endpoint", string()),
+            ("include_detailed_metrics", boolean()),
+            ("listen_address", nullable(string())),
+        ], &[])),
+        ("RateLimitingConfig", object(vec![
+            ("enabled", boolean()),
+            ("default_limit", nullable(reference("RateLimitWindowConfig"))),
+            ("rules", array(reference("RateLimitRuleConfig"))),
+            ("rules_file", nullable(string())),
+            ("refresh_secs", unsigned()),
+            ("deny", nullable(reference("RateLimitClientMatchConfig"))),
+            ("deny_status", unsigned()),
+            ("response_headers", boolean()),
+        ], &[])),
+        ("RateLimitWindowConfig", object(vec![
+            ("limit", unsigned()),
+            ("window_secs", unsigned()),
+        ], &["limit", "window_secs"])),
+        ("RateLimitRuleConfig", object(vec![
+            ("id", string()),
+            ("limit", unsigned()),
+            ("window_secs", unsigned()),
+            ("path_prefix", nullable(string())),
+            ("methods", nullable(array(string()))),
+            ("exempt", nullable(reference("RateLimitClientMatchConfig"))),
+            ("graphql", nullable(reference("GraphQlOperationMatchConfig"))),
+        ], &["id", "limit", "window_secs"])),
+        ("RateLimitClientMatchConfig", object(vec![
+            ("cidrs", array(string())),
+            ("api_keys", array(string())),
+            ("headers", map(array(string()))),
+        ], &[])),
+        ("WebSocketConfig", object(vec![
+            ("enabled", boolean()),
+            ("allowed_origins", array(string())),
+            ("supported_protocols", array(string())),
+            ("timeout_seconds", unsigned()),
+            ("extensions", reference("WebSocketExtensionsMode")),
+            ("allowed_extensions", array(string())),
+            ("shutdown_drain_seconds", unsigned()),
+        ], &[])),
+        ("HealthCheckConfig", object(vec![
+            ("interval_secs", unsigned()),
+            ("endpoint", nullable(string())),
+            ("timeout_secs", unsigned()),
+        ], &[])),
+        ("TcpKeepaliveConfig", object(vec![
+            ("time_secs", unsigned()),
+            ("interval_secs", unsigned()),
+            ("retries", unsigned()),
+        ], &[])),
+        ("ReverseProxyConfig", object(vec![
+            ("pool_max_idle_per_host", unsigned()),
+            ("pool_idle_timeout_secs", unsigned()),
+            ("health_check", nullable(reference("HealthCheckConfig"))),
+            ("keepalive", nullable(reference("TcpKeepaliveConfig"))),
+            ("retry_on_reuse_error", boolean()),
+            ("prewarm", nullable(reference("PrewarmConfig"))),
+            ("preserve_host_header", nullable(boolean())),
+            ("relay_proxies", array(reference("RelayProxyConfig"))),
+            ("source_addresses", array(string())),
+            ("interface", nullable(string())),
+            ("dscp", nullable(unsigned())),
+            ("header_case", reference("HeaderCase")),
+        ], &[])),
+        ("HeaderCase", one_of_strings(&["lowercase", "title_case", "preserve"])),
+        ("PrewarmConfig", object(vec![
+            ("connections", unsigned()),
+            ("path", string()),
+            ("interval_secs", nullable(unsigned())),
+        ], &[])),
+        ("ReverseProxyTargetConfig", object(vec![
+            ("id", string()),
+            ("url", string()),
+            ("weight", unsigned()),
+            ("enabled", boolean()),
+        ], &["id", "url"])),
+        ("OutlierDetectionConfig", object(vec![
+            ("enabled", boolean()),
+            ("consecutive_failures", unsigned()),
+            ("window_requests", unsigned()),
+            ("min_requests", unsigned()),
+            ("min_success_rate", number()),
+            ("slow_latency_factor", number()),
+            ("latency_ewma_alpha", number()),
+            ("ejection_secs", unsigned()),
+        ], &[])),
+        ("DynamicWeightsConfig", object(vec![
+            ("error_penalty", number()),
+        ], &[])),
+        ("LoadBalancingConfig", object(vec![
+            ("policy", reference("LoadBalancingPolicy")),
+            ("outlier_detection", reference("OutlierDetectionConfig")),
+            ("dynamic_weights", nullable(reference("DynamicWeightsConfig"))),
+            ("slow_start", nullable(reference("SlowStartConfig"))),
+        ], &[])),
+        ("SlowStartConfig", object(vec![
+            ("window_secs", unsigned()),
+            ("min_percent", unsigned()),
+        ], &[])),
+        ("StickyConfig", object(vec![
+            ("mode", reference("StickyMode")),
+            ("cookie_name", nullable(string())),
+            ("header_name", nullable(string())),
+            ("ttl_seconds", nullable(unsigned())),
+        ], &["mode"])),
+        ("HeaderOverrideConfig", object(vec![
+            ("header_name", string()),
+            ("allowed_values", map(string())),
+            ("allowed_groups", map(array(string()))),
+        ], &["header_name"])),
+        ("RetryPolicyConfig", object(vec![
+            ("max_attempts", unsigned()),
+            ("retry_on_connect_error", boolean()),
+            ("retry_on_statuses", array(unsigned())),
+            ("methods", array(string())),
+        ], &[])),
+        ("HmacVerificationConfig", object(vec![
+            ("secret", string()),
+            ("algorithm", reference("HmacAlgorithm")),
+            ("signature_header", string()),
+            ("signature_prefix", nullable(string())),
+            ("encoding", reference("SignatureEncoding")),
+            ("timestamp_header", string()),
+            ("max_skew_secs", unsigned()),
+            ("payload_format", string()),
+            ("max_body_bytes", unsigned()),
+        ], &["secret"])),
+        ("FanOutConfig", object(vec![
+            ("targets", array(string())),
+            ("sample_rate", number()),
+            ("timeout_ms", unsigned()),
+            ("max_body_bytes", unsigned()),
+        ], &["targets"])),
+        ("AggregateBranchConfig", object(vec![
+            ("name", string()),
+            ("url", string()),
+            ("timeout_ms", nullable(unsigned())),
+        ], &["name", "url"])),
+        ("AggregateConfig", object(vec![
+            ("branches", array(reference("AggregateBranchConfig"))),
+            ("timeout_ms", unsigned()),
+            ("template", json!({})),
+            ("require_all", boolean()),
+            ("max_body_bytes", unsigned()),
+        ], &["branches"])),
+        ("ReplayConfig", object(vec![
+            ("mode", reference("ReplayMode")),
+            ("dir", string()),
+            ("methods", array(string())),
+            ("match_headers", array(string())),
+            ("unavailable_statuses", array(unsigned())),
+        ], &["dir"])),
+        ("StaticResponseConfig", object(vec![
+            ("status", unsigned()),
+            ("body", string()),
+            ("content_type", string()),
+            ("headers", map(string())),
+            ("template", boolean()),
+        ], &[])),
+        ("RouteLimitsConfig", object(vec![
+            ("connect_timeout_secs", nullable(unsigned())),
+            ("response_timeout_secs", nullable(unsigned())),
+            ("idle_timeout_secs", nullable(unsigned())),
+            ("max_body_bytes", nullable(unsigned())),
+            ("request_body", reference("BodyMode")),
+            ("response_body", reference("BodyMode")),
+            ("max_buffer_bytes", nullable(unsigned())),
+        ], &[])),
+        ("UpstreamTlsConfig", object(vec![
+            ("verify", boolean()),
+            ("ca_file", nullable(string())),
+            ("server_name", nullable(string())),
+            ("http2", boolean()),
+        ], &[])),
+        ("RoutePredicateConfig", route_predicate()),
+        ("ReverseProxyRouteConfig", object(vec![
+            ("id", string()),
+            ("target", nullable(string())),
+            ("targets", array(reference("ReverseProxyTargetConfig"))),
+            ("load_balancing", nullable(reference("LoadBalancingConfig"))),
+            ("sticky", nullable(reference("StickyConfig"))),
+            ("header_override", nullable(reference("HeaderOverrideConfig"))),
+            ("retry_policy", nullable(reference("RetryPolicyConfig"))),
+            ("reverse_proxy_config", nullable(reference("ReverseProxyConfig"))),
+            ("strip_path_prefix", nullable(string())),
+            ("preserve_host_header", nullable(boolean())),
+            ("priority", nullable(integer())),
+            ("require_api_key", boolean()),
+            ("hmac_verification", nullable(reference("HmacVerificationConfig"))),
+            ("fan_out", nullable(reference("FanOutConfig"))),
+            ("aggregate", nullable(reference("AggregateConfig"))),
+            ("replay", nullable(reference("ReplayConfig"))),
+            ("static_response", nullable(reference("StaticResponseConfig"))),
+            ("allowed_methods", array(string())),
+            ("method_override", nullable(reference("MethodOverride"))),
+            ("allowed_upgrades", array(string())),
+            ("path_param_headers", map(string())),
+            ("limits", nullable(reference("RouteLimitsConfig"))),
+            ("upstream_tls", nullable(reference("UpstreamTlsConfig"))),
+            ("metadata", map(string())),
+            ("predicates", array(reference("RoutePredicateConfig"))),
+        ], &["id"])),
+        ("StaticMount", object(vec![
+            ("path", string()),
+            ("root_dir", string()),
+            ("enable_directory_listing", nullable(boolean())),
+            ("index_files", nullable(array(string()))),
+            ("spa_mode", nullable(boolean())),
+            ("spa_fallback_file", nullable(string())),
+            ("no_cache_files", nullable(array(string()))),
+            ("cache_millisecs", nullable(unsigned())),
+            ("etag", nullable(reference("EtagMode"))),
+            ("last_modified", nullable(boolean())),
+            ("precompressed", nullable(boolean())),
+        ], &["path", "root_dir"])),
+        ("StaticFileConfig", object(vec![
+            ("mounts", array(reference("StaticMount"))),
+            ("enable_directory_listing", boolean()),
+            ("index_files", array(string())),
+            ("spa_mode", boolean()),
+            ("spa_fallback_file", string()),
+            ("worker_threads", nullable(unsigned())),
+            ("custom_mime_types", map(string())),
+            ("no_cache_files", array(string())),
+            ("cache_millisecs", unsigned()),
+            ("etag", reference("EtagMode")),
+            ("last_modified", boolean()),
+            ("precompressed", boolean()),
+        ], &["mounts", "enable_directory_listing", "index_files", "spa_mode", "spa_fallback_file"])),
+        ("TunnelDomainLimitConfig", object(vec![
+            ("domains", array(string())),
+            ("max_concurrent", unsigned()),
+        ], &["domains", "max_concurrent"])),
+        ("ByteQuotaConfig", object(vec![
+            ("limit_bytes", unsigned()),
+            ("window_secs", unsigned()),
+        ], &["limit_bytes"])),
+        ("TunnelLimitsConfig", object(vec![
+            ("max_per_destination", nullable(unsigned())),
+            ("max_per_client_per_destination", nullable(unsigned())),
+            ("domain_limits", array(reference("TunnelDomainLimitConfig"))),
+        ], &[])),
+        ("FtpGatewayConfig", object(vec![
+            ("enabled", boolean()),
+            ("anonymous_password", string()),
+            ("timeout_secs", unsigned()),
+        ], &[])),
+        ("ContentHookConfig", object(vec![
+            ("url", string()),
+            ("inspect_requests", boolean()),
+            ("inspect_responses", boolean()),
+            ("timeout_ms", unsigned()),
+            ("on_failure", reference("HookFailurePolicy")),
+            ("max_body_bytes", unsigned()),
+        ], &["url"])),
+        ("ScanContentTypeRule", object(vec![
+            ("content_type", string()),
+            ("scan", boolean()),
+            ("min_size_bytes", nullable(unsigned())),
+        ], &["content_type"])),
+        ("DownloadScanConfig", object(vec![
+            ("clamd_addr", string()),
+            ("min_size_bytes", unsigned()),
+            ("max_size_bytes", unsigned()),
+            ("timeout_ms", unsigned()),
+            ("on_failure", reference("HookFailurePolicy")),
+            ("content_types", array(reference("ScanContentTypeRule"))),
+        ], &[])),
+        ("ConnectPortOverrideConfig", object(vec![
+            ("domains", array(string())),
+            ("ports", array(unsigned())),
+        ], &["domains", "ports"])),
+        ("ConnectPortsConfig", object(vec![
+            ("allowed_ports", array(unsigned())),
+            ("overrides", array(reference("ConnectPortOverrideConfig"))),
+        ], &[])),
+        ("ConnectRequestLimitsConfig", object(vec![
+            ("header_timeout_secs", unsigned()),
+            ("max_line_bytes", unsigned()),
+            ("max_headers", unsigned()),
+        ], &[])),
+        ("DigestAuthConfig", object(vec![
+            ("enabled", boolean()),
+            ("nonce_ttl_secs", unsigned()),
+        ], &[])),
+        ("AuthLockoutConfig", object(vec![
+            ("enabled", boolean()),
+            ("max_failures", unsigned()),
+            ("window_secs", unsigned()),
+            ("lockout_secs", unsigned()),
+            ("base_delay_ms", unsigned()),
+            ("max_delay_ms", unsigned()),
+        ], &[])),
+        ("NegotiateAuthConfig", object(vec![
+            ("enabled", boolean()),
+            ("strip_realm", nullable(string())),
+        ], &[])),
+        ("ForwardProxyConfig", object(vec![
+            ("tunnel_limits", nullable(reference("TunnelLimitsConfig"))),
+            ("ftp_gateway", nullable(reference("FtpGatewayConfig"))),
+            ("content_hook", nullable(reference("ContentHookConfig"))),
+            ("download_scan", nullable(reference("DownloadScanConfig"))),
+            ("connect_ports", nullable(reference("ConnectPortsConfig"))),
+            ("connect_request_limits", nullable(reference("ConnectRequestLimitsConfig"))),
+            ("digest_auth", nullable(reference("DigestAuthConfig"))),
+            ("auth_lockout", nullable(reference("AuthLockoutConfig"))),
+            ("negotiate_auth", nullable(reference("NegotiateAuthConfig"))),
+            ("byte_quota", nullable(reference("ByteQuotaConfig"))),
+        ], &[])),
+        ("RelayProxyConfig", object(vec![
+            ("relay_proxy_url", string()),
+            ("relay_proxy_username", nullable(string())),
+            ("relay_proxy_password", nullable(string())),
+            ("relay_proxy_domains", array(string())),
+            ("relay_proxy_auth_scheme", reference("RelayAuthScheme")),
+        ], &["relay_proxy_url"])),
+        ("ApiKeyEntryConfig", object(vec![
+            ("id", string()),
+            ("key", string()),
+            ("tier", nullable(string())),
+            ("routes", array(string())),
+        ], &["id", "key"])),
+        ("ApiKeyTierConfig", object(vec![
+            ("name", string()),
+            ("limit", unsigned()),
+            ("window_secs", unsigned()),
+        ], &["name", "limit", "window_secs"])),
+        ("ApiKeysConfig", object(vec![
+            ("header_name", string()),
+            ("query_param", nullable(string())),
+            ("keys", array(reference("ApiKeyEntryConfig"))),
+            ("keys_file", nullable(string())),
+            ("keys_url", nullable(string())),
+            ("refresh_secs", unsigned()),
+            ("tiers", array(reference("ApiKeyTierConfig"))),
+        ], &[])),
+        ("ResourceMonitorConfig", object(vec![
+            ("interval_secs", unsigned()),
+            ("fd_warning_ratio", number()),
+            ("raise_fd_limit", boolean()),
+            ("tunnel_idle_timeout_secs", nullable(unsigned())),
+        ], &[])),
+        ("PreflightConfig", object(vec![
+            ("fail_fast", boolean()),
+            ("connect_timeout_ms", unsigned()),
+            ("cert_warning_days", unsigned()),
+        ], &[])),
+        ("CertificateExpiryConfig", object(vec![
+            ("check_interval_secs", unsigned()),
+            ("warning_days", unsigned()),
+        ], &[])),
+        ("TlsSessionConfig", object(vec![
+            ("session_cache_size", unsigned()),
+            ("tickets", boolean()),
+            ("ticket_lifetime_secs", unsigned()),
+            ("ticket_key_file", nullable(string())),
+            ("max_early_data_bytes", unsigned()),
+            ("early_data_policy", reference("EarlyDataPolicy")),
+        ], &[])),
+        ("ClientAuthConfig", object(vec![
+            ("ca_file", string()),
+            ("mode", reference("ClientAuthMode")),
+            ("subject_header", string()),
+        ], &["ca_file"])),
+        ("Http2Config", object(vec![
+            ("alpn", boolean()),
+            ("h2c", boolean()),
+            ("max_concurrent_streams", unsigned()),
+            ("keep_alive_interval_secs", nullable(unsigned())),
+        ], &[])),
+        ("RequestLimitsConfig", object(vec![
+            ("max_uri_bytes", unsigned()),
+            ("max_headers", unsigned()),
+            ("max_header_bytes", unsigned()),
+        ], &[])),
+        ("ListenerConfig", object(vec![
+            ("tcp_nodelay", boolean()),
+            ("keepalive", nullable(reference("TcpKeepaliveConfig"))),
+            ("backlog", unsigned()),
+            ("recv_buffer_bytes", nullable(unsigned())),
+            ("send_buffer_bytes", nullable(unsigned())),
+            ("tcp_fastopen_queue", nullable(unsigned())),
+            ("protocol_mux", nullable(reference("ProtocolMuxConfig"))),
+        ], &[])),
+        ("ProtocolMuxConfig", object(vec![
+            ("tls", nullable(string())),
+            ("http", nullable(string())),
+            ("ssh", nullable(string())),
+            ("other", nullable(string())),
+            ("sniff_timeout_ms", unsigned()),
+            ("connect_timeout_secs", unsigned()),
+            ("sni_routes", array(reference("SniRouteConfig"))),
+        ], &[])),
+        ("SniRouteConfig", object(vec![
+            ("hostnames", array(string())),
+            ("backend", string()),
+        ], &["hostnames", "backend"])),
+        ("RouteFallbackConfig", object(vec![
+            ("route_id", nullable(string())),
+            ("status", unsigned()),
+            ("body", string()),
+            ("content_type", string()),
+        ], &[])),
+        ("RouteMetadataConfig", object(vec![
+            ("access_log", boolean()),
+            ("response_header_prefix", nullable(string())),
+            ("metric_labels", array(string())),
+        ], &[])),
+        ("AccessLogPolicyConfig", object(vec![
+            ("sample_rate", number()),
+            ("max_per_second", nullable(unsigned())),
+            ("min_status", nullable(unsigned())),
+            ("min_duration_ms", nullable(unsigned())),
+            ("routes", array(string())),
+            ("keep_errors", boolean()),
+        ], &[])),
+        ("PrivacyConfig", object(vec![
+            ("client_ip", reference("ClientIpMode")),
+            ("ipv4_prefix", unsigned()),
+            ("ipv6_prefix", unsigned()),
+            ("hash_key", nullable(string())),
+            ("key_rotation_secs", unsigned()),
+        ], &[])),
+        ("TarpitAction", one_of_strings(&["delay", "fake"])),
+        ("TarpitConfig", object(vec![
+            ("paths", array(string())),
+            ("action", reference("TarpitAction")),
+            ("delay_secs", unsigned()),
+            ("fake_body", string()),
+            ("max_concurrent", unsigned()),
+            ("ban_secs", nullable(unsigned())),
+        ], &[])),
+        ("AutoBanThresholds", object(vec![
+            ("unauthorized", nullable(unsigned())),
+            ("forbidden", nullable(unsigned())),
+            ("not_found", nullable(unsigned())),
+            ("too_many_requests", nullable(unsigned())),
+        ], &[])),
+        ("AutoBanConfig", object(vec![
+            ("thresholds", reference("AutoBanThresholds")),
+            ("window_secs", unsigned()),
+            ("ban_secs", unsigned()),
+            ("exempt", array(string())),
+        ], &[])),
+        ("ClusterConfig", object(vec![
+            ("redis_url", string()),
+            ("key_prefix", string()),
+            ("node_id", nullable(string())),
+            ("sync_interval_secs", unsigned()),
+            ("timeout_ms", unsigned()),
+        ], &["redis_url"])),
+        ("AdminApiConfig", object(vec![
+            ("listen_addr", string()),
+            ("token", nullable(string())),
+        ], &["listen_addr"])),
+        ("MetricsPushFormat", one_of_strings(&["remote_write", "otlp"])),
+        ("MetricsPushConfig", object(vec![
+            ("url", string()),
+            ("format", reference("MetricsPushFormat")),
+            ("interval_secs", unsigned()),
+            ("timeout_secs", unsigned()),
+            ("bearer_token", nullable(string())),
+            ("username", nullable(string())),
+            ("password", nullable(string())),
+            ("headers", map(string())),
+            ("labels", map(string())),
+        ], &["url"])),
+        ("WebhookFormat", one_of_strings(&["json", "slack"])),
+        ("WebhookConfig", object(vec![
+            ("url", string()),
+            ("format", reference("WebhookFormat")),
+            ("events", array(string())),
+            ("headers", map(string())),
+        ], &["url"])),
+        ("NotificationsConfig", object(vec![
+            ("webhooks", array(reference("WebhookConfig"))),
+            ("max_per_minute", unsigned()),
+            ("retries", unsigned()),
+            ("timeout_secs", unsigned()),
+        ], &[])),
+        ("TrustedProxiesConfig", object(vec![
+            ("cidrs", array(string())),
+            ("headers", array(string())),
+        ], &[])),
+        ("GraphQlConfig", object(vec![
+            ("paths", array(string())),
+            ("max_body_bytes", unsigned()),
+            ("max_operation_labels", unsigned()),
+        ], &[])),
+        ("GraphQlOperationMatchConfig", object(vec![
+            ("operation_names", array(string())),
+            ("operation_types", array(string())),
+        ], &[])),
+        ("TenantConfig", object(vec![
+            ("id", string()),
+            ("routes", array(string())),
+            ("rate_limits", array(reference("RateLimitRuleConfig"))),
+            ("api_keys", array(string())),
+            ("quota", nullable(reference("RateLimitWindowConfig"))),
+            ("admin_token", nullable(string())),
+        ], &["id", "routes"])),
+        ("DnsConfig", object(vec![
+            ("nameservers", array(string())),
+            ("min_ttl_secs", unsigned()),
+            ("max_ttl_secs", unsigned()),
+            ("fallback_ttl_secs", unsigned()),
+            ("timeout_secs", unsigned()),
+        ], &[])),
+        ("SecretsConfig", object(vec![
+            ("refresh_secs", unsigned()),
+            ("exec_timeout_secs", unsigned()),
+            ("vault", nullable(reference("VaultConfig"))),
+        ], &[])),
+        ("VaultConfig", object(vec![
+            ("address", string()),
+            ("token", string()),
+            ("namespace", nullable(string())),
+            ("timeout_secs", unsigned()),
+        ], &["address", "token"])),
+        ("ConfigReloadConfig", object(vec![
+            ("watch", boolean()),
+            ("watch_interval_secs", unsigned()),
+        ], &[])),
+        ("PercentDecoding", one_of_strings(&["none", "unreserved"])),
+        ("EncodedSlashes", one_of_strings(&["keep", "decode", "reject"])),
+        ("PathNormalizationConfig", object(vec![
+            ("enabled", boolean()),
+            ("percent_decoding", reference("PercentDecoding")),
+            ("encoded_slashes", reference("EncodedSlashes")),
+            ("collapse_slashes", boolean()),
+            ("resolve_dot_segments", boolean()),
+        ], &[])),
+        ("AccessLogFormat", one_of_strings(&["text", "common", "json", "template"])),
+        ("AccessLogFileConfig", object(vec![
+            ("path", string()),
+            ("max_size_mb", unsigned()),
+            ("max_files", unsigned()),
+        ], &["path"])),
+        ("AccessLogConfig", object(vec![
+            ("reverse", nullable(reference("AccessLogPolicyConfig"))),
+            ("forward", nullable(reference("AccessLogPolicyConfig"))),
+            ("format", reference("AccessLogFormat")),
+            ("template", nullable(string())),
+            ("file", nullable(reference("AccessLogFileConfig"))),
+        ], &[])),
+    ]
+}
+
+/// Route predicates are tagged by their `type` field.
+fn route_predicate() -> Value {
+    let variant = |name: &str, mut properties: Vec<(&str, Value)>, required: &[&str]| {
+        properties.insert(0, ("type", json!({ "const": name })));
+        let mut required = required.to_vec();
+        required.insert(0, "type");
+        object(properties, &required)
+    };
+    let matcher = |name: &str| {
+        variant(name, vec![
+            ("name", string()),
+            ("value", nullable(string())),
+            ("regex", nullable(string())),
+        ], &["name"])
+    };
+    json!({
+        "oneOf": [
+            variant("Path", vec![("patterns", array(string())), ("match_trailing_slash", boolean())], &["patterns"]),
+            variant("Host", vec![("patterns", array(string()))], &["patterns"]),
+            variant("Method", vec![("methods", array(string()))], &["methods"]),
+            matcher("Header"),
+            matcher("Query"),
+            matcher("Cookie"),
+            variant("After", vec![("instant", string())], &["instant"]),
+            variant("Before", vec![("instant", string())], &["instant"]),
+            variant("Between", vec![("start", string()), ("end", string())], &["start", "end"]),
+            variant("JsonBody", vec![
+                ("field", string()),
+                ("value", nullable(string())),
+                ("regex", nullable(string())),
+                ("max_body_bytes", unsigned()),
+            ], &["field"]),
+            variant("GraphQL", vec![
+                ("operation_names", array(string())),
+                ("operation_types", array(string())),
+            ], &[]),
+            variant("RemoteAddr", vec![("cidrs", array(string()))], &["cidrs"]),
+            variant("Weight", vec![("group", string()), ("weight", unsigned())], &["group", "weight"]),
+        ]
+    })
+}
+
+/// The schema of a complete configuration file.
+pub fn config_schema() -> Value {
+    let root = object(vec![
+        ("mode", reference("ProxyMode")),
+        ("listen_addr", string()),
+        ("forward_listen_addr", nullable(string())),
+        ("reverse_proxy_target", nullable(string())),
+        ("reverse_proxy_routes", array(reference("ReverseProxyRouteConfig"))),
+        ("max_connections", nullable(unsigned())),
+        ("connect_timeout_secs", nullable(unsigned())),
+        ("idle_timeout_secs", nullable(unsigned())),
+        ("max_connection_lifetime_secs", nullable(unsigned())),
+        ("timeout_secs", nullable(unsigned())),
+        ("worker_threads", nullable(unsigned())),
+        ("static_files", nullable(reference("StaticFileConfig"))),
+        ("private_key", nullable(string())),
+        ("certificate", nullable(string())),
+        ("connection_pool_enabled", nullable(boolean())),
+        ("max_header_size", nullable(unsigned())),
+        ("relay_proxies", nullable(array(reference("RelayProxyConfig")))),
+        ("relay_proxy_url", nullable(string())),
+        ("relay_proxy_username", nullable(string())),
+        ("relay_proxy_password", nullable(string())),
+        ("relay_proxy_domain_suffixes", nullable(array(string()))),
+        ("proxy_username", nullable(string())),
+        ("proxy_password", nullable(string())),
+        ("reverse_proxy_config", nullable(reference("ReverseProxyConfig"))),
+        ("logging", nullable(reference("LoggingConfig"))),
+        ("monitoring", reference("MonitoringConfig")),
+        ("websocket", nullable(reference("WebSocketConfig"))),
+        ("rate_limiting", nullable(reference("RateLimitingConfig"))),
+        ("forward_proxy_config", nullable(reference("ForwardProxyConfig"))),
+        ("api_keys", nullable(reference("ApiKeysConfig"))),
+        ("resource_monitor", nullable(reference("ResourceMonitorConfig"))),
+        ("preflight", nullable(reference("PreflightConfig"))),
+        ("certificate_expiry", nullable(reference("CertificateExpiryConfig"))),
+        ("tls_session", nullable(reference("TlsSessionConfig"))),
+        ("client_auth", nullable(reference("ClientAuthConfig"))),
+        ("http2", nullable(reference("Http2Config"))),
+        ("listener", nullable(reference("ListenerConfig"))),
+        ("request_limits", nullable(reference("RequestLimitsConfig"))),
+        ("route_fallback", nullable(reference("RouteFallbackConfig"))),
+        ("route_metadata", nullable(reference("RouteMetadataConfig"))),
+        ("access_log", nullable(reference("AccessLogConfig"))),
+        ("privacy", nullable(reference("PrivacyConfig"))),
+        ("tarpit", nullable(reference("TarpitConfig"))),
+        ("auto_ban", nullable(reference("AutoBanConfig"))),
+        ("cluster", nullable(reference("ClusterConfig"))),
+        ("metrics_push", nullable(reference("MetricsPushConfig"))),
+        ("admin_api", nullable(reference("AdminApiConfig"))),
+        ("notifications", nullable(reference("NotificationsConfig"))),
+        ("dns", nullable(reference("DnsConfig"))),
+        ("trusted_proxies", nullable(reference("TrustedProxiesConfig"))),
+        ("graphql", nullable(reference("GraphQlConfig"))),
+        ("tenants", array(reference("TenantConfig"))),
+        ("secrets", nullable(reference("SecretsConfig"))),
+        ("config_reload", nullable(reference("ConfigReloadConfig"))),
+        ("path_normalization", nullable(reference("PathNormalizationConfig"))),
+    ], &["mode", "listen_addr"]);
+
+    let mut schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Bifrost Bridge configuration",
+    });
+    let defs: Map<String, Value> =
+        definitions().into_iter().map(|(name, def)| (name.to_string(), def)).collect();
+    if let (Value::Object(schema), Value::Object(root)) = (&mut schema, root) {
+        schema.extend(root);
+        schema.insert("$defs".to_string(), Value::Object(defs));
+    }
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::*;
+    use serde::de::{self, Deserialize, Deserializer, Visitor};
+    use std::cell::RefCell;
+
+    /// Error carrying nothing; the probe only needs to stop deserialization.
+    #[derive(Debug)]
+    struct Stop;
+
+    impl std::fmt::Display for Stop {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "probe finished")
+        }
+    }
+
+    impl std::error::Error for Stop {}
+
+    impl de::Error for Stop {
+        fn custom<T: std::fmt::Display>(_msg: T) -> Self {
+            Stop
+        }
+    }
+
+    /// Captures the field or variant names serde's derive passes to the deserializer.
+    struct NameProbe<'a>(&'a RefCell<Vec<&'static str>>);
+
+    impl<'de> Deserializer<'de> for NameProbe<'_> {
+        type Error = Stop;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Stop> {
+            Err(Stop)
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Stop> {
+            self.0.borrow_mut().extend(fields);
+            Err(Stop)
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            variants: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Stop> {
+            self.0.borrow_mut().extend(variants);
+            Err(Stop)
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map identifier ignored_any
+        }
+    }
+
+    fn names_of<'de, T: Deserialize<'de>>() -> Vec<String> {
+        let names = RefCell::new(Vec::new());
+        let _ = T::deserialize(NameProbe(&names));
+        let mut names: Vec<String> = names.into_inner().into_iter().map(str::to_string).collect();
+        names.sort();
+        names
+    }
+
+    fn schema_names(def: &Value) -> Vec<String> {
+        let mut names: Vec<String> = match (def.get("properties"), def.get("enum")) {
+            (Some(Value::Object(properties)), _) => properties.keys().cloned().collect(),
+            (_, Some(Value::Array(values))) => values.iter().filter_map(|v| v.as_str().map(str::to_string)).collect(),
+            _ => Vec::new(),
+        };
+        names.sort();
+        names
+    }
+
+    fn collect_refs(value: &Value, refs: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(target)) = map.get("$ref") {
+                    refs.push(target.trim_start_matches("#/$defs/").to_string());
+                }
+                map.values().for_each(|v| collect_refs(v, refs));
+            }
+            Value::Array(items) => items.iter().for_each(|v| collect_refs(v, refs)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn schema_matches_the_config_types() {
+        let schema = config_schema();
+        let defs = schema["$defs"].as_object().unwrap();
+
+        macro_rules! check {
+            ($($ty:ident),* $(,)?) => {{
+                let mut checked = vec!["RoutePredicateConfig".to_string()];
+                $(
+                    let def = defs.get(stringify!($ty)).expect(concat!("schema has no ", stringify!($ty)));
+                    assert_eq!(schema_names(def), names_of::<$ty>(), "schema of {}", stringify!($ty));
+                    checked.push(stringify!($ty).to_string());
+                )*
+                checked
+            }};
+        }
+        let mut checked = check!(
+            ProxyMode, LogLevel, LogFormat, LogOutputType, LoadBalancingPolicy, StickyMode,
+            HmacAlgorithm, SignatureEncoding, BodyMode, WebSocketExtensionsMode, HookFailurePolicy,
+            RelayAuthScheme, EarlyDataPolicy, ClientAuthMode, LogTarget, LoggingConfig, SecurityLogConfig, MonitoringConfig,
+            RateLimitingConfig, RateLimitWindowConfig, RateLimitRuleConfig, RateLimitClientMatchConfig,
+            WebSocketConfig,
+            HealthCheckConfig, TcpKeepaliveConfig, ReverseProxyConfig, HeaderCase, PrewarmConfig, ReverseProxyTargetConfig,
+            OutlierDetectionConfig, DynamicWeightsConfig, SlowStartConfig, LoadBalancingConfig, StickyConfig, HeaderOverrideConfig,
+            RetryPolicyConfig, HmacVerificationConfig, FanOutConfig, AggregateBranchConfig,
+            AggregateConfig, ReplayMode, MethodOverride, ReplayConfig, StaticResponseConfig,
+            RouteLimitsConfig, UpstreamTlsConfig, ReverseProxyRouteConfig, StaticMount, EtagMode,
+            StaticFileConfig, TunnelDomainLimitConfig, TunnelLimitsConfig, FtpGatewayConfig,
+            ContentHookConfig, ScanContentTypeRule, DownloadScanConfig, ConnectPortOverrideConfig,
+            ConnectPortsConfig, ConnectRequestLimitsConfig, DigestAuthConfig, AuthLockoutConfig,
+            NegotiateAuthConfig, ByteQuotaConfig, ForwardProxyConfig, RelayProxyConfig, ApiKeyEntryConfig,
+            ApiKeyTierConfig, ApiKeysConfig, ResourceMonitorConfig, PreflightConfig,
+            CertificateExpiryConfig, TlsSessionConfig, ClientAuthConfig, Http2Config, RequestLimitsConfig, ListenerConfig, ProtocolMuxConfig, SniRouteConfig, RouteFallbackConfig,
+            RouteMetadataConfig, AccessLogPolicyConfig, AccessLogFormat, AccessLogFileConfig, AccessLogConfig,
+            ClientIpMode, PrivacyConfig, TarpitAction, TarpitConfig,
+            AutoBanThresholds, AutoBanConfig, ClusterConfig, MetricsPushFormat, MetricsPushConfig, AdminApiConfig,
+            WebhookFormat, WebhookConfig, NotificationsConfig, DnsConfig,
+            TrustedProxiesConfig, GraphQlConfig, GraphQlOperationMatchConfig, TenantConfig,
+            SecretsConfig, VaultConfig, ConfigReloadConfig, PercentDecoding, EncodedSlashes,
+            PathNormalizationConfig,
+        );
+        assert_eq!(schema_names(&schema), names_of::<Config>(), "schema of Config");
+
+        let mut defined: Vec<String> = defs.keys().cloned().collect();
+        defined.sort();
+        checked.sort();
+        assert_eq!(defined, checked, "every definition is compared with its type");
+
+        let mut refs = Vec::new();
+        collect_refs(&schema, &mut refs);
+        for target in refs {
+            assert!(defs.contains_key(&target), "dangling reference to {}", target);
+        }
+    }
+}
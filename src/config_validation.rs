@@ -3,7 +3,8 @@
 //! This module provides validation for all configuration aspects of the worker
 //! separation architecture, ensuring safe and optimal operation.
 
-use crate::common::{ProxyType, WorkerResourceLimits};
+use crate::common::{ProxyType, WorkerResourceLimits, parse_host_port, split_host_port};
+use crate::config::{BodyMode, ReverseProxyRouteConfig};
 use std::collections::HashMap;
 
 /// Comprehensive validation result
@@ -332,22 +333,16 @@ impl WorkerSeparationValidator {
 
     /// Extract port from address string
     fn extract_port(addr: &str) -> Result<u16, String> {
-        let parts: Vec<&str> = addr.split(':').collect();
-        if parts.len() != 2 {
-            return Err("Invalid address format".to_string());
-        }
-
-        parts[1].parse::<u16>()
-            .map_err(|_| "Invalid port number".to_string())
+        parse_host_port(addr)
+            .map(|(_, port)| port)
+            .ok_or_else(|| "Invalid address format".to_string())
     }
 
-    /// Extract host from address string
+    /// Extract host from address string, without the brackets of an IPv6 literal
     fn extract_host(addr: &str) -> Option<String> {
-        let parts: Vec<&str> = addr.split(':').collect();
-        if parts.len() >= 2 {
-            Some(parts[0].to_string())
-        } else {
-            None
+        match split_host_port(addr)? {
+            (host, Some(_)) => Some(host.to_string()),
+            _ => None,
         }
     }
 
@@ -357,19 +352,16 @@ impl WorkerSeparationValidator {
             return Err("Address cannot be empty".to_string());
         }
 
-        let parts: Vec<&str> = addr.split(':').collect();
-        if parts.len() != 2 {
-            return Err("Address must be in format 'host:port'".to_string());
-        }
+        let Some((host, Some(port_str))) = split_host_port(addr) else {
+            return Err("Address must be in format 'host:port' or '[ipv6]:port'".to_string());
+        };
 
         // Validate host part
-        let host = parts[0];
         if host.is_empty() {
             return Err("Host cannot be empty".to_string());
         }
 
         // Validate port part
-        let port_str = parts[1];
         let port = port_str.parse::<u16>()
             .map_err(|_| "Invalid port number".to_string())?;
 
@@ -381,6 +373,46 @@ impl WorkerSeparationValidator {
     }
 }
 
+/// Validate per-route timeout, body size and buffering overrides
+pub fn validate_route_limits(routes: &[ReverseProxyRouteConfig]) -> ValidationResult {
+    let mut result = ValidationResult::success();
+    for route in routes {
+        let Some(limits) = &route.limits else {
+            continue;
+        };
+        for (field, value) in [
+            ("connect_timeout_secs", limits.connect_timeout_secs),
+            ("response_timeout_secs", limits.response_timeout_secs),
+            ("idle_timeout_secs", limits.idle_timeout_secs),
+        ] {
+            if value == Some(0) {
+                result.add_error(format!("Route {}: limits.{} must be greater than 0", route.id, field));
+            }
+        }
+        for (field, value) in [("max_body_bytes", limits.max_body_bytes), ("max_buffer_bytes", limits.max_buffer_bytes)] {
+            if value == Some(0) {
+                result.add_error(format!("Route {}: limits.{} must be greater than 0", route.id, field));
+            }
+        }
+        if let (Some(connect), Some(response)) = (limits.connect_timeout_secs, limits.response_timeout_secs)
+            && response <= connect
+        {
+            result.add_warning(format!(
+                "Route {}: response_timeout_secs ({}) does not leave time beyond connect_timeout_secs ({})",
+                route.id, response, connect
+            ));
+        }
+        if limits.request_body == BodyMode::Buffer && limits.max_body_bytes.is_none() {
+            result.add_warning(format!(
+                "Route {}: buffered request bodies without max_body_bytes are held in memory at any size",
+                route.id
+            ));
+            result.add_suggestion(format!("Set limits.max_body_bytes on route {}", route.id));
+        }
+    }
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -418,4 +450,36 @@ mod tests {
         let result = validator.validate();
         assert!(!result.is_valid);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_validation_ipv6_addresses() {
+        let validator = WorkerSeparationValidator::new()
+            .with_enabled_types(true, false, false, false)
+            .with_listen_address(ProxyType::ForwardProxy, "[::]:8080".to_string());
+        let result = validator.validate();
+        assert!(result.is_valid, "{:?}", result.errors);
+        assert!(result.warnings.iter().any(|w| w.contains("Binding to all interfaces")));
+
+        let validator = WorkerSeparationValidator::new()
+            .with_enabled_types(true, false, false, false)
+            .with_listen_address(ProxyType::ForwardProxy, "::1:8080".to_string());
+        assert!(!validator.validate().is_valid);
+    }
+
+    #[test]
+    fn test_route_limits_validation() {
+        let route: ReverseProxyRouteConfig = serde_json::from_value(serde_json::json!({
+            "id": "uploads",
+            "target": "http://localhost:3000",
+            "predicates": [{ "type": "Path", "patterns": ["/upload/**"] }],
+            "limits": { "connect_timeout_secs": 5, "response_timeout_secs": 0, "request_body": "buffer" }
+        }))
+        .unwrap();
+
+        let result = validate_route_limits(&[route]);
+        assert!(!result.is_valid);
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].contains("response_timeout_secs"));
+        assert_eq!(result.warnings.len(), 2);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::config::{ConnectPortOverrideConfig, ConnectPortsConfig};
+use crate::forward_proxy::ForwardProxy;
+
+/// Decides which destination ports CONNECT tunnels may reach.
+///
+/// Without a policy any port could be tunneled, which turns the proxy into an open TCP
+/// relay (e.g. for SMTP abuse), so only 443 is allowed unless configured otherwise.
+#[derive(Clone, Debug)]
+pub struct ConnectPortPolicy {
+    allowed_ports: Vec<u16>,
+    overrides: Vec<ConnectPortOverrideConfig>,
+}
+
+impl ConnectPortPolicy {
+    pub fn new(config: Option<ConnectPortsConfig>) -> Self {
+        let config = config.unwrap_or_default();
+        Self {
+            allowed_ports: config.allowed_ports,
+            overrides: config.overrides,
+        }
+    }
+
+    /// Returns `true` when `host:port` may be tunneled.
+    pub fn is_allowed(&self, host: &str, port: u16) -> bool {
+        if self.allowed_ports.contains(&port) {
+            return true;
+        }
+
+        let host = host.trim_end_matches('.').to_lowercase();
+        self.overrides.iter().any(|entry| {
+            entry.ports.contains(&port)
+                && ForwardProxy::matching_no_proxy_pattern(&host, &entry.domains).is_some()
+        })
+    }
+}
+
+impl Default for ConnectPortPolicy {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_https_only() {
+        let policy = ConnectPortPolicy::default();
+        assert!(policy.is_allowed("example.com", 443));
+        assert!(!policy.is_allowed("example.com", 25));
+        assert!(!policy.is_allowed("example.com", 22));
+    }
+
+    #[test]
+    fn overrides_open_ports_for_matching_domains_only() {
+        let policy = ConnectPortPolicy::new(Some(ConnectPortsConfig {
+            allowed_ports: vec![443, 8443],
+            overrides: vec![ConnectPortOverrideConfig {
+                domains: vec!["*.git.example.com".to_string()],
+                ports: vec![22],
+            }],
+        }));
+
+        assert!(policy.is_allowed("other.com", 8443));
+        assert!(policy.is_allowed("ssh.git.example.com", 22));
+        assert!(policy.is_allowed("SSH.git.example.com.", 22));
+        assert!(!policy.is_allowed("other.com", 22));
+        assert!(!policy.is_allowed("ssh.git.example.com", 25));
+    }
+}
@@ -0,0 +1,314 @@
+//! External content adaptation hook (HTTP callout).
+//!
+//! A lightweight alternative to ICAP: before a request is forwarded (and/or before a
+//! response is returned) the forward proxy POSTs the message body to an external
+//! DLP/AV service together with a few `X-Bifrost-*` metadata headers. The service
+//! answers `2xx` to allow the message or `403` to block it; anything else, including
+//! a timeout, is handled according to the configured failure policy.
+
+use crate::config::{ContentHookConfig, HookFailurePolicy};
+use crate::error::ProxyError;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{Method, Request, Response, StatusCode, Uri};
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use log::{debug, warn};
+use prometheus::{IntCounterVec, Opts, Registry};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Which side of the exchange is being inspected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPhase {
+    Request,
+    Response,
+}
+
+impl HookPhase {
+    fn as_str(&self) -> &'static str {
+        match self {
+            HookPhase::Request => "request",
+            HookPhase::Response => "response",
+        }
+    }
+}
+
+/// Metadata sent alongside the body to the callout service.
+pub struct HookContext<'a> {
+    pub method: &'a Method,
+    pub uri: &'a Uri,
+    pub client_ip: Option<&'a str>,
+    pub content_type: Option<&'a str>,
+    /// Upstream status code (response phase only)
+    pub status: Option<StatusCode>,
+}
+
+/// Final decision for a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookVerdict {
+    Allow,
+    /// Blocked by the service (or by a fail-closed policy); carries the page to return
+    Block { status: StatusCode, body: Bytes },
+}
+
+pub struct ContentHook {
+    config: ContentHookConfig,
+    endpoint: Uri,
+    client: Client<HttpConnector, Full<Bytes>>,
+}
+
+impl ContentHook {
+    pub fn new(config: ContentHookConfig) -> Result<Self, ProxyError> {
+        let endpoint: Uri = config.url.parse()
+            .map_err(|e| ProxyError::Config(format!("Invalid content hook URL '{}': {}", config.url, e)))?;
+        if endpoint.scheme_str() != Some("http") {
+            return Err(ProxyError::Config(
+                "Content hook URL must use http://".to_string(),
+            ));
+        }
+
+        let mut connector = HttpConnector::new();
+        connector.set_connect_timeout(Some(Duration::from_millis(config.timeout_ms)));
+        connector.set_nodelay(true);
+        let client = Client::builder(TokioExecutor::new()).build(connector);
+
+        Ok(Self { config, endpoint, client })
+    }
+
+    pub fn inspects(&self, phase: HookPhase) -> bool {
+        match phase {
+            HookPhase::Request => self.config.inspect_requests,
+            HookPhase::Response => self.config.inspect_responses,
+        }
+    }
+
+    /// Largest body sent to the callout; bigger request bodies are refused with `413`.
+    pub fn max_body_bytes(&self) -> usize {
+        self.config.max_body_bytes
+    }
+
+    /// Sends `body` to the callout service and returns the verdict.
+    pub async fn inspect(&self, phase: HookPhase, ctx: &HookContext<'_>, body: &Bytes) -> HookVerdict {
+        let outcome = tokio::time::timeout(
+            Duration::from_millis(self.config.timeout_ms),
+            self.call(phase, ctx, body),
+        ).await;
+
+        match outcome {
+            Ok(Ok(verdict)) => {
+                let label = if verdict == HookVerdict::Allow { "allowed" } else { "blocked" };
+                hook_telemetry().record(phase, label);
+                verdict
+            }
+            Ok(Err(reason)) => {
+                warn!("Content hook {} check for {} failed: {}", phase.as_str(), ctx.uri, reason);
+                self.apply_failure_policy(phase)
+            }
+            Err(_) => {
+                warn!(
+                    "Content hook {} check for {} timed out after {}ms",
+                    phase.as_str(), ctx.uri, self.config.timeout_ms
+                );
+                self.apply_failure_policy(phase)
+            }
+        }
+    }
+
+    fn apply_failure_policy(&self, phase: HookPhase) -> HookVerdict {
+        match self.config.on_failure {
+            HookFailurePolicy::Bypass => {
+                hook_telemetry().record(phase, "bypassed");
+                HookVerdict::Allow
+            }
+            HookFailurePolicy::Block => {
+                hook_telemetry().record(phase, "failed_closed");
+                HookVerdict::Block {
+                    status: StatusCode::SERVICE_UNAVAILABLE,
+                    body: Bytes::from_static(b"Content inspection service unavailable"),
+                }
+            }
+        }
+    }
+
+    async fn call(&self, phase: HookPhase, ctx: &HookContext<'_>, body: &Bytes) -> Result<HookVerdict, String> {
+        let truncated = body.len() > self.config.max_body_bytes;
+        let payload = if truncated {
+            body.slice(..self.config.max_body_bytes)
+        } else {
+            body.clone()
+        };
+
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri(self.endpoint.clone())
+            .header("X-Bifrost-Phase", phase.as_str())
+            .header("X-Bifrost-Method", ctx.method.as_str())
+            .header("X-Bifrost-Url", ctx.uri.to_string())
+            .header("X-Bifrost-Body-Truncated", if truncated { "true" } else { "false" })
+            .header("Content-Type", ctx.content_type.unwrap_or("application/octet-stream"));
+        if let Some(ip) = ctx.client_ip {
+            builder = builder.header("X-Bifrost-Client-Ip", ip);
+        }
+        if let Some(status) = ctx.status {
+            builder = builder.header("X-Bifrost-Status", status.as_u16().to_string());
+        }
+
+        let request = builder
+            .body(Full::new(payload))
+            .map_err(|e| e.to_string())?;
+        let response = self.client.request(request).await.map_err(|e| e.to_string())?;
+        let status = response.status();
+
+        if status.is_success() {
+            debug!("Content hook allowed {} {} ({})", phase.as_str(), ctx.uri, status);
+            return Ok(HookVerdict::Allow);
+        }
+
+        if status == StatusCode::FORBIDDEN {
+            let page = response.into_body().collect().await
+                .map(|collected| collected.to_bytes())
+                .unwrap_or_default();
+            let page = if page.is_empty() {
+                Bytes::from_static(b"Blocked by content inspection policy")
+            } else {
+                page
+            };
+            return Ok(HookVerdict::Block { status: StatusCode::FORBIDDEN, body: page });
+        }
+
+        Err(format!("unexpected status {} from content hook", status))
+    }
+}
+
+/// Builds the response returned to the client for a blocked message.
+pub fn blocked_response(status: StatusCode, body: Bytes) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .header("X-Bifrost-Blocked", "content-hook")
+        .body(Full::new(body))
+        .unwrap()
+}
+
+struct HookTelemetry {
+    decisions: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl HookTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "content_hook_decisions_total",
+            "Content hook verdicts by phase and outcome (allowed, blocked, bypassed, failed_closed)",
+        ).namespace("bifrost");
+        Self {
+            decisions: IntCounterVec::new(opts, &["phase", "outcome"])
+                .expect("content_hook_decisions_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.decisions.clone())) {
+            warn!("Failed to register content_hook_decisions_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    fn record(&self, phase: HookPhase, outcome: &str) {
+        self.decisions.with_label_values(&[phase.as_str(), outcome]).inc();
+    }
+}
+
+fn hook_telemetry() -> &'static HookTelemetry {
+    static TELEMETRY: OnceLock<HookTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(HookTelemetry::new)
+}
+
+/// Registers the content hook counters with the shared Prometheus registry.
+pub fn register_content_hook_metrics(registry: &Registry) {
+    hook_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::server::conn::http1::Builder as ServerBuilder;
+    use hyper::service::service_fn;
+    use hyper_util::rt::TokioIo;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+
+    async fn spawn_hook(status: StatusCode, delay: Duration) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<hyper::body::Incoming>| async move {
+                        let body = req.into_body().collect().await.unwrap().to_bytes();
+                        tokio::time::sleep(delay).await;
+                        let reply = if body.as_ref() == b"EICAR" { StatusCode::FORBIDDEN } else { status };
+                        Ok::<_, Infallible>(Response::builder().status(reply).body(Full::new(Bytes::from("nope"))).unwrap())
+                    });
+                    let _ = ServerBuilder::new().serve_connection(TokioIo::new(stream), service).await;
+                });
+            }
+        });
+        addr
+    }
+
+    fn hook(addr: SocketAddr, timeout_ms: u64, on_failure: HookFailurePolicy) -> ContentHook {
+        ContentHook::new(ContentHookConfig {
+            url: format!("http://{}/scan", addr),
+            timeout_ms,
+            on_failure,
+            ..ContentHookConfig::default()
+        }).unwrap()
+    }
+
+    fn ctx<'a>(method: &'a Method, uri: &'a Uri) -> HookContext<'a> {
+        HookContext { method, uri, client_ip: Some("10.0.0.1"), content_type: None, status: None }
+    }
+
+    #[tokio::test]
+    async fn allows_and_blocks_based_on_service_status() {
+        let addr = spawn_hook(StatusCode::NO_CONTENT, Duration::ZERO).await;
+        let hook = hook(addr, 1_000, HookFailurePolicy::Bypass);
+        let (method, uri) = (Method::POST, "http://example.com/upload".parse::<Uri>().unwrap());
+
+        assert_eq!(hook.inspect(HookPhase::Request, &ctx(&method, &uri), &Bytes::from("hello")).await, HookVerdict::Allow);
+        assert_eq!(
+            hook.inspect(HookPhase::Request, &ctx(&method, &uri), &Bytes::from("EICAR")).await,
+            HookVerdict::Block { status: StatusCode::FORBIDDEN, body: Bytes::from("nope") }
+        );
+    }
+
+    #[tokio::test]
+    async fn timeout_follows_failure_policy() {
+        let addr = spawn_hook(StatusCode::OK, Duration::from_millis(500)).await;
+        let (method, uri) = (Method::GET, "http://example.com/".parse::<Uri>().unwrap());
+
+        let bypass = hook(addr, 50, HookFailurePolicy::Bypass);
+        assert_eq!(bypass.inspect(HookPhase::Response, &ctx(&method, &uri), &Bytes::new()).await, HookVerdict::Allow);
+
+        let block = hook(addr, 50, HookFailurePolicy::Block);
+        assert!(matches!(
+            block.inspect(HookPhase::Response, &ctx(&method, &uri), &Bytes::new()).await,
+            HookVerdict::Block { status: StatusCode::SERVICE_UNAVAILABLE, .. }
+        ));
+    }
+
+    #[test]
+    fn rejects_non_http_endpoints() {
+        let config = ContentHookConfig { url: "https://scanner/".to_string(), ..ContentHookConfig::default() };
+        assert!(ContentHook::new(config).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! HTTP Digest proxy authentication (RFC 7616, `algorithm=SHA-256`, `qop=auth`).
+//!
+//! Nonces are stateless: each one carries its issue time and an HMAC over it, so any
+//! worker can validate them without shared storage. Nonce counts are not tracked, which
+//! means a captured response can be replayed until its nonce expires; keep
+//! `nonce_ttl_secs` short.
+
+use crate::common::{constant_time_eq, unix_now};
+use crate::config::DigestAuthConfig;
+use crate::error::ProxyError;
+use base64::{Engine as _, engine::general_purpose};
+use ring::{digest, hmac, rand};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Realm advertised in every `Proxy-Authenticate` challenge.
+pub const PROXY_AUTH_REALM: &str = "Proxy Server";
+
+/// Number of HMAC bytes kept in a nonce.
+const NONCE_TAG_LEN: usize = 16;
+
+pub struct DigestAuth {
+    nonce_ttl: Duration,
+    key: hmac::Key,
+    opaque: String,
+}
+
+impl DigestAuth {
+    pub fn new(config: &DigestAuthConfig) -> Self {
+        let rng = rand::SystemRandom::new();
+        let key = hmac::Key::generate(hmac::HMAC_SHA256, &rng)
+            .expect("system random generator unavailable");
+        let mut opaque = [0u8; 16];
+        rand::SecureRandom::fill(&rng, &mut opaque).expect("system random generator unavailable");
+        let opaque = opaque.iter().map(|byte| format!("{:02x}", byte)).collect();
+        Self {
+            nonce_ttl: Duration::from_secs(config.nonce_ttl_secs),
+            key,
+            opaque,
+        }
+    }
+
+    /// Value for a `Proxy-Authenticate` header carrying a fresh nonce.
+    pub fn challenge(&self) -> String {
+        format!(
+            "Digest realm=\"{}\", qop=\"auth\", algorithm=SHA-256, nonce=\"{}\", opaque=\"{}\"",
+            PROXY_AUTH_REALM,
+            self.issue_nonce(unix_now()),
+            self.opaque,
+        )
+    }
+
+    /// Verifies a `Digest ...` credential for `method` against the configured user.
+    pub fn verify(&self, method: &str, header: &str, username: &str, password: &str) -> Result<(), ProxyError> {
+        let params = header
+            .strip_prefix("Digest ")
+            .map(parse_digest_params)
+            .ok_or_else(|| ProxyError::Auth("Not a Digest credential".to_string()))?;
+        let param = |name: &str| {
+            params.get(name)
+                .map(String::as_str)
+                .ok_or_else(|| ProxyError::Auth(format!("Digest credential missing '{}'", name)))
+        };
+
+        if let Some(algorithm) = params.get("algorithm").filter(|algorithm| !algorithm.eq_ignore_ascii_case("SHA-256")) {
+            return Err(ProxyError::Auth(format!("Unsupported digest algorithm {}", algorithm)));
+        }
+        if param("realm")? != PROXY_AUTH_REALM {
+            return Err(ProxyError::Auth("Digest realm mismatch".to_string()));
+        }
+        let nonce = param("nonce")?;
+        self.check_nonce(nonce, unix_now())?;
+
+        let ha1 = sha256_hex(&format!("{}:{}:{}", username, PROXY_AUTH_REALM, password));
+        let ha2 = sha256_hex(&format!("{}:{}", method, param("uri")?));
+        let expected = match params.get("qop").map(String::as_str) {
+            Some("auth") => sha256_hex(&format!(
+                "{}:{}:{}:{}:auth:{}",
+                ha1, nonce, param("nc")?, param("cnonce")?, ha2
+            )),
+            Some(other) => return Err(ProxyError::Auth(format!("Unsupported digest qop {}", other))),
+            None => sha256_hex(&format!("{}:{}:{}", ha1, nonce, ha2)),
+        };
+
+        let user_matches = constant_time_eq(param("username")?.as_bytes(), username.as_bytes());
+        let response_matches = constant_time_eq(param("response")?.to_ascii_lowercase().as_bytes(), expected.as_bytes());
+        if user_matches && response_matches {
+            Ok(())
+        } else {
+            Err(ProxyError::Auth("Invalid username or password".to_string()))
+        }
+    }
+
+    fn issue_nonce(&self, issued_at: u64) -> String {
+        let timestamp = issued_at.to_be_bytes();
+        let tag = hmac::sign(&self.key, &timestamp);
+        let mut raw = timestamp.to_vec();
+        raw.extend_from_slice(&tag.as_ref()[..NONCE_TAG_LEN]);
+        general_purpose::URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    fn check_nonce(&self, nonce: &str, now: u64) -> Result<(), ProxyError> {
+        let raw = general_purpose::URL_SAFE_NO_PAD.decode(nonce)
+            .map_err(|_| ProxyError::Auth("Malformed digest nonce".to_string()))?;
+        if raw.len() != 8 + NONCE_TAG_LEN {
+            return Err(ProxyError::Auth("Malformed digest nonce".to_string()));
+        }
+
+        let (timestamp, tag) = raw.split_at(8);
+        let expected = hmac::sign(&self.key, timestamp);
+        if !constant_time_eq(&expected.as_ref()[..NONCE_TAG_LEN], tag) {
+            return Err(ProxyError::Auth("Unknown digest nonce".to_string()));
+        }
+
+        let issued_at = u64::from_be_bytes(timestamp.try_into().unwrap());
+        if now.saturating_sub(issued_at) > self.nonce_ttl.as_secs() {
+            return Err(ProxyError::Auth("Digest nonce expired".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Parses `key=value` / `key="quoted, value"` pairs of a Digest credential.
+fn parse_digest_params(input: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut rest = input.trim();
+
+    while !rest.is_empty() {
+        let Some(eq) = rest.find('=') else {
+            break;
+        };
+        let key = rest[..eq].trim().trim_start_matches(',').trim().to_ascii_lowercase();
+        rest = rest[eq + 1..].trim_start();
+
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((index, c)) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some((_, escaped)) = chars.next() {
+                            value.push(escaped);
+                        }
+                    }
+                    '"' => {
+                        end = index + 1;
+                        break;
+                    }
+                    _ => value.push(c),
+                }
+            }
+            rest = &quoted[end..];
+            value
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            let value = rest[..end].trim().to_string();
+            rest = &rest[end..];
+            value
+        };
+
+        params.insert(key, value);
+        rest = rest.trim_start().trim_start_matches(',').trim_start();
+    }
+
+    params
+}
+
+fn sha256_hex(input: &str) -> String {
+    digest::digest(&digest::SHA256, input.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client_response(auth: &DigestAuth, method: &str, uri: &str, password: &str) -> String {
+        let challenge = parse_digest_params(auth.challenge().strip_prefix("Digest ").unwrap());
+        let nonce = &challenge["nonce"];
+        let ha1 = sha256_hex(&format!("alice:{}:{}", PROXY_AUTH_REALM, password));
+        let ha2 = sha256_hex(&format!("{}:{}", method, uri));
+        let response = sha256_hex(&format!("{}:{}:00000001:c0ffee:auth:{}", ha1, nonce, ha2));
+        format!(
+            "Digest username=\"alice\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm=SHA-256, \
+             qop=auth, nc=00000001, cnonce=\"c0ffee\", response=\"{}\", opaque=\"{}\"",
+            PROXY_AUTH_REALM, nonce, uri, response, challenge["opaque"]
+        )
+    }
+
+    #[test]
+    fn accepts_valid_digest_and_rejects_wrong_password() {
+        let auth = DigestAuth::new(&DigestAuthConfig { enabled: true, ..DigestAuthConfig::default() });
+
+        let header = client_response(&auth, "CONNECT", "example.com:443", "s3cret");
+        assert!(auth.verify("CONNECT", &header, "alice", "s3cret").is_ok());
+        // The response is bound to the method
+        assert!(auth.verify("GET", &header, "alice", "s3cret").is_err());
+
+        let header = client_response(&auth, "GET", "http://example.com/", "wrong");
+        assert!(auth.verify("GET", &header, "alice", "s3cret").is_err());
+    }
+
+    #[test]
+    fn rejects_forged_and_expired_nonces() {
+        let auth = DigestAuth::new(&DigestAuthConfig { enabled: true, nonce_ttl_secs: 60 });
+        let other = DigestAuth::new(&DigestAuthConfig::default());
+
+        let nonce = auth.issue_nonce(1_000);
+        assert!(auth.check_nonce(&nonce, 1_030).is_ok());
+        assert!(auth.check_nonce(&nonce, 1_061).is_err());
+        assert!(other.check_nonce(&nonce, 1_030).is_err());
+        assert!(auth.check_nonce("not-a-nonce", 1_030).is_err());
+    }
+
+    #[test]
+    fn parses_quoted_parameters() {
+        let params = parse_digest_params(r#"username="a\"b", uri="/x,y", qop=auth, nc=00000001"#);
+        assert_eq!(params["username"], "a\"b");
+        assert_eq!(params["uri"], "/x,y");
+        assert_eq!(params["qop"], "auth");
+        assert_eq!(params["nc"], "00000001");
+    }
+}
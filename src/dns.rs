@@ -0,0 +1,527 @@
+//! Backend hostname resolution that follows DNS changes.
+//!
+//! Without a `dns` section, every new backend connection asks the system resolver, and pooled
+//! connections keep the address they were opened with for as long as they live. With it,
+//! backend hostnames are looked up by querying the nameservers directly, so the record TTLs are
+//! known. Answers are cached until their TTL runs out, each new connection starts at the next
+//! address of the record set, and a pooled connection to an address the name no longer
+//! resolves to is closed after the response it is serving. Names the nameservers do not answer
+//! (hosts file entries, names that need a search domain) fall back to the system resolver and
+//! are kept for `fallback_ttl_secs`.
+
+use crate::config::DnsConfig;
+use crate::error::ProxyError;
+use hyper::http::Extensions;
+use hyper_util::client::legacy::connect::dns::Name;
+use hyper_util::client::legacy::connect::{Connected, HttpInfo};
+use log::{debug, info, warn};
+use prometheus::{IntCounter, IntCounterVec, Opts, Registry};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tower_service::Service;
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+static CACHE: OnceLock<Arc<DnsCache>> = OnceLock::new();
+
+/// Sets up TTL-aware resolution for the backend clients built after this call.
+pub fn init(config: &DnsConfig) -> Result<(), ProxyError> {
+    let cache = DnsCache::new(config)?;
+    if cache.nameservers.is_empty() {
+        warn!("DNS: no nameservers configured or found in {}; using the system resolver", RESOLV_CONF);
+    } else {
+        info!("DNS: resolving backend hostnames through {:?}", cache.nameservers);
+    }
+    if CACHE.set(Arc::new(cache)).is_err() {
+        warn!("DNS already initialized; keeping the first configuration");
+    }
+    Ok(())
+}
+
+/// Whether `init` was called, i.e. whether pooled connections are checked against DNS.
+pub fn enabled() -> bool {
+    CACHE.get().is_some()
+}
+
+/// Closes a pooled connection after its current response when its address is no longer among
+/// the ones `host` resolves to.
+pub fn retire_if_moved(host: &str, connected: &Connected) {
+    let Some(cache) = CACHE.get() else {
+        return;
+    };
+    let mut extras = Extensions::new();
+    connected.get_extras(&mut extras);
+    let Some(info) = extras.get::<HttpInfo>() else {
+        return;
+    };
+    let remote = info.remote_addr();
+    if !cache.still_resolves(host, remote.ip()) {
+        debug!("DNS: {} no longer resolves to {}; closing the connection", host, remote);
+        connected.poison();
+        dns_telemetry().retired.inc();
+    }
+}
+
+/// Resolver for the backend connector: the shared cache when `init` was called, the system
+/// resolver otherwise.
+#[derive(Clone, Default)]
+pub struct DnsResolver {
+    cache: Option<Arc<DnsCache>>,
+}
+
+impl DnsResolver {
+    pub fn shared() -> Self {
+        Self { cache: CACHE.get().cloned() }
+    }
+}
+
+impl Service<Name> for DnsResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let cache = self.cache.clone();
+        Box::pin(async move {
+            let addrs = match cache {
+                Some(cache) => cache.resolve(name.as_str()).await?,
+                None => system_lookup(name.as_str()).await?,
+            };
+            // The connector fills in the port
+            Ok(addrs.into_iter().map(|ip| SocketAddr::new(ip, 0)).collect::<Vec<_>>().into_iter())
+        })
+    }
+}
+
+struct Entry {
+    /// Sorted, so a changed answer is told apart from a reordered one
+    addrs: Vec<IpAddr>,
+    expires: Instant,
+    /// Where the next connection starts in `addrs`
+    next: usize,
+    refreshing: bool,
+}
+
+impl Entry {
+    fn rotated(&mut self) -> Vec<IpAddr> {
+        let start = self.next % self.addrs.len().max(1);
+        self.next = self.next.wrapping_add(1);
+        let mut addrs = self.addrs.clone();
+        addrs.rotate_left(start);
+        addrs
+    }
+}
+
+/// A record set as answered by a nameserver; `ttl` is the lowest TTL along the answer chain.
+#[derive(Debug, Default, PartialEq)]
+struct Answer {
+    addrs: Vec<IpAddr>,
+    ttl: Option<u32>,
+}
+
+pub struct DnsCache {
+    nameservers: Vec<SocketAddr>,
+    min_ttl: Duration,
+    max_ttl: Duration,
+    fallback_ttl: Duration,
+    timeout: Duration,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl DnsCache {
+    fn new(config: &DnsConfig) -> Result<Self, ProxyError> {
+        if config.timeout_secs == 0 {
+            return Err(ProxyError::Config("dns.timeout_secs must be greater than 0".to_string()));
+        }
+        if config.min_ttl_secs > config.max_ttl_secs {
+            return Err(ProxyError::Config("dns.min_ttl_secs must not exceed dns.max_ttl_secs".to_string()));
+        }
+        let nameservers = if config.nameservers.is_empty() {
+            std::fs::read_to_string(RESOLV_CONF).map(|conf| resolv_conf_nameservers(&conf)).unwrap_or_default()
+        } else {
+            config
+                .nameservers
+                .iter()
+                .map(|server| {
+                    server
+                        .parse::<SocketAddr>()
+                        .or_else(|_| server.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+                        .map_err(|_| ProxyError::Config(format!("dns: invalid nameserver '{}'", server)))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        Ok(Self {
+            nameservers,
+            min_ttl: Duration::from_secs(config.min_ttl_secs),
+            max_ttl: Duration::from_secs(config.max_ttl_secs),
+            fallback_ttl: Duration::from_secs(config.fallback_ttl_secs),
+            timeout: Duration::from_secs(config.timeout_secs),
+            entries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn resolve(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        if let Some(addrs) = self.fresh(host) {
+            return Ok(addrs);
+        }
+        self.refresh(host).await;
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get_mut(host) {
+            Some(entry) => Ok(entry.rotated()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, format!("no addresses found for {}", host))),
+        }
+    }
+
+    fn fresh(&self, host: &str) -> Option<Vec<IpAddr>> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(host)?;
+        (entry.expires > Instant::now()).then(|| entry.rotated())
+    }
+
+    /// Whether `host` still resolves to `ip`. An expired answer is refreshed in the
+    /// background, so names whose connections are all pooled are re-resolved too.
+    fn still_resolves(self: &Arc<Self>, host: &str, ip: IpAddr) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(entry) = entries.get_mut(host) else {
+            return true;
+        };
+        if entry.expires <= Instant::now() && !entry.refreshing {
+            entry.refreshing = true;
+            let cache = self.clone();
+            let host = host.to_string();
+            tokio::spawn(async move { cache.refresh(&host).await });
+        }
+        entry.addrs.contains(&ip)
+    }
+
+    async fn refresh(&self, host: &str) {
+        let looked_up = self.lookup(host).await;
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        match looked_up {
+            Ok((mut addrs, ttl)) => {
+                addrs.sort();
+                addrs.dedup();
+                let entry = entries.entry(host.to_string()).or_insert_with(|| Entry {
+                    addrs: Vec::new(),
+                    expires: now,
+                    next: 0,
+                    refreshing: false,
+                });
+                if entry.addrs != addrs {
+                    if !entry.addrs.is_empty() {
+                        info!("DNS: {} now resolves to {:?} (was {:?})", host, addrs, entry.addrs);
+                    }
+                    entry.addrs = addrs;
+                }
+                entry.expires = now + ttl;
+                entry.refreshing = false;
+            }
+            Err(err) => {
+                dns_telemetry().lookups.with_label_values(&["failed"]).inc();
+                // Keep serving the last answer and try again soon
+                match entries.get_mut(host) {
+                    Some(entry) => {
+                        warn!("DNS lookup of {} failed ({}); keeping {:?}", host, err, entry.addrs);
+                        entry.expires = now + self.min_ttl.max(Duration::from_secs(1));
+                        entry.refreshing = false;
+                    }
+                    None => warn!("DNS lookup of {} failed: {}", host, err),
+                }
+            }
+        }
+    }
+
+    async fn lookup(&self, host: &str) -> io::Result<(Vec<IpAddr>, Duration)> {
+        if let Some(answer) = self.query_nameservers(host).await
+            && !answer.addrs.is_empty()
+        {
+            dns_telemetry().lookups.with_label_values(&["dns"]).inc();
+            let ttl = Duration::from_secs(answer.ttl.unwrap_or(0).into());
+            return Ok((answer.addrs, ttl.clamp(self.min_ttl, self.max_ttl)));
+        }
+        let addrs = system_lookup(host).await?;
+        dns_telemetry().lookups.with_label_values(&["system"]).inc();
+        Ok((addrs, self.fallback_ttl))
+    }
+
+    /// Asks the nameservers in order for the A and AAAA records until one answers.
+    async fn query_nameservers(&self, host: &str) -> Option<Answer> {
+        for server in &self.nameservers {
+            let (v4, v6) = tokio::join!(
+                self.query(*server, host, TYPE_A),
+                self.query(*server, host, TYPE_AAAA)
+            );
+            match (v4, v6) {
+                (Err(err), Err(_)) => debug!("DNS: {} did not answer for {}: {}", server, host, err),
+                (v4, v6) => {
+                    let mut answer = Answer::default();
+                    for part in [v4, v6].into_iter().flatten() {
+                        answer.addrs.extend(part.addrs);
+                        answer.ttl = match (answer.ttl, part.ttl) {
+                            (Some(a), Some(b)) => Some(a.min(b)),
+                            (a, b) => a.or(b),
+                        };
+                    }
+                    return Some(answer);
+                }
+            }
+        }
+        None
+    }
+
+    async fn query(&self, server: SocketAddr, host: &str, qtype: u16) -> Result<Answer, String> {
+        let id: u16 = rand::random();
+        let query = encode_query(id, host, qtype)?;
+        let exchange = async {
+            let local: SocketAddr = if server.is_ipv4() {
+                (Ipv4Addr::UNSPECIFIED, 0).into()
+            } else {
+                (Ipv6Addr::UNSPECIFIED, 0).into()
+            };
+            let socket = UdpSocket::bind(local).await.map_err(|e| e.to_string())?;
+            socket.connect(server).await.map_err(|e| e.to_string())?;
+            socket.send(&query).await.map_err(|e| e.to_string())?;
+            let mut buf = [0u8; 4096];
+            loop {
+                let len = socket.recv(&mut buf).await.map_err(|e| e.to_string())?;
+                // Stray datagrams with another id are skipped
+                if let Some(answer) = decode_response(&buf[..len], id, qtype)? {
+                    return Ok(answer);
+                }
+            }
+        };
+        tokio::time::timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| "timed out".to_string())?
+    }
+}
+
+async fn system_lookup(host: &str) -> io::Result<Vec<IpAddr>> {
+    Ok(tokio::net::lookup_host((host, 0)).await?.map(|addr| addr.ip()).collect())
+}
+
+fn resolv_conf_nameservers(conf: &str) -> Vec<SocketAddr> {
+    conf.lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|rest| rest.trim().parse::<IpAddr>().ok())
+        .map(|ip| SocketAddr::new(ip, 53))
+        .collect()
+}
+
+fn encode_query(id: u16, host: &str, qtype: u16) -> Result<Vec<u8>, String> {
+    let mut query = Vec::with_capacity(18 + host.len());
+    query.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired, one question
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("invalid hostname '{}'", host));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&qtype.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+/// Reads the `qtype` records out of a response; `None` when the response is to another query.
+fn decode_response(msg: &[u8], id: u16, qtype: u16) -> Result<Option<Answer>, String> {
+    let u16_at = |pos: usize| -> Result<u16, String> {
+        msg.get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(|| "truncated response".to_string())
+    };
+    if msg.len() < 12 {
+        return Err("truncated response".to_string());
+    }
+    if u16_at(0)? != id || msg[2] & 0x80 == 0 {
+        return Ok(None);
+    }
+    match msg[3] & 0x0f {
+        0 => {}
+        // NXDOMAIN: the name has no records at all
+        3 => return Ok(Some(Answer::default())),
+        rcode => return Err(format!("server answered with rcode {}", rcode)),
+    }
+    let questions = u16_at(4)?;
+    let answers = u16_at(6)?;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(msg, pos)? + 4;
+    }
+    let mut answer = Answer::default();
+    for _ in 0..answers {
+        pos = skip_name(msg, pos)?;
+        let rtype = u16_at(pos)?;
+        let class = u16_at(pos + 2)?;
+        let ttl = ((u16_at(pos + 4)? as u32) << 16) | u16_at(pos + 6)? as u32;
+        let len = u16_at(pos + 8)? as usize;
+        pos += 10;
+        let data = msg.get(pos..pos + len).ok_or_else(|| "truncated record".to_string())?;
+        pos += len;
+        if class != CLASS_IN {
+            continue;
+        }
+        let addr = match (rtype, data.len()) {
+            (TYPE_A, 4) if rtype == qtype => Some(IpAddr::from(<[u8; 4]>::try_from(data).unwrap())),
+            (TYPE_AAAA, 16) if rtype == qtype => Some(IpAddr::from(<[u8; 16]>::try_from(data).unwrap())),
+            _ => None,
+        };
+        // CNAMEs on the way to the addresses count towards the TTL too
+        if addr.is_some() || rtype == 5 {
+            answer.ttl = Some(answer.ttl.map_or(ttl, |current| current.min(ttl)));
+        }
+        answer.addrs.extend(addr);
+    }
+    Ok(Some(answer))
+}
+
+fn skip_name(msg: &[u8], mut pos: usize) -> Result<usize, String> {
+    loop {
+        let len = *msg.get(pos).ok_or_else(|| "truncated name".to_string())? as usize;
+        match len {
+            0 => return Ok(pos + 1),
+            // A compression pointer ends the name
+            l if l & 0xc0 == 0xc0 => return Ok(pos + 2),
+            l => pos += 1 + l,
+        }
+    }
+}
+
+struct DnsTelemetry {
+    lookups: IntCounterVec,
+    retired: IntCounter,
+    registered: AtomicBool,
+}
+
+impl DnsTelemetry {
+    fn new() -> Self {
+        let lookups = Opts::new(
+            "dns_lookups_total",
+            "Backend hostname lookups by source (dns, system) or failed",
+        ).namespace("bifrost");
+        let retired = Opts::new(
+            "dns_retired_connections_total",
+            "Pooled backend connections closed because their address left the DNS answer",
+        ).namespace("bifrost");
+        Self {
+            lookups: IntCounterVec::new(lookups, &["outcome"]).expect("dns_lookups_total metric"),
+            retired: IntCounter::with_opts(retired).expect("dns_retired_connections_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        let collectors: [Box<dyn prometheus::core::Collector>; 2] =
+            [Box::new(self.lookups.clone()), Box::new(self.retired.clone())];
+        for collector in collectors {
+            if let Err(err) = registry.register(collector) {
+                warn!("Failed to register DNS metrics: {}", err);
+                return;
+            }
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn dns_telemetry() -> &'static DnsTelemetry {
+    static TELEMETRY: OnceLock<DnsTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(DnsTelemetry::new)
+}
+
+/// Registers the DNS counters with the shared Prometheus registry.
+pub fn register_dns_metrics(registry: &Registry) {
+    dns_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers A queries for any name with a CNAME and the addresses in `records`.
+    async fn nameserver(records: Arc<Mutex<Vec<Ipv4Addr>>>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+                let query = &buf[..len];
+                let question_end = skip_name(query, 12).unwrap() + 4;
+                let qtype = u16::from_be_bytes([query[question_end - 4], query[question_end - 3]]);
+                let records = if qtype == TYPE_A { records.lock().unwrap().clone() } else { Vec::new() };
+
+                let mut response = query[..question_end].to_vec();
+                response[2] = 0x81;
+                response[3] = 0x80;
+                response[7] = 1 + records.len() as u8;
+                // CNAME pointing to a compressed copy of the question name, with a longer TTL
+                response.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2, 0xc0, 12]);
+                for ip in records {
+                    response.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 1, 0, 4]);
+                    response.extend_from_slice(&ip.octets());
+                }
+                socket.send_to(&response, peer).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn follows_record_changes_and_rotates_addresses() {
+        let records = Arc::new(Mutex::new(vec![Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 0, 1)]));
+        let server = nameserver(records.clone()).await;
+        let cache = Arc::new(
+            DnsCache::new(&DnsConfig {
+                nameservers: vec![server.to_string()],
+                min_ttl_secs: 0,
+                ..DnsConfig::default()
+            })
+            .unwrap(),
+        );
+        let old: IpAddr = Ipv4Addr::new(10, 0, 0, 1).into();
+        let new: IpAddr = Ipv4Addr::new(10, 0, 0, 3).into();
+
+        let first = cache.resolve("api.test").await.unwrap();
+        let second = cache.resolve("api.test").await.unwrap();
+        assert_eq!(first, vec![old, Ipv4Addr::new(10, 0, 0, 2).into()]);
+        assert_eq!(second, vec![first[1], first[0]], "each connection starts at the next address");
+        assert!(cache.still_resolves("api.test", old));
+        assert!(cache.still_resolves("unknown.test", new));
+
+        // The 1s TTL of the A records wins over the CNAME's
+        *records.lock().unwrap() = vec![Ipv4Addr::new(10, 0, 0, 3)];
+        assert_eq!(cache.resolve("api.test").await.unwrap().len(), 2);
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(cache.resolve("api.test").await.unwrap(), vec![new]);
+        assert!(!cache.still_resolves("api.test", old));
+
+        assert_eq!(
+            resolv_conf_nameservers("search corp\nnameserver 10.0.0.53\nnameserver fe80::1%eth0\n"),
+            vec![SocketAddr::from(([10, 0, 0, 53], 53))]
+        );
+        assert!(encode_query(1, "a..b", TYPE_A).is_err());
+    }
+}
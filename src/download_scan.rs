@@ -0,0 +1,371 @@
+//! Malware scanning for forward-proxy downloads.
+//!
+//! Buffered response bodies that fall inside the configured size window are handed to a
+//! [`MalwareScanner`] before they are returned to the client. The bundled scanner speaks
+//! the clamd `INSTREAM` protocol over TCP; other engines can be plugged in through
+//! [`DownloadScanner::with_scanner`].
+
+use crate::common::escape_html;
+use crate::config::{DownloadScanConfig, HookFailurePolicy, ScanContentTypeRule};
+use crate::error::ProxyError;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Response, StatusCode};
+use log::{info, warn};
+use prometheus::{Histogram, HistogramOpts, IntCounterVec, Opts, Registry};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Result reported by a scanning engine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanOutcome {
+    Clean,
+    /// Carries the signature name reported by the engine
+    Infected(String),
+}
+
+/// Pluggable scanning engine.
+pub trait MalwareScanner: Send + Sync {
+    /// Short engine name used in logs
+    fn name(&self) -> &str;
+
+    fn scan<'a>(&'a self, body: &'a Bytes) -> Pin<Box<dyn Future<Output = Result<ScanOutcome, ProxyError>> + Send + 'a>>;
+}
+
+/// clamd client using the `zINSTREAM` command.
+pub struct ClamdScanner {
+    addr: String,
+}
+
+/// clamd rejects chunks larger than its StreamMaxLength; 64 KiB keeps every write small.
+const CLAMD_CHUNK_SIZE: usize = 64 * 1024;
+
+impl ClamdScanner {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self { addr: addr.into() }
+    }
+
+    async fn instream(&self, body: &Bytes) -> Result<ScanOutcome, ProxyError> {
+        let mut stream = TcpStream::connect(&self.addr).await
+            .map_err(|e| ProxyError::Connection(format!("Failed to connect to clamd at {}: {}", self.addr, e)))?;
+
+        stream.write_all(b"zINSTREAM\0").await?;
+        for chunk in body.chunks(CLAMD_CHUNK_SIZE) {
+            stream.write_all(&(chunk.len() as u32).to_be_bytes()).await?;
+            stream.write_all(chunk).await?;
+        }
+        stream.write_all(&0u32.to_be_bytes()).await?;
+        stream.flush().await?;
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await?;
+        parse_clamd_reply(&reply)
+    }
+}
+
+impl MalwareScanner for ClamdScanner {
+    fn name(&self) -> &str {
+        "clamd"
+    }
+
+    fn scan<'a>(&'a self, body: &'a Bytes) -> Pin<Box<dyn Future<Output = Result<ScanOutcome, ProxyError>> + Send + 'a>> {
+        Box::pin(self.instream(body))
+    }
+}
+
+/// Parses `stream: OK`, `stream: <signature> FOUND` and `... ERROR` replies.
+fn parse_clamd_reply(reply: &[u8]) -> Result<ScanOutcome, ProxyError> {
+    let text = String::from_utf8_lossy(reply);
+    let text = text.trim_end_matches(['\0', '\n', '\r']).trim();
+    let verdict = text.split_once(": ").map(|(_, rest)| rest).unwrap_or(text);
+
+    if verdict == "OK" {
+        Ok(ScanOutcome::Clean)
+    } else if let Some(signature) = verdict.strip_suffix(" FOUND") {
+        Ok(ScanOutcome::Infected(signature.to_string()))
+    } else {
+        Err(ProxyError::Connection(format!("clamd error: {}", text)))
+    }
+}
+
+/// Decision applied to a download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+    Allow,
+    Block { status: StatusCode, reason: String },
+}
+
+pub struct DownloadScanner {
+    config: DownloadScanConfig,
+    scanner: Box<dyn MalwareScanner>,
+}
+
+impl DownloadScanner {
+    pub fn new(config: DownloadScanConfig) -> Self {
+        let scanner = Box::new(ClamdScanner::new(config.clamd_addr.clone()));
+        Self { config, scanner }
+    }
+
+    /// Replaces the default clamd engine.
+    pub fn with_scanner(mut self, scanner: Box<dyn MalwareScanner>) -> Self {
+        self.scanner = scanner;
+        self
+    }
+
+    /// Whether a response with this content type and body length should be scanned.
+    pub fn should_scan(&self, content_type: Option<&str>, len: usize) -> bool {
+        let min_size = if self.config.content_types.is_empty() {
+            self.config.min_size_bytes
+        } else {
+            match find_content_type_rule(&self.config.content_types, content_type) {
+                Some(rule) if rule.scan => rule.min_size_bytes.unwrap_or(self.config.min_size_bytes),
+                _ => return false,
+            }
+        };
+        len >= min_size && len <= self.config.max_size_bytes
+    }
+
+    /// Scans `body` if it falls within the configured size window and content-type rules.
+    pub async fn check(&self, url: &str, content_type: Option<&str>, body: &Bytes) -> ScanVerdict {
+        if !self.should_scan(content_type, body.len()) {
+            scan_telemetry().record("skipped");
+            return ScanVerdict::Allow;
+        }
+
+        let started = Instant::now();
+        let outcome = tokio::time::timeout(
+            Duration::from_millis(self.config.timeout_ms),
+            self.scanner.scan(body),
+        ).await;
+        scan_telemetry().duration.observe(started.elapsed().as_secs_f64());
+
+        match outcome {
+            Ok(Ok(ScanOutcome::Clean)) => {
+                scan_telemetry().record("clean");
+                ScanVerdict::Allow
+            }
+            Ok(Ok(ScanOutcome::Infected(signature))) => {
+                scan_telemetry().record("infected");
+                info!("{} flagged download {} as {}", self.scanner.name(), url, signature);
+                ScanVerdict::Block { status: StatusCode::FORBIDDEN, reason: signature }
+            }
+            Ok(Err(e)) => {
+                warn!("{} scan of {} failed: {}", self.scanner.name(), url, e);
+                self.apply_failure_policy()
+            }
+            Err(_) => {
+                warn!("{} scan of {} timed out after {}ms", self.scanner.name(), url, self.config.timeout_ms);
+                self.apply_failure_policy()
+            }
+        }
+    }
+
+    fn apply_failure_policy(&self) -> ScanVerdict {
+        scan_telemetry().record("error");
+        match self.config.on_failure {
+            HookFailurePolicy::Bypass => ScanVerdict::Allow,
+            HookFailurePolicy::Block => ScanVerdict::Block {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                reason: "scanner unavailable".to_string(),
+            },
+        }
+    }
+}
+
+fn find_content_type_rule<'a>(rules: &'a [ScanContentTypeRule], content_type: Option<&str>) -> Option<&'a ScanContentTypeRule> {
+    let mime = content_type
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    rules.iter().find(|rule| {
+        let pattern = rule.content_type.trim().to_ascii_lowercase();
+        if pattern == "*" || pattern == "*/*" {
+            return true;
+        }
+        match pattern.strip_suffix("/*") {
+            Some(major) => mime.split('/').next() == Some(major),
+            None => mime == pattern,
+        }
+    })
+}
+
+/// Builds the page returned in place of a blocked download.
+pub fn blocked_download_response(url: &str, status: StatusCode, reason: &str) -> Response<Full<Bytes>> {
+    let body = format!(
+        "<!DOCTYPE html>\n<html><head><title>Download blocked</title></head><body>\
+         <h1>Download blocked</h1><p>The file at <code>{}</code> was blocked by the malware scanner.</p>\
+         <p>Reason: {}</p></body></html>\n",
+        escape_html(url),
+        escape_html(reason),
+    );
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .header("Cache-Control", "no-store")
+        .header("X-Bifrost-Blocked", "malware-scan")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+struct ScanTelemetry {
+    scans: IntCounterVec,
+    duration: Histogram,
+    registered: AtomicBool,
+}
+
+impl ScanTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "download_scans_total",
+            "Download scan results by outcome (clean, infected, error, skipped)",
+        ).namespace("bifrost");
+        let duration_opts = HistogramOpts::new(
+            "download_scan_duration_seconds",
+            "Time spent waiting for the malware scanner",
+        ).namespace("bifrost");
+        Self {
+            scans: IntCounterVec::new(opts, &["outcome"]).expect("download_scans_total metric"),
+            duration: Histogram::with_opts(duration_opts).expect("download_scan_duration_seconds metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.scans.clone())) {
+            warn!("Failed to register download_scans_total metric: {}", err);
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.duration.clone())) {
+            warn!("Failed to register download_scan_duration_seconds metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    fn record(&self, outcome: &str) {
+        self.scans.with_label_values(&[outcome]).inc();
+    }
+}
+
+fn scan_telemetry() -> &'static ScanTelemetry {
+    static TELEMETRY: OnceLock<ScanTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(ScanTelemetry::new)
+}
+
+/// Registers the download scanning metrics with the shared Prometheus registry.
+pub fn register_download_scan_metrics(registry: &Registry) {
+    scan_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedScanner(Result<ScanOutcome, ()>);
+
+    impl MalwareScanner for FixedScanner {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn scan<'a>(&'a self, _body: &'a Bytes) -> Pin<Box<dyn Future<Output = Result<ScanOutcome, ProxyError>> + Send + 'a>> {
+            let result = self.0.clone().map_err(|_| ProxyError::Connection("down".to_string()));
+            Box::pin(async move { result })
+        }
+    }
+
+    fn scanner(config: DownloadScanConfig, result: Result<ScanOutcome, ()>) -> DownloadScanner {
+        DownloadScanner::new(config).with_scanner(Box::new(FixedScanner(result)))
+    }
+
+    #[test]
+    fn parses_clamd_replies() {
+        assert_eq!(parse_clamd_reply(b"stream: OK\0").unwrap(), ScanOutcome::Clean);
+        assert_eq!(
+            parse_clamd_reply(b"stream: Eicar-Test-Signature FOUND\0").unwrap(),
+            ScanOutcome::Infected("Eicar-Test-Signature".to_string())
+        );
+        assert!(parse_clamd_reply(b"INSTREAM size limit exceeded. ERROR\0").is_err());
+    }
+
+    #[test]
+    fn content_type_rules_control_thresholds() {
+        let config = DownloadScanConfig {
+            min_size_bytes: 100,
+            content_types: vec![
+                ScanContentTypeRule { content_type: "text/*".to_string(), scan: false, min_size_bytes: None },
+                ScanContentTypeRule { content_type: "application/zip".to_string(), scan: true, min_size_bytes: Some(1) },
+                ScanContentTypeRule { content_type: "application/*".to_string(), scan: true, min_size_bytes: None },
+            ],
+            ..DownloadScanConfig::default()
+        };
+        let scanner = scanner(config, Ok(ScanOutcome::Clean));
+
+        assert!(!scanner.should_scan(Some("text/html; charset=utf-8"), 10_000));
+        assert!(scanner.should_scan(Some("application/zip"), 10));
+        assert!(!scanner.should_scan(Some("application/octet-stream"), 10));
+        assert!(scanner.should_scan(Some("Application/Octet-Stream"), 500));
+        assert!(!scanner.should_scan(Some("image/png"), 500));
+        assert!(!scanner.should_scan(None, 500));
+    }
+
+    #[tokio::test]
+    async fn infected_and_failed_scans_follow_policy() {
+        let body = Bytes::from(vec![0u8; 2048]);
+
+        let infected = scanner(DownloadScanConfig::default(), Ok(ScanOutcome::Infected("Eicar".to_string())));
+        assert_eq!(
+            infected.check("http://example.com/a.exe", None, &body).await,
+            ScanVerdict::Block { status: StatusCode::FORBIDDEN, reason: "Eicar".to_string() }
+        );
+
+        let bypass = scanner(DownloadScanConfig::default(), Err(()));
+        assert_eq!(bypass.check("http://example.com/a.exe", None, &body).await, ScanVerdict::Allow);
+
+        let closed = scanner(
+            DownloadScanConfig { on_failure: HookFailurePolicy::Block, ..DownloadScanConfig::default() },
+            Err(()),
+        );
+        assert!(matches!(
+            closed.check("http://example.com/a.exe", None, &body).await,
+            ScanVerdict::Block { status: StatusCode::SERVICE_UNAVAILABLE, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn clamd_scanner_speaks_instream() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut command = [0u8; 10];
+            stream.read_exact(&mut command).await.unwrap();
+            assert_eq!(&command, b"zINSTREAM\0");
+            let mut received = Vec::new();
+            loop {
+                let len = stream.read_u32().await.unwrap() as usize;
+                if len == 0 {
+                    break;
+                }
+                let mut chunk = vec![0u8; len];
+                stream.read_exact(&mut chunk).await.unwrap();
+                received.extend_from_slice(&chunk);
+            }
+            let reply: &[u8] = if received.starts_with(b"X5O") { b"stream: Eicar FOUND\0" } else { b"stream: OK\0" };
+            stream.write_all(reply).await.unwrap();
+        });
+
+        let clamd = ClamdScanner::new(addr.to_string());
+        let outcome = clamd.scan(&Bytes::from_static(b"X5O!P%@AP")).await.unwrap();
+        assert_eq!(outcome, ScanOutcome::Infected("Eicar".to_string()));
+    }
+}
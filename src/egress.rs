@@ -0,0 +1,388 @@
+//! How reverse proxy backend connections leave the host.
+//!
+//! A route whose `reverse_proxy_config.relay_proxies` match a target host connects to that target
+//! through the proxy instead of directly. `http://` proxies get a CONNECT tunnel, opened by the
+//! same code the forward proxy uses for its relays. `socks5://` proxies get a SOCKS5 CONNECT.
+//! Either way the proxy resolves the target name. Once the tunnel is up it is a plain TCP stream,
+//! so pooling and stale-connection retries work as they do for direct connections.
+//!
+//! Direct connections can also be opened from a fixed source address or interface, and every
+//! connection can carry a DSCP mark, so tenants on different routes egress differently.
+
+use crate::config::{RelayAuthScheme, RelayProxyConfig, ReverseProxyConfig};
+use crate::dns::DnsResolver;
+use crate::error::ProxyError;
+use crate::forward_proxy::{ForwardProxy, RelayProxyWithAuth};
+use crate::redact::redact_url;
+use crate::secrets::Secret;
+use hyper::Uri;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioIo;
+use log::debug;
+use std::error::Error;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{Duration, timeout};
+use tower_service::Service;
+use url::Url;
+use zeroize::Zeroizing;
+
+/// Checks the egress settings of one route at startup.
+pub fn validate(route_id: &str, config: &ReverseProxyConfig) -> Result<(), ProxyError> {
+    let ipv4 = config.source_addresses.iter().filter(|ip| ip.is_ipv4()).count();
+    if ipv4 > 1 || config.source_addresses.len() - ipv4 > 1 {
+        return Err(ProxyError::Config(format!(
+            "Route {} source_addresses may hold at most one IPv4 and one IPv6 address",
+            route_id
+        )));
+    }
+    if config.dscp.is_some_and(|dscp| dscp > 63) {
+        return Err(ProxyError::Config(format!("Route {} dscp must be between 0 and 63", route_id)));
+    }
+    for relay in &config.relay_proxies {
+        let url = Url::parse(&relay.relay_proxy_url).map_err(|e| {
+            ProxyError::Config(format!("Route {} has an invalid egress proxy URL: {}", route_id, e))
+        })?;
+        if url.host_str().is_none() {
+            return Err(ProxyError::Config(format!(
+                "Route {} egress proxy URL has no host: {}",
+                route_id, redact_url(&relay.relay_proxy_url)
+            )));
+        }
+        match url.scheme() {
+            "http" => {}
+            "socks5" | "socks5h" if relay.relay_proxy_auth_scheme == RelayAuthScheme::Ntlm => {
+                return Err(ProxyError::Config(format!(
+                    "Route {} egress proxy {}: SOCKS5 proxies only support username/password authentication",
+                    route_id, redact_url(&relay.relay_proxy_url)
+                )));
+            }
+            "socks5" | "socks5h" => {}
+            other => {
+                return Err(ProxyError::Config(format!(
+                    "Route {} egress proxy scheme '{}' is not supported (use http or socks5)",
+                    route_id, other
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+enum Tunnel {
+    Connect(RelayProxyWithAuth),
+    Socks5 {
+        addr: String,
+        credentials: Option<(String, Secret)>,
+    },
+}
+
+struct EgressProxy {
+    url: String,
+    domains: Vec<String>,
+    tunnel: Tunnel,
+}
+
+impl EgressProxy {
+    fn new(config: &RelayProxyConfig) -> Self {
+        let url = Url::parse(&config.relay_proxy_url).ok();
+        let tunnel = match url.as_ref().map(Url::scheme) {
+            Some("socks5" | "socks5h") => {
+                let url = url.as_ref().expect("scheme implies a parsed URL");
+                Tunnel::Socks5 {
+                    addr: format!("{}:{}", url.host_str().unwrap_or_default(), url.port().unwrap_or(1080)),
+                    credentials: config.relay_proxy_username.clone().zip(config.relay_proxy_password.clone()),
+                }
+            }
+            _ => Tunnel::Connect(RelayProxyWithAuth::from(config.clone())),
+        };
+        Self { url: redact_url(&config.relay_proxy_url), domains: config.relay_proxy_domains.clone(), tunnel }
+    }
+
+    /// Same rule as the forward proxy's relays: no patterns matches every host.
+    fn applies_to(&self, host: &str) -> bool {
+        self.domains.is_empty() || ForwardProxy::matching_no_proxy_pattern(host, &self.domains).is_some()
+    }
+
+    async fn connect(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        match &self.tunnel {
+            Tunnel::Connect(relay) => ForwardProxy::connect_via_relay(relay, host, port).await,
+            Tunnel::Socks5 { addr, credentials } => {
+                let stream = TcpStream::connect(addr).await?;
+                socks5_connect(stream, credentials.as_ref(), host, port).await
+            }
+        }
+    }
+}
+
+/// Backend connector: a direct connection, or a tunnel through the first matching egress proxy.
+#[derive(Clone)]
+pub struct UpstreamConnector {
+    direct: HttpConnector<DnsResolver>,
+    proxies: Arc<[EgressProxy]>,
+    connect_timeout: Duration,
+    dscp: Option<u8>,
+}
+
+impl UpstreamConnector {
+    pub fn new(direct: HttpConnector<DnsResolver>, relays: &[RelayProxyConfig], connect_timeout: Duration) -> Self {
+        Self {
+            direct,
+            proxies: relays.iter().map(EgressProxy::new).collect(),
+            connect_timeout,
+            dscp: None,
+        }
+    }
+
+    /// Marks every connection, direct or tunneled, with this DSCP value.
+    pub fn with_dscp(mut self, dscp: Option<u8>) -> Self {
+        self.dscp = dscp;
+        self
+    }
+
+    fn proxy_for(&self, host: &str) -> Option<usize> {
+        self.proxies.iter().position(|proxy| proxy.applies_to(host))
+    }
+}
+
+impl Service<Uri> for UpstreamConnector {
+    type Response = TokioIo<TcpStream>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.direct.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let host = uri.host().unwrap_or_default().trim_start_matches('[').trim_end_matches(']').to_string();
+        let dscp = self.dscp;
+        let Some(index) = self.proxy_for(&host) else {
+            let connecting = self.direct.call(uri);
+            return Box::pin(async move {
+                let io = connecting.await?;
+                if let Some(dscp) = dscp {
+                    set_dscp(io.inner(), dscp)?;
+                }
+                Ok(io)
+            });
+        };
+        let proxies = self.proxies.clone();
+        let connect_timeout = self.connect_timeout;
+        let port = uri.port_u16().unwrap_or(if uri.scheme_str() == Some("https") { 443 } else { 80 });
+        Box::pin(async move {
+            let proxy = &proxies[index];
+            debug!("Connecting to {}:{} through egress proxy {}", host, port, proxy.url);
+            let stream = timeout(connect_timeout, proxy.connect(&host, port))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "egress proxy connect timed out"))??;
+            stream.set_nodelay(true)?;
+            if let Some(dscp) = dscp {
+                set_dscp(&stream, dscp)?;
+            }
+            Ok(TokioIo::new(stream))
+        })
+    }
+}
+
+/// Sets the DSCP bits of the IPv4 TOS byte or IPv6 traffic class. The connection is already
+/// open, so its handshake packets go out unmarked.
+#[cfg(target_os = "linux")]
+fn set_dscp(stream: &TcpStream, dscp: u8) -> io::Result<()> {
+    let socket = socket2::SockRef::from(stream);
+    let tos = u32::from(dscp) << 2;
+    match stream.peer_addr()? {
+        SocketAddr::V4(_) => socket.set_tos_v4(tos),
+        SocketAddr::V6(_) => socket.set_tclass_v6(tos),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_dscp(_stream: &TcpStream, _dscp: u8) -> io::Result<()> {
+    log::warn!("DSCP marking is only supported on Linux; ignoring dscp");
+    Ok(())
+}
+
+/// Runs the SOCKS5 greeting, optional username/password sub-negotiation (RFC 1929) and CONNECT.
+/// The target name is passed through unresolved.
+async fn socks5_connect(
+    mut stream: TcpStream,
+    credentials: Option<&(String, Secret)>,
+    host: &str,
+    port: u16,
+) -> io::Result<TcpStream> {
+    let refused = |message: String| io::Error::new(io::ErrorKind::PermissionDenied, message);
+
+    let greeting: &[u8] = if credentials.is_some() { &[5, 2, 0x00, 0x02] } else { &[5, 1, 0x00] };
+    stream.write_all(greeting).await?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    match (choice, credentials) {
+        ([5, 0x00], _) => {}
+        ([5, 0x02], Some((username, password))) => {
+            let password = password.expose();
+            if username.len() > 255 || password.len() > 255 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "SOCKS5 credentials longer than 255 bytes"));
+            }
+            let mut request = Zeroizing::new(vec![1, username.len() as u8]);
+            request.extend_from_slice(username.as_bytes());
+            request.push(password.len() as u8);
+            request.extend_from_slice(password.as_bytes());
+            stream.write_all(&request).await?;
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[1] != 0 {
+                return Err(refused("SOCKS5 proxy rejected the credentials".to_string()));
+            }
+        }
+        ([5, method], _) => return Err(refused(format!("SOCKS5 proxy offered no usable auth method ({:#04x})", method))),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "not a SOCKS5 proxy")),
+    }
+
+    let mut request = vec![5, 1, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) if host.len() <= 255 => {
+            request.extend_from_slice(&[3, host.len() as u8]);
+            request.extend_from_slice(host.as_bytes());
+        }
+        Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "host name too long for SOCKS5")),
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        return Err(refused(format!("SOCKS5 proxy refused CONNECT to {}:{} (reply {})", host, port, reply[1])));
+    }
+    // Skip the bound address and port, which are of no use here
+    let address_len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => stream.read_u8().await? as usize,
+        other => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("bad SOCKS5 address type {}", other))),
+    };
+    let mut bound = vec![0u8; address_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+    use hyper_util::client::legacy::Client;
+    use hyper_util::rt::TokioExecutor;
+    use tokio::net::TcpListener;
+
+    fn with_relays(relay_proxies: Vec<RelayProxyConfig>) -> ReverseProxyConfig {
+        ReverseProxyConfig { relay_proxies, ..ReverseProxyConfig::default() }
+    }
+
+    fn relay(url: String, domains: &[&str]) -> RelayProxyConfig {
+        RelayProxyConfig {
+            relay_proxy_url: url,
+            relay_proxy_username: Some("svc".to_string()),
+            relay_proxy_password: Some(Secret::new("s3cret")),
+            relay_proxy_domains: domains.iter().map(|d| d.to_string()).collect(),
+            relay_proxy_auth_scheme: RelayAuthScheme::Basic,
+        }
+    }
+
+    #[tokio::test]
+    async fn socks5_proxy_carries_matching_backends() {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_port = backend.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = backend.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await;
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 6\r\n\r\nviaegr").await;
+        });
+
+        // Minimal SOCKS5 server that insists on credentials and sends every name to the backend
+        let socks = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socks_addr = socks.local_addr().unwrap();
+        let proxy = tokio::spawn(async move {
+            let (mut client, _) = socks.accept().await.unwrap();
+            let mut greeting = [0u8; 4];
+            client.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [5, 2, 0, 2]);
+            client.write_all(&[5, 2]).await.unwrap();
+            let mut auth = [0u8; 12];
+            client.read_exact(&mut auth).await.unwrap();
+            assert_eq!(&auth, b"\x01\x03svc\x06s3cret");
+            client.write_all(&[1, 0]).await.unwrap();
+            let mut head = [0u8; 5];
+            client.read_exact(&mut head).await.unwrap();
+            assert_eq!(head[..4], [5, 1, 0, 3]);
+            let mut name = vec![0u8; head[4] as usize + 2];
+            client.read_exact(&mut name).await.unwrap();
+            client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await.unwrap();
+            let mut upstream = TcpStream::connect(("127.0.0.1", backend_port)).await.unwrap();
+            let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+            String::from_utf8(name[..name.len() - 2].to_vec()).unwrap()
+        });
+
+        let relays = vec![relay(format!("socks5://{}", socks_addr), &[".internal"])];
+        validate("orders", &with_relays(relays.clone())).unwrap();
+        let connector = UpstreamConnector::new(
+            HttpConnector::new_with_resolver(DnsResolver::default()),
+            &relays,
+            Duration::from_secs(5),
+        );
+        assert_eq!(connector.proxy_for("billing.internal"), Some(0));
+        assert_eq!(connector.proxy_for("example.com"), None);
+
+        let client: Client<_, Full<Bytes>> = Client::builder(TokioExecutor::new()).build(connector);
+        let uri: Uri = format!("http://billing.internal:{}/orders", backend_port).parse().unwrap();
+        let response = client.request(hyper::Request::get(uri).body(Full::new(Bytes::new())).unwrap()).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"viaegr");
+        drop(client);
+        assert_eq!(proxy.await.unwrap(), "billing.internal");
+
+        let mut ntlm = relay("socks5://127.0.0.1:1080".to_string(), &[]);
+        ntlm.relay_proxy_auth_scheme = RelayAuthScheme::Ntlm;
+        assert!(validate("orders", &with_relays(vec![ntlm])).is_err());
+        assert!(validate("orders", &with_relays(vec![relay("https://proxy:443".to_string(), &[])])).is_err());
+    }
+
+    #[tokio::test]
+    async fn direct_connections_use_the_source_address_and_dscp() {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = backend.local_addr().unwrap();
+        let accept = tokio::spawn(async move { backend.accept().await.unwrap() });
+
+        let mut direct = HttpConnector::new_with_resolver(DnsResolver::default());
+        direct.set_local_address(Some("127.0.0.1".parse().unwrap()));
+        let mut connector = UpstreamConnector::new(direct, &[], Duration::from_secs(5)).with_dscp(Some(46));
+        let io = connector.call(format!("http://{}/", addr).parse().unwrap()).await.unwrap();
+        let (_accepted, peer) = accept.await.unwrap();
+        assert_eq!(io.inner().local_addr().unwrap(), peer);
+        #[cfg(target_os = "linux")]
+        assert_eq!(socket2::SockRef::from(io.inner()).tos_v4().unwrap(), 46 << 2);
+
+        let config = ReverseProxyConfig {
+            source_addresses: vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()],
+            ..ReverseProxyConfig::default()
+        };
+        assert!(validate("tenant-a", &config).is_err());
+        assert!(validate("tenant-a", &ReverseProxyConfig { dscp: Some(64), ..ReverseProxyConfig::default() }).is_err());
+    }
+}
@@ -18,6 +18,9 @@ pub enum ProxyError {
     #[error("Authentication error: {0}")]
     Auth(String),
 
+    #[error("Too many failed authentication attempts, retry in {0}s")]
+    AuthLockedOut(u64),
+
     #[error("URL parsing error: {0}")]
     Url(#[from] url::ParseError),
 
@@ -63,6 +66,64 @@ pub enum ProxyError {
 
     #[error("Worker recovery failed: {0}")]
     WorkerRecoveryFailed(String),
+
+    /// A backend request that failed in transport, classified by `upstream_error::classify`
+    #[error("Upstream {kind} error: {message}")]
+    Upstream { kind: UpstreamErrorKind, message: String },
+}
+
+/// Why a request to a backend failed before a response arrived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UpstreamErrorKind {
+    /// The TCP connection was not established in time
+    ConnectTimeout,
+    /// The connection was refused or the host was unreachable
+    Connect,
+    /// The backend host name could not be resolved
+    Dns,
+    /// The TLS handshake with the backend failed
+    Tls,
+    /// The connection was reset or closed before a complete response
+    Reset,
+    /// An established connection stopped making progress
+    Timeout,
+    /// The backend sent something that is not valid HTTP
+    Protocol,
+    Other,
+}
+
+impl UpstreamErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UpstreamErrorKind::ConnectTimeout => "connect_timeout",
+            UpstreamErrorKind::Connect => "connect",
+            UpstreamErrorKind::Dns => "dns",
+            UpstreamErrorKind::Tls => "tls",
+            UpstreamErrorKind::Reset => "reset",
+            UpstreamErrorKind::Timeout => "timeout",
+            UpstreamErrorKind::Protocol => "protocol",
+            UpstreamErrorKind::Other => "other",
+        }
+    }
+
+    /// Whether another attempt, possibly on another target, can succeed. TLS and protocol
+    /// failures repeat on every attempt, so they are fatal.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            UpstreamErrorKind::ConnectTimeout
+                | UpstreamErrorKind::Connect
+                | UpstreamErrorKind::Dns
+                | UpstreamErrorKind::Reset
+                | UpstreamErrorKind::Timeout
+        )
+    }
+}
+
+impl std::fmt::Display for UpstreamErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// Error severity levels for determining recovery actions
@@ -80,10 +141,10 @@ impl ProxyError {
         match self {
             // Low severity - common operational issues
             ProxyError::Io(_) | ProxyError::Http(_) | ProxyError::Connection(_) |
-            ProxyError::NotFound(_) | ProxyError::Uri(_) => ErrorSeverity::Low,
+            ProxyError::NotFound(_) | ProxyError::Uri(_) | ProxyError::Upstream { .. } => ErrorSeverity::Low,
 
             // Medium severity - resource issues that can be recovered
-            ProxyError::Auth(_) | ProxyError::Url(_) | ProxyError::Utf8(_) |
+            ProxyError::Auth(_) | ProxyError::AuthLockedOut(_) | ProxyError::Url(_) | ProxyError::Utf8(_) |
             ProxyError::Hyper(_) | ProxyError::Worker(_) | ProxyError::MetricsError(_) => ErrorSeverity::Medium,
 
             // High severity - requires intervention but system can continue
@@ -99,9 +160,20 @@ impl ProxyError {
 
     /// Check if this error is recoverable
     pub fn is_recoverable(&self) -> bool {
+        if let ProxyError::Upstream { kind, .. } = self {
+            return kind.is_retryable();
+        }
         matches!(self.severity(), ErrorSeverity::Low | ErrorSeverity::Medium)
     }
 
+    /// The classification of a failed backend request, if this is one
+    pub fn upstream_kind(&self) -> Option<UpstreamErrorKind> {
+        match self {
+            ProxyError::Upstream { kind, .. } => Some(*kind),
+            _ => None,
+        }
+    }
+
     /// Check if this error requires worker isolation
     pub fn requires_worker_isolation(&self) -> bool {
         matches!(
@@ -120,6 +192,7 @@ impl ProxyError {
             ProxyError::Connection(_) => RecoveryAction::Reconnect,
             ProxyError::Config(_) => RecoveryAction::Reconfigure,
             ProxyError::Auth(_) => RecoveryAction::Reauthenticate,
+            ProxyError::AuthLockedOut(_) => RecoveryAction::Throttle,
             ProxyError::Url(_) | ProxyError::Uri(_) => RecoveryAction::BadRequest,
             ProxyError::Utf8(_) => RecoveryAction::BadRequest,
             ProxyError::Hyper(_) => RecoveryAction::Retry,
@@ -134,6 +207,14 @@ impl ProxyError {
             ProxyError::ResourceContention(_) => RecoveryAction::Throttle,
             ProxyError::WorkerShutdownTimeout(_) => RecoveryAction::ForceShutdown,
             ProxyError::WorkerRecoveryFailed(_) => RecoveryAction::SkipWorker,
+            ProxyError::Upstream { kind, .. } => match kind {
+                UpstreamErrorKind::ConnectTimeout | UpstreamErrorKind::Connect | UpstreamErrorKind::Dns => {
+                    RecoveryAction::Reconnect
+                }
+                UpstreamErrorKind::Reset | UpstreamErrorKind::Timeout => RecoveryAction::Retry,
+                UpstreamErrorKind::Tls => RecoveryAction::Reconfigure,
+                UpstreamErrorKind::Protocol | UpstreamErrorKind::Other => RecoveryAction::Ignore,
+            },
         }
     }
 
@@ -0,0 +1,188 @@
+use crate::config::FanOutConfig;
+use crate::error::ProxyError;
+use hyper::StatusCode;
+use log::{debug, warn};
+use prometheus::{IntCounterVec, Opts, Registry};
+use rand::Rng;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use tokio::time::{Duration, timeout};
+use url::Url;
+
+/// Copies requests on a route to additional targets.
+///
+/// The caller is always answered by the route's primary target; copies are delivered in the
+/// background and only their outcomes are recorded.
+pub struct FanOut {
+    targets: Vec<Url>,
+    sample_rate: f64,
+    timeout: Duration,
+    max_body_bytes: usize,
+}
+
+impl FanOut {
+    pub fn new(config: &FanOutConfig) -> Result<Self, ProxyError> {
+        if config.targets.is_empty() {
+            return Err(ProxyError::Config("fan_out.targets must not be empty".to_string()));
+        }
+        if !(0.0..=1.0).contains(&config.sample_rate) {
+            return Err(ProxyError::Config(format!(
+                "fan_out.sample_rate must be between 0.0 and 1.0, got {}",
+                config.sample_rate
+            )));
+        }
+        let targets = config
+            .targets
+            .iter()
+            .map(|target| {
+                Url::parse(target)
+                    .map_err(|e| ProxyError::Config(format!("Invalid fan_out target '{}': {}", target, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            targets,
+            sample_rate: config.sample_rate,
+            timeout: Duration::from_millis(config.timeout_ms),
+            max_body_bytes: config.max_body_bytes,
+        })
+    }
+
+    pub fn targets(&self) -> &[Url] {
+        &self.targets
+    }
+
+    pub fn max_body_bytes(&self) -> usize {
+        self.max_body_bytes
+    }
+
+    /// Decides whether this request is copied; a sampled request goes to every target.
+    pub fn sample(&self) -> bool {
+        self.sample_rate >= 1.0 || rand::thread_rng().gen_bool(self.sample_rate)
+    }
+
+    /// Runs each delivery in the background with the configured timeout and records its outcome.
+    pub fn dispatch<F>(&self, route_id: &str, deliveries: Vec<(String, F)>)
+    where
+        F: Future<Output = Result<StatusCode, ProxyError>> + Send + 'static,
+    {
+        for (target, delivery) in deliveries {
+            let route_id = route_id.to_string();
+            let limit = self.timeout;
+            tokio::spawn(async move {
+                let outcome = match timeout(limit, delivery).await {
+                    Ok(Ok(status)) if status.is_success() => {
+                        debug!("Fan-out copy for route {} delivered to {} ({})", route_id, target, status);
+                        "delivered"
+                    }
+                    Ok(Ok(status)) => {
+                        warn!("Fan-out copy for route {} to {} returned {}", route_id, target, status);
+                        "http_error"
+                    }
+                    Ok(Err(e)) => {
+                        warn!("Fan-out copy for route {} to {} failed: {}", route_id, target, e);
+                        "failed"
+                    }
+                    Err(_) => {
+                        warn!("Fan-out copy for route {} to {} timed out after {:?}", route_id, target, limit);
+                        "timeout"
+                    }
+                };
+                fan_out_telemetry().record(&route_id, &target, outcome);
+            });
+        }
+    }
+}
+
+struct FanOutTelemetry {
+    deliveries: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl FanOutTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "fan_out_deliveries_total",
+            "Background fan-out deliveries by route, target and outcome",
+        ).namespace("bifrost");
+        Self {
+            deliveries: IntCounterVec::new(opts, &["route", "target", "outcome"]).expect("fan_out_deliveries_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.deliveries.clone())) {
+            warn!("Failed to register fan_out_deliveries_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    fn record(&self, route: &str, target: &str, outcome: &str) {
+        self.deliveries.with_label_values(&[route, target, outcome]).inc();
+    }
+}
+
+fn fan_out_telemetry() -> &'static FanOutTelemetry {
+    static TELEMETRY: OnceLock<FanOutTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(FanOutTelemetry::new)
+}
+
+/// Registers the fan-out delivery counters with the shared Prometheus registry.
+pub fn register_fan_out_metrics(registry: &Registry) {
+    fan_out_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(targets: &[&str], sample_rate: f64) -> FanOutConfig {
+        FanOutConfig {
+            targets: targets.iter().map(|t| t.to_string()).collect(),
+            sample_rate,
+            ..FanOutConfig::default()
+        }
+    }
+
+    #[test]
+    fn validates_targets_and_sample_rate() {
+        assert!(FanOut::new(&config(&[], 1.0)).is_err());
+        assert!(FanOut::new(&config(&["not a url"], 1.0)).is_err());
+        assert!(FanOut::new(&config(&["http://staging:9000"], 1.5)).is_err());
+
+        let fan_out = FanOut::new(&config(&["http://staging:9000", "http://audit:9100"], 1.0)).unwrap();
+        assert_eq!(fan_out.targets().len(), 2);
+        assert!(fan_out.sample());
+        assert!(!FanOut::new(&config(&["http://staging:9000"], 0.0)).unwrap().sample());
+    }
+
+    #[tokio::test]
+    async fn records_delivery_outcomes() {
+        let fan_out = FanOut::new(&FanOutConfig {
+            timeout_ms: 20,
+            ..config(&["http://staging:9000"], 1.0)
+        })
+        .unwrap();
+
+        let slow = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok(StatusCode::OK)
+        };
+        let deliveries: Vec<(String, std::pin::Pin<Box<dyn Future<Output = _> + Send>>)> = vec![
+            ("ok".to_string(), Box::pin(async { Ok(StatusCode::OK) })),
+            ("slow".to_string(), Box::pin(slow)),
+        ];
+        fan_out.dispatch("hooks", deliveries);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let telemetry = fan_out_telemetry();
+        assert_eq!(telemetry.deliveries.with_label_values(&["hooks", "ok", "delivered"]).get(), 1);
+        assert_eq!(telemetry.deliveries.with_label_values(&["hooks", "slow", "timeout"]).get(), 1);
+    }
+}
@@ -0,0 +1,223 @@
+//! Client IPs behind trusted proxies.
+//!
+//! When the proxy sits behind a load balancer or CDN, the TCP peer is that hop, not the client.
+//! For peers in `trusted_proxies.cidrs`, the client IP is taken from the first of
+//! `trusted_proxies.headers` the request carries (`X-Forwarded-For` by default, or ones like
+//! `CF-Connecting-IP` and `X-Real-IP`): the rightmost address in it that is not itself a trusted
+//! proxy. That address is what rate limiting, `RemoteAddr` predicates, tarpits and logs see.
+//! Towards the backend, the chain of a trusted peer is extended with the peer's address; a chain
+//! sent by any other peer is replaced, since the client could have written anything into it.
+
+use crate::common::split_host_port;
+use crate::config::TrustedProxiesConfig;
+use crate::error::ProxyError;
+use hyper::HeaderMap;
+use hyper::header::{AsHeaderName, HeaderName};
+use ipnet::IpNet;
+use log::warn;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+static TRUSTED: OnceLock<TrustedProxies> = OnceLock::new();
+
+/// Makes `client_ip` and `forwarded_for` believe the chains of these proxies.
+pub fn init(config: &TrustedProxiesConfig) -> Result<(), ProxyError> {
+    let trusted = TrustedProxies::new(config)?;
+    if TRUSTED.set(trusted).is_err() {
+        warn!("Trusted proxies already initialized; keeping the first configuration");
+    }
+    Ok(())
+}
+
+/// The client behind `peer`; `peer` itself unless it is a trusted proxy.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+    match TRUSTED.get() {
+        Some(trusted) => trusted.client_ip(peer, headers),
+        None => peer,
+    }
+}
+
+/// The `X-Forwarded-For` value to send to the backend for a request from `peer`.
+pub fn forwarded_for(peer: IpAddr, headers: &HeaderMap) -> String {
+    match TRUSTED.get() {
+        Some(trusted) => trusted.forwarded_for(peer, headers),
+        None => peer.to_string(),
+    }
+}
+
+pub struct TrustedProxies {
+    nets: Vec<IpNet>,
+    headers: Vec<HeaderName>,
+}
+
+impl TrustedProxies {
+    pub fn new(config: &TrustedProxiesConfig) -> Result<Self, ProxyError> {
+        let nets = config
+            .cidrs
+            .iter()
+            .map(|cidr| {
+                cidr.parse::<IpNet>()
+                    .or_else(|_| cidr.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| ProxyError::Config(format!("trusted_proxies: invalid CIDR '{}'", cidr)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let headers = config
+            .headers
+            .iter()
+            .map(|name| {
+                HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| ProxyError::Config(format!("trusted_proxies: invalid header name '{}'", name)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { nets, headers })
+    }
+
+    fn trusts(&self, ip: IpAddr) -> bool {
+        self.nets.iter().any(|net| net.contains(&ip))
+    }
+
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.trusts(peer) {
+            return peer;
+        }
+        self.headers
+            .iter()
+            .find_map(|name| self.named_client(chain(headers, name)))
+            .unwrap_or(peer)
+    }
+
+    /// Walks back from the nearest hop; entries left of an untrusted one may be forged.
+    fn named_client<'a>(&self, hops: impl DoubleEndedIterator<Item = &'a str>) -> Option<IpAddr> {
+        let mut client = None;
+        for hop in hops.rev() {
+            match parse_hop(hop) {
+                Some(ip) => {
+                    client = Some(ip);
+                    if !self.trusts(ip) {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        client
+    }
+
+    pub fn forwarded_for(&self, peer: IpAddr, headers: &HeaderMap) -> String {
+        if !self.trusts(peer) {
+            return peer.to_string();
+        }
+        let peer = peer.to_string();
+        let mut hops: Vec<&str> = chain(headers, X_FORWARDED_FOR).collect();
+        hops.push(&peer);
+        hops.join(", ")
+    }
+}
+
+/// Entries of all `name` headers, oldest hop first. Single-address headers such as
+/// `CF-Connecting-IP` are a chain of one.
+fn chain(headers: &HeaderMap, name: impl AsHeaderName) -> impl DoubleEndedIterator<Item = &str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|hop| !hop.is_empty())
+}
+
+/// An address as some proxies write it: bare, or with a port (`[v6]:port` for IPv6), or an
+/// IPv6 address in brackets without one.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let (host, port) = split_host_port(hop)?;
+    if let Some(port) = port {
+        port.parse::<u16>().ok()?;
+    }
+    host.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    #[test]
+    fn trusted_peers_extend_the_chain_and_reveal_the_client() {
+        let trusted = TrustedProxies::new(&TrustedProxiesConfig {
+            cidrs: vec!["10.0.0.0/8".to_string(), "192.0.2.7".to_string()],
+            ..TrustedProxiesConfig::default()
+        })
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.append(X_FORWARDED_FOR, HeaderValue::from_static("1.1.1.1, 203.0.113.9:4431"));
+        headers.append(X_FORWARDED_FOR, HeaderValue::from_static("192.0.2.7"));
+
+        let balancer: IpAddr = "10.1.2.3".parse().unwrap();
+        assert_eq!(trusted.client_ip(balancer, &headers), "203.0.113.9".parse::<IpAddr>().unwrap());
+        assert_eq!(
+            trusted.forwarded_for(balancer, &headers),
+            "1.1.1.1, 203.0.113.9:4431, 192.0.2.7, 10.1.2.3"
+        );
+
+        // Anyone else's chain is ignored and replaced
+        let client: IpAddr = "198.51.100.1".parse().unwrap();
+        assert_eq!(trusted.client_ip(client, &headers), client);
+        assert_eq!(trusted.forwarded_for(client, &headers), "198.51.100.1");
+
+        // A chain of trusted hops only ends at its oldest entry
+        let mut internal = HeaderMap::new();
+        internal.insert(X_FORWARDED_FOR, HeaderValue::from_static("10.9.9.9, 192.0.2.7"));
+        assert_eq!(trusted.client_ip(balancer, &internal), "10.9.9.9".parse::<IpAddr>().unwrap());
+        assert_eq!(trusted.client_ip(balancer, &HeaderMap::new()), balancer);
+
+        assert!(TrustedProxies::new(&TrustedProxiesConfig {
+            cidrs: vec!["10.0.0.0/33".to_string()],
+            ..TrustedProxiesConfig::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn ipv6_hops_with_and_without_ports() {
+        let trusted = TrustedProxies::new(&TrustedProxiesConfig {
+            cidrs: vec!["2001:db8:ffff::/48".to_string()],
+            ..TrustedProxiesConfig::default()
+        })
+        .unwrap();
+        let balancer: IpAddr = "2001:db8:ffff::1".parse().unwrap();
+        let client: IpAddr = "2001:db8::7".parse().unwrap();
+        for hop in ["2001:db8::7", "[2001:db8::7]", "[2001:db8::7]:4431"] {
+            let mut headers = HeaderMap::new();
+            headers.insert(X_FORWARDED_FOR, HeaderValue::from_str(hop).unwrap());
+            assert_eq!(trusted.client_ip(balancer, &headers), client, "{}", hop);
+        }
+
+        // The chain gains the peer as a bare address, as the other hops are written
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static("2001:db8::7"));
+        assert_eq!(trusted.forwarded_for(balancer, &headers), "2001:db8::7, 2001:db8:ffff::1");
+        assert_eq!(parse_hop("[2001:db8::7]:http"), None);
+    }
+
+    #[test]
+    fn client_ip_headers_are_tried_in_order() {
+        let trusted = TrustedProxies::new(&TrustedProxiesConfig {
+            cidrs: vec!["173.245.48.0/20".to_string()],
+            headers: vec!["CF-Connecting-IP".to_string(), "x-real-ip".to_string(), "x-forwarded-for".to_string()],
+        })
+        .unwrap();
+        let edge: IpAddr = "173.245.48.10".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static("198.51.100.1"));
+        headers.insert("x-real-ip", HeaderValue::from_static("not an address"));
+        assert_eq!(trusted.client_ip(edge, &headers), "198.51.100.1".parse::<IpAddr>().unwrap());
+
+        headers.insert("cf-connecting-ip", HeaderValue::from_static("2001:db8::7"));
+        assert_eq!(trusted.client_ip(edge, &headers), "2001:db8::7".parse::<IpAddr>().unwrap());
+        // Only the trusted edge may name the client
+        let direct: IpAddr = "203.0.113.5".parse().unwrap();
+        assert_eq!(trusted.client_ip(direct, &headers), direct);
+    }
+}
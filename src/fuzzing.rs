@@ -0,0 +1,107 @@
+//! Entry points for the cargo-fuzz targets in `fuzz/`.
+//!
+//! Each function feeds arbitrary input to one parser that handles untrusted bytes and checks the
+//! invariants its callers rely on, panicking when one breaks. Only built with the `fuzzing`
+//! feature, so the parsers themselves can stay crate-private.
+
+use crate::config::{ConnectRequestLimitsConfig, StaticFileConfig};
+use crate::forward_proxy::ForwardProxy;
+use crate::reverse_proxy::build_ant_regex;
+use crate::static_files::StaticFileHandler;
+use futures::executor::block_on;
+use std::path::Component;
+use std::sync::OnceLock;
+use tokio::io::BufReader;
+
+/// A relay's answer to a forwarded request: status line, headers and body.
+pub fn relay_response(data: &[u8]) {
+    block_on(async {
+        let mut reader = BufReader::new(data);
+        if let Ok((_, _, content_length, chunked)) = ForwardProxy::parse_relay_status_and_headers(&mut reader).await
+            && let Ok(body) = ForwardProxy::read_relay_body(&mut reader, content_length, chunked).await
+            && let Some(length) = content_length.filter(|_| !chunked)
+        {
+            assert_eq!(body.len(), length);
+        }
+    });
+}
+
+/// A raw CONNECT request head as read from a client, and the target in its request line.
+pub fn connect_request(data: &[u8]) {
+    let limits = ConnectRequestLimitsConfig { max_line_bytes: 1024, max_headers: 16, ..Default::default() };
+    block_on(async {
+        let mut reader = BufReader::new(data);
+        if let Ok(head) = ForwardProxy::read_connect_head_lines(&mut reader, &limits).await {
+            assert!(head.request_line.len() <= limits.max_line_bytes + 2);
+            assert!(head.headers.len() <= limits.max_headers);
+            if let Some((target, host, _)) = ForwardProxy::parse_connect_target(&head.request_line) {
+                assert!(target.contains(&host));
+            }
+        }
+    });
+}
+
+/// An ant-style route pattern and a path to match it against, separated by the first newline.
+/// The first byte picks the trailing-slash and case-insensitivity options.
+pub fn ant_pattern(data: &[u8]) {
+    let Some((&flags, rest)) = data.split_first() else {
+        return;
+    };
+    let Ok(text) = std::str::from_utf8(rest) else {
+        return;
+    };
+    let (pattern, path) = text.split_once('\n').unwrap_or((text, text));
+    let Ok(regex) = build_ant_regex(pattern, flags & 1 != 0, flags & 2 != 0) else {
+        return;
+    };
+    let _ = regex.captures(path);
+    // Without wildcards or variables a pattern is a literal path and matches itself
+    if !pattern.contains(['*', '?', '{']) {
+        assert!(regex.is_match(pattern), "{:?} does not match itself", pattern);
+    }
+}
+
+/// A request path resolved against the static mounts `/` and `/assets`; the result must stay
+/// inside the mount's root.
+pub fn static_path(data: &[u8]) {
+    static HANDLER: OnceLock<StaticFileHandler> = OnceLock::new();
+    let handler = HANDLER.get_or_init(|| {
+        let root = std::env::temp_dir().to_string_lossy().to_string();
+        let mut config = StaticFileConfig::single(root.clone(), false);
+        config.add_mount("/assets".to_string(), root, false);
+        StaticFileHandler::new(config).expect("temporary directory as static root")
+    });
+
+    let Ok(path) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Some((mount_info, relative_path)) = handler.find_mount_for_path(path) else {
+        return;
+    };
+    if let Some(resolved) = handler.resolve_file_path_in_mount(mount_info, &relative_path) {
+        let inside = resolved
+            .strip_prefix(mount_info.root_path())
+            .map(|rest| rest.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir)))
+            .unwrap_or(false);
+        assert!(inside, "{:?} resolved outside the mount: {:?}", path, resolved);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_points_accept_sample_inputs() {
+        relay_response(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello");
+        relay_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n");
+        relay_response(b"HTTP/1.1 200 OK\r\nContent-Length: 18446744073709551615\r\n\r\nshort");
+        connect_request(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n");
+        connect_request(b"CONNECT\r\n\r\n");
+        ant_pattern(b"\x03/api/{id}/**\n/API/42/items/");
+        ant_pattern(b"\x00/static/app.v1+2.js");
+        ant_pattern(b"\x00/odd}name$[x]");
+        static_path(b"/assets/../../etc/passwd");
+        static_path(b"//etc/passwd");
+    }
+}
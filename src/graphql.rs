@@ -0,0 +1,399 @@
+//! GraphQL operations of proxied requests.
+//!
+//! With a `graphql` section, JSON POST bodies sent to its `paths` are read before routing (see
+//! `json_body`) and the operation is taken from them: the name from `operationName` or the
+//! document, the type from the selected operation definition. The operation is cached in the
+//! request extensions for `GraphQL` predicates and rate limit rules, and labels the
+//! per-operation metrics. The body itself is forwarded unchanged.
+
+use crate::config::{GraphQlConfig, GraphQlOperationMatchConfig};
+use crate::error::ProxyError;
+use crate::json_body::JsonBody;
+use hyper::http::Extensions;
+use hyper::{HeaderMap, Method, StatusCode};
+use log::{info, warn};
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+const OTHER_OPERATIONS: &str = "other";
+
+static GRAPHQL: OnceLock<GraphQl> = OnceLock::new();
+
+/// Turns on operation extraction for requests to the configured paths.
+pub fn init(config: &GraphQlConfig) -> Result<(), ProxyError> {
+    if config.paths.iter().any(|path| !path.starts_with('/')) {
+        return Err(ProxyError::Config("graphql.paths must start with '/'".to_string()));
+    }
+    if config.max_body_bytes == 0 {
+        return Err(ProxyError::Config("graphql.max_body_bytes must be greater than zero".to_string()));
+    }
+    info!(
+        "GraphQL: reading operations of requests to {:?} (bodies up to {} bytes)",
+        config.paths, config.max_body_bytes
+    );
+    let graphql = GraphQl {
+        paths: config.paths.clone(),
+        max_body_bytes: config.max_body_bytes,
+        max_operation_labels: config.max_operation_labels,
+        labelled: Mutex::new(HashSet::new()),
+    };
+    if GRAPHQL.set(graphql).is_err() {
+        warn!("GraphQL already initialized; keeping the first configuration");
+    }
+    Ok(())
+}
+
+/// The body size to read for a request that looks like a GraphQL call, if it does.
+pub fn body_limit(method: &Method, path: &str, headers: &HeaderMap) -> Option<usize> {
+    let graphql = GRAPHQL.get()?;
+    (method == Method::POST
+        && graphql.paths.iter().any(|p| p == path)
+        && crate::json_body::wants_body(headers, graphql.max_body_bytes))
+    .then_some(graphql.max_body_bytes)
+}
+
+/// Derives the operation from a body cached by `JsonBody::cache` and stores it next to it.
+pub fn annotate(extensions: &mut Extensions) {
+    let operation = extensions
+        .get::<JsonBody>()
+        .and_then(JsonBody::value)
+        .and_then(Operation::from_request);
+    if let Some(operation) = operation {
+        extensions.insert(operation);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperationType {
+    Query,
+    Mutation,
+    Subscription,
+}
+
+impl OperationType {
+    fn parse(keyword: &str) -> Option<Self> {
+        match keyword {
+            "query" => Some(Self::Query),
+            "mutation" => Some(Self::Mutation),
+            "subscription" => Some(Self::Subscription),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Query => "query",
+            Self::Mutation => "mutation",
+            Self::Subscription => "subscription",
+        }
+    }
+}
+
+/// The operation a GraphQL request executes. The type is unknown when the request carries no
+/// document (persisted queries) or names an operation the document does not define.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Operation {
+    pub name: Option<String>,
+    pub kind: Option<OperationType>,
+}
+
+impl Operation {
+    fn from_request(body: &Value) -> Option<Self> {
+        let requested = body.get("operationName").and_then(Value::as_str).filter(|n| !n.is_empty());
+        let document = body.get("query").and_then(Value::as_str);
+        if requested.is_none() && document.is_none() {
+            return None;
+        }
+        let definitions = document.map(definitions).unwrap_or_default();
+        let selected = match requested {
+            Some(name) => definitions.iter().find(|(_, n)| n.as_deref() == Some(name)),
+            // Without a name, the document must hold exactly one operation
+            None if definitions.len() == 1 => definitions.first(),
+            None => None,
+        };
+        Some(Self {
+            name: requested.map(str::to_string).or_else(|| selected.and_then(|(_, n)| n.clone())),
+            kind: selected.map(|(kind, _)| *kind),
+        })
+    }
+}
+
+/// Operation definitions of a document, in order. Only the top level is read: fragments,
+/// variables, directives and selection sets are skipped.
+fn definitions(document: &str) -> Vec<(OperationType, Option<String>)> {
+    let mut found = Vec::new();
+    let mut depth = 0usize;
+    let mut parens = 0usize;
+    let mut at_definition = true;
+    let mut pending: Option<OperationType> = None;
+    let mut name: Option<String> = None;
+    let mut name_next = false;
+    let mut chars = document.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '#' => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            }
+            '"' => skip_string(document, start, &mut chars),
+            // Braces inside arguments are input objects, not selection sets
+            '{' | '}' if parens > 0 => {}
+            '{' => {
+                if depth == 0 {
+                    if at_definition {
+                        found.push((OperationType::Query, None));
+                    } else if let Some(kind) = pending.take() {
+                        found.push((kind, name.take()));
+                    }
+                }
+                depth += 1;
+                name_next = false;
+            }
+            '}' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    at_definition = true;
+                    pending = None;
+                    name = None;
+                }
+            }
+            '(' => {
+                parens += 1;
+                name_next = false;
+            }
+            ')' => parens = parens.saturating_sub(1),
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|&(_, c)| c.is_ascii_alphanumeric() || c == '_') {
+                    end = i + c.len_utf8();
+                }
+                let word = &document[start..end];
+                if depth == 0 && parens == 0 {
+                    if at_definition {
+                        at_definition = false;
+                        pending = OperationType::parse(word);
+                        name_next = pending.is_some();
+                    } else if name_next {
+                        name = Some(word.to_string());
+                        name_next = false;
+                    }
+                }
+            }
+            c if c.is_whitespace() || c == ',' => {}
+            _ => name_next = false,
+        }
+    }
+    found
+}
+
+/// Skips a string or block string whose opening quote is at `start`.
+fn skip_string(
+    document: &str,
+    start: usize,
+    chars: &mut std::iter::Peekable<std::str::CharIndices<'_>>,
+) {
+    if document[start..].starts_with("\"\"\"") {
+        chars.next();
+        chars.next();
+        let mut quotes = 0;
+        for (_, c) in chars.by_ref() {
+            quotes = if c == '"' { quotes + 1 } else { 0 };
+            if quotes == 3 {
+                return;
+            }
+        }
+        return;
+    }
+    let mut escaped = false;
+    for (_, c) in chars.by_ref() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return,
+            _ => escaped = false,
+        }
+    }
+}
+
+/// Picks out operations by name and type, as used by predicates and rate limit rules.
+#[derive(Clone, Debug)]
+pub struct OperationMatcher {
+    names: HashSet<String>,
+    kinds: Vec<OperationType>,
+}
+
+impl OperationMatcher {
+    pub fn new(config: &GraphQlOperationMatchConfig) -> Result<Self, ProxyError> {
+        let kinds = config
+            .operation_types
+            .iter()
+            .map(|kind| {
+                OperationType::parse(&kind.to_ascii_lowercase()).ok_or_else(|| {
+                    ProxyError::Config(format!("Unknown GraphQL operation type '{}'", kind))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { names: config.operation_names.iter().cloned().collect(), kinds })
+    }
+
+    /// Whether the request's cached operation qualifies; requests without one never do.
+    pub fn matches(&self, extensions: &Extensions) -> bool {
+        extensions.get::<Operation>().is_some_and(|operation| self.matches_operation(operation))
+    }
+
+    pub fn matches_operation(&self, operation: &Operation) -> bool {
+        let named = self.names.is_empty()
+            || operation.name.as_ref().is_some_and(|name| self.names.contains(name));
+        let typed = self.kinds.is_empty() || operation.kind.is_some_and(|kind| self.kinds.contains(&kind));
+        named && typed
+    }
+}
+
+struct GraphQl {
+    paths: Vec<String>,
+    max_body_bytes: usize,
+    max_operation_labels: usize,
+    labelled: Mutex<HashSet<String>>,
+}
+
+impl GraphQl {
+    /// The metric label for an operation name, capping how many distinct names get one.
+    fn label(&self, name: Option<&str>) -> String {
+        let Some(name) = name else {
+            return "anonymous".to_string();
+        };
+        let mut labelled = self.labelled.lock().unwrap_or_else(|e| e.into_inner());
+        if labelled.contains(name) {
+            return name.to_string();
+        }
+        if labelled.len() < self.max_operation_labels {
+            labelled.insert(name.to_string());
+            return name.to_string();
+        }
+        OTHER_OPERATIONS.to_string()
+    }
+}
+
+/// Counts a finished GraphQL request of `route` under its operation.
+pub fn record(route: &str, operation: &Operation, status: StatusCode, elapsed: Duration) {
+    let Some(graphql) = GRAPHQL.get() else {
+        return;
+    };
+    let name = graphql.label(operation.name.as_deref());
+    let kind = operation.kind.map(OperationType::as_str).unwrap_or("unknown");
+    let telemetry = graphql_telemetry();
+    telemetry
+        .operations
+        .with_label_values(&[route, kind, &name, status.as_str()])
+        .inc();
+    telemetry
+        .duration
+        .with_label_values(&[route, kind, &name])
+        .observe(elapsed.as_secs_f64());
+}
+
+struct GraphQlTelemetry {
+    operations: IntCounterVec,
+    duration: HistogramVec,
+    registered: AtomicBool,
+}
+
+impl GraphQlTelemetry {
+    fn new() -> Self {
+        let operations = Opts::new(
+            "graphql_operations_total",
+            "GraphQL requests by route, operation type, operation name and response status",
+        ).namespace("bifrost");
+        let duration = HistogramOpts::new(
+            "graphql_operation_duration_seconds",
+            "Time to answer GraphQL requests by route, operation type and operation name",
+        ).namespace("bifrost");
+        Self {
+            operations: IntCounterVec::new(operations, &["route", "operation_type", "operation_name", "status"])
+                .expect("graphql_operations_total metric"),
+            duration: HistogramVec::new(duration, &["route", "operation_type", "operation_name"])
+                .expect("graphql_operation_duration_seconds metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        let collectors: [Box<dyn prometheus::core::Collector>; 2] =
+            [Box::new(self.operations.clone()), Box::new(self.duration.clone())];
+        for collector in collectors {
+            if let Err(err) = registry.register(collector) {
+                warn!("Failed to register GraphQL metrics: {}", err);
+                return;
+            }
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn graphql_telemetry() -> &'static GraphQlTelemetry {
+    static TELEMETRY: OnceLock<GraphQlTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(GraphQlTelemetry::new)
+}
+
+/// Registers the per-operation GraphQL metrics with the shared Prometheus registry.
+pub fn register_graphql_metrics(registry: &Registry) {
+    graphql_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn operations_come_from_operation_name_and_document() {
+        let operation = |body: Value| Operation::from_request(&body);
+        let named = |name: &str, kind| Some(Operation { name: Some(name.to_string()), kind: Some(kind) });
+
+        assert_eq!(operation(json!({ "query": "{ orders { id } }" })), Some(Operation { name: None, kind: Some(OperationType::Query) }));
+        assert_eq!(
+            operation(json!({ "query": "mutation AddOrder($input: OrderInput = {note: \"a { b\"}) @auth(role: \"x\") { addOrder(input: $input) { id } }" })),
+            named("AddOrder", OperationType::Mutation)
+        );
+        let document = "# comment { \nfragment F on Order { id }\nquery List { orders { ...F } }\nsubscription Watch { \"\"\"doc } \"\"\" orderAdded { id } }";
+        assert_eq!(operation(json!({ "query": document, "operationName": "Watch" })), named("Watch", OperationType::Subscription));
+        assert_eq!(operation(json!({ "query": document, "operationName": "List" })), named("List", OperationType::Query));
+        // Several operations and no name: the server will refuse it, the type stays unknown
+        assert_eq!(operation(json!({ "query": document })), Some(Operation { name: None, kind: None }));
+        // Persisted queries carry a name only
+        assert_eq!(
+            operation(json!({ "operationName": "List", "extensions": { "persistedQuery": {} } })),
+            Some(Operation { name: Some("List".to_string()), kind: None })
+        );
+        assert_eq!(operation(json!({ "event": "order.created" })), None);
+
+        let mutations = OperationMatcher::new(&GraphQlOperationMatchConfig {
+            operation_names: Vec::new(),
+            operation_types: vec!["Mutation".to_string()],
+        })
+        .unwrap();
+        assert!(mutations.matches_operation(&named("AddOrder", OperationType::Mutation).unwrap()));
+        assert!(!mutations.matches_operation(&Operation { name: Some("AddOrder".to_string()), kind: None }));
+        assert!(OperationMatcher::new(&GraphQlOperationMatchConfig {
+            operation_names: Vec::new(),
+            operation_types: vec!["fragment".to_string()],
+        })
+        .is_err());
+
+        let graphql = GraphQl {
+            paths: Vec::new(),
+            max_body_bytes: 1024,
+            max_operation_labels: 1,
+            labelled: Mutex::new(HashSet::new()),
+        };
+        assert_eq!(graphql.label(Some("List")), "List");
+        assert_eq!(graphql.label(Some("Watch")), OTHER_OPERATIONS);
+        assert_eq!(graphql.label(Some("List")), "List");
+        assert_eq!(graphql.label(None), "anonymous");
+    }
+}
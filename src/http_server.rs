@@ -0,0 +1,172 @@
+//! Serving accepted connections with HTTP/1.1, and with HTTP/2 where `http2` is configured.
+//!
+//! HTTPS listeners offer `h2` through ALPN and serve each connection with the protocol the client
+//! picked. Plain listeners with `h2c` tell the two apart by the HTTP/2 connection preface. HTTP/2
+//! requests carry their authority in the URI instead of a `Host` header, so one is added before
+//! the handlers see the request; they route and log on it.
+
+use crate::config::Http2Config;
+use crate::request_limits;
+use bytes::Bytes;
+use http_body_util::Either;
+use hyper::body::{Body, Incoming};
+use hyper::header::{HOST, HeaderValue};
+use hyper::server::conn::http1;
+use hyper::service::{Service, service_fn};
+use hyper::{Request, Response, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use rustls::ServerConfig;
+use std::error::Error as StdError;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// ALPN protocol id of HTTP/2 over TLS
+pub const ALPN_H2: &[u8] = b"h2";
+const ALPN_HTTP11: &[u8] = b"http/1.1";
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// Protocol a connection is served with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionProtocol {
+    Http1,
+    Http2,
+    /// Either one, told apart by the HTTP/2 connection preface
+    Detect,
+}
+
+impl ConnectionProtocol {
+    /// For a plain connection: HTTP/1.1, or either one with `h2c`
+    pub fn plain(http2: Option<&Http2Config>) -> Self {
+        if http2.is_some_and(|http2| http2.h2c) {
+            ConnectionProtocol::Detect
+        } else {
+            ConnectionProtocol::Http1
+        }
+    }
+
+    /// For a TLS connection, from the protocol ALPN settled on
+    pub fn negotiated(alpn: Option<&[u8]>) -> Self {
+        if alpn == Some(ALPN_H2) {
+            ConnectionProtocol::Http2
+        } else {
+            ConnectionProtocol::Http1
+        }
+    }
+}
+
+/// Sets the protocols an HTTPS listener offers through ALPN. Without `http2` nothing is offered,
+/// and clients fall back to HTTP/1.1.
+pub fn set_alpn(config: &mut ServerConfig, http2: Option<&Http2Config>) {
+    if http2.is_some_and(|http2| http2.alpn) {
+        config.alpn_protocols = vec![ALPN_H2.to_vec(), ALPN_HTTP11.to_vec()];
+    }
+}
+
+/// Marks a request whose HTTP/1 header names were recorded as the client spelled them. hyper's
+/// client writes that spelling whenever the record travels with a request.
+#[derive(Debug, Clone, Copy)]
+pub struct HeaderCaseRecorded;
+
+/// Serves one connection until the client closes it. Upgrades (WebSocket, CONNECT) are supported
+/// on both protocols. With `preserve_header_case`, HTTP/1 requests keep a record of how the
+/// client spelled each header name, for routes that forward that spelling.
+pub async fn serve<I, S, B>(
+    io: I,
+    service: S,
+    protocol: ConnectionProtocol,
+    http2: Option<&Http2Config>,
+    preserve_header_case: bool,
+) -> Result<(), BoxError>
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Service<Request<Incoming>, Response = Response<B>> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<BoxError>,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    let limits = request_limits::limits();
+    let service = service_fn(move |mut req: Request<Incoming>| {
+        add_host_header(&mut req);
+        if preserve_header_case && req.version() != Version::HTTP_2 {
+            req.extensions_mut().insert(HeaderCaseRecorded);
+        }
+        let call = match limits.map(|limits| limits.check(&req)) {
+            Some(Err(exceeded)) => Err(exceeded),
+            _ => Ok(service.call(req)),
+        };
+        async move {
+            match call {
+                Ok(response) => response.await.map(|response| response.map(Either::Left)),
+                Err(exceeded) => Ok(exceeded.response().map(Either::Right)),
+            }
+        }
+    });
+    let io = TokioIo::new(io);
+
+    // auto::Builder ignores http1_only() once upgrades are enabled, so HTTP/1.1-only connections
+    // get the plain HTTP/1 builder; an ALPN-negotiated h2 connection starts with the preface.
+    if protocol == ConnectionProtocol::Http1 {
+        let mut builder = http1::Builder::new();
+        builder.keep_alive(true).preserve_header_case(preserve_header_case);
+        if let Some(limits) = limits {
+            builder.max_headers(limits.max_headers);
+        }
+        return Ok(builder.serve_connection(io, service).with_upgrades().await?);
+    }
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder.http1().keep_alive(true).preserve_header_case(preserve_header_case);
+    if let Some(limits) = limits {
+        builder.http1().max_headers(limits.max_headers);
+    }
+    if let Some(http2) = http2 {
+        let mut h2 = builder.http2();
+        h2.max_concurrent_streams(http2.max_concurrent_streams);
+        if let Some(secs) = http2.keep_alive_interval_secs {
+            h2.timer(TokioTimer::new()).keep_alive_interval(Duration::from_secs(secs));
+        }
+    }
+    builder.serve_connection_with_upgrades(io, service).await
+}
+
+/// Copies the `:authority` of an HTTP/2 request into a `Host` header, as HTTP/1.1 clients send it.
+fn add_host_header<B>(req: &mut Request<B>) {
+    if req.version() != Version::HTTP_2 || req.headers().contains_key(HOST) {
+        return;
+    }
+    if let Some(value) = req.uri().authority().and_then(|authority| HeaderValue::from_str(authority.as_str()).ok()) {
+        req.headers_mut().insert(HOST, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http2_requests_get_a_host_header_from_their_authority() {
+        let mut req = Request::get("https://shop.example.com:8443/cart").version(Version::HTTP_2).body(()).unwrap();
+        add_host_header(&mut req);
+        assert_eq!(req.headers()[HOST], "shop.example.com:8443");
+
+        let mut req = Request::get("https://shop.example.com/cart").body(()).unwrap();
+        add_host_header(&mut req);
+        assert!(!req.headers().contains_key(HOST));
+    }
+
+    #[test]
+    fn alpn_and_plain_protocols_follow_the_config() {
+        let http2 = Http2Config::default();
+        assert_eq!(ConnectionProtocol::plain(None), ConnectionProtocol::Http1);
+        assert_eq!(ConnectionProtocol::plain(Some(&http2)), ConnectionProtocol::Http1);
+        assert_eq!(
+            ConnectionProtocol::plain(Some(&Http2Config { h2c: true, ..Http2Config::default() })),
+            ConnectionProtocol::Detect
+        );
+        assert_eq!(ConnectionProtocol::negotiated(Some(b"h2")), ConnectionProtocol::Http2);
+        assert_eq!(ConnectionProtocol::negotiated(Some(b"http/1.1")), ConnectionProtocol::Http1);
+        assert_eq!(ConnectionProtocol::negotiated(None), ConnectionProtocol::Http1);
+    }
+}
@@ -0,0 +1,269 @@
+//! Interactive questionnaire behind `bifrost-bridge init`.
+//!
+//! Asks for the handful of settings a first deployment needs and builds a [`Config`] from the
+//! answers. Invalid answers are explained and asked again; the caller validates and writes the
+//! result.
+
+use crate::config::{
+    ApiKeyEntryConfig, ApiKeysConfig, Config, ProxyMode, ReverseProxyRouteConfig, RoutePredicateConfig,
+    StaticFileConfig,
+};
+use crate::error::ProxyError;
+use crate::secrets::Secret;
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+
+pub struct InitWizard<R, W> {
+    input: R,
+    output: W,
+}
+
+/// What the proxy is set up to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Forward,
+    Reverse,
+    Static,
+}
+
+impl<R: BufRead, W: Write> InitWizard<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    /// Asks every question and returns the resulting configuration.
+    pub fn run(&mut self) -> Result<Config, ProxyError> {
+        writeln!(self.output, "Bifrost Bridge setup. Press Enter to accept the [default].")?;
+
+        let role = self.ask_parsed("Mode (forward, reverse, static)", Some("reverse"), |answer| {
+            match answer.to_ascii_lowercase().as_str() {
+                "forward" => Ok(Role::Forward),
+                "reverse" => Ok(Role::Reverse),
+                "static" => Ok(Role::Static),
+                _ => Err("answer forward, reverse or static".to_string()),
+            }
+        })?;
+        let listen_addr: SocketAddr = self.ask_parsed("Listen address", Some("127.0.0.1:8080"), |answer| {
+            answer.parse().map_err(|_| "expected host:port, e.g. 0.0.0.0:8080".to_string())
+        })?;
+
+        let mut config = Config {
+            mode: if role == Role::Forward { ProxyMode::Forward } else { ProxyMode::Reverse },
+            listen_addr,
+            ..Config::default()
+        };
+
+        match role {
+            Role::Forward => {
+                if self.ask_yes_no("Require proxy authentication (Basic)?", false)? {
+                    config.proxy_username = Some(self.ask_parsed("Username", None, non_empty)?);
+                    config.proxy_password = Some(Secret::new(self.ask_parsed("Password", None, non_empty)?));
+                }
+            }
+            Role::Reverse => {
+                let target = self.ask_parsed("Backend URL", Some("http://127.0.0.1:3000"), |answer| {
+                    match url::Url::parse(answer) {
+                        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(answer.to_string()),
+                        _ => Err("expected an http:// or https:// URL".to_string()),
+                    }
+                })?;
+                let require_api_key = self.ask_yes_no("Require an API key from clients?", false)?;
+                config.reverse_proxy_routes = vec![default_route(target, require_api_key)];
+                if require_api_key {
+                    let key = generate_api_key();
+                    writeln!(
+                        self.output,
+                        "Clients must send this key in the X-API-Key header: {}",
+                        key
+                    )?;
+                    config.api_keys = Some(ApiKeysConfig {
+                        keys: vec![ApiKeyEntryConfig {
+                            id: "default".to_string(),
+                            key: Secret::new(key),
+                            tier: None,
+                            routes: Vec::new(),
+                        }],
+                        ..ApiKeysConfig::default()
+                    });
+                }
+            }
+            Role::Static => {
+                let root_dir = self.ask_parsed("Directory to serve", Some("./public"), non_empty)?;
+                if !Path::new(&root_dir).is_dir() {
+                    writeln!(self.output, "Note: {} does not exist yet.", root_dir)?;
+                }
+                let spa = self.ask_yes_no("Single-page app (serve index.html for unknown paths)?", false)?;
+                config.static_files = Some(StaticFileConfig::single(root_dir, spa));
+            }
+        }
+
+        if self.ask_yes_no("Enable HTTPS?", false)? {
+            config.certificate = Some(self.ask_file("Certificate file (PEM)")?);
+            config.private_key = Some(self.ask_file("Private key file (PKCS#8 PEM)")?);
+        }
+
+        Ok(config)
+    }
+
+    fn ask(&mut self, question: &str, default: Option<&str>) -> Result<String, ProxyError> {
+        match default {
+            Some(default) => write!(self.output, "{} [{}]: ", question, default)?,
+            None => write!(self.output, "{}: ", question)?,
+        }
+        self.output.flush()?;
+
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Err(ProxyError::Config("Setup aborted: no more input".to_string()));
+        }
+        let answer = line.trim();
+        Ok(match default {
+            Some(default) if answer.is_empty() => default.to_string(),
+            _ => answer.to_string(),
+        })
+    }
+
+    /// Repeats the question until `parse` accepts the answer.
+    fn ask_parsed<T>(
+        &mut self,
+        question: &str,
+        default: Option<&str>,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> Result<T, ProxyError> {
+        loop {
+            let answer = self.ask(question, default)?;
+            match parse(&answer) {
+                Ok(value) => return Ok(value),
+                Err(hint) => writeln!(self.output, "Invalid answer: {}", hint)?,
+            }
+        }
+    }
+
+    fn ask_yes_no(&mut self, question: &str, default: bool) -> Result<bool, ProxyError> {
+        let default = if default { "y" } else { "n" };
+        self.ask_parsed(question, Some(default), |answer| match answer.to_ascii_lowercase().as_str() {
+            "y" | "yes" => Ok(true),
+            "n" | "no" => Ok(false),
+            _ => Err("answer y or n".to_string()),
+        })
+    }
+
+    fn ask_file(&mut self, question: &str) -> Result<String, ProxyError> {
+        let path = self.ask_parsed(question, None, non_empty)?;
+        if !Path::new(&path).is_file() {
+            writeln!(self.output, "Note: {} does not exist yet.", path)?;
+        }
+        Ok(path)
+    }
+}
+
+fn non_empty(answer: &str) -> Result<String, String> {
+    if answer.is_empty() {
+        Err("a value is required".to_string())
+    } else {
+        Ok(answer.to_string())
+    }
+}
+
+fn default_route(target: String, require_api_key: bool) -> ReverseProxyRouteConfig {
+    ReverseProxyRouteConfig {
+        id: "default".to_string(),
+        target: Some(target),
+        targets: Vec::new(),
+        load_balancing: None,
+        sticky: None,
+        header_override: None,
+        retry_policy: None,
+        reverse_proxy_config: None,
+        strip_path_prefix: None,
+        preserve_host_header: None,
+        priority: None,
+        require_api_key,
+        hmac_verification: None,
+        fan_out: None,
+        aggregate: None,
+        replay: None,
+        static_response: None,
+        allowed_methods: Vec::new(),
+        method_override: None,
+        allowed_upgrades: Vec::new(),
+        path_param_headers: HashMap::new(),
+        limits: None,
+        upstream_tls: None,
+        metadata: HashMap::new(),
+        predicates: vec![RoutePredicateConfig::Path {
+            patterns: vec!["/**".to_string()],
+            match_trailing_slash: true,
+        }],
+    }
+}
+
+fn generate_api_key() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+/// The configuration as JSON without the `null` entries of unset optional sections, which keeps
+/// the generated file short.
+pub fn to_compact_json(config: &Config) -> Result<String, ProxyError> {
+    let mut value = serde_json::to_value(config)
+        .map_err(|e| ProxyError::Config(format!("Failed to serialize configuration: {}", e)))?;
+    strip_nulls(&mut value);
+    serde_json::to_string_pretty(&value)
+        .map_err(|e| ProxyError::Config(format!("Failed to serialize configuration: {}", e)))
+}
+
+fn strip_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, item| !item.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(answers: &str) -> (Result<Config, ProxyError>, String) {
+        let mut output = Vec::new();
+        let result = InitWizard::new(answers.as_bytes(), &mut output).run();
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn builds_configs_from_answers_and_reasks_invalid_ones() {
+        let (config, transcript) = run("reverse\nlocalhost\n0.0.0.0:9000\nftp://x\nhttp://api:3000\ny\nn\n");
+        let config = config.unwrap();
+        assert_eq!(transcript.matches("Invalid answer").count(), 2);
+        assert_eq!(config.listen_addr, "0.0.0.0:9000".parse().unwrap());
+        assert_eq!(config.reverse_proxy_routes[0].target.as_deref(), Some("http://api:3000"));
+        assert!(config.reverse_proxy_routes[0].require_api_key);
+        let key = &config.api_keys.as_ref().unwrap().keys[0].key;
+        assert!(transcript.contains(key.expose().as_str()));
+
+        let (config, _) = run("forward\n\ny\nalice\ns3cret\ny\ncert.pem\nkey.pem\n");
+        let config = config.unwrap();
+        assert!(matches!(config.mode, ProxyMode::Forward));
+        assert_eq!(config.proxy_username.as_deref(), Some("alice"));
+        assert_eq!(config.certificate.as_deref(), Some("cert.pem"));
+
+        let json = to_compact_json(&config).unwrap();
+        assert!(!json.contains("null"));
+        let reparsed: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(reparsed.private_key.as_deref(), Some("key.pem"));
+
+        let (result, _) = run("static\n");
+        assert!(result.is_err(), "running out of input aborts the wizard");
+    }
+}
@@ -0,0 +1,167 @@
+//! Routing on fields of small JSON request bodies.
+//!
+//! A `JsonBody` predicate needs the body before a route is chosen, so the proxy reads it into
+//! memory first. Only bodies that can match are read: `application/json` (or `+json`) content
+//! with a declared Content-Length no larger than the biggest `max_body_bytes` of any such
+//! predicate. Chunked bodies stream through unread and never match. The body is parsed once and
+//! cached in the request extensions, where every predicate and the forwarding step find it.
+
+use crate::error::ProxyError;
+use hyper::HeaderMap;
+use hyper::body::Bytes;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::http::Extensions;
+use regex::Regex;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// A request body read before routing, with its parsed form (`None` when it is not valid JSON).
+#[derive(Clone)]
+pub struct JsonBody {
+    pub raw: Bytes,
+    value: Option<Arc<Value>>,
+}
+
+impl JsonBody {
+    /// Parses `raw` and caches it for the predicates of this request.
+    pub fn cache(extensions: &mut Extensions, raw: Bytes) {
+        let value = serde_json::from_slice(&raw).ok().map(Arc::new);
+        extensions.insert(JsonBody { raw, value });
+    }
+
+    /// The parsed body, unless it is not valid JSON.
+    pub fn value(&self) -> Option<&Value> {
+        self.value.as_deref()
+    }
+}
+
+/// Whether a request carries a JSON body small enough to read for routing.
+pub fn wants_body(headers: &HeaderMap, limit: usize) -> bool {
+    let is_json = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or("").trim().to_ascii_lowercase())
+        .is_some_and(|mime| mime == "application/json" || mime.ends_with("+json"));
+    let length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    is_json && length.is_some_and(|length| length > 0 && length <= limit)
+}
+
+/// Matches one field of the body: by exact value, by regex, or (with neither) by presence.
+#[derive(Clone)]
+pub struct JsonBodyMatcher {
+    path: Vec<String>,
+    value: Option<String>,
+    regex: Option<Regex>,
+    max_body_bytes: usize,
+}
+
+impl JsonBodyMatcher {
+    pub fn new(
+        field: &str,
+        value: Option<String>,
+        regex: Option<String>,
+        max_body_bytes: usize,
+    ) -> Result<Self, ProxyError> {
+        if value.is_some() && regex.is_some() {
+            return Err(ProxyError::Config(
+                "JsonBody predicate cannot specify both value and regex".to_string(),
+            ));
+        }
+        if max_body_bytes == 0 {
+            return Err(ProxyError::Config(
+                "JsonBody predicate max_body_bytes must be greater than zero".to_string(),
+            ));
+        }
+        let trimmed = field.strip_prefix("$.").unwrap_or(field);
+        let path: Vec<String> = trimmed.split('.').map(str::to_string).collect();
+        if path.iter().any(String::is_empty) {
+            return Err(ProxyError::Config(format!("Invalid JsonBody field path: {}", field)));
+        }
+        let regex = if let Some(r) = regex {
+            Some(Regex::new(&r).map_err(|e| ProxyError::Config(format!("Invalid JsonBody regex: {}", e)))?)
+        } else {
+            None
+        };
+        Ok(Self { path, value, regex, max_body_bytes })
+    }
+
+    pub fn max_body_bytes(&self) -> usize {
+        self.max_body_bytes
+    }
+
+    pub fn matches(&self, extensions: &Extensions) -> bool {
+        let Some(body) = extensions.get::<JsonBody>() else {
+            return false;
+        };
+        if body.raw.len() > self.max_body_bytes {
+            return false;
+        }
+        let Some(field) = body.value.as_deref().and_then(|value| self.lookup(value)) else {
+            return false;
+        };
+        // Strings compare without their quotes; numbers, booleans and objects as JSON text
+        let actual = match field {
+            Value::Null => return false,
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        match (&self.value, &self.regex) {
+            (Some(expected), _) => &actual == expected,
+            (None, Some(re)) => re.is_match(&actual),
+            (None, None) => true,
+        }
+    }
+
+    /// Follows the dotted path; numeric segments also index arrays.
+    fn lookup<'a>(&self, root: &'a Value) -> Option<&'a Value> {
+        self.path.iter().try_fold(root, |node, segment| match node {
+            Value::Object(map) => map.get(segment),
+            Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    #[test]
+    fn matches_fields_of_the_cached_body() {
+        let mut extensions = Extensions::new();
+        let operation = JsonBodyMatcher::new("operationName", Some("GetOrders".to_string()), None, 1024).unwrap();
+        assert!(!operation.matches(&extensions));
+
+        JsonBody::cache(
+            &mut extensions,
+            Bytes::from_static(br#"{"operationName":"GetOrders","events":[{"type":"order.created","v":2}]}"#),
+        );
+        assert!(operation.matches(&extensions));
+        assert!(JsonBodyMatcher::new("$.events.0.type", None, Some("^order\\.".to_string()), 1024)
+            .unwrap()
+            .matches(&extensions));
+        assert!(JsonBodyMatcher::new("events.0.v", Some("2".to_string()), None, 1024).unwrap().matches(&extensions));
+        assert!(!JsonBodyMatcher::new("events.1", None, None, 1024).unwrap().matches(&extensions));
+        // Bodies above the predicate's own cap never match
+        assert!(!JsonBodyMatcher::new("operationName", None, None, 16).unwrap().matches(&extensions));
+
+        JsonBody::cache(&mut extensions, Bytes::from_static(b"{not json"));
+        assert!(!operation.matches(&extensions));
+
+        assert!(JsonBodyMatcher::new("a..b", None, None, 1024).is_err());
+        assert!(JsonBodyMatcher::new("a", Some("x".to_string()), Some("x".to_string()), 1024).is_err());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/cloudevents+json; charset=utf-8"));
+        assert!(!wants_body(&headers, 1024));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("512"));
+        assert!(wants_body(&headers, 1024));
+        assert!(!wants_body(&headers, 256));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        assert!(!wants_body(&headers, 1024));
+    }
+}
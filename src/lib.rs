@@ -1,17 +1,84 @@
+pub mod access_log;
+pub mod admin_api;
+pub mod aggregate;
+pub mod api_keys;
+pub mod auth_lockout;
+pub mod auto_ban;
+pub mod ban_list;
+pub mod bench;
+pub mod byte_quota;
+pub mod byte_range;
+pub mod cert_expiry;
+pub mod client_auth;
+pub mod cluster;
+pub mod conditional;
 pub mod config;
+pub mod connect_policy;
+pub mod content_hook;
+pub mod digest_auth;
+pub mod dns;
+pub mod download_scan;
+pub mod egress;
 pub mod forward_proxy;
+pub mod forwarded;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod json_body;
+pub mod response_body;
+pub mod ftp_gateway;
+pub mod graphql;
+pub mod http_server;
+pub mod negotiate_auth;
+pub mod notifications;
+pub mod ntlm;
 pub mod reverse_proxy;
+pub mod request_signing;
 pub mod proxy;
 pub mod error;
+pub mod fan_out;
+pub mod init_wizard;
 pub mod static_files;
+pub mod static_response;
+pub mod listener;
 pub mod logging;
 pub mod common;
+pub mod config_schema;
 pub mod config_validation;
 pub mod memory_profiler;
+pub mod metrics_push;
 pub mod error_recovery;
 pub mod monitoring;
+pub mod outlier;
+pub mod path_params;
+pub mod precompressed;
+pub mod preflight;
+pub mod privacy;
+pub mod protocol_mux;
 pub mod rate_limit;
+pub mod redact;
+pub mod reload;
+pub mod replay;
+pub mod route_index;
+pub mod route_metadata;
 pub mod secrets;
+pub mod secret_providers;
+pub mod security_log;
+pub mod selftest;
+pub mod tunnel_limits;
+pub mod tls_session;
+pub mod shutdown;
+pub mod method_policy;
+pub mod path_normalization;
+pub mod request_limits;
+pub mod tarpit;
+pub mod tenants;
+pub mod stale_connection;
+pub mod upstream_error;
+pub mod upstream_tls;
+pub mod resource_monitor;
+pub mod websocket_tunnel;
+pub mod wire_syntax;
+pub mod test_support;
 
 pub use config::{Config, ProxyMode};
 pub use error::ProxyError;
@@ -0,0 +1,180 @@
+//! Listener socket construction and per-connection TCP options.
+//!
+//! Options that only make sense on the listening socket (backlog, TCP Fast Open) are applied in
+//! [`bind`]; options that belong to each connection are applied to every accepted stream by
+//! [`tune_stream`], since not every platform copies them from the listener. With
+//! `protocol_mux`, the listener only yields the connections mapped to the local handler.
+
+use crate::config::ListenerConfig;
+use crate::protocol_mux::ProtocolMux;
+use log::debug;
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+/// A bound listener; accepts directly, or takes what the protocol multiplexer hands over.
+pub struct Listener {
+    local_addr: SocketAddr,
+    accept: Accept,
+}
+
+enum Accept {
+    Direct(TcpListener),
+    Muxed {
+        local: Mutex<mpsc::Receiver<io::Result<(TcpStream, SocketAddr)>>>,
+        task: JoinHandle<()>,
+    },
+}
+
+impl Listener {
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        match &self.accept {
+            Accept::Direct(listener) => listener.accept().await,
+            Accept::Muxed { local, .. } => local
+                .lock()
+                .await
+                .recv()
+                .await
+                .unwrap_or_else(|| Err(io::Error::other("protocol multiplexer stopped"))),
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Accept::Muxed { task, .. } = &self.accept {
+            task.abort();
+        }
+    }
+}
+
+/// Creates a listening socket on `addr` with the configured options.
+pub fn bind(addr: SocketAddr, config: &ListenerConfig) -> io::Result<Listener> {
+    let listener = bind_socket(addr, config)?;
+    let local_addr = listener.local_addr()?;
+    let accept = match &config.protocol_mux {
+        Some(mux_config) => {
+            let mux = Arc::new(ProtocolMux::new(mux_config)?);
+            let (sender, receiver) = mpsc::channel(config.backlog.clamp(1, 4096) as usize);
+            let task = tokio::spawn(mux.run(listener, config.clone(), sender));
+            Accept::Muxed { local: Mutex::new(receiver), task }
+        }
+        None => Accept::Direct(listener),
+    };
+    Ok(Listener { local_addr, accept })
+}
+
+fn bind_socket(addr: SocketAddr, config: &ListenerConfig) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // Same as tokio's TcpListener::bind: allow quick restarts while old connections sit in TIME_WAIT
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    if let Some(size) = config.recv_buffer_bytes {
+        socket.set_recv_buffer_size(size)?;
+    }
+    if let Some(size) = config.send_buffer_bytes {
+        socket.set_send_buffer_size(size)?;
+    }
+    if let Some(queue) = config.tcp_fastopen_queue {
+        set_tcp_fastopen(&socket, queue)?;
+    }
+
+    socket.bind(&addr.into())?;
+    socket.listen(config.backlog.min(i32::MAX as u32) as i32)?;
+    socket.set_nonblocking(true)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Applies per-connection options to an accepted stream; failures are logged and ignored.
+pub fn tune_stream(stream: &TcpStream, config: &ListenerConfig) {
+    if let Err(e) = apply_stream_options(stream, config) {
+        debug!("Failed to apply TCP options to accepted connection: {}", e);
+    }
+}
+
+fn apply_stream_options(stream: &TcpStream, config: &ListenerConfig) -> io::Result<()> {
+    if config.tcp_nodelay {
+        stream.set_nodelay(true)?;
+    }
+    let socket = SockRef::from(stream);
+    if let Some(keepalive) = &config.keepalive {
+        let params = TcpKeepalive::new()
+            .with_time(Duration::from_secs(keepalive.time_secs))
+            .with_interval(Duration::from_secs(keepalive.interval_secs));
+        #[cfg(not(any(target_os = "windows", target_os = "openbsd")))]
+        let params = params.with_retries(keepalive.retries);
+        socket.set_tcp_keepalive(&params)?;
+    }
+    if let Some(size) = config.recv_buffer_bytes {
+        socket.set_recv_buffer_size(size)?;
+    }
+    if let Some(size) = config.send_buffer_bytes {
+        socket.set_send_buffer_size(size)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_tcp_fastopen(socket: &Socket, queue: u32) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let queue = queue.min(i32::MAX as u32) as libc::c_int;
+    // SAFETY: setsockopt reads `size_of::<c_int>()` bytes from a valid pointer.
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_FASTOPEN,
+            &queue as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_tcp_fastopen(_socket: &Socket, _queue: u32) -> io::Result<()> {
+    log::warn!("TCP Fast Open is only supported on Linux; ignoring tcp_fastopen_queue");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TcpKeepaliveConfig;
+
+    #[tokio::test]
+    async fn applies_listener_and_stream_options() {
+        let config = ListenerConfig {
+            tcp_nodelay: true,
+            keepalive: Some(TcpKeepaliveConfig { time_secs: 30, interval_secs: 5, retries: 3 }),
+            backlog: 64,
+            recv_buffer_bytes: Some(256 * 1024),
+            ..ListenerConfig::default()
+        };
+        let listener = bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = TcpStream::connect(addr).await.unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+        tune_stream(&accepted, &config);
+
+        assert!(accepted.nodelay().unwrap());
+        let socket = SockRef::from(&accepted);
+        assert!(socket.keepalive().unwrap());
+        #[cfg(target_os = "linux")]
+        assert_eq!(socket.tcp_keepalive_time().unwrap(), Duration::from_secs(30));
+        // The kernel may round or double the requested size, but never shrinks it
+        assert!(socket.recv_buffer_size().unwrap() >= 256 * 1024);
+        drop(client);
+    }
+}
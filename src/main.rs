@@ -1,14 +1,39 @@
-use clap::Parser;
-use log::{info, error};
+use clap::{Parser, Subcommand};
+use log::{info, error, warn};
 use bifrost_bridge::{
-    config::{Config, ProxyMode},
+    access_log,
+    admin_api::AdminApi,
+    bench::{self, BenchOptions, BenchProxy},
+    cert_expiry::CertificateExpiryMonitor,
+    config::{Config, ProxyMode, RateLimitingConfig, RelayAuthScheme, RelayProxyConfig, WebSocketConfig},
+    config::PreflightConfig,
+    config_schema::config_schema,
+    config_validation::validate_route_limits,
+    dns,
+    forwarded,
+    graphql,
+    init_wizard::{self, InitWizard},
     logging,
+    notifications,
+    path_normalization,
+    preflight::{self, Preflight, PreflightReport},
     proxy::ProxyFactory,
-    secrets::{config_has_encrypted_values, SecretManager},
+    reload::{ConfigLoader, ConfigReloader},
+    rate_limit,
+    request_limits,
+    resource_monitor::ResourceMonitor,
+    privacy,
+    secret_providers,
+    secrets::{config_has_encrypted_values, Secret, SecretManager},
+    security_log,
+    selftest,
+    shutdown,
 };
 use std::path::Path;
+use std::sync::Arc;
 use tokio::signal;
 use tokio::sync::oneshot;
+use tokio::time::Duration;
 use std::io::Read;
 
 const ENCRYPT_STDIN_PLACEHOLDER: &str = "__BIFROST_STDIN__";
@@ -29,7 +54,7 @@ struct Args {
     #[clap(short, long, value_name = "URL", help = "Target URL for reverse proxy (e.g., http://backend:3000)")]
     target: Option<String>,
 
-    #[clap(short, long, value_name = "FILE", help = "Configuration file path")]
+    #[clap(short, long, global = true, value_name = "FILE", help = "Configuration file path")]
     config: Option<String>,
 
     #[clap(long, value_name = "SECONDS", help = "Connection timeout in seconds")]
@@ -86,6 +111,24 @@ struct Args {
     #[clap(long, value_name = "PASSWORD", help = "Password for proxy authentication (Basic Auth)")]
     proxy_password: Option<String>,
 
+    #[clap(long, value_name = "RULE", help = "Rate limit rule, e.g. \"100/60s per-ip\" or \"20/1m /api/login POST\" (can be used multiple times)")]
+    rate_limit: Vec<String>,
+
+    #[clap(long, help = "Reject WebSocket upgrade requests")]
+    no_websocket: bool,
+
+    #[clap(long, value_name = "ORIGIN", help = "Origin allowed to open WebSocket connections (can be used multiple times; default: any)")]
+    ws_allowed_origin: Vec<String>,
+
+    #[clap(long, value_name = "URL", help = "Relay proxy that forward proxy requests are sent through")]
+    relay_proxy: Option<String>,
+
+    #[clap(long, value_name = "USER:PASS", help = "Credentials for the relay proxy")]
+    relay_proxy_auth: Option<String>,
+
+    #[clap(long, value_name = "DOMAIN", help = "Only relay requests for this domain pattern (can be used multiple times; default: all domains)")]
+    relay_domain: Vec<String>,
+
     #[clap(long, value_name = "LEVEL", help = "Set logging level (trace, debug, info, warn, error)")]
     log_level: Option<String>,
 
@@ -103,6 +146,45 @@ struct Args {
         help = "Encrypt a secret payload; omit PAYLOAD to read from stdin"
     )]
     encrypt: Option<String>,
+
+    #[clap(long, help = "Run the startup preflight checks, print the report and exit")]
+    preflight_only: bool,
+
+    #[clap(long, help = "Print the JSON Schema of the configuration file and exit")]
+    print_schema: bool,
+
+    #[clap(long, help = "Print the effective configuration with credentials masked and exit")]
+    print_config: bool,
+
+    #[clap(long, help = "Log unknown configuration fields instead of refusing to start")]
+    allow_unknown_fields: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Interactively create a configuration file
+    Init {
+        #[clap(short, long, value_name = "FILE", default_value = "config.json", help = "Where to write the configuration")]
+        output: String,
+    },
+    /// Generate HTTP or CONNECT load through the proxy and report throughput and latency
+    Bench {
+        #[clap(long, value_name = "URL", help = "URL to request, or the CONNECT destination with --connect")]
+        target: String,
+        #[clap(long, value_name = "N", default_value_t = 10, help = "Concurrent connections")]
+        connections: usize,
+        #[clap(long, value_name = "DURATION", default_value = "30s", value_parser = bench::parse_duration, help = "Length of the run (e.g. 30s, 2m)")]
+        duration: Duration,
+        #[clap(long, value_name = "URL", help = "Forward proxy to load (http://[user:pass@]host:port); defaults to the --config forward listener")]
+        proxy: Option<String>,
+        #[clap(long, help = "Open a CONNECT tunnel per iteration instead of sending requests")]
+        connect: bool,
+    },
+    /// Boot the configured proxy on ephemeral ports, run end-to-end checks and exit non-zero on failure
+    Selftest,
 }
 
 fn init_logging_from_config(config: &Config, args: Option<&Args>) -> Result<(), Box<dyn std::error::Error>> {
@@ -111,6 +193,9 @@ fn init_logging_from_config(config: &Config, args: Option<&Args>) -> Result<(),
         logging::CustomLogger::init(logging_config.clone())?;
         info!("Initialized advanced logging system with {} targets",
               logging_config.targets.as_ref().map(|t| t.len()).unwrap_or(0));
+        if let Some(security) = &logging_config.security {
+            security_log::init(security)?;
+        }
     } else {
         // Fallback to CLI arguments or defaults
         let args = args.expect("Args required when no logging config provided");
@@ -135,10 +220,30 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Parse args first to get logging configuration
     let args = Args::parse();
 
+    if args.print_schema {
+        println!("{}", serde_json::to_string_pretty(&config_schema())?);
+        return Ok(());
+    }
+
+    if let Some(Command::Init { output }) = &args.command {
+        return run_init_wizard(output);
+    }
+
+    if let Some(Command::Bench { target, connections, duration, proxy, connect }) = &args.command {
+        init_logging_from_args(&args)?;
+        return run_bench(&args, target, *connections, *duration, proxy.as_deref(), *connect);
+    }
+
+    if let Some(Command::Selftest) = &args.command {
+        // Proxy logs would drown the report unless asked for
+        logging::init_fallback(Some(args.log_level.as_deref().unwrap_or("warn")), args.log_format.as_deref())?;
+        return run_selftest(&args);
+    }
+
     // Initialize logging based on configuration
     if let Some(config_file) = &args.config {
         // Load configuration first to get logging settings
-        let config = Config::from_file(config_file)?;
+        let config = Config::from_file_with_options(config_file, args.allow_unknown_fields)?;
         init_logging_from_config(&config, Some(&args))?;
     } else {
         // Use CLI arguments for logging configuration
@@ -178,23 +283,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // Load configuration
-    let mut config = if let Some(config_file) = &args.config {
+    let config = if let Some(config_file) = &args.config {
         if !Path::new(config_file).exists() {
             return Err(format!("Configuration file not found: {}", config_file).into());
         }
-        Config::from_file(config_file)?
+        load_config_file(config_file, args.allow_unknown_fields)?
     } else {
-        create_config_from_args(&args)?
+        prepare_config(create_config_from_args(&args)?)?
     };
 
-    if config_has_encrypted_values(&config) {
-        let manager = SecretManager::new()?;
-        manager.apply_to_config(&mut config)?;
+    if args.print_config {
+        println!("{}", config.to_redacted_json()?);
+        return Ok(());
     }
 
-    // Validate configuration
-    validate_config(&config)?;
-
     // Create tokio runtime with custom thread pool if configured
     // Priority: static_files.worker_threads > top-level worker_threads > default
     let worker_threads = config.static_files.as_ref()
@@ -212,15 +314,122 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         tokio::runtime::Runtime::new()?
     };
 
+    // Before anything reads a credential; a refresh task keeps running on the runtime
+    runtime.block_on(secret_providers::init(&config))?;
+
+    if args.preflight_only {
+        return runtime.block_on(run_preflight_only(config));
+    }
+
+    // A configuration file is read again on SIGHUP, through the same loading and validation
+    let reload = args.config.clone().map(|config_file| {
+        let allow_unknown_fields = args.allow_unknown_fields;
+        let load: ConfigLoader = Arc::new(move |path: &Path| {
+            load_config_file(&path.to_string_lossy(), allow_unknown_fields).map_err(|e| e.to_string())
+        });
+        (config_file, load)
+    });
+
     // Run the async main function in the configured runtime
-    runtime.block_on(async_main(config))
+    runtime.block_on(async_main(config, reload))
 }
 
-async fn async_main(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+/// Reads a configuration file and prepares it like any other configuration.
+fn load_config_file(path: &str, allow_unknown_fields: bool) -> Result<Config, Box<dyn std::error::Error>> {
+    prepare_config(Config::from_file_with_options(path, allow_unknown_fields)?)
+}
+
+/// Decrypts the encrypted values of a configuration and validates it.
+fn prepare_config(config: Config) -> Result<Config, Box<dyn std::error::Error>> {
+    if config_has_encrypted_values(&config) {
+        let manager = SecretManager::new()?;
+        manager.apply_to_config(&config)?;
+    }
+    validate_config(&config)?;
+    Ok(config)
+}
+
+async fn run_preflight_only(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let preflight = Preflight::new(&config, config.preflight.clone().unwrap_or_default());
+    let mut report = preflight.local_checks();
+    report.extend(preflight.network_checks().await);
+    println!("{}", report);
+
+    if report.passed() {
+        Ok(())
+    } else {
+        Err(format!("Preflight failed with {} failing check(s)", report.failures()).into())
+    }
+}
+
+async fn async_main(config: Config, reload: Option<(String, ConfigLoader)>) -> Result<(), Box<dyn std::error::Error>> {
     // Create and run proxy with graceful shutdown
     info!("Starting proxy server...");
 
-    let proxy = ProxyFactory::create_proxy(config)?;
+    if let Some(privacy_config) = &config.privacy {
+        privacy::init(privacy_config)?;
+    }
+
+    if let Some(notifications_config) = &config.notifications {
+        notifications::init(notifications_config)?;
+    }
+
+    // Before the proxy is created, since its backend clients pick up the resolver
+    if let Some(dns_config) = &config.dns {
+        dns::init(dns_config)?;
+    }
+
+    if let Some(trusted_proxies) = &config.trusted_proxies {
+        forwarded::init(trusted_proxies)?;
+    }
+
+    if let Some(graphql_config) = &config.graphql {
+        graphql::init(graphql_config)?;
+    }
+
+    if let Some(request_limits) = &config.request_limits {
+        request_limits::init(request_limits)?;
+    }
+
+    if let Some(access_log) = &config.access_log {
+        access_log::init(access_log)?;
+    }
+
+    if let Some(path_normalization) = &config.path_normalization {
+        path_normalization::init(path_normalization)?;
+    }
+
+    if let Some(monitor_config) = config.resource_monitor.clone() {
+        ResourceMonitor::new(monitor_config).spawn();
+    }
+
+    if let Some(certificate) = config.certificate.clone() {
+        CertificateExpiryMonitor::new(vec![certificate], config.certificate_expiry.clone().unwrap_or_default()).spawn();
+    }
+
+    // Local checks run before any listener is bound; network checks run once the server is up
+    let preflight = match config.preflight.clone() {
+        Some(settings) => {
+            let report = Preflight::new(&config, settings.clone()).local_checks();
+            report.log();
+            if settings.fail_fast && !report.passed() {
+                return Err(format!("Preflight failed with {} failing check(s)", report.failures()).into());
+            }
+            Some((config.clone(), settings, report))
+        }
+        None => None,
+    };
+
+    let drain = Duration::from_secs(config.websocket.clone().unwrap_or_default().shutdown_drain_seconds);
+    let reload_settings = config.config_reload.clone().unwrap_or_default();
+    let admin_api = config.admin_api.clone();
+    let (proxy, handle) = ProxyFactory::create_reloadable(config)?;
+    if let Some(admin_api) = admin_api {
+        AdminApi::new(admin_api, handle.clone()).spawn().await?;
+    }
+    if let Some((config_file, load)) = reload {
+        ConfigReloader::new(config_file, load, handle).with_settings(&reload_settings).spawn();
+    }
 
     // Create a shutdown signal
     let (_shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
@@ -236,21 +445,63 @@ async fn async_main(config: Config) -> Result<(), Box<dyn std::error::Error>> {
     tokio::select! {
         _ = signal::ctrl_c() => {
             info!("\n🛑 Received Ctrl+C, shutting down gracefully...");
+            drain_tunnels(drain).await;
+        }
+        _ = shutdown::drain_requested() => {
+            info!("🛑 Drain requested through the admin API, shutting down gracefully...");
+            drain_tunnels(drain).await;
         }
         _ = &mut shutdown_rx => {
             info!("🛑 Shutdown signal received, shutting down gracefully...");
+            drain_tunnels(drain).await;
         }
         result = server_handle => {
             if let Err(e) = result {
                 error!("Server task error: {}", e);
             }
         }
+        Err(reason) = declare_readiness(preflight) => {
+            error!("🛑 {}, shutting down", reason);
+            return Err(reason.into());
+        }
     }
 
     info!("👋 Proxy server stopped. Goodbye!");
     Ok(())
 }
 
+/// Finishes the preflight (if configured) and marks the process ready; only returns on failure.
+async fn declare_readiness(preflight: Option<(Config, PreflightConfig, PreflightReport)>) -> Result<(), String> {
+    if let Some((config, settings, mut report)) = preflight {
+        let network = Preflight::new(&config, settings.clone()).network_checks().await;
+        network.log();
+        report.extend(network);
+        if settings.fail_fast && !report.passed() {
+            return Err(format!("Preflight failed with {} failing check(s)", report.failures()));
+        }
+        info!("Preflight finished: {} check(s), {} failed", report.checks.len(), report.failures());
+        preflight::mark_ready(Some(report));
+    } else {
+        preflight::mark_ready(None);
+    }
+    std::future::pending().await
+}
+
+/// Lets open WebSocket tunnels finish, then closes them with Close frames before exiting.
+async fn drain_tunnels(drain: Duration) {
+    shutdown::begin_shutdown();
+    let open = shutdown::open_tunnels();
+    if open == 0 {
+        return;
+    }
+    info!("Draining {} open tunnel(s) for up to {:?}", open, drain);
+    // Extra time for the Close frames to be written once the drain period ends
+    let remaining = shutdown::wait_for_tunnels(drain + Duration::from_secs(2)).await;
+    if remaining > 0 {
+        warn!("{} tunnel(s) still open after draining; dropping them", remaining);
+    }
+}
+
 fn read_secret_from_stdin() -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let mut buffer = Vec::new();
     std::io::stdin().read_to_end(&mut buffer)?;
@@ -348,18 +599,46 @@ fn create_config_from_args(args: &Args) -> Result<Config, Box<dyn std::error::Er
         certificate: args.certificate.clone(),
         connection_pool_enabled: Some(!args.no_connection_pool),
         max_header_size: args.max_header_size,
-        relay_proxies: None,
+        relay_proxies: relay_proxy_from_args(args)?.map(|relay| vec![relay]),
         relay_proxy_url: None,
         relay_proxy_username: None,
         relay_proxy_password: None,
         relay_proxy_domain_suffixes: None,
         proxy_username: args.proxy_username.clone(),
-        proxy_password: args.proxy_password.clone(),
+        proxy_password: args.proxy_password.clone().map(Secret::new),
         reverse_proxy_config: None,
         logging: None,
         monitoring: bifrost_bridge::config::MonitoringConfig::default(),
-        websocket: None,
-        rate_limiting: None,
+        websocket: websocket_from_args(args),
+        rate_limiting: rate_limiting_from_args(args)?,
+        forward_proxy_config: None,
+        api_keys: None,
+        resource_monitor: None,
+        preflight: None,
+        certificate_expiry: None,
+        tls_session: None,
+        client_auth: None,
+        http2: None,
+        listener: None,
+        request_limits: None,
+        route_fallback: None,
+        route_metadata: None,
+        access_log: None,
+        privacy: None,
+        tarpit: None,
+        auto_ban: None,
+        cluster: None,
+        metrics_push: None,
+        admin_api: None,
+        notifications: None,
+        dns: None,
+        trusted_proxies: None,
+        graphql: None,
+        tenants: Vec::new(),
+        secrets: None,
+        config_reload: None,
+        path_normalization: None,
+        forward_listen_addr: None,
     };
 
     // Configure static files if specified
@@ -382,6 +661,9 @@ fn create_config_from_args(args: &Args) -> Result<Config, Box<dyn std::error::Er
                 custom_mime_types: std::collections::HashMap::new(),
                 no_cache_files: vec![],
                 cache_millisecs: 3600,
+                etag: bifrost_bridge::config::EtagMode::default(),
+                last_modified: true,
+                precompressed: true,
             }
         };
 
@@ -423,9 +705,179 @@ fn create_config_from_args(args: &Args) -> Result<Config, Box<dyn std::error::Er
     Ok(config)
 }
 
+fn rate_limiting_from_args(args: &Args) -> Result<Option<RateLimitingConfig>, Box<dyn std::error::Error>> {
+    if args.rate_limit.is_empty() {
+        return Ok(None);
+    }
+    let rules = args
+        .rate_limit
+        .iter()
+        .enumerate()
+        .map(|(idx, spec)| {
+            rate_limit::parse_rule_spec(&format!("cli-{}", idx + 1), spec)
+                .map_err(|e| format!("Invalid --rate-limit '{}': {}", spec, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Some(RateLimitingConfig {
+        enabled: true,
+        rules,
+        ..RateLimitingConfig::default()
+    }))
+}
+
+fn websocket_from_args(args: &Args) -> Option<WebSocketConfig> {
+    if !args.no_websocket && args.ws_allowed_origin.is_empty() {
+        return None;
+    }
+    let mut websocket = WebSocketConfig {
+        enabled: !args.no_websocket,
+        ..WebSocketConfig::default()
+    };
+    if !args.ws_allowed_origin.is_empty() {
+        websocket.allowed_origins = args.ws_allowed_origin.clone();
+    }
+    Some(websocket)
+}
+
+fn relay_proxy_from_args(args: &Args) -> Result<Option<RelayProxyConfig>, Box<dyn std::error::Error>> {
+    let Some(url) = &args.relay_proxy else {
+        if args.relay_proxy_auth.is_some() || !args.relay_domain.is_empty() {
+            return Err("--relay-proxy-auth and --relay-domain require --relay-proxy".into());
+        }
+        return Ok(None);
+    };
+    url::Url::parse(url).map_err(|e| format!("Invalid relay proxy URL '{}': {}", url, e))?;
+
+    // Split at the first colon only; passwords may contain colons
+    let (username, password) = match &args.relay_proxy_auth {
+        Some(auth) => {
+            let (user, pass) = auth
+                .split_once(':')
+                .ok_or("Invalid relay proxy credentials. Use format 'USER:PASS'")?;
+            (Some(user.to_string()), Some(pass.to_string()))
+        }
+        None => (None, None),
+    };
+
+    Ok(Some(RelayProxyConfig {
+        relay_proxy_url: url.clone(),
+        relay_proxy_username: username,
+        relay_proxy_password: password.map(Secret::new),
+        relay_proxy_domains: args.relay_domain.clone(),
+        relay_proxy_auth_scheme: RelayAuthScheme::default(),
+    }))
+}
+
+/// Runs the `init` questionnaire on the terminal and writes the resulting configuration.
+fn run_init_wizard(output: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    if Path::new(output).exists() {
+        print!("{} already exists. Overwrite? [y/N]: ", output);
+        std::io::Write::flush(&mut stdout)?;
+        let mut answer = String::new();
+        stdin.read_line(&mut answer)?;
+        if !matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes") {
+            println!("Nothing written.");
+            return Ok(());
+        }
+    }
+
+    let config = InitWizard::new(stdin.lock(), &mut stdout).run()?;
+    validate_config(&config)?;
+    std::fs::write(output, init_wizard::to_compact_json(&config)?)?;
+    // Read the file back the way the server will, so what was written is known to load
+    Config::from_file(output)?;
+    println!("Configuration written to {}. Start with: bifrost-bridge --config {}", output, output);
+    Ok(())
+}
+
+/// Runs `bench` against the given proxy, or the forward listener of `--config`, and prints the report.
+fn run_bench(
+    args: &Args,
+    target: &str,
+    connections: usize,
+    duration: Duration,
+    proxy: Option<&str>,
+    connect: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let target = url::Url::parse(target).map_err(|e| format!("Invalid target URL '{}': {}", target, e))?;
+    let proxy = match (proxy, &args.config) {
+        (Some(proxy), _) => Some(BenchProxy::from_url(proxy)?),
+        (None, Some(config_file)) => {
+            let config = Config::from_file_with_options(config_file, args.allow_unknown_fields)?;
+            let listen = match config.mode {
+                ProxyMode::Forward => Some(config.listen_addr),
+                ProxyMode::Combined => config.forward_listen_addr,
+                ProxyMode::Reverse => None,
+            };
+            listen.map(|mut addr| {
+                // A wildcard listener is reached over loopback
+                if addr.ip().is_unspecified() {
+                    addr.set_ip(if addr.is_ipv4() {
+                        std::net::Ipv4Addr::LOCALHOST.into()
+                    } else {
+                        std::net::Ipv6Addr::LOCALHOST.into()
+                    });
+                }
+                BenchProxy {
+                    addr: addr.to_string(),
+                    authorization: config.proxy_username.as_deref().map(|user| {
+                        bench::basic_authorization(user, &config.proxy_password.as_ref().map(Secret::expose).unwrap_or_default())
+                    }),
+                }
+            })
+        }
+        (None, None) => None,
+    };
+
+    match &proxy {
+        Some(proxy) => println!(
+            "Running {} {} connections against {} through {} for {:?}...",
+            connections,
+            if connect { "CONNECT" } else { "HTTP" },
+            target,
+            proxy.addr,
+            duration
+        ),
+        None => println!("Running {} HTTP connections directly against {} for {:?}...", connections, target, duration),
+    }
+    let report = tokio::runtime::Runtime::new()?.block_on(bench::run(BenchOptions {
+        target,
+        proxy,
+        connections,
+        duration,
+        connect,
+    }))?;
+    println!("{}", report);
+    Ok(())
+}
+
+/// Runs `selftest` on the `--config` file and prints the report; fails when any check failed.
+fn run_selftest(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = args.config.as_deref().ok_or("selftest requires --config")?;
+    let config = Config::from_file_with_options(config_file, args.allow_unknown_fields)?;
+    if config_has_encrypted_values(&config) {
+        SecretManager::new()?.apply_to_config(&config)?;
+    }
+    validate_config(&config)?;
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(secret_providers::init(&config))?;
+    let report = runtime.block_on(selftest::run(config))?;
+    // Proxy background tasks never finish on their own
+    runtime.shutdown_timeout(Duration::from_secs(1));
+    println!("{}", report);
+    if report.passed() {
+        Ok(())
+    } else {
+        Err(format!("Selftest failed with {} failing check(s)", report.failures()).into())
+    }
+}
+
 fn validate_config(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
     match config.mode {
-        ProxyMode::Reverse => {
+        ProxyMode::Reverse | ProxyMode::Combined => {
             let has_target = config.reverse_proxy_target.is_some();
             let has_routes = !config.reverse_proxy_routes.is_empty();
             info!(
@@ -437,6 +889,15 @@ fn validate_config(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
             if !has_target && !has_routes && config.static_files.is_none() {
                 return Err("Reverse proxy mode requires either a target URL, reverse_proxy_routes, or static files configuration".into());
             }
+            if matches!(config.mode, ProxyMode::Combined) {
+                match config.forward_listen_addr {
+                    None => return Err("Combined mode requires forward_listen_addr for the forward proxy".into()),
+                    Some(addr) if addr == config.listen_addr => {
+                        return Err("forward_listen_addr must differ from listen_addr in Combined mode".into());
+                    }
+                    Some(_) => {}
+                }
+            }
         }
         ProxyMode::Forward => {
             // Forward proxy specific validation
@@ -446,6 +907,14 @@ fn validate_config(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    let route_limits = validate_route_limits(&config.reverse_proxy_routes);
+    for warning in &route_limits.warnings {
+        warn!("{}", warning);
+    }
+    if !route_limits.is_valid {
+        return Err(route_limits.errors.join("; ").into());
+    }
+
     // Validate worker_threads configuration
     // Check top-level worker_threads first (shared for reverse proxy + static files)
     if let Some(worker_threads) = config.worker_threads {
@@ -491,7 +960,21 @@ mod config_validation_tests {
             retry_policy: None,
             reverse_proxy_config: None,
             strip_path_prefix: None,
+            preserve_host_header: None,
             priority: Some(0),
+            require_api_key: false,
+            hmac_verification: None,
+            fan_out: None,
+            aggregate: None,
+            replay: None,
+            static_response: None,
+            allowed_methods: Vec::new(),
+            method_override: None,
+            allowed_upgrades: Vec::new(),
+            path_param_headers: std::collections::HashMap::new(),
+            limits: None,
+            upstream_tls: None,
+            metadata: std::collections::HashMap::new(),
             predicates: vec![RoutePredicateConfig::Path {
                 patterns: vec!["/**".to_string()],
                 match_trailing_slash: true,
@@ -509,4 +992,62 @@ mod config_validation_tests {
 
         assert!(validate_config(&config).is_ok());
     }
+
+    #[test]
+    fn relay_proxy_flags_build_a_relay_rule() {
+        let args = Args::parse_from([
+            "bifrost-bridge",
+            "--relay-proxy",
+            "http://relay.corp.example:3128",
+            "--relay-proxy-auth",
+            "svc:p:ss",
+            "--relay-domain",
+            ".corp.example",
+            "--relay-domain",
+            "git.example.com",
+        ]);
+        let config = create_config_from_args(&args).unwrap();
+        let relays = config.effective_relay_proxies();
+        assert_eq!(relays.len(), 1);
+        assert_eq!(relays[0].relay_proxy_url, "http://relay.corp.example:3128");
+        assert_eq!(relays[0].relay_proxy_username.as_deref(), Some("svc"));
+        assert_eq!(relays[0].relay_proxy_password.as_ref().unwrap().expose().as_str(), "p:ss");
+        assert_eq!(relays[0].relay_proxy_domains, [".corp.example", "git.example.com"]);
+
+        let args = Args::parse_from(["bifrost-bridge", "--relay-domain", ".corp.example"]);
+        assert!(create_config_from_args(&args).is_err());
+        let args = Args::parse_from(["bifrost-bridge", "--relay-proxy", "http://relay:3128", "--relay-proxy-auth", "svc"]);
+        assert!(create_config_from_args(&args).is_err());
+    }
+
+    #[test]
+    fn rate_limit_and_websocket_flags() {
+        let args = Args::parse_from([
+            "bifrost-bridge",
+            "--rate-limit",
+            "100/60s per-ip",
+            "--rate-limit",
+            "20/1m /api/login post",
+            "--ws-allowed-origin",
+            "https://app.example.com",
+        ]);
+        let config = create_config_from_args(&args).unwrap();
+        let rules = config.rate_limiting.unwrap().rules;
+        assert_eq!((rules[0].id.as_str(), rules[0].limit, rules[0].window_secs), ("cli-1", 100, 60));
+        assert_eq!(rules[1].path_prefix.as_deref(), Some("/api/login"));
+        assert_eq!(rules[1].methods.as_deref(), Some(&["POST".to_string()][..]));
+        let websocket = config.websocket.unwrap();
+        assert!(websocket.enabled);
+        assert_eq!(websocket.allowed_origins, ["https://app.example.com"]);
+
+        let args = Args::parse_from(["bifrost-bridge", "--no-websocket"]);
+        let config = create_config_from_args(&args).unwrap();
+        assert!(config.rate_limiting.is_none());
+        assert!(!config.websocket.unwrap().enabled);
+
+        for spec in ["100", "0/60s", "10/xs", "10/60s per-user"] {
+            let args = Args::parse_from(["bifrost-bridge", "--rate-limit", spec]);
+            assert!(create_config_from_args(&args).is_err(), "{}", spec);
+        }
+    }
 }
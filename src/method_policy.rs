@@ -0,0 +1,177 @@
+//! Per-route method allow lists and `X-HTTP-Method-Override` handling.
+//!
+//! Clients behind firewalls that only pass GET and POST send other methods as a POST naming
+//! the real one in `X-HTTP-Method-Override`. With `apply`, the proxy makes that the request's
+//! method before the allowed methods are checked and forwards it as such. With `forward`, the
+//! header reaches the backend, which may honor it, so an override naming a method the route
+//! does not allow is refused like the method itself.
+
+use crate::config::MethodOverride;
+use crate::error::ProxyError;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::header::{ALLOW, HeaderName, HeaderValue};
+use hyper::{Method, Request, Response, StatusCode};
+
+const METHOD_OVERRIDE: HeaderName = HeaderName::from_static("x-http-method-override");
+
+/// Methods a route serves, and what it does with `X-HTTP-Method-Override`.
+pub struct MethodPolicy {
+    /// Empty when any method is allowed
+    allowed: Vec<Method>,
+    allow_header: Option<HeaderValue>,
+    method_override: MethodOverride,
+}
+
+impl MethodPolicy {
+    pub fn new(route_id: &str, allowed_methods: &[String], method_override: Option<MethodOverride>) -> Result<Self, ProxyError> {
+        let mut allowed = Vec::new();
+        for method in allowed_methods {
+            let parsed = Method::from_bytes(method.trim().to_ascii_uppercase().as_bytes()).map_err(|_| {
+                ProxyError::Config(format!("Route {}: allowed method is invalid: {}", route_id, method))
+            })?;
+            if !allowed.contains(&parsed) {
+                allowed.push(parsed);
+            }
+        }
+        // A resource that answers GET answers HEAD too (RFC 9110 9.3.2)
+        if allowed.contains(&Method::GET) && !allowed.contains(&Method::HEAD) {
+            allowed.push(Method::HEAD);
+        }
+        let allow_header = (!allowed.is_empty()).then(|| {
+            let names: Vec<&str> = allowed.iter().map(Method::as_str).collect();
+            HeaderValue::from_str(&names.join(", ")).expect("method names are valid header values")
+        });
+        Ok(Self { allowed, allow_header, method_override: method_override.unwrap_or_default() })
+    }
+
+    /// Applies the override policy to `req`; `Some` is the response refusing it.
+    pub fn check<B>(&self, req: &mut Request<B>) -> Option<Response<Full<Bytes>>> {
+        if let Some(value) = req.headers().get(&METHOD_OVERRIDE) {
+            let named = value
+                .to_str()
+                .ok()
+                .and_then(|name| Method::from_bytes(name.trim().to_ascii_uppercase().as_bytes()).ok());
+            match self.method_override {
+                MethodOverride::Forward => {
+                    if let Some(named) = &named
+                        && !self.allows(named)
+                    {
+                        return Some(self.not_allowed());
+                    }
+                }
+                MethodOverride::Apply => {
+                    let Some(named) = named else {
+                        return Some(bad_request("Invalid X-HTTP-Method-Override"));
+                    };
+                    if req.method() == Method::POST {
+                        *req.method_mut() = named;
+                    }
+                    req.headers_mut().remove(&METHOD_OVERRIDE);
+                }
+                MethodOverride::Strip => {
+                    req.headers_mut().remove(&METHOD_OVERRIDE);
+                }
+                MethodOverride::Reject => return Some(bad_request("X-HTTP-Method-Override is not accepted")),
+            }
+        }
+
+        (!self.allows(req.method())).then(|| self.not_allowed())
+    }
+
+    fn allows(&self, method: &Method) -> bool {
+        self.allowed.is_empty() || self.allowed.contains(method)
+    }
+
+    fn not_allowed(&self) -> Response<Full<Bytes>> {
+        let mut response = Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .body(Full::new(Bytes::from_static(b"Method Not Allowed")))
+            .unwrap();
+        if let Some(allow) = &self.allow_header {
+            response.headers_mut().insert(ALLOW, allow.clone());
+        }
+        response
+    }
+}
+
+fn bad_request(message: &'static str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Full::new(Bytes::from_static(message.as_bytes())))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: Method, override_to: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder().method(method).uri("/orders/7");
+        if let Some(name) = override_to {
+            builder = builder.header("X-HTTP-Method-Override", name);
+        }
+        builder.body(()).unwrap()
+    }
+
+    fn policy(allowed: &[&str], method_override: Option<MethodOverride>) -> MethodPolicy {
+        let allowed: Vec<String> = allowed.iter().map(|method| method.to_string()).collect();
+        MethodPolicy::new("orders", &allowed, method_override).unwrap()
+    }
+
+    #[test]
+    fn methods_outside_the_list_get_405_with_allow() {
+        let policy = policy(&["get", "POST"], None);
+        assert!(policy.check(&mut request(Method::GET, None)).is_none());
+        assert!(policy.check(&mut request(Method::HEAD, None)).is_none());
+
+        let refused = policy.check(&mut request(Method::DELETE, None)).unwrap();
+        assert_eq!(refused.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(refused.headers()[ALLOW], "GET, POST, HEAD");
+
+        assert!(MethodPolicy::new("orders", &["GE T".to_string()], None).is_err());
+    }
+
+    #[test]
+    fn applied_overrides_replace_the_method_of_posts() {
+        let policy = policy(&["GET", "PATCH"], Some(MethodOverride::Apply));
+        let mut req = request(Method::POST, Some("patch"));
+        assert!(policy.check(&mut req).is_none());
+        assert_eq!(req.method(), Method::PATCH);
+        assert!(!req.headers().contains_key(METHOD_OVERRIDE));
+
+        let refused = policy.check(&mut request(Method::POST, Some("DELETE"))).unwrap();
+        assert_eq!(refused.status(), StatusCode::METHOD_NOT_ALLOWED);
+        // Only a POST is reinterpreted
+        let mut req = request(Method::GET, Some("PATCH"));
+        assert!(policy.check(&mut req).is_none());
+        assert_eq!(req.method(), Method::GET);
+        assert_eq!(
+            policy.check(&mut request(Method::POST, Some("PAT CH"))).unwrap().status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn forwarded_overrides_are_checked_against_the_allowed_methods() {
+        let policy = policy(&["GET", "POST"], None);
+        let refused = policy.check(&mut request(Method::POST, Some("DELETE"))).unwrap();
+        assert_eq!(refused.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let mut req = request(Method::POST, Some("GET"));
+        assert!(policy.check(&mut req).is_none());
+        assert!(req.headers().contains_key(METHOD_OVERRIDE));
+    }
+
+    #[test]
+    fn overrides_can_be_stripped_or_refused() {
+        let mut req = request(Method::POST, Some("DELETE"));
+        assert!(policy(&[], Some(MethodOverride::Strip)).check(&mut req).is_none());
+        assert_eq!(req.method(), Method::POST);
+        assert!(!req.headers().contains_key(METHOD_OVERRIDE));
+
+        let refused = policy(&[], Some(MethodOverride::Reject)).check(&mut request(Method::POST, Some("DELETE")));
+        assert_eq!(refused.unwrap().status(), StatusCode::BAD_REQUEST);
+        assert!(policy(&[], Some(MethodOverride::Reject)).check(&mut request(Method::POST, None)).is_none());
+    }
+}
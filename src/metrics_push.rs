@@ -0,0 +1,494 @@
+//! Metrics push exporter.
+//!
+//! Where nothing can scrape the monitoring server, everything in the [`MonitoringRegistry`] is
+//! pushed to a collector every `interval_secs` instead, either as a Prometheus remote-write
+//! request or as an OTLP/HTTP JSON export.
+//!
+//! Remote-write bodies are protobuf in snappy block framing. They are written as snappy literals
+//! only: receivers decode them like any other snappy block, the body is just not made smaller.
+
+use crate::redact::redact_url;
+use crate::common::MonitoringRegistry;
+use crate::config::{MetricsPushConfig, MetricsPushFormat};
+use crate::error::ProxyError;
+use crate::secrets::Secret;
+use base64::{Engine as _, engine::general_purpose};
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use hyper::{Method, Request, Uri};
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use log::{debug, info, warn};
+use prometheus::proto::{MetricFamily, MetricType};
+use prometheus::{IntCounterVec, Opts, Registry};
+use serde_json::{Value, json};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Longest literal written in one snappy element.
+const SNAPPY_LITERAL_MAX: usize = 65536;
+
+pub struct MetricsPusher {
+    url: Uri,
+    format: MetricsPushFormat,
+    interval: Duration,
+    timeout: Duration,
+    headers: HeaderMap,
+    /// Extra labels, sorted by name
+    labels: Vec<(String, String)>,
+    registry: Arc<MonitoringRegistry>,
+    client: Client<HttpsConnector<HttpConnector>, Full<Bytes>>,
+    /// Start of the cumulative counters, for OTLP
+    started_ns: u128,
+}
+
+impl MetricsPusher {
+    pub fn new(config: &MetricsPushConfig, registry: Arc<MonitoringRegistry>) -> Result<Self, ProxyError> {
+        let url: Uri = config.url.parse()
+            .map_err(|e| ProxyError::Config(format!("metrics_push: invalid URL '{}': {}", redact_url(&config.url), e)))?;
+        if !matches!(url.scheme_str(), Some("http" | "https")) {
+            return Err(ProxyError::Config("metrics_push: URL must use http:// or https://".to_string()));
+        }
+        if config.interval_secs == 0 || config.timeout_secs == 0 {
+            return Err(ProxyError::Config("metrics_push.interval_secs and timeout_secs must be greater than 0".to_string()));
+        }
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| ProxyError::Config(format!("metrics_push: invalid header name '{}'", name)))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| ProxyError::Config(format!("metrics_push: invalid value for header '{}'", name)))?;
+            headers.insert(name, value);
+        }
+        let authorization = match (&config.bearer_token, &config.username) {
+            (Some(token), _) => Some(format!("Bearer {}", token.expose().as_str())),
+            (None, Some(username)) => {
+                let password = config.password.as_ref().map(Secret::expose).unwrap_or_default();
+                let credentials = format!("{}:{}", username, password.as_str());
+                Some(format!("Basic {}", general_purpose::STANDARD.encode(credentials)))
+            }
+            (None, None) => None,
+        };
+        if let Some(authorization) = authorization {
+            let mut value = HeaderValue::from_str(&authorization)
+                .map_err(|_| ProxyError::Config("metrics_push: invalid credentials".to_string()))?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        let mut labels: Vec<(String, String)> = config.labels.clone().into_iter().collect();
+        labels.sort();
+
+        Ok(Self {
+            url,
+            format: config.format,
+            interval: Duration::from_secs(config.interval_secs),
+            timeout: Duration::from_secs(config.timeout_secs),
+            headers,
+            labels,
+            registry,
+            client: Client::builder(TokioExecutor::new()).build(HttpsConnector::new()),
+            started_ns: unix_now().as_nanos(),
+        })
+    }
+
+    /// Pushes the registry every interval until the runtime shuts down.
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        info!("Pushing metrics to {} every {}s", redact_url(&self.url.to_string()), self.interval.as_secs());
+        tokio::spawn(async move {
+            let mut interval_timer = tokio::time::interval_at(
+                tokio::time::Instant::now() + self.interval,
+                self.interval,
+            );
+            let mut failing = false;
+            loop {
+                interval_timer.tick().await;
+                match self.push().await {
+                    Ok(()) => {
+                        push_telemetry().record("ok");
+                        if failing {
+                            info!("Metrics push to {} recovered", redact_url(&self.url.to_string()));
+                        }
+                        failing = false;
+                    }
+                    Err(e) => {
+                        push_telemetry().record("error");
+                        warn!("Metrics push to {} failed: {}", redact_url(&self.url.to_string()), e);
+                        failing = true;
+                    }
+                }
+            }
+        })
+    }
+
+    async fn push(&self) -> Result<(), ProxyError> {
+        let families = self.registry.gather();
+        let now = unix_now();
+        let (body, content_type) = match self.format {
+            MetricsPushFormat::RemoteWrite => {
+                let samples = flatten(&families, &self.labels);
+                let body = snappy_literal(&remote_write_body(&samples, now.as_millis() as i64));
+                (body, "application/x-protobuf")
+            }
+            MetricsPushFormat::Otlp => {
+                let body = otlp_body(&families, &self.labels, self.started_ns, now.as_nanos());
+                (body.to_string().into_bytes(), "application/json")
+            }
+        };
+
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri(self.url.clone())
+            .header(CONTENT_TYPE, content_type);
+        if self.format == MetricsPushFormat::RemoteWrite {
+            builder = builder
+                .header(CONTENT_ENCODING, "snappy")
+                .header("X-Prometheus-Remote-Write-Version", "0.1.0");
+        }
+        let mut request = builder
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| ProxyError::Http(e.to_string()))?;
+        request.headers_mut().extend(self.headers.clone());
+
+        let response = tokio::time::timeout(self.timeout, self.client.request(request))
+            .await
+            .map_err(|_| ProxyError::Connection(format!("timed out after {}s", self.timeout.as_secs())))?
+            .map_err(|e| ProxyError::Connection(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(ProxyError::Http(format!("receiver answered {}", response.status())));
+        }
+        debug!("Pushed {} metric families to {}", families.len(), redact_url(&self.url.to_string()));
+        Ok(())
+    }
+}
+
+fn unix_now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/// One Prometheus sample; `labels` include `__name__` and are sorted by name.
+#[derive(Debug, PartialEq)]
+struct Sample {
+    labels: Vec<(String, String)>,
+    value: f64,
+}
+
+/// Expands the families into plain samples the way the text exposition format does: histograms
+/// into `_bucket`, `_sum` and `_count`, summaries into quantiles, `_sum` and `_count`.
+fn flatten(families: &[MetricFamily], extra_labels: &[(String, String)]) -> Vec<Sample> {
+    let mut samples = Vec::new();
+    for family in families {
+        let name = family.get_name();
+        for metric in family.get_metric() {
+            let mut labels: Vec<(String, String)> = metric
+                .get_label()
+                .iter()
+                .map(|pair| (pair.get_name().to_string(), pair.get_value().to_string()))
+                .collect();
+            for (key, value) in extra_labels {
+                if !labels.iter().any(|(existing, _)| existing == key) {
+                    labels.push((key.clone(), value.clone()));
+                }
+            }
+            let mut push = |suffix: &str, extra: Option<(&str, String)>, value: f64| {
+                let mut labels = labels.clone();
+                labels.push(("__name__".to_string(), format!("{}{}", name, suffix)));
+                if let Some((key, value)) = extra {
+                    labels.push((key.to_string(), value));
+                }
+                labels.sort();
+                samples.push(Sample { labels, value });
+            };
+            match family.get_field_type() {
+                MetricType::COUNTER => push("", None, metric.get_counter().get_value()),
+                MetricType::GAUGE => push("", None, metric.get_gauge().get_value()),
+                MetricType::UNTYPED => push("", None, metric.get_untyped().get_value()),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    for bucket in histogram.get_bucket() {
+                        let le = bucket.get_upper_bound().to_string();
+                        push("_bucket", Some(("le", le)), bucket.get_cumulative_count() as f64);
+                    }
+                    let count = histogram.get_sample_count() as f64;
+                    push("_bucket", Some(("le", "+Inf".to_string())), count);
+                    push("_sum", None, histogram.get_sample_sum());
+                    push("_count", None, count);
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        push("", Some(("quantile", quantile.get_quantile().to_string())), quantile.get_value());
+                    }
+                    push("_sum", None, summary.get_sample_sum());
+                    push("_count", None, summary.get_sample_count() as f64);
+                }
+            }
+        }
+    }
+    samples
+}
+
+/// Encodes a remote-write `WriteRequest` with one sample per time series.
+fn remote_write_body(samples: &[Sample], timestamp_ms: i64) -> Vec<u8> {
+    let mut body = Vec::new();
+    for sample in samples {
+        let mut series = Vec::new();
+        for (name, value) in &sample.labels {
+            let mut label = Vec::new();
+            put_length_delimited(&mut label, 1, name.as_bytes());
+            put_length_delimited(&mut label, 2, value.as_bytes());
+            put_length_delimited(&mut series, 1, &label);
+        }
+        let mut point = vec![0x09];
+        point.extend_from_slice(&sample.value.to_le_bytes());
+        point.push(0x10);
+        put_varint(&mut point, timestamp_ms as u64);
+        put_length_delimited(&mut series, 2, &point);
+        put_length_delimited(&mut body, 1, &series);
+    }
+    body
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn put_length_delimited(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    put_varint(buf, (field << 3) | 2);
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+/// Wraps `data` in snappy block framing as a run of literals.
+fn snappy_literal(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 3 * (data.len() / SNAPPY_LITERAL_MAX + 1) + 10);
+    put_varint(&mut out, data.len() as u64);
+    for chunk in data.chunks(SNAPPY_LITERAL_MAX) {
+        let length = chunk.len() - 1;
+        if length < 60 {
+            out.push((length as u8) << 2);
+        } else if length < 256 {
+            out.push(60 << 2);
+            out.push(length as u8);
+        } else {
+            out.push(61 << 2);
+            out.extend_from_slice(&(length as u16).to_le_bytes());
+        }
+        out.extend_from_slice(chunk);
+    }
+    out
+}
+
+fn otlp_attributes(labels: &[(String, String)]) -> Value {
+    labels
+        .iter()
+        .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+        .collect()
+}
+
+/// Builds an OTLP `ExportMetricsServiceRequest`; counters and histograms are cumulative since
+/// `started_ns`.
+fn otlp_body(families: &[MetricFamily], resource_labels: &[(String, String)], started_ns: u128, now_ns: u128) -> Value {
+    let start = started_ns.to_string();
+    let now = now_ns.to_string();
+    let mut metrics = Vec::new();
+    for family in families {
+        let points = family.get_metric().iter().map(|metric| {
+            let labels: Vec<(String, String)> = metric
+                .get_label()
+                .iter()
+                .map(|pair| (pair.get_name().to_string(), pair.get_value().to_string()))
+                .collect();
+            let mut point = json!({
+                "attributes": otlp_attributes(&labels),
+                "startTimeUnixNano": start,
+                "timeUnixNano": now,
+            });
+            let fields = point.as_object_mut().expect("data point is an object");
+            match family.get_field_type() {
+                MetricType::COUNTER => {
+                    fields.insert("asDouble".to_string(), json!(metric.get_counter().get_value()));
+                }
+                MetricType::GAUGE => {
+                    fields.insert("asDouble".to_string(), json!(metric.get_gauge().get_value()));
+                }
+                MetricType::UNTYPED => {
+                    fields.insert("asDouble".to_string(), json!(metric.get_untyped().get_value()));
+                }
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    // OTLP counts per bucket, Prometheus counts cumulatively up to each bound
+                    let mut previous = 0;
+                    let mut bucket_counts = Vec::new();
+                    let mut bounds = Vec::new();
+                    for bucket in histogram.get_bucket() {
+                        bucket_counts.push((bucket.get_cumulative_count() - previous).to_string());
+                        bounds.push(bucket.get_upper_bound());
+                        previous = bucket.get_cumulative_count();
+                    }
+                    bucket_counts.push(histogram.get_sample_count().saturating_sub(previous).to_string());
+                    fields.insert("count".to_string(), json!(histogram.get_sample_count().to_string()));
+                    fields.insert("sum".to_string(), json!(histogram.get_sample_sum()));
+                    fields.insert("bucketCounts".to_string(), json!(bucket_counts));
+                    fields.insert("explicitBounds".to_string(), json!(bounds));
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    let quantiles: Vec<Value> = summary
+                        .get_quantile()
+                        .iter()
+                        .map(|quantile| json!({ "quantile": quantile.get_quantile(), "value": quantile.get_value() }))
+                        .collect();
+                    fields.insert("count".to_string(), json!(summary.get_sample_count().to_string()));
+                    fields.insert("sum".to_string(), json!(summary.get_sample_sum()));
+                    fields.insert("quantileValues".to_string(), json!(quantiles));
+                }
+            }
+            point
+        }).collect::<Vec<Value>>();
+
+        let data = match family.get_field_type() {
+            MetricType::COUNTER => ("sum", json!({ "aggregationTemporality": 2, "isMonotonic": true, "dataPoints": points })),
+            MetricType::GAUGE | MetricType::UNTYPED => ("gauge", json!({ "dataPoints": points })),
+            MetricType::HISTOGRAM => ("histogram", json!({ "aggregationTemporality": 2, "dataPoints": points })),
+            MetricType::SUMMARY => ("summary", json!({ "dataPoints": points })),
+        };
+        metrics.push(json!({ "name": family.get_name(), "description": family.get_help(), data.0: data.1 }));
+    }
+
+    let mut resource = resource_labels.to_vec();
+    if !resource.iter().any(|(key, _)| key == "service.name") {
+        resource.insert(0, ("service.name".to_string(), "bifrost-bridge".to_string()));
+    }
+    json!({
+        "resourceMetrics": [{
+            "resource": { "attributes": otlp_attributes(&resource) },
+            "scopeMetrics": [{
+                "scope": { "name": "bifrost-bridge", "version": env!("CARGO_PKG_VERSION") },
+                "metrics": metrics,
+            }],
+        }],
+    })
+}
+
+struct PushTelemetry {
+    pushes: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl PushTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "metrics_pushes_total",
+            "Metrics pushes to the configured receiver by outcome (ok, error)",
+        ).namespace("bifrost");
+        Self {
+            pushes: IntCounterVec::new(opts, &["outcome"]).expect("metrics_pushes_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.pushes.clone())) {
+            warn!("Failed to register metrics_pushes_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    fn record(&self, outcome: &str) {
+        self.pushes.with_label_values(&[outcome]).inc();
+    }
+}
+
+fn push_telemetry() -> &'static PushTelemetry {
+    static TELEMETRY: OnceLock<PushTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(PushTelemetry::new)
+}
+
+/// Registers the push exporter counters with the shared Prometheus registry.
+pub fn register_metrics_push_metrics(registry: &Registry) {
+    push_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{HistogramOpts, HistogramVec};
+
+    #[test]
+    fn encodes_families_for_both_formats() {
+        let registry = Registry::new();
+        let requests = IntCounterVec::new(Opts::new("requests_total", "Requests"), &["route"]).unwrap();
+        let latency = HistogramVec::new(
+            HistogramOpts::new("latency_seconds", "Latency").buckets(vec![0.1, 1.0]),
+            &["route"],
+        ).unwrap();
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(latency.clone())).unwrap();
+        requests.with_label_values(&["api"]).inc_by(3);
+        for seconds in [0.05, 0.5, 5.0] {
+            latency.with_label_values(&["api"]).observe(seconds);
+        }
+        let families = registry.gather();
+        let instance = vec![("instance".to_string(), "edge-1".to_string())];
+
+        let samples = flatten(&families, &instance);
+        let label = |sample: &Sample, key: &str| {
+            sample.labels.iter().find(|(name, _)| name == key).map(|(_, value)| value.clone())
+        };
+        let buckets: Vec<(Option<String>, f64)> = samples
+            .iter()
+            .filter(|sample| label(sample, "__name__").as_deref() == Some("latency_seconds_bucket"))
+            .map(|sample| (label(sample, "le"), sample.value))
+            .collect();
+        assert_eq!(buckets, vec![
+            (Some("0.1".to_string()), 1.0),
+            (Some("1".to_string()), 2.0),
+            (Some("+Inf".to_string()), 3.0),
+        ]);
+        let counter = samples.iter().find(|sample| label(sample, "__name__").as_deref() == Some("requests_total")).unwrap();
+        assert_eq!(counter.value, 3.0);
+        assert_eq!(counter.labels, vec![
+            ("__name__".to_string(), "requests_total".to_string()),
+            ("instance".to_string(), "edge-1".to_string()),
+            ("route".to_string(), "api".to_string()),
+        ]);
+        assert_eq!(samples.len(), 6, "three buckets, sum, count and the counter");
+
+        let body = remote_write_body(&samples[..1], 1_700_000_000_000);
+        assert_eq!(body[0], 0x0a, "field 1 (timeseries), length-delimited");
+        let framed = snappy_literal(&body);
+        assert_eq!(framed[0] as usize, body.len());
+        assert_eq!(&framed[framed.len() - body.len()..], &body[..]);
+        let large = snappy_literal(&[7u8; 70_000]);
+        assert_eq!(&large[..3], &[0xf0, 0xa2, 0x04], "varint of 70000");
+        assert_eq!(&large[3..6], &[61 << 2, 0xff, 0xff]);
+        assert_eq!(large[6 + SNAPPY_LITERAL_MAX], 61 << 2);
+
+        let otlp = otlp_body(&families, &instance, 1, 2);
+        let resource = &otlp["resourceMetrics"][0];
+        assert_eq!(resource["resource"]["attributes"][0]["value"]["stringValue"], "bifrost-bridge");
+        let metrics = resource["scopeMetrics"][0]["metrics"].as_array().unwrap();
+        let histogram = metrics.iter().find(|metric| metric["name"] == "latency_seconds").unwrap();
+        let point = &histogram["histogram"]["dataPoints"][0];
+        assert_eq!(point["bucketCounts"], json!(["1", "1", "1"]));
+        assert_eq!(point["explicitBounds"], json!([0.1, 1.0]));
+        assert_eq!(point["count"], "3");
+        let counter = metrics.iter().find(|metric| metric["name"] == "requests_total").unwrap();
+        assert_eq!(counter["sum"]["isMonotonic"], true);
+        assert_eq!(counter["sum"]["dataPoints"][0]["asDouble"], 3.0);
+        assert_eq!(counter["sum"]["dataPoints"][0]["attributes"][0]["key"], "route");
+    }
+}
@@ -1,9 +1,15 @@
+use crate::ban_list::BanList;
 use crate::common::{HtmlTemplates, MetricsSummary, MonitoringHandles};
-use crate::config::MonitoringConfig;
+use crate::config::{MonitoringConfig, RateLimitRuleConfig, RelayProxyConfig};
 use crate::error::ProxyError;
+use crate::forward_proxy::explain_relay_route;
+use crate::preflight;
+use crate::privacy;
+use crate::rate_limit::RateLimiter;
+use crate::tenants::Tenants;
 use bytes::Bytes;
-use http_body_util::Full;
-use hyper::{Request, Response, StatusCode};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::{Method, Request, Response, StatusCode};
 use hyper::body::Incoming;
 use hyper::server::conn::http1::Builder as ServerBuilder;
 use hyper::service::service_fn;
@@ -14,14 +20,52 @@ use std::convert::Infallible;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Largest rule definition accepted by the rate limits endpoint.
+const MAX_RULE_BODY_BYTES: usize = 64 * 1024;
+
 pub struct MonitoringServer {
     config: MonitoringConfig,
     handles: MonitoringHandles,
+    relay_proxies: Vec<RelayProxyConfig>,
+    rate_limiter: Arc<RateLimiter>,
+    bans: Arc<BanList>,
+    tenants: Arc<Tenants>,
 }
 
 impl MonitoringServer {
     pub fn new(config: MonitoringConfig, handles: MonitoringHandles) -> Self {
-        Self { config, handles }
+        Self {
+            config,
+            handles,
+            relay_proxies: Vec::new(),
+            rate_limiter: Arc::new(RateLimiter::disabled()),
+            bans: Arc::new(BanList::new()),
+            tenants: Arc::new(Tenants::default()),
+        }
+    }
+
+    /// Relay rules used to answer the relay-match dry-run endpoint.
+    pub fn with_relay_proxies(mut self, relay_proxies: Vec<RelayProxyConfig>) -> Self {
+        self.relay_proxies = relay_proxies;
+        self
+    }
+
+    /// Rate limiter described by the rate limits endpoint.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    /// Ban list listed, and lifted from, by the bans endpoint.
+    pub fn with_bans(mut self, bans: Arc<BanList>) -> Self {
+        self.bans = bans;
+        self
+    }
+
+    /// Tenants shown by the tenants endpoint.
+    pub fn with_tenants(mut self, tenants: Arc<Tenants>) -> Self {
+        self.tenants = tenants;
+        self
     }
 
     pub async fn run(self) -> Result<(), ProxyError> {
@@ -36,6 +80,10 @@ impl MonitoringServer {
         let state = Arc::new(MonitoringState {
             config: self.config,
             handles: self.handles,
+            relay_proxies: self.relay_proxies,
+            rate_limiter: self.rate_limiter,
+            bans: self.bans,
+            tenants: self.tenants,
         });
 
         loop {
@@ -68,14 +116,24 @@ impl MonitoringServer {
 struct MonitoringState {
     config: MonitoringConfig,
     handles: MonitoringHandles,
+    relay_proxies: Vec<RelayProxyConfig>,
+    rate_limiter: Arc<RateLimiter>,
+    bans: Arc<BanList>,
+    tenants: Arc<Tenants>,
 }
 
 impl MonitoringState {
     async fn route(&self, req: Request<Incoming>) -> Response<Full<Bytes>> {
         match req.uri().path() {
             path if path == self.config.metrics_endpoint => self.handle_metrics(),
+            path if path == self.config.metrics_json_endpoint => self.handle_metrics_json(),
             path if path == self.config.health_endpoint => self.handle_health(),
-            path if path == self.config.status_endpoint => self.handle_status(),
+            path if path == self.config.status_endpoint || path == self.config.dashboard_endpoint => self.handle_status(),
+            path if path == self.config.ready_endpoint => self.handle_ready(),
+            path if path == self.config.relay_match_endpoint => self.handle_relay_match(req.uri().query()),
+            path if path == self.config.rate_limits_endpoint => self.handle_rate_limits(req).await,
+            path if path == self.config.bans_endpoint => self.handle_bans(&req),
+            path if path == self.config.tenants_endpoint => self.handle_tenants(&req).await,
             _ => Response::builder()
                 .status(StatusCode::NOT_FOUND)
                 .body(Full::new(Bytes::from("Monitoring endpoint not found")))
@@ -100,6 +158,14 @@ impl MonitoringState {
         }
     }
 
+    fn handle_metrics_json(&self) -> Response<Full<Bytes>> {
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Full::new(Bytes::from(self.aggregate_summary().to_json())))
+            .unwrap()
+    }
+
     fn handle_status(&self) -> Response<Full<Bytes>> {
         let summary = self.aggregate_summary();
         let html = HtmlTemplates::render_metrics_dashboard(&summary);
@@ -134,6 +200,166 @@ impl MonitoringState {
             .unwrap()
     }
 
+    fn handle_ready(&self) -> Response<Full<Bytes>> {
+        let report = preflight::readiness_report();
+        let (status_code, status) = match &report {
+            _ if !preflight::is_ready() => (StatusCode::SERVICE_UNAVAILABLE, "starting"),
+            Some(report) if !report.passed() => (StatusCode::OK, "degraded"),
+            _ => (StatusCode::OK, "ready"),
+        };
+
+        let payload = json!({
+            "status": status,
+            "timestamp": current_timestamp(),
+            "checks": report.map(|r| r.checks).unwrap_or_default(),
+        });
+
+        Response::builder()
+            .status(status_code)
+            .header("Content-Type", "application/json")
+            .body(Full::new(Bytes::from(payload.to_string())))
+            .unwrap()
+    }
+
+    fn handle_relay_match(&self, query: Option<&str>) -> Response<Full<Bytes>> {
+        let host = query.and_then(|q| {
+            url::form_urlencoded::parse(q.as_bytes())
+                .find(|(key, _)| key == "host")
+                .map(|(_, value)| value.into_owned())
+        });
+
+        let Some(host) = host.filter(|h| !h.is_empty()) else {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("Content-Type", "application/json")
+                .body(Full::new(Bytes::from(
+                    json!({ "error": "missing required query parameter 'host'" }).to_string(),
+                )))
+                .unwrap();
+        };
+
+        let decision = explain_relay_route(&self.relay_proxies, &host);
+        log::info!("Relay dry-run for {}: {}", host, decision.reason);
+
+        let payload = json!({
+            "decision": decision,
+            "rules_evaluated": self.relay_proxies.len(),
+        });
+
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Full::new(Bytes::from(payload.to_string())))
+            .unwrap()
+    }
+
+    async fn handle_rate_limits(&self, req: Request<Incoming>) -> Response<Full<Bytes>> {
+        if req.method() == Method::GET {
+            let payload = serde_json::to_value(self.rate_limiter.snapshot().await)
+                .unwrap_or_else(|e| json!({ "error": e.to_string() }));
+            return json_response(StatusCode::OK, payload);
+        }
+        if !matches!(*req.method(), Method::PUT | Method::DELETE) {
+            return json_response(StatusCode::METHOD_NOT_ALLOWED, json!({ "error": "use GET, PUT or DELETE" }));
+        }
+        if !self.config.rate_limit_updates {
+            return json_response(
+                StatusCode::FORBIDDEN,
+                json!({ "error": "rate limit updates are disabled (monitoring.rate_limit_updates)" }),
+            );
+        }
+
+        if req.method() == Method::DELETE {
+            let id = req.uri().query().and_then(|q| {
+                url::form_urlencoded::parse(q.as_bytes())
+                    .find(|(key, _)| key == "id")
+                    .map(|(_, value)| value.into_owned())
+            });
+            let Some(id) = id else {
+                return json_response(StatusCode::BAD_REQUEST, json!({ "error": "missing required query parameter 'id'" }));
+            };
+            return if self.rate_limiter.remove_rule(&id).await {
+                log::info!("Rate limit rule {} removed through the monitoring API", id);
+                json_response(StatusCode::OK, json!({ "removed": id }))
+            } else {
+                json_response(StatusCode::NOT_FOUND, json!({ "error": format!("no rate limit rule '{}'", id) }))
+            };
+        }
+
+        let body = match Limited::new(req.into_body(), MAX_RULE_BODY_BYTES).collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(e) => return json_response(StatusCode::BAD_REQUEST, json!({ "error": e.to_string() })),
+        };
+        let rule: RateLimitRuleConfig = match serde_json::from_slice(&body) {
+            Ok(rule) => rule,
+            Err(e) => return json_response(StatusCode::BAD_REQUEST, json!({ "error": format!("invalid rule: {}", e) })),
+        };
+        let id = rule.id.clone();
+        match self.rate_limiter.upsert_rule(rule).await {
+            Ok(created) => {
+                log::info!("Rate limit rule {} {} through the monitoring API", id, if created { "added" } else { "updated" });
+                let status = if created { StatusCode::CREATED } else { StatusCode::OK };
+                json_response(status, json!({ "rule": id, "created": created }))
+            }
+            Err(e) => json_response(StatusCode::BAD_REQUEST, json!({ "error": e })),
+        }
+    }
+
+    fn handle_bans(&self, req: &Request<Incoming>) -> Response<Full<Bytes>> {
+        match *req.method() {
+            Method::GET => json_response(StatusCode::OK, json!({ "bans": self.bans.list() })),
+            Method::DELETE => {
+                let client = req.uri().query().and_then(|q| {
+                    url::form_urlencoded::parse(q.as_bytes())
+                        .find(|(key, _)| key == "client")
+                        .map(|(_, value)| value.into_owned())
+                });
+                let Some(client) = client else {
+                    return json_response(StatusCode::BAD_REQUEST, json!({ "error": "missing required query parameter 'client'" }));
+                };
+                if self.bans.lift(&client) {
+                    log::info!("Ban of {} lifted through the monitoring API", privacy::client_ip(&client));
+                    json_response(StatusCode::OK, json!({ "lifted": client }))
+                } else {
+                    json_response(StatusCode::NOT_FOUND, json!({ "error": format!("'{}' is not banned", client) }))
+                }
+            }
+            _ => json_response(StatusCode::METHOD_NOT_ALLOWED, json!({ "error": "use GET or DELETE" })),
+        }
+    }
+
+    /// Without `?tenant=`, lists tenant ids and routes. With it, shows that tenant's limits and
+    /// counters, which takes the tenant's `admin_token` as a bearer token when one is set.
+    async fn handle_tenants(&self, req: &Request<Incoming>) -> Response<Full<Bytes>> {
+        if req.method() != Method::GET {
+            return json_response(StatusCode::METHOD_NOT_ALLOWED, json!({ "error": "use GET" }));
+        }
+        let id = req.uri().query().and_then(|q| {
+            url::form_urlencoded::parse(q.as_bytes())
+                .find(|(key, _)| key == "tenant")
+                .map(|(_, value)| value.into_owned())
+        });
+        let Some(id) = id else {
+            return json_response(StatusCode::OK, json!({ "tenants": self.tenants.list() }));
+        };
+        let Some(tenant) = self.tenants.get(&id) else {
+            return json_response(StatusCode::NOT_FOUND, json!({ "error": format!("no tenant '{}'", id) }));
+        };
+        let token = req
+            .headers()
+            .get(hyper::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        if !tenant.authorizes(token) {
+            log::warn!("Refused tenant view of {} without its admin token", id);
+            return json_response(StatusCode::UNAUTHORIZED, json!({ "error": "tenant admin token required" }));
+        }
+        let payload = serde_json::to_value(tenant.view().await)
+            .unwrap_or_else(|e| json!({ "error": e.to_string() }));
+        json_response(StatusCode::OK, payload)
+    }
+
     fn aggregate_summary(&self) -> MetricsSummary {
         let mut summary = MetricsSummary {
             requests_total: 0,
@@ -196,9 +422,47 @@ struct ProxyHealth {
     average_response_time_ms: u64,
 }
 
+pub(crate) fn json_response(status: StatusCode, payload: serde_json::Value) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Full::new(Bytes::from(payload.to_string())))
+        .unwrap()
+}
+
 fn current_timestamp() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{free_addr, get, wait_for_listener};
+
+    #[tokio::test]
+    async fn metrics_json_and_dashboard_are_served_at_their_endpoints() {
+        let addr = free_addr().await;
+        let handles = MonitoringHandles::new();
+        handles.forward_metrics().increment_requests();
+        handles.forward_metrics().increment_requests();
+        let config = MonitoringConfig { listen_address: Some(addr), ..MonitoringConfig::default() };
+        tokio::spawn(MonitoringServer::new(config, handles).run());
+        wait_for_listener(addr).await.unwrap();
+
+        let metrics = get(addr, "/metrics.json").await;
+        assert_eq!(metrics.status(), StatusCode::OK);
+        assert_eq!(metrics.headers()["content-type"], "application/json");
+        let summary: serde_json::Value = serde_json::from_slice(metrics.body()).unwrap();
+        assert_eq!(summary["requests_total"], 2);
+        for field in ["response_bytes_total", "connections_active", "connection_errors", "average_response_time_ms", "timestamp"] {
+            assert!(summary[field].is_u64(), "{} in {}", field, summary);
+        }
+
+        let dashboard = get(addr, "/dashboard").await;
+        assert_eq!(dashboard.status(), StatusCode::OK);
+        assert_eq!(dashboard.headers()["content-type"], "text/html; charset=utf-8");
+    }
+}
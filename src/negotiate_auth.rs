@@ -0,0 +1,206 @@
+//! Kerberos/SPNEGO (`Negotiate`) proxy authentication.
+//!
+//! Tickets are accepted through the system GSSAPI library, which is only linked when the
+//! crate is built with the `spnego` feature. The acceptor credentials come from the
+//! default keytab (`KRB5_KTNAME`), which must contain the `HTTP/<proxy-host>` principal.
+//! Only single-leg exchanges are supported (plain Kerberos tickets); multi-leg SPNEGO
+//! negotiations such as NTLM fallback are rejected.
+
+use crate::config::NegotiateAuthConfig;
+use crate::error::ProxyError;
+use base64::{Engine as _, engine::general_purpose};
+
+pub struct NegotiateAuth {
+    /// Realm suffix stripped from principals before they are reported (e.g. `@CORP.EXAMPLE`)
+    strip_realm: Option<String>,
+}
+
+impl NegotiateAuth {
+    pub fn new(config: &NegotiateAuthConfig) -> Result<Self, ProxyError> {
+        if !cfg!(feature = "spnego") {
+            return Err(ProxyError::Config(
+                "negotiate_auth requires bifrost-bridge to be built with the 'spnego' feature".to_string(),
+            ));
+        }
+        Ok(Self {
+            strip_realm: config.strip_realm.clone(),
+        })
+    }
+
+    /// Validates the base64 token of a `Negotiate <token>` credential and returns the client principal.
+    pub fn authenticate(&self, encoded_token: &str) -> Result<String, ProxyError> {
+        let token = general_purpose::STANDARD.decode(encoded_token.trim())
+            .map_err(|_| ProxyError::Auth("Invalid base64 in Negotiate token".to_string()))?;
+        let principal = accept_token(&token)?;
+        Ok(self.display_principal(principal))
+    }
+
+    fn display_principal(&self, principal: String) -> String {
+        match &self.strip_realm {
+            Some(realm) => {
+                let suffix = format!("@{}", realm.trim_start_matches('@'));
+                principal.strip_suffix(&suffix).map(str::to_string).unwrap_or(principal)
+            }
+            None => principal,
+        }
+    }
+}
+
+#[cfg(not(feature = "spnego"))]
+fn accept_token(_token: &[u8]) -> Result<String, ProxyError> {
+    Err(ProxyError::Auth("Negotiate authentication is not available in this build".to_string()))
+}
+
+#[cfg(feature = "spnego")]
+fn accept_token(token: &[u8]) -> Result<String, ProxyError> {
+    gssapi::accept(token)
+}
+
+/// Minimal bindings to the MIT/Heimdal GSSAPI acceptor calls.
+#[cfg(feature = "spnego")]
+mod gssapi {
+    use crate::error::ProxyError;
+    use std::ffi::c_void;
+    use std::ptr;
+
+    type OmUint32 = u32;
+
+    #[repr(C)]
+    struct GssBufferDesc {
+        length: usize,
+        value: *mut c_void,
+    }
+
+    const GSS_S_COMPLETE: OmUint32 = 0;
+    const GSS_S_CONTINUE_NEEDED: OmUint32 = 1;
+
+    #[link(name = "gssapi_krb5")]
+    unsafe extern "C" {
+        fn gss_accept_sec_context(
+            minor_status: *mut OmUint32,
+            context_handle: *mut *mut c_void,
+            acceptor_cred_handle: *mut c_void,
+            input_token: *mut GssBufferDesc,
+            input_chan_bindings: *mut c_void,
+            src_name: *mut *mut c_void,
+            mech_type: *mut *mut c_void,
+            output_token: *mut GssBufferDesc,
+            ret_flags: *mut OmUint32,
+            time_rec: *mut OmUint32,
+            delegated_cred_handle: *mut *mut c_void,
+        ) -> OmUint32;
+
+        fn gss_display_name(
+            minor_status: *mut OmUint32,
+            input_name: *mut c_void,
+            output_name_buffer: *mut GssBufferDesc,
+            output_name_type: *mut *mut c_void,
+        ) -> OmUint32;
+
+        fn gss_release_buffer(minor_status: *mut OmUint32, buffer: *mut GssBufferDesc) -> OmUint32;
+
+        fn gss_release_name(minor_status: *mut OmUint32, name: *mut *mut c_void) -> OmUint32;
+
+        fn gss_delete_sec_context(
+            minor_status: *mut OmUint32,
+            context_handle: *mut *mut c_void,
+            output_token: *mut GssBufferDesc,
+        ) -> OmUint32;
+    }
+
+    pub(super) fn accept(token: &[u8]) -> Result<String, ProxyError> {
+        let mut minor: OmUint32 = 0;
+        let mut context: *mut c_void = ptr::null_mut();
+        let mut src_name: *mut c_void = ptr::null_mut();
+        let mut input = GssBufferDesc {
+            length: token.len(),
+            value: token.as_ptr() as *mut c_void,
+        };
+        let mut output = GssBufferDesc { length: 0, value: ptr::null_mut() };
+
+        // SAFETY: all pointers are valid for the duration of the call; the input buffer is
+        // only read by the library, and every handle it allocates is released below.
+        let major = unsafe {
+            gss_accept_sec_context(
+                &mut minor,
+                &mut context,
+                ptr::null_mut(),
+                &mut input,
+                ptr::null_mut(),
+                &mut src_name,
+                ptr::null_mut(),
+                &mut output,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+
+        let result = match major {
+            GSS_S_COMPLETE => display_name(src_name),
+            GSS_S_CONTINUE_NEEDED => Err(ProxyError::Auth(
+                "Multi-step Negotiate exchanges are not supported".to_string(),
+            )),
+            _ => Err(ProxyError::Auth(format!(
+                "Negotiate token rejected (major {:#x}, minor {})",
+                major, minor
+            ))),
+        };
+
+        // SAFETY: releasing handles allocated by gss_accept_sec_context; null handles are no-ops.
+        unsafe {
+            let mut ignored: OmUint32 = 0;
+            if !output.value.is_null() {
+                gss_release_buffer(&mut ignored, &mut output);
+            }
+            if !src_name.is_null() {
+                gss_release_name(&mut ignored, &mut src_name);
+            }
+            if !context.is_null() {
+                gss_delete_sec_context(&mut ignored, &mut context, ptr::null_mut());
+            }
+        }
+
+        result
+    }
+
+    fn display_name(name: *mut c_void) -> Result<String, ProxyError> {
+        let mut minor: OmUint32 = 0;
+        let mut buffer = GssBufferDesc { length: 0, value: ptr::null_mut() };
+
+        // SAFETY: `name` is a valid name handle returned by gss_accept_sec_context.
+        let major = unsafe { gss_display_name(&mut minor, name, &mut buffer, ptr::null_mut()) };
+        if major != GSS_S_COMPLETE || buffer.value.is_null() {
+            return Err(ProxyError::Auth("Unable to read Negotiate client principal".to_string()));
+        }
+
+        // SAFETY: the library guarantees `buffer.value` points to `buffer.length` bytes.
+        let principal = unsafe {
+            let bytes = std::slice::from_raw_parts(buffer.value as *const u8, buffer.length);
+            let principal = String::from_utf8_lossy(bytes).into_owned();
+            gss_release_buffer(&mut minor, &mut buffer);
+            principal
+        };
+        Ok(principal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(feature = "spnego"))]
+    #[test]
+    fn requires_spnego_feature() {
+        let config = NegotiateAuthConfig { enabled: true, strip_realm: None };
+        assert!(matches!(NegotiateAuth::new(&config), Err(ProxyError::Config(_))));
+    }
+
+    #[test]
+    fn strips_configured_realm() {
+        let auth = NegotiateAuth { strip_realm: Some("CORP.EXAMPLE".to_string()) };
+        assert_eq!(auth.display_principal("alice@CORP.EXAMPLE".to_string()), "alice");
+        assert_eq!(auth.display_principal("bob@OTHER.EXAMPLE".to_string()), "bob@OTHER.EXAMPLE");
+        assert!(auth.authenticate("not base64!").is_err());
+    }
+}
@@ -0,0 +1,427 @@
+//! Operational event notifications.
+//!
+//! Events an operator should hear about without watching the logs (a target going offline, a
+//! certificate about to expire, a rejected rules file, ...) are POSTed as JSON to the webhooks in
+//! `notifications`. Each webhook has its own queue and delivery task, retries failed deliveries
+//! with exponential backoff and sends at most `max_per_minute` notifications; the ones over the
+//! budget are dropped and counted in the next notification that goes out.
+
+use crate::redact::redact_url;
+use crate::config::{NotificationsConfig, WebhookConfig, WebhookFormat};
+use crate::error::ProxyError;
+use bytes::Bytes;
+use chrono::Utc;
+use http_body_util::Full;
+use hyper::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use hyper::{Method, Request, Uri};
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use log::{debug, warn};
+use prometheus::{IntCounterVec, Opts, Registry};
+use serde_json::{Map, Value, json};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Notifications waiting for delivery per webhook; beyond it new ones are dropped.
+const QUEUE_SIZE: usize = 256;
+
+/// What happened; the `event` field, the names accepted in `events` and the metric label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    /// Active health check found a target offline
+    TargetUnhealthy,
+    /// Active health check found an offline target back
+    TargetHealthy,
+    /// Outlier detection stopped sending traffic to a target for a while
+    TargetEjected,
+    /// A served certificate expires within the warning window, or has expired
+    CertificateExpiring,
+    /// A served certificate file now holds a certificate that expires later
+    CertificateRenewed,
+    /// Rules were reloaded from a file
+    ConfigReloaded,
+    /// Reloaded rules or keys were rejected; the previous ones stay in effect
+    ConfigReloadFailed,
+    /// Open file descriptors reached the warning ratio of the limit
+    ResourcesCritical,
+    /// A `{secret}` reference read a new value on refresh
+    SecretRotated,
+    /// A `{secret}` reference could not be read on refresh; the previous value stays in effect
+    SecretRefreshFailed,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 10] = [
+        NotificationKind::TargetUnhealthy,
+        NotificationKind::TargetHealthy,
+        NotificationKind::TargetEjected,
+        NotificationKind::CertificateExpiring,
+        NotificationKind::CertificateRenewed,
+        NotificationKind::ConfigReloaded,
+        NotificationKind::ConfigReloadFailed,
+        NotificationKind::ResourcesCritical,
+        NotificationKind::SecretRotated,
+        NotificationKind::SecretRefreshFailed,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NotificationKind::TargetUnhealthy => "target_unhealthy",
+            NotificationKind::TargetHealthy => "target_healthy",
+            NotificationKind::TargetEjected => "target_ejected",
+            NotificationKind::CertificateExpiring => "certificate_expiring",
+            NotificationKind::CertificateRenewed => "certificate_renewed",
+            NotificationKind::ConfigReloaded => "config_reloaded",
+            NotificationKind::ConfigReloadFailed => "config_reload_failed",
+            NotificationKind::ResourcesCritical => "resources_critical",
+            NotificationKind::SecretRotated => "secret_rotated",
+            NotificationKind::SecretRefreshFailed => "secret_refresh_failed",
+        }
+    }
+
+    fn severity(self) -> &'static str {
+        match self {
+            NotificationKind::TargetHealthy
+            | NotificationKind::CertificateRenewed
+            | NotificationKind::ConfigReloaded
+            | NotificationKind::SecretRotated => "info",
+            _ => "warning",
+        }
+    }
+}
+
+/// One notification, built up with the details that apply and then sent.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    kind: NotificationKind,
+    message: String,
+    details: Map<String, Value>,
+    timestamp: String,
+}
+
+impl Notification {
+    pub fn new(kind: NotificationKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            details: Map::new(),
+            timestamp: Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+        }
+    }
+
+    pub fn detail(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.details.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Queues the notification for every webhook that wants it; a no-op without `notifications`.
+    pub fn send(self) {
+        let Some(notifier) = NOTIFIER.get() else {
+            return;
+        };
+        for webhook in &notifier.webhooks {
+            if !webhook.events.is_empty() && !webhook.events.contains(&self.kind) {
+                continue;
+            }
+            if webhook.queue.try_send(self.clone()).is_err() {
+                notification_telemetry().record(self.kind, "dropped");
+            }
+        }
+    }
+
+    fn to_json(&self, suppressed: u64) -> Value {
+        let mut body = Map::new();
+        body.insert("timestamp".to_string(), json!(self.timestamp));
+        body.insert("event".to_string(), json!(self.kind.as_str()));
+        body.insert("severity".to_string(), json!(self.kind.severity()));
+        if let Ok(host) = std::env::var("HOSTNAME") {
+            body.insert("host".to_string(), json!(host));
+        }
+        body.insert("message".to_string(), json!(self.message));
+        body.extend(self.details.clone());
+        if suppressed > 0 {
+            body.insert("suppressed".to_string(), json!(suppressed));
+        }
+        Value::Object(body)
+    }
+
+    /// Slack incoming-webhook payload: one line of text with the details after the message.
+    fn to_slack(&self, suppressed: u64) -> Value {
+        let icon = if self.kind.severity() == "warning" { ":warning:" } else { ":information_source:" };
+        let mut text = format!("{} *{}*: {}", icon, self.kind.as_str(), self.message);
+        for (name, value) in &self.details {
+            let value = value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string());
+            text.push_str(&format!("\n{}: {}", name, value));
+        }
+        if suppressed > 0 {
+            text.push_str(&format!("\n({} earlier notification(s) suppressed)", suppressed));
+        }
+        json!({ "text": text })
+    }
+}
+
+static NOTIFIER: OnceLock<Notifier> = OnceLock::new();
+
+struct Notifier {
+    webhooks: Vec<WebhookQueue>,
+}
+
+struct WebhookQueue {
+    /// Kinds this webhook receives; all when empty
+    events: Vec<NotificationKind>,
+    queue: mpsc::Sender<Notification>,
+}
+
+/// Starts a delivery task per webhook; notifications sent before this call are discarded.
+pub fn init(config: &NotificationsConfig) -> Result<(), ProxyError> {
+    if config.timeout_secs == 0 {
+        return Err(ProxyError::Config("notifications.timeout_secs must be greater than 0".to_string()));
+    }
+    let client = Client::builder(TokioExecutor::new()).build(HttpsConnector::new());
+    let mut webhooks = Vec::new();
+    for webhook in &config.webhooks {
+        let events = webhook
+            .events
+            .iter()
+            .map(|name| {
+                NotificationKind::ALL
+                    .into_iter()
+                    .find(|kind| kind.as_str() == name)
+                    .ok_or_else(|| ProxyError::Config(format!("notifications: unknown event '{}'", name)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let delivery = Webhook::new(webhook, config, client.clone())?;
+        let (queue, receiver) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(delivery.run(receiver));
+        webhooks.push(WebhookQueue { events, queue });
+    }
+    if NOTIFIER.set(Notifier { webhooks }).is_err() {
+        warn!("Notifications already initialized; keeping the first configuration");
+    }
+    Ok(())
+}
+
+struct Webhook {
+    url: Uri,
+    format: WebhookFormat,
+    headers: HeaderMap,
+    budget: Budget,
+    retries: u32,
+    timeout: Duration,
+    client: Client<HttpsConnector<HttpConnector>, Full<Bytes>>,
+}
+
+impl Webhook {
+    fn new(
+        config: &WebhookConfig,
+        settings: &NotificationsConfig,
+        client: Client<HttpsConnector<HttpConnector>, Full<Bytes>>,
+    ) -> Result<Self, ProxyError> {
+        let url: Uri = config.url.parse()
+            .map_err(|e| ProxyError::Config(format!("notifications: invalid webhook URL '{}': {}", redact_url(&config.url), e)))?;
+        if !matches!(url.scheme_str(), Some("http" | "https")) {
+            return Err(ProxyError::Config(format!("notifications: webhook URL '{}' must use http:// or https://", redact_url(&config.url))));
+        }
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| ProxyError::Config(format!("notifications: invalid header name '{}'", name)))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| ProxyError::Config(format!("notifications: invalid value for header '{}'", name)))?;
+            headers.insert(name, value);
+        }
+        Ok(Self {
+            url,
+            format: config.format,
+            headers,
+            budget: Budget::new(settings.max_per_minute),
+            retries: settings.retries,
+            timeout: Duration::from_secs(settings.timeout_secs),
+            client,
+        })
+    }
+
+    async fn run(mut self, mut receiver: mpsc::Receiver<Notification>) {
+        while let Some(notification) = receiver.recv().await {
+            let Some(suppressed) = self.budget.admit(Instant::now()) else {
+                notification_telemetry().record(notification.kind, "suppressed");
+                continue;
+            };
+            let body = match self.format {
+                WebhookFormat::Json => notification.to_json(suppressed),
+                WebhookFormat::Slack => notification.to_slack(suppressed),
+            };
+            match self.deliver(Bytes::from(body.to_string())).await {
+                Ok(()) => {
+                    debug!("Sent {} notification to {}", notification.kind.as_str(), redact_url(&self.url.to_string()));
+                    notification_telemetry().record(notification.kind, "sent");
+                }
+                Err(e) => {
+                    warn!("Failed to send {} notification to {}: {}", notification.kind.as_str(), redact_url(&self.url.to_string()), e);
+                    notification_telemetry().record(notification.kind, "failed");
+                }
+            }
+        }
+    }
+
+    /// POSTs `body`, retrying transport errors, 429 and 5xx with exponential backoff.
+    async fn deliver(&self, body: Bytes) -> Result<(), String> {
+        let mut attempt = 0;
+        loop {
+            match self.post(body.clone()).await {
+                Ok(()) => return Ok(()),
+                Err((true, _)) if attempt < self.retries => {
+                    tokio::time::sleep(Duration::from_secs(1 << attempt.min(6))).await;
+                    attempt += 1;
+                }
+                Err((_, reason)) => return Err(reason),
+            }
+        }
+    }
+
+    /// Error side: whether a retry may succeed, and why the attempt failed.
+    async fn post(&self, body: Bytes) -> Result<(), (bool, String)> {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(self.url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::new(body))
+            .map_err(|e| (false, e.to_string()))?;
+        request.headers_mut().extend(self.headers.clone());
+
+        let response = tokio::time::timeout(self.timeout, self.client.request(request))
+            .await
+            .map_err(|_| (true, format!("timed out after {}s", self.timeout.as_secs())))?
+            .map_err(|e| (true, e.to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err((status.is_server_error() || status.as_u16() == 429, format!("webhook answered {}", status)))
+        }
+    }
+}
+
+/// Fixed one-minute window of deliveries for one webhook.
+struct Budget {
+    max_per_minute: u32,
+    window_start: Option<Instant>,
+    sent: u32,
+    suppressed: u64,
+}
+
+impl Budget {
+    fn new(max_per_minute: u32) -> Self {
+        Self { max_per_minute, window_start: None, sent: 0, suppressed: 0 }
+    }
+
+    /// Whether a notification may go out now; if so, how many were suppressed since the last one.
+    fn admit(&mut self, now: Instant) -> Option<u64> {
+        if self.max_per_minute == 0 {
+            return Some(0);
+        }
+        if self.window_start.is_none_or(|start| now.saturating_duration_since(start) >= Duration::from_secs(60)) {
+            self.window_start = Some(now);
+            self.sent = 0;
+        }
+        if self.sent >= self.max_per_minute {
+            self.suppressed += 1;
+            return None;
+        }
+        self.sent += 1;
+        Some(std::mem::take(&mut self.suppressed))
+    }
+}
+
+struct NotificationTelemetry {
+    notifications: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl NotificationTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "notifications_total",
+            "Webhook notifications by event and outcome (sent, failed, suppressed, dropped)",
+        ).namespace("bifrost");
+        Self {
+            notifications: IntCounterVec::new(opts, &["event", "outcome"]).expect("notifications_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.notifications.clone())) {
+            warn!("Failed to register notifications_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    fn record(&self, kind: NotificationKind, outcome: &str) {
+        self.notifications.with_label_values(&[kind.as_str(), outcome]).inc();
+    }
+}
+
+fn notification_telemetry() -> &'static NotificationTelemetry {
+    static TELEMETRY: OnceLock<NotificationTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(NotificationTelemetry::new)
+}
+
+/// Registers the notification counter with the shared Prometheus registry.
+pub fn register_notification_metrics(registry: &Registry) {
+    notification_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_notifications_and_limits_their_rate() {
+        let notification = Notification::new(NotificationKind::TargetUnhealthy, "Target 'api-1' is offline")
+            .detail("route", "api")
+            .detail("target", "api-1");
+
+        let body = notification.to_json(0);
+        assert_eq!(body["event"], "target_unhealthy");
+        assert_eq!(body["severity"], "warning");
+        assert_eq!(body["route"], "api");
+        assert!(body.get("suppressed").is_none());
+        assert_eq!(notification.to_json(4)["suppressed"], 4);
+
+        let slack = notification.to_slack(2);
+        assert_eq!(
+            slack["text"],
+            ":warning: *target_unhealthy*: Target 'api-1' is offline\nroute: api\ntarget: api-1\n(2 earlier notification(s) suppressed)"
+        );
+
+        let mut budget = Budget::new(2);
+        let start = Instant::now();
+        assert_eq!(budget.admit(start), Some(0));
+        assert_eq!(budget.admit(start), Some(0));
+        assert_eq!(budget.admit(start + Duration::from_secs(10)), None);
+        assert_eq!(budget.admit(start + Duration::from_secs(20)), None);
+        assert_eq!(budget.admit(start + Duration::from_secs(60)), Some(2), "a new window reports what was dropped");
+        assert_eq!(budget.admit(start + Duration::from_secs(61)), Some(0));
+        let mut unlimited = Budget::new(0);
+        assert!((0..100).all(|_| unlimited.admit(start) == Some(0)));
+
+        let unknown = NotificationsConfig {
+            webhooks: vec![WebhookConfig {
+                url: "http://127.0.0.1:9/hooks".to_string(),
+                format: WebhookFormat::Json,
+                events: vec!["target_down".to_string()],
+                headers: Default::default(),
+            }],
+            ..NotificationsConfig::default()
+        };
+        assert!(init(&unknown).is_err(), "event names are checked");
+    }
+}
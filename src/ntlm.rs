@@ -0,0 +1,465 @@
+//! NTLMv2 client authentication towards upstream relay proxies (MS-NLMP).
+//!
+//! NTLM authenticates a connection rather than a request: the negotiate / challenge /
+//! authenticate exchange has to happen on the TCP connection that then carries the
+//! proxied request, so every relay connection runs its own [`NtlmHandshake`].
+//! Only NTLMv2 responses are produced; signing and sealing are not negotiated.
+
+use crate::error::ProxyError;
+use base64::{Engine as _, engine::general_purpose};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SIGNATURE: &[u8; 8] = b"NTLMSSP\0";
+
+const NEGOTIATE_UNICODE: u32 = 0x0000_0001;
+const REQUEST_TARGET: u32 = 0x0000_0004;
+const NEGOTIATE_NTLM: u32 = 0x0000_0200;
+const NEGOTIATE_ALWAYS_SIGN: u32 = 0x0000_8000;
+const NEGOTIATE_EXTENDED_SESSIONSECURITY: u32 = 0x0008_0000;
+const NEGOTIATE_TARGET_INFO: u32 = 0x0080_0000;
+const NEGOTIATE_128: u32 = 0x2000_0000;
+
+const CLIENT_FLAGS: u32 = NEGOTIATE_UNICODE
+    | REQUEST_TARGET
+    | NEGOTIATE_NTLM
+    | NEGOTIATE_ALWAYS_SIGN
+    | NEGOTIATE_EXTENDED_SESSIONSECURITY
+    | NEGOTIATE_TARGET_INFO
+    | NEGOTIATE_128;
+
+/// `MsvAvTimestamp` attribute of the challenge target info.
+const AV_TIMESTAMP: u16 = 7;
+const AV_EOL: u16 = 0;
+
+/// Seconds between 1601-01-01 (FILETIME epoch) and the Unix epoch.
+const FILETIME_UNIX_OFFSET_SECS: u64 = 11_644_473_600;
+
+/// Relay credentials for NTLM; `DOMAIN\user` usernames carry the domain.
+#[derive(Clone)]
+pub struct NtlmCredentials {
+    domain: String,
+    username: String,
+    password: String,
+}
+
+impl NtlmCredentials {
+    pub fn new(username: &str, password: &str) -> Self {
+        let (domain, username) = username.split_once('\\').unwrap_or(("", username));
+        Self {
+            domain: domain.to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Debug for NtlmCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NtlmCredentials")
+            .field("domain", &self.domain)
+            .field("username", &self.username)
+            .field("password", &"***")
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HandshakeState {
+    Initial,
+    Negotiated,
+    Authenticated,
+}
+
+/// Authentication state of a single relay connection.
+pub struct NtlmHandshake<'a> {
+    credentials: &'a NtlmCredentials,
+    state: HandshakeState,
+}
+
+impl<'a> NtlmHandshake<'a> {
+    pub fn new(credentials: &'a NtlmCredentials) -> Self {
+        Self {
+            credentials,
+            state: HandshakeState::Initial,
+        }
+    }
+
+    /// `Proxy-Authorization` value for the first (negotiate) leg.
+    pub fn negotiate(&mut self) -> String {
+        self.state = HandshakeState::Negotiated;
+        let mut message = Vec::with_capacity(32);
+        message.extend_from_slice(SIGNATURE);
+        message.extend_from_slice(&1u32.to_le_bytes());
+        message.extend_from_slice(&CLIENT_FLAGS.to_le_bytes());
+        // Empty domain and workstation security buffers
+        message.extend_from_slice(&[0u8; 16]);
+        format!("NTLM {}", general_purpose::STANDARD.encode(message))
+    }
+
+    /// `Proxy-Authorization` value answering the relay's `Proxy-Authenticate: NTLM <challenge>`.
+    pub fn authenticate(&mut self, proxy_authenticate: &str) -> Result<String, ProxyError> {
+        if self.state != HandshakeState::Negotiated {
+            return Err(ProxyError::Auth("NTLM challenge received out of sequence".to_string()));
+        }
+        let encoded = proxy_authenticate
+            .trim()
+            .strip_prefix("NTLM ")
+            .ok_or_else(|| ProxyError::Auth("Relay did not send an NTLM challenge".to_string()))?;
+        let raw = general_purpose::STANDARD.decode(encoded.trim())
+            .map_err(|_| ProxyError::Auth("Invalid base64 in NTLM challenge".to_string()))?;
+        let challenge = ChallengeMessage::parse(&raw)?;
+
+        let mut client_challenge = [0u8; 8];
+        SystemRandom::new().fill(&mut client_challenge)
+            .map_err(|_| ProxyError::Auth("System random generator unavailable".to_string()))?;
+
+        let message = self.authenticate_message(&challenge, client_challenge, current_filetime());
+        self.state = HandshakeState::Authenticated;
+        Ok(format!("NTLM {}", general_purpose::STANDARD.encode(message)))
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.state == HandshakeState::Authenticated
+    }
+
+    fn authenticate_message(&self, challenge: &ChallengeMessage, client_challenge: [u8; 8], now: u64) -> Vec<u8> {
+        let credentials = self.credentials;
+        let response_key = ntowf_v2(&credentials.username, &credentials.domain, &credentials.password);
+        let timestamp = challenge.timestamp.unwrap_or(now);
+        let nt_response = ntlmv2_response(
+            &response_key,
+            &challenge.server_challenge,
+            &client_challenge,
+            timestamp,
+            &challenge.target_info,
+        );
+        // With a server timestamp the LM response must be zeroed (MS-NLMP 3.1.5.1.2)
+        let lm_response = if challenge.timestamp.is_some() {
+            vec![0u8; 24]
+        } else {
+            let mut data = challenge.server_challenge.to_vec();
+            data.extend_from_slice(&client_challenge);
+            let mut lm = hmac_md5(&response_key, &data).to_vec();
+            lm.extend_from_slice(&client_challenge);
+            lm
+        };
+
+        let domain = utf16le(&credentials.domain);
+        let user = utf16le(&credentials.username);
+        let fields: [&[u8]; 6] = [&lm_response, &nt_response, &domain, &user, &[], &[]];
+
+        let header_len = 64u32;
+        let mut message = Vec::new();
+        message.extend_from_slice(SIGNATURE);
+        message.extend_from_slice(&3u32.to_le_bytes());
+        let mut offset = header_len;
+        for field in fields {
+            message.extend_from_slice(&(field.len() as u16).to_le_bytes());
+            message.extend_from_slice(&(field.len() as u16).to_le_bytes());
+            message.extend_from_slice(&offset.to_le_bytes());
+            offset += field.len() as u32;
+        }
+        message.extend_from_slice(&(challenge.flags & CLIENT_FLAGS).to_le_bytes());
+        for field in fields {
+            message.extend_from_slice(field);
+        }
+        message
+    }
+}
+
+/// Fields of a CHALLENGE_MESSAGE needed to build the NTLMv2 response.
+struct ChallengeMessage {
+    flags: u32,
+    server_challenge: [u8; 8],
+    target_info: Vec<u8>,
+    timestamp: Option<u64>,
+}
+
+impl ChallengeMessage {
+    fn parse(raw: &[u8]) -> Result<Self, ProxyError> {
+        let malformed = || ProxyError::Auth("Malformed NTLM challenge".to_string());
+        if raw.len() < 32 || &raw[..8] != SIGNATURE || u32_at(raw, 8) != 2 {
+            return Err(malformed());
+        }
+
+        let flags = u32_at(raw, 20);
+        let server_challenge: [u8; 8] = raw[24..32].try_into().map_err(|_| malformed())?;
+
+        let target_info = if raw.len() >= 48 {
+            let len = u16::from_le_bytes([raw[40], raw[41]]) as usize;
+            let start = u32_at(raw, 44) as usize;
+            raw.get(start..start + len).ok_or_else(malformed)?.to_vec()
+        } else {
+            Vec::new()
+        };
+
+        let timestamp = find_av_pair(&target_info, AV_TIMESTAMP)
+            .and_then(|value| value.try_into().ok())
+            .map(u64::from_le_bytes);
+
+        Ok(Self {
+            flags,
+            server_challenge,
+            target_info,
+            timestamp,
+        })
+    }
+}
+
+fn find_av_pair(target_info: &[u8], id: u16) -> Option<&[u8]> {
+    let mut rest = target_info;
+    while rest.len() >= 4 {
+        let av_id = u16::from_le_bytes([rest[0], rest[1]]);
+        let len = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        if av_id == AV_EOL {
+            return None;
+        }
+        let value = rest.get(4..4 + len)?;
+        if av_id == id {
+            return Some(value);
+        }
+        rest = &rest[4 + len..];
+    }
+    None
+}
+
+fn u32_at(raw: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([raw[offset], raw[offset + 1], raw[offset + 2], raw[offset + 3]])
+}
+
+fn utf16le(value: &str) -> Vec<u8> {
+    value.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn current_filetime() -> u64 {
+    let unix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    (unix + FILETIME_UNIX_OFFSET_SECS) * 10_000_000
+}
+
+/// NTOWFv2: HMAC-MD5 keyed with the NT hash over `UPPER(user) || domain`.
+fn ntowf_v2(username: &str, domain: &str, password: &str) -> [u8; 16] {
+    let nt_hash = md4(&utf16le(password));
+    let identity = utf16le(&format!("{}{}", username.to_uppercase(), domain));
+    hmac_md5(&nt_hash, &identity)
+}
+
+/// NtChallengeResponse: `NTProofStr || temp` (MS-NLMP 3.3.2).
+fn ntlmv2_response(
+    response_key: &[u8; 16],
+    server_challenge: &[u8; 8],
+    client_challenge: &[u8; 8],
+    timestamp: u64,
+    target_info: &[u8],
+) -> Vec<u8> {
+    let mut temp = vec![0x01, 0x01, 0, 0, 0, 0, 0, 0];
+    temp.extend_from_slice(&timestamp.to_le_bytes());
+    temp.extend_from_slice(client_challenge);
+    temp.extend_from_slice(&[0u8; 4]);
+    temp.extend_from_slice(target_info);
+    temp.extend_from_slice(&[0u8; 4]);
+
+    let mut data = server_challenge.to_vec();
+    data.extend_from_slice(&temp);
+    let mut response = hmac_md5(response_key, &data).to_vec();
+    response.extend_from_slice(&temp);
+    response
+}
+
+fn hmac_md5(key: &[u8], data: &[u8]) -> [u8; 16] {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        block[..16].copy_from_slice(&md5(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner: Vec<u8> = block.iter().map(|byte| byte ^ 0x36).collect();
+    inner.extend_from_slice(data);
+    let mut outer: Vec<u8> = block.iter().map(|byte| byte ^ 0x5c).collect();
+    outer.extend_from_slice(&md5(&inner));
+    md5(&outer)
+}
+
+/// MD4/MD5 message padding: 0x80, zeros, then the bit length (little endian).
+fn md_padding(input: &[u8]) -> Vec<u8> {
+    let mut padded = input.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&((input.len() as u64).wrapping_mul(8)).to_le_bytes());
+    padded
+}
+
+fn words(block: &[u8]) -> [u32; 16] {
+    let mut words = [0u32; 16];
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    words
+}
+
+fn digest_bytes(state: [u32; 4]) -> [u8; 16] {
+    let mut out = [0u8; 16];
+    for (chunk, word) in out.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+/// MD4 (RFC 1320); only used for the NT password hash.
+fn md4(input: &[u8]) -> [u8; 16] {
+    let mut state: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+
+    for block in md_padding(input).chunks_exact(64) {
+        let x = words(block);
+        let [mut a, mut b, mut c, mut d] = state;
+
+        let f = |x: u32, y: u32, z: u32| (x & y) | (!x & z);
+        let g = |x: u32, y: u32, z: u32| (x & y) | (x & z) | (y & z);
+        let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+
+        for i in [0, 4, 8, 12] {
+            a = a.wrapping_add(f(b, c, d)).wrapping_add(x[i]).rotate_left(3);
+            d = d.wrapping_add(f(a, b, c)).wrapping_add(x[i + 1]).rotate_left(7);
+            c = c.wrapping_add(f(d, a, b)).wrapping_add(x[i + 2]).rotate_left(11);
+            b = b.wrapping_add(f(c, d, a)).wrapping_add(x[i + 3]).rotate_left(19);
+        }
+        for i in 0..4 {
+            a = a.wrapping_add(g(b, c, d)).wrapping_add(x[i]).wrapping_add(0x5a82_7999).rotate_left(3);
+            d = d.wrapping_add(g(a, b, c)).wrapping_add(x[i + 4]).wrapping_add(0x5a82_7999).rotate_left(5);
+            c = c.wrapping_add(g(d, a, b)).wrapping_add(x[i + 8]).wrapping_add(0x5a82_7999).rotate_left(9);
+            b = b.wrapping_add(g(c, d, a)).wrapping_add(x[i + 12]).wrapping_add(0x5a82_7999).rotate_left(13);
+        }
+        for i in [0, 2, 1, 3] {
+            a = a.wrapping_add(h(b, c, d)).wrapping_add(x[i]).wrapping_add(0x6ed9_eba1).rotate_left(3);
+            d = d.wrapping_add(h(a, b, c)).wrapping_add(x[i + 8]).wrapping_add(0x6ed9_eba1).rotate_left(9);
+            c = c.wrapping_add(h(d, a, b)).wrapping_add(x[i + 4]).wrapping_add(0x6ed9_eba1).rotate_left(11);
+            b = b.wrapping_add(h(c, d, a)).wrapping_add(x[i + 12]).wrapping_add(0x6ed9_eba1).rotate_left(15);
+        }
+
+        state = [
+            state[0].wrapping_add(a),
+            state[1].wrapping_add(b),
+            state[2].wrapping_add(c),
+            state[3].wrapping_add(d),
+        ];
+    }
+
+    digest_bytes(state)
+}
+
+/// MD5 (RFC 1321); only used inside HMAC-MD5 for NTLMv2.
+fn md5(input: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    let constants: [u32; 64] =
+        std::array::from_fn(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32);
+    let mut state: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+
+    for block in md_padding(input).chunks_exact(64) {
+        let x = words(block);
+        let [mut a, mut b, mut c, mut d] = state;
+
+        for i in 0..64 {
+            let (f, index) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let shift = SHIFTS[(i / 16) * 4 + i % 4];
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(constants[i])
+                .wrapping_add(x[index])
+                .rotate_left(shift);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+
+        state = [
+            state[0].wrapping_add(a),
+            state[1].wrapping_add(b),
+            state[2].wrapping_add(c),
+            state[3].wrapping_add(d),
+        ];
+    }
+
+    digest_bytes(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn hash_primitives_match_reference_vectors() {
+        assert_eq!(hex(&md4(b"abc")), "a448017aaf21d8525fc10ae87aa6729d");
+        assert_eq!(hex(&md4(b"")), "31d6cfe0d16ae931b73c59d7e0c089c0");
+        assert_eq!(hex(&md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            hex(&hmac_md5(b"Jefe", b"what do ya want for nothing?")),
+            "750c783e6ab0b503eaa86e310a5db738"
+        );
+    }
+
+    #[test]
+    fn ntlmv2_matches_ms_nlmp_example() {
+        // MS-NLMP 4.2.4: User / Domain / Password
+        let key = ntowf_v2("User", "Domain", "Password");
+        assert_eq!(hex(&key), "0c868a403bfd7a93a3001ef22ef02e3f");
+
+        let mut target_info = vec![0x02, 0x00, 0x0c, 0x00];
+        target_info.extend_from_slice(&utf16le("Domain"));
+        target_info.extend_from_slice(&[0x01, 0x00, 0x0c, 0x00]);
+        target_info.extend_from_slice(&utf16le("Server"));
+        target_info.extend_from_slice(&[0u8; 4]);
+
+        let server_challenge = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef];
+        let response = ntlmv2_response(&key, &server_challenge, &[0xaa; 8], 0, &target_info);
+        assert_eq!(hex(&response[..16]), "68cd0ab851e51c96aabc927bebef6a1c");
+    }
+
+    #[test]
+    fn handshake_builds_authenticate_message_from_challenge() {
+        let credentials = NtlmCredentials::new("CORP\\alice", "s3cret");
+        let mut handshake = NtlmHandshake::new(&credentials);
+        assert!(handshake.authenticate("NTLM AAAA").is_err());
+
+        let negotiate = handshake.negotiate();
+        let raw = general_purpose::STANDARD.decode(negotiate.strip_prefix("NTLM ").unwrap()).unwrap();
+        assert_eq!(&raw[..8], SIGNATURE);
+        assert_eq!(u32_at(&raw, 8), 1);
+
+        let mut challenge = SIGNATURE.to_vec();
+        challenge.extend_from_slice(&2u32.to_le_bytes());
+        challenge.extend_from_slice(&[0u8; 8]);
+        challenge.extend_from_slice(&CLIENT_FLAGS.to_le_bytes());
+        challenge.extend_from_slice(&[0x11; 8]);
+        challenge.extend_from_slice(&[0u8; 8]);
+        let header = format!("NTLM {}", general_purpose::STANDARD.encode(&challenge));
+
+        let authenticate = handshake.authenticate(&header).unwrap();
+        assert!(handshake.is_authenticated());
+        let raw = general_purpose::STANDARD.decode(authenticate.strip_prefix("NTLM ").unwrap()).unwrap();
+        assert_eq!(u32_at(&raw, 8), 3);
+
+        // UserName security buffer points at UTF-16LE "alice"
+        let len = u16::from_le_bytes([raw[36], raw[37]]) as usize;
+        let offset = u32_at(&raw, 40) as usize;
+        assert_eq!(&raw[offset..offset + len], utf16le("alice").as_slice());
+        let len = u16::from_le_bytes([raw[28], raw[29]]) as usize;
+        let offset = u32_at(&raw, 32) as usize;
+        assert_eq!(&raw[offset..offset + len], utf16le("CORP").as_slice());
+    }
+}
@@ -0,0 +1,303 @@
+//! Passive outlier detection for reverse proxy targets.
+//!
+//! Every forwarded request updates its target's rolling success rate, consecutive failure count
+//! and latency EWMA. The load balancer sets aside targets that fail too often or answer much
+//! slower than the fastest target, without waiting for an active health check to notice. A
+//! set-aside target gets traffic again with a clean slate once its ejection period ends.
+
+use crate::config::OutlierDetectionConfig;
+use crate::notifications::{Notification, NotificationKind};
+use log::{info, warn};
+use prometheus::{Gauge, GaugeVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use tokio::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutlierReason {
+    ConsecutiveFailures,
+    SuccessRate,
+    Latency,
+}
+
+impl OutlierReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            OutlierReason::ConsecutiveFailures => "consecutive_failures",
+            OutlierReason::SuccessRate => "success_rate",
+            OutlierReason::Latency => "latency",
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Most recent outcomes, `true` for success
+    window: VecDeque<bool>,
+    successes: usize,
+    consecutive_failures: u32,
+    latency_ewma_secs: Option<f64>,
+    ejected_until: Option<Instant>,
+    /// When the target last returned from an ejection
+    returned_at: Option<Instant>,
+}
+
+/// Rolling statistics for one target of one route.
+pub struct OutlierStats {
+    route: String,
+    target: String,
+    state: Mutex<State>,
+    success_rate: Gauge,
+    consecutive_failures: IntGauge,
+    latency_ewma: Gauge,
+}
+
+impl OutlierStats {
+    pub fn new(route: &str, target: &str) -> Self {
+        let telemetry = outlier_telemetry();
+        let labels = [route, target];
+        let success_rate = telemetry.success_rate.with_label_values(&labels);
+        success_rate.set(1.0);
+        Self {
+            route: route.to_string(),
+            target: target.to_string(),
+            state: Mutex::new(State::default()),
+            success_rate,
+            consecutive_failures: telemetry.consecutive_failures.with_label_values(&labels),
+            latency_ewma: telemetry.latency_ewma.with_label_values(&labels),
+        }
+    }
+
+    /// Records the outcome of one request; `success` is false for transport errors and 5xx.
+    pub fn record(&self, success: bool, latency: Duration, config: &OutlierDetectionConfig) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.window.push_back(success);
+        if success {
+            state.successes += 1;
+            state.consecutive_failures = 0;
+        } else {
+            state.consecutive_failures += 1;
+        }
+        while state.window.len() > config.window_requests.max(1) {
+            if state.window.pop_front() == Some(true) {
+                state.successes -= 1;
+            }
+        }
+
+        let sample = latency.as_secs_f64();
+        let alpha = config.latency_ewma_alpha.clamp(0.0, 1.0);
+        let ewma = match state.latency_ewma_secs {
+            Some(previous) => alpha * sample + (1.0 - alpha) * previous,
+            None => sample,
+        };
+        state.latency_ewma_secs = Some(ewma);
+
+        self.success_rate.set(state.successes as f64 / state.window.len() as f64);
+        self.consecutive_failures.set(state.consecutive_failures as i64);
+        self.latency_ewma.set(ewma);
+    }
+
+    /// Latency EWMA, once the target has served enough requests for it to mean something.
+    pub fn latency_ewma(&self, config: &OutlierDetectionConfig) -> Option<f64> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.window.len() < config.min_requests {
+            return None;
+        }
+        state.latency_ewma_secs
+    }
+
+    /// Share of successful recent requests, once the target has served enough of them.
+    pub fn success_rate(&self, config: &OutlierDetectionConfig) -> Option<f64> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.window.is_empty() || state.window.len() < config.min_requests {
+            return None;
+        }
+        Some(state.successes as f64 / state.window.len() as f64)
+    }
+
+    /// When the target last returned from an ejection, if it ever was ejected.
+    pub fn returned_at(&self) -> Option<Instant> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).returned_at
+    }
+
+    /// Whether the balancer should avoid this target. `fastest_latency` is the lowest latency
+    /// EWMA among the route's candidate targets.
+    pub fn is_ejected(&self, config: &OutlierDetectionConfig, fastest_latency: Option<f64>, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(until) = state.ejected_until {
+            if now < until {
+                return true;
+            }
+            *state = State { returned_at: Some(now), ..State::default() };
+            self.success_rate.set(1.0);
+            self.consecutive_failures.set(0);
+            info!("Target {} of route {} returns from outlier ejection", self.target, self.route);
+            return false;
+        }
+
+        let Some(reason) = Self::classify(&state, config, fastest_latency) else {
+            return false;
+        };
+        state.ejected_until = Some(now + Duration::from_secs(config.ejection_secs));
+        outlier_telemetry()
+            .ejections
+            .with_label_values(&[self.route.as_str(), self.target.as_str(), reason.as_str()])
+            .inc();
+        warn!(
+            "Target {} of route {} deprioritized for {}s ({})",
+            self.target,
+            self.route,
+            config.ejection_secs,
+            reason.as_str()
+        );
+        Notification::new(
+            NotificationKind::TargetEjected,
+            format!("Target {} of route {} gets no traffic for {}s", self.target, self.route, config.ejection_secs),
+        )
+        .detail("route", self.route.as_str())
+        .detail("target", self.target.as_str())
+        .detail("reason", reason.as_str())
+        .send();
+        true
+    }
+
+    fn classify(state: &State, config: &OutlierDetectionConfig, fastest_latency: Option<f64>) -> Option<OutlierReason> {
+        if config.consecutive_failures > 0 && state.consecutive_failures >= config.consecutive_failures {
+            return Some(OutlierReason::ConsecutiveFailures);
+        }
+        if state.window.len() < config.min_requests.max(1) {
+            return None;
+        }
+        if (state.successes as f64 / state.window.len() as f64) < config.min_success_rate {
+            return Some(OutlierReason::SuccessRate);
+        }
+        match (state.latency_ewma_secs, fastest_latency) {
+            (Some(latency), Some(fastest))
+                if config.slow_latency_factor > 0.0 && latency > fastest * config.slow_latency_factor =>
+            {
+                Some(OutlierReason::Latency)
+            }
+            _ => None,
+        }
+    }
+}
+
+struct OutlierTelemetry {
+    success_rate: GaugeVec,
+    consecutive_failures: IntGaugeVec,
+    latency_ewma: GaugeVec,
+    ejections: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl OutlierTelemetry {
+    fn new() -> Self {
+        let labels = &["route", "target"];
+        Self {
+            success_rate: GaugeVec::new(
+                Opts::new("upstream_success_rate", "Rolling share of successful requests per reverse proxy target")
+                    .namespace("bifrost"),
+                labels,
+            ).expect("upstream_success_rate metric"),
+            consecutive_failures: IntGaugeVec::new(
+                Opts::new("upstream_consecutive_failures", "Failed requests in a row per reverse proxy target")
+                    .namespace("bifrost"),
+                labels,
+            ).expect("upstream_consecutive_failures metric"),
+            latency_ewma: GaugeVec::new(
+                Opts::new("upstream_latency_ewma_seconds", "Exponentially weighted response latency per reverse proxy target")
+                    .namespace("bifrost"),
+                labels,
+            ).expect("upstream_latency_ewma_seconds metric"),
+            ejections: IntCounterVec::new(
+                Opts::new("upstream_outlier_ejections_total", "Times a reverse proxy target was deprioritized as an outlier")
+                    .namespace("bifrost"),
+                &["route", "target", "reason"],
+            ).expect("upstream_outlier_ejections_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        let collectors: [Box<dyn prometheus::core::Collector>; 4] = [
+            Box::new(self.success_rate.clone()),
+            Box::new(self.consecutive_failures.clone()),
+            Box::new(self.latency_ewma.clone()),
+            Box::new(self.ejections.clone()),
+        ];
+        for collector in collectors {
+            if let Err(err) = registry.register(collector) {
+                warn!("Failed to register outlier detection metric: {}", err);
+                return;
+            }
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn outlier_telemetry() -> &'static OutlierTelemetry {
+    static TELEMETRY: OnceLock<OutlierTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(OutlierTelemetry::new)
+}
+
+/// Registers the per-target outlier statistics with the shared Prometheus registry.
+pub fn register_outlier_metrics(registry: &Registry) {
+    outlier_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> OutlierDetectionConfig {
+        OutlierDetectionConfig { min_requests: 4, ..OutlierDetectionConfig::default() }
+    }
+
+    #[test]
+    fn consecutive_failures_eject_until_the_period_ends() {
+        let config = OutlierDetectionConfig::default();
+        let stats = OutlierStats::new("r", "failing");
+        let now = Instant::now();
+        for _ in 0..config.consecutive_failures - 1 {
+            stats.record(false, Duration::from_millis(5), &config);
+        }
+        assert!(!stats.is_ejected(&config, None, now));
+        stats.record(false, Duration::from_millis(5), &config);
+        assert!(stats.is_ejected(&config, None, now));
+        assert!(stats.is_ejected(&config, None, now + Duration::from_secs(1)));
+
+        let later = now + Duration::from_secs(config.ejection_secs);
+        assert!(!stats.is_ejected(&config, None, later));
+        assert_eq!(stats.consecutive_failures.get(), 0);
+    }
+
+    #[test]
+    fn low_success_rate_and_slow_latency_are_outliers() {
+        let config = config();
+        let now = Instant::now();
+
+        let flaky = OutlierStats::new("r", "flaky");
+        for success in [true, false, true, false, false, true, false, false] {
+            flaky.record(success, Duration::from_millis(5), &config);
+        }
+        assert_eq!(
+            OutlierStats::classify(&flaky.state.lock().unwrap(), &config, None),
+            Some(OutlierReason::SuccessRate)
+        );
+
+        let fast = OutlierStats::new("r", "fast");
+        let slow = OutlierStats::new("r", "slow");
+        for _ in 0..config.min_requests {
+            fast.record(true, Duration::from_millis(10), &config);
+            slow.record(true, Duration::from_millis(100), &config);
+        }
+        let fastest = fast.latency_ewma(&config);
+        assert!(!fast.is_ejected(&config, fastest, now));
+        assert!(slow.is_ejected(&config, fastest, now));
+    }
+}
@@ -0,0 +1,247 @@
+//! Canonical request paths.
+//!
+//! Route predicates, static mounts, tarpits and rate limit rules compare request paths as
+//! strings, so `/admin` can be reached unnoticed as `/public/../admin`, `//admin` or
+//! `/%61dmin` unless every spelling is first reduced to one. Before any of them sees a request,
+//! its path is rewritten: escapes of unreserved characters are decoded (RFC 3986 6.2.2.2), runs
+//! of slashes collapsed and dot segments resolved (RFC 3986 5.2.4). The backend receives the
+//! rewritten path, so it serves what the checks judged. The query string is left alone.
+
+use crate::config::{EncodedSlashes, PathNormalizationConfig, PercentDecoding};
+use crate::error::ProxyError;
+use hyper::{Request, Uri};
+use log::{info, warn};
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+static SETTINGS: OnceLock<PathNormalizationConfig> = OnceLock::new();
+
+/// Replaces the default normalization with the configured one.
+pub fn init(config: &PathNormalizationConfig) -> Result<(), ProxyError> {
+    if !config.enabled {
+        warn!("Path normalization is off; path-based routes and limits see paths as clients send them");
+    } else {
+        info!(
+            "Path normalization: percent decoding {:?}, encoded slashes {:?}, collapse slashes {}, resolve dot segments {}",
+            config.percent_decoding, config.encoded_slashes, config.collapse_slashes, config.resolve_dot_segments
+        );
+    }
+    if SETTINGS.set(config.clone()).is_err() {
+        warn!("Path normalization already initialized; keeping the first configuration");
+    }
+    Ok(())
+}
+
+fn settings() -> &'static PathNormalizationConfig {
+    SETTINGS.get_or_init(PathNormalizationConfig::default)
+}
+
+/// A path refused by `encoded_slashes: "reject"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RejectedPath;
+
+/// The canonical form of a request path under the configured normalization.
+pub fn normalize(path: &str) -> Result<Cow<'_, str>, RejectedPath> {
+    normalize_with(path, settings())
+}
+
+/// Rewrites the path of `req` into its canonical form, keeping the query.
+pub fn normalize_request<B>(req: &mut Request<B>) -> Result<(), RejectedPath> {
+    let path = match normalize(req.uri().path())? {
+        Cow::Borrowed(_) => return Ok(()),
+        Cow::Owned(path) => path,
+    };
+    let path_and_query = match req.uri().query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+    let mut parts = req.uri().clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().map_err(|_| RejectedPath)?);
+    *req.uri_mut() = Uri::from_parts(parts).map_err(|_| RejectedPath)?;
+    Ok(())
+}
+
+pub fn normalize_with<'a>(path: &'a str, config: &PathNormalizationConfig) -> Result<Cow<'a, str>, RejectedPath> {
+    // `*` of `OPTIONS *` and other non-origin forms have no segments to resolve
+    if !config.enabled || !path.starts_with('/') {
+        return Ok(Cow::Borrowed(path));
+    }
+
+    let mut normalized = decode(path, config)?;
+    if config.collapse_slashes && normalized.contains("//") {
+        normalized = collapse_slashes(&normalized);
+    }
+    if config.resolve_dot_segments {
+        normalized = resolve_dot_segments(&normalized);
+    }
+
+    if normalized == path {
+        Ok(Cow::Borrowed(path))
+    } else {
+        Ok(Cow::Owned(normalized))
+    }
+}
+
+fn decode(path: &str, config: &PathNormalizationConfig) -> Result<String, RejectedPath> {
+    let bytes = path.as_bytes();
+    let mut decoded = String::with_capacity(path.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let value = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        let Some(value) = value else {
+            // Everything up to the next `%` is copied as it is, so raw UTF-8 stays intact
+            let next = bytes[i + 1..].iter().position(|&byte| byte == b'%').map_or(bytes.len(), |at| i + 1 + at);
+            decoded.push_str(&path[i..next]);
+            i = next;
+            continue;
+        };
+        let raw = &path[i..i + 3];
+        i += 3;
+
+        if value == b'/' || value == b'\\' {
+            match config.encoded_slashes {
+                EncodedSlashes::Reject => return Err(RejectedPath),
+                EncodedSlashes::Decode => decoded.push('/'),
+                EncodedSlashes::Keep => push_escape(&mut decoded, raw, config.percent_decoding),
+            }
+        } else if config.percent_decoding == PercentDecoding::Unreserved && is_unreserved(value) {
+            decoded.push(value as char);
+        } else {
+            push_escape(&mut decoded, raw, config.percent_decoding);
+        }
+    }
+    Ok(decoded)
+}
+
+/// An escape left in place; `%2f` and `%2F` become the same path once decoding is on.
+fn push_escape(decoded: &mut String, raw: &str, decoding: PercentDecoding) {
+    match decoding {
+        PercentDecoding::None => decoded.push_str(raw),
+        PercentDecoding::Unreserved => decoded.push_str(&raw.to_ascii_uppercase()),
+    }
+}
+
+fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~')
+}
+
+fn collapse_slashes(path: &str) -> String {
+    let mut collapsed = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c != '/' || !collapsed.ends_with('/') {
+            collapsed.push(c);
+        }
+    }
+    collapsed
+}
+
+/// `.` segments are dropped and `..` removes the segment before it, never going above the root.
+/// A path ending in either keeps its trailing slash, as `/a/b/..` names the directory `/a/`.
+fn resolve_dot_segments(path: &str) -> String {
+    let segments: Vec<&str> = path[1..].split('/').collect();
+    let last = segments.len() - 1;
+    let mut resolved: Vec<&str> = Vec::with_capacity(segments.len());
+    for (index, segment) in segments.into_iter().enumerate() {
+        match segment {
+            "." => {}
+            ".." => {
+                resolved.pop();
+            }
+            _ => {
+                resolved.push(segment);
+                continue;
+            }
+        }
+        if index == last {
+            resolved.push("");
+        }
+    }
+    format!("/{}", resolved.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(path: &str) -> String {
+        normalize_with(path, &PathNormalizationConfig::default()).unwrap().into_owned()
+    }
+
+    #[test]
+    fn every_spelling_of_a_path_reduces_to_one() {
+        assert_eq!(normalized("/a//b/../c"), "/a/c");
+        assert_eq!(normalized("/public/../admin"), "/admin");
+        assert_eq!(normalized("//admin"), "/admin");
+        assert_eq!(normalized("/%61dmin/%7Euser"), "/admin/~user");
+        assert_eq!(normalized("/public/%2e%2E/admin"), "/admin");
+        assert_eq!(normalized("/./a/./b/."), "/a/b/");
+        assert_eq!(normalized("/a/b/.."), "/a/");
+        assert_eq!(normalized("/../../etc/passwd"), "/etc/passwd");
+        assert_eq!(normalized("/a/..b/.c"), "/a/..b/.c");
+        assert!(matches!(normalize_with("/api/users", &PathNormalizationConfig::default()), Ok(Cow::Borrowed(_))));
+    }
+
+    #[test]
+    fn reserved_escapes_stay_encoded() {
+        assert_eq!(normalized("/files/a%2fb%3f"), "/files/a%2Fb%3F");
+        assert_eq!(normalized("/files/a%2Fb/../c"), "/files/c");
+        assert_eq!(normalized("/bad%zz%4"), "/bad%zz%4");
+        assert_eq!(normalized("/caf%C3%A9"), "/caf%C3%A9");
+    }
+
+    #[test]
+    fn non_ascii_paths_are_left_alone() {
+        assert_eq!(normalized("/café/x"), "/café/x");
+        assert_eq!(normalized("/日本/./語//x"), "/日本/語/x");
+        assert!(matches!(normalize_with("/café/x", &PathNormalizationConfig::default()), Ok(Cow::Borrowed(_))));
+
+        for path in ["/café/x", "/caf%C3%A9/x"] {
+            let mut req = Request::get(path).body(()).unwrap();
+            normalize_request(&mut req).unwrap();
+            assert_eq!(req.uri().path().as_bytes(), path.as_bytes());
+        }
+    }
+
+    #[test]
+    fn encoded_slashes_follow_the_policy() {
+        let decode = PathNormalizationConfig { encoded_slashes: EncodedSlashes::Decode, ..PathNormalizationConfig::default() };
+        assert_eq!(normalize_with("/public%2F..%2Fadmin", &decode).unwrap(), "/admin");
+        assert_eq!(normalize_with("/a%5cb", &decode).unwrap(), "/a/b");
+
+        let reject = PathNormalizationConfig { encoded_slashes: EncodedSlashes::Reject, ..PathNormalizationConfig::default() };
+        assert_eq!(normalize_with("/a%2fb", &reject), Err(RejectedPath));
+        assert_eq!(normalize_with("/a%5Cb", &reject), Err(RejectedPath));
+        assert_eq!(normalize_with("/a/b", &reject).unwrap(), "/a/b");
+    }
+
+    #[test]
+    fn each_step_can_be_turned_off() {
+        let raw_escapes = PathNormalizationConfig { percent_decoding: PercentDecoding::None, ..PathNormalizationConfig::default() };
+        assert_eq!(normalize_with("/%2e%2e/%61", &raw_escapes).unwrap(), "/%2e%2e/%61");
+
+        let keep_slashes = PathNormalizationConfig { collapse_slashes: false, ..PathNormalizationConfig::default() };
+        assert_eq!(normalize_with("/a//b/../c", &keep_slashes).unwrap(), "/a//c");
+
+        let keep_dots = PathNormalizationConfig { resolve_dot_segments: false, ..PathNormalizationConfig::default() };
+        assert_eq!(normalize_with("/a//b/../c", &keep_dots).unwrap(), "/a/b/../c");
+
+        let off = PathNormalizationConfig { enabled: false, ..PathNormalizationConfig::default() };
+        assert_eq!(normalize_with("/a//b/../%63", &off).unwrap(), "/a//b/../%63");
+        assert_eq!(normalize_with("*", &PathNormalizationConfig::default()).unwrap(), "*");
+    }
+
+    #[test]
+    fn requests_keep_their_query() {
+        let mut req = Request::get("http://example.com/a/./b//c?x=/../y").body(()).unwrap();
+        normalize_request(&mut req).unwrap();
+        assert_eq!(req.uri().to_string(), "http://example.com/a/b/c?x=/../y");
+
+        let mut req = Request::get("/a/../b?q=1").body(()).unwrap();
+        normalize_request(&mut req).unwrap();
+        assert_eq!(req.uri().to_string(), "/b?q=1");
+    }
+}
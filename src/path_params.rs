@@ -0,0 +1,109 @@
+//! Named path variables and target URL templates.
+//!
+//! Path patterns may name their variables (`/users/{id}/orders/**`); `{*name}` captures the rest
+//! of the path, slashes included. A target URL that mentions variables (`http://orders/{id}`) is
+//! a template: it is filled in per request and defines the whole backend path, so the request
+//! path is not appended to it.
+
+use crate::error::ProxyError;
+use url::Url;
+
+/// Values captured from the request path, in pattern order.
+pub type PathParams = Vec<(String, String)>;
+
+/// Whether `name` can be used as a capture name (and therefore as a template variable).
+pub fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A target URL containing `{name}` placeholders.
+#[derive(Debug, Clone)]
+pub struct TargetTemplate {
+    raw: String,
+    variables: Vec<String>,
+}
+
+impl TargetTemplate {
+    /// Returns `None` for a plain URL without placeholders.
+    pub fn parse(raw: &str) -> Result<Option<Self>, ProxyError> {
+        let mut variables = Vec::new();
+        let mut rest = raw;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}').ok_or_else(|| {
+                ProxyError::Config(format!("Unclosed variable in target URL {}", raw))
+            })?;
+            let name = &rest[start + 1..start + end];
+            if !is_variable_name(name) {
+                return Err(ProxyError::Config(format!(
+                    "Invalid variable {{{}}} in target URL {}",
+                    name, raw
+                )));
+            }
+            variables.push(name.to_string());
+            rest = &rest[start + end + 1..];
+        }
+        if variables.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self { raw: raw.to_string(), variables }))
+    }
+
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// The template with every variable replaced by a stand-in, for validation and as the
+    /// target's nominal URL.
+    pub fn placeholder_url(&self) -> Result<Url, url::ParseError> {
+        let stand_ins: PathParams = self.variables.iter().map(|v| (v.clone(), v.clone())).collect();
+        Url::parse(&self.fill(&stand_ins))
+    }
+
+    /// Fills in the captured values; a variable the request did not capture becomes empty.
+    pub fn resolve(&self, params: &PathParams) -> Result<Url, ProxyError> {
+        let filled = self.fill(params);
+        Url::parse(&filled)
+            .map_err(|e| ProxyError::Uri(format!("Target template produced invalid URL {}: {}", filled, e)))
+    }
+
+    fn fill(&self, params: &PathParams) -> String {
+        let mut out = self.raw.clone();
+        for name in &self.variables {
+            let value = params
+                .iter()
+                .find(|(param, _)| param == name)
+                .map(|(_, value)| value.as_str())
+                .unwrap_or("");
+            out = out.replace(&format!("{{{}}}", name), value);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_templates_from_captured_params() {
+        assert!(TargetTemplate::parse("http://orders:8080").unwrap().is_none());
+        assert!(TargetTemplate::parse("http://orders/{id").is_err());
+        assert!(TargetTemplate::parse("http://orders/{1d}").is_err());
+
+        let template = TargetTemplate::parse("http://{tenant}.orders.svc/v1/{id}/{rest}").unwrap().unwrap();
+        assert_eq!(template.variables(), ["tenant", "id", "rest"]);
+        assert!(template.placeholder_url().is_ok());
+
+        let params = vec![
+            ("tenant".to_string(), "acme".to_string()),
+            ("id".to_string(), "42".to_string()),
+            ("rest".to_string(), "items/7".to_string()),
+        ];
+        assert_eq!(
+            template.resolve(&params).unwrap().as_str(),
+            "http://acme.orders.svc/v1/42/items/7"
+        );
+    }
+}
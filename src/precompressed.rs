@@ -0,0 +1,120 @@
+//! Precompressed sidecars of static files.
+//!
+//! Build tools for SPAs often emit `app.js.br` and `app.js.gz` next to `app.js`. When the
+//! client's `Accept-Encoding` accepts one of them, the sidecar is sent in place of the file with
+//! `Content-Encoding`, so nothing is compressed at request time. Brotli wins over gzip when the
+//! client rates both the same. Responses for a file that has sidecars carry
+//! `Vary: Accept-Encoding`, whichever representation was chosen.
+
+use http::HeaderMap;
+use http::header::ACCEPT_ENCODING;
+use std::ffi::OsString;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+
+/// Content codings with a sidecar, in order of preference, and the suffix of their files.
+const ENCODINGS: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+/// A sidecar file of one content coding.
+#[derive(Debug)]
+pub struct Variant {
+    pub path: PathBuf,
+    pub encoding: &'static str,
+    pub metadata: Metadata,
+}
+
+/// The sidecars present next to `path`, in order of preference.
+pub fn variants(path: &Path) -> Vec<Variant> {
+    ENCODINGS
+        .iter()
+        .filter_map(|(encoding, suffix)| {
+            let mut name = OsString::from(path.as_os_str());
+            name.push(".");
+            name.push(suffix);
+            let path = PathBuf::from(name);
+            let metadata = fs::metadata(&path).ok().filter(|m| m.is_file())?;
+            Some(Variant { path, encoding, metadata })
+        })
+        .collect()
+}
+
+/// The sidecar to send for a request: the one the client rates highest, earlier ones on a tie.
+/// `None` when the client accepts none of them.
+pub fn negotiate<'a>(headers: &HeaderMap, variants: &'a [Variant]) -> Option<&'a Variant> {
+    let accept = headers.get(ACCEPT_ENCODING)?.to_str().ok()?;
+    let mut best: Option<(&Variant, f32)> = None;
+    for variant in variants {
+        let quality = quality(accept, variant.encoding);
+        if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+            best = Some((variant, quality));
+        }
+    }
+    best.map(|(variant, _)| variant)
+}
+
+/// The q-value `Accept-Encoding` gives `encoding`; `*` covers codings it does not name.
+fn quality(accept: &str, encoding: &str) -> f32 {
+    let mut wildcard = 0.0;
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or("").trim();
+        let q = parts
+            .filter_map(|param| param.trim().strip_prefix("q=").or_else(|| param.trim().strip_prefix("Q=")))
+            .find_map(|value| value.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if coding.eq_ignore_ascii_case(encoding) || (encoding == "gzip" && coding.eq_ignore_ascii_case("x-gzip")) {
+            return q;
+        }
+        if coding == "*" {
+            wildcard = q;
+        }
+    }
+    wildcard
+}
+
+/// The `ETag` of a sidecar: the tag its own size and mtime give, marked with the coding so it
+/// never equals the tag of another representation.
+pub fn variant_etag(etag: &str, encoding: &str) -> String {
+    match etag.strip_suffix('"') {
+        Some(open) => format!("{}-{}\"", open, encoding),
+        None => etag.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn negotiation_follows_q_values_and_prefers_brotli_on_ties() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.js");
+        fs::write(&file, "plain").unwrap();
+        fs::write(dir.path().join("app.js.gz"), "gz").unwrap();
+        fs::write(dir.path().join("app.js.br"), "br").unwrap();
+        let variants = variants(&file);
+        assert_eq!(variants.iter().map(|v| v.encoding).collect::<Vec<_>>(), ["br", "gzip"]);
+
+        let chosen = |value: &str| negotiate(&accept(value), &variants).map(|v| v.encoding);
+        assert_eq!(chosen("gzip, deflate, br"), Some("br"));
+        assert_eq!(chosen("gzip"), Some("gzip"));
+        assert_eq!(chosen("x-gzip"), Some("gzip"));
+        assert_eq!(chosen("br;q=0.5, gzip;q=0.8"), Some("gzip"));
+        assert_eq!(chosen("*;q=0.3, gzip;q=0"), Some("br"));
+        assert_eq!(chosen("identity"), None);
+        assert_eq!(chosen("br;q=0, gzip;q=0"), None);
+        assert_eq!(negotiate(&HeaderMap::new(), &variants).map(|v| v.encoding), None);
+    }
+
+    #[test]
+    fn variant_etag_marks_the_coding() {
+        assert_eq!(variant_etag("W/\"10-abc\"", "br"), "W/\"10-abc-br\"");
+        assert_eq!(variant_etag("\"0123\"", "gzip"), "\"0123-gzip\"");
+    }
+}
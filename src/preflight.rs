@@ -0,0 +1,315 @@
+//! Startup preflight checks and process readiness.
+//!
+//! Local checks (static directories, TLS files, listen ports) run before any listener is bound;
+//! network checks (backends, relay proxies) run once the proxy is up, and readiness is only
+//! declared after both have finished.
+
+use crate::common::{CertificateValidity, TlsConfig, unix_now};
+use crate::config::{Config, PreflightConfig, ProxyMode};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tokio::net::TcpStream;
+use tokio::time::{Duration, timeout};
+use url::Url;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckStatus::Pass => write!(f, "PASS"),
+            CheckStatus::Warn => write!(f, "WARN"),
+            CheckStatus::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PreflightCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PreflightReport {
+    pub checks: Vec<PreflightCheck>,
+}
+
+impl PreflightReport {
+    pub(crate) fn record(&mut self, name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(PreflightCheck { name: name.into(), status, detail: detail.into() });
+    }
+
+    pub fn extend(&mut self, other: PreflightReport) {
+        self.checks.extend(other.checks);
+    }
+
+    pub fn failures(&self) -> usize {
+        self.checks.iter().filter(|check| check.status == CheckStatus::Fail).count()
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failures() == 0
+    }
+
+    /// Logs every check at a level matching its outcome.
+    pub fn log(&self) {
+        for check in &self.checks {
+            match check.status {
+                CheckStatus::Pass => log::info!("Preflight {} passed: {}", check.name, check.detail),
+                CheckStatus::Warn => log::warn!("Preflight {}: {}", check.name, check.detail),
+                CheckStatus::Fail => log::error!("Preflight {} failed: {}", check.name, check.detail),
+            }
+        }
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(f, "{:<5} {:<32} {}", check.status, check.name, check.detail)?;
+        }
+        write!(f, "{} check(s), {} failed", self.checks.len(), self.failures())
+    }
+}
+
+pub struct Preflight<'a> {
+    config: &'a Config,
+    settings: PreflightConfig,
+}
+
+impl<'a> Preflight<'a> {
+    pub fn new(config: &'a Config, settings: PreflightConfig) -> Self {
+        Self { config, settings }
+    }
+
+    /// Checks that touch only the local machine; run these before binding any listener.
+    pub fn local_checks(&self) -> PreflightReport {
+        let mut report = PreflightReport::default();
+        self.check_static_dirs(&mut report);
+        self.check_tls(&mut report, unix_now() as i64);
+        self.check_ports(&mut report);
+        report
+    }
+
+    /// Checks that connect to backends and relay proxies.
+    pub async fn network_checks(&self) -> PreflightReport {
+        let mut report = PreflightReport::default();
+        let limit = Duration::from_millis(self.settings.connect_timeout_ms);
+
+        for backend in self.backend_urls() {
+            let (status, detail) = probe(&backend, limit).await;
+            report.record(format!("backend {}", backend), status, detail);
+        }
+        for relay in self.config.effective_relay_proxies() {
+            let url = if relay.relay_proxy_url.contains("://") {
+                relay.relay_proxy_url.clone()
+            } else {
+                format!("http://{}", relay.relay_proxy_url)
+            };
+            let (status, detail) = probe(&url, limit).await;
+            report.record(format!("relay {}", relay.relay_proxy_url), status, detail);
+        }
+        report
+    }
+
+    fn backend_urls(&self) -> BTreeSet<String> {
+        let mut urls = BTreeSet::new();
+        if let Some(target) = &self.config.reverse_proxy_target {
+            urls.insert(target.clone());
+        }
+        for route in &self.config.reverse_proxy_routes {
+            if let Some(target) = &route.target {
+                urls.insert(target.clone());
+            }
+            urls.extend(route.targets.iter().filter(|t| t.enabled).map(|t| t.url.clone()));
+        }
+        urls
+    }
+
+    fn check_static_dirs(&self, report: &mut PreflightReport) {
+        let Some(static_files) = &self.config.static_files else {
+            return;
+        };
+        for mount in &static_files.mounts {
+            let name = format!("static {}", mount.path);
+            let path = Path::new(&mount.root_dir);
+            if !path.is_dir() {
+                report.record(name, CheckStatus::Fail, format!("{} is not a directory", mount.root_dir));
+            } else if let Err(e) = std::fs::read_dir(path) {
+                report.record(name, CheckStatus::Fail, format!("{} is not readable: {}", mount.root_dir, e));
+            } else {
+                report.record(name, CheckStatus::Pass, mount.root_dir.clone());
+            }
+        }
+    }
+
+    fn check_tls(&self, report: &mut PreflightReport, now: i64) {
+        let (Some(private_key), Some(certificate)) = (&self.config.private_key, &self.config.certificate) else {
+            return;
+        };
+        if let Err(e) = TlsConfig::validate_tls_files(private_key, certificate) {
+            report.record("tls files", CheckStatus::Fail, e.to_string());
+            return;
+        }
+        let leaf = match TlsConfig::certificate_validity(certificate) {
+            Ok(chain) => chain[0],
+            Err(e) => {
+                report.record("tls certificate", CheckStatus::Fail, e.to_string());
+                return;
+            }
+        };
+
+        let (status, detail) = certificate_status(certificate, leaf, now, self.settings.cert_warning_days);
+        report.record("tls certificate", status, detail);
+    }
+
+    fn check_ports(&self, report: &mut PreflightReport) {
+        let mut addrs = vec![("listen", self.config.listen_addr)];
+        if matches!(self.config.mode, ProxyMode::Combined)
+            && let Some(addr) = self.config.forward_listen_addr
+        {
+            addrs.push(("forward listen", addr));
+        }
+        if self.config.monitoring.enabled
+            && let Some(addr) = self.config.monitoring.listen_address
+        {
+            addrs.push(("monitoring", addr));
+        }
+        for (label, addr) in addrs {
+            let name = format!("{} port {}", label, addr);
+            match bind_probe(addr) {
+                Ok(()) => report.record(name, CheckStatus::Pass, "bindable"),
+                Err(e) => report.record(name, CheckStatus::Fail, format!("cannot bind: {}", e)),
+            }
+        }
+    }
+}
+
+fn certificate_status(path: &str, leaf: CertificateValidity, now: i64, warning_days: u64) -> (CheckStatus, String) {
+    let days_left = (leaf.not_after - now) / 86_400;
+    if now < leaf.not_before {
+        (CheckStatus::Fail, format!("{} is not valid yet", path))
+    } else if now >= leaf.not_after {
+        (CheckStatus::Fail, format!("{} has expired", path))
+    } else if days_left < warning_days as i64 {
+        (CheckStatus::Warn, format!("{} expires in {} day(s)", path, days_left))
+    } else {
+        (CheckStatus::Pass, format!("{} valid for {} more day(s)", path, days_left))
+    }
+}
+
+fn bind_probe(addr: SocketAddr) -> std::io::Result<()> {
+    std::net::TcpListener::bind(addr).map(drop)
+}
+
+/// Opens (and immediately closes) a TCP connection to the host and port of `url`.
+async fn probe(url: &str, limit: Duration) -> (CheckStatus, String) {
+    let parsed = match Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(e) => return (CheckStatus::Fail, format!("invalid URL: {}", e)),
+    };
+    let (Some(host), Some(port)) = (parsed.host_str(), parsed.port_or_known_default()) else {
+        return (CheckStatus::Fail, "URL has no host or port".to_string());
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match timeout(limit, TcpStream::connect((host, port))).await {
+        Ok(Ok(_)) => (CheckStatus::Pass, format!("reachable at {}:{}", host, port)),
+        Ok(Err(e)) => (CheckStatus::Fail, format!("{}:{} unreachable: {}", host, port, e)),
+        Err(_) => (CheckStatus::Fail, format!("{}:{} timed out after {:?}", host, port, limit)),
+    }
+}
+
+struct Readiness {
+    ready: AtomicBool,
+    report: Mutex<Option<PreflightReport>>,
+}
+
+fn readiness() -> &'static Readiness {
+    static READINESS: OnceLock<Readiness> = OnceLock::new();
+    READINESS.get_or_init(|| Readiness { ready: AtomicBool::new(false), report: Mutex::new(None) })
+}
+
+/// Declares the process ready, keeping the preflight report (if any) for the readiness probe.
+pub fn mark_ready(report: Option<PreflightReport>) {
+    *readiness().report.lock().unwrap_or_else(|e| e.into_inner()) = report;
+    readiness().ready.store(true, Ordering::SeqCst);
+}
+
+pub fn is_ready() -> bool {
+    readiness().ready.load(Ordering::SeqCst)
+}
+
+pub fn readiness_report() -> Option<PreflightReport> {
+    readiness().report.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{StaticFileConfig, StaticMount};
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.monitoring.enabled = false;
+        config
+    }
+
+    #[test]
+    fn local_checks_flag_missing_dirs_and_busy_ports() {
+        let held = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = config();
+        config.listen_addr = held.local_addr().unwrap();
+        let mut static_files = StaticFileConfig::single(dir.path().display().to_string(), false);
+        static_files.mounts.push(StaticMount {
+            path: "/missing".to_string(),
+            root_dir: dir.path().join("missing").display().to_string(),
+            ..static_files.mounts[0].clone()
+        });
+        config.static_files = Some(static_files);
+
+        let report = Preflight::new(&config, PreflightConfig::default()).local_checks();
+        let statuses: Vec<_> = report.checks.iter().map(|c| (c.name.as_str(), c.status)).collect();
+        assert!(statuses.contains(&("static /", CheckStatus::Pass)));
+        assert!(statuses.contains(&("static /missing", CheckStatus::Fail)));
+        assert_eq!(statuses.last().unwrap().1, CheckStatus::Fail);
+        assert_eq!(report.failures(), 2);
+    }
+
+    #[tokio::test]
+    async fn network_checks_probe_backends() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let mut config = config();
+        config.reverse_proxy_target = Some(format!("http://{}", listener.local_addr().unwrap()));
+        config.relay_proxy_url = Some(closed.to_string());
+
+        let report = Preflight::new(&config, PreflightConfig::default()).network_checks().await;
+        assert_eq!(report.checks.len(), 2);
+        assert_eq!(report.checks[0].status, CheckStatus::Pass);
+        assert_eq!(report.checks[1].status, CheckStatus::Fail);
+    }
+
+    #[test]
+    fn certificate_expiry_thresholds() {
+        let leaf = CertificateValidity { not_before: 86_400, not_after: 100 * 86_400 };
+        let status = |day: i64| certificate_status("cert.pem", leaf, day * 86_400, 30).0;
+        assert_eq!(status(0), CheckStatus::Fail);
+        assert_eq!(status(10), CheckStatus::Pass);
+        assert_eq!(status(80), CheckStatus::Warn);
+        assert_eq!(status(100), CheckStatus::Fail);
+    }
+}
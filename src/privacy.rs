@@ -0,0 +1,170 @@
+//! Anonymization of client IPs in logs, security events and status output.
+//!
+//! Rate limiting, lockouts and other decisions still see the real address; only what is written
+//! out is changed. `truncate` keeps the network (e.g. the /24) and drops the host part. `hash`
+//! replaces the address with a keyed HMAC whose key rotates, so one client can be followed within
+//! a rotation window but not across windows.
+
+use crate::common::unix_now;
+use crate::config::{ClientIpMode, PrivacyConfig};
+use crate::error::ProxyError;
+use log::warn;
+use rand::RngCore;
+use ring::hmac;
+use std::borrow::Cow;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Mutex, OnceLock};
+
+pub struct IpAnonymizer {
+    mode: ClientIpMode,
+    ipv4_prefix: u8,
+    ipv6_prefix: u8,
+    rotation_secs: u64,
+    base_key: hmac::Key,
+    /// Key of the current rotation window, by window number
+    window_key: Mutex<Option<(u64, hmac::Key)>>,
+}
+
+impl IpAnonymizer {
+    pub fn new(config: &PrivacyConfig) -> Result<Self, ProxyError> {
+        if config.ipv4_prefix > 32 || config.ipv6_prefix > 128 {
+            return Err(ProxyError::Config(format!(
+                "privacy: ipv4_prefix must be at most 32 and ipv6_prefix at most 128, got {} and {}",
+                config.ipv4_prefix, config.ipv6_prefix
+            )));
+        }
+        if config.key_rotation_secs == 0 {
+            return Err(ProxyError::Config("privacy.key_rotation_secs must be greater than 0".to_string()));
+        }
+        let secret = match &config.hash_key {
+            Some(key) if !key.is_empty() => key.as_bytes().to_vec(),
+            Some(_) => return Err(ProxyError::Config("privacy.hash_key must not be empty".to_string())),
+            None => {
+                let mut key = vec![0u8; 32];
+                rand::thread_rng().fill_bytes(&mut key);
+                key
+            }
+        };
+        Ok(Self {
+            mode: config.client_ip,
+            ipv4_prefix: config.ipv4_prefix,
+            ipv6_prefix: config.ipv6_prefix,
+            rotation_secs: config.key_rotation_secs,
+            base_key: hmac::Key::new(hmac::HMAC_SHA256, &secret),
+            window_key: Mutex::new(None),
+        })
+    }
+
+    /// The form of `ip` to write out; values that are not an IP address are returned unchanged.
+    pub fn anonymize<'a>(&self, ip: &'a str) -> Cow<'a, str> {
+        self.anonymize_at(ip, unix_now())
+    }
+
+    fn anonymize_at<'a>(&self, ip: &'a str, now: u64) -> Cow<'a, str> {
+        if self.mode == ClientIpMode::Full {
+            return Cow::Borrowed(ip);
+        }
+        let Ok(addr) = ip.parse::<IpAddr>() else {
+            return Cow::Borrowed(ip);
+        };
+        match self.mode {
+            ClientIpMode::Full => Cow::Borrowed(ip),
+            ClientIpMode::Truncate => Cow::Owned(self.truncate(addr).to_string()),
+            ClientIpMode::Hash => Cow::Owned(self.hash(addr, now)),
+        }
+    }
+
+    fn truncate(&self, addr: IpAddr) -> IpAddr {
+        match addr {
+            IpAddr::V4(v4) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.ipv4_prefix)).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+            }
+            IpAddr::V6(v6) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.ipv6_prefix)).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+            }
+        }
+    }
+
+    fn hash(&self, addr: IpAddr, now: u64) -> String {
+        let window = now / self.rotation_secs;
+        let key = {
+            let mut current = self.window_key.lock().unwrap_or_else(|e| e.into_inner());
+            match current.as_ref() {
+                Some((number, key)) if *number == window => key.clone(),
+                _ => {
+                    let derived = hmac::sign(&self.base_key, &window.to_be_bytes());
+                    let key = hmac::Key::new(hmac::HMAC_SHA256, derived.as_ref());
+                    *current = Some((window, key.clone()));
+                    key
+                }
+            }
+        };
+        let canonical = addr.to_canonical().to_string();
+        let tag = hmac::sign(&key, canonical.as_bytes());
+        let hex: String = tag.as_ref()[..8].iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("anon-{}", hex)
+    }
+}
+
+static ANONYMIZER: OnceLock<IpAnonymizer> = OnceLock::new();
+
+/// Applies the `privacy` settings to every client IP written out from now on.
+pub fn init(config: &PrivacyConfig) -> Result<(), ProxyError> {
+    let anonymizer = IpAnonymizer::new(config)?;
+    if ANONYMIZER.set(anonymizer).is_err() {
+        warn!("Client IP anonymization already initialized; keeping the first settings");
+    }
+    Ok(())
+}
+
+/// A client IP as it may appear in logs and status output.
+pub fn client_ip(ip: &str) -> Cow<'_, str> {
+    match ANONYMIZER.get() {
+        Some(anonymizer) => anonymizer.anonymize(ip),
+        None => Cow::Borrowed(ip),
+    }
+}
+
+/// A client socket address as it may appear in logs; the port is left out once anonymized.
+pub fn peer(addr: &SocketAddr) -> String {
+    match ANONYMIZER.get() {
+        Some(anonymizer) if anonymizer.mode != ClientIpMode::Full => {
+            anonymizer.anonymize(&addr.ip().to_string()).into_owned()
+        }
+        _ => addr.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncates_and_hashes_within_a_rotation_window() {
+        let config = |mode| PrivacyConfig {
+            client_ip: mode,
+            hash_key: Some("test-key".to_string()),
+            key_rotation_secs: 3600,
+            ..PrivacyConfig::default()
+        };
+
+        let truncate = IpAnonymizer::new(&config(ClientIpMode::Truncate)).unwrap();
+        assert_eq!(truncate.anonymize("192.168.17.42"), "192.168.17.0");
+        assert_eq!(truncate.anonymize("2001:db8:abcd:12::1"), "2001:db8:abcd::");
+        assert_eq!(truncate.anonymize("unknown"), "unknown");
+
+        let hash = IpAnonymizer::new(&config(ClientIpMode::Hash)).unwrap();
+        let first = hash.anonymize_at("192.168.17.42", 7_200);
+        assert!(first.starts_with("anon-") && first.len() == 21, "{}", first);
+        assert_eq!(hash.anonymize_at("192.168.17.42", 10_799), first, "same window, same value");
+        assert_ne!(hash.anonymize_at("192.168.17.43", 7_200), first);
+        assert_ne!(hash.anonymize_at("192.168.17.42", 10_800), first, "the key rotates");
+        assert_eq!(hash.anonymize_at("::ffff:192.168.17.42", 7_200), first, "mapped IPv4 is the same client");
+
+        let full = IpAnonymizer::new(&config(ClientIpMode::Full)).unwrap();
+        assert_eq!(full.anonymize("192.168.17.42"), "192.168.17.42");
+        assert!(IpAnonymizer::new(&PrivacyConfig { ipv4_prefix: 33, ..PrivacyConfig::default() }).is_err());
+    }
+}
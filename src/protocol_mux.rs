@@ -0,0 +1,541 @@
+//! Several protocols on one port.
+//!
+//! With `listener.protocol_mux`, every accepted connection is peeked at (nothing is consumed)
+//! until its first bytes identify it as TLS, HTTP, SSH or something else. Connections mapped to
+//! "local" are handed to the proxy's own accept loop as if they had just been accepted; the
+//! others are relayed byte for byte to their backend, or closed. This lets port 443 serve HTTPS
+//! and SSH at once.
+//!
+//! With `sni_routes`, TLS connections are also routed by the server name in their ClientHello
+//! and relayed without being decrypted, for services that terminate TLS themselves.
+
+use crate::common::{normalize_domain, parse_host_port};
+use crate::config::{ListenerConfig, ProtocolMuxConfig, SniRouteConfig};
+use crate::listener::tune_stream;
+use crate::privacy;
+use log::{debug, warn};
+use prometheus::{IntCounterVec, Opts, Registry};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use tokio::io::copy_bidirectional;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+
+/// How long to wait for more bytes when the ones so far could still start several protocols.
+const SNIFF_POLL: Duration = Duration::from_millis(10);
+
+/// TLS record header: content type, version, length.
+const TLS_RECORD_HEADER: usize = 5;
+const TLS_MAX_RECORD: usize = 16384;
+
+/// Request-line starts of HTTP/1.x methods, and the HTTP/2 connection preface.
+const HTTP_SIGNATURES: [&[u8]; 10] = [
+    b"GET ", b"POST ", b"PUT ", b"HEAD ", b"DELETE ", b"OPTIONS ", b"PATCH ", b"CONNECT ", b"TRACE ",
+    b"PRI * HTTP/2",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Tls,
+    Http,
+    Ssh,
+    Other,
+}
+
+impl Protocol {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Tls => "tls",
+            Self::Http => "http",
+            Self::Ssh => "ssh",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// The protocol the first bytes of a connection belong to; `None` while more are needed.
+pub fn classify(bytes: &[u8]) -> Option<Protocol> {
+    match bytes {
+        [] => None,
+        // TLS handshake record (0x16) of any version 3.x
+        [0x16] => None,
+        [0x16, 0x03, ..] => Some(Protocol::Tls),
+        _ => {
+            let signatures = std::iter::once((&b"SSH-"[..], Protocol::Ssh))
+                .chain(HTTP_SIGNATURES.iter().map(|sig| (*sig, Protocol::Http)));
+            let mut undecided = false;
+            for (signature, protocol) in signatures {
+                if bytes.starts_with(signature) {
+                    return Some(protocol);
+                }
+                undecided |= signature.starts_with(bytes);
+            }
+            (!undecided).then_some(Protocol::Other)
+        }
+    }
+}
+
+/// The server name a TLS ClientHello record asks for, if it is complete and names one.
+pub fn client_hello_sni(record: &[u8]) -> Option<String> {
+    let mut reader = Reader(record);
+    // Handshake record, then a ClientHello (type 1) inside it
+    if reader.u8()? != 0x16 {
+        return None;
+    }
+    reader.skip(2)?;
+    let mut record = Reader(reader.vec(2)?);
+    if record.u8()? != 0x01 {
+        return None;
+    }
+    let length = record.u24()?;
+    let mut hello = Reader(record.take(length)?);
+    hello.skip(2 + 32)?; // client version, random
+    hello.vec(1)?; // session id
+    hello.vec(2)?; // cipher suites
+    hello.vec(1)?; // compression methods
+    let mut extensions = Reader(hello.vec(2)?);
+    while !extensions.0.is_empty() {
+        let kind = extensions.u16()?;
+        let mut data = Reader(extensions.vec(2)?);
+        if kind != 0x0000 {
+            continue;
+        }
+        let mut names = Reader(data.vec(2)?);
+        while !names.0.is_empty() {
+            let name_type = names.u8()?;
+            let name = names.vec(2)?;
+            if name_type == 0 {
+                return std::str::from_utf8(name).ok().map(|name| name.to_ascii_lowercase());
+            }
+        }
+    }
+    None
+}
+
+/// Big-endian reads over a byte slice that fail instead of panicking on truncation.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        self.take(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> Option<usize> {
+        self.take(3).map(|b| (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize)
+    }
+
+    /// A length-prefixed vector whose length takes `prefix` bytes.
+    fn vec(&mut self, prefix: usize) -> Option<&'a [u8]> {
+        let length = match prefix {
+            1 => self.u8()? as usize,
+            _ => self.u16()? as usize,
+        };
+        self.take(length)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Destination {
+    Local,
+    Relay(String),
+    Close,
+}
+
+fn parse_destination(field: &str, target: Option<&str>) -> io::Result<Destination> {
+    match target {
+        None => Ok(Destination::Close),
+        Some("local") => Ok(Destination::Local),
+        Some(backend) => match parse_host_port(backend) {
+            Some(_) => Ok(Destination::Relay(backend.to_string())),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("protocol_mux.{} must be \"local\" or a backend host:port, got '{}'", field, backend),
+            )),
+        },
+    }
+}
+
+/// One `sni_routes` entry: exact names and `*.` suffixes.
+struct SniRoute {
+    names: Vec<String>,
+    suffixes: Vec<String>,
+    destination: Destination,
+}
+
+impl SniRoute {
+    fn new(config: &SniRouteConfig) -> io::Result<Self> {
+        let mut names = Vec::new();
+        let mut suffixes = Vec::new();
+        for hostname in &config.hostnames {
+            let hostname = normalize_domain(hostname.trim()).into_owned();
+            match hostname.strip_prefix('*') {
+                Some(suffix) if suffix.starts_with('.') && suffix.len() > 1 => suffixes.push(suffix.to_string()),
+                None if !hostname.is_empty() && !hostname.contains('*') => names.push(hostname),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("protocol_mux.sni_routes: invalid hostname '{}'", hostname),
+                    ));
+                }
+            }
+        }
+        let destination = parse_destination("sni_routes.backend", Some(&config.backend))?;
+        Ok(Self { names, suffixes, destination })
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name) || self.suffixes.iter().any(|suffix| name.ends_with(suffix.as_str()))
+    }
+}
+
+/// The compiled `protocol_mux` mappings.
+pub struct ProtocolMux {
+    tls: Destination,
+    http: Destination,
+    ssh: Destination,
+    other: Destination,
+    sni_routes: Vec<SniRoute>,
+    sniff_timeout: Duration,
+    connect_timeout: Duration,
+}
+
+impl ProtocolMux {
+    pub fn new(config: &ProtocolMuxConfig) -> io::Result<Self> {
+        Ok(Self {
+            tls: parse_destination("tls", config.tls.as_deref())?,
+            http: parse_destination("http", config.http.as_deref())?,
+            ssh: parse_destination("ssh", config.ssh.as_deref())?,
+            other: parse_destination("other", config.other.as_deref())?,
+            sni_routes: config.sni_routes.iter().map(SniRoute::new).collect::<io::Result<_>>()?,
+            sniff_timeout: Duration::from_millis(config.sniff_timeout_ms),
+            connect_timeout: Duration::from_secs(config.connect_timeout_secs),
+        })
+    }
+
+    /// Where a TLS connection goes: the first SNI route naming its server, else `tls`.
+    async fn tls_destination(&self, stream: &TcpStream) -> &Destination {
+        if self.sni_routes.is_empty() {
+            return &self.tls;
+        }
+        let Some(name) = self.peek_sni(stream).await else {
+            return &self.tls;
+        };
+        match self.sni_routes.iter().find(|route| route.matches(&name)) {
+            Some(route) => {
+                debug!("protocol_mux: routing TLS for {} by SNI", name);
+                &route.destination
+            }
+            None => &self.tls,
+        }
+    }
+
+    /// Peeks until the first TLS record (the ClientHello) is complete and reads its SNI.
+    async fn peek_sni(&self, stream: &TcpStream) -> Option<String> {
+        let deadline = Instant::now() + self.sniff_timeout;
+        let mut buf = vec![0u8; TLS_RECORD_HEADER + TLS_MAX_RECORD];
+        loop {
+            let n = match tokio::time::timeout_at(deadline, stream.peek(&mut buf)).await {
+                Ok(Ok(n)) if n > 0 => n,
+                _ => return None,
+            };
+            if n >= TLS_RECORD_HEADER {
+                let needed = TLS_RECORD_HEADER + u16::from_be_bytes([buf[3], buf[4]]) as usize;
+                if n >= needed.min(buf.len()) {
+                    return client_hello_sni(&buf[..n]);
+                }
+            }
+            if Instant::now() + SNIFF_POLL >= deadline {
+                return None;
+            }
+            tokio::time::sleep(SNIFF_POLL).await;
+        }
+    }
+
+    fn destination(&self, protocol: Protocol) -> &Destination {
+        match protocol {
+            Protocol::Tls => &self.tls,
+            Protocol::Http => &self.http,
+            Protocol::Ssh => &self.ssh,
+            Protocol::Other => &self.other,
+        }
+    }
+
+    /// Peeks until the protocol is known. Connections that stay silent, close, or send
+    /// too little within the timeout count as `Other`.
+    async fn sniff(&self, stream: &TcpStream) -> Protocol {
+        let deadline = Instant::now() + self.sniff_timeout;
+        let mut buf = [0u8; 16];
+        loop {
+            let n = match tokio::time::timeout_at(deadline, stream.peek(&mut buf)).await {
+                Ok(Ok(n)) if n > 0 => n,
+                _ => return Protocol::Other,
+            };
+            if let Some(protocol) = classify(&buf[..n]) {
+                return protocol;
+            }
+            // `peek` returns at once while the bytes it saw are still unread, so poll for more
+            if Instant::now() + SNIFF_POLL >= deadline {
+                return Protocol::Other;
+            }
+            tokio::time::sleep(SNIFF_POLL).await;
+        }
+    }
+
+    /// Accepts connections on `listener` until the receiving side is gone, passing the ones
+    /// for the local handler to `local`.
+    pub async fn run(
+        self: Arc<Self>,
+        listener: TcpListener,
+        config: ListenerConfig,
+        local: mpsc::Sender<io::Result<(TcpStream, SocketAddr)>>,
+    ) {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    if local.send(Err(e)).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            let mux = self.clone();
+            let local = local.clone();
+            let config = config.clone();
+            tokio::spawn(async move {
+                let protocol = mux.sniff(&stream).await;
+                let telemetry = mux_telemetry();
+                let destination = match protocol {
+                    Protocol::Tls => mux.tls_destination(&stream).await,
+                    _ => mux.destination(protocol),
+                };
+                match destination {
+                    Destination::Local => {
+                        telemetry.record(protocol, "local");
+                        let _ = local.send(Ok((stream, peer))).await;
+                    }
+                    Destination::Relay(backend) => {
+                        tune_stream(&stream, &config);
+                        let outcome = mux.relay(stream, backend).await;
+                        telemetry.record(protocol, outcome);
+                    }
+                    Destination::Close => {
+                        debug!(
+                            "Closing {} connection from {}: no protocol_mux mapping",
+                            protocol.as_str(),
+                            privacy::peer(&peer)
+                        );
+                        telemetry.record(protocol, "closed");
+                    }
+                }
+            });
+        }
+    }
+
+    async fn relay(&self, mut client: TcpStream, backend: &str) -> &'static str {
+        let mut upstream = match tokio::time::timeout(self.connect_timeout, TcpStream::connect(backend)).await {
+            Ok(Ok(upstream)) => upstream,
+            Ok(Err(e)) => {
+                warn!("protocol_mux: failed to connect to {}: {}", backend, e);
+                return "failed";
+            }
+            Err(_) => {
+                warn!("protocol_mux: connecting to {} timed out", backend);
+                return "failed";
+            }
+        };
+        if let Err(e) = copy_bidirectional(&mut client, &mut upstream).await {
+            debug!("protocol_mux: relay to {} ended: {}", backend, e);
+        }
+        "relayed"
+    }
+}
+
+struct MuxTelemetry {
+    connections: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl MuxTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "protocol_mux_connections_total",
+            "Connections on a shared port by detected protocol and outcome (local, relayed, failed, closed)",
+        ).namespace("bifrost");
+        Self {
+            connections: IntCounterVec::new(opts, &["protocol", "outcome"])
+                .expect("protocol_mux_connections_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.connections.clone())) {
+            warn!("Failed to register protocol_mux_connections_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    fn record(&self, protocol: Protocol, outcome: &str) {
+        self.connections.with_label_values(&[protocol.as_str(), outcome]).inc();
+    }
+}
+
+fn mux_telemetry() -> &'static MuxTelemetry {
+    static TELEMETRY: OnceLock<MuxTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(MuxTelemetry::new)
+}
+
+/// Registers the shared-port connection counter with the shared Prometheus registry.
+pub fn register_protocol_mux_metrics(registry: &Registry) {
+    mux_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_first_bytes() {
+        assert_eq!(classify(&[0x16, 0x03, 0x01, 0x02, 0x00]), Some(Protocol::Tls));
+        assert_eq!(classify(&[0x16]), None);
+        assert_eq!(classify(b"SSH-2.0-OpenSSH_9.6\r\n"), Some(Protocol::Ssh));
+        assert_eq!(classify(b"GET / HTTP/1.1\r\n"), Some(Protocol::Http));
+        assert_eq!(classify(b"PRI * HTTP/2.0\r\n\r\nSM"), Some(Protocol::Http));
+        // "P" could still become POST, PUT, PATCH or PRI
+        assert_eq!(classify(b"P"), None);
+        assert_eq!(classify(b"PX"), Some(Protocol::Other));
+        assert_eq!(classify(&[0x16, 0x01]), Some(Protocol::Other));
+        assert_eq!(classify(b"\x00\x00\x00\x08"), Some(Protocol::Other));
+
+        let invalid = ProtocolMuxConfig { ssh: Some("ssh.internal".to_string()), ..ProtocolMuxConfig::default() };
+        assert!(ProtocolMux::new(&invalid).is_err());
+    }
+
+    /// A minimal TLS 1.3 style ClientHello record.
+    fn client_hello(sni: Option<&str>) -> Vec<u8> {
+        let mut extensions = vec![0x00, 0x2b, 0x00, 0x03, 0x02, 0x03, 0x04];
+        if let Some(name) = sni {
+            let mut entry = vec![0u8];
+            entry.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            entry.extend_from_slice(name.as_bytes());
+            extensions.extend_from_slice(&[0x00, 0x00]);
+            extensions.extend_from_slice(&(entry.len() as u16 + 2).to_be_bytes());
+            extensions.extend_from_slice(&(entry.len() as u16).to_be_bytes());
+            extensions.extend_from_slice(&entry);
+        }
+        let mut hello = vec![0x03, 0x03];
+        hello.extend_from_slice(&[7u8; 32]);
+        hello.extend_from_slice(&[0x00, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+        hello.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        hello.extend_from_slice(&extensions);
+        let mut handshake = vec![0x01];
+        handshake.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&hello);
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    #[tokio::test]
+    async fn routes_tls_by_sni_without_decrypting() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let hello = client_hello(Some("DB.internal.example"));
+        assert_eq!(client_hello_sni(&hello).as_deref(), Some("db.internal.example"));
+        assert_eq!(client_hello_sni(&client_hello(None)), None);
+        assert_eq!(client_hello_sni(&hello[..hello.len() - 4]), None);
+
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ListenerConfig {
+            protocol_mux: Some(ProtocolMuxConfig {
+                sni_routes: vec![SniRouteConfig {
+                    hostnames: vec!["*.internal.example".to_string()],
+                    backend: backend.local_addr().unwrap().to_string(),
+                }],
+                ..ProtocolMuxConfig::default()
+            }),
+            ..ListenerConfig::default()
+        };
+        let listener = crate::listener::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Split across writes, as large ClientHellos arrive in several segments
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(&hello[..9]).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        client.write_all(&hello[9..]).await.unwrap();
+        let (mut relayed, _) = backend.accept().await.unwrap();
+        let mut received = vec![0u8; hello.len()];
+        relayed.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, hello);
+
+        // Other names stay with the local TLS handler
+        let mut local = TcpStream::connect(addr).await.unwrap();
+        local.write_all(&client_hello(Some("www.example.com"))).await.unwrap();
+        let (mut accepted, _) = listener.accept().await.unwrap();
+        let mut header = [0u8; 5];
+        accepted.read_exact(&mut header).await.unwrap();
+        assert_eq!(header[0], 0x16);
+    }
+
+    #[tokio::test]
+    async fn relays_ssh_and_hands_http_to_the_local_listener() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ListenerConfig {
+            protocol_mux: Some(ProtocolMuxConfig {
+                ssh: Some(backend.local_addr().unwrap().to_string()),
+                ..ProtocolMuxConfig::default()
+            }),
+            ..ListenerConfig::default()
+        };
+        let listener = crate::listener::bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut ssh = TcpStream::connect(addr).await.unwrap();
+        ssh.write_all(b"SSH-2.0-test\r\n").await.unwrap();
+        let (mut relayed, _) = backend.accept().await.unwrap();
+        let mut banner = [0u8; 14];
+        relayed.read_exact(&mut banner).await.unwrap();
+        assert_eq!(&banner, b"SSH-2.0-test\r\n");
+        relayed.write_all(b"SSH-2.0-server\r\n").await.unwrap();
+        let mut reply = [0u8; 16];
+        ssh.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"SSH-2.0-server\r\n");
+
+        // Sniffing only peeks: the local handler still reads the whole request
+        let mut http = TcpStream::connect(addr).await.unwrap();
+        http.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+        let (mut accepted, _) = listener.accept().await.unwrap();
+        let mut request_line = [0u8; 16];
+        accepted.read_exact(&mut request_line).await.unwrap();
+        assert_eq!(&request_line, b"GET / HTTP/1.1\r\n");
+    }
+}
@@ -1,18 +1,43 @@
-use crate::config::{Config, ProxyMode, RelayProxyConfig};
+use crate::client_auth::ClientCertHeader;
+use crate::config::{ClientAuthConfig, Config, Http2Config, ListenerConfig, ProxyMode, StaticFileConfig, TlsSessionConfig};
+use crate::listener;
+use crate::forwarded;
+use crate::tls_session::accept_tls;
+use crate::http_server::{self, ConnectionProtocol};
 use crate::error::{ProxyError, ErrorContext, ContextualError};
 use crate::error_recovery::ErrorRecoveryManager;
-use crate::forward_proxy::ForwardProxy;
-use crate::reverse_proxy::ReverseProxy;
-use crate::static_files::StaticFileHandler;
+use crate::access_log::{AccessLogListener, AccessLogPolicy};
+use crate::api_keys::ApiKeyStore;
+use crate::auto_ban::AutoBan;
+use crate::cluster::Cluster;
+use crate::metrics_push::MetricsPusher;
+use crate::ban_list::BanList;
+use crate::path_normalization;
+use crate::privacy;
+use crate::security_log::{SecurityEvent, SecurityEventKind};
+use crate::auth_lockout::AuthLockout;
+use crate::connect_policy::ConnectPortPolicy;
+use crate::content_hook::ContentHook;
+use crate::digest_auth::DigestAuth;
+use crate::negotiate_auth::NegotiateAuth;
+use crate::download_scan::DownloadScanner;
+use crate::forward_proxy::{ForwardProxy, RelayProxies};
+use crate::ftp_gateway::FtpGateway;
+use crate::reverse_proxy::{ReverseProxy, RouteSummary, RouteTable};
+use crate::tarpit::Tarpit;
+use crate::tenants::Tenants;
+use crate::static_files::{StaticFileHandler, StaticMounts};
 use crate::common::{MonitoringHandles, ResponseBuilder, TlsConfig, FileBody, ProxyType, IsolatedWorker};
 use crate::monitoring::MonitoringServer;
 use crate::rate_limit::{RateLimiter, RateLimitHit};
+use crate::tunnel_limits::TunnelLimiter;
+use crate::byte_quota::ByteQuota;
+use crate::redact::redact_url;
 use log::{info, debug, warn, error};
-use hyper::{Response, StatusCode};
+use hyper::body::Incoming;
+use hyper::{Request, Response, StatusCode};
 use hyper::body::Bytes;
 use hyper::service::service_fn;
-use hyper::server::conn::http1::Builder as ServerBuilder;
-use hyper_util::rt::TokioIo;
 use http_body_util::Full;
 use std::convert::Infallible;
 use std::future::Future;
@@ -29,184 +54,444 @@ pub trait Proxy {
 
 pub struct ProxyFactory;
 
+/// State that outlives any one set of routes: metrics, counters, bans and keys are kept when a
+/// reloaded configuration is applied.
+struct SharedState {
+    monitoring_handles: MonitoringHandles,
+    rate_limiter: Arc<RateLimiter>,
+    api_keys: Arc<ApiKeyStore>,
+    tenants: Arc<Tenants>,
+    bans: Arc<BanList>,
+    cluster: Option<Arc<Cluster>>,
+}
+
+/// Applies reloaded configurations to a proxy built by [`ProxyFactory::create_reloadable`].
+///
+/// Routes, rate limit rules, static file mounts and relay proxies are swapped in place: accepted
+/// connections stay open, and requests in flight finish with the settings they started with.
+/// Everything else, such as listen addresses, TLS and authentication, applies on restart.
+#[derive(Clone)]
+pub struct ProxyHandle {
+    shared: Arc<SharedState>,
+    /// The configuration last applied
+    applied: Arc<std::sync::Mutex<Config>>,
+    routes: Option<RouteTable>,
+    static_files: Option<StaticMounts>,
+    relay_proxies: Option<RelayProxies>,
+}
+
+/// Top-level settings a reload applies; changes to the others are reported as needing a restart.
+const RELOADED_SETTINGS: &[&str] = &[
+    "reverse_proxy_target",
+    "reverse_proxy_routes",
+    "reverse_proxy_config",
+    "route_fallback",
+    "route_metadata",
+    "tarpit",
+    "rate_limiting",
+    "static_files",
+    "relay_proxies",
+    "relay_proxy_url",
+    "relay_proxy_username",
+    "relay_proxy_password",
+    "relay_proxy_domains",
+    "config_reload",
+];
+
+impl ProxyHandle {
+    /// Applies `config`. Every part is built before any is swapped, so on error the proxy keeps
+    /// running as it was. Changes that need another kind of listener, such as another mode or
+    /// static files added to a reverse proxy, are refused.
+    pub async fn reconfigure(&self, config: Config) -> Result<(), ProxyError> {
+        let applied = self.applied.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if std::mem::discriminant(&config.mode) != std::mem::discriminant(&applied.mode) {
+            return Err(ProxyError::Config("changing the proxy mode requires a restart".to_string()));
+        }
+        let serves_routes = !matches!(config.mode, ProxyMode::Forward)
+            && (config.reverse_proxy_target.is_some() || !config.reverse_proxy_routes.is_empty());
+        if serves_routes != self.routes.is_some() || config.static_files.is_some() != self.static_files.is_some() {
+            return Err(ProxyError::Config(
+                "adding or removing the reverse proxy or static files requires a restart".to_string(),
+            ));
+        }
+
+        let routes = match &self.routes {
+            Some(_) => Some(ProxyFactory::build_reverse_proxy(&config, &self.shared)?.route_table()),
+            None => None,
+        };
+        let static_handler = match &config.static_files {
+            Some(static_config) => Some(ProxyFactory::static_handler(static_config, &self.shared)?),
+            None => None,
+        };
+        self.shared.rate_limiter.reload(config.rate_limiting.clone()).await
+            .map_err(|e| ProxyError::Config(format!("rate_limiting: {}", e)))?;
+
+        if let (Some(table), Some(routes)) = (&self.routes, routes) {
+            table.replace(&routes);
+            info!("Reloaded {} reverse proxy route(s)", table.route_count());
+        }
+        if let (Some(mounts), Some(handler)) = (&self.static_files, static_handler) {
+            mounts.replace(handler);
+        }
+        if let Some(relays) = &self.relay_proxies {
+            relays.replace(config.effective_relay_proxies());
+        }
+
+        for setting in Self::restart_only_changes(&applied, &config) {
+            warn!("Configuration reload: the change to '{}' applies on restart", setting);
+        }
+        *self.applied.lock().unwrap_or_else(|e| e.into_inner()) = config;
+        Ok(())
+    }
+
+    /// The configuration last applied.
+    pub fn applied_config(&self) -> Config {
+        self.applied.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The reverse proxy routes being served; empty without a reverse proxy.
+    pub fn route_summaries(&self) -> Vec<RouteSummary> {
+        self.routes.as_ref().map(RouteTable::summaries).unwrap_or_default()
+    }
+
+    pub fn rate_limiter(&self) -> Arc<RateLimiter> {
+        self.shared.rate_limiter.clone()
+    }
+
+    pub fn monitoring_handles(&self) -> MonitoringHandles {
+        self.shared.monitoring_handles.clone()
+    }
+
+    /// Top-level settings that differ between `before` and `after` but are not reloaded.
+    fn restart_only_changes(before: &Config, after: &Config) -> Vec<String> {
+        let (Ok(serde_json::Value::Object(before)), Ok(serde_json::Value::Object(after))) =
+            (serde_json::to_value(before), serde_json::to_value(after))
+        else {
+            return Vec::new();
+        };
+        after
+            .iter()
+            .filter(|(name, value)| !RELOADED_SETTINGS.contains(&name.as_str()) && before.get(*name) != Some(*value))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
 impl ProxyFactory {
     pub fn create_proxy(config: Config) -> Result<Box<dyn Proxy + Send>, ProxyError> {
+        Self::create_reloadable(config).map(|(proxy, _)| proxy)
+    }
+
+    /// Builds the proxy like [`ProxyFactory::create_proxy`], along with the handle that applies
+    /// later configurations to it while it runs.
+    pub fn create_reloadable(config: Config) -> Result<(Box<dyn Proxy + Send>, ProxyHandle), ProxyError> {
         info!("Creating proxy instance for mode: {:?}", config.mode);
         debug!("Proxy configuration - listen_addr: {}, max_connections: {:?}",
                config.listen_addr, config.max_connections);
 
         let monitoring_handles = MonitoringHandles::new();
         let monitoring_config = config.monitoring.clone();
-        let rate_limiter = Arc::new(RateLimiter::new(config.rate_limiting.clone()));
+        if let Some(push) = &config.metrics_push {
+            MetricsPusher::new(push, monitoring_handles.registry())?.spawn();
+        }
+        let mut rate_limiter = RateLimiter::new(config.rate_limiting.clone());
+        if let Some(api_keys) = &config.api_keys {
+            rate_limiter = rate_limiter.with_api_key_header(&api_keys.header_name);
+        }
+        let bans = Arc::new(BanList::new());
+        let auto_ban = config.auto_ban.as_ref()
+            .map(|auto_ban| AutoBan::new(auto_ban, bans.clone()))
+            .transpose()?;
+        let cluster = config.cluster.as_ref().map(Cluster::new).transpose()?.map(Arc::new);
+        if let Some(cluster) = &cluster {
+            cluster.spawn_sync(bans.clone());
+        }
+        let rate_limiter = Arc::new(
+            rate_limiter
+                .with_bans(bans.clone(), auto_ban)
+                .with_cluster(cluster.clone()),
+        );
+        rate_limiter.spawn_refresh();
+        let monitoring_relays = config.effective_relay_proxies();
+        let api_keys = Arc::new(
+            config.api_keys.clone()
+                .map(ApiKeyStore::new)
+                .transpose()?
+                .unwrap_or_else(ApiKeyStore::empty),
+        );
+        let tenants = Arc::new(Tenants::new(
+            &config.tenants,
+            config.api_keys.as_ref().map(|api_keys| api_keys.header_name.as_str()),
+        )?);
+
+        let mut handle = ProxyHandle {
+            shared: Arc::new(SharedState { monitoring_handles, rate_limiter, api_keys, tenants, bans, cluster }),
+            applied: Arc::new(std::sync::Mutex::new(config.clone())),
+            routes: None,
+            static_files: None,
+            relay_proxies: None,
+        };
 
         let proxy: Box<dyn Proxy + Send> = match config.mode {
-            ProxyMode::Forward => {
-                info!("Initializing Forward Proxy mode");
-                debug!("Forward proxy configuration - connection_pool: {:?}",
-                       config.connection_pool_enabled);
-                // Support backward compatibility with timeout_secs
-                let connect_timeout_secs = config.connect_timeout_secs
-                    .or(config.timeout_secs)
-                    .unwrap_or(10);
-                let idle_timeout_secs = config.idle_timeout_secs
-                    .unwrap_or(90);
-                let max_connection_lifetime_secs = config.max_connection_lifetime_secs
-                    .unwrap_or(300);
-                let connection_pool_enabled = config.connection_pool_enabled.unwrap_or(true);
-                
-                // Support both new relay_proxies and legacy relay_proxy fields
-                let relay_configs = if let Some(relay_proxies) = config.relay_proxies {
-                    // Use new multi-relay configuration
-                    relay_proxies
-                } else if config.relay_proxy_url.is_some() {
-                    // Convert legacy single relay proxy to new format
-                    vec![RelayProxyConfig {
-                        relay_proxy_url: config.relay_proxy_url.unwrap(),
-                        relay_proxy_username: config.relay_proxy_username,
-                        relay_proxy_password: config.relay_proxy_password,
-                        relay_proxy_domains: config.relay_proxy_domain_suffixes.unwrap_or_default(),
-                    }]
-                } else {
-                    Vec::new()
-                };
-                
-                let proxy = ForwardProxy::new_with_relay_proxies(
-                    connect_timeout_secs,
-                    idle_timeout_secs,
-                    max_connection_lifetime_secs,
-                    connection_pool_enabled,
-                    relay_configs,
-                    config.proxy_username,
-                    config.proxy_password,
-                    config.websocket.clone(),
-                    rate_limiter.clone(),
+            ProxyMode::Forward => Self::forward_proxy(&config, config.listen_addr, true, &mut handle)?,
+            ProxyMode::Reverse => Self::reverse_proxy(config, &mut handle)?,
+            ProxyMode::Combined => {
+                let forward_addr = config.forward_listen_addr.ok_or_else(|| {
+                    ProxyError::Config("Combined mode requires forward_listen_addr".to_string())
+                })?;
+                info!(
+                    "Initializing Combined mode: forward proxy on {}, reverse proxy on {}",
+                    forward_addr, config.listen_addr
                 );
-                
-                Box::new(ForwardProxyAdapter {
-                    proxy,
+                let forward = Self::forward_proxy(&config, forward_addr, false, &mut handle)?;
+                let reverse = Self::reverse_proxy(config, &mut handle)?;
+                Box::new(MultiProxyAdapter { proxies: vec![forward, reverse] })
+            }
+        };
+
+        if monitoring_config.enabled {
+            let shared = &handle.shared;
+            let server = MonitoringServer::new(monitoring_config, shared.monitoring_handles.clone())
+                .with_relay_proxies(monitoring_relays)
+                .with_rate_limiter(shared.rate_limiter.clone())
+                .with_bans(shared.bans.clone())
+                .with_tenants(shared.tenants.clone());
+            Ok((Box::new(ProxyWithMonitoring::new(proxy, Some(server))), handle))
+        } else {
+            Ok((proxy, handle))
+        }
+    }
+
+    /// The listener options for `addr`; protocol multiplexing only applies to `listen_addr`.
+    fn listener_config(config: &Config, addr: SocketAddr) -> ListenerConfig {
+        let mut listener = config.listener.clone().unwrap_or_default();
+        if addr != config.listen_addr {
+            listener.protocol_mux = None;
+        }
+        listener
+    }
+
+    /// Builds the forward proxy listening on `addr`; `tls` controls whether the configured
+    /// certificate applies to this listener.
+    fn forward_proxy(
+        config: &Config,
+        addr: SocketAddr,
+        tls: bool,
+        handle: &mut ProxyHandle,
+    ) -> Result<Box<dyn Proxy + Send>, ProxyError> {
+        let shared = handle.shared.clone();
+        info!("Initializing Forward Proxy mode");
+        debug!("Forward proxy configuration - connection_pool: {:?}",
+               config.connection_pool_enabled);
+        // Support backward compatibility with timeout_secs
+        let connect_timeout_secs = config.connect_timeout_secs
+            .or(config.timeout_secs)
+            .unwrap_or(10);
+        let idle_timeout_secs = config.idle_timeout_secs
+            .unwrap_or(90);
+        let max_connection_lifetime_secs = config.max_connection_lifetime_secs
+            .unwrap_or(300);
+        let connection_pool_enabled = config.connection_pool_enabled.unwrap_or(true);
+        
+        // Support both new relay_proxies and legacy relay_proxy fields
+        let relay_configs = config.effective_relay_proxies();
+        let content_hook = config.forward_proxy_config.as_ref()
+            .and_then(|c| c.content_hook.clone())
+            .map(ContentHook::new)
+            .transpose()?;
+        let negotiate_auth = config.forward_proxy_config.as_ref()
+            .and_then(|c| c.negotiate_auth.as_ref())
+            .filter(|negotiate| negotiate.enabled)
+            .map(NegotiateAuth::new)
+            .transpose()?;
+        let access_log = config.access_log.as_ref()
+            .map(|c| AccessLogPolicy::for_listener(AccessLogListener::Forward, c))
+            .transpose()?
+            .flatten();
+
+        let proxy = ForwardProxy::new_with_relay_proxies(
+            connect_timeout_secs,
+            idle_timeout_secs,
+            max_connection_lifetime_secs,
+            connection_pool_enabled,
+            relay_configs,
+            config.proxy_username.clone(),
+            config.proxy_password.clone(),
+            config.websocket.clone(),
+            shared.rate_limiter.clone(),
+        )
+        .with_tunnel_limiter(Arc::new(TunnelLimiter::new(
+            config.forward_proxy_config.clone().and_then(|c| c.tunnel_limits),
+        )))
+        .with_byte_quota(Arc::new(ByteQuota::new(
+            config.forward_proxy_config.clone().and_then(|c| c.byte_quota),
+        )))
+        .with_connect_ports(Arc::new(ConnectPortPolicy::new(
+            config.forward_proxy_config.clone().and_then(|c| c.connect_ports),
+        )))
+        .with_digest_auth(
+            config.forward_proxy_config.as_ref()
+                .and_then(|c| c.digest_auth.as_ref())
+                .filter(|digest| digest.enabled)
+                .map(DigestAuth::new),
+        )
+        .with_negotiate_auth(negotiate_auth)
+        .with_auth_lockout(Arc::new(AuthLockout::new(
+            config.forward_proxy_config.clone().and_then(|c| c.auth_lockout).unwrap_or_default(),
+        )))
+        .with_connect_request_limits(
+            config.forward_proxy_config.clone().and_then(|c| c.connect_request_limits).unwrap_or_default(),
+        )
+        .with_ftp_gateway(FtpGateway::new(
+            config.forward_proxy_config.clone().and_then(|c| c.ftp_gateway).unwrap_or_default(),
+        ))
+        .with_content_hook(content_hook)
+        .with_download_scanner(
+            config.forward_proxy_config.as_ref()
+                .and_then(|c| c.download_scan.clone())
+                .map(DownloadScanner::new),
+        )
+        .with_listener_config(Self::listener_config(config, addr))
+        .with_http2(config.http2.clone())
+        .with_access_log(access_log)
+        .with_metrics(shared.monitoring_handles.forward_metrics());
+        handle.relay_proxies = Some(proxy.relay_proxies());
+
+        let (private_key, certificate) = if tls {
+            (config.private_key.clone(), config.certificate.clone())
+        } else {
+            (None, None)
+        };
+        Ok(Box::new(ForwardProxyAdapter {
+            proxy,
+            addr,
+            private_key,
+            certificate,
+            tls_session: config.tls_session.clone(),
+            client_auth: config.client_auth.clone(),
+        }))
+    }
+
+    /// Builds the reverse proxy, static file server or both, listening on `listen_addr`.
+    fn reverse_proxy(config: Config, handle: &mut ProxyHandle) -> Result<Box<dyn Proxy + Send>, ProxyError> {
+        info!("Initializing Reverse Proxy mode");
+
+        let shared = handle.shared.clone();
+        let has_routes = config.reverse_proxy_target.is_some() || !config.reverse_proxy_routes.is_empty();
+        let proxy: Box<dyn Proxy + Send> = match &config.static_files {
+            Some(static_config) if !has_routes => {
+                info!("Static files only mode (no reverse proxy target)");
+                let handler = StaticMounts::new(Self::static_handler(static_config, &shared)?);
+                handle.static_files = Some(handler.clone());
+                Box::new(StaticFileProxyAdapter {
+                    handler,
                     addr: config.listen_addr,
                     private_key: config.private_key,
                     certificate: config.certificate,
+                    tls_session: config.tls_session,
+                    client_auth: config.client_auth,
+                    http2: config.http2,
+                    listener: config.listener.unwrap_or_default(),
+                    rate_limiter: shared.rate_limiter.clone(),
                 })
             }
-            ProxyMode::Reverse => {
-                info!("Initializing Reverse Proxy mode");
-
-                let reverse_routes = config.reverse_proxy_routes.clone();
-                if config.static_files.is_some() && config.reverse_proxy_target.is_none() && reverse_routes.is_empty() {
-                    info!("Static files only mode (no reverse proxy target)");
-                    let static_config = config.static_files.unwrap();
-                    debug!("Static files configuration - mounts: {}", static_config.mounts.len());
-                    let handler = StaticFileHandler::new(static_config)?
-                        .with_metrics(monitoring_handles.static_metrics());
-                    Box::new(StaticFileProxyAdapter {
-                        handler,
-                        addr: config.listen_addr,
-                        private_key: config.private_key,
-                        certificate: config.certificate,
-                        rate_limiter: rate_limiter.clone(),
-                    })
-                } else if config.static_files.is_some() && (config.reverse_proxy_target.is_some() || !reverse_routes.is_empty()) {
-                    // Combined mode: both reverse proxy and static files
-                    info!("Combined reverse proxy + static files mode");
-                    let static_config = config.static_files.unwrap();
-                    debug!("Static files configuration - mounts: {}", static_config.mounts.len());
-                    let handler = StaticFileHandler::new(static_config)?
-                        .with_metrics(monitoring_handles.static_metrics());
-
-                    // Support backward compatibility with timeout_secs
-                    let connect_timeout_secs = config.connect_timeout_secs
-                        .or(config.timeout_secs)
-                        .unwrap_or(10);
-                    let idle_timeout_secs = config.idle_timeout_secs
-                        .unwrap_or(90);
-                    let max_connection_lifetime_secs = config.max_connection_lifetime_secs
-                        .unwrap_or(300);
-                    let proxy = if !reverse_routes.is_empty() {
-                        info!("Reverse proxy routes: {}", reverse_routes.len());
-                        ReverseProxy::new_with_routes(
-                            reverse_routes,
-                            connect_timeout_secs,
-                            idle_timeout_secs,
-                            max_connection_lifetime_secs,
-                            config.reverse_proxy_config.clone(),
-                            config.websocket.clone(),
-                        )?
-                    } else {
-                        let target_url = config.reverse_proxy_target.unwrap();
-                        info!("Reverse proxy target: {}", target_url);
-                        ReverseProxy::new_with_config(
-                            target_url,
-                            connect_timeout_secs,
-                            idle_timeout_secs,
-                            max_connection_lifetime_secs,
-                            config.reverse_proxy_config.clone(),
-                            config.websocket.clone(),
-                        )?
-                    }
-                    .with_metrics(monitoring_handles.reverse_metrics())
-                    .with_rate_limiter(rate_limiter.clone());
-
-                    Box::new(CombinedProxyAdapter {
-                        reverse_proxy: proxy,
-                        static_handler: handler,
-                        addr: config.listen_addr,
-                        private_key: config.private_key,
-                        certificate: config.certificate,
-                        rate_limiter: rate_limiter.clone(),
-                    })
-                } else {
-                    // Reverse proxy only mode
-                    // Support backward compatibility with timeout_secs
-                    let connect_timeout_secs = config.connect_timeout_secs
-                        .or(config.timeout_secs)
-                        .unwrap_or(10);
-                    let idle_timeout_secs = config.idle_timeout_secs
-                        .unwrap_or(90);
-                    let max_connection_lifetime_secs = config.max_connection_lifetime_secs
-                        .unwrap_or(300);
-                    let reverse_routes = config.reverse_proxy_routes.clone();
-                    let proxy = if !reverse_routes.is_empty() {
-                        info!("Reverse proxy routes: {}", reverse_routes.len());
-                        ReverseProxy::new_with_routes(
-                            reverse_routes,
-                            connect_timeout_secs,
-                            idle_timeout_secs,
-                            max_connection_lifetime_secs,
-                            config.reverse_proxy_config.clone(),
-                            config.websocket.clone(),
-                        )?
-                    } else {
-                        let target_url = config.reverse_proxy_target
-                            .ok_or_else(|| ProxyError::Config("Reverse proxy target URL is required for reverse proxy mode".to_string()))?;
-                        info!("Reverse proxy target: {}", target_url);
-                        ReverseProxy::new_with_config(
-                            target_url,
-                            connect_timeout_secs,
-                            idle_timeout_secs,
-                            max_connection_lifetime_secs,
-                            config.reverse_proxy_config.clone(),
-                            config.websocket.clone(),
-                        )?
-                    }
-                    .with_metrics(monitoring_handles.reverse_metrics())
-                    .with_rate_limiter(rate_limiter.clone());
-                    Box::new(ReverseProxyAdapter {
-                        proxy,
-                        addr: config.listen_addr,
-                        private_key: config.private_key,
-                        certificate: config.certificate,
-                    })
-                }
+            Some(static_config) => {
+                // Combined mode: both reverse proxy and static files
+                info!("Combined reverse proxy + static files mode");
+                let static_handler = StaticMounts::new(Self::static_handler(static_config, &shared)?);
+                let reverse_proxy = Self::build_reverse_proxy(&config, &shared)?;
+                handle.static_files = Some(static_handler.clone());
+                handle.routes = Some(reverse_proxy.route_table());
+                Box::new(CombinedProxyAdapter {
+                    reverse_proxy,
+                    static_handler,
+                    addr: config.listen_addr,
+                    private_key: config.private_key,
+                    certificate: config.certificate,
+                    tls_session: config.tls_session,
+                    client_auth: config.client_auth,
+                    http2: config.http2,
+                    listener: config.listener.unwrap_or_default(),
+                    rate_limiter: shared.rate_limiter.clone(),
+                })
+            }
+            None => {
+                // Reverse proxy only mode
+                let proxy = Self::build_reverse_proxy(&config, &shared)?;
+                handle.routes = Some(proxy.route_table());
+                Box::new(ReverseProxyAdapter {
+                    proxy,
+                    addr: config.listen_addr,
+                    private_key: config.private_key,
+                    certificate: config.certificate,
+                    tls_session: config.tls_session,
+                    client_auth: config.client_auth,
+                })
             }
         };
+        Ok(proxy)
+    }
 
-        if monitoring_config.enabled {
-            let server = MonitoringServer::new(monitoring_config, monitoring_handles.clone());
-            Ok(Box::new(ProxyWithMonitoring::new(proxy, Some(server))))
+    fn static_handler(static_config: &StaticFileConfig, shared: &SharedState) -> Result<StaticFileHandler, ProxyError> {
+        debug!("Static files configuration - mounts: {}", static_config.mounts.len());
+        Ok(StaticFileHandler::new(static_config.clone())?
+            .with_metrics(shared.monitoring_handles.static_metrics()))
+    }
+
+    /// The reverse proxy for the routes, or the single target, of `config`.
+    fn build_reverse_proxy(config: &Config, shared: &SharedState) -> Result<ReverseProxy, ProxyError> {
+        let access_log = config.access_log.as_ref()
+            .map(|c| AccessLogPolicy::for_listener(AccessLogListener::Reverse, c))
+            .transpose()?
+            .flatten();
+        let tarpit = config.tarpit.as_ref()
+            .map(|tarpit| Tarpit::new(tarpit, shared.bans.clone()))
+            .transpose()?;
+
+        // Support backward compatibility with timeout_secs
+        let connect_timeout_secs = config.connect_timeout_secs
+            .or(config.timeout_secs)
+            .unwrap_or(10);
+        let idle_timeout_secs = config.idle_timeout_secs
+            .unwrap_or(90);
+        let max_connection_lifetime_secs = config.max_connection_lifetime_secs
+            .unwrap_or(300);
+        let reverse_routes = config.reverse_proxy_routes.clone();
+        let proxy = if !reverse_routes.is_empty() {
+            info!("Reverse proxy routes: {}", reverse_routes.len());
+            ReverseProxy::new_with_routes(
+                reverse_routes,
+                connect_timeout_secs,
+                idle_timeout_secs,
+                max_connection_lifetime_secs,
+                config.reverse_proxy_config.clone(),
+                config.websocket.clone(),
+            )?
         } else {
-            Ok(proxy)
-        }
+            let target_url = config.reverse_proxy_target.clone()
+                .ok_or_else(|| ProxyError::Config("Reverse proxy target URL is required for reverse proxy mode".to_string()))?;
+            info!("Reverse proxy target: {}", redact_url(&target_url));
+            ReverseProxy::new_with_config(
+                target_url,
+                connect_timeout_secs,
+                idle_timeout_secs,
+                max_connection_lifetime_secs,
+                config.reverse_proxy_config.clone(),
+                config.websocket.clone(),
+            )?
+        };
+        Ok(proxy
+            .with_metrics(shared.monitoring_handles.reverse_metrics())
+            .with_rate_limiter(shared.rate_limiter.clone())
+            .with_api_keys(shared.api_keys.clone())
+            .with_tenants(&shared.tenants)?
+            .with_listener_config(config.listener.clone().unwrap_or_default())
+            .with_http2(config.http2.clone())
+            .with_route_fallback(config.route_fallback.clone())?
+            .with_route_metadata(config.route_metadata.clone())?
+            .with_access_log(access_log)?
+            .with_tarpit(tarpit)?
+            .with_cluster(shared.cluster.clone()))
     }
 }
 
@@ -244,21 +529,55 @@ impl Proxy for ProxyWithMonitoring {
     }
 }
 
+/// Runs several proxies side by side in one process; the first one to stop ends them all.
+struct MultiProxyAdapter {
+    proxies: Vec<Box<dyn Proxy + Send>>,
+}
+
+impl Proxy for MultiProxyAdapter {
+    fn run(self: Box<Self>) -> Pin<Box<dyn Future<Output = Result<(), ProxyError>> + Send>> {
+        Box::pin(async move {
+            let mut tasks = tokio::task::JoinSet::new();
+            for proxy in self.proxies {
+                tasks.spawn(proxy.run());
+            }
+            let result = match tasks.join_next().await {
+                Some(Ok(result)) => result,
+                Some(Err(err)) => Err(ProxyError::Connection(format!("Proxy task failed: {}", err))),
+                None => Ok(()),
+            };
+            tasks.abort_all();
+            result
+        })
+    }
+}
+
 struct ForwardProxyAdapter {
     proxy: ForwardProxy,
     addr: std::net::SocketAddr,
     private_key: Option<String>,
     certificate: Option<String>,
+    tls_session: Option<TlsSessionConfig>,
+    client_auth: Option<ClientAuthConfig>,
 }
 
 impl Proxy for ForwardProxyAdapter {
     fn run(self: Box<Self>) -> Pin<Box<dyn Future<Output = Result<(), ProxyError>> + Send>> {
         Box::pin(async move {
             let addr = self.addr;
-            let private_key = self.private_key;
-            let certificate = self.certificate;
-
-            self.proxy.run_with_config(addr, private_key, certificate).await
+            match (self.private_key, self.certificate) {
+                (Some(private_key_path), Some(cert_path)) => {
+                    let tls_config = TlsConfig::create_config_with_client_auth(
+                        &private_key_path,
+                        &cert_path,
+                        self.tls_session.as_ref(),
+                        self.client_auth.as_ref(),
+                    )?;
+                    let early_data_policy = self.tls_session.as_ref().map(|t| t.early_data_policy).unwrap_or_default();
+                    self.proxy.with_early_data_policy(early_data_policy).run_with_tls(addr, tls_config).await
+                }
+                _ => self.proxy.run(addr).await,
+            }
         })
     }
 }
@@ -266,56 +585,79 @@ impl Proxy for ForwardProxyAdapter {
 struct ReverseProxyAdapter {
     proxy: ReverseProxy,
     addr: std::net::SocketAddr,
-    #[allow(dead_code)]
     private_key: Option<String>,
-    #[allow(dead_code)]
     certificate: Option<String>,
+    tls_session: Option<TlsSessionConfig>,
+    client_auth: Option<ClientAuthConfig>,
 }
 
 impl Proxy for ReverseProxyAdapter {
     fn run(self: Box<Self>) -> Pin<Box<dyn Future<Output = Result<(), ProxyError>> + Send>> {
         Box::pin(async move {
             let addr = self.addr;
-            self.proxy.run(addr).await
+            match (self.private_key, self.certificate) {
+                (Some(private_key_path), Some(cert_path)) => {
+                    let tls_config = TlsConfig::create_config_with_client_auth(
+                        &private_key_path,
+                        &cert_path,
+                        self.tls_session.as_ref(),
+                        self.client_auth.as_ref(),
+                    )?;
+                    let client_cert_header = self.client_auth.as_ref().map(ClientCertHeader::new).transpose()?;
+                    let early_data_policy = self.tls_session.as_ref().map(|t| t.early_data_policy).unwrap_or_default();
+                    self.proxy
+                        .with_early_data_policy(early_data_policy)
+                        .with_client_cert_header(client_cert_header)
+                        .run_with_tls(addr, tls_config)
+                        .await
+                }
+                _ => self.proxy.run(addr).await,
+            }
         })
     }
 }
 
 struct StaticFileProxyAdapter {
-    handler: StaticFileHandler,
+    handler: StaticMounts,
     addr: SocketAddr,
     private_key: Option<String>,
     certificate: Option<String>,
+    tls_session: Option<TlsSessionConfig>,
+    client_auth: Option<ClientAuthConfig>,
+    http2: Option<Http2Config>,
+    listener: ListenerConfig,
     rate_limiter: Arc<RateLimiter>,
 }
 
 impl StaticFileProxyAdapter {
     fn rate_limited_response(hit: &RateLimitHit) -> Response<FileBody> {
         let mut builder = Response::builder()
-            .status(StatusCode::TOO_MANY_REQUESTS)
+            .status(hit.status)
             .header("Content-Type", "text/plain; charset=utf-8");
 
         if hit.retry_after_secs > 0 {
             builder = builder.header("Retry-After", hit.retry_after_secs.to_string());
         }
 
-        builder
-            .body(FileBody::InMemory(Full::new(Bytes::from(format!(
-                "Rate limit '{}' exceeded. Please retry later.",
-                hit.rule_id
-            )))))
-            .unwrap()
+        let mut response = builder
+            .body(FileBody::InMemory(Full::new(Bytes::from(hit.message()))))
+            .unwrap();
+        if let Some(info) = &hit.info {
+            info.write_headers(response.headers_mut());
+        }
+        response
     }
 }
 
 impl Proxy for StaticFileProxyAdapter {
     fn run(self: Box<Self>) -> Pin<Box<dyn Future<Output = Result<(), ProxyError>> + Send>> {
         Box::pin(async move {
-            let handler = Arc::new(self.handler);
+            let handler = self.handler;
             let addr = self.addr;
             let private_key = self.private_key;
             let certificate = self.certificate;
             let rate_limiter = self.rate_limiter.clone();
+            let http2 = self.http2.clone();
 
             match (private_key, certificate) {
                 (Some(private_key_path), Some(cert_path)) => {
@@ -324,13 +666,21 @@ impl Proxy for StaticFileProxyAdapter {
                     debug!("Loading TLS certificate from: {}", cert_path);
                     debug!("Loading TLS private key from: {}", private_key_path);
 
-                    let tls_config = TlsConfig::create_config(&private_key_path, &cert_path)?;
+                    let mut tls_config = TlsConfig::create_config_with_client_auth(
+                        &private_key_path,
+                        &cert_path,
+                        self.tls_session.as_ref(),
+                        self.client_auth.as_ref(),
+                    )?;
+                    http_server::set_alpn(&mut tls_config, self.http2.as_ref());
+                    let client_cert_header = self.client_auth.as_ref().map(ClientCertHeader::new).transpose()?;
                     let tls_config = Arc::new(tls_config);
                     let acceptor = TlsAcceptor::from(tls_config.clone());
+                    let early_data_policy = self.tls_session.as_ref().map(|t| t.early_data_policy).unwrap_or_default();
 
                     info!("Binding TCP listener to: {}", addr);
-                    let tcp_listener = tokio::net::TcpListener::bind(&addr).await
-                        .map_err(|e| ProxyError::Io(e))?;
+                    let tcp_listener = listener::bind(addr, &self.listener)
+                        .map_err(ProxyError::Io)?;
 
                     info!("HTTPS static file server listening on: https://{}", addr);
                     debug!("TLS certificate file: {}", cert_path);
@@ -339,20 +689,31 @@ impl Proxy for StaticFileProxyAdapter {
                     loop {
                         let (tcp_stream, remote_addr) = tcp_listener.accept().await
                             .map_err(|e| ProxyError::Io(e))?;
+                        listener::tune_stream(&tcp_stream, &self.listener);
+                        if rate_limiter.refuses(&remote_addr) {
+                            continue;
+                        }
                         let acceptor = acceptor.clone();
+                        let http2 = http2.clone();
+                        let client_cert_header = client_cert_header.clone();
                         let handler_ref = handler.clone();
                         let rate_limiter = rate_limiter.clone();
-                        let client_ip = remote_addr.ip().to_string();
 
                         tokio::spawn(async move {
-                            match acceptor.accept(tcp_stream).await {
+                            match accept_tls(&acceptor, tcp_stream, early_data_policy).await {
                                 Ok(tls_stream) => {
-                                    let service = service_fn(move |req| {
-                                        let handler = handler_ref.clone();
+                                    let client_identity = client_cert_header
+                                        .as_ref()
+                                        .map(|header| header.identity(tls_stream.peer_certificates()));
+                                    let service = service_fn(move |mut req: Request<Incoming>| {
+                                        if let Some(identity) = &client_identity {
+                                            identity.apply(req.headers_mut());
+                                        }
+                                        let handler = handler_ref.current();
                                         let rate_limiter = rate_limiter.clone();
-                                        let client_ip = client_ip.clone();
-                                        async move {
-                                            if let Err(hit) = rate_limiter
+                                        let client_ip = forwarded::client_ip(remote_addr.ip(), req.headers()).to_string();
+                                        rate_limiter.clone().observe(client_ip.clone(), async move {
+                                            let rate_limit_info = match rate_limiter
                                                 .check_request(
                                                     &client_ip,
                                                     req.method(),
@@ -360,29 +721,39 @@ impl Proxy for StaticFileProxyAdapter {
                                                         .path_and_query()
                                                         .map(|pq| pq.as_str())
                                                         .unwrap_or("/"),
+                                                    req.headers(),
                                                 )
                                                 .await
                                             {
-                                                warn!(
-                                                    "Static HTTPS rate limit hit for {} via rule {}",
-                                                    client_ip, hit.rule_id
-                                                );
-                                                return Ok::<_, Infallible>(
-                                                    StaticFileProxyAdapter::rate_limited_response(&hit),
-                                                );
-                                            }
+                                                Ok(info) => info,
+                                                Err(hit) => {
+                                                    warn!(
+                                                        "Static HTTPS rate limit hit for {} via rule {}",
+                                                        privacy::client_ip(&client_ip), hit.rule_id
+                                                    );
+                                                    return Ok::<_, Infallible>(
+                                                        StaticFileProxyAdapter::rate_limited_response(&hit),
+                                                    );
+                                                }
+                                            };
                                             match handler.handle_request(&req).await {
-                                                Ok(response) => Ok::<_, Infallible>(response),
+                                                Ok(mut response) => {
+                                                    if let Some(info) = &rate_limit_info {
+                                                        info.write_headers(response.headers_mut());
+                                                    }
+                                                    Ok::<_, Infallible>(response)
+                                                }
                                                 Err(_) => {
                                                     Ok::<_, Infallible>(ResponseBuilder::internal_server_error_file_body())
                                                 }
                                             }
-                                        }
+                                        })
                                     });
 
-                                    if let Err(e) = ServerBuilder::new()
-                                        .keep_alive(true)
-                                        .serve_connection(TokioIo::new(tls_stream), service)
+                                    let protocol = ConnectionProtocol::negotiated(tls_stream.alpn_protocol());
+
+                                    if let Err(e) = http_server::serve(tls_stream, service, protocol, http2.as_ref(), false)
+
                                         .await
                                     {
                                         error!("Error serving TLS connection: {}", e);
@@ -390,7 +761,10 @@ impl Proxy for StaticFileProxyAdapter {
                                 }
                                 Err(e) => {
                                     warn!("Error establishing TLS connection from {}: {}",
-                                          remote_addr, e);
+                                          privacy::peer(&remote_addr), e);
+                                    SecurityEvent::new(SecurityEventKind::TlsHandshakeFailed, e.to_string())
+                                        .client_ip(Some(&remote_addr.ip().to_string()))
+                                        .emit();
                                 }
                             }
                         });
@@ -400,57 +774,71 @@ impl Proxy for StaticFileProxyAdapter {
                     // HTTP mode
                     info!("Running in HTTP mode (no TLS)");
                     info!("Binding HTTP listener to: {}", addr);
-                    let listener = tokio::net::TcpListener::bind(addr).await
+                    let tcp_listener = listener::bind(addr, &self.listener)
                         .map_err(|e| ProxyError::Hyper(e.to_string()))?;
                     info!("HTTP static file server listening on: http://{}", addr);
 
                     loop {
-                        let (stream, remote_addr) = listener.accept().await
+                        let (stream, remote_addr) = tcp_listener.accept().await
                             .map_err(|e| ProxyError::Hyper(e.to_string()))?;
+                        listener::tune_stream(&stream, &self.listener);
+                        if rate_limiter.refuses(&remote_addr) {
+                            continue;
+                        }
 
                         let handler = handler.clone();
                         let rate_limiter = rate_limiter.clone();
-                        let client_ip = remote_addr.ip().to_string();
+                        let http2 = http2.clone();
                         tokio::spawn(async move {
-                            let io = TokioIo::new(stream);
-
-                            if let Err(err) = ServerBuilder::new()
-                                .serve_connection(
-                                    io,
-                                    service_fn(move |req| {
-                                        let handler = handler.clone();
-                                        let rate_limiter = rate_limiter.clone();
-                                        let client_ip = client_ip.clone();
-                                        async move {
-                                            if let Err(hit) = rate_limiter
-                                                .check_request(
-                                                    &client_ip,
-                                                    req.method(),
-                                                    req.uri()
-                                                        .path_and_query()
-                                                        .map(|pq| pq.as_str())
-                                                        .unwrap_or("/"),
-                                                )
-                                                .await
-                                            {
+                            let protocol = ConnectionProtocol::plain(http2.as_ref());
+                            if let Err(err) = http_server::serve(
+                                stream,
+                                service_fn(move |req| {
+                                    let handler = handler.current();
+                                    let rate_limiter = rate_limiter.clone();
+                                    let client_ip = forwarded::client_ip(remote_addr.ip(), req.headers()).to_string();
+                                    rate_limiter.clone().observe(client_ip.clone(), async move {
+                                        let rate_limit_info = match rate_limiter
+                                            .check_request(
+                                                &client_ip,
+                                                req.method(),
+                                                req.uri()
+                                                    .path_and_query()
+                                                    .map(|pq| pq.as_str())
+                                                    .unwrap_or("/"),
+                                                req.headers(),
+                                            )
+                                            .await
+                                        {
+                                            Ok(info) => info,
+                                            Err(hit) => {
                                                 warn!(
                                                     "Static HTTP rate limit hit for {} via rule {}",
-                                                    client_ip, hit.rule_id
+                                                    privacy::client_ip(&client_ip), hit.rule_id
                                                 );
                                                 return Ok::<_, Infallible>(
                                                     StaticFileProxyAdapter::rate_limited_response(&hit),
                                                 );
                                             }
-                                            match handler.handle_request(&req).await {
-                                                Ok(response) => Ok::<_, Infallible>(response),
-                                                Err(_) => {
-                                                    Ok::<_, Infallible>(ResponseBuilder::internal_server_error_file_body())
+                                        };
+                                        match handler.handle_request(&req).await {
+                                            Ok(mut response) => {
+                                                if let Some(info) = &rate_limit_info {
+                                                    info.write_headers(response.headers_mut());
                                                 }
+                                                Ok::<_, Infallible>(response)
+                                            }
+                                            Err(_) => {
+                                                Ok::<_, Infallible>(ResponseBuilder::internal_server_error_file_body())
                                             }
                                         }
                                     })
-                                )
-                                .await
+                                }),
+                                protocol,
+                                http2.as_ref(),
+                                false,
+                            )
+                            .await
                             {
                                 error!("Error serving HTTP connection: {}", err);
                             }
@@ -464,12 +852,16 @@ impl Proxy for StaticFileProxyAdapter {
 
 struct CombinedProxyAdapter {
     reverse_proxy: ReverseProxy,
-    static_handler: StaticFileHandler,
+    static_handler: StaticMounts,
     addr: std::net::SocketAddr,
     #[allow(dead_code)]
     private_key: Option<String>,
     #[allow(dead_code)]
     certificate: Option<String>,
+    tls_session: Option<TlsSessionConfig>,
+    client_auth: Option<ClientAuthConfig>,
+    http2: Option<Http2Config>,
+    listener: ListenerConfig,
     rate_limiter: Arc<RateLimiter>,
 }
 
@@ -480,8 +872,11 @@ impl Proxy for CombinedProxyAdapter {
             let private_key = self.private_key;
             let certificate = self.certificate;
             let reverse_proxy = Arc::new(self.reverse_proxy);
-            let static_handler = Arc::new(self.static_handler);
+            reverse_proxy.spawn_api_key_refresh();
+            reverse_proxy.spawn_prewarm();
+            let static_handler = self.static_handler;
             let rate_limiter = self.rate_limiter.clone();
+            let http2 = self.http2.clone();
 
             match (private_key, certificate) {
                 (Some(private_key_path), Some(cert_path)) => {
@@ -490,13 +885,21 @@ impl Proxy for CombinedProxyAdapter {
                     debug!("Loading TLS certificate from: {}", cert_path);
                     debug!("Loading TLS private key from: {}", private_key_path);
 
-                    let tls_config = TlsConfig::create_config(&private_key_path, &cert_path)?;
+                    let mut tls_config = TlsConfig::create_config_with_client_auth(
+                        &private_key_path,
+                        &cert_path,
+                        self.tls_session.as_ref(),
+                        self.client_auth.as_ref(),
+                    )?;
+                    http_server::set_alpn(&mut tls_config, self.http2.as_ref());
+                    let client_cert_header = self.client_auth.as_ref().map(ClientCertHeader::new).transpose()?;
                     let tls_config = Arc::new(tls_config);
                     let acceptor = TlsAcceptor::from(tls_config.clone());
+                    let early_data_policy = self.tls_session.as_ref().map(|t| t.early_data_policy).unwrap_or_default();
 
                     info!("Binding TCP listener to: {}", addr);
-                    let tcp_listener = tokio::net::TcpListener::bind(&addr).await
-                        .map_err(|e| ProxyError::Io(e))?;
+                    let tcp_listener = listener::bind(addr, &self.listener)
+                        .map_err(ProxyError::Io)?;
 
                     info!("HTTPS combined proxy server listening on: https://{}", addr);
                     debug!("TLS certificate file: {}", cert_path);
@@ -505,27 +908,40 @@ impl Proxy for CombinedProxyAdapter {
                     loop {
                         let (tcp_stream, remote_addr) = tcp_listener.accept().await
                             .map_err(|e| ProxyError::Io(e))?;
+                        listener::tune_stream(&tcp_stream, &self.listener);
+                        if rate_limiter.refuses(&remote_addr) {
+                            continue;
+                        }
                         let acceptor = acceptor.clone();
+                        let http2 = http2.clone();
+                        let client_cert_header = client_cert_header.clone();
                         let reverse_proxy_ref = reverse_proxy.clone();
                         let static_handler_ref = static_handler.clone();
                         let rate_limiter = rate_limiter.clone();
-                        let client_ip = remote_addr.ip().to_string();
 
                         tokio::spawn(async move {
-                            match acceptor.accept(tcp_stream).await {
+                            match accept_tls(&acceptor, tcp_stream, early_data_policy).await {
                                 Ok(tls_stream) => {
-                                    let service = service_fn(move |req| {
+                                    let client_identity = client_cert_header
+                                        .as_ref()
+                                        .map(|header| header.identity(tls_stream.peer_certificates()));
+                                    let preserve_header_case = reverse_proxy_ref.preserves_header_case();
+                                    let service = service_fn(move |mut req: Request<Incoming>| {
+                                        if let Some(identity) = &client_identity {
+                                            identity.apply(req.headers_mut());
+                                        }
                                         let reverse_proxy = reverse_proxy_ref.clone();
-                                        let static_handler = static_handler_ref.clone();
+                                        let static_handler = static_handler_ref.current();
                                         let rate_limiter = rate_limiter.clone();
-                                        let client_ip = client_ip.clone();
-                                        async move {
-                                            // Route request to appropriate handler
-                                            let request_path = req.uri().path();
+                                        let client_ip = forwarded::client_ip(remote_addr.ip(), req.headers()).to_string();
+                                        rate_limiter.clone().observe(client_ip.clone(), async move {
+                                            // Route request to appropriate handler; a rejected path is answered by the reverse proxy
+                                            let serves_static = path_normalization::normalize(req.uri().path())
+                                                .is_ok_and(|path| static_handler.find_mount_for_path(&path).is_some());
 
                                             // Check if request matches any static file mount
-                                            if let Some((_mount_info, _relative_path)) = static_handler.find_mount_for_path(request_path) {
-                                                if let Err(hit) = rate_limiter
+                                            if serves_static {
+                                                let rate_limit_info = match rate_limiter
                                                     .check_request(
                                                         &client_ip,
                                                         req.method(),
@@ -533,29 +949,34 @@ impl Proxy for CombinedProxyAdapter {
                                                             .path_and_query()
                                                             .map(|pq| pq.as_str())
                                                             .unwrap_or("/"),
+                                                        req.headers(),
                                                     )
                                                     .await
                                                 {
-                                                    warn!(
-                                                        "Combined HTTPS rate limit hit for {} via rule {}",
-                                                        client_ip, hit.rule_id
-                                                    );
-                                                    return Ok::<_, Infallible>(StaticFileProxyAdapter::rate_limited_response(&hit));
-                                                }
+                                                    Ok(info) => info,
+                                                    Err(hit) => {
+                                                        warn!(
+                                                            "Combined HTTPS rate limit hit for {} via rule {}",
+                                                            privacy::client_ip(&client_ip), hit.rule_id
+                                                        );
+                                                        return Ok::<_, Infallible>(StaticFileProxyAdapter::rate_limited_response(&hit));
+                                                    }
+                                                };
 
                                                 // Serve static file
                                                 match static_handler.handle_request(&req).await {
-                                                    Ok(response) => Ok::<_, Infallible>(response),
+                                                    Ok(mut response) => {
+                                                        if let Some(info) = &rate_limit_info {
+                                                            info.write_headers(response.headers_mut());
+                                                        }
+                                                        Ok::<_, Infallible>(response)
+                                                    }
                                                     Err(ProxyError::NotFound(_)) => {
                                                         // Fall back to reverse proxy if static file not found
-                                                        let context = crate::reverse_proxy::RequestContext {
-                                                            client_ip: Some(remote_addr.ip().to_string()),
-                                                        };
+                                                        let context = crate::reverse_proxy::RequestContext::from_peer(remote_addr.ip(), req.headers());
                                                         match reverse_proxy.handle_request_with_context(req, context).await {
                                                             Ok(response) => {
-                                                                // Convert Full<Bytes> to FileBody
-                                                                let (parts, body) = response.into_parts();
-                                                                let response_with_file_body = Response::from_parts(parts, FileBody::InMemory(body));
+                                                                let response_with_file_body = response.map(FileBody::Proxied);
                                                                 Ok::<_, Infallible>(response_with_file_body)
                                                             }
                                                             Err(_) => {
@@ -575,14 +996,10 @@ impl Proxy for CombinedProxyAdapter {
                                                 }
                                             } else {
                                                 // Forward to reverse proxy
-                                                let context = crate::reverse_proxy::RequestContext {
-                                                    client_ip: Some(remote_addr.ip().to_string()),
-                                                };
+                                                let context = crate::reverse_proxy::RequestContext::from_peer(remote_addr.ip(), req.headers());
                                                 match reverse_proxy.handle_request_with_context(req, context).await {
                                                     Ok(response) => {
-                                                        // Convert Full<Bytes> to FileBody
-                                                        let (parts, body) = response.into_parts();
-                                                        let response_with_file_body = Response::from_parts(parts, FileBody::InMemory(body));
+                                                        let response_with_file_body = response.map(FileBody::Proxied);
                                                         Ok::<_, Infallible>(response_with_file_body)
                                                     }
                                                     Err(_) => {
@@ -594,12 +1011,13 @@ impl Proxy for CombinedProxyAdapter {
                                                 }
                                             }
 
-                                        }
+                                        })
                                     });
 
-                                    if let Err(e) = ServerBuilder::new()
-                                        .keep_alive(true)
-                                        .serve_connection(TokioIo::new(tls_stream), service)
+                                    let protocol = ConnectionProtocol::negotiated(tls_stream.alpn_protocol());
+
+                                    if let Err(e) = http_server::serve(tls_stream, service, protocol, http2.as_ref(), preserve_header_case)
+
                                         .await
                                     {
                                         error!("Error serving TLS connection: {}", e);
@@ -607,7 +1025,10 @@ impl Proxy for CombinedProxyAdapter {
                                 }
                                 Err(e) => {
                                     warn!("Error establishing TLS connection from {}: {}",
-                                          remote_addr, e);
+                                          privacy::peer(&remote_addr), e);
+                                    SecurityEvent::new(SecurityEventKind::TlsHandshakeFailed, e.to_string())
+                                        .client_ip(Some(&remote_addr.ip().to_string()))
+                                        .emit();
                                 }
                             }
                         });
@@ -617,108 +1038,116 @@ impl Proxy for CombinedProxyAdapter {
                     // HTTP mode
                     info!("Running in HTTP mode for combined proxy");
                     info!("Binding HTTP listener to: {}", addr);
-                    let listener = tokio::net::TcpListener::bind(addr).await
+                    let tcp_listener = listener::bind(addr, &self.listener)
                         .map_err(|e| ProxyError::Hyper(e.to_string()))?;
                     info!("HTTP combined proxy server listening on: http://{}", addr);
 
                     loop {
-                        let (stream, remote_addr) = listener.accept().await
+                        let (stream, remote_addr) = tcp_listener.accept().await
                             .map_err(|e| ProxyError::Hyper(e.to_string()))?;
+                        listener::tune_stream(&stream, &self.listener);
+                        if rate_limiter.refuses(&remote_addr) {
+                            continue;
+                        }
 
                         let reverse_proxy = reverse_proxy.clone();
                         let static_handler = static_handler.clone();
                         let rate_limiter = rate_limiter.clone();
-                        let client_ip = remote_addr.ip().to_string();
+                        let http2 = http2.clone();
                         tokio::spawn(async move {
-                            let io = TokioIo::new(stream);
-
-                            if let Err(err) = ServerBuilder::new()
-                                .serve_connection(
-                                    io,
-                                    service_fn(move |req| {
-                                        let reverse_proxy = reverse_proxy.clone();
-                                        let static_handler = static_handler.clone();
-                                        let rate_limiter = rate_limiter.clone();
-                                        let client_ip = client_ip.clone();
-                                        async move {
-                                            // Route request to appropriate handler
-                                            let request_path = req.uri().path();
-
-                                            // Check if request matches any static file mount
-                                            if let Some((_mount_info, _relative_path)) = static_handler.find_mount_for_path(request_path) {
-                                                if let Err(hit) = rate_limiter
-                                                    .check_request(
-                                                        &client_ip,
-                                                        req.method(),
-                                                        req.uri()
-                                                            .path_and_query()
-                                                            .map(|pq| pq.as_str())
-                                                            .unwrap_or("/"),
-                                                    )
-                                                    .await
-                                                {
+                            let protocol = ConnectionProtocol::plain(http2.as_ref());
+                            let preserve_header_case = reverse_proxy.preserves_header_case();
+                            if let Err(err) = http_server::serve(
+                                stream,
+                                service_fn(move |req| {
+                                    let reverse_proxy = reverse_proxy.clone();
+                                    let static_handler = static_handler.current();
+                                    let rate_limiter = rate_limiter.clone();
+                                    let client_ip = forwarded::client_ip(remote_addr.ip(), req.headers()).to_string();
+                                    rate_limiter.clone().observe(client_ip.clone(), async move {
+                                        // Route request to appropriate handler; a rejected path is answered by the reverse proxy
+                                        let serves_static = path_normalization::normalize(req.uri().path())
+                                            .is_ok_and(|path| static_handler.find_mount_for_path(&path).is_some());
+
+                                        // Check if request matches any static file mount
+                                        if serves_static {
+                                            let rate_limit_info = match rate_limiter
+                                                .check_request(
+                                                    &client_ip,
+                                                    req.method(),
+                                                    req.uri()
+                                                        .path_and_query()
+                                                        .map(|pq| pq.as_str())
+                                                        .unwrap_or("/"),
+                                                    req.headers(),
+                                                )
+                                                .await
+                                            {
+                                                Ok(info) => info,
+                                                Err(hit) => {
                                                     warn!(
                                                         "Combined HTTP rate limit hit for {} via rule {}",
-                                                        client_ip, hit.rule_id
+                                                        privacy::client_ip(&client_ip), hit.rule_id
                                                     );
                                                     return Ok::<_, Infallible>(StaticFileProxyAdapter::rate_limited_response(&hit));
                                                 }
+                                            };
 
-                                                // Serve static file
-                                                match static_handler.handle_request(&req).await {
-                                                    Ok(response) => Ok::<_, Infallible>(response),
-                                                    Err(ProxyError::NotFound(_)) => {
-                                                        // Fall back to reverse proxy if static file not found
-                                                        let context = crate::reverse_proxy::RequestContext {
-                                                            client_ip: Some(remote_addr.ip().to_string()),
-                                                        };
-                                                        match reverse_proxy.handle_request_with_context(req, context).await {
-                                                            Ok(response) => {
-                                                                // Convert Full<Bytes> to FileBody
-                                                                let (parts, body) = response.into_parts();
-                                                                let response_with_file_body = Response::from_parts(parts, FileBody::InMemory(body));
-                                                                Ok::<_, Infallible>(response_with_file_body)
-                                                            }
-                                                            Err(_) => {
-                                                                Ok::<_, Infallible>(Response::builder()
-                                                                    .status(StatusCode::BAD_GATEWAY)
-                                                                    .body(FileBody::InMemory(Full::new(Bytes::from("Proxy Error"))))
-                                                                    .unwrap())
-                                                            }
-                                                        }
-                                                    },
-                                                    Err(_) => {
-                                                        Ok::<_, Infallible>(Response::builder()
-                                                            .status(StatusCode::INTERNAL_SERVER_ERROR)
-                                                            .body(FileBody::InMemory(Full::new(Bytes::from("Internal Server Error"))))
-                                                            .unwrap())
+                                            // Serve static file
+                                            match static_handler.handle_request(&req).await {
+                                                Ok(mut response) => {
+                                                    if let Some(info) = &rate_limit_info {
+                                                        info.write_headers(response.headers_mut());
                                                     }
+                                                    Ok::<_, Infallible>(response)
                                                 }
-                                            } else {
-                                                // Forward to reverse proxy
-                                                let context = crate::reverse_proxy::RequestContext {
-                                                    client_ip: Some(remote_addr.ip().to_string()),
-                                                };
-                                                match reverse_proxy.handle_request_with_context(req, context).await {
-                                                    Ok(response) => {
-                                                        // Convert Full<Bytes> to FileBody
-                                                        let (parts, body) = response.into_parts();
-                                                        let response_with_file_body = Response::from_parts(parts, FileBody::InMemory(body));
-                                                        Ok::<_, Infallible>(response_with_file_body)
-                                                    }
-                                                    Err(_) => {
-                                                        Ok::<_, Infallible>(Response::builder()
-                                                            .status(StatusCode::BAD_GATEWAY)
-                                                            .body(FileBody::InMemory(Full::new(Bytes::from("Proxy Error"))))
-                                                            .unwrap())
+                                                Err(ProxyError::NotFound(_)) => {
+                                                    // Fall back to reverse proxy if static file not found
+                                                    let context = crate::reverse_proxy::RequestContext::from_peer(remote_addr.ip(), req.headers());
+                                                    match reverse_proxy.handle_request_with_context(req, context).await {
+                                                        Ok(response) => {
+                                                            let response_with_file_body = response.map(FileBody::Proxied);
+                                                            Ok::<_, Infallible>(response_with_file_body)
+                                                        }
+                                                        Err(_) => {
+                                                            Ok::<_, Infallible>(Response::builder()
+                                                                .status(StatusCode::BAD_GATEWAY)
+                                                                .body(FileBody::InMemory(Full::new(Bytes::from("Proxy Error"))))
+                                                                .unwrap())
+                                                        }
                                                     }
+                                                },
+                                                Err(_) => {
+                                                    Ok::<_, Infallible>(Response::builder()
+                                                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                                                        .body(FileBody::InMemory(Full::new(Bytes::from("Internal Server Error"))))
+                                                        .unwrap())
+                                                }
+                                            }
+                                        } else {
+                                            // Forward to reverse proxy
+                                            let context = crate::reverse_proxy::RequestContext::from_peer(remote_addr.ip(), req.headers());
+                                            match reverse_proxy.handle_request_with_context(req, context).await {
+                                                Ok(response) => {
+                                                    let response_with_file_body = response.map(FileBody::Proxied);
+                                                    Ok::<_, Infallible>(response_with_file_body)
+                                                }
+                                                Err(_) => {
+                                                    Ok::<_, Infallible>(Response::builder()
+                                                        .status(StatusCode::BAD_GATEWAY)
+                                                        .body(FileBody::InMemory(Full::new(Bytes::from("Proxy Error"))))
+                                                        .unwrap())
                                                 }
                                             }
-
                                         }
+
                                     })
-                                )
-                                .await
+                                }),
+                                protocol,
+                                http2.as_ref(),
+                                preserve_header_case,
+                            )
+                            .await
                             {
                                 error!("Error serving HTTP connection: {}", err);
                             }
@@ -766,6 +1195,18 @@ mod tests {
         let proxy = ProxyFactory::create_proxy(config);
         assert!(proxy.is_err());
     }
+
+    #[test]
+    fn test_proxy_factory_combined() {
+        let mut config = Config::default();
+        config.mode = ProxyMode::Combined;
+        config.listen_addr = "127.0.0.1:8080".parse().unwrap();
+        config.reverse_proxy_target = Some("http://backend.example.com".to_string());
+        assert!(ProxyFactory::create_proxy(config.clone()).is_err(), "forward_listen_addr is required");
+
+        config.forward_listen_addr = Some("127.0.0.1:3128".parse().unwrap());
+        assert!(ProxyFactory::create_proxy(config).is_ok());
+    }
 }
 
 /// Isolated proxy adapter that uses dedicated workers with separate resources
@@ -963,7 +1404,10 @@ impl IsolatedProxyAdapter {
                         request_timer.finish();
                     }
                     Err(e) => {
-                        error!("TLS handshake failed from {} to {}: {}", remote_addr, worker_ref.get_proxy_type(), e);
+                        error!("TLS handshake failed from {} to {}: {}", privacy::peer(&remote_addr), worker_ref.get_proxy_type(), e);
+                        SecurityEvent::new(SecurityEventKind::TlsHandshakeFailed, e.to_string())
+                            .client_ip(Some(&remote_addr.ip().to_string()))
+                            .emit();
                         worker_ref.metrics.increment_connection_errors();
                     }
                 }
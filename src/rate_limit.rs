@@ -1,95 +1,516 @@
-use crate::config::{RateLimitingConfig, RateLimitRuleConfig, RateLimitWindowConfig};
-use hyper::Method;
-use log::{debug, warn};
+use crate::auto_ban::AutoBan;
+use crate::ban_list::BanList;
+use crate::cluster::Cluster;
+use crate::config::{RateLimitClientMatchConfig, RateLimitingConfig, RateLimitRuleConfig};
+use crate::error::ProxyError;
+use crate::graphql::{Operation, OperationMatcher};
+use crate::notifications::{Notification, NotificationKind};
+use crate::privacy;
+use crate::security_log::{SecurityEvent, SecurityEventKind};
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{HeaderMap, Method, Response, StatusCode};
+use ipnet::IpNet;
+use log::{debug, info, warn};
+use prometheus::{GaugeVec, IntCounterVec, IntGaugeVec, Opts, Registry};
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
-use std::sync::Arc;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
 use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
+/// How often expired buckets are dropped and the bucket gauges refreshed.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+/// Period over which limited clients are tallied for introspection.
+const LIMITED_INTERVAL: Duration = Duration::from_secs(60);
+/// Clients listed in a snapshot's `top_limited_clients`.
+const TOP_LIMITED_CLIENTS: usize = 10;
+/// Header that carries API keys unless `api_keys.header_name` says otherwise.
+const DEFAULT_API_KEY_HEADER: &str = "X-API-Key";
+/// Rule id reported for clients on the deny list.
+const DENY_RULE_ID: &str = "deny";
+
 #[derive(Clone, Debug)]
 pub struct RateLimitHit {
     pub rule_id: String,
     pub retry_after_secs: u64,
+    /// 429, or the configured `deny_status` for denied clients
+    pub status: StatusCode,
+    /// Whether the client is on the deny list rather than over a limit
+    pub denied: bool,
+    /// Headers for the response, when `response_headers` is on
+    pub info: Option<RateLimitInfo>,
+}
+
+/// A client's standing against the tightest rule that counted its request, sent back in the
+/// `X-RateLimit-*` headers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimitInfo {
+    pub limit: u64,
+    pub remaining: u64,
+    /// Seconds until the rule's window for this client ends
+    pub reset_secs: u64,
 }
 
+impl RateLimitInfo {
+    pub fn write_headers(&self, headers: &mut HeaderMap) {
+        headers.insert("X-RateLimit-Limit", HeaderValue::from(self.limit));
+        headers.insert("X-RateLimit-Remaining", HeaderValue::from(self.remaining));
+        headers.insert("X-RateLimit-Reset", HeaderValue::from(self.reset_secs));
+    }
+}
+
+impl RateLimitHit {
+    /// Response body text for the hit.
+    pub fn message(&self) -> String {
+        if self.denied {
+            "Request denied.".to_string()
+        } else {
+            format!("Rate limit '{}' exceeded. Please retry later.", self.rule_id)
+        }
+    }
+}
+
+/// Per-client request limits.
+///
+/// The rule set can change while the proxy runs: through [`RateLimiter::upsert_rule`] and
+/// [`RateLimiter::remove_rule`], or by editing `rules_file`, which
+/// [`RateLimiter::refresh_loop`] re-reads. Counters of rules that survive a change are kept.
+/// [`RateLimiter::set_rule_paused`] stops a rule from counting without removing it.
 #[derive(Clone)]
 pub struct RateLimiter {
-    enabled: bool,
-    rules: Arc<Vec<RateLimitRule>>,
-    buckets: Arc<Mutex<HashMap<BucketKey, RateWindow>>>,
+    /// The configuration's `enabled` switch; without it rules are never enforced
+    switched_on: bool,
+    rules: Arc<RwLock<Arc<Vec<RateLimitRule>>>>,
+    state: Arc<Mutex<LimiterState>>,
+    /// Rules from the configuration itself (default tier first), which `rules_file` extends
+    inline_rules: Arc<RwLock<Arc<Vec<RateLimitRuleConfig>>>>,
+    rules_file: Option<String>,
+    refresh: Duration,
+    /// Clients refused before any rule is checked
+    deny: Arc<ClientMatcher>,
+    deny_status: StatusCode,
+    /// Header compared with the `api_keys` of exemption and deny lists
+    api_key_header: HeaderName,
+    /// Whether checks report the client's standing for the `X-RateLimit-*` headers
+    response_headers: bool,
+    /// Clients refused at accept, whether rate limiting is switched on or not
+    bans: Arc<BanList>,
+    /// Puts clients with too many error responses on `bans`
+    auto_ban: Option<Arc<AutoBan>>,
+    /// Shared counters in cluster mode; local ones are used while it is unreachable
+    cluster: Option<Arc<Cluster>>,
+}
+
+/// Configured rules, their counters and the clients limited most often, for the monitoring
+/// server's rate limit endpoint.
+#[derive(Debug, Serialize)]
+pub struct RateLimitSnapshot {
+    pub enabled: bool,
+    pub rules: Vec<RateLimitRuleSnapshot>,
+    /// Length of the period `top_limited_clients` covers
+    pub interval_secs: u64,
+    /// Clients limited most often in the last completed interval
+    pub top_limited_clients: Vec<LimitedClient>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RateLimitRuleSnapshot {
+    pub id: String,
+    pub limit: u64,
+    pub window_secs: u64,
+    pub path_prefix: Option<String>,
+    pub methods: Option<Vec<String>>,
+    /// Paused rules neither count nor limit requests
+    pub paused: bool,
+    /// Clients with an open window
+    pub active_clients: usize,
+    pub allowed_total: u64,
+    pub limited_total: u64,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct LimitedClient {
+    pub client: String,
+    pub rule: String,
+    pub limited: u64,
 }
 
 impl RateLimiter {
     pub fn new(config: Option<RateLimitingConfig>) -> Self {
-        if let Some(config) = config {
-            let mut rules = Vec::new();
+        let Some(config) = config else {
+            return Self::disabled();
+        };
 
-            if let Some(default_rule) = config.default_limit {
-                rules.push(RateLimitRule::from_default(default_rule));
+        let inline_rules = Self::inline_rules(&config);
+        let mut rule_configs = inline_rules.clone();
+        if let Some(path) = &config.rules_file {
+            match load_rules_file(path) {
+                Ok(file_rules) => rule_configs.extend(file_rules),
+                Err(e) => warn!("{}; starting without its rate limit rules", e),
             }
+        }
+        let rules = rule_configs.into_iter().filter_map(RateLimitRule::from_rule_config).collect();
 
-            for rule in config.rules {
-                if let Some(parsed) = RateLimitRule::from_rule_config(rule) {
-                    rules.push(parsed);
-                }
+        let deny = config
+            .deny
+            .as_ref()
+            .map(|deny| ClientMatcher::lenient(deny, "rate limit deny list"))
+            .unwrap_or_default();
+        let deny_status = match StatusCode::from_u16(config.deny_status) {
+            Ok(status) if matches!(status, StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS) => status,
+            _ => {
+                warn!("Unsupported rate limit deny_status {}; using 403", config.deny_status);
+                StatusCode::FORBIDDEN
             }
+        };
 
-            let enabled = config.enabled && !rules.is_empty();
+        Self {
+            switched_on: config.enabled,
+            rules: Arc::new(RwLock::new(Arc::new(rules))),
+            state: Arc::new(Mutex::new(LimiterState::new(Instant::now()))),
+            inline_rules: Arc::new(RwLock::new(Arc::new(inline_rules))),
+            rules_file: config.rules_file,
+            refresh: Duration::from_secs(config.refresh_secs.max(1)),
+            deny: Arc::new(deny),
+            deny_status,
+            api_key_header: HeaderName::from_static("x-api-key"),
+            response_headers: config.response_headers,
+            bans: Arc::new(BanList::new()),
+            auto_ban: None,
+            cluster: None,
+        }
+    }
 
-            Self {
-                enabled,
-                rules: Arc::new(rules),
-                buckets: Arc::new(Mutex::new(HashMap::new())),
-            }
-        } else {
-            Self::disabled()
+    /// The configuration's rules, with the default tier first.
+    fn inline_rules(config: &RateLimitingConfig) -> Vec<RateLimitRuleConfig> {
+        let mut inline_rules = Vec::new();
+        if let Some(default_rule) = &config.default_limit {
+            inline_rules.push(RateLimitRuleConfig {
+                id: "default".to_string(),
+                limit: default_rule.limit,
+                window_secs: default_rule.window_secs,
+                path_prefix: None,
+                methods: None,
+                exempt: None,
+                graphql: None,
+            });
+        }
+        inline_rules.extend(config.rules.iter().cloned());
+        inline_rules
+    }
+
+    /// Sets the header whose value is compared with the `api_keys` of exemption and deny lists.
+    pub fn with_api_key_header(mut self, header: &str) -> Self {
+        match HeaderName::from_bytes(header.as_bytes()) {
+            Ok(name) => self.api_key_header = name,
+            Err(_) => warn!("Invalid API key header '{}'; rate limit lists keep using {}", header, DEFAULT_API_KEY_HEADER),
         }
+        self
+    }
+
+    /// Sets the ban list checked at accept and the automatic bans that feed it.
+    pub fn with_bans(mut self, bans: Arc<BanList>, auto_ban: Option<AutoBan>) -> Self {
+        self.bans = bans;
+        self.auto_ban = auto_ban.map(Arc::new);
+        self
+    }
+
+    /// Counts requests in the cluster's shared windows instead of this instance's own.
+    pub fn with_cluster(mut self, cluster: Option<Arc<Cluster>>) -> Self {
+        self.cluster = cluster;
+        self
     }
 
     pub fn disabled() -> Self {
         Self {
-            enabled: false,
-            rules: Arc::new(Vec::new()),
-            buckets: Arc::new(Mutex::new(HashMap::new())),
+            switched_on: false,
+            rules: Arc::new(RwLock::new(Arc::new(Vec::new()))),
+            state: Arc::new(Mutex::new(LimiterState::new(Instant::now()))),
+            inline_rules: Arc::new(RwLock::new(Arc::new(Vec::new()))),
+            rules_file: None,
+            refresh: Duration::from_secs(RateLimitingConfig::default().refresh_secs),
+            deny: Arc::new(ClientMatcher::default()),
+            deny_status: StatusCode::FORBIDDEN,
+            api_key_header: HeaderName::from_static("x-api-key"),
+            response_headers: false,
+            bans: Arc::new(BanList::new()),
+            auto_ban: None,
+            cluster: None,
         }
     }
 
     pub fn is_enabled(&self) -> bool {
-        self.enabled
+        self.switched_on && (!self.rules().is_empty() || !self.deny.is_empty())
     }
 
+    /// Whether a connection from `remote_addr` is dropped right after accept because the client
+    /// is banned.
+    pub fn refuses(&self, remote_addr: &SocketAddr) -> bool {
+        self.bans.refuses(remote_addr)
+    }
+
+    /// Whether a request from `client_ip`, forwarded by a trusted proxy, is refused because
+    /// the client is banned.
+    pub fn refuses_client(&self, client_ip: &str) -> bool {
+        self.bans.refuses_client(client_ip)
+    }
+
+    /// Counts the status of a response to `client_ip` towards `auto_ban`.
+    pub fn record_status(&self, client_ip: &str, status: StatusCode) {
+        if let Some(auto_ban) = &self.auto_ban {
+            auto_ban.record(client_ip, status);
+        }
+    }
+
+    /// Awaits a response and counts its status towards `auto_ban`.
+    pub async fn observe<B, E>(
+        self: Arc<Self>,
+        client_ip: String,
+        response: impl Future<Output = Result<Response<B>, E>>,
+    ) -> Result<Response<B>, E> {
+        let response = response.await;
+        if let Ok(response) = &response {
+            self.record_status(&client_ip, response.status());
+        }
+        response
+    }
+
+    fn rules(&self) -> Arc<Vec<RateLimitRule>> {
+        self.rules.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Adds a rule, or replaces the rule with the same id; returns whether it was new.
+    /// Counters of a replaced rule are kept.
+    pub async fn upsert_rule(&self, config: RateLimitRuleConfig) -> Result<bool, String> {
+        if !self.switched_on {
+            return Err("rate limiting is disabled in the configuration".to_string());
+        }
+        let rule = RateLimitRule::try_from_rule_config(config)?;
+        let mut rules: Vec<RateLimitRule> = self.rules().as_ref().clone();
+        let created = match rules.iter_mut().find(|existing| existing.id == rule.id) {
+            Some(existing) => {
+                *existing = rule;
+                false
+            }
+            None => {
+                rules.push(rule);
+                true
+            }
+        };
+        self.install(rules).await;
+        Ok(created)
+    }
+
+    /// Removes a rule and its counters; returns whether it existed.
+    pub async fn remove_rule(&self, id: &str) -> bool {
+        let mut rules: Vec<RateLimitRule> = self.rules().as_ref().clone();
+        let before = rules.len();
+        rules.retain(|rule| rule.id != id);
+        if rules.len() == before {
+            return false;
+        }
+        self.install(rules).await;
+        true
+    }
+
+    /// Pauses or resumes a rule; returns whether it exists. A paused rule keeps its counters, and
+    /// is resumed when it is replaced or the rules are reloaded.
+    pub async fn set_rule_paused(&self, id: &str, paused: bool) -> bool {
+        let mut rules: Vec<RateLimitRule> = self.rules().as_ref().clone();
+        let Some(rule) = rules.iter_mut().find(|rule| rule.id == id) else {
+            return false;
+        };
+        rule.paused = paused;
+        self.install(rules).await;
+        true
+    }
+
+    /// Replaces the whole rule set; counters of rules whose id remains are kept.
+    pub async fn replace_rules(&self, configs: Vec<RateLimitRuleConfig>) -> Result<(), String> {
+        let mut rules: Vec<RateLimitRule> = Vec::with_capacity(configs.len());
+        for config in configs {
+            let rule = RateLimitRule::try_from_rule_config(config)?;
+            if rules.iter().any(|existing| existing.id == rule.id) {
+                return Err(format!("duplicate rate limit rule id '{}'", rule.id));
+            }
+            rules.push(rule);
+        }
+        self.install(rules).await;
+        Ok(())
+    }
+
+    async fn install(&self, rules: Vec<RateLimitRule>) {
+        let mut state = self.state.lock().await;
+        state.buckets.retain(|key, _| rules.iter().any(|rule| rule.id == key.rule_id));
+        state.totals.retain(|id, _| rules.iter().any(|rule| &rule.id == id));
+        for removed in self.rules().iter().filter(|old| !rules.iter().any(|rule| rule.id == old.id)) {
+            rate_limit_telemetry().forget(&removed.id);
+        }
+        *self.rules.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(rules);
+    }
+
+    /// Applies the rules of a reloaded configuration together with the current content of
+    /// `rules_file`; counters of rules whose id remains are kept. Switching rate limiting on or
+    /// off and moving `rules_file` need a restart; the other settings apply on restart.
+    pub async fn reload(&self, config: Option<RateLimitingConfig>) -> Result<(), String> {
+        let config = config.unwrap_or_else(|| RateLimitingConfig { enabled: false, ..RateLimitingConfig::default() });
+        if config.enabled != self.switched_on {
+            return Err("switching rate limiting on or off requires a restart".to_string());
+        }
+        if config.rules_file != self.rules_file {
+            return Err("moving rate_limiting.rules_file requires a restart".to_string());
+        }
+        let inline_rules = Self::inline_rules(&config);
+        let mut rules = inline_rules.clone();
+        if let Some(path) = &self.rules_file {
+            rules.extend(load_rules_file(path).map_err(|e| e.to_string())?);
+        }
+        self.replace_rules(rules).await?;
+        *self.inline_rules.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(inline_rules);
+        Ok(())
+    }
+
+    /// Starts watching `rules_file` in the background, when one is configured.
+    pub fn spawn_refresh(&self) {
+        if self.rules_file.is_some() {
+            tokio::spawn(self.clone().refresh_loop());
+        }
+    }
+
+    /// Re-reads `rules_file` every `refresh_secs` and applies it when its content changed.
+    /// Rules changed at runtime are replaced by the configuration's rules plus the file's.
+    pub async fn refresh_loop(self) {
+        let Some(path) = self.rules_file.clone() else {
+            return;
+        };
+        let mut last = std::fs::read_to_string(&path).ok();
+        loop {
+            tokio::time::sleep(self.refresh).await;
+            let content = match std::fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) => {
+                    warn!("Failed to reload rate limit rules from '{}': {}", path, e);
+                    continue;
+                }
+            };
+            if last.as_deref() == Some(content.as_str()) {
+                continue;
+            }
+            let applied = parse_rules(&path, &content).map(|file_rules| {
+                let mut rules = self.inline_rules.read().unwrap_or_else(|e| e.into_inner()).as_ref().clone();
+                rules.extend(file_rules);
+                rules
+            });
+            let applied = match applied {
+                Ok(rules) => self.replace_rules(rules).await,
+                Err(e) => Err(e.to_string()),
+            };
+            match applied {
+                Ok(()) => {
+                    info!("Reloaded rate limit rules from '{}'", path);
+                    Notification::new(NotificationKind::ConfigReloaded, "Reloaded rate limit rules")
+                        .detail("file", path.as_str())
+                        .send();
+                }
+                Err(e) => {
+                    warn!("Rejected rate limit rules from '{}': {}", path, e);
+                    Notification::new(NotificationKind::ConfigReloadFailed, format!("Rejected rate limit rules: {}", e))
+                        .detail("file", path.as_str())
+                        .send();
+                }
+            }
+            last = Some(content);
+        }
+    }
+
+    /// Checks a request against the deny list and every matching rule, counting it towards
+    /// the rules it does not exceed. `headers` feed the API key and header exemptions.
+    ///
+    /// With `response_headers` on, an allowed request returns its standing against the rule
+    /// with the fewest requests left.
     pub async fn check_request(
         &self,
         client_ip: &str,
         method: &Method,
         path: &str,
-    ) -> Result<(), RateLimitHit> {
-        if !self.enabled {
-            return Ok(());
+        headers: &HeaderMap,
+    ) -> Result<Option<RateLimitInfo>, RateLimitHit> {
+        self.check_operation(client_ip, method, path, headers, None).await
+    }
+
+    /// `check_request` for a request whose GraphQL operation is known; rules with a `graphql`
+    /// section only count requests whose operation they match.
+    pub async fn check_operation(
+        &self,
+        client_ip: &str,
+        method: &Method,
+        path: &str,
+        headers: &HeaderMap,
+        operation: Option<&Operation>,
+    ) -> Result<Option<RateLimitInfo>, RateLimitHit> {
+        if !self.switched_on {
+            return Ok(None);
         }
 
+        let telemetry = rate_limit_telemetry();
+        if self.deny.matches(client_ip, headers, &self.api_key_header) {
+            debug!("Rate limiting denied {} (deny list)", client_ip);
+            SecurityEvent::new(SecurityEventKind::RateLimited, "deny list")
+                .client_ip(Some(client_ip))
+                .target(path)
+                .emit();
+            telemetry.requests.with_label_values(&[DENY_RULE_ID, "denied"]).inc();
+            return Err(RateLimitHit {
+                rule_id: DENY_RULE_ID.to_string(),
+                retry_after_secs: 0,
+                status: self.deny_status,
+                denied: true,
+                info: None,
+            });
+        }
+
+        let rules = self.rules();
         let mut matched = Vec::new();
-        for rule in self.rules.iter() {
-            if rule.matches(method, path) {
-                matched.push(rule.clone());
+        for rule in rules.iter() {
+            if rule.paused || !rule.matches(method, path, operation) {
+                continue;
+            }
+            if rule.exempt.as_ref().is_some_and(|exempt| exempt.matches(client_ip, headers, &self.api_key_header)) {
+                telemetry.requests.with_label_values(&[rule.id.as_str(), "exempt"]).inc();
+                continue;
             }
+            matched.push(rule.clone());
         }
 
         if matched.is_empty() {
-            return Ok(());
+            return Ok(None);
+        }
+
+        if let Some(cluster) = &self.cluster {
+            match self.check_shared(cluster, &matched, client_ip, path).await {
+                Ok(result) => return result,
+                Err(e) => debug!("Shared rate limit counters unavailable, counting locally: {}", e),
+            }
         }
 
         let now = Instant::now();
-        let mut buckets = self.buckets.lock().await;
+        let mut state = self.state.lock().await;
+        if now.saturating_duration_since(state.last_sweep) >= SWEEP_INTERVAL {
+            state.sweep(&rules, now);
+        }
 
+        let mut tightest = None;
         for rule in matched {
             let key = BucketKey {
                 rule_id: rule.id.clone(),
                 client_id: client_ip.to_string(),
             };
 
-            let entry = buckets.entry(key).or_insert_with(|| RateWindow {
+            let entry = state.buckets.entry(key).or_insert_with(|| RateWindow {
                 count: 0,
                 window_start: now,
             });
@@ -110,19 +531,296 @@ impl RateLimiter {
                     "Rate limit exceeded for {} via rule {} (limit {}, window {:?})",
                     client_ip, rule.id, rule.limit, rule.window
                 );
+                state.totals.entry(rule.id.clone()).or_default().1 += 1;
+                state.limited.record(&rule.id, client_ip, now);
+                telemetry.requests.with_label_values(&[rule.id.as_str(), "limited"]).inc();
+                SecurityEvent::new(SecurityEventKind::RateLimited, format!("rule {}", rule.id))
+                    .client_ip(Some(client_ip))
+                    .target(path)
+                    .emit();
                 return Err(RateLimitHit {
                     rule_id: rule.id.clone(),
                     retry_after_secs: retry_after,
+                    status: StatusCode::TOO_MANY_REQUESTS,
+                    denied: false,
+                    info: self.response_headers.then_some(RateLimitInfo {
+                        limit: rule.limit,
+                        remaining: 0,
+                        reset_secs: retry_after,
+                    }),
                 });
             }
 
             entry.count += 1;
+            let standing = RateLimitInfo {
+                limit: rule.limit,
+                remaining: rule.limit - entry.count,
+                reset_secs: rule
+                    .window
+                    .saturating_sub(now.saturating_duration_since(entry.window_start))
+                    .as_secs()
+                    .max(1),
+            };
+            if tightest.is_none_or(|tightest: RateLimitInfo| standing.remaining < tightest.remaining) {
+                tightest = Some(standing);
+            }
+            state.totals.entry(rule.id.clone()).or_default().0 += 1;
+            telemetry.requests.with_label_values(&[rule.id.as_str(), "allowed"]).inc();
         }
 
-        Ok(())
+        Ok(tightest.filter(|_| self.response_headers))
+    }
+
+    /// Like the local check, over fixed windows shared by all instances of the cluster.
+    async fn check_shared(
+        &self,
+        cluster: &Cluster,
+        matched: &[RateLimitRule],
+        client_ip: &str,
+        path: &str,
+    ) -> Result<Result<Option<RateLimitInfo>, RateLimitHit>, ProxyError> {
+        let telemetry = rate_limit_telemetry();
+        let mut tightest = None;
+        for rule in matched {
+            let (count, reset_secs) = cluster.count_request(&rule.id, client_ip, rule.window.as_secs()).await?;
+            let mut state = self.state.lock().await;
+            if count > rule.limit {
+                debug!("Shared rate limit exceeded for {} via rule {} (limit {})", client_ip, rule.id, rule.limit);
+                state.totals.entry(rule.id.clone()).or_default().1 += 1;
+                state.limited.record(&rule.id, client_ip, Instant::now());
+                telemetry.requests.with_label_values(&[rule.id.as_str(), "limited"]).inc();
+                SecurityEvent::new(SecurityEventKind::RateLimited, format!("rule {}", rule.id))
+                    .client_ip(Some(client_ip))
+                    .target(path)
+                    .emit();
+                return Ok(Err(RateLimitHit {
+                    rule_id: rule.id.clone(),
+                    retry_after_secs: reset_secs,
+                    status: StatusCode::TOO_MANY_REQUESTS,
+                    denied: false,
+                    info: self.response_headers.then_some(RateLimitInfo {
+                        limit: rule.limit,
+                        remaining: 0,
+                        reset_secs,
+                    }),
+                }));
+            }
+            state.totals.entry(rule.id.clone()).or_default().0 += 1;
+            telemetry.requests.with_label_values(&[rule.id.as_str(), "allowed"]).inc();
+            let standing = RateLimitInfo { limit: rule.limit, remaining: rule.limit - count, reset_secs };
+            if tightest.is_none_or(|tightest: RateLimitInfo| standing.remaining < tightest.remaining) {
+                tightest = Some(standing);
+            }
+        }
+        Ok(Ok(tightest.filter(|_| self.response_headers)))
+    }
+
+    /// Describes the configured rules, how often each allowed or limited a request and which
+    /// clients were limited most in the last interval.
+    pub async fn snapshot(&self) -> RateLimitSnapshot {
+        let now = Instant::now();
+        let mut state = self.state.lock().await;
+        let rules = self.rules();
+        state.sweep(&rules, now);
+
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let (allowed_total, limited_total) = state.totals.get(&rule.id).copied().unwrap_or_default();
+                let mut methods = rule
+                    .methods
+                    .as_ref()
+                    .map(|methods| methods.iter().map(|m| m.to_string()).collect::<Vec<_>>());
+                if let Some(methods) = methods.as_mut() {
+                    methods.sort();
+                }
+                RateLimitRuleSnapshot {
+                    id: rule.id.clone(),
+                    limit: rule.limit,
+                    window_secs: rule.window.as_secs(),
+                    path_prefix: rule.path_prefix.clone(),
+                    methods,
+                    paused: rule.paused,
+                    active_clients: state.buckets.keys().filter(|key| key.rule_id == rule.id).count(),
+                    allowed_total,
+                    limited_total,
+                }
+            })
+            .collect();
+
+        RateLimitSnapshot {
+            enabled: self.is_enabled(),
+            rules,
+            interval_secs: LIMITED_INTERVAL.as_secs(),
+            top_limited_clients: state.limited.top(TOP_LIMITED_CLIENTS),
+        }
+    }
+}
+
+struct LimiterState {
+    buckets: HashMap<BucketKey, RateWindow>,
+    last_sweep: Instant,
+    /// Allowed and limited requests per rule id
+    totals: HashMap<String, (u64, u64)>,
+    limited: LimitedTally,
+}
+
+impl LimiterState {
+    fn new(now: Instant) -> Self {
+        Self {
+            buckets: HashMap::new(),
+            last_sweep: now,
+            totals: HashMap::new(),
+            limited: LimitedTally::new(now),
+        }
+    }
+
+    /// Drops buckets whose window has passed and refreshes the per-rule bucket gauges.
+    fn sweep(&mut self, rules: &[RateLimitRule], now: Instant) {
+        let windows: HashMap<&str, (Duration, u64)> =
+            rules.iter().map(|rule| (rule.id.as_str(), (rule.window, rule.limit))).collect();
+        self.buckets.retain(|key, bucket| {
+            windows
+                .get(key.rule_id.as_str())
+                .is_some_and(|(window, _)| now.saturating_duration_since(bucket.window_start) < *window)
+        });
+        self.limited.rotate(now);
+        self.last_sweep = now;
+
+        let telemetry = rate_limit_telemetry();
+        for rule in rules {
+            let open: Vec<u64> = self
+                .buckets
+                .iter()
+                .filter(|(key, _)| key.rule_id == rule.id)
+                .map(|(_, bucket)| bucket.count)
+                .collect();
+            let fullest = open.iter().copied().max().unwrap_or(0);
+            telemetry.active_buckets.with_label_values(&[rule.id.as_str()]).set(open.len() as i64);
+            telemetry
+                .occupancy
+                .with_label_values(&[rule.id.as_str()])
+                .set(fullest as f64 / rule.limit.max(1) as f64);
+        }
+    }
+}
+
+/// Limited requests per client and rule, for the current and the last completed interval.
+struct LimitedTally {
+    started: Instant,
+    current: HashMap<(String, String), u64>,
+    previous: HashMap<(String, String), u64>,
+}
+
+impl LimitedTally {
+    fn new(now: Instant) -> Self {
+        Self { started: now, current: HashMap::new(), previous: HashMap::new() }
+    }
+
+    fn record(&mut self, rule_id: &str, client: &str, now: Instant) {
+        self.rotate(now);
+        *self.current.entry((client.to_string(), rule_id.to_string())).or_default() += 1;
+    }
+
+    fn rotate(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.started);
+        if elapsed < LIMITED_INTERVAL {
+            return;
+        }
+        self.previous = if elapsed < LIMITED_INTERVAL * 2 {
+            std::mem::take(&mut self.current)
+        } else {
+            self.current.clear();
+            HashMap::new()
+        };
+        self.started = now;
+    }
+
+    fn top(&self, count: usize) -> Vec<LimitedClient> {
+        let mut clients: Vec<LimitedClient> = self
+            .previous
+            .iter()
+            .map(|((client, rule), limited)| LimitedClient {
+                client: privacy::client_ip(client).into_owned(),
+                rule: rule.clone(),
+                limited: *limited,
+            })
+            .collect();
+        clients.sort_by(|a, b| b.limited.cmp(&a.limited).then_with(|| a.client.cmp(&b.client)));
+        clients.truncate(count);
+        clients
+    }
+}
+
+struct RateLimitTelemetry {
+    requests: IntCounterVec,
+    active_buckets: IntGaugeVec,
+    occupancy: GaugeVec,
+    registered: AtomicBool,
+}
+
+impl RateLimitTelemetry {
+    fn new() -> Self {
+        Self {
+            requests: IntCounterVec::new(
+                Opts::new("rate_limit_requests_total", "Requests checked by each rate limit rule, by outcome")
+                    .namespace("bifrost"),
+                &["rule", "outcome"],
+            ).expect("rate_limit_requests_total metric"),
+            active_buckets: IntGaugeVec::new(
+                Opts::new("rate_limit_active_buckets", "Clients with an open rate limit window per rule")
+                    .namespace("bifrost"),
+                &["rule"],
+            ).expect("rate_limit_active_buckets metric"),
+            occupancy: GaugeVec::new(
+                Opts::new("rate_limit_bucket_occupancy", "Share of the limit used by the fullest open bucket per rule")
+                    .namespace("bifrost"),
+                &["rule"],
+            ).expect("rate_limit_bucket_occupancy metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        let collectors: [Box<dyn prometheus::core::Collector>; 3] = [
+            Box::new(self.requests.clone()),
+            Box::new(self.active_buckets.clone()),
+            Box::new(self.occupancy.clone()),
+        ];
+        for collector in collectors {
+            if let Err(err) = registry.register(collector) {
+                warn!("Failed to register rate limit metric: {}", err);
+                return;
+            }
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+impl RateLimitTelemetry {
+    /// Drops the series of a rule that no longer exists.
+    fn forget(&self, rule_id: &str) {
+        for outcome in ["allowed", "limited", "exempt"] {
+            let _ = self.requests.remove_label_values(&[rule_id, outcome]);
+        }
+        let _ = self.active_buckets.remove_label_values(&[rule_id]);
+        let _ = self.occupancy.remove_label_values(&[rule_id]);
     }
 }
 
+fn rate_limit_telemetry() -> &'static RateLimitTelemetry {
+    static TELEMETRY: OnceLock<RateLimitTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(RateLimitTelemetry::new)
+}
+
+/// Registers the per-rule rate limit metrics with the shared Prometheus registry.
+pub fn register_rate_limit_metrics(registry: &Registry) {
+    rate_limit_telemetry().register_if_needed(registry);
+}
+
 #[derive(Clone)]
 struct RateLimitRule {
     id: String,
@@ -130,19 +828,12 @@ struct RateLimitRule {
     window: Duration,
     path_prefix: Option<String>,
     methods: Option<HashSet<Method>>,
+    exempt: Option<ClientMatcher>,
+    graphql: Option<OperationMatcher>,
+    paused: bool,
 }
 
 impl RateLimitRule {
-    fn from_default(config: RateLimitWindowConfig) -> Self {
-        Self {
-            id: "default".to_string(),
-            limit: config.limit,
-            window: Duration::from_secs(config.window_secs),
-            path_prefix: None,
-            methods: None,
-        }
-    }
-
     fn from_rule_config(config: RateLimitRuleConfig) -> Option<Self> {
         if config.limit == 0 || config.window_secs == 0 {
             warn!(
@@ -151,7 +842,34 @@ impl RateLimitRule {
             );
             return None;
         }
+        if let Some(Err(e)) = config.graphql.as_ref().map(OperationMatcher::new) {
+            warn!("Ignoring rate limit rule '{}': {}", config.id, e);
+            return None;
+        }
+        Some(Self::build(config))
+    }
+
+    /// Like `from_rule_config`, but reports what is wrong with the rule instead of skipping it.
+    fn try_from_rule_config(config: RateLimitRuleConfig) -> Result<Self, String> {
+        if config.id.trim().is_empty() {
+            return Err("rate limit rule id must not be empty".to_string());
+        }
+        if config.limit == 0 || config.window_secs == 0 {
+            return Err(format!("rate limit rule '{}' needs a limit and window greater than zero", config.id));
+        }
+        if let Some(method) = config.methods.iter().flatten().find(|m| Method::from_bytes(m.trim().as_bytes()).is_err()) {
+            return Err(format!("rate limit rule '{}' has unsupported method '{}'", config.id, method));
+        }
+        if let Some(problem) = config.exempt.as_ref().and_then(|exempt| ClientMatcher::parse(exempt).1.into_iter().next()) {
+            return Err(format!("rate limit rule '{}' has an invalid exemption: {}", config.id, problem));
+        }
+        if let Some(Err(e)) = config.graphql.as_ref().map(OperationMatcher::new) {
+            return Err(format!("rate limit rule '{}': {}", config.id, e));
+        }
+        Ok(Self::build(config))
+    }
 
+    fn build(config: RateLimitRuleConfig) -> Self {
         let path_prefix = config
             .path_prefix
             .as_ref()
@@ -168,16 +886,34 @@ impl RateLimitRule {
                 .collect::<HashSet<_>>()
         });
 
-        Some(Self {
+        let exempt = config
+            .exempt
+            .as_ref()
+            .map(|exempt| ClientMatcher::lenient(exempt, &format!("rate limit rule {} exemptions", config.id)))
+            .filter(|exempt| !exempt.is_empty());
+
+        // Both constructors skip rules whose operation types do not parse
+        let graphql = config.graphql.as_ref().and_then(|graphql| OperationMatcher::new(graphql).ok());
+
+        Self {
             id: config.id,
             limit: config.limit,
             window: Duration::from_secs(config.window_secs),
             path_prefix,
             methods,
-        })
+            exempt,
+            graphql,
+            paused: false,
+        }
     }
 
-    fn matches(&self, method: &Method, path: &str) -> bool {
+    fn matches(&self, method: &Method, path: &str, operation: Option<&Operation>) -> bool {
+        if let Some(graphql) = &self.graphql
+            && !operation.is_some_and(|operation| graphql.matches_operation(operation))
+        {
+            return false;
+        }
+
         if let Some(methods) = &self.methods {
             if !methods.contains(method) {
                 return false;
@@ -192,6 +928,84 @@ impl RateLimitRule {
     }
 }
 
+/// Clients picked out by address, API key or header value, for exemptions and the deny list.
+#[derive(Clone, Debug, Default)]
+struct ClientMatcher {
+    nets: Vec<IpNet>,
+    api_keys: HashSet<String>,
+    headers: Vec<(HeaderName, HashSet<String>)>,
+}
+
+impl ClientMatcher {
+    /// Builds the matcher from the entries that parse and describes the ones that do not.
+    fn parse(config: &RateLimitClientMatchConfig) -> (Self, Vec<String>) {
+        let mut problems = Vec::new();
+        let nets = config
+            .cidrs
+            .iter()
+            .filter_map(|cidr| {
+                let cidr = cidr.trim();
+                let parsed = cidr.parse::<IpNet>().or_else(|_| cidr.parse::<IpAddr>().map(IpNet::from));
+                parsed.map_err(|_| problems.push(format!("invalid CIDR '{}'", cidr))).ok()
+            })
+            .collect();
+        let api_keys = config
+            .api_keys
+            .iter()
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())
+            .collect();
+        let headers = config
+            .headers
+            .iter()
+            .filter_map(|(name, values)| match HeaderName::from_bytes(name.trim().as_bytes()) {
+                Ok(name) => Some((name, values.iter().map(|value| value.trim().to_string()).collect())),
+                Err(_) => {
+                    problems.push(format!("invalid header name '{}'", name));
+                    None
+                }
+            })
+            .collect();
+        (Self { nets, api_keys, headers }, problems)
+    }
+
+    /// Like `parse`, but logs the entries it skips.
+    fn lenient(config: &RateLimitClientMatchConfig, context: &str) -> Self {
+        let (matcher, problems) = Self::parse(config);
+        for problem in problems {
+            warn!("Ignoring {} entry: {}", context, problem);
+        }
+        matcher
+    }
+
+    fn is_empty(&self) -> bool {
+        self.nets.is_empty() && self.api_keys.is_empty() && self.headers.is_empty()
+    }
+
+    fn matches(&self, client_ip: &str, headers: &HeaderMap, api_key_header: &HeaderName) -> bool {
+        if !self.nets.is_empty()
+            && let Ok(ip) = client_ip.parse::<IpAddr>()
+            && self.nets.iter().any(|net| net.contains(&ip))
+        {
+            return true;
+        }
+        let header_value = |name: &HeaderName| {
+            headers
+                .get_all(name)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .map(str::trim)
+                .collect::<Vec<_>>()
+        };
+        if !self.api_keys.is_empty() && header_value(api_key_header).iter().any(|key| self.api_keys.contains(*key)) {
+            return true;
+        }
+        self.headers
+            .iter()
+            .any(|(name, values)| header_value(name).iter().any(|value| values.contains(*value)))
+    }
+}
+
 #[derive(Hash, Eq, PartialEq)]
 struct BucketKey {
     rule_id: String,
@@ -203,6 +1017,67 @@ struct RateWindow {
     window_start: Instant,
 }
 
+/// Parses a command-line rule such as `100/60s per-ip /api POST,PUT`: a limit per window
+/// (`s`, `m` or `h`; seconds when no unit is given), then optionally the scope, a path prefix
+/// and a comma-separated method list. `per-ip` is the only scope; every rule counts per client.
+pub fn parse_rule_spec(id: &str, spec: &str) -> Result<RateLimitRuleConfig, String> {
+    let mut tokens = spec.split_whitespace();
+    let rate = tokens.next().ok_or("empty rate limit")?;
+    let (limit, window) = rate
+        .split_once('/')
+        .ok_or_else(|| format!("'{}' is not LIMIT/WINDOW", rate))?;
+    let limit: u64 = limit
+        .parse()
+        .map_err(|_| format!("invalid request limit '{}'", limit))?;
+    let (amount, unit_secs) = match window.char_indices().last() {
+        Some((idx, 's')) => (&window[..idx], 1),
+        Some((idx, 'm')) => (&window[..idx], 60),
+        Some((idx, 'h')) => (&window[..idx], 3600),
+        _ => (window, 1),
+    };
+    let window_secs = amount
+        .parse::<u64>()
+        .map_err(|_| format!("invalid window '{}'", window))?
+        * unit_secs;
+    if limit == 0 || window_secs == 0 {
+        return Err("limit and window must be greater than zero".to_string());
+    }
+
+    let mut rule = RateLimitRuleConfig {
+        id: id.to_string(),
+        limit,
+        window_secs,
+        path_prefix: None,
+        methods: None,
+        exempt: None,
+        graphql: None,
+    };
+    for token in tokens {
+        if token == "per-ip" {
+            continue;
+        }
+        if token.starts_with('/') {
+            rule.path_prefix = Some(token.to_string());
+        } else if token.chars().all(|c| c.is_ascii_alphabetic() || c == ',') {
+            rule.methods = Some(token.split(',').map(|m| m.to_ascii_uppercase()).collect());
+        } else {
+            return Err(format!("unexpected '{}' (expected per-ip, a path prefix or methods)", token));
+        }
+    }
+    Ok(rule)
+}
+
+fn load_rules_file(path: &str) -> Result<Vec<RateLimitRuleConfig>, ProxyError> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| ProxyError::Config(format!("Failed to read rate limit rules file '{}': {}", path, e)))?;
+    parse_rules(path, &content)
+}
+
+fn parse_rules(path: &str, content: &str) -> Result<Vec<RateLimitRuleConfig>, ProxyError> {
+    serde_json::from_str(content)
+        .map_err(|e| ProxyError::Config(format!("Invalid rate limit rules file '{}': {}", path, e)))
+}
+
 fn normalize_path_prefix(prefix: &str) -> Option<String> {
     let trimmed = prefix.trim();
     if trimmed.is_empty() {
@@ -215,3 +1090,222 @@ fn normalize_path_prefix(prefix: &str) -> Option<String> {
         Some(format!("/{}", trimmed))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn snapshot_reports_rule_counters_and_limited_clients() {
+        let limiter = RateLimiter::new(Some(RateLimitingConfig {
+            enabled: true,
+            rules: vec![parse_rule_spec("api", "2/60s /api GET").unwrap()],
+            ..RateLimitingConfig::default()
+        }));
+        for _ in 0..3 {
+            let _ = limiter.check_request("10.0.0.1", &Method::GET, "/api/items", &HeaderMap::new()).await;
+        }
+        assert!(limiter.check_request("10.0.0.2", &Method::GET, "/api/items", &HeaderMap::new()).await.is_ok());
+        assert!(limiter.check_request("10.0.0.1", &Method::GET, "/other", &HeaderMap::new()).await.is_ok());
+
+        let snapshot = limiter.snapshot().await;
+        let rule = &snapshot.rules[0];
+        assert_eq!((rule.allowed_total, rule.limited_total), (3, 1));
+        assert_eq!(rule.active_clients, 2);
+        assert_eq!(rule.methods.as_deref(), Some(&["GET".to_string()][..]));
+        assert!(snapshot.top_limited_clients.is_empty(), "the current interval is not over yet");
+
+        let mut tally = LimitedTally::new(Instant::now());
+        let start = tally.started;
+        tally.record("api", "10.0.0.1", start);
+        tally.record("api", "10.0.0.1", start);
+        tally.record("api", "10.0.0.3", start);
+        tally.rotate(start + LIMITED_INTERVAL);
+        assert_eq!(
+            tally.top(1),
+            vec![LimitedClient { client: "10.0.0.1".to_string(), rule: "api".to_string(), limited: 2 }]
+        );
+        tally.rotate(start + LIMITED_INTERVAL * 4);
+        assert!(tally.top(10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn runtime_rule_updates_keep_counters_of_surviving_rules() {
+        let limiter = RateLimiter::new(Some(RateLimitingConfig {
+            enabled: true,
+            rules: vec![parse_rule_spec("api", "2/60s /api").unwrap()],
+            ..RateLimitingConfig::default()
+        }));
+        for _ in 0..2 {
+            assert!(limiter.check_request("10.0.0.1", &Method::GET, "/api/a", &HeaderMap::new()).await.is_ok());
+        }
+
+        let mut raised = parse_rule_spec("api", "3/60s /api").unwrap();
+        assert_eq!(limiter.upsert_rule(raised.clone()).await, Ok(false));
+        assert!(limiter.check_request("10.0.0.1", &Method::GET, "/api/a", &HeaderMap::new()).await.is_ok());
+        assert!(limiter.check_request("10.0.0.1", &Method::GET, "/api/a", &HeaderMap::new()).await.is_err());
+        assert_eq!(limiter.snapshot().await.rules[0].allowed_total, 3);
+
+        raised.id = "other".to_string();
+        raised.limit = 0;
+        assert!(limiter.upsert_rule(raised).await.is_err());
+        assert_eq!(limiter.upsert_rule(parse_rule_spec("login", "1/60s /login").unwrap()).await, Ok(true));
+
+        assert!(limiter.remove_rule("api").await);
+        assert!(!limiter.remove_rule("api").await);
+        assert!(limiter.check_request("10.0.0.1", &Method::GET, "/api/a", &HeaderMap::new()).await.is_ok());
+        let ids: Vec<String> = limiter.snapshot().await.rules.into_iter().map(|rule| rule.id).collect();
+        assert_eq!(ids, vec!["login".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn paused_rules_stop_limiting_until_resumed() {
+        let limiter = RateLimiter::new(Some(RateLimitingConfig {
+            enabled: true,
+            rules: vec![parse_rule_spec("api", "1/60s /api").unwrap()],
+            ..RateLimitingConfig::default()
+        }));
+        assert!(limiter.check_request("10.0.0.1", &Method::GET, "/api/a", &HeaderMap::new()).await.is_ok());
+        assert!(limiter.check_request("10.0.0.1", &Method::GET, "/api/a", &HeaderMap::new()).await.is_err());
+
+        assert!(limiter.set_rule_paused("api", true).await);
+        assert!(!limiter.set_rule_paused("missing", true).await);
+        assert!(limiter.check_request("10.0.0.1", &Method::GET, "/api/a", &HeaderMap::new()).await.is_ok());
+        let snapshot = limiter.snapshot().await;
+        assert!(snapshot.rules[0].paused);
+        assert_eq!((snapshot.rules[0].allowed_total, snapshot.rules[0].limited_total), (1, 1));
+
+        assert!(limiter.set_rule_paused("api", false).await);
+        assert!(limiter.check_request("10.0.0.1", &Method::GET, "/api/a", &HeaderMap::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reloaded_configuration_replaces_rules_and_keeps_counters() {
+        let config = |rules: &[(&str, &str)]| RateLimitingConfig {
+            enabled: true,
+            rules: rules.iter().map(|(id, spec)| parse_rule_spec(id, spec).unwrap()).collect(),
+            ..RateLimitingConfig::default()
+        };
+        let limiter = RateLimiter::new(Some(config(&[("api", "2/60s /api")])));
+        for _ in 0..2 {
+            assert!(limiter.check_request("10.0.0.1", &Method::GET, "/api/a", &HeaderMap::new()).await.is_ok());
+        }
+
+        let reloaded = config(&[("api", "3/60s /api"), ("login", "1/60s /login")]);
+        limiter.reload(Some(reloaded)).await.unwrap();
+        assert!(limiter.check_request("10.0.0.1", &Method::GET, "/api/a", &HeaderMap::new()).await.is_ok());
+        assert!(limiter.check_request("10.0.0.1", &Method::GET, "/api/a", &HeaderMap::new()).await.is_err());
+        assert!(limiter.check_request("10.0.0.1", &Method::GET, "/login", &HeaderMap::new()).await.is_ok());
+
+        assert!(limiter.reload(None).await.is_err());
+        let mut invalid = config(&[("api", "3/60s /api")]);
+        invalid.rules[0].limit = 0;
+        assert!(limiter.reload(Some(invalid)).await.is_err());
+        assert_eq!(limiter.snapshot().await.rules.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn exempt_clients_skip_rules_and_denied_clients_are_refused() {
+        let mut rule = parse_rule_spec("api", "1/60s /api").unwrap();
+        rule.exempt = Some(RateLimitClientMatchConfig {
+            cidrs: vec!["192.0.2.0/24".to_string()],
+            api_keys: vec!["partner-key".to_string()],
+            headers: HashMap::from([("X-Partner".to_string(), vec!["acme".to_string()])]),
+        });
+        let limiter = RateLimiter::new(Some(RateLimitingConfig {
+            enabled: true,
+            rules: vec![rule],
+            deny: Some(RateLimitClientMatchConfig {
+                cidrs: vec!["198.51.100.7".to_string()],
+                ..RateLimitClientMatchConfig::default()
+            }),
+            deny_status: 429,
+            ..RateLimitingConfig::default()
+        }))
+        .with_api_key_header("X-Key");
+        let none = HeaderMap::new();
+        let header = |name: &'static str, value: &'static str| {
+            HeaderMap::from_iter([(HeaderName::from_static(name), value.parse().unwrap())])
+        };
+
+        for _ in 0..3 {
+            assert!(limiter.check_request("192.0.2.10", &Method::GET, "/api", &none).await.is_ok());
+            assert!(limiter.check_request("10.0.0.1", &Method::GET, "/api", &header("x-key", "partner-key")).await.is_ok());
+            assert!(limiter.check_request("10.0.0.2", &Method::GET, "/api", &header("x-partner", "acme")).await.is_ok());
+        }
+        assert!(limiter.check_request("10.0.0.3", &Method::GET, "/api", &header("x-partner", "other")).await.is_ok());
+        let hit = limiter.check_request("10.0.0.3", &Method::GET, "/api", &none).await.unwrap_err();
+        assert!(!hit.denied);
+
+        let hit = limiter.check_request("198.51.100.7", &Method::GET, "/elsewhere", &none).await.unwrap_err();
+        assert!(hit.denied);
+        assert_eq!(hit.status, StatusCode::TOO_MANY_REQUESTS);
+
+        let mut invalid = parse_rule_spec("bad", "1/60s").unwrap();
+        invalid.exempt = Some(RateLimitClientMatchConfig { cidrs: vec!["10.0.0.0/99".to_string()], ..Default::default() });
+        assert!(limiter.upsert_rule(invalid).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn graphql_rules_count_only_their_operations() {
+        use crate::config::GraphQlOperationMatchConfig;
+        use crate::graphql::OperationType;
+
+        let mut mutations = parse_rule_spec("mutations", "1/60s /graphql").unwrap();
+        mutations.graphql = Some(GraphQlOperationMatchConfig {
+            operation_names: Vec::new(),
+            operation_types: vec!["mutation".to_string()],
+        });
+        let limiter = RateLimiter::new(Some(RateLimitingConfig {
+            enabled: true,
+            rules: vec![mutations],
+            ..RateLimitingConfig::default()
+        }));
+        let none = HeaderMap::new();
+        let operation = |kind| Operation { name: Some("AddOrder".to_string()), kind: Some(kind) };
+        let check = |op: Option<Operation>| {
+            let limiter = &limiter;
+            let none = &none;
+            async move { limiter.check_operation("10.0.0.1", &Method::POST, "/graphql", none, op.as_ref()).await }
+        };
+
+        assert!(check(Some(operation(OperationType::Mutation))).await.is_ok());
+        for _ in 0..3 {
+            assert!(check(Some(operation(OperationType::Query))).await.is_ok());
+            assert!(check(None).await.is_ok());
+        }
+        assert!(check(Some(operation(OperationType::Mutation))).await.is_err());
+
+        let mut invalid = parse_rule_spec("bad", "1/60s").unwrap();
+        invalid.graphql = Some(GraphQlOperationMatchConfig {
+            operation_names: Vec::new(),
+            operation_types: vec!["fragment".to_string()],
+        });
+        assert!(limiter.upsert_rule(invalid).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn response_headers_report_the_tightest_rule() {
+        let limiter = RateLimiter::new(Some(RateLimitingConfig {
+            enabled: true,
+            default_limit: Some(crate::config::RateLimitWindowConfig { limit: 100, window_secs: 60 }),
+            rules: vec![parse_rule_spec("login", "2/30s /login").unwrap()],
+            response_headers: true,
+            ..RateLimitingConfig::default()
+        }));
+        let none = HeaderMap::new();
+
+        let info = limiter.check_request("10.0.0.1", &Method::POST, "/login", &none).await.unwrap().unwrap();
+        assert_eq!((info.limit, info.remaining), (2, 1));
+        assert!(info.reset_secs <= 30);
+        let info = limiter.check_request("10.0.0.1", &Method::GET, "/home", &none).await.unwrap().unwrap();
+        assert_eq!((info.limit, info.remaining), (100, 98));
+
+        limiter.check_request("10.0.0.1", &Method::POST, "/login", &none).await.unwrap();
+        let hit = limiter.check_request("10.0.0.1", &Method::POST, "/login", &none).await.unwrap_err();
+        let mut headers = HeaderMap::new();
+        hit.info.unwrap().write_headers(&mut headers);
+        assert_eq!(headers["X-RateLimit-Remaining"], "0");
+        assert_eq!(headers["X-RateLimit-Limit"], "2");
+    }
+}
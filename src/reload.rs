@@ -0,0 +1,133 @@
+//! Configuration reload while the proxy runs.
+//!
+//! The configuration file is read again on SIGHUP and, with `config_reload.watch`, whenever its
+//! modification time changes. The new configuration goes through the same loading and validation
+//! as at startup before [`ProxyHandle::reconfigure`] applies it; one that fails either step is
+//! rejected and the running configuration stays in effect.
+
+use crate::config::{Config, ConfigReloadConfig};
+use crate::notifications::{Notification, NotificationKind};
+use crate::proxy::ProxyHandle;
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+/// Reads, validates and returns the configuration at a path.
+pub type ConfigLoader = Arc<dyn Fn(&Path) -> Result<Config, String> + Send + Sync>;
+
+pub struct ConfigReloader {
+    path: PathBuf,
+    load: ConfigLoader,
+    handle: ProxyHandle,
+    /// How often the file's modification time is compared, when watching
+    watch: Option<Duration>,
+}
+
+impl ConfigReloader {
+    pub fn new(path: impl Into<PathBuf>, load: ConfigLoader, handle: ProxyHandle) -> Self {
+        Self { path: path.into(), load, handle, watch: None }
+    }
+
+    pub fn with_settings(mut self, settings: &ConfigReloadConfig) -> Self {
+        self.watch = settings.watch.then(|| Duration::from_secs(settings.watch_interval_secs.max(1)));
+        self
+    }
+
+    /// Starts reloading on SIGHUP and, when watching, on changes to the file.
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(self.run())
+    }
+
+    async fn run(self) {
+        let mut hangup = Hangup::new();
+        let mut last_modified = modified(&self.path);
+        let mut watch = self.watch.map(|interval| {
+            let mut timer = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            timer
+        });
+        match self.watch {
+            Some(interval) => info!(
+                "Reloading '{}' on SIGHUP and when it changes (checked every {}s)",
+                self.path.display(),
+                interval.as_secs()
+            ),
+            None => info!("Reloading '{}' on SIGHUP", self.path.display()),
+        }
+
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => info!("SIGHUP received; reloading '{}'", self.path.display()),
+                _ = async { watch.as_mut().unwrap().tick().await }, if watch.is_some() => {
+                    let current = modified(&self.path);
+                    if current == last_modified {
+                        continue;
+                    }
+                    last_modified = current;
+                    info!("'{}' changed; reloading", self.path.display());
+                }
+            }
+            let _ = self.reload().await;
+        }
+    }
+
+    /// Loads the file and applies it; the error says why it was rejected.
+    pub async fn reload(&self) -> Result<(), String> {
+        let applied = match (self.load)(&self.path) {
+            Ok(config) => self.handle.reconfigure(config).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        let file = self.path.display().to_string();
+        match &applied {
+            Ok(()) => {
+                info!("Applied configuration from '{}'", file);
+                Notification::new(NotificationKind::ConfigReloaded, "Reloaded the configuration")
+                    .detail("file", file.as_str())
+                    .send();
+            }
+            Err(e) => {
+                warn!("Rejected configuration from '{}', keeping the running one: {}", file, e);
+                Notification::new(NotificationKind::ConfigReloadFailed, format!("Rejected the configuration: {}", e))
+                    .detail("file", file.as_str())
+                    .send();
+            }
+        }
+        applied
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// SIGHUP deliveries; never fires where there are no Unix signals.
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    fn new() -> Self {
+        #[cfg(unix)]
+        {
+            let signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .map_err(|e| warn!("Cannot listen for SIGHUP, configuration reload on signal is off: {}", e))
+                .ok();
+            Self { signal }
+        }
+        #[cfg(not(unix))]
+        Self {}
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            signal.recv().await;
+            return;
+        }
+        std::future::pending::<()>().await
+    }
+}
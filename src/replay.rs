@@ -0,0 +1,292 @@
+use crate::config::{ReplayConfig, ReplayMode};
+use crate::error::ProxyError;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Method, Request, Response, StatusCode};
+use log::{debug, warn};
+use prometheus::{IntCounterVec, Opts, Registry};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Header marking responses served from a recording.
+pub const REPLAY_HEADER: &str = "x-bifrost-replay";
+
+/// Recorded backend responses of one route.
+///
+/// A recording is keyed by method, path and query plus the values of `match_headers`, and
+/// stored as one JSON file under `<dir>/<route id>/`. File names start with the method and a
+/// readable form of the path, followed by a hash of the full key, so recordings can be found and
+/// edited by hand.
+pub struct ReplayStore {
+    route_id: String,
+    mode: ReplayMode,
+    dir: PathBuf,
+    methods: HashSet<Method>,
+    match_headers: Vec<HeaderName>,
+    unavailable: HashSet<StatusCode>,
+}
+
+/// Where the recording of one request lives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayKey {
+    file: PathBuf,
+    method: String,
+    uri: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Recording {
+    method: String,
+    uri: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    /// Base64, so binary bodies survive the JSON file
+    body: String,
+}
+
+impl ReplayStore {
+    pub fn new(route_id: &str, config: &ReplayConfig) -> Result<Self, ProxyError> {
+        if config.dir.trim().is_empty() {
+            return Err(ProxyError::Config(format!("Route {}: replay.dir must not be empty", route_id)));
+        }
+        let methods = config
+            .methods
+            .iter()
+            .map(|method| {
+                Method::from_bytes(method.trim().to_ascii_uppercase().as_bytes()).map_err(|_| {
+                    ProxyError::Config(format!("Route {}: invalid replay method '{}'", route_id, method))
+                })
+            })
+            .collect::<Result<HashSet<_>, _>>()?;
+        let match_headers = config
+            .match_headers
+            .iter()
+            .map(|name| {
+                HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| {
+                    ProxyError::Config(format!("Route {}: invalid replay match header '{}'", route_id, name))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let unavailable = config
+            .unavailable_statuses
+            .iter()
+            .map(|status| {
+                StatusCode::from_u16(*status).map_err(|_| {
+                    ProxyError::Config(format!("Route {}: invalid replay status {}", route_id, status))
+                })
+            })
+            .collect::<Result<HashSet<_>, _>>()?;
+
+        Ok(Self {
+            route_id: route_id.to_string(),
+            mode: config.mode,
+            dir: PathBuf::from(&config.dir).join(file_safe(route_id)),
+            methods,
+            match_headers,
+            unavailable,
+        })
+    }
+
+    pub fn mode(&self) -> ReplayMode {
+        self.mode
+    }
+
+    /// Whether a backend answer with this status counts as the backend being unavailable.
+    pub fn is_unavailable(&self, status: StatusCode) -> bool {
+        self.unavailable.contains(&status)
+    }
+
+    /// The recording key of a request, or `None` when its method is not recorded.
+    pub fn key<B>(&self, req: &Request<B>) -> Option<ReplayKey> {
+        if !self.methods.contains(req.method()) {
+            return None;
+        }
+        let method = req.method().to_string();
+        let uri = req.uri().path_and_query().map(|pq| pq.as_str()).unwrap_or("/").to_string();
+
+        let mut full_key = format!("{} {}", method, uri);
+        for name in &self.match_headers {
+            let values: Vec<&str> = req.headers().get_all(name).iter().filter_map(|v| v.to_str().ok()).collect();
+            full_key.push_str(&format!("\n{}: {}", name, values.join(", ")));
+        }
+        let readable: String = file_safe(req.uri().path().trim_matches('/')).chars().take(80).collect();
+        let file = self.dir.join(format!("{}_{}_{:016x}.json", method, readable, fnv1a(full_key.as_bytes())));
+        Some(ReplayKey { file, method, uri })
+    }
+
+    /// The recorded response for `key`, marked with [`REPLAY_HEADER`].
+    pub async fn load(&self, key: &ReplayKey) -> Option<Response<Full<Bytes>>> {
+        let contents = match tokio::fs::read(&key.file).await {
+            Ok(contents) => contents,
+            Err(_) => {
+                replay_telemetry().record(&self.route_id, "missing");
+                return None;
+            }
+        };
+        let response = serde_json::from_slice::<Recording>(&contents)
+            .map_err(|e| e.to_string())
+            .and_then(|recording| recording.into_response());
+        match response {
+            Ok(response) => {
+                debug!("Route {}: replaying {} {} from {}", self.route_id, key.method, key.uri, key.file.display());
+                replay_telemetry().record(&self.route_id, "replayed");
+                Some(response)
+            }
+            Err(e) => {
+                warn!("Route {}: ignoring unreadable recording {}: {}", self.route_id, key.file.display(), e);
+                replay_telemetry().record(&self.route_id, "missing");
+                None
+            }
+        }
+    }
+
+    /// Writes the response as the recording for `key`, replacing any earlier one.
+    pub async fn save(&self, key: &ReplayKey, response: &Response<Full<Bytes>>) {
+        let Ok(collected) = response.body().clone().collect().await;
+        let recording = Recording {
+            method: key.method.clone(),
+            uri: key.uri.clone(),
+            status: response.status().as_u16(),
+            headers: response
+                .headers()
+                .iter()
+                .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+                .collect(),
+            body: BASE64.encode(collected.to_bytes()),
+        };
+        let written = async {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            let json = serde_json::to_vec_pretty(&recording).map_err(std::io::Error::other)?;
+            tokio::fs::write(&key.file, json).await
+        };
+        match written.await {
+            Ok(()) => replay_telemetry().record(&self.route_id, "recorded"),
+            Err(e) => warn!("Route {}: failed to write recording {}: {}", self.route_id, key.file.display(), e),
+        }
+    }
+}
+
+impl Recording {
+    fn into_response(self) -> Result<Response<Full<Bytes>>, String> {
+        let status = StatusCode::from_u16(self.status).map_err(|e| e.to_string())?;
+        let body = BASE64.decode(self.body).map_err(|e| e.to_string())?;
+        let mut response = Response::new(Full::new(Bytes::from(body)));
+        *response.status_mut() = status;
+        for (name, value) in self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| e.to_string())?;
+            let value = HeaderValue::from_str(&value).map_err(|e| e.to_string())?;
+            response.headers_mut().append(name, value);
+        }
+        response.headers_mut().insert(REPLAY_HEADER, HeaderValue::from_static("recorded"));
+        Ok(response)
+    }
+}
+
+/// Replaces everything but ASCII letters, digits, `-` and `.` with `_`.
+fn file_safe(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect()
+}
+
+/// 64-bit FNV-1a; unlike `DefaultHasher` it is stable across Rust releases, so recordings keep
+/// their names.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+struct ReplayTelemetry {
+    responses: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl ReplayTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "replay_responses_total",
+            "Recorded backend responses by route and outcome (recorded, replayed, missing)",
+        ).namespace("bifrost");
+        Self {
+            responses: IntCounterVec::new(opts, &["route", "outcome"]).expect("replay_responses_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.responses.clone())) {
+            warn!("Failed to register replay_responses_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    fn record(&self, route: &str, outcome: &str) {
+        self.responses.with_label_values(&[route, outcome]).inc();
+    }
+}
+
+fn replay_telemetry() -> &'static ReplayTelemetry {
+    static TELEMETRY: OnceLock<ReplayTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(ReplayTelemetry::new)
+}
+
+/// Registers the replay counters with the shared Prometheus registry.
+pub fn register_replay_metrics(registry: &Registry) {
+    replay_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn recordings_round_trip_per_method_path_and_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ReplayStore::new(
+            "api/users",
+            &ReplayConfig {
+                mode: ReplayMode::Record,
+                dir: dir.path().display().to_string(),
+                methods: vec!["get".to_string()],
+                match_headers: vec!["Accept".to_string()],
+                unavailable_statuses: vec![502, 503, 504],
+            },
+        )
+        .unwrap();
+        let request = |uri: &str, accept: &str| Request::get(uri).header("accept", accept).body(()).unwrap();
+
+        let json = store.key(&request("/users/1?full=1", "application/json")).unwrap();
+        let response = Response::builder()
+            .status(StatusCode::CREATED)
+            .header("content-type", "application/json")
+            .body(Full::new(Bytes::from_static(b"{\"id\":1}")))
+            .unwrap();
+        store.save(&json, &response).await;
+
+        let replayed = store.load(&json).await.unwrap();
+        assert_eq!(replayed.status(), StatusCode::CREATED);
+        assert_eq!(replayed.headers()["content-type"], "application/json");
+        assert_eq!(replayed.headers()[REPLAY_HEADER], "recorded");
+        assert_eq!(replayed.into_body().collect().await.unwrap().to_bytes(), "{\"id\":1}");
+
+        assert!(json.file.starts_with(dir.path().join("api_users")));
+        assert_eq!(store.key(&request("/users/1?full=1", "application/json")), Some(json.clone()));
+        let xml = store.key(&request("/users/1?full=1", "text/xml")).unwrap();
+        assert_ne!(xml, json, "match headers are part of the key");
+        assert!(store.load(&xml).await.is_none());
+        assert!(store.key(&Request::post("/users").body(()).unwrap()).is_none());
+        assert!(store.is_unavailable(StatusCode::BAD_GATEWAY));
+    }
+}
@@ -0,0 +1,155 @@
+//! Size limits on request heads.
+//!
+//! With a `request_limits` section, every listener refuses requests whose target is longer than
+//! `max_uri_bytes` with 414, and requests with more than `max_headers` header fields or a field
+//! larger than `max_header_bytes` with 431, before any handler sees them. The HTTP/1.1 parser is
+//! told the header count, so it stops reading a head that has too many. CONNECT requests read by
+//! the forward proxy's own parser are held to the same limits.
+
+use crate::config::RequestLimitsConfig;
+use crate::error::ProxyError;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Request, Response, StatusCode, Version};
+use log::{info, warn};
+use std::sync::OnceLock;
+
+static LIMITS: OnceLock<RequestLimits> = OnceLock::new();
+
+/// Enforces `config` on every listener.
+pub fn init(config: &RequestLimitsConfig) -> Result<(), ProxyError> {
+    let limits = RequestLimits::new(config)?;
+    info!(
+        "Request limits: targets up to {} bytes, {} headers of up to {} bytes",
+        limits.max_uri_bytes, limits.max_headers, limits.max_header_bytes
+    );
+    if LIMITS.set(limits).is_err() {
+        warn!("Request limits already initialized; keeping the first configuration");
+    }
+    Ok(())
+}
+
+/// The configured limits; `None` without a `request_limits` section.
+pub fn limits() -> Option<&'static RequestLimits> {
+    LIMITS.get()
+}
+
+/// Why a request head was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum LimitExceeded {
+    #[error("request target exceeds {0} bytes")]
+    UriTooLong(usize),
+    #[error("more than {0} headers")]
+    TooManyHeaders(usize),
+    #[error("header field exceeds {0} bytes")]
+    HeaderTooLarge(usize),
+}
+
+impl LimitExceeded {
+    pub fn status(self) -> StatusCode {
+        match self {
+            LimitExceeded::UriTooLong(_) => StatusCode::URI_TOO_LONG,
+            LimitExceeded::TooManyHeaders(_) | LimitExceeded::HeaderTooLarge(_) => {
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+            }
+        }
+    }
+
+    pub fn response(self) -> Response<Full<Bytes>> {
+        let mut response = Response::new(Full::new(Bytes::from(self.status().canonical_reason().unwrap_or_default())));
+        *response.status_mut() = self.status();
+        response
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestLimits {
+    pub max_uri_bytes: usize,
+    pub max_headers: usize,
+    pub max_header_bytes: usize,
+}
+
+impl RequestLimits {
+    pub fn new(config: &RequestLimitsConfig) -> Result<Self, ProxyError> {
+        if config.max_uri_bytes == 0 || config.max_headers == 0 || config.max_header_bytes == 0 {
+            return Err(ProxyError::Config("request_limits values must be greater than zero".to_string()));
+        }
+        Ok(Self {
+            max_uri_bytes: config.max_uri_bytes,
+            max_headers: config.max_headers,
+            max_header_bytes: config.max_header_bytes,
+        })
+    }
+
+    pub fn check<B>(&self, req: &Request<B>) -> Result<(), LimitExceeded> {
+        let uri = req.uri();
+        let target = match uri.path_and_query() {
+            // Origin form, and the :path of HTTP/2 requests
+            Some(target) if uri.scheme().is_none() || req.version() == Version::HTTP_2 => target.as_str().len(),
+            // Absolute form of forward proxy requests, authority form of CONNECT
+            _ => uri.to_string().len(),
+        };
+        self.check_head(
+            target,
+            req.headers().len(),
+            req.headers().iter().map(|(name, value)| name.as_str().len() + value.len()),
+        )
+    }
+
+    /// Checks a head given the target's length and the combined name and value length of each
+    /// header field.
+    pub fn check_head(
+        &self,
+        target_len: usize,
+        header_count: usize,
+        header_lens: impl IntoIterator<Item = usize>,
+    ) -> Result<(), LimitExceeded> {
+        if target_len > self.max_uri_bytes {
+            return Err(LimitExceeded::UriTooLong(self.max_uri_bytes));
+        }
+        if header_count > self.max_headers {
+            return Err(LimitExceeded::TooManyHeaders(self.max_headers));
+        }
+        if header_lens.into_iter().any(|len| len > self.max_header_bytes) {
+            return Err(LimitExceeded::HeaderTooLarge(self.max_header_bytes));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> RequestLimits {
+        RequestLimits::new(&RequestLimitsConfig { max_uri_bytes: 32, max_headers: 3, max_header_bytes: 24 }).unwrap()
+    }
+
+    #[test]
+    fn long_targets_get_414_and_large_heads_431() {
+        let limits = limits();
+        let ok = Request::get("/search?q=short").header("accept", "*/*").body(()).unwrap();
+        assert_eq!(limits.check(&ok), Ok(()));
+
+        let long = Request::get(format!("/search?q={}", "x".repeat(30))).body(()).unwrap();
+        let refused = limits.check(&long).unwrap_err();
+        assert_eq!(refused, LimitExceeded::UriTooLong(32));
+        assert_eq!(refused.response().status(), StatusCode::URI_TOO_LONG);
+
+        let absolute = Request::get(format!("http://example.com/{}", "x".repeat(16))).body(()).unwrap();
+        assert_eq!(limits.check(&absolute), Err(LimitExceeded::UriTooLong(32)));
+
+        let mut many = Request::get("/").body(()).unwrap();
+        for name in ["a", "b", "c", "d"] {
+            many.headers_mut().insert(name, "1".parse().unwrap());
+        }
+        let refused = limits.check(&many).unwrap_err();
+        assert_eq!(refused, LimitExceeded::TooManyHeaders(3));
+        assert_eq!(refused.response().status(), StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+
+        let large = Request::get("/").header("cookie", "x".repeat(20)).body(()).unwrap();
+        assert_eq!(limits.check(&large), Err(LimitExceeded::HeaderTooLarge(24)));
+
+        assert!(RequestLimits::new(&RequestLimitsConfig { max_headers: 0, ..RequestLimitsConfig::default() }).is_err());
+    }
+}
@@ -0,0 +1,285 @@
+use crate::common::{constant_time_eq, unix_now};
+use crate::config::{HmacAlgorithm, HmacVerificationConfig, SignatureEncoding};
+use crate::error::ProxyError;
+use base64::{Engine as _, engine::general_purpose};
+use hyper::{HeaderMap, Method, Uri};
+use log::warn;
+use prometheus::{IntCounterVec, Opts, Registry};
+use ring::{digest, hmac};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+/// Timestamps above this are treated as milliseconds rather than seconds.
+const MILLIS_THRESHOLD: u64 = 100_000_000_000;
+
+/// Reasons a signed request is rejected.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SignatureError {
+    #[error("missing signature header")]
+    MissingSignature,
+    #[error("missing timestamp header")]
+    MissingTimestamp,
+    #[error("invalid timestamp")]
+    InvalidTimestamp,
+    #[error("timestamp outside the allowed window")]
+    Stale,
+    #[error("signature does not match")]
+    Mismatch,
+}
+
+impl SignatureError {
+    fn outcome(&self) -> &'static str {
+        match self {
+            SignatureError::MissingSignature | SignatureError::MissingTimestamp => "missing",
+            SignatureError::InvalidTimestamp => "invalid_timestamp",
+            SignatureError::Stale => "stale",
+            SignatureError::Mismatch => "mismatch",
+        }
+    }
+}
+
+/// Verifies HMAC signatures over a timestamp and the request body.
+///
+/// The signed payload is built from `payload_format`, whose placeholders are
+/// `{timestamp}`, `{method}`, `{path}` (path and query), `{body}` and `{body_sha256}`.
+pub struct HmacVerifier {
+    config: HmacVerificationConfig,
+    key: hmac::Key,
+}
+
+impl HmacVerifier {
+    pub fn new(config: HmacVerificationConfig) -> Result<Self, ProxyError> {
+        if config.secret.is_empty() {
+            return Err(ProxyError::Config("hmac_verification.secret must not be empty".to_string()));
+        }
+        if config.payload_format.contains("{timestamp}") && config.timestamp_header.is_empty() {
+            return Err(ProxyError::Config(
+                "hmac_verification.timestamp_header is required when payload_format uses {timestamp}".to_string(),
+            ));
+        }
+        let algorithm = match config.algorithm {
+            HmacAlgorithm::Sha256 => hmac::HMAC_SHA256,
+            HmacAlgorithm::Sha384 => hmac::HMAC_SHA384,
+            HmacAlgorithm::Sha512 => hmac::HMAC_SHA512,
+        };
+        let key = hmac::Key::new(algorithm, config.secret.expose().as_bytes());
+        Ok(Self { config, key })
+    }
+
+    pub fn max_body_bytes(&self) -> usize {
+        self.config.max_body_bytes
+    }
+
+    /// Checks the signature of a request for `route_id` and records the outcome.
+    pub fn verify(
+        &self,
+        route_id: &str,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<(), SignatureError> {
+        let result = self.verify_at(method, uri, headers, body, unix_now());
+        let outcome = match &result {
+            Ok(()) => "valid",
+            Err(e) => e.outcome(),
+        };
+        signing_telemetry().record(route_id, outcome);
+        result
+    }
+
+    fn verify_at(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+        body: &[u8],
+        now: u64,
+    ) -> Result<(), SignatureError> {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(str::trim);
+
+        let signatures = header(&self.config.signature_header)
+            .filter(|value| !value.is_empty())
+            .ok_or(SignatureError::MissingSignature)?;
+
+        let timestamp = if self.config.timestamp_header.is_empty() {
+            ""
+        } else {
+            let raw = header(&self.config.timestamp_header).ok_or(SignatureError::MissingTimestamp)?;
+            let value: u64 = raw.parse().map_err(|_| SignatureError::InvalidTimestamp)?;
+            let seconds = if value > MILLIS_THRESHOLD { value / 1000 } else { value };
+            if now.abs_diff(seconds) > self.config.max_skew_secs {
+                return Err(SignatureError::Stale);
+            }
+            raw
+        };
+
+        let path = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+        let payload = self.signed_payload(timestamp, method.as_str(), path, body);
+        let expected = hmac::sign(&self.key, &payload);
+
+        // Several signatures may be sent during secret rotation (comma separated)
+        let prefix = self.config.signature_prefix.as_deref().unwrap_or("");
+        let matches = signatures.split(',').any(|candidate| {
+            let candidate = candidate.trim();
+            let Some(encoded) = candidate.strip_prefix(prefix) else {
+                return false;
+            };
+            self.decode(encoded)
+                .is_some_and(|decoded| constant_time_eq(&decoded, expected.as_ref()))
+        });
+
+        if matches { Ok(()) } else { Err(SignatureError::Mismatch) }
+    }
+
+    fn signed_payload(&self, timestamp: &str, method: &str, path: &str, body: &[u8]) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(self.config.payload_format.len() + body.len());
+        let mut rest = self.config.payload_format.as_str();
+
+        while let Some(start) = rest.find('{') {
+            payload.extend_from_slice(&rest.as_bytes()[..start]);
+            let after = &rest[start..];
+            let Some(end) = after.find('}') else {
+                break;
+            };
+            match &after[..=end] {
+                "{timestamp}" => payload.extend_from_slice(timestamp.as_bytes()),
+                "{method}" => payload.extend_from_slice(method.as_bytes()),
+                "{path}" => payload.extend_from_slice(path.as_bytes()),
+                "{body}" => payload.extend_from_slice(body),
+                "{body_sha256}" => payload.extend_from_slice(hex(digest::digest(&digest::SHA256, body).as_ref()).as_bytes()),
+                literal => payload.extend_from_slice(literal.as_bytes()),
+            }
+            rest = &after[end + 1..];
+        }
+        payload.extend_from_slice(rest.as_bytes());
+        payload
+    }
+
+    fn decode(&self, encoded: &str) -> Option<Vec<u8>> {
+        match self.config.encoding {
+            SignatureEncoding::Hex => decode_hex(encoded),
+            SignatureEncoding::Base64 => general_purpose::STANDARD.decode(encoded).ok(),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(encoded: &str) -> Option<Vec<u8>> {
+    if !encoded.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..encoded.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(encoded.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+struct SigningTelemetry {
+    verifications: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl SigningTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "hmac_verifications_total",
+            "HMAC request signature checks by route and outcome",
+        ).namespace("bifrost");
+        Self {
+            verifications: IntCounterVec::new(opts, &["route", "outcome"]).expect("hmac_verifications_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.verifications.clone())) {
+            warn!("Failed to register hmac_verifications_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    fn record(&self, route: &str, outcome: &str) {
+        self.verifications.with_label_values(&[route, outcome]).inc();
+    }
+}
+
+fn signing_telemetry() -> &'static SigningTelemetry {
+    static TELEMETRY: OnceLock<SigningTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(SigningTelemetry::new)
+}
+
+/// Registers the signature verification counters with the shared Prometheus registry.
+pub fn register_request_signing_metrics(registry: &Registry) {
+    signing_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secrets::Secret;
+
+    fn verifier(config: HmacVerificationConfig) -> HmacVerifier {
+        HmacVerifier::new(HmacVerificationConfig { secret: Secret::new("whsec"), ..config }).unwrap()
+    }
+
+    fn sign(secret: &str, payload: &[u8]) -> String {
+        hex(hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()), payload).as_ref())
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(hyper::header::HeaderName::from_bytes(name.as_bytes()).unwrap(), value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn accepts_valid_signature_and_rejects_tampering() {
+        let verifier = verifier(HmacVerificationConfig::default());
+        let uri: Uri = "/hooks/github".parse().unwrap();
+        let body = br#"{"action":"opened"}"#;
+        let signature = sign("whsec", format!("1700000000.{}", std::str::from_utf8(body).unwrap()).as_bytes());
+        let valid = headers(&[("X-Signature", &signature), ("X-Timestamp", "1700000000")]);
+
+        assert_eq!(verifier.verify_at(&Method::POST, &uri, &valid, body, 1_700_000_100), Ok(()));
+        assert_eq!(
+            verifier.verify_at(&Method::POST, &uri, &valid, br#"{"action":"closed"}"#, 1_700_000_100),
+            Err(SignatureError::Mismatch)
+        );
+        assert_eq!(verifier.verify_at(&Method::POST, &uri, &valid, body, 1_700_000_400), Err(SignatureError::Stale));
+        assert_eq!(
+            verifier.verify_at(&Method::POST, &uri, &headers(&[("X-Timestamp", "1700000000")]), body, 1_700_000_000),
+            Err(SignatureError::MissingSignature)
+        );
+    }
+
+    #[test]
+    fn supports_prefixed_signatures_and_body_hash_payloads() {
+        let verifier = verifier(HmacVerificationConfig {
+            signature_header: "X-Hub-Signature-256".to_string(),
+            signature_prefix: Some("sha256=".to_string()),
+            payload_format: "{method}\n{path}\n{timestamp}\n{body_sha256}".to_string(),
+            ..HmacVerificationConfig::default()
+        });
+        let uri: Uri = "/hooks?x=1".parse().unwrap();
+        let body_hash = hex(digest::digest(&digest::SHA256, b"payload").as_ref());
+        let signature = sign("whsec", format!("POST\n/hooks?x=1\n1700000000000\n{}", body_hash).as_bytes());
+        let rotated = format!("sha256=00ff, sha256={}", signature);
+        let request_headers = headers(&[("X-Hub-Signature-256", &rotated), ("X-Timestamp", "1700000000000")]);
+
+        assert_eq!(verifier.verify_at(&Method::POST, &uri, &request_headers, b"payload", 1_700_000_000), Ok(()));
+        assert_eq!(
+            verifier.verify_at(&Method::PUT, &uri, &request_headers, b"payload", 1_700_000_000),
+            Err(SignatureError::Mismatch)
+        );
+    }
+}
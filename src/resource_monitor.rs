@@ -0,0 +1,381 @@
+//! File descriptor monitoring and idle tunnel reaping.
+//!
+//! Tunnels register themselves with [`register_tunnel`] and wrap the client side of the
+//! connection in an [`ActivityStream`], which records the time of the last byte moved in either
+//! direction. A background task started by [`ResourceMonitor::spawn`] reports descriptor usage
+//! and cancels tunnels that have been idle longer than the configured policy.
+
+use crate::config::ResourceMonitorConfig;
+use crate::notifications::{Notification, NotificationKind};
+use log::{info, warn};
+use prometheus::{IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::Notify;
+use tokio::time::Duration;
+
+struct TunnelEntry {
+    kind: &'static str,
+    description: String,
+    last_activity: Arc<AtomicU64>,
+    cancel: Arc<Notify>,
+}
+
+#[derive(Default)]
+struct TunnelRegistry {
+    next_id: AtomicU64,
+    tunnels: Mutex<HashMap<u64, TunnelEntry>>,
+}
+
+fn registry() -> &'static TunnelRegistry {
+    static REGISTRY: OnceLock<TunnelRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(TunnelRegistry::default)
+}
+
+/// Milliseconds since the first call; a cheap monotonic clock shared by all tunnels.
+fn now_millis() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
+/// Registration of one open tunnel; removed from the registry when dropped.
+pub struct TunnelHandle {
+    id: u64,
+    kind: &'static str,
+    last_activity: Arc<AtomicU64>,
+    cancel: Arc<Notify>,
+}
+
+impl TunnelHandle {
+    /// Wraps a stream so that reads and writes count as tunnel activity.
+    pub fn watch<S>(&self, stream: S) -> ActivityStream<S> {
+        ActivityStream {
+            inner: stream,
+            last_activity: self.last_activity.clone(),
+        }
+    }
+
+    /// Runs the tunnel until it finishes or is reaped for idleness; `None` means it was reaped.
+    pub async fn run<F: Future>(&self, tunnel: F) -> Option<F::Output> {
+        tokio::select! {
+            output = tunnel => Some(output),
+            _ = self.cancel.notified() => None,
+        }
+    }
+}
+
+impl Drop for TunnelHandle {
+    fn drop(&mut self) {
+        registry().tunnels.lock().unwrap().remove(&self.id);
+        monitor_telemetry().open_tunnels.with_label_values(&[self.kind]).dec();
+    }
+}
+
+/// Registers an open tunnel of `kind` (e.g. `connect`, `websocket`).
+pub fn register_tunnel(kind: &'static str, description: impl Into<String>) -> TunnelHandle {
+    let registry = registry();
+    let id = registry.next_id.fetch_add(1, Ordering::Relaxed);
+    let last_activity = Arc::new(AtomicU64::new(now_millis()));
+    let cancel = Arc::new(Notify::new());
+    registry.tunnels.lock().unwrap().insert(
+        id,
+        TunnelEntry {
+            kind,
+            description: description.into(),
+            last_activity: last_activity.clone(),
+            cancel: cancel.clone(),
+        },
+    );
+    monitor_telemetry().open_tunnels.with_label_values(&[kind]).inc();
+    TunnelHandle { id, kind, last_activity, cancel }
+}
+
+/// Cancels tunnels idle for longer than `idle_timeout` and returns how many were reaped.
+fn reap_idle_tunnels(idle_timeout: Duration) -> usize {
+    let now = now_millis();
+    let limit = idle_timeout.as_millis() as u64;
+    let tunnels = registry().tunnels.lock().unwrap();
+    let mut reaped = 0;
+    for entry in tunnels.values() {
+        let idle = now.saturating_sub(entry.last_activity.load(Ordering::Relaxed));
+        if idle > limit {
+            info!(
+                "Closing idle {} tunnel {} (idle {}s)",
+                entry.kind,
+                entry.description,
+                idle / 1000
+            );
+            // notify_one stores a permit, so a tunnel that is not polling yet still sees it
+            entry.cancel.notify_one();
+            monitor_telemetry().reaped.with_label_values(&[entry.kind]).inc();
+            reaped += 1;
+        }
+    }
+    reaped
+}
+
+/// Stream wrapper that timestamps every successful read or write.
+pub struct ActivityStream<S> {
+    inner: S,
+    last_activity: Arc<AtomicU64>,
+}
+
+impl<S> ActivityStream<S> {
+    fn touch(&self) {
+        self.last_activity.store(now_millis(), Ordering::Relaxed);
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ActivityStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if matches!(poll, Poll::Ready(Ok(()))) && buf.filled().len() > before {
+            self.touch();
+        }
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ActivityStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, data);
+        if matches!(poll, Poll::Ready(Ok(written)) if written > 0) {
+            self.touch();
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Number of descriptors currently open by this process (Linux only).
+pub fn open_fd_count() -> Option<usize> {
+    std::fs::read_dir("/proc/self/fd").ok().map(|entries| entries.count())
+}
+
+/// Soft and hard `RLIMIT_NOFILE` values.
+#[cfg(unix)]
+pub fn nofile_limit() -> Option<(u64, u64)> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: getrlimit only writes to the provided struct.
+    let result = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) };
+    (result == 0).then_some((limit.rlim_cur, limit.rlim_max))
+}
+
+#[cfg(not(unix))]
+pub fn nofile_limit() -> Option<(u64, u64)> {
+    None
+}
+
+/// Raises the soft `RLIMIT_NOFILE` to the hard limit and returns the new soft limit.
+#[cfg(unix)]
+pub fn raise_nofile_limit() -> io::Result<u64> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: getrlimit/setrlimit only access the provided struct.
+    unsafe {
+        if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if limit.rlim_cur < limit.rlim_max {
+            limit.rlim_cur = limit.rlim_max;
+            if libc::setrlimit(libc::RLIMIT_NOFILE, &limit) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(limit.rlim_cur)
+}
+
+#[cfg(not(unix))]
+pub fn raise_nofile_limit() -> io::Result<u64> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "RLIMIT_NOFILE is not available on this platform"))
+}
+
+pub struct ResourceMonitor {
+    config: ResourceMonitorConfig,
+    /// Whether descriptor usage was over the warning ratio at the last check
+    critical: AtomicBool,
+}
+
+impl ResourceMonitor {
+    pub fn new(config: ResourceMonitorConfig) -> Self {
+        Self { config, critical: AtomicBool::new(false) }
+    }
+
+    /// Applies startup settings and spawns the periodic check.
+    pub fn spawn(self) {
+        if self.config.raise_fd_limit {
+            match raise_nofile_limit() {
+                Ok(limit) => info!("Open file soft limit set to {}", limit),
+                Err(e) => warn!("Failed to raise open file limit: {}", e),
+            }
+        }
+
+        let interval = Duration::from_secs(self.config.interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                self.check();
+            }
+        });
+    }
+
+    fn check(&self) {
+        let telemetry = monitor_telemetry();
+        let limit = nofile_limit().map(|(soft, _)| soft);
+        if let Some(limit) = limit {
+            telemetry.fd_limit.set(limit as i64);
+        }
+        if let Some(open) = open_fd_count() {
+            telemetry.open_fds.set(open as i64);
+            let critical = limit.is_some_and(|limit| limit > 0 && open as f64 >= limit as f64 * self.config.fd_warning_ratio);
+            if let Some(limit) = limit
+                && critical
+            {
+                warn!(
+                    "Open file descriptors at {} of {} ({:.0}%); raise the limit or reduce connections",
+                    open,
+                    limit,
+                    open as f64 * 100.0 / limit as f64
+                );
+                // Notify once per episode rather than at every check
+                if !self.critical.swap(true, Ordering::Relaxed) {
+                    Notification::new(
+                        NotificationKind::ResourcesCritical,
+                        format!("Open file descriptors at {} of {}", open, limit),
+                    )
+                    .detail("open_fds", open)
+                    .detail("fd_limit", limit)
+                    .send();
+                }
+            } else {
+                self.critical.store(false, Ordering::Relaxed);
+            }
+        }
+
+        if let Some(idle_secs) = self.config.tunnel_idle_timeout_secs {
+            reap_idle_tunnels(Duration::from_secs(idle_secs));
+        }
+    }
+}
+
+struct MonitorTelemetry {
+    open_fds: IntGauge,
+    fd_limit: IntGauge,
+    open_tunnels: IntGaugeVec,
+    reaped: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl MonitorTelemetry {
+    fn new() -> Self {
+        Self {
+            open_fds: IntGauge::with_opts(
+                Opts::new("open_fds", "Open file descriptors").namespace("bifrost"),
+            ).expect("open_fds metric"),
+            fd_limit: IntGauge::with_opts(
+                Opts::new("fd_limit", "Soft RLIMIT_NOFILE of the process").namespace("bifrost"),
+            ).expect("fd_limit metric"),
+            open_tunnels: IntGaugeVec::new(
+                Opts::new("tunnels_open", "Open tunnels by kind").namespace("bifrost"),
+                &["kind"],
+            ).expect("tunnels_open metric"),
+            reaped: IntCounterVec::new(
+                Opts::new("tunnels_reaped_total", "Tunnels closed for exceeding the idle timeout").namespace("bifrost"),
+                &["kind"],
+            ).expect("tunnels_reaped_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        let collectors: [Box<dyn prometheus::core::Collector>; 4] = [
+            Box::new(self.open_fds.clone()),
+            Box::new(self.fd_limit.clone()),
+            Box::new(self.open_tunnels.clone()),
+            Box::new(self.reaped.clone()),
+        ];
+        for collector in collectors {
+            if let Err(err) = registry.register(collector) {
+                warn!("Failed to register resource monitor metric: {}", err);
+                return;
+            }
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn monitor_telemetry() -> &'static MonitorTelemetry {
+    static TELEMETRY: OnceLock<MonitorTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(MonitorTelemetry::new)
+}
+
+/// Registers descriptor and tunnel metrics with the shared Prometheus registry.
+pub fn register_resource_monitor_metrics(registry: &Registry) {
+    monitor_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, duplex};
+
+    #[tokio::test]
+    async fn reaps_only_idle_tunnels() {
+        let idle = register_tunnel("test", "idle");
+        let busy = register_tunnel("test", "busy");
+        let (client, mut peer) = duplex(64);
+        let mut watched = busy.watch(client);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        peer.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        watched.read_exact(&mut buf).await.unwrap();
+
+        reap_idle_tunnels(Duration::from_millis(40));
+
+        let pending = std::future::pending::<()>();
+        assert_eq!(idle.run(pending).await, None);
+        let finished = tokio::time::timeout(Duration::from_millis(50), busy.run(async { 7 })).await;
+        assert_eq!(finished.unwrap(), Some(7));
+
+        let open = monitor_telemetry().open_tunnels.with_label_values(&["test"]);
+        assert_eq!(open.get(), 2);
+        drop(idle);
+        assert_eq!(open.get(), 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reads_descriptor_usage() {
+        assert!(open_fd_count().unwrap() > 0);
+        let (soft, hard) = nofile_limit().unwrap();
+        assert!(soft <= hard);
+    }
+}
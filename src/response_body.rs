@@ -0,0 +1,323 @@
+//! Bodies passed through the reverse proxy.
+//!
+//! A backend response is sent to the client frame by frame as it arrives, so large downloads and
+//! server-sent events are never held in memory. A body is only read first when a feature needs
+//! all of it (recording a response for replay, `response_body: "buffer"`, retrying a streamed
+//! request), and then only up to the route's `max_buffer_bytes`: a longer body goes on from what
+//! was already read, and the feature is skipped for it.
+
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use std::any::Any;
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep};
+
+pub type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Default for a route's `max_buffer_bytes`.
+pub const DEFAULT_MAX_BUFFER_BYTES: usize = 10 * 1024 * 1024;
+
+/// No new frame of a body arrived within the route's `idle_timeout_secs`.
+#[derive(Debug)]
+pub struct BodyIdleTimeout(pub Duration);
+
+impl std::fmt::Display for BodyIdleTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no body data received for {:?}", self.0)
+    }
+}
+
+impl Error for BodyIdleTimeout {}
+
+/// Body of a reverse proxy response.
+pub enum ResponseBody {
+    /// An answer of the proxy itself, or a backend body that was read completely
+    Full(Full<Bytes>),
+    /// A backend body passed through as it arrives
+    Streaming(StreamingBody),
+}
+
+impl From<Full<Bytes>> for ResponseBody {
+    fn from(body: Full<Bytes>) -> Self {
+        ResponseBody::Full(body)
+    }
+}
+
+impl From<StreamingBody> for ResponseBody {
+    fn from(body: StreamingBody) -> Self {
+        ResponseBody::Streaming(body)
+    }
+}
+
+impl Body for ResponseBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match self.get_mut() {
+            ResponseBody::Full(full) => Pin::new(full).poll_frame(cx).map_err(|never| match never {}),
+            ResponseBody::Streaming(stream) => Pin::new(stream).poll_frame(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
+            ResponseBody::Full(full) => full.is_end_stream(),
+            ResponseBody::Streaming(stream) => stream.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            ResponseBody::Full(full) => full.size_hint(),
+            ResponseBody::Streaming(stream) => stream.size_hint(),
+        }
+    }
+}
+
+/// A body passed on frame by frame, behind the part of it that was already read.
+pub struct StreamingBody {
+    prefix: Option<Bytes>,
+    inner: BoxBody<Bytes, BoxError>,
+    idle: Option<IdleTimer>,
+    /// Kept until the body has been passed on completely or dropped, e.g. in-flight counters
+    guards: Vec<Box<dyn Any + Send + Sync>>,
+}
+
+struct IdleTimer {
+    limit: Duration,
+    timer: Pin<Box<Sleep>>,
+    /// Whether the timer runs; it starts when the inner body has nothing to give, so time the
+    /// client takes to accept a frame does not count
+    waiting: bool,
+}
+
+impl StreamingBody {
+    pub fn new<B>(body: B) -> Self
+    where
+        B: Body<Data = Bytes> + Send + Sync + 'static,
+        B::Error: Into<BoxError>,
+    {
+        Self {
+            prefix: None,
+            inner: body.map_err(Into::into).boxed(),
+            idle: None,
+            guards: Vec::new(),
+        }
+    }
+
+    /// Sends `prefix` before the rest of the body.
+    pub fn with_prefix(mut self, prefix: Bytes) -> Self {
+        self.prefix = Some(prefix).filter(|prefix| !prefix.is_empty());
+        self
+    }
+
+    /// Fails the body when `idle` passes without a new frame.
+    pub fn with_idle_timeout(mut self, idle: Option<Duration>) -> Self {
+        self.idle = idle.map(|limit| IdleTimer {
+            limit,
+            timer: Box::pin(tokio::time::sleep(limit)),
+            waiting: false,
+        });
+        self
+    }
+
+    /// Keeps `guard` alive until the body ends or is dropped.
+    pub fn with_guard(mut self, guard: impl Any + Send + Sync) -> Self {
+        self.guards.push(Box::new(guard));
+        self
+    }
+}
+
+impl Body for StreamingBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        if let Some(prefix) = this.prefix.take() {
+            return Poll::Ready(Some(Ok(Frame::data(prefix))));
+        }
+        match Pin::new(&mut this.inner).poll_frame(cx) {
+            Poll::Ready(frame) => {
+                if let Some(idle) = &mut this.idle {
+                    idle.waiting = false;
+                }
+                if !matches!(frame, Some(Ok(_))) {
+                    this.guards.clear();
+                }
+                Poll::Ready(frame)
+            }
+            Poll::Pending => {
+                let Some(idle) = &mut this.idle else {
+                    return Poll::Pending;
+                };
+                if !idle.waiting {
+                    idle.waiting = true;
+                    idle.timer.as_mut().reset(Instant::now() + idle.limit);
+                }
+                if idle.timer.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                this.guards.clear();
+                Poll::Ready(Some(Err(Box::new(BodyIdleTimeout(idle.limit)))))
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.prefix.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let hint = self.inner.size_hint();
+        let read = self.prefix.as_ref().map_or(0, |prefix| prefix.len() as u64);
+        match hint.exact() {
+            Some(exact) => SizeHint::with_exact(exact + read),
+            None => {
+                let mut total = SizeHint::new();
+                if let Some(upper) = hint.upper() {
+                    total.set_upper(upper + read);
+                }
+                total.set_lower(hint.lower() + read);
+                total
+            }
+        }
+    }
+}
+
+/// A body read into memory up to a limit.
+pub enum Buffered<B> {
+    /// The whole body
+    Complete(Bytes),
+    /// A body longer than the limit: what was read, and the rest
+    Partial(Bytes, B),
+}
+
+impl<B> Buffered<B>
+where
+    B: Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
+{
+    /// The body again, for sending on without the feature that wanted it whole.
+    pub fn into_stream(self) -> StreamingBody {
+        match self {
+            Buffered::Complete(bytes) => StreamingBody::new(Full::new(bytes)),
+            Buffered::Partial(read, rest) => StreamingBody::new(rest).with_prefix(read),
+        }
+    }
+}
+
+/// Reads `body` until it ends or more than `limit` bytes have arrived, giving up when `idle`
+/// passes without a new frame. A body that declares a longer length is not read at all.
+pub async fn buffer_up_to<B>(mut body: B, limit: usize, idle: Option<Duration>) -> Result<Buffered<B>, BoxError>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<BoxError>,
+{
+    if body.size_hint().lower() > limit as u64 {
+        return Ok(Buffered::Partial(Bytes::new(), body));
+    }
+    let mut collected = Vec::new();
+    loop {
+        let frame = match idle {
+            Some(idle) => tokio::time::timeout(idle, body.frame())
+                .await
+                .map_err(|_| Box::new(BodyIdleTimeout(idle)) as BoxError)?,
+            None => body.frame().await,
+        };
+        let Some(frame) = frame else {
+            return Ok(Buffered::Complete(Bytes::from(collected)));
+        };
+        if let Ok(data) = frame.map_err(Into::into)?.into_data() {
+            collected.extend_from_slice(&data);
+            if collected.len() > limit {
+                return Ok(Buffered::Partial(Bytes::from(collected), body));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tokio::sync::mpsc;
+
+    struct Flag(Arc<AtomicBool>);
+
+    impl Drop for Flag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// A body whose frames are sent by the test.
+    struct ChannelBody(mpsc::Receiver<Bytes>);
+
+    impl Body for ChannelBody {
+        type Data = Bytes;
+        type Error = BoxError;
+
+        fn poll_frame(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, BoxError>>> {
+            self.get_mut().0.poll_recv(cx).map(|data| data.map(|data| Ok(Frame::data(data))))
+        }
+    }
+
+    fn channel_body() -> (mpsc::Sender<Bytes>, ChannelBody) {
+        let (tx, rx) = mpsc::channel(4);
+        (tx, ChannelBody(rx))
+    }
+
+    #[tokio::test]
+    async fn streams_frames_as_they_arrive_and_buffers_only_up_to_the_limit() {
+        // Each frame reaches the reader before the next one is produced
+        let (tx, body) = channel_body();
+        let released = Arc::new(AtomicBool::new(false));
+        let mut streaming = StreamingBody::new(body)
+            .with_prefix(Bytes::from_static(b"data: 0\n\n"))
+            .with_guard(Flag(released.clone()));
+        assert_eq!(streaming.frame().await.unwrap().unwrap().into_data().unwrap(), "data: 0\n\n");
+        tx.send(Bytes::from_static(b"data: 1\n\n")).await.unwrap();
+        assert_eq!(streaming.frame().await.unwrap().unwrap().into_data().unwrap(), "data: 1\n\n");
+        assert!(!released.load(Ordering::SeqCst));
+        drop(tx);
+        assert!(streaming.frame().await.is_none());
+        assert!(released.load(Ordering::SeqCst));
+
+        // A backend that goes quiet fails the body instead of holding the client forever
+        let (_tx, body) = channel_body();
+        let mut stalled = StreamingBody::new(body).with_idle_timeout(Some(Duration::from_millis(50)));
+        let err = stalled.frame().await.unwrap().unwrap_err();
+        assert!(err.downcast_ref::<BodyIdleTimeout>().is_some());
+
+        let small = buffer_up_to(Full::new(Bytes::from_static(b"hello")), 16, None).await.unwrap();
+        assert!(matches!(small, Buffered::Complete(ref bytes) if bytes == "hello"));
+
+        // Past the limit, what was read goes out first and the rest follows
+        let (tx, body) = channel_body();
+        for chunk in [&b"0123456789"[..], b"abcdefghij", b"KLMNOP"] {
+            tx.send(Bytes::from_static(chunk)).await.unwrap();
+        }
+        drop(tx);
+        let large = buffer_up_to(body, 16, None).await.unwrap();
+        assert!(matches!(large, Buffered::Partial(ref read, _) if read.len() == 20));
+        let all = large.into_stream().collect().await.unwrap().to_bytes();
+        assert_eq!(all, "0123456789abcdefghijKLMNOP");
+    }
+}
@@ -1,27 +1,73 @@
+use crate::redact::redact_url;
+use crate::aggregate::Aggregator;
+use crate::api_keys::{ApiKeyRejection, ApiKeyStore};
+use crate::client_auth::{ClientCertHeader, ClientCertIdentity};
 use crate::common::{
-    ConnectionTracker, PerformanceMetrics, RequestTimer, ResponseBuilder, is_websocket_upgrade,
+    ConnectionTracker, PerformanceMetrics, RequestTimer, ResponseBuilder,
+    ascii_lowercase, filter_websocket_extensions, normalize_domain, upgrade_protocol,
 };
 use crate::config::{
-    HeaderOverrideConfig, HealthCheckConfig, LoadBalancingPolicy, ReverseProxyConfig,
-    ReverseProxyRouteConfig, ReverseProxyTargetConfig, RoutePredicateConfig, StickyConfig,
+    DynamicWeightsConfig, EarlyDataPolicy, HeaderOverrideConfig, SlowStartConfig, HealthCheckConfig, Http2Config, ListenerConfig, LoadBalancingConfig, LoadBalancingPolicy,
+    HeaderCase, OutlierDetectionConfig, ReverseProxyConfig, RouteFallbackConfig, RouteLimitsConfig, RouteMetadataConfig, BodyMode,
+    ReplayMode, ReverseProxyRouteConfig, ReverseProxyTargetConfig, RoutePredicateConfig, StickyConfig,
     StickyMode, WebSocketConfig,
 };
-use crate::error::ProxyError;
+use crate::error::{ProxyError, UpstreamErrorKind};
+use crate::fan_out::FanOut;
+use crate::replay::{ReplayKey, ReplayStore};
+use crate::response_body::{
+    BodyIdleTimeout, Buffered, DEFAULT_MAX_BUFFER_BYTES, ResponseBody, StreamingBody, buffer_up_to,
+};
+use crate::static_response::{ResponseVars, StaticResponder};
+use crate::listener;
 use crate::rate_limit::RateLimiter;
+use crate::outlier::OutlierStats;
+use crate::path_params::{PathParams, TargetTemplate, is_variable_name};
+use crate::resource_monitor::register_tunnel;
+use crate::access_log::{AccessLogPolicy, UpstreamTime};
+use crate::method_policy::MethodPolicy;
+use crate::path_normalization;
+use crate::privacy;
+use crate::tarpit::Tarpit;
+use crate::tenants::{Tenant, Tenants};
+use crate::cluster::Cluster;
+use crate::dns::{self, DnsResolver};
+use crate::egress::{self, UpstreamConnector};
+use crate::upstream_tls::UpstreamTls;
+use crate::tls_session::accept_tls;
+use crate::forwarded;
+use crate::graphql::{self, OperationMatcher};
+use crate::json_body::{self, JsonBody, JsonBodyMatcher};
+use crate::notifications::{Notification, NotificationKind};
+use crate::security_log::{SecurityEvent, SecurityEventKind};
+use crate::route_index::{RouteIndex, RouteKeys};
+use crate::route_metadata::{RequestSummary, RouteTags, configure_metric_labels};
+use crate::stale_connection::{is_idempotent, is_stale_connection_error, record_retry};
+use crate::upstream_error::upstream_error;
+use crate::http_server::{self, ConnectionProtocol, HeaderCaseRecorded};
+use crate::websocket_tunnel::relay_websocket;
+use crate::request_signing::{HmacVerifier, SignatureError};
 use chrono::{DateTime, FixedOffset, Utc};
-use http_body_util::{BodyExt, Empty, Full};
+use http_body_util::{BodyExt, Empty, Full, LengthLimitError, Limited};
 use http_body_util::combinators::BoxBody;
 use hyper::body::{Body as _, Bytes, Incoming};
-use hyper::header::{HeaderName, HOST, ORIGIN};
-use hyper::server::conn::http1::Builder as ServerBuilder;
+use hyper::header::{
+    HeaderName, HeaderValue, CONNECTION, CONTENT_LENGTH, CONTENT_TYPE, HOST, ORIGIN, PROXY_AUTHENTICATE, PROXY_AUTHORIZATION, TE,
+    TRANSFER_ENCODING, UPGRADE,
+};
 use hyper::service::service_fn;
-use hyper::{Method, Request, Response, StatusCode, Uri};
-use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use hyper::http::uri::PathAndQuery;
+use hyper::{Method, Request, Response, StatusCode, Uri, Version};
+use hyper_util::client::legacy::connect::{HttpConnector, capture_connection};
+use hyper_util::client::legacy::Client;
+use hyper_rustls::HttpsConnector;
 use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
 use ipnet::IpNet;
 use log::{debug, error, info, warn};
+use prometheus::{IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
 use rand::Rng;
-use regex::Regex;
+use regex::{Regex, RegexSet};
+use rustls::ServerConfig;
 use std::collections::{HashMap, HashSet};
 use std::collections::hash_map::DefaultHasher;
 use std::convert::Infallible;
@@ -29,11 +75,12 @@ use std::error::Error;
 use std::hash::{Hash, Hasher};
 use std::net::{IpAddr, SocketAddr};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 use tokio::io::copy_bidirectional;
 use tokio::time::Duration;
+use tokio_rustls::TlsAcceptor;
 use url::form_urlencoded;
-use url::Url;
+use url::{Position, Url};
 
 // Custom header names for X-Forwarded-* headers
 static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
@@ -42,11 +89,24 @@ static X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host"
 
 type BoxError = Box<dyn Error + Send + Sync>;
 type BoxedBody = BoxBody<Bytes, BoxError>;
+type UpstreamClient = Client<HttpsConnector<UpstreamConnector>, BoxedBody>;
 
 /// Wrapper to store request data including client IP
 #[derive(Clone, Debug)]
 pub struct RequestContext {
+    /// The client, behind any trusted proxies
     pub client_ip: Option<String>,
+    /// The TCP peer, which is a proxy in front of this one when it differs from `client_ip`
+    pub peer_ip: Option<IpAddr>,
+}
+
+impl RequestContext {
+    pub fn from_peer(peer: IpAddr, headers: &hyper::HeaderMap) -> Self {
+        Self {
+            client_ip: Some(forwarded::client_ip(peer, headers).to_string()),
+            peer_ip: Some(peer),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -63,22 +123,81 @@ struct CompiledTarget {
     enabled: bool,
     healthy: Arc<AtomicBool>,
     inflight: Arc<AtomicU64>,
+    /// HTTP requests waiting for this target's response; unlike `inflight`, no upgraded tunnels
+    requests: IntGauge,
+    recovery: Arc<Recovery>,
+    outlier: Arc<OutlierStats>,
+    /// Set when the target URL contains path variables
+    template: Option<TargetTemplate>,
+}
+
+/// Notices a target's health check passing again, so slow start can ramp it up.
+struct Recovery {
+    /// Health as last seen by target selection
+    healthy: AtomicBool,
+    at: Mutex<Option<std::time::Instant>>,
+}
+
+impl Recovery {
+    fn new() -> Self {
+        Self { healthy: AtomicBool::new(true), at: Mutex::new(None) }
+    }
+
+    /// Records the current health; true when the target just came back.
+    fn observe(&self, healthy: bool, now: std::time::Instant) -> bool {
+        if !healthy {
+            self.healthy.store(false, Ordering::Relaxed);
+            return false;
+        }
+        if self.healthy.swap(true, Ordering::Relaxed) {
+            return false;
+        }
+        *self.at.lock().unwrap_or_else(|e| e.into_inner()) = Some(now);
+        true
+    }
+
+    fn at(&self) -> Option<std::time::Instant> {
+        *self.at.lock().unwrap_or_else(|e| e.into_inner())
+    }
 }
 
 struct CompiledRoute {
     id: String,
     targets: Vec<CompiledTarget>,
-    http_client: Arc<Client<HttpConnector, BoxedBody>>,
+    http_client: Arc<UpstreamClient>,
+    /// Unpooled client for resending idempotent requests lost to a stale pooled connection;
+    /// `None` when pooling is disabled, since every connection is already fresh
+    fresh_client: Option<Arc<UpstreamClient>>,
     health_check_config: Option<HealthCheckConfig>,
+    prewarm: Option<Prewarm>,
+    /// Overrides the proxy-wide `preserve_host` when set
+    preserve_host: Option<bool>,
+    header_case: HeaderCase,
     strip_path_prefix: Option<String>,
     priority: i32,
     predicates: Vec<Predicate>,
     weight: Option<WeightMeta>,
     original_index: usize,
     load_balancing: LoadBalancingPolicy,
+    outlier_detection: OutlierDetectionConfig,
+    dynamic_weights: Option<DynamicWeightsConfig>,
+    slow_start: Option<SlowStartConfig>,
     sticky: Option<StickyConfig>,
     header_override: Option<HeaderOverrideConfig>,
     retry_policy: Option<CompiledRetryPolicy>,
+    require_api_key: bool,
+    hmac_verifier: Option<HmacVerifier>,
+    fan_out: Option<FanOut>,
+    aggregator: Option<Aggregator>,
+    replay: Option<ReplayStore>,
+    static_responder: Option<StaticResponder>,
+    method_policy: MethodPolicy,
+    allowed_upgrades: Vec<String>,
+    path_param_headers: Vec<(String, HeaderName)>,
+    limits: RouteLimitsConfig,
+    tags: RouteTags,
+    /// Owner of the route, whose limits, keys and metrics apply to it
+    tenant: Option<Arc<Tenant>>,
     rr_counter: AtomicU64,
 }
 
@@ -103,7 +222,11 @@ impl CompiledRetryPolicy {
         if !self.retry_on_connect_error {
             return false;
         }
-        matches!(error, ProxyError::Connection(_) | ProxyError::Hyper(_))
+        match error {
+            ProxyError::Upstream { kind, .. } => kind.is_retryable(),
+            ProxyError::Connection(_) | ProxyError::Hyper(_) => true,
+            _ => false,
+        }
     }
 }
 
@@ -115,6 +238,7 @@ struct TargetSelection<'a> {
 enum RequestFailure {
     Selection(ProxyError),
     Forward(ProxyError),
+    Timeout(Duration),
 }
 
 struct InflightGuard {
@@ -134,12 +258,155 @@ impl Drop for InflightGuard {
     }
 }
 
+/// Per-connection settings for WebSocket tunnels, which get frame-aware relaying.
+struct WebSocketTunnel {
+    active: IntGauge,
+    drain: Duration,
+}
+
+/// Keeps a gauge raised for as long as a tunnel is open.
+struct GaugeGuard {
+    gauge: IntGauge,
+}
+
+impl GaugeGuard {
+    fn new(gauge: IntGauge) -> Self {
+        gauge.inc();
+        Self { gauge }
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.gauge.dec();
+    }
+}
+
+struct WebSocketTelemetry {
+    active: IntGaugeVec,
+    registered: AtomicBool,
+}
+
+impl WebSocketTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "websocket_connections_active",
+            "Open reverse proxy WebSocket tunnels by route and target",
+        ).namespace("bifrost");
+        Self {
+            active: IntGaugeVec::new(opts, &["route", "target"]).expect("websocket_connections_active metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.active.clone())) {
+            warn!("Failed to register websocket_connections_active metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn websocket_telemetry() -> &'static WebSocketTelemetry {
+    static TELEMETRY: OnceLock<WebSocketTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(WebSocketTelemetry::new)
+}
+
+/// Registers the per-target WebSocket gauges with the shared Prometheus registry.
+pub fn register_websocket_metrics(registry: &Registry) {
+    websocket_telemetry().register_if_needed(registry);
+}
+
+struct RouterTelemetry {
+    no_match: IntCounterVec,
+    requests_in_flight: IntGaugeVec,
+    registered: AtomicBool,
+}
+
+impl RouterTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "router_no_match_total",
+            "Reverse proxy requests that matched no route, by how they were answered",
+        ).namespace("bifrost");
+        let in_flight = Opts::new(
+            "upstream_requests_in_flight",
+            "Requests sent to a reverse proxy target that have not been answered yet",
+        ).namespace("bifrost");
+        Self {
+            no_match: IntCounterVec::new(opts, &["action"]).expect("router_no_match_total metric"),
+            requests_in_flight: IntGaugeVec::new(in_flight, &["route", "target"])
+                .expect("upstream_requests_in_flight metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        let collectors: [Box<dyn prometheus::core::Collector>; 2] =
+            [Box::new(self.no_match.clone()), Box::new(self.requests_in_flight.clone())];
+        for collector in collectors {
+            if let Err(err) = registry.register(collector) {
+                warn!("Failed to register router metric: {}", err);
+                return;
+            }
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn router_telemetry() -> &'static RouterTelemetry {
+    static TELEMETRY: OnceLock<RouterTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(RouterTelemetry::new)
+}
+
+/// Registers the unmatched request counter and in-flight request gauge with the shared
+/// Prometheus registry.
+pub fn register_router_metrics(registry: &Registry) {
+    router_telemetry().register_if_needed(registry);
+}
+
 #[derive(Clone)]
 struct WeightedEntry {
     route_index: usize,
     weight: u32,
 }
 
+/// A target with an active health check, as handed to its check loop.
+struct HealthCheckTarget {
+    /// `route/target`, unique across routes
+    key: String,
+    target_id: String,
+    url: Url,
+    client: Arc<UpstreamClient>,
+    config: HealthCheckConfig,
+    healthy: Arc<AtomicBool>,
+}
+
+/// Warm connections a route keeps open to each of its targets.
+#[derive(Clone)]
+struct Prewarm {
+    connections: usize,
+    path: String,
+    interval: Duration,
+}
+
+/// A target whose pooled connections are kept warm, as handed to its prewarm loop.
+struct PrewarmTarget {
+    /// `route/target`, unique across routes
+    key: String,
+    url: Url,
+    client: Arc<UpstreamClient>,
+    prewarm: Prewarm,
+    healthy: Arc<AtomicBool>,
+}
+
 struct WeightedGroup {
     entries: Vec<WeightedEntry>,
     counter: AtomicU64,
@@ -147,7 +414,63 @@ struct WeightedGroup {
 
 struct RouteMatcher {
     routes: Vec<CompiledRoute>,
+    /// Narrows the routes whose predicates are evaluated for a request
+    index: RouteIndex,
     weighted_groups: HashMap<String, WeightedGroup>,
+    fallback: RouteFallback,
+    tarpit: Option<Tarpit>,
+    /// Largest body any `JsonBody` predicate inspects; such bodies are read before routing
+    json_body_limit: Option<usize>,
+}
+
+/// Compiled `route_fallback`: what happens to requests no route matches.
+struct RouteFallback {
+    route_index: Option<usize>,
+    status: StatusCode,
+    body: Bytes,
+    content_type: HeaderValue,
+}
+
+impl RouteFallback {
+    fn compile(config: &RouteFallbackConfig, routes: &[CompiledRoute]) -> Result<Self, ProxyError> {
+        let route_index = match &config.route_id {
+            Some(id) => Some(routes.iter().position(|r| &r.id == id).ok_or_else(|| {
+                ProxyError::Config(format!("route_fallback.route_id {} does not match any route", id))
+            })?),
+            None => None,
+        };
+        let status = StatusCode::from_u16(config.status).map_err(|_| {
+            ProxyError::Config(format!("route_fallback.status {} is not a valid HTTP status", config.status))
+        })?;
+        let content_type = HeaderValue::from_str(&config.content_type).map_err(|_| {
+            ProxyError::Config(format!("route_fallback.content_type {:?} is not a valid header value", config.content_type))
+        })?;
+        Ok(Self {
+            route_index,
+            status,
+            body: Bytes::from(config.body.clone()),
+            content_type,
+        })
+    }
+
+    fn response(&self) -> Response<Full<Bytes>> {
+        Response::builder()
+            .status(self.status)
+            .header(CONTENT_TYPE, self.content_type.clone())
+            .body(Full::new(self.body.clone()))
+            .unwrap()
+    }
+}
+
+impl Default for RouteFallback {
+    fn default() -> Self {
+        Self {
+            route_index: None,
+            status: StatusCode::NOT_FOUND,
+            body: Bytes::from_static(b"No matching route"),
+            content_type: HeaderValue::from_static("text/plain; charset=utf-8"),
+        }
+    }
 }
 
 impl RouteMatcher {
@@ -196,7 +519,7 @@ impl RouteMatcher {
                     weight: 1,
                     enabled: true,
                 });
-            } else {
+            } else if cfg.aggregate.is_none() && cfg.static_response.is_none() {
                 return Err(ProxyError::Config(format!(
                     "Route {} must define a target or targets",
                     cfg.id
@@ -208,12 +531,43 @@ impl RouteMatcher {
                 .clone()
                 .or_else(|| default_pool_config.clone())
                 .unwrap_or_default();
-            let http_client = Arc::new(ReverseProxy::build_http_client(
-                connect_timeout_secs,
-                pool_cfg.pool_max_idle_per_host,
-                pool_cfg.pool_idle_timeout_secs,
-            ));
+            egress::validate(&cfg.id, &pool_cfg)?;
+            let limits = cfg.limits.clone().unwrap_or_default();
+            let connect_timeout_secs = limits.connect_timeout_secs.unwrap_or(connect_timeout_secs);
+            let upstream_tls = UpstreamTls::new(&cfg.id, cfg.upstream_tls.as_ref())?;
+            let http_client = Arc::new(ReverseProxy::build_http_client(connect_timeout_secs, &pool_cfg, &upstream_tls));
+            let fresh_client = (pool_cfg.pool_max_idle_per_host > 0).then(|| {
+                let unpooled = ReverseProxyConfig { pool_max_idle_per_host: 0, ..pool_cfg.clone() };
+                Arc::new(ReverseProxy::build_http_client(connect_timeout_secs, &unpooled, &upstream_tls))
+            });
             let health_check_config = pool_cfg.health_check.clone();
+            let prewarm = match &pool_cfg.prewarm {
+                Some(prewarm) => {
+                    if prewarm.connections == 0 || prewarm.connections > pool_cfg.pool_max_idle_per_host {
+                        return Err(ProxyError::Config(format!(
+                            "Route {} prewarm.connections must be between 1 and pool_max_idle_per_host ({})",
+                            cfg.id, pool_cfg.pool_max_idle_per_host
+                        )));
+                    }
+                    if !prewarm.path.starts_with('/') {
+                        return Err(ProxyError::Config(format!(
+                            "Route {} prewarm.path must start with '/'",
+                            cfg.id
+                        )));
+                    }
+                    // Refreshing well within the idle timeout keeps the pool from evicting them
+                    let interval_secs = prewarm
+                        .interval_secs
+                        .unwrap_or(pool_cfg.pool_idle_timeout_secs / 2)
+                        .max(1);
+                    Some(Prewarm {
+                        connections: prewarm.connections,
+                        path: prewarm.path.clone(),
+                        interval: Duration::from_secs(interval_secs),
+                    })
+                }
+                None => None,
+            };
 
             let mut weight_meta = None;
             let predicates = cfg
@@ -222,6 +576,28 @@ impl RouteMatcher {
                 .map(|p| Predicate::try_from(p, &mut weight_meta))
                 .collect::<Result<Vec<_>, _>>()?;
 
+            let path_variables: HashSet<&str> = predicates
+                .iter()
+                .filter_map(|predicate| match predicate {
+                    Predicate::Path(matcher) => Some(matcher),
+                    _ => None,
+                })
+                .flat_map(PathMatcher::variable_names)
+                .collect();
+            let mut path_param_headers = Vec::new();
+            for (variable, header) in &cfg.path_param_headers {
+                if !path_variables.contains(variable.as_str()) {
+                    return Err(ProxyError::Config(format!(
+                        "Route {} path_param_headers uses {}, which no path pattern of the route captures",
+                        cfg.id, variable
+                    )));
+                }
+                let header = HeaderName::from_bytes(header.as_bytes()).map_err(|e| {
+                    ProxyError::Config(format!("Route {} has invalid path_param_headers header {}: {}", cfg.id, header, e))
+                })?;
+                path_param_headers.push((variable.clone(), header));
+            }
+
             if let Some(meta) = weight_meta.clone() {
                 weighted_groups
                     .entry(meta.group.clone())
@@ -247,12 +623,29 @@ impl RouteMatcher {
                         cfg.id, target_cfg.id
                     )));
                 }
-                let url = Url::parse(&target_cfg.url).map_err(|e| {
+                let template = TargetTemplate::parse(&target_cfg.url)?;
+                let url = match &template {
+                    Some(template) => template.placeholder_url(),
+                    None => Url::parse(&target_cfg.url),
+                }
+                .map_err(|e| {
                     ProxyError::Config(format!(
                         "Invalid target URL for {} ({}): {}",
                         cfg.id, target_cfg.id, e
                     ))
                 })?;
+                if let Some(template) = &template
+                    && let Some(unknown) = template.variables().iter().find(|v| !path_variables.contains(v.as_str()))
+                {
+                    return Err(ProxyError::Config(format!(
+                        "Route {} target {} uses {{{}}}, which no path pattern of the route captures",
+                        cfg.id, target_cfg.id, unknown
+                    )));
+                }
+                let outlier = Arc::new(OutlierStats::new(&cfg.id, &target_cfg.id));
+                let requests = router_telemetry()
+                    .requests_in_flight
+                    .with_label_values(&[cfg.id.as_str(), target_cfg.id.as_str()]);
                 targets.push(CompiledTarget {
                     id: target_cfg.id,
                     url,
@@ -260,17 +653,30 @@ impl RouteMatcher {
                     enabled: target_cfg.enabled,
                     healthy: Arc::new(AtomicBool::new(true)),
                     inflight: Arc::new(AtomicU64::new(0)),
+                    requests,
+                    recovery: Arc::new(Recovery::new()),
+                    outlier,
+                    template,
                 });
             }
 
-            if targets.is_empty() {
+            let aggregator = cfg.aggregate.as_ref().map(Aggregator::new).transpose()?;
+            let static_responder = cfg
+                .static_response
+                .as_ref()
+                .map(|response| StaticResponder::new(&cfg.id, response))
+                .transpose()?;
+
+            let method_policy = MethodPolicy::new(&cfg.id, &cfg.allowed_methods, cfg.method_override)?;
+
+            if targets.is_empty() && aggregator.is_none() && static_responder.is_none() {
                 return Err(ProxyError::Config(format!(
                     "Route {} must define at least one target",
                     cfg.id
                 )));
             }
 
-            if targets.iter().all(|t| !t.enabled) {
+            if !targets.is_empty() && targets.iter().all(|t| !t.enabled) {
                 return Err(ProxyError::Config(format!(
                     "Route {} must have at least one enabled target",
                     cfg.id
@@ -378,26 +784,54 @@ impl RouteMatcher {
                 None
             };
 
-            let load_balancing = cfg
-                .load_balancing
+            let hmac_verifier = cfg
+                .hmac_verification
                 .clone()
-                .unwrap_or_default()
-                .policy;
+                .map(HmacVerifier::new)
+                .transpose()?;
+            let fan_out = cfg.fan_out.as_ref().map(FanOut::new).transpose()?;
+            let replay = cfg.replay.as_ref().map(|replay| ReplayStore::new(&cfg.id, replay)).transpose()?;
+
+            let LoadBalancingConfig { policy: load_balancing, outlier_detection, dynamic_weights, slow_start } =
+                cfg.load_balancing.clone().unwrap_or_default();
 
             routes.push(CompiledRoute {
                 id: cfg.id,
                 targets,
                 http_client,
+                fresh_client,
                 health_check_config,
+                prewarm,
+                preserve_host: cfg.preserve_host_header.or(pool_cfg.preserve_host_header),
+                header_case: pool_cfg.header_case,
                 strip_path_prefix: cfg.strip_path_prefix,
                 priority: cfg.priority.unwrap_or(0),
                 predicates,
                 weight: weight_meta,
                 original_index: idx,
                 load_balancing,
+                outlier_detection,
+                dynamic_weights,
+                slow_start,
                 sticky: cfg.sticky,
                 header_override: cfg.header_override,
                 retry_policy,
+                require_api_key: cfg.require_api_key,
+                hmac_verifier,
+                fan_out,
+                aggregator,
+                replay,
+                static_responder,
+                method_policy,
+                allowed_upgrades: cfg
+                    .allowed_upgrades
+                    .iter()
+                    .map(|protocol| protocol.trim().to_ascii_lowercase())
+                    .collect(),
+                path_param_headers,
+                limits,
+                tags: RouteTags::new(&cfg.metadata),
+                tenant: None,
                 rr_counter: AtomicU64::new(0),
             });
         }
@@ -422,9 +856,30 @@ impl RouteMatcher {
             })
             .collect::<Result<HashMap<_, _>, ProxyError>>()?;
 
+        let json_body_limit = routes
+            .iter()
+            .flat_map(|route| &route.predicates)
+            .filter_map(|predicate| match predicate {
+                Predicate::JsonBody(matcher) => Some(matcher.max_body_bytes()),
+                _ => None,
+            })
+            .max();
+        if let Some(limit) = json_body_limit {
+            info!(
+                "JsonBody predicates configured: JSON request bodies up to {} bytes are buffered before routing",
+                limit
+            );
+        }
+
+        let index = RouteIndex::new(&routes.iter().map(CompiledRoute::index_keys).collect::<Vec<_>>());
+
         Ok(Self {
             routes,
+            index,
             weighted_groups,
+            fallback: RouteFallback::default(),
+            tarpit: None,
+            json_body_limit,
         })
     }
 
@@ -432,21 +887,30 @@ impl RouteMatcher {
         self.routes.len()
     }
 
-    fn routes_with_health_checks(
-        &self,
-    ) -> Vec<(String, Url, Arc<Client<HttpConnector, BoxedBody>>, HealthCheckConfig, Arc<AtomicBool>)> {
+    /// Whether a route forwards header names as the client spelled them, which needs the
+    /// listener to record that spelling.
+    fn preserves_header_case(&self) -> bool {
+        self.routes.iter().any(|route| route.header_case == HeaderCase::Preserve)
+    }
+
+    fn fallback_route(&self) -> Option<&CompiledRoute> {
+        self.fallback.route_index.and_then(|index| self.routes.get(index))
+    }
+
+    fn routes_with_health_checks(&self) -> Vec<HealthCheckTarget> {
         let mut entries = Vec::new();
         for route in &self.routes {
             if let Some(cfg) = route.health_check_config.clone() {
                 for target in &route.targets {
                     if target.enabled {
-                        entries.push((
-                            target.id.clone(),
-                            target.url.clone(),
-                            route.http_client.clone(),
-                            cfg.clone(),
-                            target.healthy.clone(),
-                        ));
+                        entries.push(HealthCheckTarget {
+                            key: format!("{}/{}", route.id, target.id),
+                            target_id: target.id.clone(),
+                            url: target.url.clone(),
+                            client: route.http_client.clone(),
+                            config: cfg.clone(),
+                            healthy: target.healthy.clone(),
+                        });
                     }
                 }
             }
@@ -454,9 +918,28 @@ impl RouteMatcher {
         entries
     }
 
+    fn routes_with_prewarm(&self) -> Vec<PrewarmTarget> {
+        let mut entries = Vec::new();
+        for route in &self.routes {
+            if let Some(prewarm) = &route.prewarm {
+                for target in route.targets.iter().filter(|target| target.enabled) {
+                    entries.push(PrewarmTarget {
+                        key: format!("{}/{}", route.id, target.id),
+                        url: target.url.clone(),
+                        client: route.http_client.clone(),
+                        prewarm: prewarm.clone(),
+                        healthy: target.healthy.clone(),
+                    });
+                }
+            }
+        }
+        entries
+    }
+
     fn select_route<'a, B>(&'a self, req: &Request<B>, context: &RequestContext) -> Option<&'a CompiledRoute> {
         let mut matches: Vec<(&CompiledRoute, i32)> = Vec::new();
-        for route in &self.routes {
+        for index in self.index.candidates(req.method(), request_host(req), req.uri().path()) {
+            let route = &self.routes[index];
             if route.matches(req, context) {
                 matches.push((route, route.priority));
             }
@@ -521,6 +1004,54 @@ impl RouteMatcher {
 }
 
 impl CompiledRoute {
+    /// Applies the route's path variables to a request bound for `target`: copies them into the
+    /// configured headers and, for a templated target, returns the filled-in backend URL.
+    fn apply_path_params<B>(&self, target: &CompiledTarget, req: &mut Request<B>) -> Result<Option<Url>, ProxyError> {
+        if target.template.is_none() && self.path_param_headers.is_empty() {
+            return Ok(None);
+        }
+        let params = self.path_params(req);
+        for (variable, header) in &self.path_param_headers {
+            let value = params.iter().find(|(name, _)| name == variable).map(|(_, value)| value);
+            if let Some(value) = value.and_then(|v| HeaderValue::from_str(v).ok()) {
+                req.headers_mut().insert(header.clone(), value);
+            }
+        }
+        target.template.as_ref().map(|template| template.resolve(&params)).transpose()
+    }
+
+    /// hyper's client writes header names as recorded whenever the record travels with the
+    /// request, so routes that do not preserve case drop it from requests that have one. Those
+    /// only come from listeners serving a route with `header_case: "preserve"`. hyper keeps the
+    /// record's type private, so the request's other extensions go too; nothing reads them
+    /// once the request is rewritten, and the upgrade has been taken off it by then.
+    fn apply_header_case<B>(&self, mut req: Request<B>) -> Request<B> {
+        if self.header_case != HeaderCase::Preserve && req.extensions().get::<HeaderCaseRecorded>().is_some() {
+            req.extensions_mut().clear();
+        }
+        req
+    }
+
+    /// Variables captured by the route's path predicate.
+    fn path_params<B>(&self, req: &Request<B>) -> PathParams {
+        self.predicates
+            .iter()
+            .find_map(|predicate| match predicate {
+                Predicate::Path(matcher) => matcher.captures(req.uri().path()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// WebSocket is governed by the global WebSocket config; other protocols need a route opt-in.
+    fn tunnels_upgrade(&self, protocol: &str) -> bool {
+        protocol == "websocket"
+            || self
+                .allowed_upgrades
+                .iter()
+                .any(|allowed| allowed == "*" || allowed == protocol)
+    }
+
     fn select_target<'a, B>(
         &'a self,
         req: &Request<B>,
@@ -536,6 +1067,18 @@ impl CompiledRoute {
         context: &RequestContext,
         excluded: &HashSet<String>,
     ) -> Result<TargetSelection<'a>, ProxyError> {
+        if let Some(slow_start) = &self.slow_start {
+            let now = std::time::Instant::now();
+            for target in &self.targets {
+                if target.recovery.observe(target.healthy.load(Ordering::Relaxed), now) {
+                    info!(
+                        "Target {} of route {} is back; ramping up its traffic over {}s",
+                        target.id, self.id, slow_start.window_secs
+                    );
+                }
+            }
+        }
+
         let eligible_targets: Vec<&CompiledTarget> = self
             .targets
             .iter()
@@ -635,7 +1178,8 @@ impl CompiledRoute {
             }
         }
 
-        let target = self.select_by_policy(&eligible_targets).ok_or_else(|| {
+        let candidates = self.without_outliers(&eligible_targets);
+        let target = self.select_by_policy(&candidates).ok_or_else(|| {
             ProxyError::Connection(format!(
                 "No available targets for route {}",
                 self.id
@@ -657,10 +1201,62 @@ impl CompiledRoute {
         Ok(TargetSelection { target, set_cookie })
     }
 
+    /// Drops targets outlier detection has set aside, unless that would leave none.
+    fn without_outliers<'a>(&self, targets: &[&'a CompiledTarget]) -> Vec<&'a CompiledTarget> {
+        let config = &self.outlier_detection;
+        if !config.enabled || targets.len() < 2 {
+            return targets.to_vec();
+        }
+        let fastest = targets
+            .iter()
+            .filter_map(|t| t.outlier.latency_ewma(config))
+            .min_by(|a, b| a.total_cmp(b));
+        let now = std::time::Instant::now();
+        let healthy: Vec<&CompiledTarget> = targets
+            .iter()
+            .filter(|t| !t.outlier.is_ejected(config, fastest, now))
+            .copied()
+            .collect();
+        if healthy.is_empty() { targets.to_vec() } else { healthy }
+    }
+
+    /// Picks a target by policy. A target still ramping up after a recovery keeps the pick only
+    /// with the probability of its current share; otherwise the pick is made among the others.
     fn select_by_policy<'a>(&'a self, targets: &[&'a CompiledTarget]) -> Option<&'a CompiledTarget> {
+        let target = self.pick_by_policy(targets)?;
+        let share = self.slow_start_share(target, std::time::Instant::now());
+        if share < 1.0 && targets.len() > 1 && !rand::thread_rng().gen_bool(share) {
+            let others: Vec<&CompiledTarget> =
+                targets.iter().filter(|t| t.id != target.id).copied().collect();
+            return self.pick_by_policy(&others).or(Some(target));
+        }
+        Some(target)
+    }
+
+    /// Share of its normal traffic a target gets while it ramps up after a recovery.
+    fn slow_start_share(&self, target: &CompiledTarget, now: std::time::Instant) -> f64 {
+        let Some(config) = &self.slow_start else {
+            return 1.0;
+        };
+        let Some(since) = [target.recovery.at(), target.outlier.returned_at()].into_iter().flatten().max() else {
+            return 1.0;
+        };
+        let window = config.window_secs as f64;
+        let elapsed = now.saturating_duration_since(since).as_secs_f64();
+        if elapsed >= window {
+            return 1.0;
+        }
+        let min = f64::from(config.min_percent.min(100)) / 100.0;
+        min + (1.0 - min) * elapsed / window
+    }
+
+    fn pick_by_policy<'a>(&'a self, targets: &[&'a CompiledTarget]) -> Option<&'a CompiledTarget> {
         if targets.is_empty() {
             return None;
         }
+        if let Some(dynamic_weights) = &self.dynamic_weights {
+            return self.select_by_cost(targets, dynamic_weights);
+        }
 
         match self.load_balancing {
             LoadBalancingPolicy::RoundRobin => {
@@ -689,6 +1285,24 @@ impl CompiledRoute {
                     .min_by_key(|t| t.inflight.load(Ordering::Relaxed))
                     .copied()
             }
+            LoadBalancingPolicy::LeastRequests => {
+                // Compares (requests + 1) / weight without division
+                let load = |t: &CompiledTarget| (t.requests.get().max(0) as u64 + 1, u64::from(t.weight.max(1)));
+                let (mut best, mut tied) = (load(targets[0]), vec![targets[0]]);
+                for target in &targets[1..] {
+                    let (requests, weight) = load(target);
+                    match (requests * best.1).cmp(&(best.0 * weight)) {
+                        std::cmp::Ordering::Less => {
+                            best = (requests, weight);
+                            tied = vec![*target];
+                        }
+                        std::cmp::Ordering::Equal => tied.push(*target),
+                        std::cmp::Ordering::Greater => {}
+                    }
+                }
+                let seq = self.rr_counter.fetch_add(1, Ordering::Relaxed);
+                Some(tied[(seq % tied.len() as u64) as usize])
+            }
             LoadBalancingPolicy::Random => {
                 let idx = rand::thread_rng().gen_range(0..targets.len());
                 Some(targets[idx])
@@ -696,6 +1310,35 @@ impl CompiledRoute {
         }
     }
 
+    /// Power of two choices: draws two targets in proportion to their weights and takes the
+    /// cheaper one, so slow or failing targets lose traffic without being set aside entirely.
+    fn select_by_cost<'a>(
+        &self,
+        targets: &[&'a CompiledTarget],
+        config: &DynamicWeightsConfig,
+    ) -> Option<&'a CompiledTarget> {
+        let mut rng = rand::thread_rng();
+        let first = weighted_index(targets, None, &mut rng)?;
+        let Some(second) = weighted_index(targets, Some(first), &mut rng) else {
+            return Some(targets[first]);
+        };
+        let (first, second) = (targets[first], targets[second]);
+        if self.target_cost(second, config) < self.target_cost(first, config) {
+            Some(second)
+        } else {
+            Some(first)
+        }
+    }
+
+    fn target_cost(&self, target: &CompiledTarget, config: &DynamicWeightsConfig) -> f64 {
+        let stats = &self.outlier_detection;
+        // Targets without enough samples yet cost 1ms, so they get traffic to measure
+        let latency = target.outlier.latency_ewma(stats).unwrap_or(0.0).max(0.001);
+        let error_rate = 1.0 - target.outlier.success_rate(stats).unwrap_or(1.0);
+        let inflight = target.inflight.load(Ordering::Relaxed) as f64;
+        latency * (inflight + 1.0) * (1.0 + config.error_penalty.max(0.0) * error_rate)
+    }
+
     fn select_by_hash<'a>(
         &'a self,
         key: &str,
@@ -719,6 +1362,8 @@ enum Predicate {
     Header(HeaderMatcher),
     Query(QueryMatcher),
     Cookie(CookieMatcher),
+    JsonBody(JsonBodyMatcher),
+    GraphQl(OperationMatcher),
     RemoteAddr(Vec<IpNet>),
     After(DateTime<FixedOffset>),
     Before(DateTime<FixedOffset>),
@@ -774,6 +1419,11 @@ impl Predicate {
                 let matcher = CookieMatcher::new(&name, value, regex)?;
                 Ok(Predicate::Cookie(matcher))
             }
+            RoutePredicateConfig::JsonBody { field, value, regex, max_body_bytes } => {
+                let matcher = JsonBodyMatcher::new(&field, value, regex, max_body_bytes)?;
+                Ok(Predicate::JsonBody(matcher))
+            }
+            RoutePredicateConfig::GraphQl { operation } => Ok(Predicate::GraphQl(OperationMatcher::new(&operation)?)),
             RoutePredicateConfig::After { instant } => {
                 let parsed = parse_instant(&instant)?;
                 Ok(Predicate::After(parsed))
@@ -817,14 +1467,7 @@ impl Predicate {
     fn evaluate<B>(&self, req: &Request<B>, context: &RequestContext) -> Result<bool, ProxyError> {
         match self {
             Predicate::Path(matcher) => Ok(matcher.matches(req.uri().path())),
-            Predicate::Host(matcher) => {
-                let host = req
-                    .headers()
-                    .get(HOST)
-                    .and_then(|h| h.to_str().ok())
-                    .or_else(|| req.uri().host());
-                Ok(host.map(|h| matcher.matches(h)).unwrap_or(false))
-            }
+            Predicate::Host(matcher) => Ok(request_host(req).map(|h| matcher.matches(h)).unwrap_or(false)),
             Predicate::Method(methods) => {
                 if methods.is_empty() {
                     Ok(true)
@@ -835,6 +1478,8 @@ impl Predicate {
             Predicate::Header(matcher) => Ok(matcher.matches(req.headers())),
             Predicate::Query(matcher) => Ok(matcher.matches(req.uri())),
             Predicate::Cookie(matcher) => Ok(matcher.matches(req.headers())),
+            Predicate::JsonBody(matcher) => Ok(matcher.matches(req.extensions())),
+            Predicate::GraphQl(matcher) => Ok(matcher.matches(req.extensions())),
             Predicate::RemoteAddr(nets) => {
                 if let Some(ip_str) = context.client_ip.as_deref() {
                     let ip: IpAddr = ip_str
@@ -864,7 +1509,10 @@ impl Predicate {
 
 #[derive(Clone)]
 struct PathMatcher {
+    patterns: Vec<String>,
     regexes: Vec<Regex>,
+    /// All patterns in one automaton, so a miss costs one scan of the path
+    set: RegexSet,
 }
 
 impl PathMatcher {
@@ -876,36 +1524,71 @@ impl PathMatcher {
                     .map_err(|e| ProxyError::Config(format!("Invalid path pattern {}: {}", p, e)))
             })
             .collect::<Result<Vec<_>, _>>()?;
-        Ok(Self { regexes })
+        let set = regex_set(&regexes, "path")?;
+        Ok(Self { patterns, regexes, set })
     }
 
     fn matches(&self, path: &str) -> bool {
-        self.regexes.iter().any(|r| r.is_match(path))
+        self.set.is_match(path)
+    }
+
+    /// Named variables of the first pattern matching `path`.
+    fn captures(&self, path: &str) -> Option<PathParams> {
+        let regex = &self.regexes[self.set.matches(path).iter().next()?];
+        let captures = regex.captures(path)?;
+        Some(
+            regex
+                .capture_names()
+                .flatten()
+                .filter_map(|name| captures.name(name).map(|m| (name.to_string(), m.as_str().to_string())))
+                .collect(),
+        )
+    }
+
+    fn variable_names(&self) -> impl Iterator<Item = &str> {
+        self.regexes.iter().flat_map(|regex| regex.capture_names().flatten())
     }
 }
 
+/// Literal host names are looked up in a set of lowercased names; only patterns with wildcards
+/// go through the regex set.
 #[derive(Clone)]
 struct HostMatcher {
-    regexes: Vec<Regex>,
+    patterns: Vec<String>,
+    exact: HashSet<String>,
+    set: RegexSet,
 }
 
 impl HostMatcher {
     fn from_patterns(patterns: Vec<String>) -> Result<Self, ProxyError> {
-        let regexes = patterns
-            .iter()
-            .map(|p| {
-                build_ant_regex(p, false, true)
-                    .map_err(|e| ProxyError::Config(format!("Invalid host pattern {}: {}", p, e)))
-            })
-            .collect::<Result<Vec<_>, _>>()?;
-        Ok(Self { regexes })
+        // Internationalized names are matched in punycode, the form clients send them in
+        let patterns: Vec<String> = patterns.iter().map(|p| normalize_domain(p).into_owned()).collect();
+        let mut exact = HashSet::new();
+        let mut regexes = Vec::new();
+        for pattern in &patterns {
+            if pattern.is_ascii() && !pattern.contains(['*', '?', '{']) {
+                exact.insert(pattern.clone());
+                continue;
+            }
+            regexes.push(
+                build_ant_regex(pattern, false, true)
+                    .map_err(|e| ProxyError::Config(format!("Invalid host pattern {}: {}", pattern, e)))?,
+            );
+        }
+        let set = regex_set(&regexes, "host")?;
+        Ok(Self { patterns, exact, set })
     }
 
     fn matches(&self, host: &str) -> bool {
-        self.regexes.iter().any(|r| r.is_match(host))
+        (!self.exact.is_empty() && self.exact.contains(ascii_lowercase(host).as_ref())) || self.set.is_match(host)
     }
 }
 
+fn regex_set(regexes: &[Regex], kind: &str) -> Result<RegexSet, ProxyError> {
+    RegexSet::new(regexes.iter().map(Regex::as_str))
+        .map_err(|e| ProxyError::Config(format!("Invalid {} patterns: {}", kind, e)))
+}
+
 #[derive(Clone)]
 struct HeaderMatcher {
     name: HeaderName,
@@ -1037,6 +1720,26 @@ impl CookieMatcher {
 }
 
 impl CompiledRoute {
+    /// What the route index needs from the first Method, Host and Path predicates.
+    fn index_keys(&self) -> RouteKeys {
+        let mut keys = RouteKeys::default();
+        for predicate in &self.predicates {
+            match predicate {
+                Predicate::Method(methods) if !methods.is_empty() && keys.methods.is_none() => {
+                    keys.methods = Some(methods.clone());
+                }
+                Predicate::Host(matcher) if keys.host_patterns.is_none() => {
+                    keys.host_patterns = Some(matcher.patterns.clone());
+                }
+                Predicate::Path(matcher) if keys.path_patterns.is_none() => {
+                    keys.path_patterns = Some(matcher.patterns.clone());
+                }
+                _ => {}
+            }
+        }
+        keys
+    }
+
     fn matches<B>(&self, req: &Request<B>, context: &RequestContext) -> bool {
         for predicate in &self.predicates {
             match predicate.evaluate(req, context) {
@@ -1052,11 +1755,33 @@ impl CompiledRoute {
     }
 }
 
+/// The host Host predicates match: the `Host` header, or the authority of an absolute URI.
+fn request_host<B>(req: &Request<B>) -> Option<&str> {
+    req.headers().get(HOST).and_then(|h| h.to_str().ok()).or_else(|| req.uri().host())
+}
+
 fn parse_instant(raw: &str) -> Result<DateTime<FixedOffset>, ProxyError> {
     DateTime::parse_from_rfc3339(raw)
         .map_err(|e| ProxyError::Config(format!("Invalid timestamp {}: {}", raw, e)))
 }
 
+/// Draws the index of a target with probability proportional to its weight, skipping `skip`.
+fn weighted_index(targets: &[&CompiledTarget], skip: Option<usize>, rng: &mut impl Rng) -> Option<usize> {
+    let weight = |index: usize| if Some(index) == skip { 0 } else { u64::from(targets[index].weight.max(1)) };
+    let total: u64 = (0..targets.len()).map(weight).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut cursor = rng.gen_range(0..total);
+    for index in 0..targets.len() {
+        if cursor < weight(index) {
+            return Some(index);
+        }
+        cursor -= weight(index);
+    }
+    None
+}
+
 fn extract_cookie_value(headers: &hyper::HeaderMap, cookie_name: &str) -> Option<String> {
     for val in headers.get_all("cookie").iter() {
         if let Ok(cookie_str) = val.to_str() {
@@ -1084,7 +1809,35 @@ fn build_sticky_cookie(name: &str, value: &str, ttl_seconds: Option<u64>) -> Str
     cookie
 }
 
-fn build_ant_regex(
+/// Reads a whole body, giving up when `idle` passes without a new frame.
+async fn collect_body<B>(body: B, idle: Option<Duration>) -> Result<Bytes, BoxError>
+where
+    B: hyper::body::Body<Data = Bytes>,
+    B::Error: Into<BoxError>,
+{
+    let Some(idle) = idle else {
+        return Ok(body.collect().await.map_err(Into::into)?.to_bytes());
+    };
+    let mut body = std::pin::pin!(body);
+    let mut collected = Vec::new();
+    loop {
+        let frame = match tokio::time::timeout(idle, body.frame()).await {
+            Ok(Some(frame)) => frame.map_err(Into::into)?,
+            Ok(None) => return Ok(Bytes::from(collected)),
+            Err(_) => return Err(Box::new(BodyIdleTimeout(idle))),
+        };
+        if let Ok(data) = frame.into_data() {
+            collected.extend_from_slice(&data);
+        }
+    }
+}
+
+/// Most of a streamed body read into memory when a feature needs all of it.
+fn buffer_limit(limits: &RouteLimitsConfig) -> usize {
+    limits.max_buffer_bytes.unwrap_or(DEFAULT_MAX_BUFFER_BYTES)
+}
+
+pub(crate) fn build_ant_regex(
     pattern: &str,
     match_trailing_slash: bool,
     case_insensitive: bool,
@@ -1102,12 +1855,24 @@ fn build_ant_regex(
                 }
             }
             '{' => {
-                while let Some(next) = chars.next() {
+                let mut name = String::new();
+                for next in chars.by_ref() {
                     if next == '}' {
                         break;
                     }
+                    name.push(next);
+                }
+                // `{name}` captures one segment and `{*name}` the rest of the path; other
+                // brace contents stay anonymous segment wildcards
+                match name.strip_prefix('*') {
+                    Some(rest) if is_variable_name(rest) => {
+                        regex.push_str(&format!("(?P<{}>.*)", rest));
+                    }
+                    _ if is_variable_name(&name) => {
+                        regex.push_str(&format!("(?P<{}>[^/]+)", name));
+                    }
+                    _ => regex.push_str("([^/]+)"),
                 }
-                regex.push_str("([^/]+)");
             }
             '?' => regex.push_str("."),
             '.' | '+' | '(' | ')' | '|' | '^' | '$' | '[' | ']' | '\\' => {
@@ -1127,30 +1892,168 @@ fn build_ant_regex(
     Regex::new(&regex)
 }
 
-pub struct ReverseProxy {
-    routes: Arc<RouteMatcher>,
-    preserve_host: bool,
-    metrics: Arc<PerformanceMetrics>,
-    websocket_config: WebSocketConfig,
-    rate_limiter: Arc<RateLimiter>,
+/// A served route as the admin API lists it.
+#[derive(Debug, serde::Serialize)]
+pub struct RouteSummary {
+    pub id: String,
+    pub priority: i32,
+    pub tenant: Option<String>,
+    pub targets: Vec<RouteTargetSummary>,
 }
 
-impl ReverseProxy {
-    /// Creates a new reverse proxy with default pooling configuration (single route fallback)
-    pub fn new(
-        target_url: String,
-        connect_timeout_secs: u64,
-        idle_timeout_secs: u64,
-        max_connection_lifetime_secs: u64,
-    ) -> Result<Self, ProxyError> {
-        Self::new_with_config(
-            target_url,
-            connect_timeout_secs,
-            idle_timeout_secs,
-            max_connection_lifetime_secs,
-            None,
-            None,
-        )
+#[derive(Debug, serde::Serialize)]
+pub struct RouteTargetSummary {
+    pub id: String,
+    pub url: String,
+    pub weight: u32,
+    pub enabled: bool,
+    pub healthy: bool,
+    pub inflight: u64,
+}
+
+/// The routes of a reverse proxy, replaced as a whole when the configuration is reloaded.
+///
+/// Each request is matched against the routes current when it arrives and keeps them until it
+/// completes. Health checks and warm connections follow the swap: those of replaced routes stop,
+/// and targets that keep their route and id keep their health.
+#[derive(Clone)]
+pub struct RouteTable {
+    current: Arc<RwLock<Arc<RouteMatcher>>>,
+    /// Background work for the current routes, once the proxy has started it
+    tasks: Arc<Mutex<Option<RouteTasks>>>,
+}
+
+struct RouteTasks {
+    health_checks: bool,
+    cluster: Option<Arc<Cluster>>,
+    handles: Vec<tokio::task::JoinHandle<()>>,
+}
+
+impl RouteTable {
+    fn new(routes: RouteMatcher) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(routes))),
+            tasks: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn current(&self) -> Arc<RouteMatcher> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The routes while the proxy is being built; `None` once they are shared.
+    fn get_mut(&mut self) -> Option<&mut RouteMatcher> {
+        let current = Arc::get_mut(&mut self.current)?.get_mut().unwrap_or_else(|e| e.into_inner());
+        Arc::get_mut(current)
+    }
+
+    pub fn route_count(&self) -> usize {
+        self.current().route_count()
+    }
+
+    /// The routes being served, in the order they are matched.
+    pub fn summaries(&self) -> Vec<RouteSummary> {
+        self.current()
+            .routes
+            .iter()
+            .map(|route| RouteSummary {
+                id: route.id.clone(),
+                priority: route.priority,
+                tenant: route.tenant.as_ref().map(|tenant| tenant.id().to_string()),
+                targets: route
+                    .targets
+                    .iter()
+                    .map(|target| RouteTargetSummary {
+                        id: target.id.clone(),
+                        url: redact_url(target.url.as_str()),
+                        weight: target.weight,
+                        enabled: target.enabled,
+                        healthy: target.healthy.load(Ordering::Relaxed),
+                        inflight: target.inflight.load(Ordering::Relaxed),
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Serves the routes of `other` from now on.
+    pub fn replace(&self, other: &RouteTable) {
+        let routes = other.current();
+        let previous = self.current().routes_with_health_checks();
+        for target in routes.routes_with_health_checks() {
+            if let Some(old) = previous.iter().find(|old| old.key == target.key) {
+                target.healthy.store(old.healthy.load(Ordering::Relaxed), Ordering::Relaxed);
+            }
+        }
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = routes.clone();
+
+        let mut tasks = self.tasks.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(tasks) = tasks.as_mut() {
+            for handle in tasks.handles.drain(..) {
+                handle.abort();
+            }
+            tasks.handles = Self::spawn_tasks(&routes, tasks.health_checks, &tasks.cluster);
+        }
+    }
+
+    /// Starts warm connections, and health checks when `health_checks` is set, for the current
+    /// routes and every set that replaces them.
+    fn start(&self, health_checks: bool, cluster: Option<Arc<Cluster>>) {
+        let handles = Self::spawn_tasks(&self.current(), health_checks, &cluster);
+        *self.tasks.lock().unwrap_or_else(|e| e.into_inner()) = Some(RouteTasks { health_checks, cluster, handles });
+    }
+
+    fn spawn_tasks(
+        routes: &RouteMatcher,
+        health_checks: bool,
+        cluster: &Option<Arc<Cluster>>,
+    ) -> Vec<tokio::task::JoinHandle<()>> {
+        let mut handles = Vec::new();
+        if health_checks {
+            for target in routes.routes_with_health_checks() {
+                if let Some(cluster) = cluster {
+                    cluster.track_health(&target.key, target.healthy.clone());
+                }
+                handles.push(tokio::spawn(ReverseProxy::health_check_loop(target, cluster.clone())));
+            }
+        }
+        for target in routes.routes_with_prewarm() {
+            handles.push(tokio::spawn(ReverseProxy::prewarm_loop(target)));
+        }
+        handles
+    }
+}
+
+pub struct ReverseProxy {
+    routes: RouteTable,
+    preserve_host: bool,
+    metrics: Arc<PerformanceMetrics>,
+    websocket_config: WebSocketConfig,
+    rate_limiter: Arc<RateLimiter>,
+    api_keys: Arc<ApiKeyStore>,
+    listener_config: ListenerConfig,
+    http2: Option<Http2Config>,
+    cluster: Option<Arc<Cluster>>,
+    early_data_policy: EarlyDataPolicy,
+    client_cert_header: Option<ClientCertHeader>,
+}
+
+impl ReverseProxy {
+    /// Creates a new reverse proxy with default pooling configuration (single route fallback)
+    pub fn new(
+        target_url: String,
+        connect_timeout_secs: u64,
+        idle_timeout_secs: u64,
+        max_connection_lifetime_secs: u64,
+    ) -> Result<Self, ProxyError> {
+        Self::new_with_config(
+            target_url,
+            connect_timeout_secs,
+            idle_timeout_secs,
+            max_connection_lifetime_secs,
+            None,
+            None,
+        )
     }
 
     /// Creates a new reverse proxy with custom pooling configuration (single route fallback)
@@ -1172,7 +2075,21 @@ impl ReverseProxy {
             retry_policy: None,
             reverse_proxy_config: reverse_proxy_config.clone(),
             strip_path_prefix: None,
+            preserve_host_header: None,
             priority: Some(0),
+            require_api_key: false,
+            hmac_verification: None,
+            fan_out: None,
+            aggregate: None,
+            replay: None,
+            static_response: None,
+            allowed_methods: Vec::new(),
+            method_override: None,
+            allowed_upgrades: Vec::new(),
+            path_param_headers: HashMap::new(),
+            limits: None,
+            upstream_tls: None,
+            metadata: HashMap::new(),
             predicates: vec![RoutePredicateConfig::Path {
                 patterns: vec!["/**".to_string()],
                 match_trailing_slash: true,
@@ -1197,7 +2114,7 @@ impl ReverseProxy {
         reverse_proxy_config: Option<ReverseProxyConfig>,
         websocket_config: Option<WebSocketConfig>,
     ) -> Result<Self, ProxyError> {
-        let router = Arc::new(RouteMatcher::new(
+        let router = RouteTable::new(RouteMatcher::new(
             routes,
             connect_timeout_secs,
             reverse_proxy_config,
@@ -1211,21 +2128,60 @@ impl ReverseProxy {
             metrics: Arc::new(PerformanceMetrics::new()),
             websocket_config: websocket_config.unwrap_or_default(),
             rate_limiter: Arc::new(RateLimiter::new(None)),
+            api_keys: Arc::new(ApiKeyStore::empty()),
+            listener_config: ListenerConfig::default(),
+            http2: None,
+            cluster: None,
+            early_data_policy: EarlyDataPolicy::default(),
+            client_cert_header: None,
         })
     }
 
     /// Build HTTP client for reverse proxy with connection pooling
     fn build_http_client(
         connect_timeout_secs: u64,
-        pool_max_idle_per_host: usize,
-        pool_idle_timeout_secs: u64,
-    ) -> Client<HttpConnector, BoxedBody> {
-        let mut connector = HttpConnector::new();
+        pool_cfg: &ReverseProxyConfig,
+        upstream_tls: &UpstreamTls,
+    ) -> UpstreamClient {
+        let pool_max_idle_per_host = pool_cfg.pool_max_idle_per_host;
+        let pool_idle_timeout_secs = pool_cfg.pool_idle_timeout_secs;
+        let mut connector = HttpConnector::new_with_resolver(DnsResolver::shared());
         connector.set_connect_timeout(Some(Duration::from_secs(connect_timeout_secs)));
-        connector.set_keepalive(Some(Duration::from_secs(pool_idle_timeout_secs)));
+        // Keepalive probes let the OS notice a backend that vanished without closing its
+        // connections (host restart, dropped NAT entry), so the pool discards them before reuse
+        match &pool_cfg.keepalive {
+            Some(keepalive) => {
+                connector.set_keepalive(Some(Duration::from_secs(keepalive.time_secs)));
+                connector.set_keepalive_interval(Some(Duration::from_secs(keepalive.interval_secs)));
+                connector.set_keepalive_retries(Some(keepalive.retries));
+            }
+            None => connector.set_keepalive(Some(Duration::from_secs(pool_idle_timeout_secs))),
+        }
         connector.set_nodelay(true);
+        // https:// targets pass through to the TLS layer around this connector
+        connector.enforce_http(false);
+        let source_ipv4 = pool_cfg.source_addresses.iter().find(|ip| ip.is_ipv4()).copied();
+        let source_ipv6 = pool_cfg.source_addresses.iter().find(|ip| ip.is_ipv6()).copied();
+        match (source_ipv4, source_ipv6) {
+            (Some(IpAddr::V4(v4)), Some(IpAddr::V6(v6))) => connector.set_local_addresses(v4, v6),
+            (v4, v6) => connector.set_local_address(v4.or(v6)),
+        }
+        if let Some(interface) = &pool_cfg.interface {
+            Self::bind_interface(&mut connector, interface);
+        }
 
         let mut builder = Client::builder(TokioExecutor::new());
+        builder.retry_canceled_requests(pool_cfg.retry_on_reuse_error);
+        match pool_cfg.header_case {
+            HeaderCase::Lowercase => {}
+            HeaderCase::TitleCase => {
+                builder.http1_title_case_headers(true);
+            }
+            // The listener records how each request spelled its headers
+            HeaderCase::Preserve => {
+                builder.http1_preserve_header_case(true);
+            }
+        }
 
         if pool_max_idle_per_host == 0 {
             info!("Reverse proxy: connection pooling DISABLED (pool_max_idle_per_host=0)");
@@ -1240,7 +2196,23 @@ impl ReverseProxy {
             builder.pool_timer(TokioTimer::new());
         }
 
-        builder.http2_only(false).build(connector)
+        let connector = UpstreamConnector::new(
+            connector,
+            &pool_cfg.relay_proxies,
+            Duration::from_secs(connect_timeout_secs),
+        )
+        .with_dscp(pool_cfg.dscp);
+        builder.http2_only(false).build(upstream_tls.wrap(connector))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn bind_interface(connector: &mut HttpConnector<DnsResolver>, interface: &str) {
+        connector.set_interface(interface);
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn bind_interface(_connector: &mut HttpConnector<DnsResolver>, interface: &str) {
+        warn!("Binding backend connections to an interface is only supported on Linux; ignoring {}", interface);
     }
 
     pub fn with_preserve_host(mut self, preserve_host: bool) -> Self {
@@ -1258,101 +2230,300 @@ impl ReverseProxy {
         self
     }
 
+    /// Sets how requests that match no route are answered. Must be called before the proxy runs.
+    pub fn with_route_fallback(mut self, fallback: Option<RouteFallbackConfig>) -> Result<Self, ProxyError> {
+        let Some(config) = fallback else {
+            return Ok(self);
+        };
+        let routes = self.routes.get_mut().ok_or_else(|| {
+            ProxyError::Config("route_fallback must be set before the reverse proxy starts".to_string())
+        })?;
+        routes.fallback = RouteFallback::compile(&config, &routes.routes)?;
+        Ok(self)
+    }
+
+    /// Decides where route metadata is surfaced; also fixes the metric label allowlist, so it
+    /// applies even when `route_metadata` is not configured.
+    pub fn with_route_metadata(mut self, config: Option<RouteMetadataConfig>) -> Result<Self, ProxyError> {
+        let config = config.unwrap_or_default();
+        configure_metric_labels(&config.metric_labels)?;
+        let routes = self.routes.get_mut().ok_or_else(|| {
+            ProxyError::Config("route_metadata must be set before the reverse proxy starts".to_string())
+        })?;
+        for route in &mut routes.routes {
+            route.tags.apply(&route.id, &config)?;
+        }
+        Ok(self)
+    }
+
+    /// Samples and filters the access log entries of all routes.
+    pub fn with_access_log(mut self, policy: Option<AccessLogPolicy>) -> Result<Self, ProxyError> {
+        let Some(policy) = policy else {
+            return Ok(self);
+        };
+        let policy = Arc::new(policy);
+        let routes = self.routes.get_mut().ok_or_else(|| {
+            ProxyError::Config("access_log must be set before the reverse proxy starts".to_string())
+        })?;
+        if let Some(unknown) = policy.routes().find(|id| !routes.routes.iter().any(|route| route.id == *id)) {
+            return Err(ProxyError::Config(format!("access_log.reverse.routes: unknown route '{}'", unknown)));
+        }
+        for route in &mut routes.routes {
+            route.tags.set_access_policy(policy.clone());
+        }
+        Ok(self)
+    }
+
+    /// Holds requests for known scanner paths and refuses banned clients before routing.
+    pub fn with_tarpit(mut self, tarpit: Option<Tarpit>) -> Result<Self, ProxyError> {
+        let Some(tarpit) = tarpit else {
+            return Ok(self);
+        };
+        let routes = self.routes.get_mut().ok_or_else(|| {
+            ProxyError::Config("tarpit must be set before the reverse proxy starts".to_string())
+        })?;
+        routes.tarpit = Some(tarpit);
+        Ok(self)
+    }
+
+    /// Assigns each route to the tenant that owns it.
+    pub fn with_tenants(mut self, tenants: &Tenants) -> Result<Self, ProxyError> {
+        if tenants.is_empty() {
+            return Ok(self);
+        }
+        let routes = self.routes.get_mut().ok_or_else(|| {
+            ProxyError::Config("tenants must be set before the reverse proxy starts".to_string())
+        })?;
+        if let Some(unknown) = tenants.route_ids().find(|id| !routes.routes.iter().any(|route| route.id == *id)) {
+            return Err(ProxyError::Config(format!("tenants: unknown route '{}'", unknown)));
+        }
+        for route in &mut routes.routes {
+            route.tenant = tenants.for_route(&route.id);
+        }
+        Ok(self)
+    }
+
+    /// Key store consulted by routes that set `require_api_key`.
+    pub fn with_api_keys(mut self, api_keys: Arc<ApiKeyStore>) -> Self {
+        self.api_keys = api_keys;
+        self
+    }
+
+    /// Shares health check results with the other instances of the cluster.
+    pub fn with_cluster(mut self, cluster: Option<Arc<Cluster>>) -> Self {
+        self.cluster = cluster;
+        self
+    }
+
+    /// TCP options for the listening socket and accepted connections.
+    pub fn with_listener_config(mut self, listener_config: ListenerConfig) -> Self {
+        self.listener_config = listener_config;
+        self
+    }
+
+    /// HTTP/2 for clients; `None` serves HTTP/1.1 only.
+    pub fn with_http2(mut self, http2: Option<Http2Config>) -> Self {
+        self.http2 = http2;
+        self
+    }
+
+    /// How requests received as TLS early data are handled.
+    pub fn with_early_data_policy(mut self, policy: EarlyDataPolicy) -> Self {
+        self.early_data_policy = policy;
+        self
+    }
+
+    /// Header in which TLS listeners pass the client certificate's subject to backends.
+    pub fn with_client_cert_header(mut self, client_cert_header: Option<ClientCertHeader>) -> Self {
+        self.client_cert_header = client_cert_header;
+        self
+    }
+
+    /// Starts reloading externally sourced API keys in the background.
+    pub fn spawn_api_key_refresh(&self) {
+        tokio::spawn(self.api_keys.clone().refresh_loop());
+    }
+
+    /// Starts keeping warm connections to the targets of routes with `prewarm`.
+    pub fn spawn_prewarm(&self) {
+        self.routes.start(false, None);
+    }
+
+    /// Handle to the routes, through which a configuration reload replaces them.
+    pub fn route_table(&self) -> RouteTable {
+        self.routes.clone()
+    }
+
+    /// Whether listeners serving these routes record how clients spell header names.
+    pub fn preserves_header_case(&self) -> bool {
+        self.routes.current().preserves_header_case()
+    }
+
     /// Public method for handling individual requests (used by CombinedProxyAdapter)
     pub async fn handle_request_with_context(
         &self,
         req: Request<Incoming>,
         context: RequestContext,
-    ) -> Result<Response<Full<Bytes>>, Infallible> {
+    ) -> Result<Response<ResponseBody>, Infallible> {
         Self::handle_request_static(
             req,
             context,
-            self.routes.clone(),
+            self.routes.current(),
             self.preserve_host,
             Arc::new(self.websocket_config.clone()),
             self.metrics.clone(),
             self.rate_limiter.clone(),
+            self.api_keys.clone(),
         )
         .await
     }
 
+    /// Id of the route `req` would be sent to, if any.
+    pub fn route_for<B>(&self, req: &Request<B>, context: &RequestContext) -> Option<String> {
+        self.routes.current().select_route(req, context).map(|route| route.id.clone())
+    }
+
+    /// The request as it would be sent to the selected backend, without sending it: target
+    /// selection, path parameters and the header and URI rewrite all apply.
+    pub fn backend_request<B>(&self, mut req: Request<B>, context: &RequestContext) -> Result<Request<B>, ProxyError> {
+        let routes = self.routes.current();
+        let route = routes
+            .select_route(&req, context)
+            .ok_or_else(|| ProxyError::NotFound("No matching route".to_string()))?;
+        let target = route.select_target(&req, context)?.target;
+        let templated = route.apply_path_params(target, &mut req)?;
+        Self::rewrite_backend_request(
+            req,
+            context,
+            templated.as_ref().unwrap_or(&target.url),
+            route.preserve_host.unwrap_or(self.preserve_host),
+            false,
+            route.strip_path_prefix.as_deref(),
+            templated.is_none(),
+        )
+    }
+
     pub async fn run(self, addr: SocketAddr) -> Result<(), ProxyError> {
-        let listener = tokio::net::TcpListener::bind(addr)
-            .await
-            .map_err(|e| ProxyError::Hyper(e.to_string()))?;
+        self.serve(addr, None).await
+    }
 
-        info!("Reverse proxy listening on: {}", addr);
+    pub async fn run_with_tls(self, addr: SocketAddr, mut tls_config: ServerConfig) -> Result<(), ProxyError> {
+        http_server::set_alpn(&mut tls_config, self.http2.as_ref());
+        self.serve(addr, Some(TlsAcceptor::from(Arc::new(tls_config)))).await
+    }
 
-        for (target_id, target_url, client, cfg, healthy) in self.routes.routes_with_health_checks() {
-            tokio::spawn(async move {
-                Self::health_check_loop(target_id, client, target_url, cfg, healthy).await;
-            });
+    async fn serve(self, addr: SocketAddr, tls_acceptor: Option<TlsAcceptor>) -> Result<(), ProxyError> {
+        let listener_config = self.listener_config.clone();
+        let listener = listener::bind(addr, &listener_config)
+            .map_err(|e| ProxyError::Hyper(e.to_string()))?;
+
+        if tls_acceptor.is_some() {
+            info!("Reverse proxy listening on: https://{}", addr);
+        } else {
+            info!("Reverse proxy listening on: {}", addr);
         }
 
+        self.routes.start(true, self.cluster.clone());
+
         let routes = self.routes.clone();
         let preserve_host = self.preserve_host;
         let websocket_config = Arc::new(self.websocket_config.clone());
         let metrics = self.metrics.clone();
         let rate_limiter = self.rate_limiter.clone();
+        let api_keys = self.api_keys.clone();
+        let http2 = self.http2.clone();
+        let early_data_policy = self.early_data_policy;
+        let client_cert_header = self.client_cert_header.clone();
+        self.spawn_api_key_refresh();
 
         loop {
             let (stream, remote_addr) = listener
                 .accept()
                 .await
                 .map_err(|e| ProxyError::Hyper(e.to_string()))?;
+            listener::tune_stream(&stream, &listener_config);
+            if rate_limiter.refuses(&remote_addr) {
+                continue;
+            }
 
             let routes = routes.clone();
             let metrics = metrics.clone();
             let websocket_cfg = websocket_config.clone();
             let rate_limiter = rate_limiter.clone();
+            let api_keys = api_keys.clone();
+            let http2 = http2.clone();
+            let tls_acceptor = tls_acceptor.clone();
+            let client_cert_header = client_cert_header.clone();
 
             tokio::spawn(async move {
                 let _connection = ConnectionTracker::new(metrics.clone());
-                let io = TokioIo::new(stream);
-
-                if let Err(err) = ServerBuilder::new()
-                    .serve_connection(
-                        io,
-                        service_fn(move |req| {
-                            let routes = routes.clone();
-                            let client_ip = Some(remote_addr.ip().to_string());
-                            let metrics = metrics.clone();
-                            let websocket_cfg = websocket_cfg.clone();
-                            let rate_limiter = rate_limiter.clone();
-
-                            let context = RequestContext {
-                                client_ip: client_ip.clone(),
-                            };
-
-                            async move {
-                                metrics.increment_requests();
-                                let timer = RequestTimer::with_metrics(metrics.clone());
-                                let result = Self::handle_request_static(
-                                    req,
-                                    context,
-                                    routes,
-                                    preserve_host,
-                                    websocket_cfg,
-                                    metrics.clone(),
-                                    rate_limiter.clone(),
-                                )
-                                .await;
-
-                                if let Some(len) = result
-                                    .as_ref()
-                                    .ok()
-                                    .and_then(|response| response.body().size_hint().exact())
-                                {
-                                    metrics.record_response_bytes(len as u64);
-                                }
-                                timer.finish();
-                                result
-                            }
-                        }),
-                    )
-                    .await
-                {
-                    error!("Error serving reverse proxy connection: {}", err);
+                let preserve_header_case = routes.current().preserves_header_case();
+                let service = move |client_identity: Option<ClientCertIdentity>| service_fn(move |mut req: Request<Incoming>| {
+                    if let Some(identity) = &client_identity {
+                        identity.apply(req.headers_mut());
+                    }
+                    let routes = routes.current();
+                    let metrics = metrics.clone();
+                    let websocket_cfg = websocket_cfg.clone();
+                    let rate_limiter = rate_limiter.clone();
+                    let api_keys = api_keys.clone();
+
+                    let context = RequestContext::from_peer(remote_addr.ip(), req.headers());
+                    let client_ip = context.client_ip.clone();
+
+                    async move {
+                        metrics.increment_requests();
+                        let timer = RequestTimer::with_metrics(metrics.clone());
+                        let result = Self::handle_request_static(
+                            req,
+                            context,
+                            routes,
+                            preserve_host,
+                            websocket_cfg,
+                            metrics.clone(),
+                            rate_limiter.clone(),
+                            api_keys,
+                        )
+                        .await;
+
+                        if let Some(len) = result
+                            .as_ref()
+                            .ok()
+                            .and_then(|response| response.body().size_hint().exact())
+                        {
+                            metrics.record_response_bytes(len);
+                        }
+                        if let (Some(client_ip), Ok(response)) = (&client_ip, &result) {
+                            rate_limiter.record_status(client_ip, response.status());
+                        }
+                        timer.finish();
+                        result
+                    }
+                });
+
+                let Some(acceptor) = tls_acceptor else {
+                    let protocol = ConnectionProtocol::plain(http2.as_ref());
+                    if let Err(err) = http_server::serve(stream, service(None), protocol, http2.as_ref(), preserve_header_case).await {
+                        error!("Error serving reverse proxy connection: {}", err);
+                    }
+                    return;
+                };
+                match accept_tls(&acceptor, stream, early_data_policy).await {
+                    Ok(tls_stream) => {
+                        let client_identity = client_cert_header
+                            .as_ref()
+                            .map(|header| header.identity(tls_stream.peer_certificates()));
+                        let protocol = ConnectionProtocol::negotiated(tls_stream.alpn_protocol());
+                        if let Err(err) = http_server::serve(tls_stream, service(client_identity), protocol, http2.as_ref(), preserve_header_case).await {
+                            error!("Error serving reverse proxy TLS connection: {}", err);
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Error establishing TLS connection from {}: {}", privacy::peer(&remote_addr), e);
+                        SecurityEvent::new(SecurityEventKind::TlsHandshakeFailed, e.to_string())
+                            .client_ip(Some(&remote_addr.ip().to_string()))
+                            .emit();
+                    }
                 }
             });
         }
@@ -1360,45 +2531,225 @@ impl ReverseProxy {
 
     /// Static method to handle requests (used in service_fn)
     async fn handle_request_static(
-        req: Request<Incoming>,
+        mut req: Request<Incoming>,
         context: RequestContext,
         routes: Arc<RouteMatcher>,
         preserve_host: bool,
         websocket_config: Arc<WebSocketConfig>,
         metrics: Arc<PerformanceMetrics>,
         rate_limiter: Arc<RateLimiter>,
-    ) -> Result<Response<Full<Bytes>>, Infallible> {
+        api_keys: Arc<ApiKeyStore>,
+    ) -> Result<Response<ResponseBody>, Infallible> {
+        // A client behind a trusted proxy shares its connections, so its ban applies per request
+        if let Some(client_ip) = context.client_ip.as_deref()
+            && context.peer_ip.is_some_and(|peer| peer.to_string() != client_ip)
+            && rate_limiter.refuses_client(client_ip)
+        {
+            return Ok(ResponseBuilder::error(StatusCode::FORBIDDEN, "Forbidden").map(ResponseBody::from));
+        }
+
+        if path_normalization::normalize_request(&mut req).is_err() {
+            return Ok(ResponseBuilder::error(StatusCode::BAD_REQUEST, "Bad Request").map(ResponseBody::from));
+        }
+
+        if let Some(tarpit) = &routes.tarpit
+            && let Some(response) = tarpit.check(req.uri().path(), context.client_ip.as_deref()).await
+        {
+            return Ok(response.map(ResponseBody::from));
+        }
+
+        let graphql_limit = graphql::body_limit(req.method(), req.uri().path(), req.headers());
+        let json_limit = routes
+            .json_body_limit
+            .filter(|&limit| json_body::wants_body(req.headers(), limit));
+        if let Some(limit) = json_limit.max(graphql_limit)
+            && upgrade_protocol(req.headers()).is_none()
+        {
+            // Read through a reference: the drained body stays behind as the request's placeholder
+            match collect_body(Limited::new(req.body_mut(), limit), None).await {
+                Ok(raw) => JsonBody::cache(req.extensions_mut(), raw),
+                Err(e) => {
+                    return Ok(ResponseBuilder::error(
+                        StatusCode::BAD_REQUEST,
+                        &format!("Failed to read request body: {}", e),
+                    ).map(ResponseBody::from));
+                }
+            }
+            if graphql_limit.is_some() {
+                graphql::annotate(req.extensions_mut());
+            }
+        }
+
+        let mut rate_limit_info = None;
         if rate_limiter.is_enabled() {
             if let Some(client_ip) = context.client_ip.as_deref() {
-                if let Err(hit) = rate_limiter
-                    .check_request(
+                match rate_limiter
+                    .check_operation(
                         client_ip,
                         req.method(),
                         req.uri()
                             .path_and_query()
                             .map(|pq| pq.as_str())
                             .unwrap_or("/"),
+                        req.headers(),
+                        req.extensions().get::<graphql::Operation>(),
                     )
                     .await
                 {
-                    warn!(
-                        "Reverse proxy rate limit hit for {} via rule {}",
-                        client_ip, hit.rule_id
-                    );
-                    return Ok(ResponseBuilder::too_many_requests(
-                        &hit.rule_id,
-                        hit.retry_after_secs,
-                    ));
+                    Ok(info) => rate_limit_info = info,
+                    Err(hit) => {
+                        warn!(
+                            "Reverse proxy rate limit hit for {} via rule {}",
+                            privacy::client_ip(client_ip), hit.rule_id
+                        );
+                        return Ok(ResponseBuilder::rate_limited(&hit).map(ResponseBody::from));
+                    }
                 }
             }
         }
 
         let selected_route = match routes.select_route(&req, &context) {
             Some(route) => route,
-            None => return Ok(ResponseBuilder::error(StatusCode::NOT_FOUND, "No matching route")),
+            None => match routes.fallback_route() {
+                Some(route) => {
+                    router_telemetry().no_match.with_label_values(&["fallback_route"]).inc();
+                    route
+                }
+                None => {
+                    router_telemetry().no_match.with_label_values(&["not_found"]).inc();
+                    return Ok(routes.fallback.response().map(ResponseBody::from));
+                }
+            },
+        };
+
+        let started = std::time::Instant::now();
+        let client_ip = context.client_ip.clone();
+        let method = req.method().clone();
+        let path = req.uri().path().to_string();
+        let version = req.version();
+        let operation = req.extensions().get::<graphql::Operation>().cloned();
+        let admitted = match &selected_route.tenant {
+            Some(tenant) => {
+                let target = req.uri().path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+                tenant.admit(client_ip.as_deref(), &method, target, req.headers(), operation.as_ref()).await
+            }
+            None => Ok(()),
+        };
+        let mut response = match admitted {
+            Ok(()) => {
+                let Ok(response) = Self::handle_routed_request(
+                    req,
+                    context,
+                    selected_route,
+                    preserve_host,
+                    websocket_config,
+                    metrics,
+                    api_keys,
+                )
+                .await;
+                response
+            }
+            Err(hit) => {
+                warn!(
+                    "Tenant rate limit hit for {} on route {} via rule {}",
+                    privacy::client_ip(client_ip.as_deref().unwrap_or("unknown")),
+                    selected_route.id,
+                    hit.rule_id
+                );
+                ResponseBuilder::rate_limited(&hit).map(ResponseBody::from)
+            }
+        };
+        let summary = RequestSummary {
+            client_ip,
+            method,
+            path,
+            version,
+            elapsed: started.elapsed(),
+            upstream_time: UpstreamTime::of(&response),
         };
+        let status = response.status();
+        selected_route.tags.finish(&selected_route.id, &summary, response.headers_mut(), status);
+        if let Some(tenant) = &selected_route.tenant {
+            tenant.record(status);
+        }
+        if let Some(operation) = &operation {
+            graphql::record(&selected_route.id, operation, status, summary.elapsed);
+        }
+        if let Some(info) = &rate_limit_info {
+            info.write_headers(response.headers_mut());
+        }
+        Ok(response)
+    }
+
+    /// Forwards a request once its route is known: authentication, upgrades, body limits and
+    /// the backend exchange itself.
+    async fn handle_routed_request(
+        mut req: Request<Incoming>,
+        context: RequestContext,
+        selected_route: &CompiledRoute,
+        preserve_host: bool,
+        websocket_config: Arc<WebSocketConfig>,
+        metrics: Arc<PerformanceMetrics>,
+        api_keys: Arc<ApiKeyStore>,
+    ) -> Result<Response<ResponseBody>, Infallible> {
+        let preserve_host = selected_route.preserve_host.unwrap_or(preserve_host);
+        if let Some(response) = selected_route.method_policy.check(&mut req) {
+            return Ok(response.map(ResponseBody::from));
+        }
+        if selected_route.require_api_key {
+            let checked = api_keys.check(&req, &selected_route.id).await.and_then(|key_id| {
+                match &selected_route.tenant {
+                    // Keys of other tenants are valid, just not here
+                    Some(tenant) if !tenant.allows_key(&key_id) => Err(ApiKeyRejection::RouteNotAllowed { key_id }),
+                    _ => Ok(key_id),
+                }
+            });
+            match checked {
+                Ok(key_id) => {
+                    debug!("Route {} accepted API key {}", selected_route.id, key_id);
+                    // Keys are for the proxy only; never pass them on to the backend
+                    req.headers_mut().remove(api_keys.header_name());
+                }
+                Err(rejection) => {
+                    warn!(
+                        "Route {} rejected request from {}: {:?}",
+                        selected_route.id,
+                        privacy::client_ip(context.client_ip.as_deref().unwrap_or("unknown")),
+                        rejection
+                    );
+                    let kind = match rejection {
+                        ApiKeyRejection::RateLimited { .. } => SecurityEventKind::RateLimited,
+                        _ => SecurityEventKind::ApiKeyRejected,
+                    };
+                    SecurityEvent::new(kind, format!("{:?}", rejection))
+                        .client_ip(context.client_ip.as_deref())
+                        .target(req.uri().path())
+                        .route(&selected_route.id)
+                        .emit();
+                    return Ok(rejection.into_response(api_keys.header_name()).map(ResponseBody::from));
+                }
+            }
+        }
+
+        if let Some(responder) = &selected_route.static_responder {
+            let params = selected_route.path_params(&req);
+            return Ok(responder.respond(&ResponseVars {
+                request: &req,
+                params: &params,
+                client_ip: context.client_ip.as_deref(),
+            }).map(ResponseBody::from));
+        }
 
-        if is_websocket_upgrade(req.headers()) {
+        let upgrade = upgrade_protocol(req.headers())
+            .filter(|protocol| selected_route.tunnels_upgrade(protocol));
+        if let Some(protocol) = upgrade {
+            // Upgrade requests carry no body, so only their headers are signed
+            if let Some(verifier) = &selected_route.hmac_verifier
+                && let Err(e) =
+                    verifier.verify(&selected_route.id, req.method(), req.uri(), req.headers(), &[])
+            {
+                return Ok(Self::signature_rejected(selected_route, &context, e).map(ResponseBody::from));
+            }
             let TargetSelection { target, set_cookie } =
                 match selected_route.select_target(&req, &context) {
                     Ok(selection) => selection,
@@ -1410,18 +2761,32 @@ impl ReverseProxy {
                         return Ok(ResponseBuilder::error(
                             StatusCode::SERVICE_UNAVAILABLE,
                             &e.to_string(),
-                        ));
+                        ).map(ResponseBody::from));
                     }
                 };
-            let mut response = match Self::handle_websocket_request(
-                req,
-                context,
-                selected_route,
-                target,
-                preserve_host,
-                websocket_config,
-            )
-            .await {
+            let result = if protocol == "websocket" {
+                Self::handle_websocket_request(
+                    req,
+                    context,
+                    selected_route,
+                    target,
+                    preserve_host,
+                    websocket_config,
+                )
+                .await
+            } else {
+                Self::handle_upgrade_request(
+                    req,
+                    context,
+                    selected_route,
+                    target,
+                    preserve_host,
+                    &protocol,
+                    None,
+                )
+                .await
+            };
+            let mut response = match result {
                 Ok(response) => response,
                 Err(e) => match e {},
             };
@@ -1433,27 +2798,151 @@ impl ReverseProxy {
             return Ok(response);
         }
 
-        match Self::process_request_with_retries(req, context, selected_route, preserve_host).await {
-            Ok((mut response, set_cookie)) => {
-                if let Some(cookie) = set_cookie {
-                    if let Ok(value) = cookie.parse() {
-                        response.headers_mut().append("Set-Cookie", value);
+        let replay_key = selected_route.replay.as_ref().and_then(|replay| replay.key(&req));
+        if let (Some(replay), Some(key)) = (&selected_route.replay, &replay_key)
+            && replay.mode() == ReplayMode::Replay
+        {
+            return Ok(match replay.load(key).await {
+                Some(recorded) => recorded,
+                None => ResponseBuilder::error(StatusCode::NOT_FOUND, "No recording for this request"),
+            }.map(ResponseBody::from));
+        }
+
+        let limits = &selected_route.limits;
+        let content_length = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if let (Some(max), Some(length)) = (limits.max_body_bytes, content_length)
+            && length > max as u64
+        {
+            return Ok(ResponseBuilder::error(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large").map(ResponseBody::from));
+        }
+        // A streamed body without a declared length is read up front so the limit can be enforced
+        let route_buffer_limit = match (limits.request_body, limits.max_body_bytes) {
+            (BodyMode::Buffer, max) => Some(max.unwrap_or(usize::MAX)),
+            (BodyMode::Stream, Some(max)) if content_length.is_none() && !req.body().is_end_stream() => Some(max),
+            (BodyMode::Stream, _) => None,
+        };
+        let body_limit = [
+            selected_route.hmac_verifier.as_ref().map(HmacVerifier::max_body_bytes),
+            selected_route.fan_out.as_ref().map(FanOut::max_body_bytes),
+            selected_route.aggregator.as_ref().map(Aggregator::max_body_bytes),
+            route_buffer_limit,
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let idle_timeout = limits.idle_timeout_secs.map(Duration::from_secs);
+        // A body already read for routing can only be forwarded from memory
+        let prefetched = req.extensions_mut().remove::<JsonBody>().map(|body| body.raw);
+
+        let result = match body_limit.or(prefetched.as_ref().map(|_| usize::MAX)) {
+            Some(limit) => {
+                let (parts, body) = req.into_parts();
+                let collected = match prefetched {
+                    Some(raw) if raw.len() > limit => {
+                        return Ok(ResponseBuilder::error(
+                            StatusCode::PAYLOAD_TOO_LARGE,
+                            "Request body too large",
+                        ).map(ResponseBody::from));
+                    }
+                    Some(raw) => Ok(raw),
+                    None => collect_body(Limited::new(body, limit), idle_timeout).await,
+                };
+                let body = match collected {
+                    Ok(collected) => collected,
+                    Err(e) if e.downcast_ref::<LengthLimitError>().is_some() => {
+                        return Ok(ResponseBuilder::error(
+                            StatusCode::PAYLOAD_TOO_LARGE,
+                            "Request body too large",
+                        ).map(ResponseBody::from));
+                    }
+                    Err(e) if e.downcast_ref::<BodyIdleTimeout>().is_some() => {
+                        return Ok(ResponseBuilder::error(StatusCode::REQUEST_TIMEOUT, &e.to_string()).map(ResponseBody::from));
+                    }
+                    Err(e) => {
+                        return Ok(ResponseBuilder::error(
+                            StatusCode::BAD_REQUEST,
+                            &format!("Failed to read request body: {}", e),
+                        ).map(ResponseBody::from));
                     }
+                };
+                if let Some(verifier) = &selected_route.hmac_verifier
+                    && let Err(e) = verifier.verify(
+                        &selected_route.id,
+                        &parts.method,
+                        &parts.uri,
+                        &parts.headers,
+                        &body,
+                    )
+                {
+                    return Ok(Self::signature_rejected(selected_route, &context, e).map(ResponseBody::from));
                 }
-                Ok(response)
+                if let Some(fan_out) = &selected_route.fan_out
+                    && fan_out.sample()
+                {
+                    Self::dispatch_fan_out(fan_out, &parts, &body, &context, selected_route, preserve_host);
+                }
+                if let Some(aggregator) = &selected_route.aggregator {
+                    return Ok(aggregator
+                        .gather(|branch| {
+                            Self::send_aggregate_branch(&parts, &body, &context, selected_route, &branch.url)
+                        })
+                        .await.map(ResponseBody::from));
+                }
+                Self::within_response_timeout(
+                    selected_route,
+                    Self::process_buffered_with_retries(parts, body, context, selected_route, preserve_host),
+                )
+                .await
             }
-            Err(RequestFailure::Selection(e)) => {
-                warn!("Target selection failed for route {}: {}", selected_route.id, e);
-                Ok(ResponseBuilder::error(
+            None => {
+                Self::within_response_timeout(
+                    selected_route,
+                    Self::process_request_with_retries(req, context, selected_route, preserve_host),
+                )
+                .await
+            }
+        };
+        let result = match (&selected_route.replay, &replay_key) {
+            (Some(replay), Some(key)) => {
+                Self::record_or_replay(replay, key, buffer_limit(&selected_route.limits), result).await
+            }
+            _ => result,
+        };
+
+        match result {
+            Ok((mut response, set_cookie)) => {
+                if let Some(cookie) = set_cookie {
+                    if let Ok(value) = cookie.parse() {
+                        response.headers_mut().append("Set-Cookie", value);
+                    }
+                }
+                Ok(response)
+            }
+            Err(RequestFailure::Selection(e)) => {
+                warn!("Target selection failed for route {}: {}", selected_route.id, e);
+                Ok(ResponseBuilder::error(
                     StatusCode::SERVICE_UNAVAILABLE,
                     &e.to_string(),
-                ))
+                ).map(ResponseBody::from))
+            }
+            Err(RequestFailure::Timeout(limit)) => {
+                warn!("Route {} backend did not respond within {:?}", selected_route.id, limit);
+                metrics.increment_connection_errors();
+                Ok(ResponseBuilder::error(StatusCode::GATEWAY_TIMEOUT, "Backend response timed out").map(ResponseBody::from))
             }
             Err(RequestFailure::Forward(e)) => {
                 error!("Proxy error: {}", e);
-                let body = Full::new(Bytes::from(format!("Proxy Error: {}", e)));
+                let status = match e.upstream_kind() {
+                    Some(UpstreamErrorKind::ConnectTimeout | UpstreamErrorKind::Timeout) => StatusCode::GATEWAY_TIMEOUT,
+                    _ => StatusCode::BAD_GATEWAY,
+                };
+                let body = ResponseBody::from(Full::new(Bytes::from(format!("Proxy Error: {}", e))));
                 let error_response = Response::builder()
-                    .status(StatusCode::BAD_GATEWAY)
+                    .status(status)
                     .body(body)
                     .unwrap();
                 metrics.increment_connection_errors();
@@ -1462,59 +2951,279 @@ impl ReverseProxy {
         }
     }
 
+    /// Records a usable backend response, or answers from the recording while the backend is
+    /// unavailable (transport errors, timeouts and the configured statuses).
+    /// A response longer than `max_buffer_bytes` is passed on without being recorded.
+    async fn record_or_replay(
+        replay: &ReplayStore,
+        key: &ReplayKey,
+        max_buffer: usize,
+        result: Result<(Response<ResponseBody>, Option<String>), RequestFailure>,
+    ) -> Result<(Response<ResponseBody>, Option<String>), RequestFailure> {
+        let result = match result {
+            Ok((response, set_cookie)) if !replay.is_unavailable(response.status()) => {
+                let (parts, body) = response.into_parts();
+                match buffer_up_to(body, max_buffer, None).await {
+                    Ok(Buffered::Complete(bytes)) => {
+                        let response = Response::from_parts(parts, Full::new(bytes));
+                        replay.save(key, &response).await;
+                        return Ok((response.map(ResponseBody::from), set_cookie));
+                    }
+                    Ok(partial) => {
+                        debug!("Response exceeds max_buffer_bytes, passing it on without recording it");
+                        return Ok((Response::from_parts(parts, ResponseBody::from(partial.into_stream())), set_cookie));
+                    }
+                    Err(e) => Err(RequestFailure::Forward(ProxyError::Http(format!(
+                        "Failed to collect response body: {}",
+                        e
+                    )))),
+                }
+            }
+            result => result,
+        };
+        match replay.load(key).await {
+            Some(recorded) => Ok((recorded.map(ResponseBody::from), None)),
+            None => result,
+        }
+    }
+
+    /// Applies the route's `response_timeout_secs` to a forwarding attempt, retries included.
+    async fn within_response_timeout<F>(
+        route: &CompiledRoute,
+        forward: F,
+    ) -> Result<(Response<ResponseBody>, Option<String>), RequestFailure>
+    where
+        F: std::future::Future<Output = Result<(Response<ResponseBody>, Option<String>), RequestFailure>>,
+    {
+        let Some(limit) = route.limits.response_timeout_secs.map(Duration::from_secs) else {
+            return forward.await;
+        };
+        tokio::time::timeout(limit, forward)
+            .await
+            .unwrap_or(Err(RequestFailure::Timeout(limit)))
+    }
+
+    /// Sends copies of a buffered request to the route's fan-out targets without waiting for them.
+    fn dispatch_fan_out(
+        fan_out: &FanOut,
+        parts: &hyper::http::request::Parts,
+        body: &Bytes,
+        context: &RequestContext,
+        route: &CompiledRoute,
+        preserve_host: bool,
+    ) {
+        let deliveries = fan_out
+            .targets()
+            .iter()
+            .map(|target| {
+                let copy = Request::from_parts(parts.clone(), Full::new(body.clone()));
+                let prepared = Self::rewrite_backend_request(
+                    copy,
+                    context,
+                    target,
+                    preserve_host,
+                    false,
+                    route.strip_path_prefix.as_deref(),
+                    true,
+                )
+                .map(|request| Self::box_infallible_request(route.apply_header_case(request)));
+                let client = route.http_client.clone();
+                let delivery = async move {
+                    let response = Self::send_pooled(&client, prepared?)
+                        .await
+                        .map_err(|e| ProxyError::Connection(format!("Failed to deliver copy: {}", e)))?;
+                    Ok(response.status())
+                };
+                (target.to_string(), delivery)
+            })
+            .collect();
+        fan_out.dispatch(&route.id, deliveries);
+    }
+
+    /// Sends the buffered request to one aggregate branch URL, keeping the caller's method,
+    /// headers and query string.
+    async fn send_aggregate_branch(
+        parts: &hyper::http::request::Parts,
+        body: &Bytes,
+        context: &RequestContext,
+        route: &CompiledRoute,
+        branch_url: &Url,
+    ) -> Result<(StatusCode, Bytes), ProxyError> {
+        let mut url = branch_url.clone();
+        if let Some(query) = parts.uri.query() {
+            let merged = match url.query() {
+                Some(existing) => format!("{}&{}", existing, query),
+                None => query.to_string(),
+            };
+            url.set_query(Some(&merged));
+        }
+        let uri: Uri = url
+            .as_str()
+            .parse()
+            .map_err(|e: hyper::http::uri::InvalidUri| ProxyError::Uri(e.to_string()))?;
+
+        let mut request = Request::from_parts(parts.clone(), Full::new(body.clone()));
+        let original_host = request.headers().get(HOST).cloned();
+        *request.uri_mut() = uri.clone();
+        let headers = request.headers_mut();
+        if let Some(host) = uri.authority().and_then(|authority| HeaderValue::from_str(authority.as_str()).ok()) {
+            headers.insert(HOST, host);
+        }
+        Self::set_forwarded_for(headers, context);
+        if let Some(host) = original_host {
+            headers.insert(X_FORWARDED_HOST.clone(), host);
+        }
+        Self::strip_request_headers(headers, false);
+
+        let response = Self::send_pooled(&route.http_client, Self::box_infallible_request(route.apply_header_case(request)))
+            .await
+            .map_err(|e| ProxyError::Connection(format!("Failed to call aggregate branch: {}", e)))?;
+        let status = response.status();
+        let body = Limited::new(response.into_body(), buffer_limit(&route.limits))
+            .collect()
+            .await
+            .map_err(|e| ProxyError::Http(format!("Failed to read aggregate branch body: {}", e)))?
+            .to_bytes();
+        Ok((status, body))
+    }
+
+    fn signature_rejected(
+        route: &CompiledRoute,
+        context: &RequestContext,
+        error: SignatureError,
+    ) -> Response<Full<Bytes>> {
+        warn!(
+            "Route {} rejected signed request from {}: {}",
+            route.id,
+            privacy::client_ip(context.client_ip.as_deref().unwrap_or("unknown")),
+            error
+        );
+        SecurityEvent::new(SecurityEventKind::SignatureRejected, error.to_string())
+            .client_ip(context.client_ip.as_deref())
+            .route(&route.id)
+            .emit();
+        ResponseBuilder::error(StatusCode::UNAUTHORIZED, "Invalid request signature")
+    }
+
     /// Process request using HTTP client with connection pooling
     async fn process_request_static(
-        req: Request<Incoming>,
+        mut req: Request<BoxedBody>,
         context: RequestContext,
         selected_route: &CompiledRoute,
         selected_target: &CompiledTarget,
         preserve_host: bool,
-    ) -> Result<Response<Full<Bytes>>, ProxyError> {
-        let _inflight = InflightGuard::new(selected_target.inflight.clone());
+    ) -> Result<Response<ResponseBody>, ProxyError> {
+        let inflight = InflightGuard::new(selected_target.inflight.clone());
+        let request = GaugeGuard::new(selected_target.requests.clone());
+        let templated = selected_route.apply_path_params(selected_target, &mut req)?;
         let prepared = Self::rewrite_backend_request(
             req,
             &context,
-            &selected_target.url,
+            templated.as_ref().unwrap_or(&selected_target.url),
             preserve_host,
             false,
             selected_route.strip_path_prefix.as_deref(),
+            templated.is_none(),
         )?;
-
-        let prepared = Self::box_incoming_request(prepared);
-        let response = selected_route
-            .http_client
-            .request(prepared)
-            .await
-            .map_err(|e| ProxyError::Connection(format!("Failed to forward request: {}", e)))?;
-
-        Self::finalize_backend_response(response, false).await
+        let prepared = selected_route.apply_header_case(prepared);
+
+        // A request without a body can be replayed, so it gets the same stale connection handling
+        // as a buffered one
+        let started = std::time::Instant::now();
+        let response = if prepared.body().is_end_stream() {
+            let (parts, _) = prepared.into_parts();
+            Self::send_buffered(selected_route, Request::from_parts(parts, Full::new(Bytes::new()))).await
+        } else {
+            Self::send_pooled(&selected_route.http_client, prepared)
+                .await
+                .map_err(|e| upstream_error("reverse", &selected_route.id, &e))
+        };
+        Self::record_outcome(selected_route, selected_target, &response, started);
+        let upstream_time = UpstreamTime(started.elapsed());
+        let response = response?;
+
+        let mut response =
+            Self::finalize_backend_response(response, false, &selected_route.limits, (inflight, request)).await?;
+        response.extensions_mut().insert(upstream_time);
+        Ok(response)
     }
 
     async fn process_buffered_request(
-        req: Request<Full<Bytes>>,
+        mut req: Request<Full<Bytes>>,
         context: RequestContext,
         selected_route: &CompiledRoute,
         selected_target: &CompiledTarget,
         preserve_host: bool,
-    ) -> Result<Response<Full<Bytes>>, ProxyError> {
-        let _inflight = InflightGuard::new(selected_target.inflight.clone());
+    ) -> Result<Response<ResponseBody>, ProxyError> {
+        let inflight = InflightGuard::new(selected_target.inflight.clone());
+        let request = GaugeGuard::new(selected_target.requests.clone());
+        let templated = selected_route.apply_path_params(selected_target, &mut req)?;
         let prepared = Self::rewrite_backend_request(
             req,
             &context,
-            &selected_target.url,
+            templated.as_ref().unwrap_or(&selected_target.url),
             preserve_host,
             false,
             selected_route.strip_path_prefix.as_deref(),
+            templated.is_none(),
         )?;
+        let prepared = selected_route.apply_header_case(prepared);
+
+        let started = std::time::Instant::now();
+        let response = Self::send_buffered(selected_route, prepared).await;
+        Self::record_outcome(selected_route, selected_target, &response, started);
+        let upstream_time = UpstreamTime(started.elapsed());
+        let response = response?;
+
+        let mut response =
+            Self::finalize_backend_response(response, false, &selected_route.limits, (inflight, request)).await?;
+        response.extensions_mut().insert(upstream_time);
+        Ok(response)
+    }
 
-        let prepared = Self::box_infallible_request(prepared);
-        let response = selected_route
-            .http_client
-            .request(prepared)
-            .await
-            .map_err(|e| ProxyError::Connection(format!("Failed to forward request: {}", e)))?;
+    /// Feeds the target's outlier statistics; transport errors and 5xx responses count as failures.
+    fn record_outcome(
+        route: &CompiledRoute,
+        target: &CompiledTarget,
+        response: &Result<Response<Incoming>, ProxyError>,
+        started: std::time::Instant,
+    ) {
+        let success = response.as_ref().is_ok_and(|r| !r.status().is_server_error());
+        target.outlier.record(success, started.elapsed(), &route.outlier_detection);
+    }
+
+    /// Sends a replayable request through the route's pool. An idempotent request that a stale
+    /// pooled connection lost before any response arrived is sent once more on a new connection.
+    async fn send_buffered(
+        route: &CompiledRoute,
+        request: Request<Full<Bytes>>,
+    ) -> Result<Response<Incoming>, ProxyError> {
+        let (parts, body) = request.into_parts();
+        let first = Request::from_parts(parts.clone(), body.clone());
+        let error = match Self::send_pooled(&route.http_client, Self::box_infallible_request(first)).await {
+            Ok(response) => return Ok(response),
+            Err(e) => e,
+        };
 
-        Self::finalize_backend_response(response, false).await
+        let fresh_client = match &route.fresh_client {
+            Some(client) if is_idempotent(&parts.method) && is_stale_connection_error(&error) => client,
+            _ => return Err(upstream_error("reverse", &route.id, &error)),
+        };
+        debug!(
+            "Route {}: pooled connection failed before a response ({}), retrying {} {} on a new connection",
+            route.id, error, parts.method, parts.uri
+        );
+        let retry = Request::from_parts(parts, body);
+        match fresh_client.request(Self::box_infallible_request(retry)).await {
+            Ok(response) => {
+                record_retry(&route.id, "success");
+                Ok(response)
+            }
+            Err(e) => {
+                record_retry(&route.id, "failure");
+                Err(upstream_error("reverse", &route.id, &e))
+            }
+        }
     }
 
     async fn process_request_with_retries(
@@ -1522,37 +3231,75 @@ impl ReverseProxy {
         context: RequestContext,
         selected_route: &CompiledRoute,
         preserve_host: bool,
-    ) -> Result<(Response<Full<Bytes>>, Option<String>), RequestFailure> {
+    ) -> Result<(Response<ResponseBody>, Option<String>), RequestFailure> {
         let retry_policy = selected_route.retry_policy.as_ref();
 
         if retry_policy
             .map(|policy| policy.max_attempts <= 1 || !policy.allows_method(req.method()))
             .unwrap_or(true)
         {
-            let TargetSelection { target, set_cookie } =
-                selected_route.select_target(&req, &context).map_err(RequestFailure::Selection)?;
-            let response =
-                Self::process_request_static(req, context, selected_route, target, preserve_host)
-                    .await
-                    .map_err(RequestFailure::Forward)?;
-            return Ok((response, set_cookie));
+            return Self::process_request_once(Self::box_incoming_request(req), context, selected_route, preserve_host)
+                .await;
         }
 
-        let retry_policy = retry_policy.unwrap();
+        // Retries need the body again, so it is read first; one too large for that is sent once
         let (parts, body) = req.into_parts();
-        let body_bytes = body.collect().await.map_err(|e| {
-            RequestFailure::Forward(ProxyError::Http(format!(
-                "Failed to collect request body: {}",
-                e
-            )))
-        })?;
-        let body_bytes = body_bytes.to_bytes();
+        let idle_timeout = selected_route.limits.idle_timeout_secs.map(Duration::from_secs);
+        let buffered = buffer_up_to(body, buffer_limit(&selected_route.limits), idle_timeout)
+            .await
+            .map_err(|e| {
+                RequestFailure::Forward(ProxyError::Http(format!(
+                    "Failed to collect request body: {}",
+                    e
+                )))
+            })?;
+        match buffered {
+            Buffered::Complete(body_bytes) => {
+                Self::process_buffered_with_retries(parts, body_bytes, context, selected_route, preserve_host)
+                    .await
+            }
+            partial => {
+                debug!("Route {}: request body exceeds max_buffer_bytes, sending it without retries", selected_route.id);
+                let body = partial.into_stream().boxed();
+                Self::process_request_once(Request::from_parts(parts, body), context, selected_route, preserve_host)
+                    .await
+            }
+        }
+    }
+
+    async fn process_request_once(
+        req: Request<BoxedBody>,
+        context: RequestContext,
+        selected_route: &CompiledRoute,
+        preserve_host: bool,
+    ) -> Result<(Response<ResponseBody>, Option<String>), RequestFailure> {
+        let TargetSelection { target, set_cookie } =
+            selected_route.select_target(&req, &context).map_err(RequestFailure::Selection)?;
+        let response = Self::process_request_static(req, context, selected_route, target, preserve_host)
+            .await
+            .map_err(RequestFailure::Forward)?;
+        Ok((response, set_cookie))
+    }
+
+    /// Forwards an already buffered request, retrying when the route policy allows the method.
+    async fn process_buffered_with_retries(
+        parts: hyper::http::request::Parts,
+        body_bytes: Bytes,
+        context: RequestContext,
+        selected_route: &CompiledRoute,
+        preserve_host: bool,
+    ) -> Result<(Response<ResponseBody>, Option<String>), RequestFailure> {
+        let retry_policy = selected_route
+            .retry_policy
+            .as_ref()
+            .filter(|policy| policy.allows_method(&parts.method));
+        let max_attempts = retry_policy.map(|policy| policy.max_attempts.max(1)).unwrap_or(1);
 
         let mut excluded = HashSet::new();
         let mut last_error: Option<ProxyError> = None;
-        let mut last_response: Option<(Response<Full<Bytes>>, Option<String>)> = None;
+        let mut last_response: Option<(Response<ResponseBody>, Option<String>)> = None;
 
-        for attempt in 0..retry_policy.max_attempts {
+        for attempt in 0..max_attempts {
             let attempt_request =
                 Request::from_parts(parts.clone(), Full::new(body_bytes.clone()));
             let selection = match selected_route.select_target_with_exclusions(
@@ -1586,8 +3333,8 @@ impl ReverseProxy {
             .await
             {
                 Ok(response) => {
-                    if retry_policy.should_retry_status(response.status())
-                        && attempt + 1 < retry_policy.max_attempts
+                    if retry_policy.is_some_and(|policy| policy.should_retry_status(response.status()))
+                        && attempt + 1 < max_attempts
                     {
                         last_response = Some((response, set_cookie_clone));
                         continue;
@@ -1595,8 +3342,8 @@ impl ReverseProxy {
                     return Ok((response, set_cookie));
                 }
                 Err(err) => {
-                    if retry_policy.should_retry_error(&err)
-                        && attempt + 1 < retry_policy.max_attempts
+                    if retry_policy.is_some_and(|policy| policy.should_retry_error(&err))
+                        && attempt + 1 < max_attempts
                     {
                         last_error = Some(err);
                         continue;
@@ -1624,13 +3371,53 @@ impl ReverseProxy {
         selected_target: &CompiledTarget,
         preserve_host: bool,
         websocket_config: Arc<WebSocketConfig>,
-    ) -> Result<Response<Full<Bytes>>, Infallible> {
+    ) -> Result<Response<ResponseBody>, Infallible> {
         if let Err(reason) = Self::validate_websocket_headers(req.headers(), &websocket_config) {
-            return Ok(ResponseBuilder::error(StatusCode::FORBIDDEN, &reason));
+            return Ok(ResponseBuilder::error(StatusCode::FORBIDDEN, &reason).map(ResponseBody::from));
         }
+        filter_websocket_extensions(req.headers_mut(), &websocket_config);
+
+        let websocket = WebSocketTunnel {
+            active: websocket_telemetry()
+                .active
+                .with_label_values(&[selected_route.id.as_str(), selected_target.id.as_str()]),
+            drain: Duration::from_secs(websocket_config.shutdown_drain_seconds),
+        };
+        Self::handle_upgrade_request(
+            req,
+            context,
+            selected_route,
+            selected_target,
+            preserve_host,
+            "WebSocket",
+            Some(websocket),
+        )
+        .await
+    }
 
-        let target_url = selected_target.url.clone();
+    /// Forwards an upgrade request and, once the backend switches protocols, tunnels the raw
+    /// connection in both directions.
+    async fn handle_upgrade_request(
+        mut req: Request<Incoming>,
+        context: RequestContext,
+        selected_route: &CompiledRoute,
+        selected_target: &CompiledTarget,
+        preserve_host: bool,
+        protocol: &str,
+        websocket: Option<WebSocketTunnel>,
+    ) -> Result<Response<ResponseBody>, Infallible> {
         let http_client = selected_route.http_client.clone();
+        let templated = match selected_route.apply_path_params(selected_target, &mut req) {
+            Ok(templated) => templated,
+            Err(e) => {
+                error!("{} target template failed: {}", protocol, e);
+                return Ok(ResponseBuilder::error(
+                    StatusCode::BAD_GATEWAY,
+                    &format!("Invalid {} request", protocol),
+                ).map(ResponseBody::from));
+            }
+        };
+        let target_url = templated.clone().unwrap_or_else(|| selected_target.url.clone());
 
         let client_upgrade = hyper::upgrade::on(&mut req);
         let prepared_request =
@@ -1641,14 +3428,15 @@ impl ReverseProxy {
                 preserve_host,
                 true,
                 selected_route.strip_path_prefix.as_deref(),
+                templated.is_none(),
             ) {
-                Ok(request) => request,
+                Ok(request) => selected_route.apply_header_case(request),
                 Err(e) => {
-                    error!("WebSocket request rewrite failed: {}", e);
+                    error!("{} request rewrite failed: {}", protocol, e);
                     return Ok(ResponseBuilder::error(
                         StatusCode::BAD_GATEWAY,
-                        "Invalid WebSocket request",
-                    ));
+                        &format!("Invalid {} request", protocol),
+                    ).map(ResponseBody::from));
                 }
             };
         let prepared_request = Self::box_incoming_request(prepared_request);
@@ -1656,44 +3444,59 @@ impl ReverseProxy {
         let mut backend_response = match http_client.request(prepared_request).await {
             Ok(resp) => resp,
             Err(e) => {
-                error!("WebSocket backend request failed: {}", e);
+                error!("{} backend request failed: {}", protocol, e);
                 return Ok(ResponseBuilder::error(
                     StatusCode::BAD_GATEWAY,
-                    "WebSocket backend error",
-                ));
+                    &format!("{} backend error", protocol),
+                ).map(ResponseBody::from));
             }
         };
 
         if backend_response.status() != StatusCode::SWITCHING_PROTOCOLS {
-            return match Self::finalize_backend_response(backend_response, false).await {
+            return match Self::finalize_backend_response(backend_response, false, &selected_route.limits, ()).await {
                 Ok(resp) => Ok(resp),
                 Err(e) => {
                     error!("Failed to finalize backend response: {}", e);
                     Ok(ResponseBuilder::error(
                         StatusCode::BAD_GATEWAY,
-                        "WebSocket backend error",
-                    ))
+                        &format!("{} backend error", protocol),
+                    ).map(ResponseBody::from))
                 }
             };
         }
 
         let backend_upgrade = hyper::upgrade::on(&mut backend_response);
         let (parts, _) = backend_response.into_parts();
-        let switch_response = Response::from_parts(parts, Full::new(Bytes::new()));
+        let switch_response = Response::from_parts(parts, ResponseBody::from(Full::new(Bytes::new())));
 
         let inflight = selected_target.inflight.clone();
+        let protocol = protocol.to_string();
+        let target_desc = format!("{} -> {}", selected_route.id, selected_target.url);
         tokio::spawn(async move {
             let _inflight = InflightGuard::new(inflight);
             match (client_upgrade.await, backend_upgrade.await) {
                 (Ok(client_stream), Ok(backend_stream)) => {
-                    let mut client_io = TokioIo::new(client_stream);
+                    let kind = if websocket.is_some() { "websocket" } else { "upgrade" };
+                    let idle_watch = register_tunnel(kind, target_desc);
+                    let mut client_io = idle_watch.watch(TokioIo::new(client_stream));
                     let mut backend_io = TokioIo::new(backend_stream);
-                    if let Err(e) = copy_bidirectional(&mut client_io, &mut backend_io).await {
-                        error!("WebSocket tunnel error: {}", e);
+                    let tunnel = async {
+                        match websocket {
+                            Some(websocket) => {
+                                let _active = GaugeGuard::new(websocket.active);
+                                relay_websocket(client_io, backend_io, websocket.drain).await
+                            }
+                            None => copy_bidirectional(&mut client_io, &mut backend_io).await.map(|_| ()),
+                        }
+                    };
+                    match idle_watch.run(tunnel).await {
+                        Some(Err(e)) => error!("{} tunnel error: {}", protocol, e),
+                        Some(Ok(())) => {}
+                        None => debug!("Idle {} tunnel closed", protocol),
                     }
                 }
-                (Err(e), _) => error!("Client WebSocket upgrade failed: {}", e),
-                (_, Err(e)) => error!("Backend WebSocket upgrade failed: {}", e),
+                (Err(e), _) => error!("Client {} upgrade failed: {}", protocol, e),
+                (_, Err(e)) => error!("Backend {} upgrade failed: {}", protocol, e),
             }
         });
 
@@ -1724,22 +3527,19 @@ impl ReverseProxy {
             let offered = headers
                 .get("Sec-WebSocket-Protocol")
                 .and_then(|v| v.to_str().ok())
-                .map(|raw| raw.split(',').map(|s| s.trim().to_string()).collect::<Vec<_>>())
-                .unwrap_or_else(|| Vec::new());
+                .unwrap_or_default();
+            let mut offers = offered.split(',').map(str::trim).filter(|offer| !offer.is_empty()).peekable();
 
-            if offered.is_empty() {
+            if offers.peek().is_none() {
                 return Err("WebSocket subprotocol required".to_string());
             }
 
-            let supported = config
-                .supported_protocols
-                .iter()
-                .map(|p| p.to_ascii_lowercase())
-                .collect::<Vec<_>>();
-            if !offered
-                .iter()
-                .any(|offer| supported.iter().any(|allowed| allowed == &offer.to_ascii_lowercase()))
-            {
+            if !offers.any(|offer| {
+                config
+                    .supported_protocols
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(offer))
+            }) {
                 return Err("Unsupported WebSocket subprotocol".to_string());
             }
         }
@@ -1754,6 +3554,7 @@ impl ReverseProxy {
         preserve_host: bool,
         keep_upgrade: bool,
         strip_path_prefix: Option<&str>,
+        append_path: bool,
     ) -> Result<Request<B>, ProxyError> {
         let path_and_query = req
             .uri()
@@ -1766,49 +3567,74 @@ impl ReverseProxy {
             .map(|(p, q)| (p, Some(q)))
             .unwrap_or((raw, None));
 
-        let mut path = raw_path.to_string();
-        if let Some(prefix) = strip_path_prefix {
-            if path.starts_with(prefix) {
-                path = path[prefix.len()..].to_string();
-                if path.is_empty() {
-                    path = "/".to_string();
-                } else if !path.starts_with('/') {
-                    path = format!("/{}", path);
-                }
-            }
-        }
+        let stripped = strip_path_prefix
+            .and_then(|prefix| raw_path.strip_prefix(prefix))
+            .map(|rest| match rest {
+                "" => "/".to_string(),
+                rest if rest.starts_with('/') => rest.to_string(),
+                rest => format!("/{}", rest),
+            });
 
-        let new_path_and_query = if let Some(query) = raw_query {
-            format!("{}?{}", path, query)
+        let target_uri = if append_path && target_url.path() == "/" && target_url.query().is_none() {
+            // A target without path or query is just scheme and authority: the client's path and
+            // query go through as they are, without formatting and reparsing the whole URI
+            let path_and_query = match &stripped {
+                None => path_and_query.clone(),
+                Some(path) => match raw_query {
+                    Some(query) => PathAndQuery::try_from(format!("{}?{}", path, query)),
+                    None => PathAndQuery::try_from(path.as_str()),
+                }
+                .map_err(|e| ProxyError::Uri(e.to_string()))?,
+            };
+            Uri::builder()
+                .scheme(target_url.scheme())
+                .authority(&target_url[Position::BeforeUsername..Position::AfterPort])
+                .path_and_query(path_and_query)
+                .build()
+                .map_err(|e| ProxyError::Uri(e.to_string()))?
         } else {
-            path
+            let path = stripped.as_deref().unwrap_or(raw_path);
+            // A templated target already carries the full backend path; only the query is added
+            let target_url_string = if append_path {
+                let base = target_url.as_str().trim_end_matches('/');
+                match raw_query {
+                    Some(query) => format!("{}{}?{}", base, path, query),
+                    None => format!("{}{}", base, path),
+                }
+            } else {
+                match (target_url.query(), raw_query) {
+                    (None, Some(query)) => format!("{}?{}", target_url.as_str(), query),
+                    (Some(_), Some(query)) => format!("{}&{}", target_url.as_str(), query),
+                    (_, None) => target_url.as_str().to_string(),
+                }
+            };
+            target_url_string
+                .parse()
+                .map_err(|e: hyper::http::uri::InvalidUri| ProxyError::Uri(e.to_string()))?
         };
 
-        let target_url_string = format!(
-            "{}{}",
-            target_url.as_str().trim_end_matches('/'),
-            new_path_and_query
-        );
-
-        let target_uri: Uri = target_url_string
-            .parse()
-            .map_err(|e: hyper::http::uri::InvalidUri| ProxyError::Uri(e.to_string()))?;
-
-        let original_host = req.headers().get(HOST).cloned();
-        *req.uri_mut() = target_uri.clone();
+        // HTTP/2 and absolute-form requests may carry the host in the URI only
+        let original_host = req.headers().get(HOST).cloned().or_else(|| {
+            req.uri().authority().and_then(|authority| HeaderValue::from_str(authority.as_str()).ok())
+        });
+        let target_host = target_uri.authority().and_then(|authority| HeaderValue::from_str(authority.as_str()).ok());
+        *req.uri_mut() = target_uri;
+        // Backends are spoken to over HTTP/1.1 whatever the client used
+        *req.version_mut() = Version::HTTP_11;
 
         let headers = req.headers_mut();
 
-        if !preserve_host {
-            if let Some(authority) = target_uri.authority() {
-                headers.insert(HOST, authority.to_string().parse().unwrap());
+        if preserve_host {
+            // Without a Host header the client would fill in the target's authority
+            if let Some(host) = &original_host {
+                headers.insert(HOST, host.clone());
             }
+        } else if let Some(host) = target_host {
+            headers.insert(HOST, host);
         }
 
-        if let Some(client_ip) = &context.client_ip {
-            headers.insert(X_FORWARDED_FOR.clone(), client_ip.parse().unwrap());
-        }
-        headers.insert(X_FORWARDED_PROTO.clone(), "https".parse().unwrap());
+        Self::set_forwarded_for(headers, context);
+        headers.insert(X_FORWARDED_PROTO.clone(), HeaderValue::from_static("https"));
         if let Some(host) = original_host {
             headers.insert(X_FORWARDED_HOST.clone(), host);
         }
@@ -1817,17 +3643,35 @@ impl ReverseProxy {
         Ok(req)
     }
 
+    /// Extends the `X-Forwarded-For` chain of a trusted peer with its address; anyone else's
+    /// chain is replaced by the peer's address.
+    fn set_forwarded_for(headers: &mut hyper::HeaderMap, context: &RequestContext) {
+        let value = match (context.peer_ip, &context.client_ip) {
+            (Some(peer), _) => forwarded::forwarded_for(peer, headers),
+            (None, Some(client_ip)) => client_ip.clone(),
+            (None, None) => return,
+        };
+        match HeaderValue::from_str(&value) {
+            Ok(value) => {
+                headers.insert(X_FORWARDED_FOR.clone(), value);
+            }
+            Err(_) => {
+                headers.remove(&X_FORWARDED_FOR);
+            }
+        }
+    }
+
     fn strip_request_headers(headers: &mut hyper::HeaderMap, keep_upgrade: bool) {
         if !keep_upgrade {
-            headers.remove("Connection");
-            headers.remove("Upgrade");
+            headers.remove(CONNECTION);
+            headers.remove(UPGRADE);
         }
         headers.remove("Keep-Alive");
-        headers.remove("Proxy-Authenticate");
-        headers.remove("Proxy-Authorization");
-        headers.remove("TE");
+        headers.remove(PROXY_AUTHENTICATE);
+        headers.remove(PROXY_AUTHORIZATION);
+        headers.remove(TE);
         headers.remove("Trailers");
-        headers.remove("Transfer-Encoding");
+        headers.remove(TRANSFER_ENCODING);
     }
 
     fn box_incoming_request(req: Request<Incoming>) -> Request<BoxedBody> {
@@ -1836,6 +3680,27 @@ impl ReverseProxy {
         Request::from_parts(parts, body)
     }
 
+    /// Sends a request on a route's pooled client. With `dns` configured, a connection to an
+    /// address the backend's hostname no longer resolves to is closed after this response.
+    async fn send_pooled(
+        client: &UpstreamClient,
+        mut request: Request<BoxedBody>,
+    ) -> Result<Response<Incoming>, hyper_util::client::legacy::Error> {
+        if !dns::enabled() {
+            return client.request(request).await;
+        }
+        let host = request.uri().host().map(str::to_string);
+        let connection = capture_connection(&mut request);
+        let response = client.request(request).await;
+        if response.is_ok()
+            && let Some(host) = host
+            && let Some(connected) = connection.connection_metadata().as_ref()
+        {
+            dns::retire_if_moved(&host, connected);
+        }
+        response
+    }
+
     fn box_infallible_request<B>(req: Request<B>) -> Request<BoxedBody>
     where
         B: hyper::body::Body<Data = Bytes, Error = Infallible> + Send + Sync + 'static,
@@ -1845,45 +3710,54 @@ impl ReverseProxy {
         Request::from_parts(parts, body)
     }
 
+    /// Passes the backend body on as it arrives; `guard` stays alive until the client has it all.
+    /// With `response_body: buffer` a body up to `max_buffer_bytes` is read first, so the client
+    /// gets it in one piece with its length known.
     async fn finalize_backend_response(
         response: Response<Incoming>,
         keep_upgrade: bool,
-    ) -> Result<Response<Full<Bytes>>, ProxyError> {
+        limits: &RouteLimitsConfig,
+        guard: impl std::any::Any + Send + Sync,
+    ) -> Result<Response<ResponseBody>, ProxyError> {
         let (mut parts, body) = response.into_parts();
-        let body_bytes = body
-            .collect()
-            .await
-            .map_err(|e| ProxyError::Http(format!("Failed to collect response body: {}", e)))?;
+        let body = StreamingBody::new(body)
+            .with_idle_timeout(limits.idle_timeout_secs.map(Duration::from_secs))
+            .with_guard(guard);
 
         Self::strip_response_headers(&mut parts.headers, keep_upgrade);
         parts
             .headers
-            .insert("X-Proxy-Server", "rust-reverse-proxy".parse().unwrap());
+            .insert("X-Proxy-Server", HeaderValue::from_static("rust-reverse-proxy"));
 
-        Ok(Response::from_parts(parts, Full::new(body_bytes.to_bytes())))
+        let body = match limits.response_body {
+            BodyMode::Stream => ResponseBody::from(body),
+            BodyMode::Buffer => match buffer_up_to(body, buffer_limit(limits), None)
+                .await
+                .map_err(|e| ProxyError::Http(format!("Failed to collect response body: {}", e)))?
+            {
+                Buffered::Complete(bytes) => ResponseBody::from(Full::new(bytes)),
+                partial => ResponseBody::from(partial.into_stream()),
+            },
+        };
+        Ok(Response::from_parts(parts, body))
     }
 
     fn strip_response_headers(headers: &mut hyper::HeaderMap, keep_upgrade: bool) {
         if !keep_upgrade {
-            headers.remove("Connection");
-            headers.remove("Upgrade");
+            headers.remove(CONNECTION);
+            headers.remove(UPGRADE);
         }
         headers.remove("Keep-Alive");
-        headers.remove("Proxy-Authenticate");
-        headers.remove("Proxy-Authorization");
-        headers.remove("TE");
+        headers.remove(PROXY_AUTHENTICATE);
+        headers.remove(PROXY_AUTHORIZATION);
+        headers.remove(TE);
         headers.remove("Trailers");
-        headers.remove("Transfer-Encoding");
+        headers.remove(TRANSFER_ENCODING);
     }
 
     /// Health check loop (runs in background)
-    async fn health_check_loop(
-        target_id: String,
-        http_client: Arc<Client<HttpConnector, BoxedBody>>,
-        target_url: Url,
-        config: HealthCheckConfig,
-        healthy: Arc<AtomicBool>,
-    ) {
+    async fn health_check_loop(target: HealthCheckTarget, cluster: Option<Arc<Cluster>>) {
+        let HealthCheckTarget { key, target_id, url: target_url, client: http_client, config, healthy } = target;
         let interval = Duration::from_secs(config.interval_secs);
         let timeout = Duration::from_secs(config.timeout_secs);
         let endpoint = config.endpoint.clone();
@@ -1901,15 +3775,29 @@ impl ReverseProxy {
         // Track previous health state to detect transitions
         let mut was_healthy = true;
 
+        // A holder that stops renewing is replaced within three intervals
+        let lease = format!("health:{}", key);
+        let lease_ttl = interval * 3;
+
         let mut interval_timer = tokio::time::interval(interval);
         loop {
             interval_timer.tick().await;
 
+            // In cluster mode only the lease holder probes; the others take its results over
+            if let Some(cluster) = &cluster
+                && !cluster.hold_lease(&lease, lease_ttl).await
+            {
+                continue;
+            }
+
             let is_healthy = if let Some(ref endpoint) = endpoint {
                 Self::http_health_check(&http_client, &target_url, endpoint, timeout).await
             } else {
                 Self::tcp_health_check(&target_url, timeout).await
             };
+            if let Some(cluster) = &cluster {
+                cluster.publish_health(&key, is_healthy).await;
+            }
 
             if is_healthy {
                 healthy.store(true, Ordering::Relaxed);
@@ -1919,6 +3807,10 @@ impl ReverseProxy {
                         "Target '{}' on port {} is now HEALTHY (recovered)",
                         target_id, port
                     );
+                    Notification::new(NotificationKind::TargetHealthy, format!("Target '{}' is healthy again", target_id))
+                        .detail("target", key.as_str())
+                        .detail("url", target_url.as_str())
+                        .send();
                     was_healthy = true;
                 } else {
                     debug!("Health check passed for target '{}'", target_id);
@@ -1931,6 +3823,10 @@ impl ReverseProxy {
                         "Target '{}' on port {} is now OFFLINE (health check failed)",
                         target_id, port
                     );
+                    Notification::new(NotificationKind::TargetUnhealthy, format!("Target '{}' failed its health check", target_id))
+                        .detail("target", key.as_str())
+                        .detail("url", target_url.as_str())
+                        .send();
                     was_healthy = false;
                 } else {
                     debug!("Health check still failing for target '{}'", target_id);
@@ -1939,6 +3835,53 @@ impl ReverseProxy {
         }
     }
 
+    /// Prewarm loop (runs in background): opens the connections at startup and refreshes
+    /// them before the pool's idle timeout evicts them
+    async fn prewarm_loop(target: PrewarmTarget) {
+        let PrewarmTarget { key, url, client, prewarm, healthy } = target;
+        let uri = format!("{}{}", url.as_str().trim_end_matches('/'), prewarm.path);
+        info!(
+            "Keeping {} warm connection(s) to target '{}' (refresh every {}s)",
+            prewarm.connections,
+            key,
+            prewarm.interval.as_secs()
+        );
+
+        let mut interval_timer = tokio::time::interval(prewarm.interval);
+        loop {
+            interval_timer.tick().await;
+            // Unhealthy targets get no traffic, so their connections are not worth holding
+            if !healthy.load(Ordering::Relaxed) {
+                continue;
+            }
+            let warm = Self::prewarm_connections(&client, &uri, prewarm.connections).await;
+            if warm < prewarm.connections {
+                debug!("Prewarmed {} of {} connection(s) to target '{}'", warm, prewarm.connections, key);
+            }
+        }
+    }
+
+    /// Sends `connections` concurrent HEAD requests, so the pool has to hold that many
+    /// connections at once: idle ones are reused and refreshed, missing ones are opened.
+    /// Returns how many requests got a response.
+    async fn prewarm_connections(
+        client: &UpstreamClient,
+        uri: &str,
+        connections: usize,
+    ) -> usize {
+        let requests = (0..connections).map(|_| async {
+            let request = Request::builder()
+                .method(Method::HEAD)
+                .uri(uri)
+                .body(Empty::<Bytes>::new())
+                .ok()?;
+            let response = Self::send_pooled(client, Self::box_infallible_request(request)).await.ok()?;
+            // Reading the (empty) body to its end hands the connection back to the pool
+            response.into_body().collect().await.ok()
+        });
+        futures::future::join_all(requests).await.into_iter().flatten().count()
+    }
+
     /// TCP health check (default)
     async fn tcp_health_check(target_url: &Url, timeout: Duration) -> bool {
         let host = match target_url.host_str() {
@@ -1962,7 +3905,7 @@ impl ReverseProxy {
 
     /// HTTP endpoint health check
     async fn http_health_check(
-        _http_client: &Client<HttpConnector, BoxedBody>,
+        _http_client: &UpstreamClient,
         target_url: &Url,
         endpoint: &str,
         timeout: Duration,
@@ -2009,7 +3952,7 @@ mod tests {
     use bytes::Bytes;
     use http_body_util::Empty;
     use std::collections::{HashMap, HashSet};
-    use crate::config::RetryPolicyConfig;
+    use crate::config::{RetryPolicyConfig, TcpKeepaliveConfig};
 
     #[test]
     fn test_reverse_proxy_creation() {
@@ -2025,6 +3968,41 @@ mod tests {
         assert!(invalid_url.is_err());
     }
 
+    #[test]
+    fn host_matcher_combines_literal_and_wildcard_patterns() {
+        let matcher = HostMatcher::from_patterns(vec![
+            "Shop.Example.com".to_string(),
+            "*.example.org".to_string(),
+            "api-?.example.net".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(matcher.exact.len(), 1);
+        assert!(matcher.matches("shop.example.com"));
+        assert!(matcher.matches("SHOP.EXAMPLE.COM"));
+        assert!(matcher.matches("CDN.example.org"));
+        assert!(matcher.matches("api-1.example.net"));
+        assert!(!matcher.matches("example.org"));
+        assert!(!matcher.matches("shop.example.com.evil"));
+
+        let matcher = HostMatcher::from_patterns(vec!["münchen.example".to_string(), "*.zürich.example".to_string()]).unwrap();
+        assert_eq!(matcher.patterns, ["xn--mnchen-3ya.example", "*.xn--zrich-kva.example"]);
+        assert!(matcher.matches("xn--mnchen-3ya.example"));
+        assert!(matcher.matches("api.XN--ZRICH-KVA.example"));
+    }
+
+    #[test]
+    fn path_matcher_captures_from_the_first_matching_pattern() {
+        let matcher = PathMatcher::from_patterns(
+            vec!["/users/{id}/orders".to_string(), "/users/{name}".to_string()],
+            false,
+        )
+        .unwrap();
+        assert!(matcher.matches("/users/alice"));
+        assert!(!matcher.matches("/users"));
+        assert_eq!(matcher.captures("/users/7/orders").unwrap(), [("id".to_string(), "7".to_string())]);
+        assert_eq!(matcher.captures("/users/alice").unwrap(), [("name".to_string(), "alice".to_string())]);
+    }
+
     #[test]
     fn test_route_matching_priority() {
         let routes = vec![
@@ -2038,7 +4016,21 @@ mod tests {
                 retry_policy: None,
                 reverse_proxy_config: None,
                 strip_path_prefix: None,
+                preserve_host_header: None,
                 priority: Some(1),
+                require_api_key: false,
+                hmac_verification: None,
+                fan_out: None,
+                aggregate: None,
+                replay: None,
+                static_response: None,
+                allowed_methods: Vec::new(),
+                method_override: None,
+                allowed_upgrades: Vec::new(),
+                path_param_headers: HashMap::new(),
+                limits: None,
+                upstream_tls: None,
+                metadata: HashMap::new(),
                 predicates: vec![RoutePredicateConfig::Path {
                     patterns: vec!["/api/**".to_string()],
                     match_trailing_slash: true,
@@ -2054,7 +4046,21 @@ mod tests {
                 retry_policy: None,
                 reverse_proxy_config: None,
                 strip_path_prefix: None,
+                preserve_host_header: None,
                 priority: Some(5),
+                require_api_key: false,
+                hmac_verification: None,
+                fan_out: None,
+                aggregate: None,
+                replay: None,
+                static_response: None,
+                allowed_methods: Vec::new(),
+                method_override: None,
+                allowed_upgrades: Vec::new(),
+                path_param_headers: HashMap::new(),
+                limits: None,
+                upstream_tls: None,
+                metadata: HashMap::new(),
                 predicates: vec![RoutePredicateConfig::Path {
                     patterns: vec!["/**".to_string()],
                     match_trailing_slash: true,
@@ -2069,7 +4075,7 @@ mod tests {
             .body(Empty::<Bytes>::new())
             .unwrap();
         let route = matcher
-            .select_route(&req, &RequestContext { client_ip: None })
+            .select_route(&req, &RequestContext { client_ip: None, peer_ip: None })
             .unwrap();
         assert_eq!(route.id, "high");
     }
@@ -2087,7 +4093,21 @@ mod tests {
                 retry_policy: None,
                 reverse_proxy_config: None,
                 strip_path_prefix: None,
+                preserve_host_header: None,
                 priority: Some(0),
+                require_api_key: false,
+                hmac_verification: None,
+                fan_out: None,
+                aggregate: None,
+                replay: None,
+                static_response: None,
+                allowed_methods: Vec::new(),
+                method_override: None,
+                allowed_upgrades: Vec::new(),
+                path_param_headers: HashMap::new(),
+                limits: None,
+                upstream_tls: None,
+                metadata: HashMap::new(),
                 predicates: vec![
                     RoutePredicateConfig::Path {
                         patterns: vec!["/**".to_string()],
@@ -2109,7 +4129,21 @@ mod tests {
                 retry_policy: None,
                 reverse_proxy_config: None,
                 strip_path_prefix: None,
+                preserve_host_header: None,
                 priority: Some(0),
+                require_api_key: false,
+                hmac_verification: None,
+                fan_out: None,
+                aggregate: None,
+                replay: None,
+                static_response: None,
+                allowed_methods: Vec::new(),
+                method_override: None,
+                allowed_upgrades: Vec::new(),
+                path_param_headers: HashMap::new(),
+                limits: None,
+                upstream_tls: None,
+                metadata: HashMap::new(),
                 predicates: vec![
                     RoutePredicateConfig::Path {
                         patterns: vec!["/**".to_string()],
@@ -2129,7 +4163,7 @@ mod tests {
             .body(Empty::<Bytes>::new())
             .unwrap();
         let first = matcher
-            .select_route(&req, &RequestContext { client_ip: None })
+            .select_route(&req, &RequestContext { client_ip: None, peer_ip: None })
             .unwrap();
         assert!(first.id == "a" || first.id == "b");
     }
@@ -2174,7 +4208,21 @@ mod tests {
             }),
             reverse_proxy_config: None,
             strip_path_prefix: None,
+            preserve_host_header: None,
             priority: Some(0),
+            require_api_key: false,
+            hmac_verification: None,
+            fan_out: None,
+            aggregate: None,
+            replay: None,
+            static_response: None,
+            allowed_methods: Vec::new(),
+            method_override: None,
+            allowed_upgrades: Vec::new(),
+            path_param_headers: HashMap::new(),
+            limits: None,
+            upstream_tls: None,
+            metadata: HashMap::new(),
             predicates: vec![RoutePredicateConfig::Path {
                 patterns: vec!["/api/**".to_string()],
                 match_trailing_slash: true,
@@ -2191,10 +4239,10 @@ mod tests {
             .unwrap();
 
         let route = matcher
-            .select_route(&req, &RequestContext { client_ip: None })
+            .select_route(&req, &RequestContext { client_ip: None, peer_ip: None })
             .unwrap();
         let selection = route
-            .select_target(&req, &RequestContext { client_ip: None })
+            .select_target(&req, &RequestContext { client_ip: None, peer_ip: None })
             .unwrap();
 
         assert!(selection.target.id == "a" || selection.target.id == "b");
@@ -2232,7 +4280,21 @@ mod tests {
             }),
             reverse_proxy_config: None,
             strip_path_prefix: None,
+            preserve_host_header: None,
             priority: Some(0),
+            require_api_key: false,
+            hmac_verification: None,
+            fan_out: None,
+            aggregate: None,
+            replay: None,
+            static_response: None,
+            allowed_methods: Vec::new(),
+            method_override: None,
+            allowed_upgrades: Vec::new(),
+            path_param_headers: HashMap::new(),
+            limits: None,
+            upstream_tls: None,
+            metadata: HashMap::new(),
             predicates: vec![RoutePredicateConfig::Path {
                 patterns: vec!["/api/**".to_string()],
                 match_trailing_slash: true,
@@ -2249,7 +4311,7 @@ mod tests {
             .unwrap();
 
         let route = matcher
-            .select_route(&req, &RequestContext { client_ip: None })
+            .select_route(&req, &RequestContext { client_ip: None, peer_ip: None })
             .unwrap();
 
         if let Some(target) = route.targets.iter().find(|t| t.id == "a") {
@@ -2257,7 +4319,7 @@ mod tests {
         }
 
         let selection = route
-            .select_target(&req, &RequestContext { client_ip: None })
+            .select_target(&req, &RequestContext { client_ip: None, peer_ip: None })
             .unwrap();
 
         assert_eq!(selection.target.id, "b");
@@ -2287,7 +4349,21 @@ mod tests {
             header_override: None,
             reverse_proxy_config: None,
             strip_path_prefix: None,
+            preserve_host_header: None,
             priority: Some(0),
+            require_api_key: false,
+            hmac_verification: None,
+            fan_out: None,
+            aggregate: None,
+            replay: None,
+            static_response: None,
+            allowed_methods: Vec::new(),
+            method_override: None,
+            allowed_upgrades: Vec::new(),
+            path_param_headers: HashMap::new(),
+            limits: None,
+            upstream_tls: None,
+            metadata: HashMap::new(),
             predicates: vec![RoutePredicateConfig::Path {
                 patterns: vec!["/api/**".to_string()],
                 match_trailing_slash: true,
@@ -2302,19 +4378,224 @@ mod tests {
             .body(Empty::<Bytes>::new())
             .unwrap();
         let route = matcher
-            .select_route(&req, &RequestContext { client_ip: None })
+            .select_route(&req, &RequestContext { client_ip: None, peer_ip: None })
             .unwrap();
 
         let mut excluded = HashSet::new();
         excluded.insert("a".to_string());
 
         let selection = route
-            .select_target_with_exclusions(&req, &RequestContext { client_ip: None }, &excluded)
+            .select_target_with_exclusions(&req, &RequestContext { client_ip: None, peer_ip: None }, &excluded)
             .unwrap();
 
         assert_eq!(selection.target.id, "b");
     }
 
+    #[test]
+    fn dynamic_weights_prefer_fast_and_idle_targets() {
+        let target = |id: &str| ReverseProxyTargetConfig {
+            id: id.to_string(),
+            url: format!("http://{}.example.com", id),
+            weight: 1,
+            enabled: true,
+        };
+        let routes = vec![ReverseProxyRouteConfig {
+            id: "api".to_string(),
+            target: None,
+            targets: vec![target("fast"), target("slow")],
+            load_balancing: Some(LoadBalancingConfig {
+                outlier_detection: OutlierDetectionConfig { enabled: false, ..OutlierDetectionConfig::default() },
+                dynamic_weights: Some(DynamicWeightsConfig::default()),
+                ..LoadBalancingConfig::default()
+            }),
+            sticky: None,
+            header_override: None,
+            reverse_proxy_config: None,
+            strip_path_prefix: None,
+            preserve_host_header: None,
+            priority: Some(0),
+            require_api_key: false,
+            hmac_verification: None,
+            fan_out: None,
+            aggregate: None,
+            replay: None,
+            static_response: None,
+            allowed_methods: Vec::new(),
+            method_override: None,
+            allowed_upgrades: Vec::new(),
+            path_param_headers: HashMap::new(),
+            limits: None,
+            upstream_tls: None,
+            metadata: HashMap::new(),
+            predicates: vec![RoutePredicateConfig::Path {
+                patterns: vec!["/api/**".to_string()],
+                match_trailing_slash: true,
+            }],
+            retry_policy: None,
+        }];
+
+        let matcher = RouteMatcher::new(routes, 10, None).unwrap();
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("/api/users")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let context = RequestContext { client_ip: None, peer_ip: None };
+        let route = matcher.select_route(&req, &context).unwrap();
+        let (fast, slow) = (&route.targets[0], &route.targets[1]);
+        for _ in 0..30 {
+            fast.outlier.record(true, Duration::from_millis(10), &route.outlier_detection);
+            slow.outlier.record(true, Duration::from_millis(50), &route.outlier_detection);
+        }
+        let picks = |route: &CompiledRoute| {
+            (0..50).filter(|_| route.select_target(&req, &context).unwrap().target.id == "fast").count()
+        };
+        assert_eq!(picks(route), 50, "with two targets both are drawn and the faster wins");
+
+        fast.inflight.store(9, Ordering::Relaxed);
+        assert_eq!(picks(route), 0, "10ms x 10 in flight costs more than 50ms x 1");
+
+        fast.inflight.store(0, Ordering::Relaxed);
+        for _ in 0..30 {
+            fast.outlier.record(false, Duration::from_millis(10), &route.outlier_detection);
+        }
+        assert_eq!(picks(route), 0, "errors raise the cost of the faster target");
+    }
+
+    #[test]
+    fn least_requests_weighs_requests_in_flight_and_rotates_ties() {
+        let target = |id: &str, weight: u32| ReverseProxyTargetConfig {
+            id: id.to_string(),
+            url: format!("http://{}.example.com", id),
+            weight,
+            enabled: true,
+        };
+        let routes = vec![ReverseProxyRouteConfig {
+            id: "least-requests".to_string(),
+            target: None,
+            targets: vec![target("small", 1), target("large", 3)],
+            load_balancing: Some(LoadBalancingConfig {
+                policy: LoadBalancingPolicy::LeastRequests,
+                ..LoadBalancingConfig::default()
+            }),
+            sticky: None,
+            header_override: None,
+            reverse_proxy_config: None,
+            strip_path_prefix: None,
+            preserve_host_header: None,
+            priority: Some(0),
+            require_api_key: false,
+            hmac_verification: None,
+            fan_out: None,
+            aggregate: None,
+            replay: None,
+            static_response: None,
+            allowed_methods: Vec::new(),
+            method_override: None,
+            allowed_upgrades: Vec::new(),
+            path_param_headers: HashMap::new(),
+            limits: None,
+            upstream_tls: None,
+            metadata: HashMap::new(),
+            predicates: vec![RoutePredicateConfig::Path {
+                patterns: vec!["/api/**".to_string()],
+                match_trailing_slash: true,
+            }],
+            retry_policy: None,
+        }];
+
+        let matcher = RouteMatcher::new(routes, 10, None).unwrap();
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("/api/users")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let context = RequestContext { client_ip: None, peer_ip: None };
+        let route = matcher.select_route(&req, &context).unwrap();
+        let pick = || route.select_target(&req, &context).unwrap().target.id.clone();
+        let large = &route.targets[1];
+
+        assert_eq!(pick(), "large", "1 slot of 3 beats 1 of 1");
+        large.requests.set(2);
+        let tied = [pick(), pick()];
+        assert!(tied.contains(&"small".to_string()) && tied.contains(&"large".to_string()), "ties rotate");
+        large.requests.set(3);
+        assert_eq!(pick(), "small");
+        {
+            let _tunnel = InflightGuard::new(large.inflight.clone());
+            large.requests.set(0);
+            assert_eq!(pick(), "large", "upgraded connections are not requests");
+        }
+    }
+
+    #[test]
+    fn slow_start_ramps_up_recovered_targets() {
+        let target = |id: &str| ReverseProxyTargetConfig {
+            id: id.to_string(),
+            url: format!("http://{}.example.com", id),
+            weight: 1,
+            enabled: true,
+        };
+        let routes = vec![ReverseProxyRouteConfig {
+            id: "slow-start".to_string(),
+            target: None,
+            targets: vec![target("steady"), target("recovering")],
+            load_balancing: Some(LoadBalancingConfig {
+                slow_start: Some(SlowStartConfig { window_secs: 30, min_percent: 10 }),
+                ..LoadBalancingConfig::default()
+            }),
+            sticky: None,
+            header_override: None,
+            reverse_proxy_config: None,
+            strip_path_prefix: None,
+            preserve_host_header: None,
+            priority: Some(0),
+            require_api_key: false,
+            hmac_verification: None,
+            fan_out: None,
+            aggregate: None,
+            replay: None,
+            static_response: None,
+            allowed_methods: Vec::new(),
+            method_override: None,
+            allowed_upgrades: Vec::new(),
+            path_param_headers: HashMap::new(),
+            limits: None,
+            upstream_tls: None,
+            metadata: HashMap::new(),
+            predicates: vec![RoutePredicateConfig::Path {
+                patterns: vec!["/api/**".to_string()],
+                match_trailing_slash: true,
+            }],
+            retry_policy: None,
+        }];
+
+        let matcher = RouteMatcher::new(routes, 10, None).unwrap();
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("/api/users")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let context = RequestContext { client_ip: None, peer_ip: None };
+        let route = matcher.select_route(&req, &context).unwrap();
+        let recovering = &route.targets[1];
+        let picks = || {
+            (0..1000).filter(|_| route.select_target(&req, &context).unwrap().target.id == "recovering").count()
+        };
+        assert!((400..=600).contains(&picks()), "targets start at their full share");
+
+        recovering.healthy.store(false, Ordering::Relaxed);
+        assert_eq!(picks(), 0);
+        recovering.healthy.store(true, Ordering::Relaxed);
+        let ramping = picks();
+        assert!((1..=150).contains(&ramping), "about 10% of its 500, got {}", ramping);
+
+        let since = recovering.recovery.at().unwrap();
+        let share = route.slow_start_share(recovering, since + Duration::from_secs(15));
+        assert!((share - 0.55).abs() < 1e-9);
+        assert_eq!(route.slow_start_share(recovering, since + Duration::from_secs(30)), 1.0);
+    }
+
     #[test]
     fn test_retry_policy_rejects_invalid_method() {
         let routes = vec![ReverseProxyRouteConfig {
@@ -2326,7 +4607,21 @@ mod tests {
             header_override: None,
             reverse_proxy_config: None,
             strip_path_prefix: None,
+            preserve_host_header: None,
             priority: Some(0),
+            require_api_key: false,
+            hmac_verification: None,
+            fan_out: None,
+            aggregate: None,
+            replay: None,
+            static_response: None,
+            allowed_methods: Vec::new(),
+            method_override: None,
+            allowed_upgrades: Vec::new(),
+            path_param_headers: HashMap::new(),
+            limits: None,
+            upstream_tls: None,
+            metadata: HashMap::new(),
             predicates: vec![RoutePredicateConfig::Path {
                 patterns: vec!["/api/**".to_string()],
                 match_trailing_slash: true,
@@ -2350,4 +4645,609 @@ mod tests {
             _ => panic!("expected config error"),
         }
     }
+
+    #[test]
+    fn test_route_upgrade_allow_list() {
+        let routes = vec![ReverseProxyRouteConfig {
+            id: "k8s".to_string(),
+            target: Some("http://apiserver.example.com".to_string()),
+            targets: Vec::new(),
+            load_balancing: None,
+            sticky: None,
+            header_override: None,
+            retry_policy: None,
+            reverse_proxy_config: None,
+            strip_path_prefix: None,
+            preserve_host_header: None,
+            priority: Some(0),
+            require_api_key: false,
+            hmac_verification: None,
+            fan_out: None,
+            aggregate: None,
+            replay: None,
+            static_response: None,
+            allowed_methods: Vec::new(),
+            method_override: None,
+            allowed_upgrades: vec!["SPDY/3.1".to_string()],
+            path_param_headers: HashMap::new(),
+            limits: None,
+            upstream_tls: None,
+            metadata: HashMap::new(),
+            predicates: vec![RoutePredicateConfig::Path {
+                patterns: vec!["/**".to_string()],
+                match_trailing_slash: true,
+            }],
+        }];
+        let matcher = RouteMatcher::new(routes, 10, None).unwrap();
+        let route = &matcher.routes[0];
+
+        let req = Request::builder()
+            .uri("/api/v1/namespaces/default/pods/web/exec")
+            .header("Connection", "Upgrade")
+            .header("Upgrade", "SPDY/3.1")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let protocol = upgrade_protocol(req.headers()).unwrap();
+        assert_eq!(protocol, "spdy/3.1");
+        assert!(route.tunnels_upgrade(&protocol));
+        assert!(route.tunnels_upgrade("websocket"));
+        assert!(!route.tunnels_upgrade("tcp"));
+
+        let no_connection_upgrade = Request::builder()
+            .header("Upgrade", "tcp")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        assert_eq!(upgrade_protocol(no_connection_upgrade.headers()), None);
+    }
+
+    #[test]
+    fn test_websocket_upgrade_pins_backend_with_sticky_cookie() {
+        let routes = vec![ReverseProxyRouteConfig {
+            id: "ws".to_string(),
+            target: None,
+            targets: vec![
+                ReverseProxyTargetConfig {
+                    id: "a".to_string(),
+                    url: "http://a.example.com".to_string(),
+                    weight: 1,
+                    enabled: true,
+                },
+                ReverseProxyTargetConfig {
+                    id: "b".to_string(),
+                    url: "http://b.example.com".to_string(),
+                    weight: 1,
+                    enabled: true,
+                },
+            ],
+            load_balancing: None,
+            sticky: Some(StickyConfig {
+                mode: StickyMode::Cookie,
+                cookie_name: Some("ws_backend".to_string()),
+                header_name: None,
+                ttl_seconds: Some(600),
+            }),
+            header_override: None,
+            retry_policy: None,
+            reverse_proxy_config: None,
+            strip_path_prefix: None,
+            preserve_host_header: None,
+            priority: Some(0),
+            require_api_key: false,
+            hmac_verification: None,
+            fan_out: None,
+            aggregate: None,
+            replay: None,
+            static_response: None,
+            allowed_methods: Vec::new(),
+            method_override: None,
+            allowed_upgrades: Vec::new(),
+            path_param_headers: HashMap::new(),
+            limits: None,
+            upstream_tls: None,
+            metadata: HashMap::new(),
+            predicates: vec![RoutePredicateConfig::Path {
+                patterns: vec!["/ws".to_string()],
+                match_trailing_slash: true,
+            }],
+        }];
+        let matcher = RouteMatcher::new(routes, 10, None).unwrap();
+        let route = &matcher.routes[0];
+        let context = RequestContext { client_ip: None, peer_ip: None };
+        let upgrade = |cookie: Option<&str>| {
+            let mut builder = Request::builder()
+                .uri("/ws")
+                .header("Connection", "Upgrade")
+                .header("Upgrade", "websocket");
+            if let Some(cookie) = cookie {
+                builder = builder.header("Cookie", cookie);
+            }
+            builder.body(Empty::<Bytes>::new()).unwrap()
+        };
+
+        let first = route.select_target(&upgrade(None), &context).unwrap();
+        let cookie = first.set_cookie.expect("sticky cookie on first handshake");
+        assert!(cookie.starts_with(&format!("ws_backend={};", first.target.id)));
+
+        let pinned = format!("ws_backend={}", first.target.id);
+        for _ in 0..4 {
+            let reconnect = route.select_target(&upgrade(Some(&pinned)), &context).unwrap();
+            assert_eq!(reconnect.target.id, first.target.id);
+            assert!(reconnect.set_cookie.is_none());
+        }
+
+        let gauge = websocket_telemetry().active.with_label_values(&["ws", "a"]);
+        let guard = GaugeGuard::new(gauge.clone());
+        assert_eq!(gauge.get(), 1);
+        drop(guard);
+        assert_eq!(gauge.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn pooled_client_discards_connections_closed_by_backend() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Each connection answers one keep-alive request and is then closed, like a restarting backend
+        let backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = backend.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = backend.accept().await.unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok").await;
+            }
+        });
+
+        let pool_cfg = ReverseProxyConfig {
+            keepalive: Some(TcpKeepaliveConfig { time_secs: 30, interval_secs: 5, retries: 3 }),
+            ..ReverseProxyConfig::default()
+        };
+        let client = ReverseProxy::build_http_client(5, &pool_cfg, &UpstreamTls::default());
+        for _ in 0..3 {
+            let request = Request::builder()
+                .uri(format!("http://{}/", addr))
+                .body(Full::new(Bytes::new()))
+                .unwrap();
+            let response = client.request(ReverseProxy::box_infallible_request(request)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn prewarm_opens_connections_once_and_reuses_them() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = backend.local_addr().unwrap();
+        let accepted = Arc::new(AtomicU64::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = backend.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::Relaxed);
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    while stream.read(&mut buf).await.unwrap_or(0) > 0 {
+                        let _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n").await;
+                    }
+                });
+            }
+        });
+
+        let client = ReverseProxy::build_http_client(5, &ReverseProxyConfig::default(), &UpstreamTls::default());
+        let uri = format!("http://{}/", addr);
+        assert_eq!(ReverseProxy::prewarm_connections(&client, &uri, 3).await, 3);
+        assert_eq!(accepted.load(Ordering::Relaxed), 3);
+
+        // A refresh and a regular request both find warm connections
+        assert_eq!(ReverseProxy::prewarm_connections(&client, &uri, 3).await, 3);
+        let request = Request::builder().uri(&uri).body(Full::new(Bytes::new())).unwrap();
+        let response = client.request(ReverseProxy::box_infallible_request(request)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(accepted.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn idempotent_request_retried_after_stale_pooled_connection() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // The first connection answers one request, then drops the next one unanswered
+        let backend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = backend.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut first = true;
+            loop {
+                let (mut stream, _) = backend.accept().await.unwrap();
+                let answered = if first { 1 } else { usize::MAX };
+                first = false;
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    for _ in 0..answered {
+                        if stream.read(&mut buf).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                        let _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok").await;
+                    }
+                    let _ = stream.read(&mut buf).await;
+                });
+            }
+        });
+
+        let routes = vec![ReverseProxyRouteConfig {
+            id: "stale".to_string(),
+            target: Some(format!("http://{}", addr)),
+            targets: Vec::new(),
+            load_balancing: None,
+            sticky: None,
+            header_override: None,
+            retry_policy: None,
+            reverse_proxy_config: None,
+            strip_path_prefix: None,
+            preserve_host_header: None,
+            priority: Some(0),
+            require_api_key: false,
+            hmac_verification: None,
+            fan_out: None,
+            aggregate: None,
+            replay: None,
+            static_response: None,
+            allowed_methods: Vec::new(),
+            method_override: None,
+            allowed_upgrades: Vec::new(),
+            path_param_headers: HashMap::new(),
+            limits: None,
+            upstream_tls: None,
+            metadata: HashMap::new(),
+            predicates: vec![RoutePredicateConfig::Path {
+                patterns: vec!["/**".to_string()],
+                match_trailing_slash: true,
+            }],
+        }];
+        let matcher = RouteMatcher::new(routes, 5, None).unwrap();
+        let route = &matcher.routes[0];
+        let request = |method: Method| {
+            Request::builder()
+                .method(method)
+                .uri(format!("http://{}/", addr))
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        };
+
+        let first = ReverseProxy::send_buffered(route, request(Method::GET)).await.unwrap();
+        first.into_body().collect().await.unwrap();
+        let second = ReverseProxy::send_buffered(route, request(Method::GET)).await.unwrap();
+        assert_eq!(second.status(), StatusCode::OK);
+        second.into_body().collect().await.unwrap();
+
+        // Only the pooled connection is stale; POST on a healthy connection still goes through
+        let post = ReverseProxy::send_buffered(route, request(Method::POST)).await.unwrap();
+        assert_eq!(post.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn route_fallback_resolves_route_or_custom_response() {
+        let proxy = ReverseProxy::new("http://backend.example.com".to_string(), 5, 90, 300).unwrap();
+        let err = ReverseProxy::new("http://backend.example.com".to_string(), 5, 90, 300)
+            .unwrap()
+            .with_route_fallback(Some(RouteFallbackConfig {
+                route_id: Some("missing".to_string()),
+                ..RouteFallbackConfig::default()
+            }))
+            .err()
+            .expect("unknown fallback route is rejected");
+        assert!(err.to_string().contains("does not match any route"));
+
+        let proxy = proxy
+            .with_route_fallback(Some(RouteFallbackConfig {
+                route_id: Some("default".to_string()),
+                ..RouteFallbackConfig::default()
+            }))
+            .unwrap();
+        assert_eq!(proxy.routes.current().fallback_route().map(|r| r.id.as_str()), Some("default"));
+
+        let proxy = ReverseProxy::new("http://backend.example.com".to_string(), 5, 90, 300)
+            .unwrap()
+            .with_route_fallback(Some(RouteFallbackConfig {
+                route_id: None,
+                status: 410,
+                body: "{\"error\":\"gone\"}".to_string(),
+                content_type: "application/json".to_string(),
+            }))
+            .unwrap();
+        assert!(proxy.routes.current().fallback_route().is_none());
+        let response = proxy.routes.current().fallback.response();
+        assert_eq!(response.status(), StatusCode::GONE);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+    }
+
+    #[test]
+    fn path_params_fill_target_template_and_headers() {
+        let route_config = |target: &str| ReverseProxyRouteConfig {
+            id: "orders".to_string(),
+            target: Some(target.to_string()),
+            targets: Vec::new(),
+            load_balancing: None,
+            sticky: None,
+            header_override: None,
+            retry_policy: None,
+            reverse_proxy_config: None,
+            strip_path_prefix: None,
+            preserve_host_header: None,
+            priority: Some(0),
+            require_api_key: false,
+            hmac_verification: None,
+            fan_out: None,
+            aggregate: None,
+            replay: None,
+            static_response: None,
+            allowed_methods: Vec::new(),
+            method_override: None,
+            allowed_upgrades: Vec::new(),
+            path_param_headers: HashMap::from([("id".to_string(), "X-User-Id".to_string())]),
+            limits: None,
+            upstream_tls: None,
+            metadata: HashMap::new(),
+            predicates: vec![RoutePredicateConfig::Path {
+                patterns: vec!["/users/{id}/orders/{*rest}".to_string()],
+                match_trailing_slash: false,
+            }],
+        };
+        let err = RouteMatcher::new(vec![route_config("http://orders/{user}")], 10, None)
+            .err()
+            .expect("unknown template variable is rejected");
+        assert!(err.to_string().contains("{user}"));
+
+        let matcher = RouteMatcher::new(vec![route_config("http://orders:8080/v1/{id}/{rest}")], 10, None).unwrap();
+        let context = RequestContext { client_ip: None, peer_ip: None };
+        let mut req = Request::builder()
+            .uri("/users/42/orders/7/items?expand=true")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let route = matcher.select_route(&req, &context).unwrap();
+        let target = &route.targets[0];
+
+        let templated = route.apply_path_params(target, &mut req).unwrap();
+        assert_eq!(req.headers()["X-User-Id"], "42");
+        let rewritten = ReverseProxy::rewrite_backend_request(
+            req,
+            &context,
+            templated.as_ref().unwrap(),
+            false,
+            false,
+            None,
+            templated.is_none(),
+        )
+        .unwrap();
+        assert_eq!(rewritten.uri().to_string(), "http://orders:8080/v1/42/7/items?expand=true");
+    }
+
+    #[test]
+    fn backend_uris_with_and_without_a_target_path() {
+        let context = RequestContext { client_ip: None, peer_ip: None };
+        let rewrite = |target: &str, uri: &str, strip: Option<&str>| {
+            let req = Request::builder().uri(uri).body(Empty::<Bytes>::new()).unwrap();
+            let target = Url::parse(target).unwrap();
+            let rewritten = ReverseProxy::rewrite_backend_request(req, &context, &target, false, false, strip, true).unwrap();
+            (rewritten.uri().to_string(), rewritten.headers()[HOST].to_str().unwrap().to_string())
+        };
+
+        let bare = "http://backend:8080";
+        assert_eq!(rewrite(bare, "/a/b?x=1&y", None), ("http://backend:8080/a/b?x=1&y".to_string(), "backend:8080".to_string()));
+        assert_eq!(rewrite(bare, "/api/users?x=1", Some("/api")).0, "http://backend:8080/users?x=1");
+        assert_eq!(rewrite(bare, "/api", Some("/api")).0, "http://backend:8080/");
+        assert_eq!(rewrite(bare, "/apiv2", Some("/api")).0, "http://backend:8080/v2");
+        assert_eq!(rewrite("https://[::1]:8443/", "/a", None), ("https://[::1]:8443/a".to_string(), "[::1]:8443".to_string()));
+
+        assert_eq!(rewrite("http://backend:8080/base/", "/a?x=1", None).0, "http://backend:8080/base/a?x=1");
+    }
+
+    #[test]
+    fn host_header_policy_per_route_and_forwarded_host() {
+        let route = |id: &str, preserve: Option<bool>| -> ReverseProxyRouteConfig {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "target": "http://backend:8080",
+                "preserve_host_header": preserve,
+                "predicates": [{ "type": "Path", "patterns": [format!("/{}/**", id)] }]
+            }))
+            .unwrap()
+        };
+        let pool = ReverseProxyConfig { preserve_host_header: Some(false), ..ReverseProxyConfig::default() };
+        let matcher = RouteMatcher::new(vec![route("kept", Some(true)), route("inherited", None)], 10, Some(pool)).unwrap();
+        assert_eq!(matcher.routes[0].preserve_host, Some(true));
+        assert_eq!(matcher.routes[1].preserve_host, Some(false));
+
+        let context = RequestContext { client_ip: None, peer_ip: None };
+        let target = Url::parse("http://backend:8080").unwrap();
+        let rewrite = |req: Request<Empty<Bytes>>, preserve_host: bool| {
+            ReverseProxy::rewrite_backend_request(req, &context, &target, preserve_host, false, None, true).unwrap()
+        };
+
+        // An HTTP/2 or absolute-form request names its host in the URI only
+        let absolute = Request::builder().uri("http://public.example.com/kept/a").body(Empty::<Bytes>::new()).unwrap();
+        let kept = rewrite(absolute, true);
+        assert_eq!(kept.headers()[HOST], "public.example.com");
+        assert_eq!(kept.headers()[&X_FORWARDED_HOST], "public.example.com");
+
+        let with_host = Request::builder()
+            .uri("/inherited/a")
+            .header(HOST, "public.example.com")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let rewritten = rewrite(with_host, false);
+        assert_eq!(rewritten.headers()[HOST], "backend:8080");
+        assert_eq!(rewritten.headers()[&X_FORWARDED_HOST], "public.example.com");
+    }
+
+    #[test]
+    fn json_body_predicates_select_routes_from_the_cached_body() {
+        let route = |id: &str, predicates: serde_json::Value| -> ReverseProxyRouteConfig {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "target": "http://backend:8080",
+                "predicates": predicates
+            }))
+            .unwrap()
+        };
+        let matcher = RouteMatcher::new(
+            vec![
+                route("mutations", serde_json::json!([
+                    { "type": "Path", "patterns": ["/graphql"] },
+                    { "type": "JsonBody", "field": "query", "regex": "^\\s*mutation\\b", "max_body_bytes": 4096 }
+                ])),
+                route("queries", serde_json::json!([{ "type": "Path", "patterns": ["/graphql"] }])),
+            ],
+            10,
+            None,
+        )
+        .unwrap();
+        assert_eq!(matcher.json_body_limit, Some(4096));
+
+        let context = RequestContext { client_ip: None, peer_ip: None };
+        let request = |body: &'static str| {
+            let mut req = Request::post("/graphql").body(()).unwrap();
+            JsonBody::cache(req.extensions_mut(), Bytes::from_static(body.as_bytes()));
+            req
+        };
+        let selected = |req: &Request<()>| matcher.select_route(req, &context).map(|r| r.id.clone());
+        assert_eq!(selected(&request(r#"{"query":"mutation { addOrder }"}"#)).as_deref(), Some("mutations"));
+        assert_eq!(selected(&request(r#"{"query":"{ orders }"}"#)).as_deref(), Some("queries"));
+        // Unread (chunked or oversized) bodies fall through to the next route
+        assert_eq!(selected(&Request::post("/graphql").body(()).unwrap()).as_deref(), Some("queries"));
+    }
+
+    #[tokio::test]
+    async fn route_limits_time_out_slow_backends_and_bodies() {
+        let route: ReverseProxyRouteConfig = serde_json::from_value(serde_json::json!({
+            "id": "api",
+            "target": "http://backend.example.com",
+            "predicates": [{ "type": "Path", "patterns": ["/**"] }],
+            "limits": { "response_timeout_secs": 1, "idle_timeout_secs": 1 }
+        }))
+        .unwrap();
+        let matcher = RouteMatcher::new(vec![route], 10, None).unwrap();
+        let route = &matcher.routes[0];
+
+        let stalled = ReverseProxy::within_response_timeout(route, std::future::pending()).await;
+        assert!(matches!(stalled, Err(RequestFailure::Timeout(limit)) if limit == Duration::from_secs(1)));
+
+        let idle = Some(Duration::from_secs(1));
+        let body = collect_body(Full::new(Bytes::from_static(b"done")), idle).await.unwrap();
+        assert_eq!(body, Bytes::from_static(b"done"));
+        let stalled_body = http_body_util::StreamBody::new(
+            futures::stream::pending::<Result<hyper::body::Frame<Bytes>, Infallible>>(),
+        );
+        let err = collect_body(stalled_body, idle).await.unwrap_err();
+        assert!(err.downcast_ref::<BodyIdleTimeout>().is_some());
+    }
+
+    #[tokio::test]
+    async fn replay_answers_from_recordings_while_backend_is_unavailable() {
+        let dir = tempfile::tempdir().unwrap();
+        let route: ReverseProxyRouteConfig = serde_json::from_value(serde_json::json!({
+            "id": "api",
+            "target": "http://backend.example.com",
+            "predicates": [{ "type": "Path", "patterns": ["/**"] }],
+            "replay": { "dir": dir.path() }
+        }))
+        .unwrap();
+        let matcher = RouteMatcher::new(vec![route], 10, None).unwrap();
+        let replay = matcher.routes[0].replay.as_ref().unwrap();
+        let key = replay.key(&Request::get("/users").body(()).unwrap()).unwrap();
+        let backend = |status: StatusCode, body: &'static str| {
+            let mut response = Response::new(ResponseBody::from(Full::new(Bytes::from_static(body.as_bytes()))));
+            *response.status_mut() = status;
+            Ok((response, None))
+        };
+        let limit = DEFAULT_MAX_BUFFER_BYTES;
+
+        let missing = ReverseProxy::record_or_replay(replay, &key, limit, Err(RequestFailure::Timeout(Duration::from_secs(1)))).await;
+        assert!(matches!(missing, Err(RequestFailure::Timeout(_))), "nothing recorded yet");
+        let Ok((live, _)) = ReverseProxy::record_or_replay(replay, &key, limit, backend(StatusCode::OK, "live")).await else {
+            panic!("a live response is passed through");
+        };
+        assert!(live.headers().get(crate::replay::REPLAY_HEADER).is_none());
+
+        for unavailable in [
+            Err(RequestFailure::Forward(ProxyError::Connection("refused".to_string()))),
+            backend(StatusCode::SERVICE_UNAVAILABLE, "down"),
+        ] {
+            let Ok((response, _)) = ReverseProxy::record_or_replay(replay, &key, limit, unavailable).await else {
+                panic!("the recording stands in for an unavailable backend");
+            };
+            assert_eq!(response.headers()[crate::replay::REPLAY_HEADER], "recorded");
+            assert_eq!(response.into_body().collect().await.unwrap().to_bytes(), "live");
+        }
+
+        // A response too large to hold is passed on whole but leaves the recording alone
+        let Ok((large, _)) = ReverseProxy::record_or_replay(replay, &key, 4, backend(StatusCode::OK, "much larger")).await else {
+            panic!("a large response is passed through");
+        };
+        assert_eq!(large.into_body().collect().await.unwrap().to_bytes(), "much larger");
+        let recorded = replay.load(&key).await.unwrap();
+        assert_eq!(recorded.into_body().collect().await.unwrap().to_bytes(), "live");
+    }
+
+    #[test]
+    fn test_backend_request_previews_routing_and_rewrite() {
+        let routes: Vec<ReverseProxyRouteConfig> = serde_json::from_value(serde_json::json!([{
+            "id": "api",
+            "target": "http://api.internal:8080",
+            "strip_path_prefix": "/api",
+            "predicates": [{ "type": "Path", "patterns": ["/api/**"] }]
+        }]))
+        .unwrap();
+        let proxy = ReverseProxy::new_with_routes(routes, 10, 90, 300, None, None).unwrap();
+        let context = RequestContext { client_ip: Some("10.1.2.3".to_string()), peer_ip: None };
+
+        let req = Request::get("/api/users?page=2").header("host", "proxy.example").body(()).unwrap();
+        assert_eq!(proxy.route_for(&req, &context), Some("api".to_string()));
+        let backend = proxy.backend_request(req, &context).unwrap();
+        assert_eq!(backend.uri().to_string(), "http://api.internal:8080/users?page=2");
+
+        let other = Request::get("/other").body(()).unwrap();
+        assert!(proxy.route_for(&other, &context).is_none());
+        assert!(matches!(proxy.backend_request(other, &context), Err(ProxyError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_header_case_record_is_dropped_only_where_it_would_leak() {
+        #[derive(Clone, Debug, PartialEq)]
+        struct EarlierExtension(u8);
+
+        let routes = |legacy_case: &str| -> Vec<ReverseProxyRouteConfig> {
+            serde_json::from_value(serde_json::json!([
+                { "id": "api", "target": "http://api.internal", "predicates": [{ "type": "Path", "patterns": ["/api/**"] }] },
+                {
+                    "id": "legacy",
+                    "target": "http://legacy.internal",
+                    "reverse_proxy_config": { "header_case": legacy_case },
+                    "predicates": [{ "type": "Path", "patterns": ["/legacy/**"] }]
+                }
+            ]))
+            .unwrap()
+        };
+        assert!(!ReverseProxy::new_with_routes(routes("title_case"), 10, 90, 300, None, None).unwrap().preserves_header_case());
+        let proxy = ReverseProxy::new_with_routes(routes("preserve"), 10, 90, 300, None, None).unwrap();
+        assert!(proxy.preserves_header_case());
+
+        let table = proxy.routes.current();
+        let route = |id: &str| table.routes.iter().find(|route| route.id == id).unwrap();
+        let request = |recorded: bool| {
+            let mut req = Request::get("/").body(()).unwrap();
+            req.extensions_mut().insert(EarlierExtension(7));
+            if recorded {
+                req.extensions_mut().insert(HeaderCaseRecorded);
+            }
+            req
+        };
+
+        let kept = route("api").apply_header_case(request(false));
+        assert_eq!(kept.extensions().get::<EarlierExtension>(), Some(&EarlierExtension(7)));
+        let kept = route("legacy").apply_header_case(request(true));
+        assert_eq!(kept.extensions().get::<EarlierExtension>(), Some(&EarlierExtension(7)));
+        let dropped = route("api").apply_header_case(request(true));
+        assert!(dropped.extensions().get::<HeaderCaseRecorded>().is_none());
+    }
 }
@@ -0,0 +1,318 @@
+//! Pre-selection of the routes a request can match.
+//!
+//! Evaluating every predicate of every route costs O(routes × regexes) per request, which adds
+//! up on gateways with hundreds of routes. The index narrows that to a candidate set from three
+//! cheap lookups: a map from method to the routes allowing it, a trie of host labels (right to
+//! left) and a trie of literal path segments. Candidates still go through their full predicates,
+//! so the index only has to never drop a route that could match; patterns it cannot reason about
+//! leave their route a candidate for every request.
+
+use crate::common::ascii_lowercase;
+use hyper::Method;
+use std::collections::HashMap;
+
+/// Method, host and path patterns of one route, as its predicates declare them. `None` means the
+/// route does not constrain that part of the request.
+#[derive(Debug, Default, Clone)]
+pub struct RouteKeys {
+    pub methods: Option<Vec<Method>>,
+    pub host_patterns: Option<Vec<String>>,
+    pub path_patterns: Option<Vec<String>>,
+}
+
+/// Candidate routes by method, host and path prefix.
+#[derive(Debug)]
+pub struct RouteIndex {
+    len: usize,
+    any_method: RouteSet,
+    by_method: HashMap<Method, RouteSet>,
+    any_host: RouteSet,
+    hosts: HostNode,
+    paths: PathNode,
+}
+
+impl RouteIndex {
+    /// Indexes routes in declaration order; candidates are reported by position in `routes`.
+    pub fn new(routes: &[RouteKeys]) -> Self {
+        let len = routes.len();
+        let mut index = Self {
+            len,
+            any_method: RouteSet::new(len),
+            by_method: HashMap::new(),
+            any_host: RouteSet::new(len),
+            hosts: HostNode::new(len),
+            paths: PathNode::new(len),
+        };
+        for (route, keys) in routes.iter().enumerate() {
+            match &keys.methods {
+                Some(methods) => {
+                    for method in methods {
+                        index.by_method.entry(method.clone()).or_insert_with(|| RouteSet::new(len)).insert(route);
+                    }
+                }
+                None => index.any_method.insert(route),
+            }
+            // One pattern the trie cannot hold leaves the route a candidate for every host
+            let host_keys = keys.host_patterns.as_ref().and_then(|patterns| {
+                patterns.iter().map(|pattern| host_key(pattern)).collect::<Option<Vec<_>>>()
+            });
+            match host_keys {
+                Some(keys) => {
+                    for key in keys {
+                        index.hosts.insert(key, route);
+                    }
+                }
+                _ => index.any_host.insert(route),
+            }
+            match &keys.path_patterns {
+                Some(patterns) => {
+                    for pattern in patterns {
+                        index.paths.insert(&path_prefix(pattern), route);
+                    }
+                }
+                None => index.paths.routes.insert(route),
+            }
+        }
+        // Routes without a method constraint allow every method, listed or not
+        for set in index.by_method.values_mut() {
+            set.union_with(&index.any_method);
+        }
+        index
+    }
+
+    /// Positions of the routes that may match, in ascending order.
+    pub fn candidates(&self, method: &Method, host: Option<&str>, path: &str) -> impl Iterator<Item = usize> + '_ {
+        let mut set = self.by_method.get(method).unwrap_or(&self.any_method).clone();
+
+        let mut hosts = self.any_host.clone();
+        if let Some(host) = host {
+            self.hosts.collect(&ascii_lowercase(host), &mut hosts);
+        }
+        set.intersect_with(&hosts);
+
+        let mut paths = RouteSet::new(self.len);
+        self.paths.collect(path, &mut paths);
+        set.intersect_with(&paths);
+        set.into_positions()
+    }
+}
+
+/// A host pattern the trie can hold: a literal name, or `*.` / `**.` before a literal suffix.
+enum HostKey {
+    Exact(Vec<String>),
+    Subdomain(Vec<String>),
+}
+
+fn host_key(pattern: &str) -> Option<HostKey> {
+    let literal = |s: &str| s.is_ascii() && !s.contains(['*', '?', '{']);
+    let labels = |s: &str| s.to_ascii_lowercase().rsplit('.').map(str::to_string).collect();
+    if literal(pattern) {
+        return Some(HostKey::Exact(labels(pattern)));
+    }
+    let suffix = pattern.strip_prefix("**.").or_else(|| pattern.strip_prefix("*."))?;
+    literal(suffix).then(|| HostKey::Subdomain(labels(suffix)))
+}
+
+/// Host labels from the right; `exact` routes match when the host ends here, `subdomain` routes
+/// when more labels follow.
+#[derive(Debug)]
+struct HostNode {
+    children: HashMap<String, HostNode>,
+    exact: RouteSet,
+    subdomain: RouteSet,
+}
+
+impl HostNode {
+    fn new(len: usize) -> Self {
+        Self { children: HashMap::new(), exact: RouteSet::new(len), subdomain: RouteSet::new(len) }
+    }
+
+    fn insert(&mut self, key: HostKey, route: usize) {
+        let len = self.exact.len();
+        let (labels, exact) = match key {
+            HostKey::Exact(labels) => (labels, true),
+            HostKey::Subdomain(labels) => (labels, false),
+        };
+        let mut node = self;
+        for label in labels {
+            node = node.children.entry(label).or_insert_with(|| HostNode::new(len));
+        }
+        if exact {
+            node.exact.insert(route);
+        } else {
+            node.subdomain.insert(route);
+        }
+    }
+
+    /// `host` must already be lowercase, as the labels in the trie are.
+    fn collect(&self, host: &str, out: &mut RouteSet) {
+        let mut labels = host.rsplit('.').peekable();
+        let mut node = self;
+        while let Some(label) = labels.next() {
+            let Some(child) = node.children.get(label) else {
+                return;
+            };
+            node = child;
+            if labels.peek().is_some() {
+                out.union_with(&node.subdomain);
+            } else {
+                out.union_with(&node.exact);
+            }
+        }
+    }
+}
+
+/// The whole segments of a path pattern before its first wildcard. `?` also matches `/`, so the
+/// segment holding a wildcard is never part of the prefix. Patterns not starting with `/` get
+/// no prefix and stay candidates for every path.
+fn path_prefix(pattern: &str) -> Vec<String> {
+    let Some(rest) = pattern.strip_prefix('/') else {
+        return Vec::new();
+    };
+    match rest.find(['*', '?', '{']) {
+        Some(wildcard) => match rest[..wildcard].rfind('/') {
+            Some(end) => rest[..end].split('/').map(str::to_string).collect(),
+            None => Vec::new(),
+        },
+        None => rest.split('/').map(str::to_string).collect(),
+    }
+}
+
+/// Path segments from the root; routes are held at the node where their literal prefix ends.
+#[derive(Debug)]
+struct PathNode {
+    children: HashMap<String, PathNode>,
+    routes: RouteSet,
+}
+
+impl PathNode {
+    fn new(len: usize) -> Self {
+        Self { children: HashMap::new(), routes: RouteSet::new(len) }
+    }
+
+    fn insert(&mut self, prefix: &[String], route: usize) {
+        let len = self.routes.len();
+        let mut node = self;
+        for segment in prefix {
+            node = node.children.entry(segment.clone()).or_insert_with(|| PathNode::new(len));
+        }
+        node.routes.insert(route);
+    }
+
+    fn collect(&self, path: &str, out: &mut RouteSet) {
+        out.union_with(&self.routes);
+        let Some(rest) = path.strip_prefix('/') else {
+            return;
+        };
+        let mut node = self;
+        for segment in rest.split('/') {
+            let Some(child) = node.children.get(segment) else {
+                return;
+            };
+            node = child;
+            out.union_with(&node.routes);
+        }
+    }
+}
+
+/// A fixed-size set of route positions.
+#[derive(Debug, Clone)]
+struct RouteSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl RouteSet {
+    fn new(len: usize) -> Self {
+        Self { words: vec![0; len.div_ceil(64)], len }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn insert(&mut self, route: usize) {
+        self.words[route / 64] |= 1 << (route % 64);
+    }
+
+    fn union_with(&mut self, other: &RouteSet) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    fn intersect_with(&mut self, other: &RouteSet) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word &= other;
+        }
+    }
+
+    fn into_positions(self) -> impl Iterator<Item = usize> {
+        self.words.into_iter().enumerate().flat_map(|(i, mut word)| {
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(i * 64 + bit)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(methods: Option<&[Method]>, hosts: Option<&[&str]>, paths: Option<&[&str]>) -> RouteKeys {
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+        RouteKeys {
+            methods: methods.map(<[Method]>::to_vec),
+            host_patterns: hosts.map(strings),
+            path_patterns: paths.map(strings),
+        }
+    }
+
+    #[test]
+    fn path_prefixes_stop_before_the_segment_with_a_wildcard() {
+        assert_eq!(path_prefix("/api/v1/**"), ["api", "v1"]);
+        assert_eq!(path_prefix("/api/v1/users/{id}"), ["api", "v1", "users"]);
+        assert_eq!(path_prefix("/api/v?/users"), ["api"]);
+        assert_eq!(path_prefix("/health"), ["health"]);
+        assert_eq!(path_prefix("/docs/"), ["docs", ""]);
+        assert!(path_prefix("/**").is_empty());
+        assert!(path_prefix("/static*").is_empty());
+        assert!(path_prefix("relative/**").is_empty());
+    }
+
+    #[test]
+    fn candidates_combine_method_host_and_path() {
+        let index = RouteIndex::new(&[
+            keys(None, None, Some(&["/api/orders/**"])),
+            keys(Some(&[Method::POST]), None, Some(&["/api/**"])),
+            keys(None, Some(&["shop.example.com"]), None),
+            keys(None, Some(&["*.example.org"]), Some(&["/static/{file}", "/assets/**"])),
+            keys(None, Some(&["api-?.example.com"]), Some(&["/**"])),
+        ]);
+        let candidates = |method: Method, host: Option<&str>, path: &str| {
+            index.candidates(&method, host, path).collect::<Vec<_>>()
+        };
+
+        assert_eq!(candidates(Method::GET, Some("other.net"), "/api/orders/7"), [0, 4]);
+        assert_eq!(candidates(Method::POST, Some("other.net"), "/api/orders/7"), [0, 1, 4]);
+        assert_eq!(candidates(Method::GET, Some("Shop.Example.COM"), "/anything"), [2, 4]);
+        assert_eq!(candidates(Method::GET, Some("cdn.eu.example.org"), "/assets/app.js"), [3, 4]);
+        assert_eq!(candidates(Method::GET, Some("example.org"), "/assets/app.js"), [4]);
+        assert_eq!(candidates(Method::GET, None, "/static/logo.png"), [4]);
+    }
+
+    #[test]
+    fn route_sets_span_several_words() {
+        let mut set = RouteSet::new(200);
+        for route in [0, 63, 64, 130, 199] {
+            set.insert(route);
+        }
+        assert_eq!(set.into_positions().collect::<Vec<_>>(), [0, 63, 64, 130, 199]);
+    }
+}
@@ -0,0 +1,263 @@
+//! Route metadata: ownership tags surfaced per request.
+//!
+//! Routes may carry free-form `metadata` (team, service, tier). Depending on `route_metadata`
+//! it is written to the access log, returned as response headers and attached as labels to the
+//! per-route request counter. Only allowlisted keys become metric labels, so tagging a route
+//! cannot blow up metric cardinality.
+
+use crate::access_log::{self, AccessLogListener, AccessLogPolicy, AccessRecord};
+use crate::config::RouteMetadataConfig;
+use crate::error::ProxyError;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::{Method, StatusCode, Version};
+use log::warn;
+use prometheus::{IntCounterVec, Opts, Registry};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::time::Duration;
+
+/// Labels every `bifrost_route_requests_total` series has, whatever the allowlist says.
+const FIXED_LABELS: [&str; 2] = ["route", "status_class"];
+
+/// The metadata of one route, prepared for use on every request.
+#[derive(Debug, Clone, Default)]
+pub struct RouteTags {
+    /// Sorted by key so log entries are stable
+    pairs: Vec<(String, String)>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    access_log: bool,
+    /// Replaces `access_log` when the reverse listener has an access log policy
+    access_policy: Option<Arc<AccessLogPolicy>>,
+}
+
+impl RouteTags {
+    pub fn new(metadata: &HashMap<String, String>) -> Self {
+        let mut pairs: Vec<(String, String)> =
+            metadata.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        pairs.sort();
+        Self { pairs, headers: Vec::new(), access_log: false, access_policy: None }
+    }
+
+    /// Samples and filters the route's access log entries with the listener's policy.
+    pub fn set_access_policy(&mut self, policy: Arc<AccessLogPolicy>) {
+        self.access_policy = Some(policy);
+    }
+
+    /// Applies the gateway-wide `route_metadata` settings; fails when a key or value cannot be
+    /// sent as a header.
+    pub fn apply(&mut self, route_id: &str, config: &RouteMetadataConfig) -> Result<(), ProxyError> {
+        self.access_log = config.access_log;
+        self.headers.clear();
+        let Some(prefix) = &config.response_header_prefix else {
+            return Ok(());
+        };
+        for (key, value) in &self.pairs {
+            let name = HeaderName::from_bytes(format!("{}{}", prefix, key).as_bytes()).map_err(|_| {
+                ProxyError::Config(format!(
+                    "Route {} metadata key {:?} does not form a valid header name with prefix {:?}",
+                    route_id, key, prefix
+                ))
+            })?;
+            let value = HeaderValue::from_str(value).map_err(|_| {
+                ProxyError::Config(format!(
+                    "Route {} metadata {} has a value that is not a valid header value",
+                    route_id, key
+                ))
+            })?;
+            self.headers.push((name, value));
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Adds the metadata headers to a response, counts the request and writes its access log entry.
+    pub fn finish(&self, route_id: &str, request: &RequestSummary, headers: &mut HeaderMap, status: StatusCode) {
+        for (name, value) in &self.headers {
+            headers.insert(name.clone(), value.clone());
+        }
+        route_request_telemetry().record(route_id, status, self);
+        let log = match &self.access_policy {
+            Some(policy) => policy.should_log(Some(route_id), status, request.elapsed),
+            None => self.access_log,
+        };
+        if log {
+            access_log::write(&AccessRecord {
+                listener: AccessLogListener::Reverse,
+                client_ip: request.client_ip.as_deref(),
+                method: &request.method,
+                uri: &request.path,
+                version: request.version,
+                status,
+                elapsed: request.elapsed,
+                upstream_time: request.upstream_time,
+                route: Some(route_id),
+                metadata: &self.pairs,
+            });
+        }
+    }
+}
+
+/// What the access log records about a request besides its route and status.
+pub struct RequestSummary {
+    pub client_ip: Option<String>,
+    pub method: Method,
+    pub path: String,
+    pub version: Version,
+    pub elapsed: Duration,
+    /// Until the backend's response headers arrived; `None` when no backend was asked
+    pub upstream_time: Option<Duration>,
+}
+
+/// Checks the metric label allowlist and fixes the label set of the per-route request counter.
+pub fn configure_metric_labels(labels: &[String]) -> Result<(), ProxyError> {
+    for label in labels {
+        let valid = label.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !label.starts_with("__");
+        if !valid || FIXED_LABELS.contains(&label.as_str()) {
+            return Err(ProxyError::Config(format!(
+                "route_metadata.metric_labels entry {:?} is not usable as a metric label",
+                label
+            )));
+        }
+    }
+    route_request_telemetry().configure(labels);
+    Ok(())
+}
+
+struct RouteRequestTelemetry {
+    /// Label names (fixed labels first) and the counter; created on first use, since the
+    /// allowlist is only known once the configuration is loaded
+    requests: OnceLock<(Vec<String>, IntCounterVec)>,
+    registry: Mutex<Option<Registry>>,
+    registered: AtomicBool,
+}
+
+impl RouteRequestTelemetry {
+    fn new() -> Self {
+        Self {
+            requests: OnceLock::new(),
+            registry: Mutex::new(None),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn configure(&self, metadata_labels: &[String]) {
+        let (labels, _) = self.requests.get_or_init(|| Self::build(metadata_labels));
+        if labels[FIXED_LABELS.len()..] != *metadata_labels {
+            warn!(
+                "route_metadata.metric_labels {:?} ignored; route_requests_total already uses {:?}",
+                metadata_labels,
+                &labels[FIXED_LABELS.len()..]
+            );
+        }
+        self.register_if_needed();
+    }
+
+    fn build(metadata_labels: &[String]) -> (Vec<String>, IntCounterVec) {
+        let labels: Vec<String> = FIXED_LABELS
+            .iter()
+            .map(|l| l.to_string())
+            .chain(metadata_labels.iter().cloned())
+            .collect();
+        let opts = Opts::new(
+            "route_requests_total",
+            "Reverse proxy requests per route, labelled with allowlisted route metadata",
+        ).namespace("bifrost");
+        let names: Vec<&str> = labels.iter().map(String::as_str).collect();
+        let counter = IntCounterVec::new(opts, &names).expect("route_requests_total metric");
+        (labels, counter)
+    }
+
+    fn record(&self, route_id: &str, status: StatusCode, tags: &RouteTags) {
+        let (labels, counter) = self.requests.get_or_init(|| Self::build(&[]));
+        self.register_if_needed();
+        let status_class = format!("{}xx", status.as_u16() / 100);
+        let values: Vec<&str> = [route_id, status_class.as_str()]
+            .into_iter()
+            .chain(labels[FIXED_LABELS.len()..].iter().map(|key| tags.get(key).unwrap_or("")))
+            .collect();
+        counter.with_label_values(&values).inc();
+    }
+
+    fn set_registry(&self, registry: &Registry) {
+        *self.registry.lock().unwrap_or_else(|e| e.into_inner()) = Some(registry.clone());
+        self.register_if_needed();
+    }
+
+    fn register_if_needed(&self) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        let (Some((_, counter)), Some(registry)) = (
+            self.requests.get(),
+            self.registry.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        ) else {
+            return;
+        };
+        if let Err(err) = registry.register(Box::new(counter.clone())) {
+            warn!("Failed to register route_requests_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn route_request_telemetry() -> &'static RouteRequestTelemetry {
+    static TELEMETRY: OnceLock<RouteRequestTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(RouteRequestTelemetry::new)
+}
+
+/// Registers the per-route request counter with the shared Prometheus registry. The counter
+/// itself is created once the metric label allowlist is known.
+pub fn register_route_metadata_metrics(registry: &Registry) {
+    route_request_telemetry().set_registry(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_becomes_headers_and_metric_labels() {
+        let metadata = HashMap::from([
+            ("team".to_string(), "payments".to_string()),
+            ("tier".to_string(), "gold".to_string()),
+        ]);
+        let mut tags = RouteTags::new(&metadata);
+        let config = RouteMetadataConfig {
+            access_log: true,
+            response_header_prefix: Some("X-Route-".to_string()),
+            metric_labels: vec!["team".to_string()],
+        };
+        tags.apply("payments", &config).unwrap();
+
+        let summary = RequestSummary {
+            client_ip: None,
+            method: Method::GET,
+            path: "/pay".to_string(),
+            version: Version::HTTP_11,
+            elapsed: Duration::from_millis(3),
+            upstream_time: None,
+        };
+        let mut headers = HeaderMap::new();
+        tags.finish("payments", &summary, &mut headers, StatusCode::OK);
+        assert_eq!(headers.get("x-route-team").unwrap(), "payments");
+        assert_eq!(headers.get("x-route-tier").unwrap(), "gold");
+
+        let telemetry = RouteRequestTelemetry::new();
+        telemetry.configure(&config.metric_labels);
+        telemetry.record("payments", StatusCode::BAD_GATEWAY, &tags);
+        let (_, counter) = telemetry.requests.get().unwrap();
+        assert_eq!(counter.with_label_values(&["payments", "5xx", "payments"]).get(), 1);
+
+        let bad_key = RouteTags::new(&HashMap::from([("bad key".to_string(), "x".to_string())]));
+        assert!(bad_key.clone().apply("r", &config).is_err());
+        assert!(configure_metric_labels(&["route".to_string()]).is_err());
+        assert!(configure_metric_labels(&["tier-level".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,421 @@
+//! Credentials read from outside the configuration file.
+//!
+//! A credential field written as `{secret}<provider>:<source>` is read from that provider before
+//! the proxy starts, and again every `secrets.refresh_secs`. A changed value replaces the old one
+//! in place (every clone of a [`Secret`] shares it), so the next connection or request uses it;
+//! connections that already authenticated keep going. A reference that cannot be read fails
+//! startup; on a later refresh the previous value stays in effect.
+
+use crate::config::Config;
+use crate::error::ProxyError;
+use crate::notifications::{Notification, NotificationKind};
+use crate::secrets::{config_secrets, Secret};
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Bytes;
+use hyper::{Request, Uri};
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use log::{info, warn};
+use prometheus::{IntCounterVec, Opts, Registry};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// Prefix of a credential read from a provider instead of the configuration file.
+pub const PROVIDER_PREFIX: &str = "{secret}";
+
+/// Where one `{secret}` reference is read from.
+enum Source {
+    /// `env:NAME`
+    Env(String),
+    /// `file:/path`, without the trailing newline
+    File(PathBuf),
+    /// `exec:program args...`, split on whitespace and run without a shell; stdout is the value
+    Exec(Vec<String>),
+    /// `vault:path#field`, from a KV v2 (`data.data.field`) or KV v1 (`data.field`) secret
+    Vault { path: String, field: String },
+}
+
+impl Source {
+    fn parse(field_name: &str, reference: &str) -> Result<Self, ProxyError> {
+        let invalid = |reason: &str| {
+            ProxyError::Config(format!("Invalid secret reference for {}: {}", field_name, reason))
+        };
+        let spec = reference.strip_prefix(PROVIDER_PREFIX).unwrap_or(reference);
+        let (provider, source) = spec
+            .split_once(':')
+            .ok_or_else(|| invalid("expected {secret}<provider>:<source>"))?;
+        let source = source.trim();
+        if source.is_empty() {
+            return Err(invalid("the source is empty"));
+        }
+        match provider {
+            "env" => Ok(Source::Env(source.to_string())),
+            "file" => Ok(Source::File(PathBuf::from(source))),
+            "exec" => Ok(Source::Exec(source.split_whitespace().map(str::to_string).collect())),
+            "vault" => match source.rsplit_once('#') {
+                Some((path, field)) if !path.is_empty() && !field.is_empty() => Ok(Source::Vault {
+                    path: path.trim_matches('/').to_string(),
+                    field: field.to_string(),
+                }),
+                _ => Err(invalid("expected vault:<path>#<field>")),
+            },
+            other => Err(invalid(&format!("unknown provider '{}' (expected env, file, exec or vault)", other))),
+        }
+    }
+
+    fn provider(&self) -> &'static str {
+        match self {
+            Source::Env(_) => "env",
+            Source::File(_) => "file",
+            Source::Exec(_) => "exec",
+            Source::Vault { .. } => "vault",
+        }
+    }
+}
+
+struct Entry {
+    field_name: String,
+    source: Source,
+    secret: Secret,
+}
+
+struct Vault {
+    address: String,
+    token: Secret,
+    namespace: Option<String>,
+    timeout: Duration,
+    client: Client<HttpsConnector<HttpConnector>, Empty<Bytes>>,
+}
+
+/// The `{secret}` references of a configuration and the providers to read them from.
+pub struct SecretProviders {
+    /// Vault references last, so a Vault token read from another provider is loaded first
+    entries: Vec<Entry>,
+    exec_timeout: Duration,
+    vault: Option<Vault>,
+    refresh: Option<Duration>,
+}
+
+impl SecretProviders {
+    pub fn from_config(config: &Config) -> Result<Self, ProxyError> {
+        let settings = config.secrets.clone().unwrap_or_default();
+        let mut entries = Vec::new();
+        for (field_name, secret) in config_secrets(config) {
+            let Some(reference) = secret.reference().filter(|r| r.starts_with(PROVIDER_PREFIX)) else {
+                continue;
+            };
+            let source = Source::parse(&field_name, reference)?;
+            if matches!(source, Source::Vault { .. }) {
+                if settings.vault.is_none() {
+                    return Err(ProxyError::Config(format!(
+                        "{} is read from Vault, but secrets.vault is not configured",
+                        field_name
+                    )));
+                }
+                if field_name == "config.secrets.vault.token" {
+                    return Err(ProxyError::Config("secrets.vault.token cannot be read from Vault".to_string()));
+                }
+            }
+            entries.push(Entry { field_name, source, secret: secret.clone() });
+        }
+        entries.sort_by_key(|entry| matches!(entry.source, Source::Vault { .. }));
+
+        let vault = match settings.vault {
+            Some(vault) => {
+                let address = vault.address.trim_end_matches('/').to_string();
+                address
+                    .parse::<Uri>()
+                    .map_err(|e| ProxyError::Config(format!("Invalid secrets.vault.address '{}': {}", address, e)))?;
+                Some(Vault {
+                    address,
+                    token: vault.token,
+                    namespace: vault.namespace,
+                    timeout: Duration::from_secs(vault.timeout_secs.max(1)),
+                    client: Client::builder(TokioExecutor::new()).build(HttpsConnector::new()),
+                })
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            entries,
+            exec_timeout: Duration::from_secs(settings.exec_timeout_secs.max(1)),
+            vault,
+            refresh: (settings.refresh_secs > 0).then(|| Duration::from_secs(settings.refresh_secs)),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Reads every reference; fails on the first one that cannot be read.
+    pub async fn load(&self) -> Result<(), ProxyError> {
+        for entry in &self.entries {
+            let value = self
+                .read(&entry.source)
+                .await
+                .map_err(|e| ProxyError::Config(format!("Failed to read secret for {}: {}", entry.field_name, e)))?;
+            entry.secret.set(value.as_str());
+            provider_telemetry().record(entry.source.provider(), "loaded");
+        }
+        info!("Loaded {} secret(s) from providers", self.entries.len());
+        Ok(())
+    }
+
+    /// Reads every reference again each `refresh_secs`; returns at once without it.
+    pub async fn refresh_loop(self) {
+        let Some(interval) = self.refresh else {
+            return;
+        };
+        loop {
+            tokio::time::sleep(interval).await;
+            self.refresh().await;
+        }
+    }
+
+    async fn refresh(&self) {
+        for entry in &self.entries {
+            let provider = entry.source.provider();
+            match self.read(&entry.source).await {
+                Ok(value) if *entry.secret.expose() == *value => {
+                    provider_telemetry().record(provider, "unchanged");
+                }
+                Ok(value) => {
+                    entry.secret.set(value.as_str());
+                    provider_telemetry().record(provider, "rotated");
+                    info!("Rotated secret for {} from {}", entry.field_name, provider);
+                    Notification::new(NotificationKind::SecretRotated, format!("Rotated secret for {}", entry.field_name))
+                        .detail("field", entry.field_name.as_str())
+                        .detail("provider", provider)
+                        .send();
+                }
+                Err(e) => {
+                    provider_telemetry().record(provider, "failed");
+                    warn!("Failed to refresh secret for {}: {}", entry.field_name, e);
+                    Notification::new(
+                        NotificationKind::SecretRefreshFailed,
+                        format!("Failed to refresh secret for {}: {}", entry.field_name, e),
+                    )
+                    .detail("field", entry.field_name.as_str())
+                    .detail("provider", provider)
+                    .send();
+                }
+            }
+        }
+    }
+
+    async fn read(&self, source: &Source) -> Result<Zeroizing<String>, String> {
+        let value = match source {
+            Source::Env(name) => {
+                Zeroizing::new(std::env::var(name).map_err(|e| format!("environment variable {}: {}", name, e))?)
+            }
+            Source::File(path) => Zeroizing::new(
+                tokio::fs::read_to_string(path)
+                    .await
+                    .map_err(|e| format!("failed to read {}: {}", path.display(), e))?,
+            ),
+            Source::Exec(command) => self.run(command).await?,
+            Source::Vault { path, field } => self.read_vault(path, field).await?,
+        };
+        let value = Zeroizing::new(value.trim_end_matches(['\r', '\n']).to_string());
+        if value.is_empty() {
+            return Err("the value is empty".to_string());
+        }
+        Ok(value)
+    }
+
+    async fn run(&self, command: &[String]) -> Result<Zeroizing<String>, String> {
+        let mut child = tokio::process::Command::new(&command[0]);
+        child
+            .args(&command[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        let output = tokio::time::timeout(self.exec_timeout, child.output())
+            .await
+            .map_err(|_| format!("'{}' did not finish within {:?}", command[0], self.exec_timeout))?
+            .map_err(|e| format!("failed to run '{}': {}", command[0], e))?;
+        let stdout = Zeroizing::new(output.stdout);
+        if !output.status.success() {
+            return Err(format!("'{}' exited with {}", command[0], output.status));
+        }
+        String::from_utf8(stdout.to_vec())
+            .map(Zeroizing::new)
+            .map_err(|_| format!("'{}' printed a value that is not UTF-8", command[0]))
+    }
+
+    async fn read_vault(&self, path: &str, field: &str) -> Result<Zeroizing<String>, String> {
+        let vault = self.vault.as_ref().ok_or("secrets.vault is not configured")?;
+        let url = format!("{}/v1/{}", vault.address, path);
+        let mut request = Request::get(&url).header("X-Vault-Token", vault.token.expose().as_str());
+        if let Some(namespace) = &vault.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        let request = request.body(Empty::new()).map_err(|e| e.to_string())?;
+
+        let response = tokio::time::timeout(vault.timeout, vault.client.request(request))
+            .await
+            .map_err(|_| format!("timed out reading {}", url))?
+            .map_err(|e| format!("failed to read {}: {}", url, e))?;
+        if !response.status().is_success() {
+            return Err(format!("{} returned {}", url, response.status()));
+        }
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| format!("failed to read {}: {}", url, e))?
+            .to_bytes();
+        let document: Value = serde_json::from_slice(&body).map_err(|e| format!("invalid response from {}: {}", url, e))?;
+        let data = &document["data"];
+        // KV v2 nests the secret under data.data; KV v1 keeps it directly under data
+        let value = data["data"][field].as_str().or_else(|| data[field].as_str());
+        value
+            .map(|value| Zeroizing::new(value.to_string()))
+            .ok_or_else(|| format!("{} has no string field '{}'", url, field))
+    }
+}
+
+/// Reads the `{secret}` references of `config` and, with `refresh_secs`, keeps reading them
+/// again in the background.
+pub async fn init(config: &Config) -> Result<(), ProxyError> {
+    let providers = SecretProviders::from_config(config)?;
+    if providers.is_empty() {
+        return Ok(());
+    }
+    providers.load().await?;
+    if providers.refresh.is_some() {
+        tokio::spawn(providers.refresh_loop());
+    }
+    Ok(())
+}
+
+struct ProviderTelemetry {
+    reads: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl ProviderTelemetry {
+    fn new() -> Self {
+        let reads = IntCounterVec::new(
+            Opts::new("secret_provider_reads_total", "Reads of {secret} references, by provider and outcome")
+                .namespace("bifrost"),
+            &["provider", "outcome"],
+        )
+        .expect("secret_provider_reads_total metric");
+        Self { reads, registered: AtomicBool::new(false) }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.reads.clone())) {
+            warn!("Failed to register secret provider metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    fn record(&self, provider: &str, outcome: &str) {
+        self.reads.with_label_values(&[provider, outcome]).inc();
+    }
+}
+
+fn provider_telemetry() -> &'static ProviderTelemetry {
+    static TELEMETRY: OnceLock<ProviderTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(ProviderTelemetry::new)
+}
+
+/// Registers the secret provider counters with the shared Prometheus registry.
+pub fn register_secret_provider_metrics(registry: &Registry) {
+    provider_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{SecretsConfig, TenantConfig, VaultConfig};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn reference(text: &str) -> Secret {
+        serde_json::from_value(Value::String(text.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn providers_resolve_and_rotate_shared_secrets() {
+        // A Vault stand-in that answers one KV v2 read with the token it was sent
+        let vault = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let vault_addr = vault.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = vault.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            let head = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+            assert!(head.starts_with("get /v1/secret/data/bifrost "));
+            assert!(head.contains("x-vault-token: root-token"));
+            let body = r#"{"data":{"data":{"admin":"from-vault"},"metadata":{"version":3}}}"#;
+            let response = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}", body.len(), body);
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let password_file = dir.path().join("proxy-password");
+        std::fs::write(&password_file, "first\n").unwrap();
+
+        let mut config = Config::default();
+        config.proxy_password = Some(reference(&format!("{{secret}}file:{}", password_file.display())));
+        config.relay_proxy_password = Some(reference("{secret}env:PATH"));
+        config.tenants = vec![TenantConfig {
+            id: "payments".to_string(),
+            routes: Vec::new(),
+            rate_limits: Vec::new(),
+            api_keys: Vec::new(),
+            quota: None,
+            admin_token: Some(reference("{secret}vault:secret/data/bifrost#admin")),
+        }];
+        config.secrets = Some(SecretsConfig {
+            vault: Some(VaultConfig {
+                address: format!("http://{}", vault_addr),
+                token: reference("{secret}exec:echo root-token"),
+                namespace: None,
+                timeout_secs: 5,
+            }),
+            ..SecretsConfig::default()
+        });
+        let holder = config.proxy_password.clone().unwrap();
+
+        let providers = SecretProviders::from_config(&config).unwrap();
+        providers.load().await.unwrap();
+        assert_eq!(holder.expose().as_str(), "first");
+        assert_eq!(*config.relay_proxy_password.as_ref().unwrap().expose(), std::env::var("PATH").unwrap());
+        assert_eq!(config.tenants[0].admin_token.as_ref().unwrap().expose().as_str(), "from-vault");
+
+        // A rotated file reaches every clone; the reference, not the value, is written back
+        std::fs::write(&password_file, "second\n").unwrap();
+        let file_only = SecretProviders {
+            entries: providers.entries.into_iter().filter(|e| e.source.provider() == "file").collect(),
+            ..SecretProviders::from_config(&Config::default()).unwrap()
+        };
+        file_only.refresh().await;
+        assert_eq!(holder.expose().as_str(), "second");
+        assert_eq!(format!("{:?} {}", holder, holder), "Secret(***) ***");
+        let written = serde_json::to_value(&holder).unwrap();
+        assert!(written.as_str().unwrap().starts_with("{secret}file:"));
+
+        assert!(Source::parse("f", "{secret}s3:bucket").is_err());
+        assert!(Source::parse("f", "{secret}vault:secret/data/x").is_err());
+        config.secrets = None;
+        assert!(SecretProviders::from_config(&config).is_err());
+    }
+}
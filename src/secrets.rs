@@ -1,4 +1,5 @@
-use crate::config::Config;
+use crate::config::{Config, RelayProxyConfig};
+use crate::secret_providers::PROVIDER_PREFIX;
 use aes_gcm::{aead::{Aead, KeyInit}, Aes256Gcm, Nonce};
 use base64::{engine::general_purpose, Engine as _};
 use log::{info, warn};
@@ -9,7 +10,9 @@ use std::fs;
 use std::path::PathBuf;
 #[cfg(unix)]
 use std::path::Path;
-use std::sync::{atomic::{AtomicBool, Ordering}, OnceLock};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc, OnceLock, RwLock};
 use thiserror::Error;
 use zeroize::Zeroizing;
 
@@ -109,6 +112,77 @@ pub fn register_secret_metrics(registry: &Registry) {
     telemetry().register_if_needed(registry);
 }
 
+/// A credential from the configuration, such as a proxy or relay password.
+///
+/// Clones share one value, so a rotated secret reaches every holder. The value is zeroed when it
+/// is replaced and when the last holder drops it, and `Debug` and `Display` print `***` instead.
+/// A value written as an `{encrypted}` or `{secret}` reference serializes back as the reference.
+#[derive(Clone)]
+pub struct Secret {
+    value: Arc<RwLock<Zeroizing<String>>>,
+    reference: Option<Arc<str>>,
+}
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self {
+            value: Arc::new(RwLock::new(Zeroizing::new(value.into()))),
+            reference: None,
+        }
+    }
+
+    /// A copy of the current value, zeroed when dropped.
+    pub fn expose(&self) -> Zeroizing<String> {
+        self.value.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The `{encrypted}` or `{secret}` text the value was configured as.
+    pub fn reference(&self) -> Option<&str> {
+        self.reference.as_deref()
+    }
+
+    /// Replaces the value for every holder.
+    pub fn set(&self, value: impl Into<String>) {
+        *self.value.write().unwrap_or_else(|e| e.into_inner()) = Zeroizing::new(value.into());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.value.read().unwrap_or_else(|e| e.into_inner()).is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("***")
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.reference() {
+            Some(reference) => serializer.serialize_str(reference),
+            None => serializer.serialize_str(&self.expose()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Zeroizing::new(String::deserialize(deserializer)?);
+        let mut secret = Secret::new(value.as_str());
+        if value.starts_with(ENCRYPTED_PREFIX) || value.starts_with(PROVIDER_PREFIX) {
+            secret.reference = Some(Arc::from(value.as_str()));
+        }
+        Ok(secret)
+    }
+}
+
 /// Helper responsible for encrypting, decrypting, and persisting secrets.
 pub struct SecretManager {
     root_dir: PathBuf,
@@ -215,29 +289,28 @@ impl SecretManager {
         ENCRYPTED_PREFIX
     }
 
-    pub fn apply_to_config(&self, config: &mut Config) -> Result<(), SecretError> {
-        self.apply_to_top_level(config)?;
-        self.apply_to_relays(config)?;
-        Ok(())
-    }
-
-    fn apply_to_top_level(&self, config: &mut Config) -> Result<(), SecretError> {
-        self.decrypt_option_field(&mut config.proxy_password, "config.proxy_password")?;
-        self.decrypt_option_field(
-            &mut config.relay_proxy_password,
-            "config.relay_proxy_password",
-        )?;
-        Ok(())
+    /// Decrypts a `{encrypted}` secret in place. Returns `true` if decrypted.
+    pub fn decrypt_secret(&self, secret: &Secret, field_name: &str) -> Result<bool, SecretError> {
+        let Some(payload) = secret.reference().and_then(|r| r.strip_prefix(ENCRYPTED_PREFIX)) else {
+            return Ok(false);
+        };
+        match self.decrypt_secret_string(payload) {
+            Ok(plaintext) => {
+                secret.set(plaintext);
+                telemetry().inc_success();
+                info!("Decrypted encrypted secret for {}", field_name);
+                Ok(true)
+            }
+            Err(err) => {
+                telemetry().inc_failure();
+                Err(err)
+            }
+        }
     }
 
-    fn apply_to_relays(&self, config: &mut Config) -> Result<(), SecretError> {
-        if let Some(relays) = config.relay_proxies.as_mut() {
-            for (idx, relay) in relays.iter_mut().enumerate() {
-                self.decrypt_option_field(
-                    &mut relay.relay_proxy_password,
-                    &format!("config.relay_proxies[{}].relay_proxy_password", idx),
-                )?;
-            }
+    pub fn apply_to_config(&self, config: &Config) -> Result<(), SecretError> {
+        for (field_name, secret) in config_secrets(config) {
+            self.decrypt_secret(secret, &field_name)?;
         }
         Ok(())
     }
@@ -387,28 +460,67 @@ fn resolve_secret_home() -> Result<PathBuf, SecretError> {
     Ok(home.join(".bifrost"))
 }
 
-fn option_needs_decrypt(value: &Option<String>) -> bool {
-    value
-        .as_ref()
-        .map(|v| v.starts_with(ENCRYPTED_PREFIX))
-        .unwrap_or(false)
-}
+/// Every credential in `config` with its field path.
+pub fn config_secrets(config: &Config) -> Vec<(String, &Secret)> {
+    fn relays<'a>(prefix: &str, relays: &'a [RelayProxyConfig], out: &mut Vec<(String, &'a Secret)>) {
+        for (idx, relay) in relays.iter().enumerate() {
+            if let Some(password) = &relay.relay_proxy_password {
+                out.push((format!("{}[{}].relay_proxy_password", prefix, idx), password));
+            }
+        }
+    }
 
-pub fn config_has_encrypted_values(config: &Config) -> bool {
-    if option_needs_decrypt(&config.proxy_password)
-        || option_needs_decrypt(&config.relay_proxy_password)
-    {
-        return true;
-    }
-    if let Some(relays) = &config.relay_proxies {
-        if relays
-            .iter()
-            .any(|relay| option_needs_decrypt(&relay.relay_proxy_password))
-        {
-            return true;
+    let mut secrets = Vec::new();
+    if let Some(password) = &config.proxy_password {
+        secrets.push(("config.proxy_password".to_string(), password));
+    }
+    if let Some(password) = &config.relay_proxy_password {
+        secrets.push(("config.relay_proxy_password".to_string(), password));
+    }
+    relays("config.relay_proxies", config.relay_proxies.as_deref().unwrap_or_default(), &mut secrets);
+    if let Some(reverse) = &config.reverse_proxy_config {
+        relays("config.reverse_proxy_config.relay_proxies", &reverse.relay_proxies, &mut secrets);
+    }
+    for (idx, route) in config.reverse_proxy_routes.iter().enumerate() {
+        if let Some(reverse) = &route.reverse_proxy_config {
+            let prefix = format!("config.reverse_proxy_routes[{}].reverse_proxy_config.relay_proxies", idx);
+            relays(&prefix, &reverse.relay_proxies, &mut secrets);
+        }
+        if let Some(hmac) = &route.hmac_verification {
+            secrets.push((format!("config.reverse_proxy_routes[{}].hmac_verification.secret", idx), &hmac.secret));
+        }
+    }
+    if let Some(api_keys) = &config.api_keys {
+        for (idx, entry) in api_keys.keys.iter().enumerate() {
+            secrets.push((format!("config.api_keys.keys[{}].key", idx), &entry.key));
+        }
+    }
+    for (idx, tenant) in config.tenants.iter().enumerate() {
+        if let Some(token) = &tenant.admin_token {
+            secrets.push((format!("config.tenants[{}].admin_token", idx), token));
         }
     }
-    false
+    if let Some(push) = &config.metrics_push {
+        if let Some(token) = &push.bearer_token {
+            secrets.push(("config.metrics_push.bearer_token".to_string(), token));
+        }
+        if let Some(password) = &push.password {
+            secrets.push(("config.metrics_push.password".to_string(), password));
+        }
+    }
+    if let Some(token) = config.admin_api.as_ref().and_then(|admin| admin.token.as_ref()) {
+        secrets.push(("config.admin_api.token".to_string(), token));
+    }
+    if let Some(vault) = config.secrets.as_ref().and_then(|s| s.vault.as_ref()) {
+        secrets.push(("config.secrets.vault.token".to_string(), &vault.token));
+    }
+    secrets
+}
+
+pub fn config_has_encrypted_values(config: &Config) -> bool {
+    config_secrets(config)
+        .iter()
+        .any(|(_, secret)| secret.reference().is_some_and(|r| r.starts_with(ENCRYPTED_PREFIX)))
 }
 
 #[cfg(test)]
@@ -0,0 +1,234 @@
+//! Security event stream.
+//!
+//! Failed proxy authentication, lockouts, rejected API keys and signatures, refused
+//! destinations, rate limit hits, content blocks and failed TLS handshakes are emitted as
+//! structured events, one JSON object per line, to a sink of their own (`logging.security`), so a
+//! SIEM can ingest them without parsing the general log or the access log.
+
+use crate::config::{LogOutputType, SecurityLogConfig};
+use crate::error::ProxyError;
+use crate::privacy;
+use chrono::Utc;
+use log::{info, warn};
+use prometheus::{IntCounterVec, Opts, Registry};
+use serde_json::{Map, Value, json};
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// What kind of security event happened; the `event` field and metric label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityEventKind {
+    /// Wrong proxy credentials
+    AuthFailure,
+    /// Client refused while locked out after repeated authentication failures
+    AuthLockedOut,
+    /// Missing, unknown or expired API key on a route that requires one
+    ApiKeyRejected,
+    /// Request signature did not verify
+    SignatureRejected,
+    /// Destination refused by policy (e.g. CONNECT port not allowed)
+    DestinationBlocked,
+    /// Rate limit rule or deny list hit
+    RateLimited,
+    /// Request or response blocked by the content hook or download scanner
+    ContentBlocked,
+    /// TLS handshake with a client failed
+    TlsHandshakeFailed,
+    /// Request for a tarpit path
+    ScannerProbe,
+    /// Client banned for a cooldown period
+    ClientBanned,
+}
+
+impl SecurityEventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SecurityEventKind::AuthFailure => "auth_failure",
+            SecurityEventKind::AuthLockedOut => "auth_locked_out",
+            SecurityEventKind::ApiKeyRejected => "api_key_rejected",
+            SecurityEventKind::SignatureRejected => "signature_rejected",
+            SecurityEventKind::DestinationBlocked => "destination_blocked",
+            SecurityEventKind::RateLimited => "rate_limited",
+            SecurityEventKind::ContentBlocked => "content_blocked",
+            SecurityEventKind::TlsHandshakeFailed => "tls_handshake_failed",
+            SecurityEventKind::ScannerProbe => "scanner_probe",
+            SecurityEventKind::ClientBanned => "client_banned",
+        }
+    }
+}
+
+/// One security event, built up with the optional fields that apply and then emitted.
+#[derive(Debug, Clone)]
+pub struct SecurityEvent {
+    kind: SecurityEventKind,
+    reason: String,
+    client_ip: Option<String>,
+    target: Option<String>,
+    route: Option<String>,
+}
+
+impl SecurityEvent {
+    pub fn new(kind: SecurityEventKind, reason: impl Into<String>) -> Self {
+        Self { kind, reason: reason.into(), client_ip: None, target: None, route: None }
+    }
+
+    pub fn client_ip(mut self, client_ip: Option<&str>) -> Self {
+        self.client_ip = client_ip.map(|ip| privacy::client_ip(ip).into_owned());
+        self
+    }
+
+    /// The destination, URL or path the request was for.
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn route(mut self, route: &str) -> Self {
+        self.route = Some(route.to_string());
+        self
+    }
+
+    /// Counts the event and writes it to the security sink, when one is configured.
+    pub fn emit(self) {
+        security_telemetry().events.with_label_values(&[self.kind.as_str()]).inc();
+        if let Some(sink) = SINK.get() {
+            sink.write(&self);
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let mut event = Map::new();
+        event.insert("timestamp".to_string(), json!(Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()));
+        event.insert("event".to_string(), json!(self.kind.as_str()));
+        let optional = [("client_ip", &self.client_ip), ("target", &self.target), ("route", &self.route)];
+        for (name, value) in optional {
+            if let Some(value) = value {
+                event.insert(name.to_string(), json!(value));
+            }
+        }
+        event.insert("reason".to_string(), json!(self.reason));
+        Value::Object(event).to_string()
+    }
+}
+
+static SINK: OnceLock<SecuritySink> = OnceLock::new();
+
+/// Where security events are written.
+pub struct SecuritySink {
+    writer: Mutex<BufWriter<Box<dyn Write + Send>>>,
+    main_log: bool,
+}
+
+impl SecuritySink {
+    pub fn open(config: &SecurityLogConfig) -> Result<Self, ProxyError> {
+        let writer: Box<dyn Write + Send> = match config.output_type {
+            LogOutputType::Stdout => Box::new(std::io::stdout()),
+            LogOutputType::File => {
+                let path = config.path.as_ref().ok_or_else(|| {
+                    ProxyError::Config("logging.security: file output requires path".to_string())
+                })?;
+                let file = OpenOptions::new().create(true).append(true).open(path).map_err(|e| {
+                    ProxyError::Config(format!("logging.security: cannot open {}: {}", path.display(), e))
+                })?;
+                Box::new(file)
+            }
+        };
+        Ok(Self { writer: Mutex::new(BufWriter::new(writer)), main_log: config.main_log })
+    }
+
+    fn write(&self, event: &SecurityEvent) {
+        let line = event.to_json();
+        if self.main_log {
+            info!(target: "bifrost::security", "{}", line);
+        }
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writeln!(writer, "{}", line);
+            let _ = writer.flush();
+        }
+    }
+}
+
+/// Opens the security event sink; events before this call, or without it, are only counted.
+pub fn init(config: &SecurityLogConfig) -> Result<(), ProxyError> {
+    let sink = SecuritySink::open(config)?;
+    if SINK.set(sink).is_err() {
+        warn!("Security event sink already initialized; keeping the first one");
+    }
+    Ok(())
+}
+
+struct SecurityTelemetry {
+    events: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl SecurityTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new("security_events_total", "Security events by kind").namespace("bifrost");
+        Self {
+            events: IntCounterVec::new(opts, &["event"]).expect("security_events_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.events.clone())) {
+            warn!("Failed to register security_events_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn security_telemetry() -> &'static SecurityTelemetry {
+    static TELEMETRY: OnceLock<SecurityTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(SecurityTelemetry::new)
+}
+
+/// Registers the security event counter with the shared Prometheus registry.
+pub fn register_security_metrics(registry: &Registry) {
+    security_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_written_as_json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("security.log");
+        let sink = SecuritySink::open(&SecurityLogConfig {
+            output_type: LogOutputType::File,
+            path: Some(path.clone()),
+            main_log: false,
+        })
+        .unwrap();
+
+        sink.write(
+            &SecurityEvent::new(SecurityEventKind::AuthFailure, "invalid credentials")
+                .client_ip(Some("10.0.0.9"))
+                .target("example.com:443"),
+        );
+        sink.write(&SecurityEvent::new(SecurityEventKind::RateLimited, "rule api").route("api"));
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let events: Vec<Value> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["event"], "auth_failure");
+        assert_eq!(events[0]["client_ip"], "10.0.0.9");
+        assert_eq!(events[0]["target"], "example.com:443");
+        assert_eq!(events[0]["reason"], "invalid credentials");
+        assert!(events[0].get("route").is_none(), "unset fields are left out");
+        assert_eq!(events[1]["event"], "rate_limited");
+        assert_eq!(events[1]["route"], "api");
+
+        let missing_path = SecurityLogConfig { output_type: LogOutputType::File, path: None, main_log: false };
+        assert!(SecuritySink::open(&missing_path).is_err());
+    }
+}
@@ -0,0 +1,394 @@
+//! End-to-end self-test behind `bifrost-bridge selftest`.
+//!
+//! Boots the configured proxy on loopback ports picked at random, with every backend pointed at
+//! an in-process stub, and sends real traffic through it: a static file GET, a reverse proxied
+//! GET, a CONNECT tunnel to the stub, and requests that must be refused for missing credentials.
+//! Relay proxies are bypassed and TLS listeners serve plain HTTP, so only the proxy itself is
+//! under test.
+
+use crate::bench::basic_authorization;
+use crate::config::{Config, ConnectPortOverrideConfig, ProxyMode, ReverseProxyRouteConfig, RoutePredicateConfig};
+use crate::error::ProxyError;
+use crate::preflight::{CheckStatus, PreflightReport};
+use crate::proxy::ProxyFactory;
+use crate::secrets::Secret;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Empty, Full};
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::net::SocketAddr;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{Duration, Instant, timeout};
+
+/// Body the stub backend answers every request with.
+const STUB_BODY: &str = "bifrost selftest";
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Listeners of the booted proxy.
+#[derive(Debug, Default)]
+struct Listeners {
+    /// Reverse proxy and static files
+    web: Option<SocketAddr>,
+    forward: Option<SocketAddr>,
+}
+
+/// Boots the proxy described by `config` and runs every check that applies to it.
+pub async fn run(config: Config) -> Result<PreflightReport, ProxyError> {
+    let mut report = PreflightReport::default();
+    let stub = start_stub().await?;
+    let (config, listeners) = prepare(config, stub, &mut report)?;
+
+    let proxy = ProxyFactory::create_proxy(config.clone())?;
+    let server = tokio::spawn(proxy.run());
+    for addr in listeners.web.iter().chain(listeners.forward.iter()) {
+        if let Err(detail) = wait_for_listener(*addr, &server).await {
+            report.record(format!("startup {}", addr), CheckStatus::Fail, detail);
+            server.abort();
+            return Ok(report);
+        }
+    }
+    report.record("startup", CheckStatus::Pass, "all listeners accepting connections");
+
+    if let Some(web) = listeners.web {
+        check_static(&config, web, &mut report).await;
+        check_reverse(&config, web, &mut report).await;
+    }
+    if let Some(forward) = listeners.forward {
+        check_connect(&config, forward, stub, &mut report).await;
+    }
+    let forward_auth = listeners.forward.is_some() && config.proxy_username.is_some();
+    let key_auth = listeners.web.is_some() && config.reverse_proxy_routes.iter().any(|route| route.require_api_key);
+    if !forward_auth && !key_auth {
+        report.record("auth rejection", CheckStatus::Warn, "no proxy credentials or API key routes configured");
+    }
+
+    server.abort();
+    Ok(report)
+}
+
+/// Moves the listeners to free loopback ports and points every outbound dependency at `stub`.
+fn prepare(mut config: Config, stub: SocketAddr, report: &mut PreflightReport) -> Result<(Config, Listeners), ProxyError> {
+    let mut listeners = Listeners::default();
+    config.listen_addr = free_port()?;
+    match config.mode {
+        ProxyMode::Forward => listeners.forward = Some(config.listen_addr),
+        ProxyMode::Reverse => listeners.web = Some(config.listen_addr),
+        ProxyMode::Combined => {
+            let forward = free_port()?;
+            config.forward_listen_addr = Some(forward);
+            listeners.web = Some(config.listen_addr);
+            listeners.forward = Some(forward);
+        }
+    }
+    if let Some(addr) = config.monitoring.listen_address.as_mut() {
+        *addr = free_port()?;
+    }
+
+    if config.private_key.is_some() || config.certificate.is_some() {
+        config.private_key = None;
+        config.certificate = None;
+        report.record("tls", CheckStatus::Warn, "TLS listeners are tested over plain HTTP");
+    }
+    if !config.effective_relay_proxies().is_empty() {
+        config.relay_proxies = None;
+        config.relay_proxy_url = None;
+        report.record("relay", CheckStatus::Warn, "relay proxies are bypassed");
+    }
+
+    let stub_url = format!("http://{}", stub);
+    if config.reverse_proxy_target.is_some() {
+        config.reverse_proxy_target = Some(stub_url.clone());
+    }
+    for route in &mut config.reverse_proxy_routes {
+        if route.target.is_some() {
+            route.target = Some(stub_url.clone());
+        }
+        for target in &mut route.targets {
+            target.url = stub_url.clone();
+        }
+    }
+    // The stub listens on a port the CONNECT policy would otherwise refuse
+    config
+        .forward_proxy_config
+        .get_or_insert_with(Default::default)
+        .connect_ports
+        .get_or_insert_with(Default::default)
+        .overrides
+        .push(ConnectPortOverrideConfig { domains: vec![stub.ip().to_string()], ports: vec![stub.port()] });
+
+    Ok((config, listeners))
+}
+
+async fn check_static(config: &Config, web: SocketAddr, report: &mut PreflightReport) {
+    let Some(static_files) = &config.static_files else {
+        return;
+    };
+    let probe = static_files.mounts.iter().find_map(|mount| {
+        first_file(Path::new(&mount.root_dir))
+            .map(|(name, contents)| (format!("{}/{}", mount.path.trim_end_matches('/'), name), contents))
+    });
+    let Some((path, contents)) = probe else {
+        report.record("static GET", CheckStatus::Warn, "no file to request under any mount");
+        return;
+    };
+
+    let (status, detail) = match get(web, &path).await {
+        Ok((StatusCode::OK, body)) if body == contents => (CheckStatus::Pass, format!("{} served", path)),
+        Ok((StatusCode::OK, _)) => (CheckStatus::Fail, format!("{} served different content", path)),
+        Ok((status, _)) => (CheckStatus::Fail, format!("{} answered {}", path, status)),
+        Err(e) => (CheckStatus::Fail, format!("{}: {}", path, e)),
+    };
+    report.record("static GET", status, detail);
+}
+
+async fn check_reverse(config: &Config, web: SocketAddr, report: &mut PreflightReport) {
+    if config.reverse_proxy_routes.is_empty() {
+        if config.reverse_proxy_target.is_some() {
+            expect_stub(web, "/", report).await;
+        }
+        return;
+    }
+
+    match probe_path(&config.reverse_proxy_routes, false) {
+        Some(path) => expect_stub(web, &path, report).await,
+        None => report.record("reverse GET", CheckStatus::Warn, "no route with only path and method predicates"),
+    }
+    if let Some(path) = probe_path(&config.reverse_proxy_routes, true) {
+        let (status, detail) = match get(web, &path).await {
+            Ok((StatusCode::UNAUTHORIZED, _)) => (CheckStatus::Pass, format!("{} without a key answered 401", path)),
+            Ok((status, _)) => (CheckStatus::Fail, format!("{} without a key answered {}", path, status)),
+            Err(e) => (CheckStatus::Fail, format!("{}: {}", path, e)),
+        };
+        report.record("api key rejection", status, detail);
+    }
+}
+
+async fn expect_stub(web: SocketAddr, path: &str, report: &mut PreflightReport) {
+    let (status, detail) = match get(web, path).await {
+        Ok((StatusCode::OK, body)) if body == STUB_BODY.as_bytes() => {
+            (CheckStatus::Pass, format!("{} reached the stub backend", path))
+        }
+        Ok((status, _)) => (CheckStatus::Fail, format!("{} answered {} instead of the stub response", path, status)),
+        Err(e) => (CheckStatus::Fail, format!("{}: {}", path, e)),
+    };
+    report.record("reverse GET", status, detail);
+}
+
+async fn check_connect(config: &Config, forward: SocketAddr, stub: SocketAddr, report: &mut PreflightReport) {
+    let authorization = config.proxy_username.as_deref().map(|user| {
+        basic_authorization(user, &config.proxy_password.as_ref().map(Secret::expose).unwrap_or_default())
+    });
+    let (status, detail) = match connect(forward, stub, authorization.as_deref()).await {
+        Ok((200, Some(reply))) if reply.ends_with(STUB_BODY) => {
+            (CheckStatus::Pass, format!("tunnel to {} carried a request", stub))
+        }
+        Ok((200, _)) => (CheckStatus::Fail, format!("tunnel to {} opened but returned no stub response", stub)),
+        Ok((code, _)) => (CheckStatus::Fail, format!("CONNECT {} answered {}", stub, code)),
+        Err(e) => (CheckStatus::Fail, format!("CONNECT {}: {}", stub, e)),
+    };
+    report.record("CONNECT tunnel", status, detail);
+
+    if config.proxy_username.is_some() {
+        let (status, detail) = match connect(forward, stub, None).await {
+            Ok((407, _)) => (CheckStatus::Pass, "CONNECT without credentials answered 407".to_string()),
+            Ok((code, _)) => (CheckStatus::Fail, format!("CONNECT without credentials answered {}", code)),
+            Err(e) => (CheckStatus::Fail, e.to_string()),
+        };
+        report.record("proxy auth rejection", status, detail);
+    }
+}
+
+/// A request path for the first route matched on path (and GET) alone, where that route's
+/// `require_api_key` equals `require_api_key`.
+fn probe_path(routes: &[ReverseProxyRouteConfig], require_api_key: bool) -> Option<String> {
+    routes
+        .iter()
+        .filter(|route| {
+            route.require_api_key == require_api_key
+                && route.aggregate.is_none()
+                && route.static_response.is_none()
+                && route.hmac_verification.is_none()
+        })
+        .filter(|route| {
+            route.predicates.iter().all(|predicate| match predicate {
+                RoutePredicateConfig::Path { .. } => true,
+                RoutePredicateConfig::Method { methods } => methods.iter().any(|m| m.eq_ignore_ascii_case("GET")),
+                _ => false,
+            })
+        })
+        .find_map(|route| {
+            route.predicates.iter().find_map(|predicate| match predicate {
+                RoutePredicateConfig::Path { patterns, .. } => patterns.first().map(|pattern| concrete_path(pattern)),
+                _ => None,
+            })
+        })
+}
+
+/// Fills the wildcards and variables of an Ant-style pattern with a literal segment.
+fn concrete_path(pattern: &str) -> String {
+    let segments: Vec<&str> = pattern
+        .trim_start_matches('/')
+        .split('/')
+        .map(|segment| if segment.contains('*') || segment.contains('{') { "selftest" } else { segment })
+        .collect();
+    format!("/{}", segments.join("/"))
+}
+
+/// The first regular file directly under `dir` whose name needs no URL encoding.
+fn first_file(dir: &Path) -> Option<(String, Vec<u8>)> {
+    let mut entries: Vec<_> = std::fs::read_dir(dir).ok()?.flatten().map(|entry| entry.path()).collect();
+    entries.sort();
+    entries.into_iter().find_map(|path| {
+        let name = path.file_name()?.to_str()?.to_string();
+        let plain = !name.starts_with('.')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+        if !plain || !path.is_file() {
+            return None;
+        }
+        std::fs::read(&path).ok().map(|contents| (name, contents))
+    })
+}
+
+fn free_port() -> Result<SocketAddr, ProxyError> {
+    Ok(std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?)
+}
+
+/// Serves [`STUB_BODY`] to every request on a loopback port.
+async fn start_stub() -> Result<SocketAddr, ProxyError> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let service = hyper::service::service_fn(|_req| async {
+                    Ok::<_, std::convert::Infallible>(Response::new(Full::new(Bytes::from_static(STUB_BODY.as_bytes()))))
+                });
+                let _ = hyper::server::conn::http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+    Ok(addr)
+}
+
+async fn wait_for_listener(addr: SocketAddr, server: &tokio::task::JoinHandle<Result<(), ProxyError>>) -> Result<(), String> {
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    loop {
+        if TcpStream::connect(addr).await.is_ok() {
+            return Ok(());
+        }
+        if server.is_finished() {
+            return Err("the proxy stopped before accepting connections".to_string());
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("not accepting connections after {:?}", STARTUP_TIMEOUT));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+async fn get(addr: SocketAddr, path: &str) -> Result<(StatusCode, Bytes), ProxyError> {
+    let exchange = async {
+        let stream = TcpStream::connect(addr).await?;
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .map_err(|e| ProxyError::Hyper(e.to_string()))?;
+        tokio::spawn(async move {
+            let _ = connection.await;
+        });
+        let request = Request::get(path)
+            .header(hyper::header::HOST, addr.to_string())
+            .body(Empty::<Bytes>::new()).map_err(|e| ProxyError::Http(e.to_string()))?;
+        let response = sender.send_request(request).await.map_err(|e| ProxyError::Hyper(e.to_string()))?;
+        let status = response.status();
+        let body = response.into_body().collect().await.map_err(|e| ProxyError::Hyper(e.to_string()))?;
+        Ok((status, body.to_bytes()))
+    };
+    timeout(CHECK_TIMEOUT, exchange)
+        .await
+        .map_err(|_| ProxyError::Connection(format!("no response within {:?}", CHECK_TIMEOUT)))?
+}
+
+/// Opens a CONNECT tunnel to `target` and, once established, sends a GET through it. Returns the
+/// proxy's status and the tunneled response.
+async fn connect(proxy: SocketAddr, target: SocketAddr, authorization: Option<&str>) -> Result<(u16, Option<String>), ProxyError> {
+    let exchange = async {
+        let mut stream = TcpStream::connect(proxy).await?;
+        let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+        if let Some(authorization) = authorization {
+            request.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        let mut reader = BufReader::new(stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line).await?;
+        let code = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(|| ProxyError::Http(format!("unexpected CONNECT response '{}'", status_line.trim())))?;
+        let mut line = String::new();
+        while reader.read_line(&mut line).await? > 2 {
+            line.clear();
+        }
+        if code != 200 {
+            return Ok((code, None));
+        }
+
+        let tunneled = format!("GET / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", target);
+        reader.get_mut().write_all(tunneled.as_bytes()).await?;
+        // The proxy may keep the tunnel open after the backend closes, so stop at the stub body
+        let mut reply = Vec::new();
+        let mut chunk = [0u8; 1024];
+        while !reply.ends_with(STUB_BODY.as_bytes()) {
+            let read = reader.read(&mut chunk).await?;
+            if read == 0 {
+                break;
+            }
+            reply.extend_from_slice(&chunk[..read]);
+        }
+        Ok((code, Some(String::from_utf8_lossy(&reply).into_owned())))
+    };
+    timeout(CHECK_TIMEOUT, exchange)
+        .await
+        .map_err(|_| ProxyError::Connection(format!("no response within {:?}", CHECK_TIMEOUT)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StaticFileConfig;
+
+    #[tokio::test]
+    async fn combined_proxy_passes_every_check() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), "<h1>hello</h1>").unwrap();
+        let mut static_files = StaticFileConfig::single(dir.path().display().to_string(), false);
+        static_files.mounts[0].path = "/static".to_string();
+
+        let mut config = Config::default();
+        config.mode = ProxyMode::Combined;
+        config.monitoring.enabled = false;
+        config.static_files = Some(static_files);
+        config.reverse_proxy_target = Some("http://192.0.2.1:9".to_string());
+        config.proxy_username = Some("alice".to_string());
+        config.proxy_password = Some(Secret::new("secret"));
+
+        let report = run(config).await.unwrap();
+        let names: Vec<_> = report.checks.iter().map(|check| check.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["startup", "static GET", "reverse GET", "CONNECT tunnel", "proxy auth rejection"],
+            "{}",
+            report
+        );
+        assert!(report.passed(), "{}", report);
+        assert_eq!(concrete_path("/api/{id}/**"), "/api/selftest/selftest");
+    }
+}
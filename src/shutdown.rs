@@ -0,0 +1,91 @@
+//! Process-wide shutdown signal for long-lived connections.
+//!
+//! Request/response traffic ends on its own, but upgraded tunnels can stay open for hours.
+//! Tunnels subscribe to this signal so they can be closed cleanly, and register themselves
+//! so shutdown can wait for them to finish.
+
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{Notify, watch};
+use tokio::time::{Duration, Instant};
+
+struct ShutdownState {
+    signal: watch::Sender<bool>,
+    open_tunnels: AtomicUsize,
+    tunnel_closed: Notify,
+    /// Set by the admin API; the process drains and exits as on Ctrl+C
+    drain_requested: watch::Sender<bool>,
+}
+
+fn state() -> &'static ShutdownState {
+    static STATE: OnceLock<ShutdownState> = OnceLock::new();
+    STATE.get_or_init(|| ShutdownState {
+        signal: watch::channel(false).0,
+        open_tunnels: AtomicUsize::new(0),
+        tunnel_closed: Notify::new(),
+        drain_requested: watch::channel(false).0,
+    })
+}
+
+/// Tells every subscribed tunnel that the proxy is draining.
+pub fn begin_shutdown() {
+    state().signal.send_replace(true);
+}
+
+/// Asks the process to drain and exit; returns whether a drain was already requested.
+pub fn request_drain() -> bool {
+    state().drain_requested.send_replace(true)
+}
+
+/// Resolves once [`request_drain`] has been called.
+pub async fn drain_requested() {
+    let mut receiver = state().drain_requested.subscribe();
+    shutdown_started(&mut receiver).await;
+}
+
+pub fn subscribe() -> watch::Receiver<bool> {
+    state().signal.subscribe()
+}
+
+/// Resolves once shutdown has begun.
+pub async fn shutdown_started(receiver: &mut watch::Receiver<bool>) {
+    if receiver.wait_for(|draining| *draining).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Counts a tunnel as open until the returned guard is dropped.
+pub fn track_tunnel() -> TunnelGuard {
+    state().open_tunnels.fetch_add(1, Ordering::SeqCst);
+    TunnelGuard { _private: () }
+}
+
+pub struct TunnelGuard {
+    _private: (),
+}
+
+impl Drop for TunnelGuard {
+    fn drop(&mut self) {
+        state().open_tunnels.fetch_sub(1, Ordering::SeqCst);
+        state().tunnel_closed.notify_waiters();
+    }
+}
+
+pub fn open_tunnels() -> usize {
+    state().open_tunnels.load(Ordering::SeqCst)
+}
+
+/// Waits until every tracked tunnel has closed or `limit` elapses; returns the number still open.
+pub async fn wait_for_tunnels(limit: Duration) -> usize {
+    let deadline = Instant::now() + limit;
+    loop {
+        let closed = state().tunnel_closed.notified();
+        let open = open_tunnels();
+        if open == 0 {
+            return 0;
+        }
+        if tokio::time::timeout_at(deadline, closed).await.is_err() {
+            return open_tunnels();
+        }
+    }
+}
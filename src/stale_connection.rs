@@ -0,0 +1,129 @@
+//! Detection of requests lost to a stale pooled backend connection.
+//!
+//! A backend that closes an idle keep-alive connection races with the proxy reusing it: the
+//! request is written, and the reply is a reset or EOF before any response byte arrives.
+//! Idempotent requests that fail this way are safe to send again on a fresh connection.
+
+use hyper::Method;
+use log::warn;
+use prometheus::{IntCounterVec, Opts, Registry};
+use std::error::Error;
+use std::io;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Methods RFC 9110 defines as idempotent.
+pub fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+/// Whether a client error means the connection died before the backend answered, as opposed to
+/// a failure to connect at all (left to the route's retry policy).
+pub fn is_stale_connection_error(error: &hyper_util::client::legacy::Error) -> bool {
+    !error.is_connect() && error.source().is_some_and(closed_before_response)
+}
+
+fn closed_before_response(error: &(dyn Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(err) = current {
+        if let Some(hyper_err) = err.downcast_ref::<hyper::Error>()
+            && hyper_err.is_incomplete_message()
+        {
+            return true;
+        }
+        if let Some(io_err) = err.downcast_ref::<io::Error>()
+            && matches!(
+                io_err.kind(),
+                io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof
+            )
+        {
+            return true;
+        }
+        current = err.source();
+    }
+    false
+}
+
+/// Counts a resend on a fresh connection; `outcome` is `success` or `failure`.
+pub fn record_retry(route: &str, outcome: &str) {
+    stale_telemetry().retries.with_label_values(&[route, outcome]).inc();
+}
+
+struct StaleConnectionTelemetry {
+    retries: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl StaleConnectionTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "upstream_stale_connection_retries_total",
+            "Idempotent requests resent on a fresh connection after a pooled connection failed",
+        ).namespace("bifrost");
+        Self {
+            retries: IntCounterVec::new(opts, &["route", "outcome"])
+                .expect("upstream_stale_connection_retries_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.retries.clone())) {
+            warn!("Failed to register upstream_stale_connection_retries_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn stale_telemetry() -> &'static StaleConnectionTelemetry {
+    static TELEMETRY: OnceLock<StaleConnectionTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(StaleConnectionTelemetry::new)
+}
+
+/// Registers the stale connection retry counter with the shared Prometheus registry.
+pub fn register_stale_connection_metrics(registry: &Registry) {
+    stale_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Wrapped(io::Error);
+
+    impl std::fmt::Display for Wrapped {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "request failed")
+        }
+    }
+
+    impl Error for Wrapped {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn classifies_resets_and_idempotent_methods() {
+        let reset = Wrapped(io::Error::from(io::ErrorKind::ConnectionReset));
+        let refused = Wrapped(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert!(closed_before_response(&reset));
+        assert!(!closed_before_response(&refused));
+
+        assert!(is_idempotent(&Method::GET));
+        assert!(is_idempotent(&Method::PUT));
+        assert!(!is_idempotent(&Method::POST));
+        assert!(!is_idempotent(&Method::PATCH));
+    }
+}
@@ -1,13 +1,17 @@
 use crate::error::ProxyError;
 use crate::config::{StaticFileConfig, ResolvedStaticMount};
 use crate::common::{FileStreaming, FileBody, PerformanceMetrics};
-use hyper::{Method, Response, StatusCode};
+use crate::byte_range::{self, RangeSelection};
+use crate::conditional::{self, EtagCache};
+use crate::path_normalization;
+use crate::precompressed;
+use hyper::{HeaderMap, Method, Response, StatusCode};
 use hyper::body::Incoming;
 use http_body_util::Full;
 use hyper::body::Bytes;
 use std::fs;
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
 
 // HTML Templates - extracted as constants for maintainability and performance
 
@@ -109,6 +113,28 @@ pub struct StaticFileHandler {
     // Custom MIME type mappings
     custom_mime_types: std::collections::HashMap<String, String>,
     metrics: Arc<PerformanceMetrics>,
+    etags: EtagCache,
+}
+
+/// The handler serving a listener's static files, which a configuration reload replaces with
+/// one for the new mounts; each request is served by the handler current when it arrives.
+#[derive(Clone)]
+pub struct StaticMounts {
+    current: Arc<RwLock<Arc<StaticFileHandler>>>,
+}
+
+impl StaticMounts {
+    pub fn new(handler: StaticFileHandler) -> Self {
+        Self { current: Arc::new(RwLock::new(Arc::new(handler))) }
+    }
+
+    pub fn current(&self) -> Arc<StaticFileHandler> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn replace(&self, handler: StaticFileHandler) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(handler);
+    }
 }
 
 #[derive(Clone)]
@@ -118,6 +144,13 @@ pub struct MountInfo {
     path_len: usize,
 }
 
+impl MountInfo {
+    /// Canonical filesystem directory the mount serves
+    pub fn root_path(&self) -> &Path {
+        &self.root_path
+    }
+}
+
 impl StaticFileHandler {
     pub fn new(config: StaticFileConfig) -> Result<Self, ProxyError> {
         let mut mounts = Vec::new();
@@ -143,6 +176,7 @@ impl StaticFileHandler {
             mounts,
             custom_mime_types: config.custom_mime_types,
             metrics: Arc::new(PerformanceMetrics::new()),
+            etags: EtagCache::new(),
         })
     }
 
@@ -160,33 +194,44 @@ impl StaticFileHandler {
                 .map_err(|e| ProxyError::Http(e.to_string()))?);
         }
 
-        let path = req.uri().path();
+        let path = match path_normalization::normalize(req.uri().path()) {
+            Ok(path) => path,
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(FileBody::InMemory(Full::new(Bytes::from("Bad Request"))))
+                    .map_err(|e| ProxyError::Http(e.to_string()));
+            }
+        };
 
         // Find the best matching mount for this path
-        let (mount_info, relative_path) = match self.find_mount_for_path(path) {
+        let (mount_info, relative_path) = match self.find_mount_for_path(&path) {
             Some(result) => result,
             None => return Ok(self.not_found_response()),
         };
 
         // Resolve the file path within the mount
-        let file_path = self.resolve_file_path_in_mount(&mount_info, &relative_path)?;
+        let file_path = match self.resolve_file_path_in_mount(mount_info, &relative_path) {
+            Some(file_path) => file_path,
+            None => return Ok(self.not_found_response()),
+        };
 
         if !file_path.exists() {
             // If SPA mode is enabled for this mount, check if this should use fallback or return 404
             if mount_info.resolved_mount.spa_mode {
                 // Don't use SPA fallback for asset files - they should return 404 if missing
                 if !self.is_asset_file(&relative_path) {
-                    return self.handle_spa_fallback_in_mount(&mount_info, req.method() == &Method::HEAD).await;
+                    return self.handle_spa_fallback_in_mount(&mount_info, req.headers(), req.method() == &Method::HEAD).await;
                 }
             }
             return Ok(self.not_found_response());
         }
 
         if file_path.is_dir() {
-            return self.handle_directory_in_mount(mount_info, &file_path, &relative_path, req.method() == Method::HEAD).await;
+            return self.handle_directory_in_mount(mount_info, &file_path, &relative_path, req.headers(), req.method() == Method::HEAD).await;
         }
 
-        self.handle_file_with_mount_info(&file_path, req.method() == Method::HEAD, Some(mount_info), false).await
+        self.handle_file_request(&file_path, req.headers(), req.method() == Method::HEAD, Some(mount_info), false).await
     }
 
     pub fn find_mount_for_path(&self, path: &str) -> Option<(&MountInfo, String)> {
@@ -210,26 +255,20 @@ impl StaticFileHandler {
         None
     }
 
-    fn resolve_file_path_in_mount(&self, mount_info: &MountInfo, relative_path: &str) -> Result<PathBuf, ProxyError> {
-        let clean_path = if relative_path.is_empty() || relative_path == "/" {
-            "/"
-        } else {
-            relative_path
-        };
-
-        let requested_path = match clean_path {
-            "/" => mount_info.root_path.clone(),
-            _ => {
-                // Remove leading slash if present
-                let path_without_leading = clean_path.strip_prefix('/').unwrap_or(clean_path);
-                mount_info.root_path.join(path_without_leading)
-            }
-        };
-
-        Ok(requested_path)
+    /// Maps a path relative to the mount onto the filesystem. `None` when it would leave the
+    /// mount's root: a `..` segment, or an absolute path that `join` would put in place of the root.
+    pub fn resolve_file_path_in_mount(&self, mount_info: &MountInfo, relative_path: &str) -> Option<PathBuf> {
+        let relative = Path::new(relative_path.trim_start_matches('/'));
+        if relative.as_os_str().is_empty() {
+            return Some(mount_info.root_path.clone());
+        }
+        if !relative.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir)) {
+            return None;
+        }
+        Some(mount_info.root_path.join(relative))
     }
 
-    async fn handle_spa_fallback_in_mount(&self, mount_info: &MountInfo, is_head: bool) -> Result<Response<FileBody>, ProxyError> {
+    async fn handle_spa_fallback_in_mount(&self, mount_info: &MountInfo, request_headers: &HeaderMap, is_head: bool) -> Result<Response<FileBody>, ProxyError> {
         let fallback_path = mount_info.root_path.join(&mount_info.resolved_mount.spa_fallback_file);
 
         // Check if fallback file exists
@@ -237,22 +276,22 @@ impl StaticFileHandler {
             return Ok(self.not_found_response());
         }
 
-        self.handle_file_with_mount_info(&fallback_path, is_head, Some(mount_info), true).await
+        self.handle_file_request(&fallback_path, request_headers, is_head, Some(mount_info), true).await
     }
 
-    async fn handle_directory_in_mount(&self, mount_info: &MountInfo, dir_path: &PathBuf, request_path: &str, is_head: bool) -> Result<Response<FileBody>, ProxyError> {
+    async fn handle_directory_in_mount(&self, mount_info: &MountInfo, dir_path: &PathBuf, request_path: &str, request_headers: &HeaderMap, is_head: bool) -> Result<Response<FileBody>, ProxyError> {
         if !mount_info.resolved_mount.enable_directory_listing {
             // Try to serve index files for directories
             for index_file in &mount_info.resolved_mount.index_files {
                 let index_path = dir_path.join(index_file);
                 if index_path.exists() && index_path.is_file() {
-                    return self.handle_file_with_mount_info(&index_path, is_head, Some(mount_info), false).await;
+                    return self.handle_file_request(&index_path, request_headers, is_head, Some(mount_info), false).await;
                 }
             }
 
             // If SPA mode is enabled, try fallback
             if mount_info.resolved_mount.spa_mode {
-                return self.handle_spa_fallback_in_mount(mount_info, is_head).await;
+                return self.handle_spa_fallback_in_mount(mount_info, request_headers, is_head).await;
             }
 
             return Ok(self.not_found_response());
@@ -352,6 +391,19 @@ impl StaticFileHandler {
         is_head: bool,
         mount_info: Option<&MountInfo>,
         is_spa_fallback: bool,
+    ) -> Result<Response<FileBody>, ProxyError> {
+        self.handle_file_request(file_path, &HeaderMap::new(), is_head, mount_info, is_spa_fallback).await
+    }
+
+    /// Handle file honoring the request's conditional and `Range` headers, answering 304, 206
+    /// or 416 where they apply
+    pub async fn handle_file_request(
+        &self,
+        file_path: &PathBuf,
+        request_headers: &HeaderMap,
+        is_head: bool,
+        mount_info: Option<&MountInfo>,
+        is_spa_fallback: bool,
     ) -> Result<Response<FileBody>, ProxyError> {
         let metadata = fs::metadata(file_path)
             .map_err(|_| ProxyError::NotFound(format!("File not found: {:?}", file_path)))?;
@@ -367,11 +419,23 @@ impl StaticFileHandler {
             Self::guess_mime_type_static(&file_path_clone, &custom_mime_types_clone)
         }).await.map_err(|e| ProxyError::Config(format!("MIME type detection error: {}", e)))?;
 
-        let _last_modified = metadata.modified()
+        // A precompressed sidecar the client accepts is sent instead, keeping the file's MIME type
+        let variants = if mount_info.map(|m| m.resolved_mount.precompressed).unwrap_or(false) {
+            precompressed::variants(file_path)
+        } else {
+            Vec::new()
+        };
+        let variant = precompressed::negotiate(request_headers, &variants);
+        let (served_path, metadata) = match variant {
+            Some(variant) => (&variant.path, &variant.metadata),
+            None => (file_path, &metadata),
+        };
+
+        let modified = metadata.modified()
             .map_err(|e| ProxyError::Config(format!("Cannot get file metadata: {}", e)))?;
 
         // Check file size and use optimized serving strategy
-        let file_size = FileStreaming::get_file_size(file_path).await?;
+        let file_size = FileStreaming::get_file_size(served_path).await?;
 
         // Determine if we should use no-cache headers
         let spa_mode = mount_info.map(|m| m.resolved_mount.spa_mode).unwrap_or(false);
@@ -392,11 +456,61 @@ impl StaticFileHandler {
             .map(|m| m.resolved_mount.cache_millisecs)
             .unwrap_or(3600);
 
+        // Validators of the file, per the mount's settings
+        let etag_mode = mount_info.map(|m| m.resolved_mount.etag).unwrap_or_default();
+        let etag = self.etags.etag(etag_mode, served_path, file_size, modified).await?
+            .map(|etag| match variant {
+                Some(variant) => precompressed::variant_etag(&etag, variant.encoding),
+                None => etag,
+            });
+        let last_modified = mount_info
+            .map(|m| m.resolved_mount.last_modified)
+            .unwrap_or(true)
+            .then_some(modified);
+
+        if conditional::is_not_modified(request_headers, etag.as_deref(), last_modified) {
+            let mut response = self.not_modified_response(etag.as_deref(), last_modified, no_cache, cache_duration)?;
+            Self::add_encoding(&mut response, &variants, None);
+            return Ok(response);
+        }
+
+        // Ranges are only served for GET, and only while an If-Range validator still matches
+        let range_header = request_headers
+            .get(hyper::header::RANGE)
+            .filter(|_| !is_head && conditional::range_allowed(request_headers, etag.as_deref(), last_modified))
+            .and_then(|value| value.to_str().ok());
+        if let Some(range_header) = range_header {
+            match byte_range::select(range_header, file_size) {
+                RangeSelection::Full => {}
+                RangeSelection::Unsatisfiable => {
+                    let mut response = FileStreaming::range_not_satisfiable_response(file_size)?;
+                    Self::add_encoding(&mut response, &variants, None);
+                    return Ok(response);
+                }
+                RangeSelection::Partial(ranges) => {
+                    let mut response = FileStreaming::create_range_file_response(
+                        served_path,
+                        &mime_type,
+                        file_size,
+                        &ranges,
+                        no_cache,
+                        cache_duration,
+                    ).await?;
+                    self.metrics.increment_files_served();
+                    self.metrics.increment_files_streamed();
+                    self.metrics.record_response_bytes(ranges.iter().map(|range| range.len()).sum());
+                    Self::add_validators(&mut response, etag.as_deref(), last_modified);
+                    Self::add_encoding(&mut response, &variants, variant);
+                    return Ok(response);
+                }
+            }
+        }
+
         let should_stream = FileStreaming::should_stream_file(file_size, 1024 * 1024);
 
         // Use centralized optimized response with SPA-aware cache control and streaming support
-        let response = FileStreaming::create_optimized_file_response(
-            file_path,
+        let mut response = FileStreaming::create_optimized_file_response(
+            served_path,
             &mime_type,
             file_size,
             is_head,
@@ -412,6 +526,51 @@ impl StaticFileHandler {
             self.metrics.record_response_bytes(file_size);
         }
 
+        Self::add_validators(&mut response, etag.as_deref(), last_modified);
+        Self::add_encoding(&mut response, &variants, variant);
+        Ok(response)
+    }
+
+    /// `Content-Encoding` of a sidecar sent as the body, and `Vary` whenever the file has sidecars
+    fn add_encoding(response: &mut Response<FileBody>, variants: &[precompressed::Variant], variant: Option<&precompressed::Variant>) {
+        let headers = response.headers_mut();
+        if let Some(variant) = variant {
+            headers.insert(hyper::header::CONTENT_ENCODING, hyper::header::HeaderValue::from_static(variant.encoding));
+        }
+        if !variants.is_empty() {
+            headers.append(hyper::header::VARY, hyper::header::HeaderValue::from_static("Accept-Encoding"));
+        }
+    }
+
+    fn add_validators(response: &mut Response<FileBody>, etag: Option<&str>, last_modified: Option<std::time::SystemTime>) {
+        let headers = response.headers_mut();
+        if let Some(value) = etag.and_then(|etag| etag.parse().ok()) {
+            headers.insert(hyper::header::ETAG, value);
+        }
+        if let Some(value) = last_modified.and_then(|modified| conditional::http_date(modified).parse().ok()) {
+            headers.insert(hyper::header::LAST_MODIFIED, value);
+        }
+    }
+
+    /// Generates a 304 Not Modified response carrying the file's validators and caching headers
+    fn not_modified_response(
+        &self,
+        etag: Option<&str>,
+        last_modified: Option<std::time::SystemTime>,
+        no_cache: bool,
+        cache_duration: u64,
+    ) -> Result<Response<FileBody>, ProxyError> {
+        let cache_control = if no_cache {
+            "no-cache, no-store, must-revalidate".to_string()
+        } else {
+            format!("public, max-age={}", cache_duration)
+        };
+        let mut response = Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header("Cache-Control", cache_control)
+            .body(FileBody::InMemory(Full::new(Bytes::new())))
+            .map_err(|e| ProxyError::Http(e.to_string()))?;
+        Self::add_validators(&mut response, etag, last_modified);
         Ok(response)
     }
 
@@ -519,6 +678,9 @@ mod tests {
             spa_fallback_file: None,
             no_cache_files: None,
             cache_millisecs: None,
+            etag: None,
+            last_modified: None,
+            precompressed: None,
         }];
 
         let handler = StaticFileHandler::new(config).expect("Failed to create handler");
@@ -526,4 +688,20 @@ mod tests {
         assert!(handler.find_mount_for_path("/static/file.txt").is_some());
         assert!(handler.find_mount_for_path("/static-api/file.txt").is_none());
     }
+
+    #[test]
+    fn test_resolved_paths_stay_inside_the_mount() {
+        let root = tempfile::tempdir().unwrap();
+        let handler = StaticFileHandler::new(StaticFileConfig::single(root.path().to_string_lossy().to_string(), false))
+            .expect("Failed to create handler");
+        let (mount_info, _) = handler.find_mount_for_path("/").unwrap();
+        let root_path = mount_info.root_path().to_path_buf();
+
+        assert_eq!(handler.resolve_file_path_in_mount(mount_info, "/"), Some(root_path.clone()));
+        assert_eq!(handler.resolve_file_path_in_mount(mount_info, "/css//site.css"), Some(root_path.join("css/site.css")));
+        assert_eq!(handler.resolve_file_path_in_mount(mount_info, "/./a.txt"), Some(root_path.join("a.txt")));
+        assert_eq!(handler.resolve_file_path_in_mount(mount_info, "/../etc/passwd"), None);
+        assert_eq!(handler.resolve_file_path_in_mount(mount_info, "/css/../../secret"), None);
+        assert_eq!(handler.resolve_file_path_in_mount(mount_info, "//etc/passwd"), Some(root_path.join("etc/passwd")));
+    }
 }
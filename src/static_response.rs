@@ -0,0 +1,207 @@
+use crate::config::StaticResponseConfig;
+use crate::error::ProxyError;
+use crate::path_params::PathParams;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::header::{CONTENT_TYPE, HOST, HeaderName, HeaderValue};
+use hyper::{Request, Response, StatusCode};
+
+/// Fixed response of a mock route.
+///
+/// With `template` set, the body and header values may contain `{{variable}}` placeholders,
+/// resolved per request: `method`, `path`, `query`, `host`, `client_ip`, `header.<name>` and
+/// `param.<name>` (a named path variable). Placeholders are checked when the route is built, so
+/// a typo fails at startup rather than showing up in responses.
+pub struct StaticResponder {
+    status: StatusCode,
+    body: Template,
+    headers: Vec<(HeaderName, Template)>,
+}
+
+/// Request values a response is filled in from.
+pub struct ResponseVars<'a, B> {
+    pub request: &'a Request<B>,
+    pub params: &'a PathParams,
+    pub client_ip: Option<&'a str>,
+}
+
+impl StaticResponder {
+    pub fn new(route_id: &str, config: &StaticResponseConfig) -> Result<Self, ProxyError> {
+        let status = StatusCode::from_u16(config.status).map_err(|_| {
+            ProxyError::Config(format!("Route {}: static_response.status {} is not a valid HTTP status", route_id, config.status))
+        })?;
+        let template = |raw: &str| {
+            if config.template {
+                Template::parse(raw).map_err(|e| ProxyError::Config(format!("Route {}: {}", route_id, e)))
+            } else {
+                Ok(Template::literal(raw))
+            }
+        };
+
+        let mut headers = vec![(CONTENT_TYPE, template(&config.content_type)?)];
+        let mut configured: Vec<_> = config.headers.iter().collect();
+        configured.sort();
+        for (name, value) in configured {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                ProxyError::Config(format!("Route {}: invalid static_response header name '{}'", route_id, name))
+            })?;
+            let value = template(value)?;
+            if let Some(literal) = value.as_literal() {
+                HeaderValue::from_str(literal).map_err(|_| {
+                    ProxyError::Config(format!("Route {}: invalid value for static_response header {}", route_id, name))
+                })?;
+            }
+            headers.retain(|(existing, _)| *existing != name);
+            headers.push((name, value));
+        }
+
+        Ok(Self { status, body: template(&config.body)?, headers })
+    }
+
+    pub fn respond<B>(&self, vars: &ResponseVars<'_, B>) -> Response<Full<Bytes>> {
+        let mut response = Response::new(Full::new(Bytes::from(self.body.render(vars))));
+        *response.status_mut() = self.status;
+        for (name, value) in &self.headers {
+            // A request value that cannot appear in a header leaves the header out
+            if let Ok(value) = HeaderValue::from_str(&value.render(vars)) {
+                response.headers_mut().insert(name.clone(), value);
+            }
+        }
+        response
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Method,
+    Path,
+    Query,
+    Host,
+    ClientIp,
+    Header(HeaderName),
+    Param(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Template(Vec<Segment>);
+
+impl Template {
+    fn literal(raw: &str) -> Self {
+        Self(vec![Segment::Literal(raw.to_string())])
+    }
+
+    fn parse(raw: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut rest = raw;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..].find("}}").ok_or_else(|| format!("unclosed placeholder in '{}'", raw))?;
+            let name = rest[start + 2..start + end].trim();
+            segments.push(match name {
+                "method" => Segment::Method,
+                "path" => Segment::Path,
+                "query" => Segment::Query,
+                "host" => Segment::Host,
+                "client_ip" => Segment::ClientIp,
+                _ => {
+                    if let Some(header) = name.strip_prefix("header.") {
+                        Segment::Header(
+                            HeaderName::from_bytes(header.as_bytes())
+                                .map_err(|_| format!("invalid header name in placeholder {{{{{}}}}}", name))?,
+                        )
+                    } else if let Some(param) = name.strip_prefix("param.") {
+                        Segment::Param(param.to_string())
+                    } else {
+                        return Err(format!("unknown placeholder {{{{{}}}}}", name));
+                    }
+                }
+            });
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+        Ok(Self(segments))
+    }
+
+    fn as_literal(&self) -> Option<&str> {
+        match self.0.as_slice() {
+            [] => Some(""),
+            [Segment::Literal(text)] => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Fills in the request values; a missing header, parameter or query renders empty.
+    fn render<B>(&self, vars: &ResponseVars<'_, B>) -> String {
+        let request = vars.request;
+        let mut out = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(text) => out.push_str(text),
+                Segment::Method => out.push_str(request.method().as_str()),
+                Segment::Path => out.push_str(request.uri().path()),
+                Segment::Query => out.push_str(request.uri().query().unwrap_or("")),
+                Segment::Host => out.push_str(
+                    request
+                        .headers()
+                        .get(HOST)
+                        .and_then(|v| v.to_str().ok())
+                        .or_else(|| request.uri().host())
+                        .unwrap_or(""),
+                ),
+                Segment::ClientIp => out.push_str(vars.client_ip.unwrap_or("")),
+                Segment::Header(name) => {
+                    out.push_str(request.headers().get(name).and_then(|v| v.to_str().ok()).unwrap_or(""))
+                }
+                Segment::Param(name) => out.push_str(
+                    vars.params.iter().find(|(param, _)| param == name).map(|(_, v)| v.as_str()).unwrap_or(""),
+                ),
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    #[tokio::test]
+    async fn renders_templates_from_the_request() {
+        let config = StaticResponseConfig {
+            status: 201,
+            body: r#"{"user": "{{param.id}}", "path": "{{path}}", "q": "{{query}}", "agent": "{{header.user-agent}}"}"#
+                .to_string(),
+            content_type: "application/json".to_string(),
+            headers: [("X-Echo-Method".to_string(), "{{method}} from {{client_ip}}".to_string())].into(),
+            template: true,
+        };
+        let responder = StaticResponder::new("mock", &config).unwrap();
+        let request = Request::post("/users/42?full=1").header("user-agent", "curl").body(()).unwrap();
+        let params = vec![("id".to_string(), "42".to_string())];
+
+        let response = responder.respond(&ResponseVars { request: &request, params: &params, client_ip: Some("10.0.0.1") });
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(response.headers()["x-echo-method"], "POST from 10.0.0.1");
+        assert_eq!(
+            response.into_body().collect().await.unwrap().to_bytes(),
+            r#"{"user": "42", "path": "/users/42", "q": "full=1", "agent": "curl"}"#
+        );
+
+        let unknown = StaticResponseConfig { body: "{{nope}}".to_string(), template: true, ..StaticResponseConfig::default() };
+        assert!(StaticResponder::new("mock", &unknown).is_err());
+        let plain = StaticResponseConfig { body: "{{nope}}".to_string(), ..StaticResponseConfig::default() };
+        let response = StaticResponder::new("mock", &plain).unwrap().respond(&ResponseVars {
+            request: &request,
+            params: &params,
+            client_ip: None,
+        });
+        assert_eq!(response.into_body().collect().await.unwrap().to_bytes(), "{{nope}}");
+    }
+}
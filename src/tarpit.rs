@@ -0,0 +1,189 @@
+//! Tarpit for vulnerability scanners.
+//!
+//! Requests for paths no legitimate client asks for (`/wp-login.php`, `/.env`, ...) are held for
+//! a while and then answered with a 404 or a fake 200, wasting the scanner's time instead of
+//! telling it quickly that nothing is there. The source address can additionally be banned for a
+//! cooldown period, after which all its requests are refused at once.
+
+use crate::ban_list::BanList;
+use crate::config::{TarpitAction, TarpitConfig};
+use crate::error::ProxyError;
+use crate::privacy;
+use crate::reverse_proxy::build_ant_regex;
+use crate::security_log::{SecurityEvent, SecurityEventKind};
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::header::{CONTENT_TYPE, HeaderValue};
+use hyper::{Response, StatusCode};
+use log::{info, warn};
+use prometheus::{IntCounterVec, Opts, Registry};
+use regex::Regex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+pub struct Tarpit {
+    paths: Vec<Regex>,
+    action: TarpitAction,
+    delay: Duration,
+    fake_body: Bytes,
+    /// Bounds how many requests are held at once; beyond it they are answered immediately
+    slots: Arc<Semaphore>,
+    ban: Option<Duration>,
+    bans: Arc<BanList>,
+}
+
+impl Tarpit {
+    pub fn new(config: &TarpitConfig, bans: Arc<BanList>) -> Result<Self, ProxyError> {
+        let paths = config
+            .paths
+            .iter()
+            .map(|pattern| {
+                build_ant_regex(pattern, false, true)
+                    .map_err(|e| ProxyError::Config(format!("tarpit: invalid path pattern {}: {}", pattern, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            paths,
+            action: config.action,
+            delay: Duration::from_secs(config.delay_secs),
+            fake_body: Bytes::from(config.fake_body.clone()),
+            slots: Arc::new(Semaphore::new(config.max_concurrent)),
+            ban: config.ban_secs.filter(|secs| *secs > 0).map(Duration::from_secs),
+            bans,
+        })
+    }
+
+    /// Answers requests from banned clients and requests for tarpit paths; `None` for the rest.
+    pub async fn check(&self, path: &str, client_ip: Option<&str>) -> Option<Response<Full<Bytes>>> {
+        if let Some(client_ip) = client_ip
+            && self.bans.is_banned(client_ip)
+        {
+            tarpit_telemetry().record("banned");
+            return Some(plain_response(StatusCode::FORBIDDEN, "Forbidden"));
+        }
+        if !self.paths.iter().any(|regex| regex.is_match(path)) {
+            return None;
+        }
+
+        info!("Tarpit: {} requested {}", privacy::client_ip(client_ip.unwrap_or("unknown")), path);
+        SecurityEvent::new(SecurityEventKind::ScannerProbe, "request for a tarpit path")
+            .client_ip(client_ip)
+            .target(path)
+            .emit();
+        if let (Some(client_ip), Some(ban)) = (client_ip, self.ban) {
+            self.bans.ban(client_ip, ban, "tarpit path");
+            SecurityEvent::new(SecurityEventKind::ClientBanned, format!("tarpit path, banned for {}s", ban.as_secs()))
+                .client_ip(Some(client_ip))
+                .emit();
+        }
+
+        match self.slots.clone().try_acquire_owned() {
+            Ok(_slot) => {
+                tarpit_telemetry().record("held");
+                tokio::time::sleep(self.delay).await;
+            }
+            Err(_) => {
+                warn!("Tarpit full; answering {} without delay", path);
+                tarpit_telemetry().record("overflow");
+            }
+        }
+        Some(self.response())
+    }
+
+    fn response(&self) -> Response<Full<Bytes>> {
+        match self.action {
+            TarpitAction::Delay => plain_response(StatusCode::NOT_FOUND, "Not Found"),
+            TarpitAction::Fake => {
+                let mut response = Response::new(Full::new(self.fake_body.clone()));
+                response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+                response
+            }
+        }
+    }
+}
+
+fn plain_response(status: StatusCode, body: &'static str) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from_static(body.as_bytes())));
+    *response.status_mut() = status;
+    response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("text/plain; charset=utf-8"));
+    response
+}
+
+struct TarpitTelemetry {
+    requests: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl TarpitTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "tarpit_requests_total",
+            "Requests caught by the tarpit by outcome (held, overflow, banned)",
+        ).namespace("bifrost");
+        Self {
+            requests: IntCounterVec::new(opts, &["outcome"]).expect("tarpit_requests_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.requests.clone())) {
+            warn!("Failed to register tarpit_requests_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    fn record(&self, outcome: &str) {
+        self.requests.with_label_values(&[outcome]).inc();
+    }
+}
+
+fn tarpit_telemetry() -> &'static TarpitTelemetry {
+    static TELEMETRY: OnceLock<TarpitTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(TarpitTelemetry::new)
+}
+
+/// Registers the tarpit counters with the shared Prometheus registry.
+pub fn register_tarpit_metrics(registry: &Registry) {
+    tarpit_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    #[tokio::test]
+    async fn answers_scanner_requests_and_bans_the_source() {
+        let bans = Arc::new(BanList::new());
+        let tarpit = Tarpit::new(
+            &TarpitConfig {
+                action: TarpitAction::Fake,
+                delay_secs: 0,
+                fake_body: "<html></html>".to_string(),
+                ban_secs: Some(600),
+                ..TarpitConfig::default()
+            },
+            bans.clone(),
+        )
+        .unwrap();
+
+        assert!(tarpit.check("/index.html", Some("10.0.0.5")).await.is_none());
+
+        assert!(tarpit.check("/blog/wp-login.php", Some("10.0.0.5")).await.is_none(), "patterns are anchored");
+        let response = tarpit.check("/WP-LOGIN.php", Some("10.0.0.5")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.into_body().collect().await.unwrap().to_bytes(), "<html></html>");
+
+        assert!(bans.is_banned("10.0.0.5"));
+        let refused = tarpit.check("/index.html", Some("10.0.0.5")).await.unwrap();
+        assert_eq!(refused.status(), StatusCode::FORBIDDEN);
+        assert!(tarpit.check("/index.html", Some("10.0.0.6")).await.is_none());
+    }
+}
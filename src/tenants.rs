@@ -0,0 +1,366 @@
+//! Tenants: teams sharing one instance.
+//!
+//! A tenant owns a set of reverse proxy routes. Requests to them count against the tenant's own
+//! rate limit rules (per client) and its quota (across all its clients), after the gateway-wide
+//! rules. Routes that require an API key accept only the tenant's keys, and the tenant labels its
+//! own request metrics. The monitoring server's tenants endpoint shows a tenant's routes, limits
+//! and counters only to holders of that tenant's admin token.
+
+use crate::common::constant_time_eq;
+use crate::config::{RateLimitRuleConfig, RateLimitingConfig, TenantConfig};
+use crate::error::ProxyError;
+use crate::graphql::Operation;
+use crate::rate_limit::{RateLimitHit, RateLimitRuleSnapshot, RateLimitSnapshot, RateLimiter};
+use crate::secrets::Secret;
+use hyper::{HeaderMap, Method, StatusCode};
+use log::{info, warn};
+use prometheus::{IntCounterVec, Opts, Registry};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// Rule id of the quota, after the `<tenant>/` prefix every tenant rule gets.
+const QUOTA_RULE: &str = "quota";
+
+/// The configured tenants, looked up by id or by the routes they own.
+#[derive(Default)]
+pub struct Tenants {
+    tenants: Vec<Arc<Tenant>>,
+    by_route: HashMap<String, Arc<Tenant>>,
+}
+
+impl Tenants {
+    /// `api_key_header` is the header compared with the `api_keys` of rule exemptions.
+    pub fn new(configs: &[TenantConfig], api_key_header: Option<&str>) -> Result<Self, ProxyError> {
+        let mut tenants = Self::default();
+        for config in configs {
+            if config.id.trim().is_empty() {
+                return Err(ProxyError::Config("Tenant id must not be empty".to_string()));
+            }
+            if tenants.get(&config.id).is_some() {
+                return Err(ProxyError::Config(format!("Duplicate tenant id '{}'", config.id)));
+            }
+            let tenant = Arc::new(Tenant::new(config, api_key_header)?);
+            for route in &config.routes {
+                if let Some(owner) = tenants.by_route.insert(route.clone(), tenant.clone()) {
+                    return Err(ProxyError::Config(format!(
+                        "Route '{}' belongs to both tenant '{}' and tenant '{}'",
+                        route, owner.id, config.id
+                    )));
+                }
+            }
+            info!(
+                "Tenant {}: {} route(s), {} rate limit rule(s){}",
+                config.id,
+                config.routes.len(),
+                config.rate_limits.len(),
+                if config.quota.is_some() { ", quota" } else { "" }
+            );
+            tenants.tenants.push(tenant);
+        }
+        Ok(tenants)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tenants.is_empty()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Arc<Tenant>> {
+        self.tenants.iter().find(|tenant| tenant.id == id)
+    }
+
+    pub fn for_route(&self, route_id: &str) -> Option<Arc<Tenant>> {
+        self.by_route.get(route_id).cloned()
+    }
+
+    /// Every route id some tenant claims, to check them against the configured routes.
+    pub fn route_ids(&self) -> impl Iterator<Item = &str> {
+        self.by_route.keys().map(String::as_str)
+    }
+
+    /// Tenant ids and their routes, without limits or counters.
+    pub fn list(&self) -> Vec<TenantSummary> {
+        self.tenants
+            .iter()
+            .map(|tenant| TenantSummary { id: tenant.id.clone(), routes: tenant.routes.clone() })
+            .collect()
+    }
+}
+
+pub struct Tenant {
+    id: String,
+    routes: Vec<String>,
+    api_keys: Vec<String>,
+    /// The tenant's per-client rules
+    rate_limiter: RateLimiter,
+    /// One window shared by all clients, keyed by the tenant id
+    quota: Option<RateLimiter>,
+    /// Read on every check, so a rotated token applies at once
+    admin_token: Option<Secret>,
+    /// Responses by status class, 1xx to 5xx
+    responses: [AtomicU64; 5],
+}
+
+impl Tenant {
+    fn new(config: &TenantConfig, api_key_header: Option<&str>) -> Result<Self, ProxyError> {
+        let limiter = |rules: Vec<RateLimitRuleConfig>| {
+            let limiter = RateLimiter::new(Some(RateLimitingConfig { enabled: true, rules, ..RateLimitingConfig::default() }));
+            match api_key_header {
+                Some(header) => limiter.with_api_key_header(header),
+                None => limiter,
+            }
+        };
+        // Prefixed ids keep the tenants' rules apart in 429 responses and rate limit metrics
+        let rules = config
+            .rate_limits
+            .iter()
+            .map(|rule| RateLimitRuleConfig { id: format!("{}/{}", config.id, rule.id), ..rule.clone() })
+            .collect();
+        let quota = match &config.quota {
+            Some(quota) if quota.limit == 0 || quota.window_secs == 0 => {
+                return Err(ProxyError::Config(format!(
+                    "Tenant '{}' quota needs a limit and window greater than zero",
+                    config.id
+                )));
+            }
+            Some(quota) => Some(limiter(vec![RateLimitRuleConfig {
+                id: format!("{}/{}", config.id, QUOTA_RULE),
+                limit: quota.limit,
+                window_secs: quota.window_secs,
+                path_prefix: None,
+                methods: None,
+                exempt: None,
+                graphql: None,
+            }])),
+            None => None,
+        };
+        if config.admin_token.as_ref().is_some_and(Secret::is_empty) {
+            return Err(ProxyError::Config(format!("Tenant '{}' admin_token must not be empty", config.id)));
+        }
+        Ok(Self {
+            id: config.id.clone(),
+            routes: config.routes.clone(),
+            api_keys: config.api_keys.clone(),
+            rate_limiter: limiter(rules),
+            quota,
+            admin_token: config.admin_token.clone(),
+            responses: Default::default(),
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Whether an API key accepted by the key store may be used on the tenant's routes.
+    pub fn allows_key(&self, key_id: &str) -> bool {
+        self.api_keys.is_empty() || self.api_keys.iter().any(|id| id == key_id)
+    }
+
+    /// Counts a request against the tenant's rules, then its quota. A request refused by a rule
+    /// does not use up quota.
+    pub async fn admit(
+        &self,
+        client_ip: Option<&str>,
+        method: &Method,
+        path: &str,
+        headers: &HeaderMap,
+        operation: Option<&Operation>,
+    ) -> Result<(), RateLimitHit> {
+        if let Some(client_ip) = client_ip
+            && let Err(hit) = self.rate_limiter.check_operation(client_ip, method, path, headers, operation).await
+        {
+            tenant_telemetry().rejections.with_label_values(&[&self.id, "rate_limit"]).inc();
+            return Err(hit);
+        }
+        if let Some(quota) = &self.quota
+            && let Err(hit) = quota.check_request(&self.id, method, path, headers).await
+        {
+            tenant_telemetry().rejections.with_label_values(&[&self.id, "quota"]).inc();
+            return Err(hit);
+        }
+        Ok(())
+    }
+
+    /// Counts a response to one of the tenant's routes.
+    pub fn record(&self, status: StatusCode) {
+        let class = (status.as_u16() / 100).clamp(1, 5);
+        self.responses[usize::from(class - 1)].fetch_add(1, Ordering::Relaxed);
+        tenant_telemetry()
+            .requests
+            .with_label_values(&[self.id.as_str(), &format!("{}xx", class)])
+            .inc();
+    }
+
+    /// Whether `token` opens this tenant's admin view; tenants without a token are open.
+    pub fn authorizes(&self, token: Option<&str>) -> bool {
+        match (&self.admin_token, token) {
+            (None, _) => true,
+            (Some(expected), Some(token)) => constant_time_eq(expected.expose().as_bytes(), token.as_bytes()),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// The tenant's routes, rules, quota usage and response counts.
+    pub async fn view(&self) -> TenantView {
+        let quota = match &self.quota {
+            Some(quota) => quota.snapshot().await.rules.into_iter().next(),
+            None => None,
+        };
+        let responses = self
+            .responses
+            .iter()
+            .enumerate()
+            .map(|(i, count)| (format!("{}xx", i + 1), count.load(Ordering::Relaxed)))
+            .collect();
+        TenantView {
+            id: self.id.clone(),
+            routes: self.routes.clone(),
+            api_keys: self.api_keys.clone(),
+            quota,
+            rate_limits: self.rate_limiter.snapshot().await,
+            responses,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TenantSummary {
+    pub id: String,
+    pub routes: Vec<String>,
+}
+
+/// One tenant as shown by the monitoring server's tenants endpoint.
+#[derive(Debug, Serialize)]
+pub struct TenantView {
+    pub id: String,
+    pub routes: Vec<String>,
+    /// Ids of the API keys the tenant accepts (empty: any key)
+    pub api_keys: Vec<String>,
+    /// Requests counted and refused in the current quota windows
+    pub quota: Option<RateLimitRuleSnapshot>,
+    pub rate_limits: RateLimitSnapshot,
+    /// Responses since startup by status class
+    pub responses: HashMap<String, u64>,
+}
+
+struct TenantTelemetry {
+    requests: IntCounterVec,
+    rejections: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl TenantTelemetry {
+    fn new() -> Self {
+        let requests = IntCounterVec::new(
+            Opts::new("tenant_requests_total", "Reverse proxy responses on each tenant's routes, by status class")
+                .namespace("bifrost"),
+            &["tenant", "status_class"],
+        )
+        .expect("tenant_requests_total metric");
+        let rejections = IntCounterVec::new(
+            Opts::new("tenant_rejections_total", "Requests refused by a tenant's rate limit rules or quota")
+                .namespace("bifrost"),
+            &["tenant", "reason"],
+        )
+        .expect("tenant_rejections_total metric");
+        Self { requests, rejections, registered: AtomicBool::new(false) }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        for counter in [&self.requests, &self.rejections] {
+            if let Err(err) = registry.register(Box::new(counter.clone())) {
+                warn!("Failed to register tenant metric: {}", err);
+                return;
+            }
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn tenant_telemetry() -> &'static TenantTelemetry {
+    static TELEMETRY: OnceLock<TenantTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(TenantTelemetry::new)
+}
+
+/// Registers the per-tenant counters with the shared Prometheus registry.
+pub fn register_tenant_metrics(registry: &Registry) {
+    tenant_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RateLimitWindowConfig;
+
+    fn tenant(id: &str, routes: &[&str]) -> TenantConfig {
+        TenantConfig {
+            id: id.to_string(),
+            routes: routes.iter().map(|route| route.to_string()).collect(),
+            rate_limits: Vec::new(),
+            api_keys: Vec::new(),
+            quota: None,
+            admin_token: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn tenants_keep_their_own_limits_keys_and_views() {
+        let payments = TenantConfig {
+            rate_limits: vec![RateLimitRuleConfig {
+                id: "burst".to_string(),
+                limit: 2,
+                window_secs: 60,
+                path_prefix: None,
+                methods: None,
+                exempt: None,
+                graphql: None,
+            }],
+            api_keys: vec!["pay-ci".to_string()],
+            quota: Some(RateLimitWindowConfig { limit: 3, window_secs: 60 }),
+            admin_token: Some(Secret::new("pay-admin")),
+            ..tenant("payments", &["pay-api", "pay-web"])
+        };
+        let tenants = Tenants::new(&[payments, tenant("search", &["search"])], None).unwrap();
+        let pay = tenants.for_route("pay-web").unwrap();
+        let search = tenants.for_route("search").unwrap();
+        assert!(tenants.for_route("other").is_none());
+
+        let headers = HeaderMap::new();
+        let admit = |tenant: Arc<Tenant>, client: &'static str| {
+            let headers = headers.clone();
+            async move { tenant.admit(Some(client), &Method::GET, "/", &headers, None).await }
+        };
+        // Two requests per client, three per tenant
+        assert!(admit(pay.clone(), "10.0.0.1").await.is_ok());
+        assert!(admit(pay.clone(), "10.0.0.1").await.is_ok());
+        assert_eq!(admit(pay.clone(), "10.0.0.1").await.unwrap_err().rule_id, "payments/burst");
+        assert!(admit(pay.clone(), "10.0.0.2").await.is_ok());
+        assert_eq!(admit(pay.clone(), "10.0.0.3").await.unwrap_err().rule_id, "payments/quota");
+        // The other tenant's limits are untouched
+        assert!(admit(search.clone(), "10.0.0.1").await.is_ok());
+
+        assert!(pay.allows_key("pay-ci"));
+        assert!(!pay.allows_key("search-ci"));
+        assert!(search.allows_key("search-ci"));
+
+        assert!(pay.authorizes(Some("pay-admin")));
+        assert!(!pay.authorizes(Some("search-admin")));
+        assert!(!pay.authorizes(None));
+
+        pay.record(StatusCode::OK);
+        pay.record(StatusCode::TOO_MANY_REQUESTS);
+        let view = pay.view().await;
+        assert_eq!(view.routes, vec!["pay-api", "pay-web"]);
+        assert_eq!(view.quota.as_ref().map(|quota| quota.allowed_total), Some(3));
+        assert_eq!(view.rate_limits.rules[0].limited_total, 1);
+        assert_eq!(view.responses["4xx"], 1);
+
+        assert!(Tenants::new(&[tenant("a", &["shared"]), tenant("b", &["shared"])], None).is_err());
+        assert!(Tenants::new(&[tenant("a", &["x"]), tenant("a", &["y"])], None).is_err());
+    }
+}
@@ -0,0 +1,580 @@
+//! Stub backends and an in-process proxy for end-to-end tests.
+//!
+//! Everything listens on an ephemeral port of 127.0.0.1, so tests can run in parallel. Stub
+//! backends record the requests they receive; [`TestProxy`] runs a [`Config`] through
+//! [`ProxyFactory`] as `main` does, on ports picked at start. Process-wide setup that `main`
+//! performs (secret providers, DNS overrides, privacy settings) is left to the test. Backends
+//! and proxies stop when dropped.
+
+use crate::common::TlsConfig;
+use crate::config::Config;
+use crate::error::ProxyError;
+use crate::proxy::{ProxyFactory, ProxyHandle};
+use base64::Engine;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{HeaderMap, Method, Request, Response, StatusCode, Uri, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use log::error;
+use std::convert::Infallible;
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufReader as StdBufReader};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// Header in which stub backends name themselves, so a test can tell which one answered.
+pub const BACKEND_HEADER: &str = "x-stub-backend";
+
+/// How long helpers wait for a listener or a response before giving up.
+const WAIT: Duration = Duration::from_secs(10);
+
+/// A free port on 127.0.0.1. The port is released before returning, so it is only free until
+/// something else binds it; bind it right away.
+pub async fn free_addr() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind an ephemeral port");
+    listener.local_addr().expect("ephemeral port address")
+}
+
+/// Waits until something accepts connections on `addr`.
+pub async fn wait_for_listener(addr: SocketAddr) -> io::Result<()> {
+    let deadline = tokio::time::Instant::now() + WAIT;
+    while tokio::time::Instant::now() < deadline {
+        if TcpStream::connect(addr).await.is_ok() {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    Err(io::Error::new(io::ErrorKind::TimedOut, format!("nothing listening on {}", addr)))
+}
+
+/// A request as a stub backend received it.
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub uri: Uri,
+    pub version: Version,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+type Recorded = Arc<Mutex<Vec<RecordedRequest>>>;
+
+/// A backend on an ephemeral port that records what it receives.
+pub struct StubBackend {
+    addr: SocketAddr,
+    requests: Recorded,
+    task: JoinHandle<()>,
+}
+
+impl StubBackend {
+    /// An HTTP/1.1 backend answering every request with 200 and its `name`, both as the body
+    /// and in [`BACKEND_HEADER`]. Absolute-form requests are accepted, so it also stands in for
+    /// a relay proxy.
+    pub async fn http(name: &str) -> Self {
+        Self::serve_http(name, None).await
+    }
+
+    /// Same as [`StubBackend::http`], over TLS with the given PEM key and certificate. Clients
+    /// that offer `h2` through ALPN are served HTTP/2.
+    pub async fn https(name: &str, private_key_path: &str, cert_path: &str) -> Self {
+        let mut config = TlsConfig::create_config(private_key_path, cert_path).expect("stub backend TLS configuration");
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Self::serve_http(name, Some(TlsAcceptor::from(Arc::new(config)))).await
+    }
+
+    /// A backend that completes WebSocket handshakes, then echoes every frame back unmasked.
+    pub async fn websocket_echo() -> Self {
+        Self::spawn(|stream, requests| async move {
+            let service = service_fn(move |mut req: Request<Incoming>| {
+                let requests = requests.clone();
+                async move {
+                    let upgrade = hyper::upgrade::on(&mut req);
+                    let key = req.headers().get("sec-websocket-key").cloned();
+                    requests.lock().unwrap().push(RecordedRequest {
+                        method: req.method().clone(),
+                        uri: req.uri().clone(),
+                        version: req.version(),
+                        headers: req.headers().clone(),
+                        body: Bytes::new(),
+                    });
+                    let Some(key) = key else {
+                        return Ok::<_, Infallible>(empty_response(StatusCode::BAD_REQUEST));
+                    };
+                    tokio::spawn(async move {
+                        if let Ok(upgraded) = upgrade.await {
+                            let _ = echo_websocket_frames(TokioIo::new(upgraded)).await;
+                        }
+                    });
+                    let mut response = empty_response(StatusCode::SWITCHING_PROTOCOLS);
+                    let headers = response.headers_mut();
+                    headers.insert("connection", "upgrade".parse().unwrap());
+                    headers.insert("upgrade", "websocket".parse().unwrap());
+                    headers.insert("sec-websocket-accept", websocket_accept(key.as_bytes()).parse().unwrap());
+                    Ok(response)
+                }
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .with_upgrades()
+                .await;
+        })
+        .await
+    }
+
+    /// A TCP backend that echoes whatever it receives, as a target for CONNECT tunnels.
+    pub async fn tcp_echo() -> Self {
+        Self::tcp_echo_on("127.0.0.1:0").await
+    }
+
+    /// [`StubBackend::tcp_echo`] bound to `bind`, e.g. `[::1]:0` for an IPv6 target.
+    pub async fn tcp_echo_on(bind: &str) -> Self {
+        Self::spawn_on(bind, |mut stream, _| async move {
+            let (mut reader, mut writer) = stream.split();
+            let _ = tokio::io::copy(&mut reader, &mut writer).await;
+        })
+        .await
+    }
+
+    /// An upstream proxy that records each CONNECT request and tunnels it to `upstream`,
+    /// whatever authority it asked for, so tests can use names that do not resolve.
+    pub async fn connect_relay(upstream: SocketAddr) -> Self {
+        Self::spawn(move |stream, requests| async move {
+            let mut reader = BufReader::new(stream);
+            let Ok(head) = read_head(&mut reader).await else {
+                return;
+            };
+            let Some(request) = parse_request_head(&head) else {
+                return;
+            };
+            let is_connect = request.method == Method::CONNECT;
+            requests.lock().unwrap().push(request);
+            let mut client = reader.into_inner();
+            if !is_connect {
+                let _ = client.write_all(b"HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\n\r\n").await;
+                return;
+            }
+            let Ok(mut target) = TcpStream::connect(upstream).await else {
+                let _ = client.write_all(b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\n\r\n").await;
+                return;
+            };
+            if client.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n").await.is_ok() {
+                let _ = tokio::io::copy_bidirectional(&mut client, &mut target).await;
+            }
+        })
+        .await
+    }
+
+    async fn serve_http(name: &str, tls: Option<TlsAcceptor>) -> Self {
+        let name: Arc<str> = Arc::from(name);
+        Self::spawn(move |stream, requests| {
+            let (name, tls) = (name.clone(), tls.clone());
+            async move {
+                match tls {
+                    Some(acceptor) => {
+                        if let Ok(stream) = acceptor.accept(stream).await {
+                            serve_recorded(stream, name, requests).await;
+                        }
+                    }
+                    None => serve_recorded(stream, name, requests).await,
+                }
+            }
+        })
+        .await
+    }
+
+    /// Accepts connections and hands each one to `handle` on its own task.
+    async fn spawn<F, Fut>(handle: F) -> Self
+    where
+        F: Fn(TcpStream, Recorded) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self::spawn_on("127.0.0.1:0", handle).await
+    }
+
+    async fn spawn_on<F, Fut>(bind: &str, handle: F) -> Self
+    where
+        F: Fn(TcpStream, Recorded) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let listener = TcpListener::bind(bind).await.expect("bind stub backend");
+        let addr = listener.local_addr().expect("stub backend address");
+        let requests = Recorded::default();
+        let recorded = requests.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle(stream, recorded.clone()));
+            }
+        });
+        Self { addr, requests, task }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// `http://` URL of the backend, for route targets and relay URLs.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for StubBackend {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn empty_response(status: StatusCode) -> Response<Full<Bytes>> {
+    Response::builder().status(status).body(Full::new(Bytes::new())).unwrap()
+}
+
+async fn serve_recorded<S>(stream: S, name: Arc<str>, requests: Recorded)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = service_fn(move |req: Request<Incoming>| {
+        let (name, requests) = (name.clone(), requests.clone());
+        async move {
+            let (parts, body) = req.into_parts();
+            let body = body.collect().await.map(|collected| collected.to_bytes()).unwrap_or_default();
+            requests.lock().unwrap().push(RecordedRequest {
+                method: parts.method,
+                uri: parts.uri,
+                version: parts.version,
+                headers: parts.headers,
+                body,
+            });
+            let response = Response::builder()
+                .header(BACKEND_HEADER, &*name)
+                .body(Full::new(Bytes::from(name.to_string())))
+                .unwrap();
+            Ok::<_, Infallible>(response)
+        }
+    });
+    let _ = auto::Builder::new(TokioExecutor::new()).serve_connection(TokioIo::new(stream), service).await;
+}
+
+fn parse_request_head(head: &str) -> Option<RecordedRequest> {
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.parse().ok()?;
+    let uri = request_line.next()?.parse().ok()?;
+    let mut headers = HeaderMap::new();
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':')
+            && let (Ok(name), Ok(value)) = (name.trim().parse::<hyper::header::HeaderName>(), value.trim().parse())
+        {
+            headers.append(name, value);
+        }
+    }
+    Some(RecordedRequest { method, uri, version: Version::HTTP_11, headers, body: Bytes::new() })
+}
+
+/// A proxy running on the test's runtime.
+pub struct TestProxy {
+    addr: SocketAddr,
+    forward_addr: Option<SocketAddr>,
+    monitoring_addr: Option<SocketAddr>,
+    handle: ProxyHandle,
+    task: JoinHandle<()>,
+}
+
+impl TestProxy {
+    /// Runs `config` with its listeners moved to free ports: `listen_addr`, the combined mode
+    /// `forward_listen_addr` and, when monitoring is enabled, the monitoring listener. Returns
+    /// once the proxy accepts connections.
+    pub async fn start(mut config: Config) -> Result<Self, ProxyError> {
+        config.listen_addr = free_addr().await;
+        if config.forward_listen_addr.is_some() {
+            config.forward_listen_addr = Some(free_addr().await);
+        }
+        if config.monitoring.enabled {
+            config.monitoring.listen_address = Some(free_addr().await);
+        }
+        let (addr, forward_addr) = (config.listen_addr, config.forward_listen_addr);
+        let monitoring_addr = config.monitoring.listen_address;
+
+        let (proxy, handle) = ProxyFactory::create_reloadable(config)?;
+        let task = tokio::spawn(async move {
+            if let Err(e) = proxy.run().await {
+                error!("Test proxy stopped: {}", e);
+            }
+        });
+        for listener in std::iter::once(addr).chain(forward_addr) {
+            wait_for_listener(listener).await?;
+        }
+        Ok(Self { addr, forward_addr, monitoring_addr, handle, task })
+    }
+
+    /// Applies `config` to the running proxy, keeping the listeners on their test ports.
+    pub async fn reconfigure(&self, mut config: Config) -> Result<(), ProxyError> {
+        config.listen_addr = self.addr;
+        config.forward_listen_addr = self.forward_addr;
+        config.monitoring.listen_address = self.monitoring_addr;
+        self.handle.reconfigure(config).await
+    }
+
+    /// Address of the main listener.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Address of the forward proxy listener in combined mode.
+    pub fn forward_addr(&self) -> Option<SocketAddr> {
+        self.forward_addr
+    }
+}
+
+impl Drop for TestProxy {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Sends `request` over a new HTTP/1.1 connection on `io` and collects the response.
+pub async fn send<S>(io: S, request: Request<Full<Bytes>>) -> Result<Response<Bytes>, hyper::Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(io)).await?;
+    tokio::spawn(connection);
+    let response = sender.send_request(request).await?;
+    let (parts, body) = response.into_parts();
+    let body = body.collect().await?.to_bytes();
+    Ok(Response::from_parts(parts, body))
+}
+
+/// `GET path_and_query` from the server at `addr`.
+pub async fn get(addr: SocketAddr, path_and_query: &str) -> Response<Bytes> {
+    let request = Request::get(path_and_query)
+        .header("host", addr.to_string())
+        .body(Full::new(Bytes::new()))
+        .unwrap();
+    let stream = TcpStream::connect(addr).await.expect("connect to the proxy");
+    send(stream, request).await.expect("HTTP exchange with the proxy")
+}
+
+/// Reads an HTTP head up to and including its blank line.
+pub async fn read_head<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<String> {
+    let mut head = String::new();
+    let read = async {
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await? == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+            let done = line == "\r\n" || line == "\n";
+            head.push_str(&line);
+            if done {
+                return Ok(());
+            }
+        }
+    };
+    tokio::time::timeout(WAIT, read)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timed out reading an HTTP head"))??;
+    Ok(head)
+}
+
+/// Opens a CONNECT tunnel to `authority` through the proxy at `proxy`. Returns the proxy's
+/// response head and, when it answered 200, the tunnel.
+pub async fn connect_tunnel(
+    proxy: SocketAddr,
+    authority: &str,
+    extra_headers: &[(&str, &str)],
+) -> io::Result<(String, TcpStream)> {
+    let mut stream = TcpStream::connect(proxy).await?;
+    let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", authority, authority);
+    for (name, value) in extra_headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    // Read byte by byte so nothing after the head is consumed from the tunnel
+    let mut head = Vec::new();
+    let read = async {
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0u8; 1];
+            if stream.read(&mut byte).await? == 0 {
+                break;
+            }
+            head.push(byte[0]);
+        }
+        Ok::<_, io::Error>(())
+    };
+    tokio::time::timeout(WAIT, read)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timed out waiting for the CONNECT response"))??;
+    Ok((String::from_utf8_lossy(&head).into_owned(), stream))
+}
+
+/// Sends `request` over a new HTTP/2 connection on `io` and collects the response.
+pub async fn send_http2<S>(io: S, request: Request<Full<Bytes>>) -> Result<Response<Bytes>, hyper::Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(io)).await?;
+    tokio::spawn(connection);
+    let response = sender.send_request(request).await?;
+    let (parts, body) = response.into_parts();
+    let body = body.collect().await?.to_bytes();
+    Ok(Response::from_parts(parts, body))
+}
+
+/// TLS client side of `io` that trusts only the PEM certificates in `ca_path`.
+pub async fn tls_connect<S>(io: S, server_name: &str, ca_path: &str) -> io::Result<tokio_rustls::client::TlsStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    tls_connect_with_alpn(io, server_name, ca_path, &[]).await
+}
+
+/// Like [`tls_connect`], offering `alpn` protocols in order of preference.
+pub async fn tls_connect_with_alpn<S>(
+    io: S,
+    server_name: &str,
+    ca_path: &str,
+    alpn: &[&[u8]],
+) -> io::Result<tokio_rustls::client::TlsStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut config = rustls::ClientConfig::builder().with_root_certificates(load_roots(ca_path)?).with_no_client_auth();
+    config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+    connect_with(config, server_name, io).await
+}
+
+/// Like [`tls_connect`], presenting the client certificate in `cert_path` with its key.
+pub async fn tls_connect_with_client_cert<S>(
+    io: S,
+    server_name: &str,
+    ca_path: &str,
+    cert_path: &str,
+    key_path: &str,
+) -> io::Result<tokio_rustls::client::TlsStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let chain = rustls_pemfile::certs(&mut StdBufReader::new(File::open(cert_path)?)).collect::<Result<Vec<_>, _>>()?;
+    let key = rustls_pemfile::private_key(&mut StdBufReader::new(File::open(key_path)?))?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no private key"))?;
+    let config = rustls::ClientConfig::builder()
+        .with_root_certificates(load_roots(ca_path)?)
+        .with_client_auth_cert(chain, key)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    connect_with(config, server_name, io).await
+}
+
+fn load_roots(ca_path: &str) -> io::Result<rustls::RootCertStore> {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut StdBufReader::new(File::open(ca_path)?)) {
+        roots.add(cert?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    Ok(roots)
+}
+
+async fn connect_with<S>(
+    config: rustls::ClientConfig,
+    server_name: &str,
+    io: S,
+) -> io::Result<tokio_rustls::client::TlsStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let server_name = rustls::pki_types::ServerName::try_from(server_name.to_string())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    TlsConnector::from(Arc::new(config)).connect(server_name, io).await
+}
+
+/// `Sec-WebSocket-Accept` for a handshake key (RFC 6455, section 4.2.2).
+pub fn websocket_accept(key: &[u8]) -> String {
+    let mut context = ring::digest::Context::new(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY);
+    context.update(key);
+    context.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+    base64::engine::general_purpose::STANDARD.encode(context.finish())
+}
+
+/// A final text frame carrying `payload`, masked when `mask` is given as client frames must be.
+pub fn websocket_frame(payload: &[u8], mask: Option<[u8; 4]>) -> Vec<u8> {
+    let mut frame = vec![0x81];
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    match payload.len() {
+        len if len < 126 => frame.push(mask_bit | len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(mask_bit | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(mask_bit | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    match mask {
+        Some(mask) => {
+            frame.extend_from_slice(&mask);
+            frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+        }
+        None => frame.extend_from_slice(payload),
+    }
+    frame
+}
+
+/// Reads one frame; returns its opcode and unmasked payload.
+pub async fn read_websocket_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header).await?;
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len).await?;
+            u64::from(u16::from_be_bytes(len))
+        }
+        127 => {
+            let mut len = [0u8; 8];
+            reader.read_exact(&mut len).await?;
+            u64::from_be_bytes(len)
+        }
+        len => u64::from(len),
+    };
+    let mask = if header[1] & 0x80 != 0 {
+        let mut mask = [0u8; 4];
+        reader.read_exact(&mut mask).await?;
+        Some(mask)
+    } else {
+        None
+    };
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    if let Some(mask) = mask {
+        payload.iter_mut().enumerate().for_each(|(i, byte)| *byte ^= mask[i % 4]);
+    }
+    Ok((header[0] & 0x0f, payload))
+}
+
+async fn echo_websocket_frames<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S) -> io::Result<()> {
+    loop {
+        let (opcode, payload) = read_websocket_frame(&mut stream).await?;
+        if opcode == 0x8 {
+            return stream.write_all(&[0x88, 0x00]).await;
+        }
+        let mut frame = websocket_frame(&payload, None);
+        frame[0] = 0x80 | opcode;
+        stream.write_all(&frame).await?;
+    }
+}
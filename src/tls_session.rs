@@ -0,0 +1,378 @@
+//! TLS session resumption with rotating ticket keys, and 0-RTT early data screening.
+//!
+//! Time is divided into epochs of one ticket lifetime. Tickets are sealed with the current
+//! epoch's key and accepted under the current or previous epoch's key, so every ticket stays
+//! usable for at least one full lifetime. With a shared key file, each epoch key is derived from
+//! the file's secret, so instances sharing the file rotate in lockstep and accept each other's
+//! tickets without any coordination.
+//!
+//! Early data is only read once the handshake has completed, so a replayed ClientHello (which
+//! cannot finish the handshake) never gets a request processed. On top of that, only idempotent
+//! requests are allowed to arrive as early data unless the policy says otherwise.
+
+use crate::config::{EarlyDataPolicy, TlsSessionConfig};
+use crate::error::ProxyError;
+use log::{debug, warn};
+use prometheus::{IntCounterVec, Opts, Registry};
+use rand::RngCore;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::hmac;
+use rustls::server::ProducesTickets;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+
+const EPOCH_LEN: usize = 8;
+const MIN_SECRET_LEN: usize = 32;
+
+pub struct RotatingTicketer {
+    lifetime_secs: u32,
+    /// Shared secret from the key file; random per-epoch keys are used when absent
+    secret: Option<hmac::Key>,
+    random_keys: Mutex<HashMap<u64, [u8; 32]>>,
+}
+
+impl RotatingTicketer {
+    pub fn new(config: &TlsSessionConfig) -> Result<Self, ProxyError> {
+        if config.ticket_lifetime_secs == 0 {
+            return Err(ProxyError::Config("tls_session.ticket_lifetime_secs must be greater than 0".to_string()));
+        }
+        let secret = match &config.ticket_key_file {
+            Some(path) => {
+                let bytes = std::fs::read(path).map_err(|e| {
+                    ProxyError::Config(format!("Failed to read TLS ticket key file {}: {}", path, e))
+                })?;
+                if bytes.len() < MIN_SECRET_LEN {
+                    return Err(ProxyError::Config(format!(
+                        "TLS ticket key file {} must hold at least {} bytes",
+                        path, MIN_SECRET_LEN
+                    )));
+                }
+                Some(hmac::Key::new(hmac::HMAC_SHA256, &bytes))
+            }
+            None => None,
+        };
+        Ok(Self {
+            lifetime_secs: config.ticket_lifetime_secs,
+            secret,
+            random_keys: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn current_epoch(&self) -> u64 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        now / u64::from(self.lifetime_secs)
+    }
+
+    /// Returns the key for `epoch`; random keys are only created for the current epoch.
+    fn key_for(&self, epoch: u64, current: u64) -> Option<LessSafeKey> {
+        let material = match &self.secret {
+            Some(secret) => {
+                let tag = hmac::sign(secret, &[b"bifrost-ticket-key".as_slice(), &epoch.to_be_bytes()].concat());
+                let mut material = [0u8; 32];
+                material.copy_from_slice(&tag.as_ref()[..32]);
+                material
+            }
+            None => {
+                let mut keys = self.random_keys.lock().unwrap_or_else(|e| e.into_inner());
+                keys.retain(|known, _| *known + 1 >= current);
+                if epoch == current {
+                    *keys.entry(epoch).or_insert_with(|| {
+                        let mut material = [0u8; 32];
+                        rand::thread_rng().fill_bytes(&mut material);
+                        material
+                    })
+                } else {
+                    *keys.get(&epoch)?
+                }
+            }
+        };
+        UnboundKey::new(&AES_256_GCM, &material).ok().map(LessSafeKey::new)
+    }
+
+    fn seal_at(&self, plain: &[u8], epoch: u64) -> Option<Vec<u8>> {
+        let key = self.key_for(epoch, epoch)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let mut sealed = plain.to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(epoch.to_be_bytes()),
+            &mut sealed,
+        )
+        .ok()?;
+
+        let mut ticket = Vec::with_capacity(EPOCH_LEN + NONCE_LEN + sealed.len());
+        ticket.extend_from_slice(&epoch.to_be_bytes());
+        ticket.extend_from_slice(&nonce);
+        ticket.extend_from_slice(&sealed);
+        Some(ticket)
+    }
+
+    fn open_at(&self, ticket: &[u8], current: u64) -> Option<Vec<u8>> {
+        if ticket.len() < EPOCH_LEN + NONCE_LEN {
+            return None;
+        }
+        let (epoch, rest) = ticket.split_at(EPOCH_LEN);
+        let epoch = u64::from_be_bytes(epoch.try_into().ok()?);
+        if epoch != current && epoch + 1 != current {
+            return None;
+        }
+        let key = self.key_for(epoch, current)?;
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+
+        let mut buffer = sealed.to_vec();
+        let plain = key.open_in_place(nonce, Aad::from(epoch.to_be_bytes()), &mut buffer).ok()?;
+        Some(plain.to_vec())
+    }
+}
+
+impl fmt::Debug for RotatingTicketer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RotatingTicketer")
+            .field("lifetime_secs", &self.lifetime_secs)
+            .field("shared_key", &self.secret.is_some())
+            .finish()
+    }
+}
+
+impl ProducesTickets for RotatingTicketer {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.lifetime_secs
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        self.seal_at(plain, self.current_epoch())
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        self.open_at(cipher, self.current_epoch())
+    }
+}
+
+const TOO_EARLY: &[u8] = b"HTTP/1.1 425 Too Early\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+/// Completes the TLS handshake and applies the early data policy to any 0-RTT bytes.
+///
+/// Idempotent requests (GET, HEAD) received as early data are passed on with an
+/// `Early-Data: 1` header (RFC 8470). Other methods are either answered with `425 Too Early`,
+/// which makes the client retry after the handshake, or processed as usual under
+/// [`EarlyDataPolicy::Delay`].
+pub async fn accept_tls<IO>(
+    acceptor: &TlsAcceptor,
+    stream: IO,
+    policy: EarlyDataPolicy,
+) -> io::Result<EarlyDataStream<TlsStream<IO>>>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut tls_stream = acceptor.accept(stream).await?;
+    let mut early = Vec::new();
+    if let Some(mut reader) = tls_stream.get_mut().1.early_data() {
+        reader.read_to_end(&mut early)?;
+    }
+    if early.is_empty() {
+        return Ok(EarlyDataStream::new(early, tls_stream));
+    }
+
+    match screen_early_data(early, policy) {
+        Some(early) => Ok(EarlyDataStream::new(early, tls_stream)),
+        None => {
+            tls_stream.write_all(TOO_EARLY).await?;
+            let _ = tls_stream.shutdown().await;
+            Err(io::Error::other("non-idempotent request in TLS early data answered with 425 Too Early"))
+        }
+    }
+}
+
+/// Returns the bytes to hand to the HTTP server, or `None` when the request must be rejected.
+fn screen_early_data(mut early: Vec<u8>, policy: EarlyDataPolicy) -> Option<Vec<u8>> {
+    let method = early.split(|byte| *byte == b' ').next().filter(|_| early.contains(&b' '));
+    let idempotent = matches!(method, Some(b"GET") | Some(b"HEAD"));
+
+    let outcome = if idempotent {
+        // Mark the request after its request line so upstreams can apply their own policy
+        if let Some(line_end) = early.windows(2).position(|pair| pair == b"\r\n") {
+            early.splice(line_end + 2..line_end + 2, b"Early-Data: 1\r\n".iter().copied());
+        }
+        "accepted"
+    } else if policy == EarlyDataPolicy::Delay {
+        "delayed"
+    } else {
+        "rejected"
+    };
+    debug!("TLS early data request ({} bytes) {}", early.len(), outcome);
+    early_data_telemetry().requests.with_label_values(&[outcome]).inc();
+    (outcome != "rejected").then_some(early)
+}
+
+/// A stream that yields buffered early data before reading from the inner stream.
+pub struct EarlyDataStream<S> {
+    early: Vec<u8>,
+    position: usize,
+    inner: S,
+}
+
+impl<S> EarlyDataStream<S> {
+    fn new(early: Vec<u8>, inner: S) -> Self {
+        Self { early, position: 0, inner }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<IO> EarlyDataStream<TlsStream<IO>> {
+    /// The protocol agreed on through ALPN, if any
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.inner.get_ref().1.alpn_protocol()
+    }
+
+    /// The certificate chain the client presented, leaf first, if any
+    pub fn peer_certificates(&self) -> Option<&[rustls::pki_types::CertificateDer<'static>]> {
+        self.inner.get_ref().1.peer_certificates()
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for EarlyDataStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        if self.position < self.early.len() {
+            let take = buf.remaining().min(self.early.len() - self.position);
+            let start = self.position;
+            buf.put_slice(&self.early[start..start + take]);
+            self.position += take;
+            if self.position == self.early.len() {
+                self.early = Vec::new();
+                self.position = 0;
+            }
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for EarlyDataStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+struct EarlyDataTelemetry {
+    requests: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl EarlyDataTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "tls_early_data_requests_total",
+            "Requests received as TLS 1.3 early data by outcome",
+        ).namespace("bifrost");
+        Self {
+            requests: IntCounterVec::new(opts, &["outcome"]).expect("tls_early_data_requests_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.requests.clone())) {
+            warn!("Failed to register tls_early_data_requests_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn early_data_telemetry() -> &'static EarlyDataTelemetry {
+    static TELEMETRY: OnceLock<EarlyDataTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(EarlyDataTelemetry::new)
+}
+
+/// Registers the early data counters with the shared Prometheus registry.
+pub fn register_tls_session_metrics(registry: &Registry) {
+    early_data_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticketer(key_file: Option<String>) -> RotatingTicketer {
+        RotatingTicketer::new(&TlsSessionConfig { ticket_key_file: key_file, ..TlsSessionConfig::default() }).unwrap()
+    }
+
+    #[test]
+    fn tickets_survive_one_rotation_only() {
+        let ticketer = ticketer(None);
+        let ticket = ticketer.seal_at(b"session state", 100).unwrap();
+
+        assert_eq!(ticketer.open_at(&ticket, 100).unwrap(), b"session state");
+        assert_eq!(ticketer.open_at(&ticket, 101).unwrap(), b"session state");
+        assert!(ticketer.open_at(&ticket, 102).is_none());
+
+        let mut tampered = ticketer.seal_at(b"session state", 102).unwrap();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(ticketer.open_at(&tampered, 102).is_none());
+    }
+
+    #[test]
+    fn shared_key_file_lets_instances_accept_each_others_tickets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ticket.key");
+        std::fs::write(&path, [7u8; 48]).unwrap();
+        let path = path.display().to_string();
+
+        let first = ticketer(Some(path.clone()));
+        let second = ticketer(Some(path));
+        let ticket = first.seal_at(b"state", 42).unwrap();
+        assert_eq!(second.open_at(&ticket, 43).unwrap(), b"state");
+        assert!(ticketer(None).open_at(&ticket, 42).is_none());
+
+        std::fs::write(dir.path().join("short.key"), [7u8; 8]).unwrap();
+        let short = dir.path().join("short.key").display().to_string();
+        assert!(RotatingTicketer::new(&TlsSessionConfig { ticket_key_file: Some(short), ..TlsSessionConfig::default() }).is_err());
+    }
+
+    #[test]
+    fn early_data_admits_only_idempotent_methods_by_default() {
+        let get = screen_early_data(b"GET /feed HTTP/1.1\r\nHost: a\r\n\r\n".to_vec(), EarlyDataPolicy::Reject).unwrap();
+        assert_eq!(get, b"GET /feed HTTP/1.1\r\nEarly-Data: 1\r\nHost: a\r\n\r\n".to_vec());
+
+        let post = b"POST /orders HTTP/1.1\r\nHost: a\r\n\r\n".to_vec();
+        assert!(screen_early_data(post.clone(), EarlyDataPolicy::Reject).is_none());
+        assert_eq!(screen_early_data(post.clone(), EarlyDataPolicy::Delay).unwrap(), post);
+        // A request line cut short is not trusted to be idempotent
+        assert!(screen_early_data(b"GET".to_vec(), EarlyDataPolicy::Reject).is_none());
+
+        let requests = &early_data_telemetry().requests;
+        assert!(requests.with_label_values(&["accepted"]).get() >= 1);
+        assert!(requests.with_label_values(&["rejected"]).get() >= 2);
+    }
+}
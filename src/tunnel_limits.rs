@@ -0,0 +1,204 @@
+//! Caps on simultaneous CONNECT tunnels.
+//!
+//! Tunnels are counted per destination host across all clients, per client IP and destination,
+//! and per group of domains sharing one cap. A CONNECT that would go over any of them is refused
+//! with 503 and `Retry-After: 1` before the upstream connection is opened; the count drops as
+//! soon as a tunnel closes.
+
+use crate::config::{TunnelDomainLimitConfig, TunnelLimitsConfig};
+use crate::forward_proxy::ForwardProxy;
+use log::debug;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Reason a CONNECT tunnel was refused by the [`TunnelLimiter`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TunnelLimitHit {
+    /// Human readable description of the cap that was reached
+    pub scope: String,
+    pub limit: usize,
+}
+
+/// Tracks simultaneous CONNECT tunnels per destination and per client/destination pair.
+#[derive(Clone)]
+pub struct TunnelLimiter {
+    enabled: bool,
+    max_per_destination: Option<usize>,
+    max_per_client_per_destination: Option<usize>,
+    domain_limits: Arc<Vec<TunnelDomainLimitConfig>>,
+    active: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl TunnelLimiter {
+    pub fn new(config: Option<TunnelLimitsConfig>) -> Self {
+        let Some(config) = config else {
+            return Self::disabled();
+        };
+
+        let domain_limits: Vec<TunnelDomainLimitConfig> = config
+            .domain_limits
+            .into_iter()
+            .filter(|limit| !limit.domains.is_empty())
+            .collect();
+        let enabled = config.max_per_destination.is_some()
+            || config.max_per_client_per_destination.is_some()
+            || !domain_limits.is_empty();
+
+        Self {
+            enabled,
+            max_per_destination: config.max_per_destination,
+            max_per_client_per_destination: config.max_per_client_per_destination,
+            domain_limits: Arc::new(domain_limits),
+            active: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            max_per_destination: None,
+            max_per_client_per_destination: None,
+            domain_limits: Arc::new(Vec::new()),
+            active: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Reserves a tunnel slot for `client_ip` -> `host`.
+    ///
+    /// The returned permit releases the slot when dropped, so it must be held for
+    /// the lifetime of the tunnel.
+    pub fn try_acquire(&self, client_ip: &str, host: &str) -> Result<TunnelPermit, TunnelLimitHit> {
+        if !self.enabled {
+            return Ok(TunnelPermit { active: None, keys: Vec::new() });
+        }
+
+        let host = host.trim_end_matches('.').to_lowercase();
+        let mut checks: Vec<(String, usize, String)> = Vec::new();
+
+        // A domain cap replaces the per-host cap for hosts it covers
+        let domain_limit = self.domain_limits.iter().enumerate().find_map(|(index, limit)| {
+            ForwardProxy::matching_no_proxy_pattern(&host, &limit.domains)
+                .map(|pattern| (index, pattern.clone(), limit.max_concurrent))
+        });
+
+        if let Some((index, pattern, max)) = domain_limit {
+            checks.push((format!("domain#{}", index), max, format!("domain '{}'", pattern)));
+        } else if let Some(max) = self.max_per_destination {
+            checks.push((format!("dest:{}", host), max, format!("destination '{}'", host)));
+        }
+
+        if let Some(max) = self.max_per_client_per_destination {
+            checks.push((
+                format!("client:{}|{}", client_ip, host),
+                max,
+                format!("client {} to destination '{}'", client_ip, host),
+            ));
+        }
+
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        for (key, max, scope) in &checks {
+            if active.get(key).copied().unwrap_or(0) >= *max {
+                debug!("Tunnel limit reached for {} ({} active)", scope, max);
+                return Err(TunnelLimitHit { scope: scope.clone(), limit: *max });
+            }
+        }
+
+        let keys: Vec<String> = checks.into_iter().map(|(key, _, _)| key).collect();
+        for key in &keys {
+            *active.entry(key.clone()).or_insert(0) += 1;
+        }
+
+        Ok(TunnelPermit { active: Some(self.active.clone()), keys })
+    }
+
+    /// Number of tunnels currently counted against `host` (per-destination cap only).
+    pub fn active_for_destination(&self, host: &str) -> usize {
+        let active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        active.get(&format!("dest:{}", host.to_lowercase())).copied().unwrap_or(0)
+    }
+}
+
+/// Slot held by an open tunnel; released on drop.
+pub struct TunnelPermit {
+    active: Option<Arc<Mutex<HashMap<String, usize>>>>,
+    keys: Vec<String>,
+}
+
+impl Drop for TunnelPermit {
+    fn drop(&mut self) {
+        let Some(active) = self.active.take() else {
+            return;
+        };
+        let mut active = active.lock().unwrap_or_else(|e| e.into_inner());
+        for key in &self.keys {
+            if let Some(count) = active.get_mut(key) {
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    active.remove(key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(per_dest: Option<usize>, per_client: Option<usize>, domains: Vec<TunnelDomainLimitConfig>) -> TunnelLimiter {
+        TunnelLimiter::new(Some(TunnelLimitsConfig {
+            max_per_destination: per_dest,
+            max_per_client_per_destination: per_client,
+            domain_limits: domains,
+        }))
+    }
+
+    #[test]
+    fn per_destination_cap_is_released_on_drop() {
+        let limiter = limiter(Some(2), None, Vec::new());
+
+        let first = limiter.try_acquire("10.0.0.1", "example.com").unwrap();
+        let _second = limiter.try_acquire("10.0.0.2", "EXAMPLE.com").unwrap();
+        assert!(limiter.try_acquire("10.0.0.3", "example.com").is_err());
+        assert!(limiter.try_acquire("10.0.0.3", "other.com").is_ok());
+
+        drop(first);
+        assert_eq!(limiter.active_for_destination("example.com"), 1);
+        assert!(limiter.try_acquire("10.0.0.3", "example.com").is_ok());
+    }
+
+    #[test]
+    fn per_client_cap_only_affects_that_client() {
+        let limiter = limiter(None, Some(1), Vec::new());
+
+        let _held = limiter.try_acquire("10.0.0.1", "example.com").unwrap();
+        let hit = limiter.try_acquire("10.0.0.1", "example.com").err().unwrap();
+        assert_eq!(hit.limit, 1);
+        assert!(limiter.try_acquire("10.0.0.2", "example.com").is_ok());
+    }
+
+    #[test]
+    fn domain_cap_is_shared_across_subdomains() {
+        let limiter = limiter(Some(100), None, vec![TunnelDomainLimitConfig {
+            domains: vec!["*.cdn.example".to_string()],
+            max_concurrent: 1,
+        }]);
+
+        let _held = limiter.try_acquire("10.0.0.1", "a.cdn.example").unwrap();
+        let hit = limiter.try_acquire("10.0.0.2", "b.cdn.example").err().unwrap();
+        assert_eq!(hit.scope, "domain '*.cdn.example'");
+    }
+
+    #[test]
+    fn disabled_limiter_always_grants() {
+        let limiter = TunnelLimiter::new(None);
+        assert!(!limiter.is_enabled());
+        let _permits: Vec<_> = (0..10)
+            .map(|_| limiter.try_acquire("10.0.0.1", "example.com").unwrap())
+            .collect();
+    }
+}
@@ -0,0 +1,210 @@
+//! Classification of failed backend requests.
+//!
+//! The client error hyper returns wraps whatever went wrong: an `io::Error` from the socket, a
+//! resolver or TLS error from the connector, or a `hyper::Error` from the HTTP codec. [`classify`]
+//! walks that chain and names the failure, so retry decisions, circuit breakers and the
+//! `bifrost_upstream_errors_total` counter can tell a refused connection from a bad certificate.
+
+use crate::error::{ProxyError, UpstreamErrorKind};
+use log::warn;
+use prometheus::{IntCounterVec, Opts, Registry};
+use std::error::Error;
+use std::io;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Names the failure behind a client error.
+pub fn classify(error: &hyper_util::client::legacy::Error) -> UpstreamErrorKind {
+    let kind = error.source().and_then(classify_chain);
+    match kind {
+        Some(UpstreamErrorKind::Timeout) if error.is_connect() => UpstreamErrorKind::ConnectTimeout,
+        Some(kind) => kind,
+        None if error.is_connect() => UpstreamErrorKind::Connect,
+        None => UpstreamErrorKind::Other,
+    }
+}
+
+fn classify_chain(error: &(dyn Error + 'static)) -> Option<UpstreamErrorKind> {
+    let mut current = Some(error);
+    while let Some(err) = current {
+        if let Some(kind) = classify_one(err) {
+            return Some(kind);
+        }
+        // Connectors box their own errors inside an io::Error, which `source` does not expose
+        current = match err.downcast_ref::<io::Error>().and_then(|io_err| io_err.get_ref()) {
+            Some(inner) => Some(inner as &(dyn Error + 'static)),
+            None => err.source(),
+        };
+    }
+    None
+}
+
+fn classify_one(error: &(dyn Error + 'static)) -> Option<UpstreamErrorKind> {
+    if error.is::<rustls::Error>() || error.is::<hyper_tls::native_tls::Error>() {
+        return Some(UpstreamErrorKind::Tls);
+    }
+    if let Some(hyper_err) = error.downcast_ref::<hyper::Error>() {
+        if hyper_err.is_parse() || hyper_err.is_parse_status() || hyper_err.is_parse_too_large() {
+            return Some(UpstreamErrorKind::Protocol);
+        }
+        if hyper_err.is_incomplete_message() {
+            return Some(UpstreamErrorKind::Reset);
+        }
+        if hyper_err.is_timeout() {
+            return Some(UpstreamErrorKind::Timeout);
+        }
+    }
+    if let Some(io_err) = error.downcast_ref::<io::Error>() {
+        match io_err.kind() {
+            io::ErrorKind::TimedOut => return Some(UpstreamErrorKind::Timeout),
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::HostUnreachable
+            | io::ErrorKind::NetworkUnreachable
+            | io::ErrorKind::AddrNotAvailable => return Some(UpstreamErrorKind::Connect),
+            io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof => return Some(UpstreamErrorKind::Reset),
+            io::ErrorKind::InvalidData if io_err.get_ref().is_none() => {
+                return Some(UpstreamErrorKind::Protocol);
+            }
+            _ => {}
+        }
+    }
+    // The resolver and h2 errors are private types; their messages are stable enough to match
+    let message = error.to_string().to_ascii_lowercase();
+    if message.starts_with("dns error") || message.contains("failed to lookup address") {
+        return Some(UpstreamErrorKind::Dns);
+    }
+    if message.contains("certificate") || message.contains("handshake") {
+        return Some(UpstreamErrorKind::Tls);
+    }
+    if message.starts_with("http2 error") || message.contains("protocol error") {
+        return Some(UpstreamErrorKind::Protocol);
+    }
+    None
+}
+
+/// Classifies a failed backend request, counts it for `proxy` (`reverse` or `forward`) and
+/// `route`, and turns it into a [`ProxyError::Upstream`].
+pub fn upstream_error(proxy: &str, route: &str, error: &hyper_util::client::legacy::Error) -> ProxyError {
+    let kind = classify(error);
+    record(proxy, route, kind);
+    ProxyError::Upstream { kind, message: describe(error) }
+}
+
+/// Counts a failed backend request.
+pub fn record(proxy: &str, route: &str, kind: UpstreamErrorKind) {
+    upstream_telemetry().errors.with_label_values(&[proxy, route, kind.as_str()]).inc();
+}
+
+/// The client error and its causes on one line; hyper's own message is only the outermost layer.
+fn describe(error: &hyper_util::client::legacy::Error) -> String {
+    let mut message = error.to_string();
+    let mut current = error.source();
+    while let Some(err) = current {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        current = err.source();
+    }
+    message
+}
+
+struct UpstreamErrorTelemetry {
+    errors: IntCounterVec,
+    registered: AtomicBool,
+}
+
+impl UpstreamErrorTelemetry {
+    fn new() -> Self {
+        let opts = Opts::new(
+            "upstream_errors_total",
+            "Backend requests that failed before a response, by failure kind",
+        ).namespace("bifrost");
+        Self {
+            errors: IntCounterVec::new(opts, &["proxy", "route", "kind"])
+                .expect("upstream_errors_total metric"),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    fn register_if_needed(&self, registry: &Registry) {
+        if self.registered.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = registry.register(Box::new(self.errors.clone())) {
+            warn!("Failed to register upstream_errors_total metric: {}", err);
+            return;
+        }
+        self.registered.store(true, Ordering::Relaxed);
+    }
+}
+
+fn upstream_telemetry() -> &'static UpstreamErrorTelemetry {
+    static TELEMETRY: OnceLock<UpstreamErrorTelemetry> = OnceLock::new();
+    TELEMETRY.get_or_init(UpstreamErrorTelemetry::new)
+}
+
+/// Registers the upstream error counter with the shared Prometheus registry.
+pub fn register_upstream_error_metrics(registry: &Registry) {
+    upstream_telemetry().register_if_needed(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Wrapped(io::Error);
+
+    impl std::fmt::Display for Wrapped {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "client error")
+        }
+    }
+
+    impl Error for Wrapped {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    fn kind_of(error: io::Error) -> Option<UpstreamErrorKind> {
+        classify_chain(&Wrapped(error))
+    }
+
+    #[test]
+    fn classifies_socket_errors() {
+        assert_eq!(kind_of(io::ErrorKind::ConnectionRefused.into()), Some(UpstreamErrorKind::Connect));
+        assert_eq!(kind_of(io::ErrorKind::ConnectionReset.into()), Some(UpstreamErrorKind::Reset));
+        assert_eq!(kind_of(io::ErrorKind::UnexpectedEof.into()), Some(UpstreamErrorKind::Reset));
+        assert_eq!(kind_of(io::ErrorKind::TimedOut.into()), Some(UpstreamErrorKind::Timeout));
+        assert_eq!(kind_of(io::ErrorKind::PermissionDenied.into()), None);
+    }
+
+    #[test]
+    fn looks_inside_connector_errors() {
+        let dns = io::Error::other("dns error: failed to lookup address information");
+        assert_eq!(kind_of(dns), Some(UpstreamErrorKind::Dns));
+
+        let tls = io::Error::other(rustls::Error::InvalidCertificate(rustls::CertificateError::Expired));
+        assert_eq!(kind_of(tls), Some(UpstreamErrorKind::Tls));
+    }
+
+    #[test]
+    fn only_transient_kinds_are_retryable() {
+        for kind in [
+            UpstreamErrorKind::ConnectTimeout,
+            UpstreamErrorKind::Connect,
+            UpstreamErrorKind::Dns,
+            UpstreamErrorKind::Reset,
+            UpstreamErrorKind::Timeout,
+        ] {
+            assert!(kind.is_retryable(), "{} should be retryable", kind);
+            assert!(ProxyError::Upstream { kind, message: String::new() }.is_recoverable());
+        }
+        for kind in [UpstreamErrorKind::Tls, UpstreamErrorKind::Protocol, UpstreamErrorKind::Other] {
+            assert!(!kind.is_retryable(), "{} should be fatal", kind);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! TLS to `https://` backends.
+//!
+//! Every route gets its own rustls client config: the system roots or a CA bundle, or no
+//! verification at all, and optionally an SNI name other than the target host. With `http2` the
+//! backend is offered `h2` through ALPN, and hyper speaks HTTP/2 on the connections where the
+//! backend picks it. `http://` targets of the same route keep using plain connections.
+
+use crate::config::UpstreamTlsConfig;
+use crate::egress::UpstreamConnector;
+use crate::error::ProxyError;
+use hyper_rustls::{FixedServerNameResolver, HttpsConnector, HttpsConnectorBuilder};
+use log::warn;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, WebPkiSupportedAlgorithms};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{Arc, OnceLock};
+
+/// TLS settings of one route, ready to wrap its backend connector.
+#[derive(Clone)]
+pub struct UpstreamTls {
+    config: ClientConfig,
+    server_name: Option<ServerName<'static>>,
+    http2: bool,
+}
+
+impl UpstreamTls {
+    /// Builds the client config for `route_id`; defaults verify against the system roots.
+    pub fn new(route_id: &str, config: Option<&UpstreamTlsConfig>) -> Result<Self, ProxyError> {
+        let Some(config) = config else {
+            return Ok(Self::default());
+        };
+        let builder = ClientConfig::builder();
+        let client_config = if !config.verify {
+            warn!("Route {} does not verify the certificates of its https:// targets", route_id);
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate::new()))
+                .with_no_client_auth()
+        } else if let Some(ca_file) = &config.ca_file {
+            builder.with_root_certificates(load_ca_file(route_id, ca_file)?).with_no_client_auth()
+        } else {
+            builder.with_root_certificates(system_roots()).with_no_client_auth()
+        };
+        let server_name = config
+            .server_name
+            .as_ref()
+            .map(|name| {
+                ServerName::try_from(name.clone()).map_err(|_| {
+                    ProxyError::Config(format!("Route {} upstream_tls.server_name '{}' is not a valid name", route_id, name))
+                })
+            })
+            .transpose()?;
+        Ok(Self { config: client_config, server_name, http2: config.http2 })
+    }
+
+    /// Wraps `connector` so `https://` targets get TLS and `http://` targets stay plain.
+    pub fn wrap(&self, connector: UpstreamConnector) -> HttpsConnector<UpstreamConnector> {
+        let mut builder = HttpsConnectorBuilder::new().with_tls_config(self.config.clone()).https_or_http();
+        if let Some(name) = &self.server_name {
+            builder = builder.with_server_name_resolver(FixedServerNameResolver::new(name.clone()));
+        }
+        let builder = builder.enable_http1();
+        if self.http2 {
+            builder.enable_http2().wrap_connector(connector)
+        } else {
+            builder.wrap_connector(connector)
+        }
+    }
+}
+
+impl Default for UpstreamTls {
+    fn default() -> Self {
+        Self {
+            config: ClientConfig::builder().with_root_certificates(system_roots()).with_no_client_auth(),
+            server_name: None,
+            http2: false,
+        }
+    }
+}
+
+/// The platform's trusted roots, loaded once per process.
+fn system_roots() -> Arc<RootCertStore> {
+    static ROOTS: OnceLock<Arc<RootCertStore>> = OnceLock::new();
+    ROOTS
+        .get_or_init(|| {
+            let loaded = rustls_native_certs::load_native_certs();
+            for error in &loaded.errors {
+                warn!("Skipping system CA certificates: {}", error);
+            }
+            let mut roots = RootCertStore::empty();
+            let (_, ignored) = roots.add_parsable_certificates(loaded.certs);
+            if ignored > 0 {
+                warn!("Ignored {} unparsable system CA certificates", ignored);
+            }
+            Arc::new(roots)
+        })
+        .clone()
+}
+
+fn load_ca_file(route_id: &str, path: &str) -> Result<Arc<RootCertStore>, ProxyError> {
+    let invalid = |reason: String| ProxyError::Config(format!("Route {} upstream_tls.ca_file {}: {}", route_id, path, reason));
+    let file = File::open(path).map_err(|e| invalid(e.to_string()))?;
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut BufReader::new(file)) {
+        roots.add(cert.map_err(|e| invalid(e.to_string()))?).map_err(|e| invalid(e.to_string()))?;
+    }
+    if roots.is_empty() {
+        return Err(invalid("no PEM certificates found".to_string()));
+    }
+    Ok(Arc::new(roots))
+}
+
+/// `verify: false`: any certificate is accepted, but handshake signatures are still checked so
+/// the connection is at least encrypted to whoever holds the certificate's key.
+#[derive(Debug)]
+struct AcceptAnyCertificate {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl AcceptAnyCertificate {
+    fn new() -> Self {
+        let provider = CryptoProvider::get_default()
+            .cloned()
+            .unwrap_or_else(|| Arc::new(rustls::crypto::aws_lc_rs::default_provider()));
+        Self { algorithms: provider.signature_verification_algorithms }
+    }
+}
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
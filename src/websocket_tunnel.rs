@@ -0,0 +1,221 @@
+//! Frame-aware WebSocket relay.
+//!
+//! Bytes are copied as they arrive, but frame boundaries are tracked in each direction so that
+//! a Close frame can be injected cleanly when the proxy shuts down.
+
+use crate::shutdown;
+use log::debug;
+use rand::Rng;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::watch;
+use tokio::time::{Duration, sleep};
+
+/// 1001 "Going Away": the endpoint is shutting down.
+const CLOSE_GOING_AWAY: u16 = 1001;
+const CLOSE_REASON: &[u8] = b"proxy shutting down";
+
+/// Relays an upgraded WebSocket connection until either side closes it.
+///
+/// When shutdown begins the tunnel keeps running for `drain`, then both peers receive a
+/// Close frame (masked towards the backend, as client frames must be) and the tunnel ends.
+pub async fn relay_websocket<C, B>(client: C, backend: B, drain: Duration) -> io::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    let _tunnel = shutdown::track_tunnel();
+    let (client_read, client_write) = tokio::io::split(client);
+    let (backend_read, backend_write) = tokio::io::split(backend);
+    let (stop_tx, stop_rx) = watch::channel(false);
+
+    let upstream = pump(client_read, backend_write, stop_rx.clone(), close_frame(true));
+    let downstream = pump(backend_read, client_write, stop_rx, close_frame(false));
+    let pumps = async { tokio::try_join!(upstream, downstream).map(|_| ()) };
+
+    let mut shutdown_signal = shutdown::subscribe();
+    let drain_then_stop = async {
+        shutdown::shutdown_started(&mut shutdown_signal).await;
+        debug!("Draining WebSocket tunnel for {:?} before closing", drain);
+        sleep(drain).await;
+        let _ = stop_tx.send(true);
+        std::future::pending::<()>().await
+    };
+
+    tokio::select! {
+        result = pumps => result,
+        _ = drain_then_stop => Ok(()),
+    }
+}
+
+/// Copies one direction, writing `close` once `stop` is raised and the stream is between frames.
+async fn pump<R, W>(
+    mut reader: R,
+    mut writer: W,
+    mut stop: watch::Receiver<bool>,
+    close: Vec<u8>,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut tracker = FrameTracker::default();
+    let mut chunk = vec![0u8; 16 * 1024];
+    let mut stopping = false;
+
+    loop {
+        if stopping && tracker.at_boundary() {
+            writer.write_all(&close).await?;
+            writer.flush().await?;
+            let _ = writer.shutdown().await;
+            return Ok(());
+        }
+
+        let read = tokio::select! {
+            read = reader.read(&mut chunk) => read?,
+            changed = stop.changed(), if !stopping => {
+                stopping = changed.is_err() || *stop.borrow();
+                continue;
+            }
+        };
+        if read == 0 {
+            let _ = writer.shutdown().await;
+            return Ok(());
+        }
+
+        let forward = tracker.advance(&chunk[..read]);
+        writer.write_all(&forward).await?;
+    }
+}
+
+/// Tracks frame boundaries in a byte stream. Header bytes are held back until the header is
+/// complete, so a stream that is "at a boundary" has never forwarded part of an unfinished frame.
+#[derive(Default)]
+struct FrameTracker {
+    header: Vec<u8>,
+    payload_remaining: u64,
+}
+
+impl FrameTracker {
+    fn at_boundary(&self) -> bool {
+        self.payload_remaining == 0
+    }
+
+    /// Consumes received bytes and returns the bytes that can be forwarded now.
+    fn advance(&mut self, mut data: &[u8]) -> Vec<u8> {
+        let mut forward = Vec::with_capacity(data.len());
+        while !data.is_empty() {
+            if self.payload_remaining > 0 {
+                let take = data.len().min(usize::try_from(self.payload_remaining).unwrap_or(usize::MAX));
+                forward.extend_from_slice(&data[..take]);
+                self.payload_remaining -= take as u64;
+                data = &data[take..];
+                continue;
+            }
+
+            self.header.push(data[0]);
+            data = &data[1..];
+            if let Some(payload_len) = header_payload_len(&self.header) {
+                forward.append(&mut self.header);
+                self.payload_remaining = payload_len;
+            }
+        }
+        forward
+    }
+}
+
+/// Returns the payload length once `header` holds a complete frame header.
+fn header_payload_len(header: &[u8]) -> Option<u64> {
+    if header.len() < 2 {
+        return None;
+    }
+    let masked = header[1] & 0x80 != 0;
+    let (len, len_bytes) = match header[1] & 0x7f {
+        126 => (None, 2),
+        127 => (None, 8),
+        len => (Some(u64::from(len)), 0),
+    };
+    let needed = 2 + len_bytes + if masked { 4 } else { 0 };
+    if header.len() < needed {
+        return None;
+    }
+    len.or_else(|| {
+        let bytes = &header[2..2 + len_bytes];
+        Some(bytes.iter().fold(0u64, |acc, byte| (acc << 8) | u64::from(*byte)))
+    })
+}
+
+fn close_frame(masked: bool) -> Vec<u8> {
+    let mut payload = CLOSE_GOING_AWAY.to_be_bytes().to_vec();
+    payload.extend_from_slice(CLOSE_REASON);
+
+    let mut frame = vec![0x88];
+    if masked {
+        let key: [u8; 4] = rand::thread_rng().r#gen();
+        frame.push(0x80 | payload.len() as u8);
+        frame.extend_from_slice(&key);
+        frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ key[i % 4]));
+    } else {
+        frame.push(payload.len() as u8);
+        frame.extend_from_slice(&payload);
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+
+    #[test]
+    fn tracks_boundaries_across_split_headers_and_payloads() {
+        let mut tracker = FrameTracker::default();
+        // Masked text frame "hi": 2 header bytes, 4 mask bytes, 2 payload bytes
+        let frame = [0x81, 0x82, 1, 2, 3, 4, b'h' ^ 1, b'i' ^ 2];
+
+        assert!(tracker.advance(&frame[..3]).is_empty());
+        assert!(tracker.at_boundary());
+        assert_eq!(tracker.advance(&frame[3..7]), frame[..7].to_vec());
+        assert!(!tracker.at_boundary());
+        assert_eq!(tracker.advance(&frame[7..]), frame[7..].to_vec());
+        assert!(tracker.at_boundary());
+
+        let mut extended = vec![0x82, 126, 0x01, 0x00];
+        extended.extend(vec![0u8; 256]);
+        assert_eq!(tracker.advance(&extended).len(), extended.len());
+        assert!(tracker.at_boundary());
+    }
+
+    #[test]
+    fn close_frames_carry_going_away() {
+        let unmasked = close_frame(false);
+        assert_eq!(&unmasked[..4], &[0x88, 21, 0x03, 0xE9]);
+
+        let masked = close_frame(true);
+        assert_eq!(masked[1], 0x80 | 21);
+        let key = &masked[2..6];
+        let status: Vec<u8> = masked[6..8].iter().enumerate().map(|(i, b)| b ^ key[i]).collect();
+        assert_eq!(status, vec![0x03, 0xE9]);
+    }
+
+    #[tokio::test]
+    async fn stop_waits_for_frame_boundary_before_closing() {
+        let (source, mut source_writer) = duplex(1024);
+        let (mut out, out_writer) = duplex(1024);
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let task = tokio::spawn(pump(source, out_writer, stop_rx, close_frame(false)));
+
+        // Half a frame is in flight when the stop arrives
+        source_writer.write_all(&[0x82, 0x04, 1, 2]).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        stop_tx.send(true).unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        source_writer.write_all(&[3, 4]).await.unwrap();
+        task.await.unwrap().unwrap();
+
+        let mut received = Vec::new();
+        out.read_to_end(&mut received).await.unwrap();
+        assert_eq!(&received[..6], &[0x82, 0x04, 1, 2, 3, 4]);
+        assert_eq!(received[6..], close_frame(false)[..]);
+    }
+}
@@ -0,0 +1,51 @@
+//! Character rules for request heads the proxy writes itself.
+//!
+//! Requests parsed by hyper arrive with valid targets and header fields, but the raw CONNECT
+//! parser and the relay client build heads from strings. A CR or LF in a CONNECT target or a
+//! header value copied from one would end the line early and let a client add its own headers
+//! to the request the relay sees. These checks refuse such input rather than repair it.
+
+/// A header field name: one or more `tchar` (RFC 9110 5.6.2).
+pub fn is_token(name: &[u8]) -> bool {
+    !name.is_empty()
+        && name.iter().all(|&byte| {
+            byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+        })
+}
+
+/// A header field value: visible characters, obs-text, spaces and tabs (RFC 9110 5.5).
+pub fn is_field_value(value: &[u8]) -> bool {
+    value.iter().all(|&byte| byte == b'\t' || (byte >= b' ' && byte != 0x7f))
+}
+
+/// The `host:port` target of a CONNECT request (RFC 9110 9.3.6), without userinfo.
+pub fn is_connect_authority(target: &str) -> bool {
+    !target.is_empty()
+        && target.bytes().all(|byte| {
+            byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:[]%".contains(&byte)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_breaks_and_controls_are_refused() {
+        assert!(is_token(b"Proxy-Authorization"));
+        assert!(!is_token(b"X Header"));
+        assert!(!is_token(b"X-A\r\nX-B"));
+        assert!(!is_token(b""));
+
+        assert!(is_field_value(b"Basic dTpw"));
+        assert!(is_field_value(b"caf\xc3\xa9\tnoted"));
+        assert!(!is_field_value(b"a\r\nInjected: 1"));
+        assert!(!is_field_value(b"a\0b"));
+
+        assert!(is_connect_authority("example.com:443"));
+        assert!(is_connect_authority("[2001:db8::1]:8443"));
+        assert!(!is_connect_authority("example.com\r\nX-Injected: 1:443"));
+        assert!(!is_connect_authority("user@example.com:443"));
+        assert!(!is_connect_authority("example.com/path"));
+    }
+}
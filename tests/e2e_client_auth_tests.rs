@@ -0,0 +1,115 @@
+//! End-to-end tests of client certificates (mutual TLS) on HTTPS listeners
+
+use bifrost_bridge::config::Config;
+use bifrost_bridge::test_support::{StubBackend, TestProxy, send, tls_connect, tls_connect_with_client_cert};
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Request, StatusCode};
+use serde_json::json;
+use tokio::net::TcpStream;
+
+const CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/localhost.crt");
+const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/localhost.key");
+const CLIENT_CA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/client-ca.crt");
+const CLIENT_CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/client.crt");
+const CLIENT_KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/client.key");
+
+fn get(path: &str) -> Request<Full<Bytes>> {
+    Request::get(path)
+        .header("host", "localhost")
+        .header("x-client-cert-cn", "forged")
+        .body(Full::new(Bytes::new()))
+        .unwrap()
+}
+
+async fn start(backend: &StubBackend, mode: &str) -> TestProxy {
+    let site = tempfile::tempdir().unwrap();
+    let config: Config = serde_json::from_value(json!({
+        "mode": "Reverse",
+        "listen_addr": "127.0.0.1:0",
+        "monitoring": { "enabled": false },
+        "private_key": KEY,
+        "certificate": CERT,
+        "client_auth": { "ca_file": CLIENT_CA, "mode": mode },
+        "reverse_proxy_routes": [{
+            "id": "internal",
+            "target": backend.url(),
+            "predicates": [{ "type": "Path", "patterns": ["/internal/**"] }]
+        }],
+        "static_files": {
+            "mounts": [{ "path": "/site", "root_dir": site.path() }],
+            "enable_directory_listing": false,
+            "index_files": ["index.html"],
+            "spa_mode": false,
+            "spa_fallback_file": "index.html"
+        }
+    }))
+    .unwrap();
+    TestProxy::start(config).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_required_client_certificate_names_the_client_to_the_backend() {
+    let backend = StubBackend::http("internal").await;
+    let proxy = start(&backend, "required").await;
+
+    let tls = tls_connect_with_client_cert(TcpStream::connect(proxy.addr()).await.unwrap(), "localhost", CERT, CLIENT_CERT, CLIENT_KEY)
+        .await
+        .unwrap();
+    let response = send(tls, get("/internal/status")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(backend.requests()[0].headers["x-client-cert-cn"], "service-a");
+
+    // Without a certificate the handshake is refused and nothing reaches the backend
+    let refused = match tls_connect(TcpStream::connect(proxy.addr()).await.unwrap(), "localhost", CERT).await {
+        Ok(tls) => send(tls, get("/internal/status")).await.is_err(),
+        Err(_) => true,
+    };
+    assert!(refused);
+    assert_eq!(backend.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_optional_client_certificate_strips_a_forged_name() {
+    let backend = StubBackend::http("internal").await;
+    let proxy = start(&backend, "optional").await;
+
+    let tls = tls_connect(TcpStream::connect(proxy.addr()).await.unwrap(), "localhost", CERT).await.unwrap();
+    let response = send(tls, get("/internal/status")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(backend.requests()[0].headers.get("x-client-cert-cn").is_none());
+}
+
+#[tokio::test]
+async fn test_reverse_listener_without_static_files_verifies_client_certificates() {
+    let backend = StubBackend::http("internal").await;
+    let config: Config = serde_json::from_value(json!({
+        "mode": "Reverse",
+        "listen_addr": "127.0.0.1:0",
+        "monitoring": { "enabled": false },
+        "private_key": KEY,
+        "certificate": CERT,
+        "client_auth": { "ca_file": CLIENT_CA, "mode": "required" },
+        "reverse_proxy_routes": [{
+            "id": "internal",
+            "target": backend.url(),
+            "predicates": [{ "type": "Path", "patterns": ["/internal/**"] }]
+        }]
+    }))
+    .unwrap();
+    let proxy = TestProxy::start(config).await.unwrap();
+
+    let tls = tls_connect_with_client_cert(TcpStream::connect(proxy.addr()).await.unwrap(), "localhost", CERT, CLIENT_CERT, CLIENT_KEY)
+        .await
+        .unwrap();
+    let response = send(tls, get("/internal/status")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(backend.requests()[0].headers["x-client-cert-cn"], "service-a");
+
+    let refused = match tls_connect(TcpStream::connect(proxy.addr()).await.unwrap(), "localhost", CERT).await {
+        Ok(tls) => send(tls, get("/internal/status")).await.is_err(),
+        Err(_) => true,
+    };
+    assert!(refused);
+    assert_eq!(backend.requests().len(), 1);
+}
@@ -0,0 +1,194 @@
+//! End-to-end tests of the forward proxy: CONNECT tunnels, relay routing and content inspection
+
+use base64::Engine;
+use bifrost_bridge::config::Config;
+use bifrost_bridge::test_support::{StubBackend, TestProxy, connect_tunnel, send, BACKEND_HEADER};
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Request, StatusCode};
+use serde_json::{Value, json};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+fn forward_config(extra: Value) -> Config {
+    let mut config = json!({
+        "mode": "Forward",
+        "listen_addr": "127.0.0.1:0",
+        "monitoring": { "enabled": false }
+    });
+    config.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+    serde_json::from_value(config).unwrap()
+}
+
+fn allow_ports(ports: &[u16]) -> Value {
+    json!({ "connect_ports": { "allowed_ports": ports } })
+}
+
+async fn assert_echoes(stream: &mut TcpStream) {
+    stream.write_all(b"ping").await.unwrap();
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(&reply, b"ping");
+}
+
+#[tokio::test]
+async fn test_connect_tunnels_bytes_to_the_target() {
+    let target = StubBackend::tcp_echo().await;
+    let proxy = TestProxy::start(forward_config(json!({
+        "forward_proxy_config": allow_ports(&[target.addr().port()])
+    })))
+    .await
+    .unwrap();
+
+    let (head, mut tunnel) = connect_tunnel(proxy.addr(), &target.addr().to_string(), &[]).await.unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert_echoes(&mut tunnel).await;
+}
+
+#[tokio::test]
+async fn test_connect_to_a_port_outside_the_policy_is_refused() {
+    let target = StubBackend::tcp_echo().await;
+    let proxy = TestProxy::start(forward_config(json!({}))).await.unwrap();
+
+    let (head, _) = connect_tunnel(proxy.addr(), &target.addr().to_string(), &[]).await.unwrap();
+    assert!(head.starts_with("HTTP/1.1 403"), "{}", head);
+}
+
+#[tokio::test]
+async fn test_connect_requires_proxy_credentials() {
+    let target = StubBackend::tcp_echo().await;
+    let proxy = TestProxy::start(forward_config(json!({
+        "proxy_username": "alice",
+        "proxy_password": "wonderland",
+        "forward_proxy_config": allow_ports(&[target.addr().port()])
+    })))
+    .await
+    .unwrap();
+    let authority = target.addr().to_string();
+
+    let (head, _) = connect_tunnel(proxy.addr(), &authority, &[]).await.unwrap();
+    assert!(head.starts_with("HTTP/1.1 407"), "{}", head);
+
+    let credentials = format!("Basic {}", base64::engine::general_purpose::STANDARD.encode("alice:wonderland"));
+    let (head, mut tunnel) = connect_tunnel(proxy.addr(), &authority, &[("Proxy-Authorization", &credentials)])
+        .await
+        .unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert_echoes(&mut tunnel).await;
+}
+
+#[tokio::test]
+async fn test_plain_http_goes_through_the_matching_relay_only() {
+    let direct = StubBackend::http("direct").await;
+    let relay = StubBackend::http("relay").await;
+    let proxy = TestProxy::start(forward_config(json!({
+        "relay_proxies": [{ "relay_proxy_url": relay.url(), "relay_proxy_domains": ["relay.test"] }]
+    })))
+    .await
+    .unwrap();
+
+    let request = |uri: &str, host: &str| {
+        Request::get(uri).header("host", host).body(Full::new(Bytes::new())).unwrap()
+    };
+
+    let stream = TcpStream::connect(proxy.addr()).await.unwrap();
+    let response = send(stream, request("http://api.relay.test/items?page=2", "api.relay.test")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[BACKEND_HEADER], "relay");
+    let relayed = relay.requests();
+    assert_eq!(relayed.len(), 1);
+    assert_eq!(relayed[0].uri, "http://api.relay.test/items?page=2");
+
+    let host = direct.addr().to_string();
+    let stream = TcpStream::connect(proxy.addr()).await.unwrap();
+    let response = send(stream, request(&format!("{}/status", direct.url()), &host)).await.unwrap();
+    assert_eq!(response.headers()[BACKEND_HEADER], "direct");
+    assert_eq!(direct.requests()[0].uri.path(), "/status");
+    assert_eq!(relay.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_connect_goes_through_the_matching_relay() {
+    let target = StubBackend::tcp_echo().await;
+    let relay = StubBackend::connect_relay(target.addr()).await;
+    let proxy = TestProxy::start(forward_config(json!({
+        "relay_proxies": [{ "relay_proxy_url": relay.url(), "relay_proxy_domains": [".tunnel.test"] }]
+    })))
+    .await
+    .unwrap();
+
+    let (head, mut tunnel) = connect_tunnel(proxy.addr(), "secure.tunnel.test:443", &[]).await.unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert_echoes(&mut tunnel).await;
+
+    let relayed = relay.requests();
+    assert_eq!(relayed.len(), 1);
+    assert_eq!(relayed[0].method, hyper::Method::CONNECT);
+    assert_eq!(relayed[0].uri.authority().unwrap().as_str(), "secure.tunnel.test:443");
+}
+
+#[tokio::test]
+async fn test_connect_to_an_ipv6_literal() {
+    let target = StubBackend::tcp_echo_on("[::1]:0").await;
+    let proxy = TestProxy::start(forward_config(json!({
+        "forward_proxy_config": allow_ports(&[target.addr().port()])
+    })))
+    .await
+    .unwrap();
+
+    let authority = format!("[::1]:{}", target.addr().port());
+    let (head, mut tunnel) = connect_tunnel(proxy.addr(), &authority, &[]).await.unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert_echoes(&mut tunnel).await;
+}
+
+#[tokio::test]
+async fn test_connect_through_a_relay_keeps_ipv6_brackets() {
+    let target = StubBackend::tcp_echo().await;
+    let relay = StubBackend::connect_relay(target.addr()).await;
+    let proxy = TestProxy::start(forward_config(json!({
+        "relay_proxies": [{ "relay_proxy_url": relay.url(), "relay_proxy_domains": ["2001:db8::1"] }]
+    })))
+    .await
+    .unwrap();
+
+    let (head, mut tunnel) = connect_tunnel(proxy.addr(), "[2001:db8::1]:443", &[]).await.unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert_echoes(&mut tunnel).await;
+
+    let relayed = relay.requests();
+    assert_eq!(relayed.len(), 1);
+    assert_eq!(relayed[0].uri.authority().unwrap().as_str(), "[2001:db8::1]:443");
+}
+
+#[tokio::test]
+async fn test_request_body_over_the_content_hook_limit_is_refused() {
+    let target = StubBackend::http("direct").await;
+    let hook = StubBackend::http("hook").await;
+    let proxy = TestProxy::start(forward_config(json!({
+        "forward_proxy_config": {
+            "content_hook": { "url": format!("{}/scan", hook.url()), "max_body_bytes": 16 }
+        }
+    })))
+    .await
+    .unwrap();
+
+    let host = target.addr().to_string();
+    let upload = |body: &'static str| {
+        Request::post(format!("{}/upload", target.url()))
+            .header("host", &host)
+            .body(Full::new(Bytes::from_static(body.as_bytes())))
+            .unwrap()
+    };
+
+    let stream = TcpStream::connect(proxy.addr()).await.unwrap();
+    let response = send(stream, upload("small")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(hook.requests().len(), 1);
+
+    let stream = TcpStream::connect(proxy.addr()).await.unwrap();
+    let response = send(stream, upload("a body well past the sixteen byte limit")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(hook.requests().len(), 1);
+    assert_eq!(target.requests().len(), 1);
+}
@@ -0,0 +1,91 @@
+//! End-to-end tests of HTTP/2 on inbound listeners: `h2` through ALPN, and `h2c` on plain ports
+
+use bifrost_bridge::config::Config;
+use bifrost_bridge::test_support::{
+    BACKEND_HEADER, StubBackend, TestProxy, send, send_http2, tls_connect, tls_connect_with_alpn,
+};
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Request, StatusCode, Version};
+use serde_json::{Value, json};
+use tokio::net::TcpStream;
+
+const CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/localhost.crt");
+const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/localhost.key");
+
+fn reverse_config(backend: &StubBackend, extra: Value) -> Config {
+    let mut config = json!({
+        "mode": "Reverse",
+        "listen_addr": "127.0.0.1:0",
+        "monitoring": { "enabled": false },
+        "reverse_proxy_routes": [{
+            "id": "api",
+            "target": backend.url(),
+            "predicates": [{ "type": "Path", "patterns": ["/orders/**"] }]
+        }]
+    });
+    config.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+    serde_json::from_value(config).unwrap()
+}
+
+#[tokio::test]
+async fn test_https_listener_negotiates_http2_through_alpn() {
+    let site = tempfile::tempdir().unwrap();
+    let backend = StubBackend::http("api").await;
+    // TLS listeners serve static files alongside the routes
+    let config = reverse_config(
+        &backend,
+        json!({
+            "private_key": KEY,
+            "certificate": CERT,
+            "http2": {},
+            "static_files": {
+                "mounts": [{ "path": "/site", "root_dir": site.path() }],
+                "enable_directory_listing": false,
+                "index_files": ["index.html"],
+                "spa_mode": false,
+                "spa_fallback_file": "index.html"
+            }
+        }),
+    );
+    let proxy = TestProxy::start(config).await.unwrap();
+
+    let stream = TcpStream::connect(proxy.addr()).await.unwrap();
+    let tls = tls_connect_with_alpn(stream, "localhost", CERT, &[b"h2", b"http/1.1"]).await.unwrap();
+    assert_eq!(tls.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+    let request = Request::get("https://localhost/orders/7").body(Full::new(Bytes::new())).unwrap();
+    let response = send_http2(tls, request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.version(), Version::HTTP_2);
+    assert_eq!(response.headers()[BACKEND_HEADER], "api");
+
+    // The authority reaches the backend as the Host header
+    let recorded = &backend.requests()[0];
+    assert_eq!(recorded.uri.path(), "/orders/7");
+    assert_eq!(recorded.headers["host"], "localhost");
+
+    // Clients without ALPN keep HTTP/1.1
+    let tls = tls_connect(TcpStream::connect(proxy.addr()).await.unwrap(), "localhost", CERT).await.unwrap();
+    let request = Request::get("/orders/8").header("host", "localhost").body(Full::new(Bytes::new())).unwrap();
+    let response = send(tls, request).await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_11);
+    assert_eq!(response.headers()[BACKEND_HEADER], "api");
+}
+
+#[tokio::test]
+async fn test_plain_listener_serves_h2c_only_when_enabled() {
+    let backend = StubBackend::http("api").await;
+    let request = || Request::get("http://localhost/orders/7").body(Full::new(Bytes::new())).unwrap();
+
+    let proxy = TestProxy::start(reverse_config(&backend, json!({ "http2": { "h2c": true } }))).await.unwrap();
+    let response = send_http2(TcpStream::connect(proxy.addr()).await.unwrap(), request()).await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_2);
+    assert_eq!(response.headers()[BACKEND_HEADER], "api");
+    let http1 = Request::get("/orders/8").header("host", "localhost").body(Full::new(Bytes::new())).unwrap();
+    let response = send(TcpStream::connect(proxy.addr()).await.unwrap(), http1).await.unwrap();
+    assert_eq!(response.headers()[BACKEND_HEADER], "api");
+
+    let proxy = TestProxy::start(reverse_config(&backend, json!({}))).await.unwrap();
+    assert!(send_http2(TcpStream::connect(proxy.addr()).await.unwrap(), request()).await.is_err());
+    assert_eq!(backend.requests().len(), 2);
+}
@@ -0,0 +1,351 @@
+//! End-to-end tests of the reverse proxy: route predicates, path normalization, SPA fallback and
+//! WebSocket tunnels
+
+use bifrost_bridge::config::Config;
+use bifrost_bridge::test_support::{
+    StubBackend, TestProxy, get, read_head, read_websocket_frame, send, websocket_frame, BACKEND_HEADER,
+};
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde_json::{Value, json};
+use std::fs;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+fn reverse_config(extra: Value) -> Config {
+    let mut config = json!({
+        "mode": "Reverse",
+        "listen_addr": "127.0.0.1:0",
+        "monitoring": { "enabled": false }
+    });
+    config.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+    serde_json::from_value(config).unwrap()
+}
+
+#[tokio::test]
+async fn test_routes_select_backends_by_predicates() {
+    let api = StubBackend::http("api").await;
+    let writer = StubBackend::http("writer").await;
+    let admin = StubBackend::http("admin").await;
+    let web = StubBackend::http("web").await;
+    let proxy = TestProxy::start(reverse_config(json!({
+        "reverse_proxy_routes": [
+            {
+                "id": "writes",
+                "target": writer.url(),
+                "priority": 0,
+                "predicates": [
+                    { "type": "Path", "patterns": ["/api/**"] },
+                    { "type": "Method", "methods": ["POST", "PUT"] }
+                ]
+            },
+            {
+                "id": "api",
+                "target": api.url(),
+                "priority": 1,
+                "predicates": [{ "type": "Path", "patterns": ["/api/**"] }],
+                "strip_path_prefix": "/api"
+            },
+            {
+                "id": "admin",
+                "target": admin.url(),
+                "priority": 1,
+                "predicates": [
+                    { "type": "Path", "patterns": ["/admin/**"] },
+                    { "type": "Header", "name": "X-Role", "value": "admin" }
+                ]
+            },
+            {
+                "id": "web",
+                "target": web.url(),
+                "priority": 10,
+                "predicates": [{ "type": "Path", "patterns": ["/**"] }]
+            }
+        ]
+    })))
+    .await
+    .unwrap();
+
+    let response = get(proxy.addr(), "/api/users?active=true").await;
+    assert_eq!(response.headers()[BACKEND_HEADER], "api");
+    assert_eq!(api.requests()[0].uri, "/users?active=true");
+
+    let post = Request::post("/api/users")
+        .header("host", proxy.addr().to_string())
+        .body(Full::new(Bytes::from_static(b"{\"name\":\"bo\"}")))
+        .unwrap();
+    let response = send(TcpStream::connect(proxy.addr()).await.unwrap(), post).await.unwrap();
+    assert_eq!(response.headers()[BACKEND_HEADER], "writer");
+    assert_eq!(&writer.requests()[0].body[..], b"{\"name\":\"bo\"}");
+
+    let response = get(proxy.addr(), "/admin/panel").await;
+    assert_eq!(response.headers()[BACKEND_HEADER], "web");
+
+    let with_role = Request::get("/admin/panel")
+        .header("host", proxy.addr().to_string())
+        .header("x-role", "admin")
+        .body(Full::new(Bytes::new()))
+        .unwrap();
+    let response = send(TcpStream::connect(proxy.addr()).await.unwrap(), with_role).await.unwrap();
+    assert_eq!(response.headers()[BACKEND_HEADER], "admin");
+    assert_eq!(admin.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_spa_mount_falls_back_to_index_next_to_api_routes() {
+    let dist = tempfile::tempdir().unwrap();
+    fs::write(dist.path().join("index.html"), "<div id=\"app\"></div>").unwrap();
+    fs::create_dir(dist.path().join("assets")).unwrap();
+    fs::write(dist.path().join("assets").join("app.js"), "mount('#app')").unwrap();
+    let api = StubBackend::http("api").await;
+    let proxy = TestProxy::start(reverse_config(json!({
+        "reverse_proxy_routes": [{
+            "id": "api",
+            "target": api.url(),
+            "predicates": [{ "type": "Path", "patterns": ["/api/**"] }]
+        }],
+        "static_files": {
+            "mounts": [{ "path": "/app", "root_dir": dist.path() }],
+            "enable_directory_listing": false,
+            "index_files": ["index.html"],
+            "spa_mode": true,
+            "spa_fallback_file": "index.html"
+        }
+    })))
+    .await
+    .unwrap();
+
+    let response = get(proxy.addr(), "/app/settings/profile").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(&response.body()[..], b"<div id=\"app\"></div>");
+
+    let response = get(proxy.addr(), "/app/assets/app.js").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(&response.body()[..], b"mount('#app')");
+
+    let response = get(proxy.addr(), "/api/session").await;
+    assert_eq!(response.headers()[BACKEND_HEADER], "api");
+    assert_eq!(api.requests()[0].uri.path(), "/api/session");
+}
+
+#[tokio::test]
+async fn test_paths_are_normalized_before_routes_and_mounts_see_them() {
+    let dist = tempfile::tempdir().unwrap();
+    fs::create_dir(dist.path().join("assets")).unwrap();
+    fs::write(dist.path().join("assets").join("app.js"), "mount('#app')").unwrap();
+    let internal = StubBackend::http("internal").await;
+    let web = StubBackend::http("web").await;
+    let proxy = TestProxy::start(reverse_config(json!({
+        "reverse_proxy_routes": [
+            {
+                "id": "internal",
+                "target": internal.url(),
+                "priority": 0,
+                "predicates": [{ "type": "Path", "patterns": ["/internal/**"] }]
+            },
+            {
+                "id": "web",
+                "target": web.url(),
+                "priority": 10,
+                "predicates": [{ "type": "Path", "patterns": ["/**"] }]
+            }
+        ],
+        "static_files": {
+            "mounts": [{ "path": "/app", "root_dir": dist.path() }],
+            "enable_directory_listing": false,
+            "index_files": ["index.html"],
+            "spa_mode": false,
+            "spa_fallback_file": "index.html"
+        }
+    })))
+    .await
+    .unwrap();
+
+    for path in ["/public/..//internal/keys?v=1", "/%69nternal/./keys?v=1", "/public/%2e%2e/internal/keys?v=1"] {
+        let response = get(proxy.addr(), path).await;
+        assert_eq!(response.headers()[BACKEND_HEADER], "internal", "{}", path);
+    }
+    assert!(internal.requests().iter().all(|request| request.uri == "/internal/keys?v=1"));
+    assert!(web.requests().is_empty());
+
+    let response = get(proxy.addr(), "/app/settings/../assets//app.js").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(&response.body()[..], b"mount('#app')");
+
+    let response = get(proxy.addr(), "/app/../internal/keys").await;
+    assert_eq!(response.headers()[BACKEND_HEADER], "internal");
+}
+
+/// A backend that answers every request and hands over its head as received.
+async fn raw_head_backend() -> (String, tokio::sync::mpsc::UnboundedReceiver<String>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (heads, received) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let heads = heads.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                while let Ok(head) = read_head(&mut stream).await {
+                    let _ = heads.send(head);
+                    if stream.get_mut().write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n").await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    (url, received)
+}
+
+#[tokio::test]
+async fn test_header_case_is_kept_or_title_cased_per_route() {
+    let (legacy, mut legacy_heads) = raw_head_backend().await;
+    let (titled, mut titled_heads) = raw_head_backend().await;
+    let (plain, mut plain_heads) = raw_head_backend().await;
+    let proxy = TestProxy::start(reverse_config(json!({
+        "reverse_proxy_routes": [
+            {
+                "id": "legacy",
+                "target": legacy,
+                "reverse_proxy_config": { "header_case": "preserve" },
+                "predicates": [{ "type": "Path", "patterns": ["/legacy/**"] }]
+            },
+            {
+                "id": "titled",
+                "target": titled,
+                "reverse_proxy_config": { "header_case": "title_case" },
+                "predicates": [{ "type": "Path", "patterns": ["/titled/**"] }]
+            },
+            {
+                "id": "plain",
+                "target": plain,
+                "predicates": [{ "type": "Path", "patterns": ["/**"] }]
+            }
+        ]
+    })))
+    .await
+    .unwrap();
+
+    let mut client = BufReader::new(TcpStream::connect(proxy.addr()).await.unwrap());
+    for path in ["/legacy/a", "/titled/a", "/plain"] {
+        let request = format!("GET {} HTTP/1.1\r\nHost: example.com\r\nX-LEGACY-token: abc\r\n\r\n", path);
+        client.get_mut().write_all(request.as_bytes()).await.unwrap();
+        let head = read_head(&mut client).await.unwrap();
+        assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    }
+
+    assert!(legacy_heads.recv().await.unwrap().contains("\r\nX-LEGACY-token: abc\r\n"));
+    assert!(titled_heads.recv().await.unwrap().contains("\r\nX-Legacy-Token: abc\r\n"));
+    assert!(plain_heads.recv().await.unwrap().contains("\r\nx-legacy-token: abc\r\n"));
+}
+
+#[tokio::test]
+async fn test_upgrades_on_default_routes_work_while_header_case_is_recorded() {
+    let (legacy, _legacy_heads) = raw_head_backend().await;
+    let backend = StubBackend::websocket_echo().await;
+    let proxy = TestProxy::start(reverse_config(json!({
+        "reverse_proxy_routes": [
+            {
+                "id": "legacy",
+                "target": legacy,
+                "reverse_proxy_config": { "header_case": "preserve" },
+                "predicates": [{ "type": "Path", "patterns": ["/legacy/**"] }]
+            },
+            {
+                "id": "live",
+                "target": backend.url(),
+                "predicates": [{ "type": "Path", "patterns": ["/live/**"] }]
+            }
+        ]
+    })))
+    .await
+    .unwrap();
+
+    let mut stream = BufReader::new(TcpStream::connect(proxy.addr()).await.unwrap());
+    let handshake = format!(
+        "GET /live/feed HTTP/1.1\r\nHost: {}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\
+         Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+        proxy.addr()
+    );
+    stream.write_all(handshake.as_bytes()).await.unwrap();
+    let head = read_head(&mut stream).await.unwrap();
+    assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+
+    stream.write_all(&websocket_frame(b"hello", Some([1, 2, 3, 4]))).await.unwrap();
+    let (_, payload) = read_websocket_frame(&mut stream).await.unwrap();
+    assert_eq!(payload, b"hello");
+}
+
+#[tokio::test]
+async fn test_websocket_upgrade_is_tunneled_to_the_backend() {
+    let backend = StubBackend::websocket_echo().await;
+    let proxy = TestProxy::start(reverse_config(json!({
+        "reverse_proxy_routes": [{
+            "id": "live",
+            "target": backend.url(),
+            "predicates": [{ "type": "Path", "patterns": ["/live/**"] }]
+        }]
+    })))
+    .await
+    .unwrap();
+
+    let mut stream = BufReader::new(TcpStream::connect(proxy.addr()).await.unwrap());
+    let handshake = format!(
+        "GET /live/feed HTTP/1.1\r\nHost: {}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\
+         Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+        proxy.addr()
+    );
+    stream.write_all(handshake.as_bytes()).await.unwrap();
+    let head = read_head(&mut stream).await.unwrap();
+    assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+    assert!(head.to_ascii_lowercase().contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo="), "{}", head);
+
+    stream.write_all(&websocket_frame(b"hello", Some([1, 2, 3, 4]))).await.unwrap();
+    let (opcode, payload) = read_websocket_frame(&mut stream).await.unwrap();
+    assert_eq!(opcode, 0x1);
+    assert_eq!(payload, b"hello");
+
+    let upgrade = &backend.requests()[0];
+    assert_eq!(upgrade.uri.path(), "/live/feed");
+    assert_eq!(upgrade.headers["upgrade"], "websocket");
+}
+
+#[tokio::test]
+async fn test_reload_swaps_routes_for_open_connections() {
+    let old = StubBackend::http("old").await;
+    let new = StubBackend::http("new").await;
+    let routes = |target: String| {
+        reverse_config(json!({
+            "reverse_proxy_routes": [{
+                "id": "app",
+                "target": target,
+                "predicates": [{ "type": "Path", "patterns": ["/**"] }]
+            }]
+        }))
+    };
+    let proxy = TestProxy::start(routes(old.url())).await.unwrap();
+
+    // A keep-alive connection opened before the reload
+    let stream = TcpStream::connect(proxy.addr()).await.unwrap();
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await.unwrap();
+    tokio::spawn(connection);
+    let request = || Request::get("/page").header("host", "app.example").body(Full::new(Bytes::new())).unwrap();
+    let response = sender.send_request(request()).await.unwrap();
+    assert_eq!(response.headers()[BACKEND_HEADER], "old");
+
+    proxy.reconfigure(routes(new.url())).await.unwrap();
+    let response = sender.send_request(request()).await.unwrap();
+    assert_eq!(response.headers()[BACKEND_HEADER], "new");
+    assert_eq!(get(proxy.addr(), "/page").await.headers()[BACKEND_HEADER], "new");
+
+    // A configuration that cannot be applied leaves the running one in place
+    assert!(proxy.reconfigure(routes("not a url".to_string())).await.is_err());
+    let mut static_files = routes(old.url());
+    static_files.static_files = Some(Default::default());
+    assert!(proxy.reconfigure(static_files).await.is_err());
+    let response = sender.send_request(request()).await.unwrap();
+    assert_eq!(response.headers()[BACKEND_HEADER], "new");
+}
@@ -0,0 +1,79 @@
+//! End-to-end tests of TLS: an HTTPS listener, and a TLS session through a CONNECT tunnel
+
+use bifrost_bridge::config::Config;
+use bifrost_bridge::test_support::{StubBackend, TestProxy, connect_tunnel, send, tls_connect, BACKEND_HEADER};
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Request, StatusCode};
+use serde_json::json;
+use tokio::net::TcpStream;
+
+const CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/localhost.crt");
+const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/localhost.key");
+
+fn get(path: &str) -> Request<Full<Bytes>> {
+    Request::get(path).header("host", "localhost").body(Full::new(Bytes::new())).unwrap()
+}
+
+#[tokio::test]
+async fn test_https_listener_serves_static_files_and_routes() {
+    let site = tempfile::tempdir().unwrap();
+    std::fs::write(site.path().join("index.html"), "welcome").unwrap();
+    let backend = StubBackend::http("api").await;
+    let config: Config = serde_json::from_value(json!({
+        "mode": "Reverse",
+        "listen_addr": "127.0.0.1:0",
+        "monitoring": { "enabled": false },
+        "private_key": KEY,
+        "certificate": CERT,
+        "reverse_proxy_routes": [{
+            "id": "api",
+            "target": backend.url(),
+            "predicates": [{ "type": "Path", "patterns": ["/orders/**"] }]
+        }],
+        "static_files": {
+            "mounts": [{ "path": "/site", "root_dir": site.path() }],
+            "enable_directory_listing": false,
+            "index_files": ["index.html"],
+            "spa_mode": false,
+            "spa_fallback_file": "index.html"
+        }
+    }))
+    .unwrap();
+    let proxy = TestProxy::start(config).await.unwrap();
+
+    let tls = tls_connect(TcpStream::connect(proxy.addr()).await.unwrap(), "localhost", CERT).await.unwrap();
+    let response = send(tls, get("/orders/7")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[BACKEND_HEADER], "api");
+    assert_eq!(backend.requests()[0].uri.path(), "/orders/7");
+
+    let tls = tls_connect(TcpStream::connect(proxy.addr()).await.unwrap(), "localhost", CERT).await.unwrap();
+    let response = send(tls, get("/site/index.html")).await.unwrap();
+    assert_eq!(&response.body()[..], b"welcome");
+
+    // Plain HTTP on the TLS port never reaches the backend
+    let plain = send(TcpStream::connect(proxy.addr()).await.unwrap(), get("/orders/8")).await;
+    assert!(plain.is_err() || plain.unwrap().status() != StatusCode::OK);
+    assert_eq!(backend.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_tls_session_through_a_connect_tunnel() {
+    let backend = StubBackend::https("secure", KEY, CERT).await;
+    let config: Config = serde_json::from_value(json!({
+        "mode": "Forward",
+        "listen_addr": "127.0.0.1:0",
+        "monitoring": { "enabled": false },
+        "forward_proxy_config": { "connect_ports": { "allowed_ports": [backend.addr().port()] } }
+    }))
+    .unwrap();
+    let proxy = TestProxy::start(config).await.unwrap();
+
+    let (head, tunnel) = connect_tunnel(proxy.addr(), &backend.addr().to_string(), &[]).await.unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    let tls = tls_connect(tunnel, "localhost", CERT).await.unwrap();
+    let response = send(tls, get("/secret")).await.unwrap();
+    assert_eq!(response.headers()[BACKEND_HEADER], "secure");
+    assert_eq!(&response.body()[..], b"secure");
+}
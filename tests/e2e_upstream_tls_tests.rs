@@ -0,0 +1,102 @@
+//! End-to-end tests of `https://` route targets: certificate checks, SNI name and HTTP/2
+
+use bifrost_bridge::config::Config;
+use bifrost_bridge::test_support::{BACKEND_HEADER, StubBackend, TestProxy, get};
+use hyper::{StatusCode, Version};
+use serde_json::{Value, json};
+
+const CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/localhost.crt");
+const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/localhost.key");
+
+/// A proxy with one route to `target`, with `upstream_tls` when given.
+async fn proxy_to(target: String, upstream_tls: Option<Value>) -> TestProxy {
+    let mut route = json!({
+        "id": "secure",
+        "target": target,
+        "predicates": [{ "type": "Path", "patterns": ["/**"] }]
+    });
+    if let Some(upstream_tls) = upstream_tls {
+        route["upstream_tls"] = upstream_tls;
+    }
+    let config: Config = serde_json::from_value(json!({
+        "mode": "Reverse",
+        "listen_addr": "127.0.0.1:0",
+        "monitoring": { "enabled": false },
+        "reverse_proxy_routes": [route]
+    }))
+    .unwrap();
+    TestProxy::start(config).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_https_target_is_verified_against_the_configured_ca() {
+    let backend = StubBackend::https("secure", KEY, CERT).await;
+    let target = format!("https://localhost:{}", backend.addr().port());
+
+    let proxy = proxy_to(target.clone(), Some(json!({ "ca_file": CERT }))).await;
+    let response = get(proxy.addr(), "/orders").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[BACKEND_HEADER], "secure");
+    assert_eq!(backend.requests()[0].version, Version::HTTP_11);
+
+    // The test certificate is not signed by any system root
+    let proxy = proxy_to(target, None).await;
+    assert_eq!(get(proxy.addr(), "/orders").await.status(), StatusCode::BAD_GATEWAY);
+    assert_eq!(backend.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_server_name_overrides_the_target_host_and_verify_false_accepts_any_certificate() {
+    let backend = StubBackend::https("secure", KEY, CERT).await;
+    let target = format!("https://{}", backend.addr());
+
+    // The name checked is the configured one, which the certificate does not cover
+    let proxy = proxy_to(target.clone(), Some(json!({ "ca_file": CERT, "server_name": "api.example.com" }))).await;
+    assert_eq!(get(proxy.addr(), "/a").await.status(), StatusCode::BAD_GATEWAY);
+
+    let proxy = proxy_to(target.clone(), Some(json!({ "ca_file": CERT, "server_name": "localhost" }))).await;
+    assert_eq!(get(proxy.addr(), "/b").await.status(), StatusCode::OK);
+
+    let proxy = proxy_to(target, Some(json!({ "verify": false, "server_name": "api.example.com" }))).await;
+    assert_eq!(get(proxy.addr(), "/c").await.status(), StatusCode::OK);
+    let paths: Vec<_> = backend.requests().iter().map(|r| r.uri.path().to_string()).collect();
+    assert_eq!(paths, ["/b", "/c"]);
+}
+
+#[tokio::test]
+async fn test_http2_is_used_when_the_backend_negotiates_it() {
+    let backend = StubBackend::https("secure", KEY, CERT).await;
+    let target = format!("https://localhost:{}", backend.addr().port());
+    let proxy = proxy_to(target, Some(json!({ "ca_file": CERT, "http2": true }))).await;
+
+    for _ in 0..2 {
+        let response = get(proxy.addr(), "/orders").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[BACKEND_HEADER], "secure");
+    }
+    let requests = backend.requests();
+    assert!(requests.iter().all(|r| r.version == Version::HTTP_2));
+    assert_eq!(requests[0].uri.path(), "/orders");
+}
+
+#[tokio::test]
+async fn test_invalid_upstream_tls_settings_stop_startup() {
+    let config = |upstream_tls: Value| -> Config {
+        serde_json::from_value(json!({
+            "mode": "Reverse",
+            "listen_addr": "127.0.0.1:0",
+            "monitoring": { "enabled": false },
+            "reverse_proxy_routes": [{
+                "id": "secure",
+                "target": "https://localhost:8443",
+                "upstream_tls": upstream_tls,
+                "predicates": [{ "type": "Path", "patterns": ["/**"] }]
+            }]
+        }))
+        .unwrap()
+    };
+    let missing = TestProxy::start(config(json!({ "ca_file": "/nonexistent/ca.pem" }))).await;
+    assert!(missing.err().unwrap().to_string().contains("upstream_tls.ca_file"));
+    let bad_name = TestProxy::start(config(json!({ "server_name": "not a name" }))).await;
+    assert!(bad_name.err().unwrap().to_string().contains("upstream_tls.server_name"));
+}
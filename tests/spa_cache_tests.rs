@@ -3,7 +3,7 @@
 //! These tests verify that SPA files (index files and fallbacks) receive
 //! no-cache headers while regular static assets retain normal caching.
 
-use bifrost_bridge::config::{StaticFileConfig, StaticMount};
+use bifrost_bridge::config::{EtagMode, StaticFileConfig, StaticMount};
 use bifrost_bridge::static_files::StaticFileHandler;
 use std::fs;
 use tempfile::TempDir;
@@ -33,6 +33,9 @@ async fn test_spa_index_files_no_cache() {
             spa_fallback_file: Some("index.html".to_string()),
             no_cache_files: None,
             cache_millisecs: None,
+            etag: None,
+            last_modified: None,
+            precompressed: None,
         }],
         enable_directory_listing: false,
         index_files: vec!["index.html".to_string(), "index.htm".to_string()],
@@ -42,6 +45,9 @@ async fn test_spa_index_files_no_cache() {
         custom_mime_types: std::collections::HashMap::new(),
         no_cache_files: vec![],
         cache_millisecs: 3600,
+        etag: EtagMode::default(),
+        last_modified: true,
+        precompressed: true,
     };
 
     let handler = StaticFileHandler::new(config).unwrap();
@@ -136,6 +142,9 @@ async fn test_custom_index_files_spa_no_cache() {
             spa_fallback_file: Some("main.htm".to_string()),
             no_cache_files: None,
             cache_millisecs: None,
+            etag: None,
+            last_modified: None,
+            precompressed: None,
         }],
         enable_directory_listing: false,
         index_files: vec!["main.htm".to_string(), "app.html".to_string()],
@@ -145,6 +154,9 @@ async fn test_custom_index_files_spa_no_cache() {
         custom_mime_types: std::collections::HashMap::new(),
         no_cache_files: vec![],
         cache_millisecs: 3600,
+        etag: EtagMode::default(),
+        last_modified: true,
+        precompressed: true,
     };
 
     let handler = StaticFileHandler::new(config).unwrap();
@@ -207,6 +219,9 @@ async fn test_spa_fallback_file_detection() {
             spa_fallback_file: Some("fallback.html".to_string()),
             no_cache_files: None,
             cache_millisecs: None,
+            etag: None,
+            last_modified: None,
+            precompressed: None,
         }],
         enable_directory_listing: false,
         index_files: vec!["index.html".to_string()],
@@ -216,6 +231,9 @@ async fn test_spa_fallback_file_detection() {
         custom_mime_types: std::collections::HashMap::new(),
         no_cache_files: vec![],
         cache_millisecs: 3600,
+        etag: EtagMode::default(),
+        last_modified: true,
+        precompressed: true,
     };
 
     let handler = StaticFileHandler::new(config).unwrap();
@@ -278,6 +296,9 @@ async fn test_custom_no_cache_files_patterns() {
             spa_fallback_file: None,
             no_cache_files: Some(vec!["*.js".to_string(), "config.json".to_string()]),
             cache_millisecs: None,
+            etag: None,
+            last_modified: None,
+            precompressed: None,
         }],
         enable_directory_listing: false,
         index_files: vec!["index.html".to_string()],
@@ -287,6 +308,9 @@ async fn test_custom_no_cache_files_patterns() {
         custom_mime_types: std::collections::HashMap::new(),
         no_cache_files: vec![],
         cache_millisecs: 7200, // 2 hours
+        etag: EtagMode::default(),
+        last_modified: true,
+        precompressed: true,
     };
 
     let handler = StaticFileHandler::new(config).unwrap();
@@ -338,6 +362,9 @@ async fn test_custom_cache_millisecs_configuration() {
             spa_fallback_file: None,
             no_cache_files: None,
             cache_millisecs: Some(1800), // 30 minutes
+            etag: None,
+            last_modified: None,
+            precompressed: None,
         }],
         enable_directory_listing: false,
         index_files: vec!["index.html".to_string()],
@@ -347,6 +374,9 @@ async fn test_custom_cache_millisecs_configuration() {
         custom_mime_types: std::collections::HashMap::new(),
         no_cache_files: vec![],
         cache_millisecs: 3600, // Global default (should be overridden by mount)
+        etag: EtagMode::default(),
+        last_modified: true,
+        precompressed: true,
     };
 
     let handler = StaticFileHandler::new(config).unwrap();
@@ -381,6 +411,9 @@ async fn test_global_cache_millisecs_fallback() {
             spa_fallback_file: None,
             no_cache_files: None,
             cache_millisecs: None, // Mount doesn't specify, should inherit from global
+            etag: None,
+            last_modified: None,
+            precompressed: None,
         }],
         enable_directory_listing: false,
         index_files: vec!["index.html".to_string()],
@@ -390,6 +423,9 @@ async fn test_global_cache_millisecs_fallback() {
         custom_mime_types: std::collections::HashMap::new(),
         no_cache_files: vec![],
         cache_millisecs: 14400, // 4 hours
+        etag: EtagMode::default(),
+        last_modified: true,
+        precompressed: true,
     };
 
     let handler = StaticFileHandler::new(config).unwrap();
@@ -423,6 +459,9 @@ async fn test_no_cache_files_case_insensitive() {
             spa_fallback_file: None,
             no_cache_files: Some(vec!["*.js".to_string(), "config.json".to_string()]),
             cache_millisecs: None,
+            etag: None,
+            last_modified: None,
+            precompressed: None,
         }],
         enable_directory_listing: false,
         index_files: vec!["index.html".to_string()],
@@ -432,6 +471,9 @@ async fn test_no_cache_files_case_insensitive() {
         custom_mime_types: std::collections::HashMap::new(),
         no_cache_files: vec![],
         cache_millisecs: 3600,
+        etag: EtagMode::default(),
+        last_modified: true,
+        precompressed: true,
     };
 
     let handler = StaticFileHandler::new(config).unwrap();
@@ -0,0 +1,103 @@
+//! Integration tests for ETag / Last-Modified validation of static files
+
+use bifrost_bridge::config::{EtagMode, StaticFileConfig};
+use bifrost_bridge::static_files::StaticFileHandler;
+use hyper::{HeaderMap, StatusCode};
+use std::fs;
+use tempfile::TempDir;
+
+fn setup(etag: EtagMode, last_modified: bool) -> (TempDir, StaticFileHandler) {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("app.js"), "console.log('hi');").unwrap();
+    let mut config = StaticFileConfig::single(temp_dir.path().to_string_lossy().to_string(), false);
+    config.mounts[0].etag = Some(etag);
+    config.mounts[0].last_modified = Some(last_modified);
+    let handler = StaticFileHandler::new(config).unwrap();
+    (temp_dir, handler)
+}
+
+fn request_headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        headers.insert(*name, value.parse().unwrap());
+    }
+    headers
+}
+
+#[tokio::test]
+async fn test_if_none_match_returns_304_with_validators() {
+    for mode in [EtagMode::Mtime, EtagMode::Content] {
+        let (temp_dir, handler) = setup(mode, true);
+        let path = temp_dir.path().join("app.js");
+        let (mount_info, _) = handler.find_mount_for_path("/app.js").unwrap();
+
+        let first = handler.handle_file_request(&path, &HeaderMap::new(), false, Some(mount_info), false).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let etag = first.headers()["ETag"].to_str().unwrap().to_string();
+        let last_modified = first.headers()["Last-Modified"].to_str().unwrap().to_string();
+        assert_eq!(etag.starts_with("W/"), mode == EtagMode::Mtime);
+
+        let revalidated = handler
+            .handle_file_request(&path, &request_headers(&[("If-None-Match", &etag)]), false, Some(mount_info), false)
+            .await
+            .unwrap();
+        assert_eq!(revalidated.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(revalidated.headers()["ETag"], etag.as_str());
+        assert!(revalidated.headers().contains_key("Cache-Control"));
+
+        let by_date = handler
+            .handle_file_request(&path, &request_headers(&[("If-Modified-Since", &last_modified)]), false, Some(mount_info), false)
+            .await
+            .unwrap();
+        assert_eq!(by_date.status(), StatusCode::NOT_MODIFIED);
+
+        let stale = handler
+            .handle_file_request(&path, &request_headers(&[("If-None-Match", "\"stale\"")]), false, Some(mount_info), false)
+            .await
+            .unwrap();
+        assert_eq!(stale.status(), StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn test_disabled_validators_are_not_sent_or_honored() {
+    let (temp_dir, handler) = setup(EtagMode::Off, false);
+    let path = temp_dir.path().join("app.js");
+    let (mount_info, _) = handler.find_mount_for_path("/app.js").unwrap();
+
+    let response = handler
+        .handle_file_request(
+            &path,
+            &request_headers(&[("If-Modified-Since", "Fri, 01 Jan 2100 00:00:00 GMT")]),
+            false,
+            Some(mount_info),
+            false,
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key("ETag"));
+    assert!(!response.headers().contains_key("Last-Modified"));
+}
+
+#[tokio::test]
+async fn test_if_range_with_a_stale_validator_returns_the_whole_file() {
+    let (temp_dir, handler) = setup(EtagMode::Content, true);
+    let path = temp_dir.path().join("app.js");
+    let (mount_info, _) = handler.find_mount_for_path("/app.js").unwrap();
+
+    let first = handler.handle_file_request(&path, &HeaderMap::new(), false, Some(mount_info), false).await.unwrap();
+    let etag = first.headers()["ETag"].to_str().unwrap().to_string();
+
+    let current = handler
+        .handle_file_request(&path, &request_headers(&[("Range", "bytes=0-6"), ("If-Range", &etag)]), false, Some(mount_info), false)
+        .await
+        .unwrap();
+    assert_eq!(current.status(), StatusCode::PARTIAL_CONTENT);
+
+    let stale = handler
+        .handle_file_request(&path, &request_headers(&[("Range", "bytes=0-6"), ("If-Range", "\"old\"")]), false, Some(mount_info), false)
+        .await
+        .unwrap();
+    assert_eq!(stale.status(), StatusCode::OK);
+}
@@ -0,0 +1,93 @@
+//! Integration tests for precompressed `.br` / `.gz` sidecars of static files
+
+use bifrost_bridge::config::StaticFileConfig;
+use bifrost_bridge::static_files::StaticFileHandler;
+use http_body_util::BodyExt;
+use hyper::{HeaderMap, StatusCode};
+use std::fs;
+use tempfile::TempDir;
+
+fn setup(precompressed: bool) -> (TempDir, StaticFileHandler) {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("app.js"), "console.log('hi');").unwrap();
+    fs::write(temp_dir.path().join("app.js.br"), "brotli bytes").unwrap();
+    fs::write(temp_dir.path().join("app.js.gz"), "gzip bytes").unwrap();
+    fs::write(temp_dir.path().join("plain.js"), "plain").unwrap();
+    let mut config = StaticFileConfig::single(temp_dir.path().to_string_lossy().to_string(), false);
+    config.mounts[0].precompressed = Some(precompressed);
+    let handler = StaticFileHandler::new(config).unwrap();
+    (temp_dir, handler)
+}
+
+fn accept_encoding(value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("accept-encoding", value.parse().unwrap());
+    headers
+}
+
+async fn fetch(handler: &StaticFileHandler, temp_dir: &TempDir, name: &str, headers: &HeaderMap) -> (HeaderMap, Vec<u8>) {
+    let path = temp_dir.path().join(name);
+    let (mount_info, _) = handler.find_mount_for_path(&format!("/{}", name)).unwrap();
+    let response = handler.handle_file_request(&path, headers, false, Some(mount_info), false).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let (parts, body) = response.into_parts();
+    (parts.headers, body.collect().await.unwrap().to_bytes().to_vec())
+}
+
+#[tokio::test]
+async fn test_sidecar_is_served_to_clients_accepting_its_encoding() {
+    let (temp_dir, handler) = setup(true);
+
+    let (headers, body) = fetch(&handler, &temp_dir, "app.js", &accept_encoding("gzip, deflate, br")).await;
+    assert_eq!(body, b"brotli bytes");
+    assert_eq!(headers["Content-Encoding"], "br");
+    assert_eq!(headers["Content-Length"], "12");
+    assert_eq!(headers["Vary"], "Accept-Encoding");
+    assert_eq!(headers["Content-Type"], "text/javascript; charset=utf-8");
+    let br_etag = headers["ETag"].to_str().unwrap().to_string();
+
+    let (headers, body) = fetch(&handler, &temp_dir, "app.js", &accept_encoding("gzip")).await;
+    assert_eq!(body, b"gzip bytes");
+    assert_eq!(headers["Content-Encoding"], "gzip");
+    assert_ne!(headers["ETag"], br_etag.as_str());
+
+    let (headers, body) = fetch(&handler, &temp_dir, "app.js", &HeaderMap::new()).await;
+    assert_eq!(body, b"console.log('hi');");
+    assert!(!headers.contains_key("Content-Encoding"));
+    assert_eq!(headers["Vary"], "Accept-Encoding");
+    assert_ne!(headers["ETag"], br_etag.as_str());
+}
+
+#[tokio::test]
+async fn test_revalidation_matches_the_sidecar_etag() {
+    let (temp_dir, handler) = setup(true);
+    let path = temp_dir.path().join("app.js");
+    let (mount_info, _) = handler.find_mount_for_path("/app.js").unwrap();
+
+    let (headers, _) = fetch(&handler, &temp_dir, "app.js", &accept_encoding("br")).await;
+    let mut conditional = accept_encoding("br");
+    conditional.insert("if-none-match", headers["ETag"].clone());
+    let response = handler.handle_file_request(&path, &conditional, false, Some(mount_info), false).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()["Vary"], "Accept-Encoding");
+
+    // The same tag does not validate the uncompressed file
+    conditional.remove("accept-encoding");
+    let response = handler.handle_file_request(&path, &conditional, false, Some(mount_info), false).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_files_without_sidecars_or_disabled_mounts_are_served_as_is() {
+    let (temp_dir, handler) = setup(true);
+    let (headers, body) = fetch(&handler, &temp_dir, "plain.js", &accept_encoding("br, gzip")).await;
+    assert_eq!(body, b"plain");
+    assert!(!headers.contains_key("Content-Encoding"));
+    assert!(!headers.contains_key("Vary"));
+
+    let (temp_dir, handler) = setup(false);
+    let (headers, body) = fetch(&handler, &temp_dir, "app.js", &accept_encoding("br, gzip")).await;
+    assert_eq!(body, b"console.log('hi');");
+    assert!(!headers.contains_key("Content-Encoding"));
+    assert!(!headers.contains_key("Vary"));
+}
@@ -0,0 +1,117 @@
+//! Integration tests for `Range` requests against static files
+
+use bifrost_bridge::config::StaticFileConfig;
+use bifrost_bridge::static_files::StaticFileHandler;
+use http_body_util::BodyExt;
+use hyper::{HeaderMap, StatusCode};
+use std::fs;
+use tempfile::TempDir;
+
+fn setup(content: &[u8]) -> (TempDir, StaticFileHandler) {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("video.bin"), content).unwrap();
+    let config = StaticFileConfig::single(temp_dir.path().to_string_lossy().to_string(), false);
+    let handler = StaticFileHandler::new(config).unwrap();
+    (temp_dir, handler)
+}
+
+fn range_headers(value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("Range", value.parse().unwrap());
+    headers
+}
+
+#[tokio::test]
+async fn test_single_range_returns_partial_content() {
+    let content: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+    let (temp_dir, handler) = setup(&content);
+    let path = temp_dir.path().join("video.bin");
+
+    let response = handler
+        .handle_file_request(&path, &range_headers("bytes=100-199"), false, None, false)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()["Content-Range"], "bytes 100-199/4096");
+    assert_eq!(response.headers()["Content-Length"], "100");
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(&body[..], &content[100..200]);
+
+    let response = handler
+        .handle_file_request(&path, &range_headers("bytes=-10"), false, None, false)
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["Content-Range"], "bytes 4086-4095/4096");
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(&body[..], &content[4086..]);
+}
+
+#[tokio::test]
+async fn test_multiple_ranges_return_multipart_byteranges() {
+    let content: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+    let (temp_dir, handler) = setup(&content);
+    let path = temp_dir.path().join("video.bin");
+
+    let response = handler
+        .handle_file_request(&path, &range_headers("bytes=0-9,500-509"), false, None, false)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let content_type = response.headers()["Content-Type"].to_str().unwrap().to_string();
+    let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap().to_string();
+    let content_length: usize = response.headers()["Content-Length"].to_str().unwrap().parse().unwrap();
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body.len(), content_length);
+
+    let mut expected = Vec::new();
+    for (start, end) in [(0usize, 9usize), (500, 509)] {
+        expected.extend_from_slice(
+            format!(
+                "\r\n--{}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes {}-{}/1000\r\n\r\n",
+                boundary, start, end
+            )
+            .as_bytes(),
+        );
+        expected.extend_from_slice(&content[start..=end]);
+    }
+    expected.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    assert_eq!(&body[..], &expected[..]);
+}
+
+#[tokio::test]
+async fn test_unsatisfiable_range_returns_416() {
+    let (temp_dir, handler) = setup(b"hello");
+    let path = temp_dir.path().join("video.bin");
+
+    let response = handler
+        .handle_file_request(&path, &range_headers("bytes=10-20"), false, None, false)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers()["Content-Range"], "bytes */5");
+}
+
+#[tokio::test]
+async fn test_malformed_range_and_head_return_whole_file() {
+    let (temp_dir, handler) = setup(b"hello world");
+    let path = temp_dir.path().join("video.bin");
+
+    let response = handler
+        .handle_file_request(&path, &range_headers("bytes=oops"), false, None, false)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(&body[..], b"hello world");
+
+    let response = handler
+        .handle_file_request(&path, &range_headers("bytes=0-4"), true, None, false)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["Content-Length"], "11");
+}